@@ -0,0 +1,70 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Helpers shared by the example programs.
+//!
+//! Pixels are converted by the library itself, with [`Texture::repack`], [`Texture::encode`],
+//! and the conversions to and from [`image`] types, so the examples only parse arguments and move files
+
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use quicktex::container::ContainerHeader;
+use quicktex::dds::{DDSHeader, DDSHeaderArgs};
+use quicktex::format::{AlphaFormat, ColorFormat, Format};
+use quicktex::texture::Texture;
+
+/// Parse the name of one of the formats supported by the examples
+pub fn parse_format(name: &str) -> Result<Format> {
+    let rgb = |r_mask, g_mask, b_mask| ColorFormat::RGB {
+        r_mask,
+        g_mask,
+        b_mask,
+        srgb: false,
+    };
+    let uncompressed = |pitch, color_format, alpha_format| Format::Uncompressed {
+        pitch,
+        color_format,
+        alpha_format,
+    };
+    let straight = AlphaFormat::Straight {
+        alpha_mask: 0xFF000000,
+    };
+
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "rgba8" => Format::rgba8(false),
+        "bgra8" => uncompressed(4, rgb(0xFF0000, 0xFF00, 0xFF), straight),
+        "rgb8" => uncompressed(3, rgb(0xFF, 0xFF00, 0xFF0000), AlphaFormat::Opaque),
+        "bgr8" => uncompressed(3, rgb(0xFF0000, 0xFF00, 0xFF), AlphaFormat::Opaque),
+        "l8" => uncompressed(1, ColorFormat::L { l_mask: 0xFF }, AlphaFormat::Opaque),
+        "bc1" => Format::BC1 { srgb: false },
+        "bc3" => Format::BC3 { srgb: false },
+        "bc4" => Format::BC4 { signed: false },
+        "bc5" => Format::BC5 { signed: false },
+        "bc7" => Format::BC7 { srgb: false },
+        _ => bail!(
+            "Unknown format '{name}'. Expected one of rgba8, bgra8, rgb8, bgr8, l8, bc1, bc3, bc4, bc5, bc7"
+        ),
+    })
+}
+
+pub fn read_dds(path: impl AsRef<Path>) -> Result<Texture> {
+    let path = path.as_ref();
+    let mut reader =
+        BufReader::new(File::open(path).with_context(|| format!("opening {}", path.display()))?);
+    DDSHeader::read_texture(&mut reader).with_context(|| format!("reading {}", path.display()))
+}
+
+pub fn write_dds(path: impl AsRef<Path>, texture: &Texture, args: &DDSHeaderArgs) -> Result<()> {
+    let path = path.as_ref();
+    let mut writer =
+        BufWriter::new(File::create(path).with_context(|| format!("creating {}", path.display()))?);
+    DDSHeader::write_texture_args(&mut writer, texture, args)
+        .with_context(|| format!("writing {}", path.display()))
+}
@@ -0,0 +1,39 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Assemble six DDS files into a cubemap.
+//!
+//! Usage: `cargo run --example cubemap-assemble -- <output.dds> <+x> <-x> <+y> <-y> <+z> <-z>`
+//!
+//! Every face must have the same format, dimensions, and number of mips
+
+use anyhow::{bail, Result};
+use strum::VariantArray;
+
+use quicktex::shape::{CubeFace, TextureShape};
+use quicktex::texture::Texture;
+
+mod common;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some((output, faces)) = args.split_first().filter(|(_, f)| f.len() == 6) else {
+        bail!("Usage: cubemap-assemble <output.dds> <+x> <-x> <+y> <-y> <+z> <-z>");
+    };
+
+    let faces = CubeFace::VARIANTS
+        .iter()
+        .zip(faces)
+        .map(|(face, path)| Ok((*face, common::read_dds(path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let cubemap = Texture::try_from_faces(faces)?;
+
+    common::write_dds(output, &cubemap, &Default::default())?;
+    println!(
+        "{output}: {:?} cubemap with {} mips",
+        cubemap.format,
+        cubemap.mips().unwrap_or(1)
+    );
+    Ok(())
+}
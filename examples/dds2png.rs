@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Convert every surface of a DDS file to a PNG.
+//!
+//! Usage: `cargo run --example dds2png -- <input.dds> <output.png>`
+//!
+//! A texture with a single surface is written to `output.png`. Otherwise each surface is written
+//! next to it, with its layer, face, and mip appended to the file name, e.g. `output-l0-PositiveX-m2.png`
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+mod common;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input, output] = &args[..] else {
+        bail!("Usage: dds2png <input.dds> <output.png>");
+    };
+    let output = PathBuf::from(output);
+
+    let texture = common::read_dds(input)?;
+    let single = texture.slice().is_surface();
+
+    for item in texture.to_images()? {
+        let image = item.surface;
+
+        let path = if single {
+            output.clone()
+        } else {
+            let mut name = output.file_stem().unwrap_or_default().to_owned();
+            if let Some(layer) = item.layer {
+                name.push(format!("-l{layer}"));
+            }
+            if let Some(face) = item.face {
+                name.push(format!("-{face:?}"));
+            }
+            if let Some(mip) = item.mip {
+                name.push(format!("-m{mip}"));
+            }
+            output.with_file_name(name).with_extension("png")
+        };
+
+        image.save(&path)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,36 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Convert every surface of a DDS file to a different format, keeping its mips, cubemap faces, and array layers.
+//!
+//! Conversions between uncompressed formats repack each pixel exactly, and anything else
+//! is decoded and re-encoded
+//!
+//! Usage: `cargo run --example format-convert -- <input.dds> <output.dds> <format>`
+//!
+//! `format` is one of `rgba8`, `bgra8`, `rgb8`, `bgr8`, `l8`, `bc1`, `bc3`, `bc4`, `bc5`, or `bc7`
+
+use anyhow::{bail, Result};
+
+use quicktex::format::{EncodeOptions, Format};
+
+mod common;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input, output, format] = &args[..] else {
+        bail!("Usage: format-convert <input.dds> <output.dds> <format>");
+    };
+    let format = common::parse_format(format)?;
+
+    let texture = common::read_dds(input)?;
+    let converted = match (texture.format, format) {
+        (Format::Uncompressed { .. }, Format::Uncompressed { .. }) => texture.repack(format)?,
+        _ => texture.encode(format, EncodeOptions::default())?,
+    };
+
+    common::write_dds(output, &converted, &Default::default())?;
+    println!("{output}: {:?} -> {format:?}", texture.format);
+    Ok(())
+}
@@ -0,0 +1,37 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Convert an image to a DDS file with a full mip chain.
+//!
+//! Usage: `cargo run --example png2dds-with-mips -- <input.png> <output.dds> [format]`
+//!
+//! `format` is one of `rgba8` (the default), `bgra8`, `rgb8`, `bgr8`, `l8`, `bc1`, `bc3`, `bc4`, `bc5`, or `bc7`
+
+use anyhow::{bail, Result};
+
+use quicktex::format::EncodeOptions;
+use quicktex::shape::TextureShape;
+use quicktex::texture::Texture;
+
+mod common;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (input, output, format) = match &args[..] {
+        [input, output] => (input, output, "rgba8"),
+        [input, output, format] => (input, output, format.as_str()),
+        _ => bail!("Usage: png2dds-with-mips <input.png> <output.dds> [format]"),
+    };
+    let format = common::parse_format(format)?;
+
+    let texture = Texture::open_image(input, format, true, EncodeOptions::default())?;
+
+    common::write_dds(output, &texture, &Default::default())?;
+    println!(
+        "{output}: {:?} {format:?} with {} mips",
+        texture.try_dimensions()?,
+        texture.mips().unwrap_or(1)
+    );
+    Ok(())
+}
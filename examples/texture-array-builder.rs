@@ -0,0 +1,40 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Combine several DDS files into a single texture array.
+//!
+//! Usage: `cargo run --example texture-array-builder -- <output.dds> <layer.dds>...`
+//!
+//! Every layer must have the same format, dimensions, number of mips, and cubemap faces.
+//! Arrays can only be stored in DDS files with a DX10 header, so one is always written
+
+use anyhow::{bail, Result};
+
+use quicktex::dds::{DDSHeaderArgs, DDSHeaderMode};
+use quicktex::shape::TextureShape;
+use quicktex::texture::Texture;
+
+mod common;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some((output, layers)) = args.split_first().filter(|(_, l)| !l.is_empty()) else {
+        bail!("Usage: texture-array-builder <output.dds> <layer.dds>...");
+    };
+
+    let layers = layers
+        .iter()
+        .map(common::read_dds)
+        .collect::<Result<Vec<_>>>()?;
+    let array = Texture::try_from_layers(layers)?;
+
+    let args = DDSHeaderArgs::default().mode(DDSHeaderMode::ForceDX10);
+    common::write_dds(output, &array, &args)?;
+    println!(
+        "{output}: {:?} array with {} layers",
+        array.format,
+        array.layers().unwrap_or(1)
+    );
+    Ok(())
+}
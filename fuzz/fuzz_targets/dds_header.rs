@@ -0,0 +1,25 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Parse a DDS header and query everything about the texture it describes, without reading surfaces
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use quicktex::container::ContainerHeader;
+use quicktex::dds::DDSHeader;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(header) = DDSHeader::read_header(&mut Cursor::new(data)) else {
+        return;
+    };
+    let _ = header.dimensions();
+    let _ = header.layers();
+    let _ = header.faces();
+    let _ = header.mips();
+    let _ = header.format();
+    let _ = header.diagnostics();
+});
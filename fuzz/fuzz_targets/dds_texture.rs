@@ -0,0 +1,21 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Read every surface of a DDS file and decode it, as a tool loading untrusted downloads would
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use quicktex::container::{ContainerHeader, ReadOptions};
+use quicktex::dds::DDSHeader;
+
+fuzz_target!(|data: &[u8]| {
+    let options = ReadOptions::default().passthrough_unknown(true);
+    let Ok((texture, _)) = DDSHeader::read_texture_options(&mut Cursor::new(data), &options) else {
+        return;
+    };
+    let _ = texture.decode();
+});
@@ -0,0 +1,19 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Read a file with whichever registered container recognizes it, including compressed wrappers,
+//! so new containers are fuzzed as soon as they are added to the registry
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use quicktex::registry::read_any_texture;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(texture) = read_any_texture(&mut Cursor::new(data)) {
+        let _ = texture.decode();
+    }
+});
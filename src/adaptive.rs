@@ -0,0 +1,207 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::error::{TextureError, TextureResult};
+use crate::format::{EncodeOptions, Format};
+use crate::texture::Texture;
+
+/// How [`Texture::encode_adaptive`] chooses a format
+#[derive(Clone, Debug)]
+pub struct AdaptiveStrategy {
+    /// Formats to try, in order of preference. Usually the smallest format comes first,
+    /// followed by larger, higher quality formats to escalate to.
+    /// Formats without an encoder are skipped
+    pub formats: Vec<Format>,
+
+    /// The lowest acceptable peak signal-to-noise ratio in decibels, measured over all four channels
+    /// of every surface. 40dB is visually lossless for most content
+    pub min_psnr: f64,
+
+    /// Options used to encode each candidate
+    pub options: EncodeOptions,
+}
+
+impl Default for AdaptiveStrategy {
+    fn default() -> Self {
+        Self {
+            formats: vec![Format::BC1 { srgb: false }],
+            min_psnr: 40.0,
+            options: EncodeOptions::default(),
+        }
+    }
+}
+
+/// The result of trying a single format in [`Texture::encode_adaptive`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdaptiveAttempt {
+    /// The format that was tried
+    pub format: Format,
+
+    /// The PSNR of the encoded texture in decibels, or `None` if the format has no encoder.
+    /// Lossless results are [`f64::INFINITY`]
+    pub psnr: Option<f64>,
+}
+
+/// What [`Texture::encode_adaptive`] chose for a texture
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdaptiveReport {
+    /// The format of the resulting texture
+    pub format: Format,
+
+    /// The PSNR of the resulting texture in decibels, or [`f64::INFINITY`] if it is lossless
+    pub psnr: f64,
+
+    /// Every format that was tried, in order. The last attempt is the one chosen,
+    /// unless none met the target and the texture was kept uncompressed
+    pub attempts: Vec<AdaptiveAttempt>,
+
+    /// Whether the chosen format meets [`AdaptiveStrategy::min_psnr`].
+    /// If not, the texture was kept uncompressed
+    pub compressed: bool,
+}
+
+/// Peak signal-to-noise ratio in decibels between two equally sized 8-bit buffers
+pub(crate) fn psnr(a: &[u8], b: &[u8]) -> f64 {
+    let squared: u64 = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| (a.abs_diff(b) as u64).pow(2))
+        .sum();
+    match squared {
+        0 => f64::INFINITY,
+        _ => {
+            let mse = squared as f64 / a.len() as f64;
+            10.0 * (255.0 * 255.0 / mse).log10()
+        }
+    }
+}
+
+impl Texture {
+    /// Every surface decoded to 8-bit RGBA, concatenated in [`TextureSlice::iter`](crate::shape::TextureSlice::iter) order
+    fn decoded_bytes(&self) -> TextureResult<Vec<u8>> {
+        Ok(self
+            .decode()?
+            .slice()
+            .iter()
+            .flat_map(|s| s.surface.buffer.iter().copied().collect::<Vec<_>>())
+            .collect())
+    }
+
+    /// Encode the texture in the first of the strategy's formats that meets its quality target,
+    /// measured by decoding the result and comparing it to the original.
+    ///
+    /// Formats are tried in order and the first one to reach [`AdaptiveStrategy::min_psnr`] is kept.
+    /// If none do, the texture is kept uncompressed: unchanged if it already is, or decoded to
+    /// [`Format::rgba8`] otherwise. Returns an error if the texture's own format can't be decoded
+    pub fn encode_adaptive(
+        &self,
+        strategy: &AdaptiveStrategy,
+    ) -> TextureResult<(Texture, AdaptiveReport)> {
+        let original = self.decoded_bytes()?;
+        let mut attempts = Vec::new();
+        for &format in &strategy.formats {
+            let encoded = match self.encode(format, strategy.options) {
+                Ok(encoded) => encoded,
+                Err(TextureError::Format(_)) => {
+                    attempts.push(AdaptiveAttempt { format, psnr: None });
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let psnr = psnr(&original, &encoded.decoded_bytes()?);
+            attempts.push(AdaptiveAttempt {
+                format,
+                psnr: Some(psnr),
+            });
+            if psnr >= strategy.min_psnr {
+                let report = AdaptiveReport {
+                    format,
+                    psnr,
+                    attempts,
+                    compressed: true,
+                };
+                return Ok((encoded, report));
+            }
+        }
+
+        let texture = match self.format {
+            Format::Uncompressed { .. } | Format::Channels { .. } => self.clone(),
+            _ => self.decode()?,
+        };
+        let report = AdaptiveReport {
+            format: texture.format,
+            psnr: f64::INFINITY,
+            attempts,
+            compressed: false,
+        };
+        Ok((texture, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use image::{Rgba, RgbaImage};
+
+    use crate::format::Quality;
+
+    use super::*;
+
+    fn texture(image: RgbaImage) -> Result<Texture> {
+        Ok(Texture::from_image(
+            &image.into(),
+            Format::rgba8(false),
+            false,
+            Quality::Normal,
+        )?)
+    }
+
+    #[test]
+    fn smooth() -> Result<()> {
+        // a gentle gradient between two colors compresses well
+        let smooth = texture(RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([((x + y) * 8) as u8, ((x + y) * 4) as u8, 128, 255])
+        }))?;
+        let strategy = AdaptiveStrategy {
+            min_psnr: 35.0,
+            ..Default::default()
+        };
+        let (encoded, report) = smooth.encode_adaptive(&strategy)?;
+        assert!(report.compressed, "{report:?}");
+        assert_eq!(report.format, Format::BC1 { srgb: false });
+        assert_eq!(encoded.format, report.format);
+        assert!(report.psnr >= 35.0, "{}", report.psnr);
+
+        // but not well enough for a stricter target
+        let (_, strict) = smooth.encode_adaptive(&AdaptiveStrategy::default())?;
+        assert!(!strict.compressed);
+        Ok(())
+    }
+
+    #[test]
+    fn escalate() -> Result<()> {
+        // smooth alpha can't be represented by BC1, so the texture stays uncompressed
+        let gradient = texture(RgbaImage::from_fn(16, 16, |x, _| {
+            Rgba([255, 255, 255, (x * 16) as u8])
+        }))?;
+        let strategy = AdaptiveStrategy {
+            formats: vec![Format::BC1 { srgb: false }, Format::BC2 { srgb: false }],
+            ..Default::default()
+        };
+        let (kept, report) = gradient.encode_adaptive(&strategy)?;
+        assert!(!report.compressed);
+        assert_eq!(kept.format, gradient.format);
+        assert_eq!(report.psnr, f64::INFINITY);
+        assert!(report.attempts[0].psnr.unwrap() < 40.0);
+        assert_eq!(report.attempts[1].psnr, None, "BC2 has no encoder");
+        Ok(())
+    }
+
+    #[test]
+    fn psnr_values() {
+        assert_eq!(psnr(&[1, 2, 3], &[1, 2, 3]), f64::INFINITY);
+        // an error of 1 in every byte
+        assert!((psnr(&[0; 4], &[1; 4]) - 48.13).abs() < 0.01);
+    }
+}
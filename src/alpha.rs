@@ -0,0 +1,316 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Alpha histogram analysis, for choosing how to compress a texture's alpha channel.
+//!
+//! BC1 punch-through alpha stores a single bit per pixel, so it only suits alpha that is already close to
+//! 0 or 255, and the cutoff decides how much of the texture stays visible. The cutoff is chosen to preserve
+//! coverage: the fraction of pixels kept opaque matches the average alpha, so cutouts like foliage don't
+//! visibly shrink or grow. Alpha that can't be thresholded needs BC3 or BC7. BC3 always spends half of every
+//! block on alpha, which suits translucency spread across the whole texture, while BC7 only spends bits on
+//! alpha in blocks that need it, which suits mostly opaque textures with a few translucent areas
+
+use crate::error::TextureResult;
+use crate::format::{EncodeOptions, Format};
+use crate::texture::Texture;
+
+/// How a texture's alpha channel should be compressed, from [`Texture::suggest_alpha`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AlphaRecommendation {
+    /// Every pixel is opaque, so BC1 without transparency is enough
+    Opaque,
+    /// Alpha is close to binary, so BC1 punch-through alpha with [`AlphaSuggestion::threshold`] is enough
+    PunchThrough,
+    /// Translucency is spread across the texture, so BC3's dedicated alpha block is the best fit
+    BC3,
+    /// The texture is mostly opaque with some translucent areas, so BC7 is the best fit
+    BC7,
+}
+
+/// Limits used by [`Texture::suggest_alpha`] to choose an [`AlphaRecommendation`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlphaAnalysisOptions {
+    /// The highest root-mean-square alpha error, out of 255, that punch-through alpha is allowed to introduce
+    pub max_punch_through_error: f64,
+
+    /// The lowest fraction of fully opaque pixels for which BC7 is recommended over BC3
+    pub min_bc7_opaque_fraction: f64,
+}
+
+impl Default for AlphaAnalysisOptions {
+    fn default() -> Self {
+        Self {
+            max_punch_through_error: 8.0,
+            min_bc7_opaque_fraction: 0.5,
+        }
+    }
+}
+
+/// A histogram of alpha values, with a count of pixels for each of the 256 levels
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AlphaHistogram {
+    /// The number of pixels with each alpha value
+    pub counts: [usize; 256],
+}
+
+impl AlphaHistogram {
+    /// Count the alpha values of a buffer of 8-bit RGBA pixels
+    pub fn from_rgba8(buffer: &[u8]) -> Self {
+        let mut histogram = Self { counts: [0; 256] };
+        histogram.add_rgba8(buffer);
+        histogram
+    }
+
+    /// Add the alpha values of a buffer of 8-bit RGBA pixels to the histogram
+    pub fn add_rgba8(&mut self, buffer: &[u8]) {
+        for pixel in buffer.chunks_exact(4) {
+            self.counts[pixel[3] as usize] += 1;
+        }
+    }
+
+    /// The total number of pixels
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// The average alpha value, from 0 to 255
+    pub fn mean(&self) -> f64 {
+        match self.total() {
+            0 => 255.0,
+            total => {
+                let sum: usize = self.counts.iter().enumerate().map(|(a, &c)| a * c).sum();
+                sum as f64 / total as f64
+            }
+        }
+    }
+
+    /// The fraction of pixels with alpha of at least `threshold`, which punch-through alpha keeps opaque
+    pub fn coverage(&self, threshold: u8) -> f64 {
+        match self.total() {
+            0 => 1.0,
+            total => self.counts[threshold as usize..].iter().sum::<usize>() as f64 / total as f64,
+        }
+    }
+
+    /// The root-mean-square alpha error of rounding every pixel to 0 or 255 at `threshold`
+    pub fn punch_through_error(&self, threshold: u8) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        let squared: f64 = (self.counts.iter().enumerate())
+            .map(|(a, &c)| {
+                let error = match a >= threshold as usize {
+                    true => 255 - a,
+                    false => a,
+                };
+                (error * error * c) as f64
+            })
+            .sum();
+        (squared / total as f64).sqrt()
+    }
+
+    /// The threshold whose coverage is closest to the average alpha, so thresholding keeps the same
+    /// fraction of the texture visible. Ties go to the threshold closest to 128
+    pub fn coverage_threshold(&self) -> u8 {
+        let target = self.mean() / 255.0;
+        (1..=255u8)
+            .min_by(|&a, &b| {
+                let distance = |t: u8| (self.coverage(t) - target).abs();
+                distance(a)
+                    .total_cmp(&distance(b))
+                    .then((a as i32 - 128).abs().cmp(&(b as i32 - 128).abs()))
+            })
+            .unwrap()
+    }
+}
+
+/// The result of analyzing a texture's alpha channel, from [`Texture::suggest_alpha`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlphaSuggestion {
+    /// How the alpha channel should be compressed
+    pub recommendation: AlphaRecommendation,
+
+    /// The best cutoff for punch-through alpha, whatever the recommendation. 0 if every pixel is opaque
+    pub threshold: u8,
+
+    /// The root-mean-square alpha error of punch-through alpha at [`Self::threshold`]
+    pub punch_through_error: f64,
+
+    /// The histogram the suggestion was made from
+    pub histogram: AlphaHistogram,
+}
+
+impl AlphaSuggestion {
+    /// Analyze a histogram of alpha values
+    pub fn from_histogram(histogram: AlphaHistogram, options: &AlphaAnalysisOptions) -> Self {
+        let total = histogram.total();
+        if histogram.counts[255] == total {
+            return Self {
+                recommendation: AlphaRecommendation::Opaque,
+                threshold: 0,
+                punch_through_error: 0.0,
+                histogram,
+            };
+        }
+
+        let threshold = histogram.coverage_threshold();
+        let punch_through_error = histogram.punch_through_error(threshold);
+        let opaque = histogram.counts[255] as f64 / total as f64;
+        let recommendation = if punch_through_error <= options.max_punch_through_error {
+            AlphaRecommendation::PunchThrough
+        } else if opaque >= options.min_bc7_opaque_fraction {
+            AlphaRecommendation::BC7
+        } else {
+            AlphaRecommendation::BC3
+        };
+        Self {
+            recommendation,
+            threshold,
+            punch_through_error,
+            histogram,
+        }
+    }
+
+    /// The recommended format
+    pub fn format(&self, srgb: bool) -> Format {
+        match self.recommendation {
+            AlphaRecommendation::Opaque | AlphaRecommendation::PunchThrough => Format::BC1 { srgb },
+            AlphaRecommendation::BC3 => Format::BC3 { srgb },
+            AlphaRecommendation::BC7 => Format::BC7 { srgb },
+        }
+    }
+
+    /// `options` with the alpha threshold set to the suggested one
+    pub fn encode_options(&self, options: EncodeOptions) -> EncodeOptions {
+        options.alpha_threshold(self.threshold)
+    }
+}
+
+impl Texture {
+    /// Analyze the alpha channel of the texture's top mip of each face and layer,
+    /// and suggest a format and punch-through threshold to encode it with.
+    /// Returns an error if the texture's format can't be decoded
+    pub fn suggest_alpha(&self, options: &AlphaAnalysisOptions) -> TextureResult<AlphaSuggestion> {
+        let decoded = self.decode()?;
+        let mut histogram = AlphaHistogram { counts: [0; 256] };
+        for s in decoded.slice().iter() {
+            if s.mip.unwrap_or(0) == 0 {
+                histogram.add_rgba8(&s.surface.buffer);
+            }
+        }
+        Ok(AlphaSuggestion::from_histogram(histogram, options))
+    }
+
+    /// Encode the texture in the format suggested by [`Self::suggest_alpha`], with its suggested threshold
+    pub fn encode_suggested_alpha(
+        &self,
+        options: EncodeOptions,
+    ) -> TextureResult<(Texture, AlphaSuggestion)> {
+        let suggestion = self.suggest_alpha(&AlphaAnalysisOptions::default())?;
+        let encoded = self.encode(
+            suggestion.format(self.format.srgb()),
+            suggestion.encode_options(options),
+        )?;
+        Ok((encoded, suggestion))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use image::{Rgba, RgbaImage};
+
+    use crate::format::Quality;
+    use crate::shape::TextureShape;
+
+    use super::*;
+
+    fn texture(alpha: impl Fn(u32, u32) -> u8) -> Result<Texture> {
+        let image = RgbaImage::from_fn(16, 16, |x, y| Rgba([200, 100, 50, alpha(x, y)]));
+        Ok(Texture::from_image(
+            &image.into(),
+            Format::rgba8(false),
+            true,
+            Quality::Normal,
+        )?)
+    }
+
+    #[test]
+    fn histogram() {
+        // a quarter of pixels at each of 0, 64, 192, and 255
+        let buffer: Vec<u8> = [0, 64, 192, 255]
+            .iter()
+            .flat_map(|&a| [0, 0, 0, a].repeat(4))
+            .collect();
+        let histogram = AlphaHistogram::from_rgba8(&buffer);
+        assert_eq!(histogram.total(), 16);
+        assert_eq!(histogram.mean(), 127.75);
+        assert_eq!(histogram.coverage(128), 0.5);
+        assert_eq!(histogram.coverage_threshold(), 128);
+        assert_eq!(
+            histogram.punch_through_error(128),
+            ((64.0f64 * 64.0 + 63.0 * 63.0) / 4.0).sqrt()
+        );
+    }
+
+    #[test]
+    fn coverage_threshold() {
+        // a cutout with faint edges, where a threshold of 128 would drop every edge pixel and shrink it.
+        // Keeping the pixels at 100 but not at 60 is the closest to its average alpha
+        let buffer: Vec<u8> = [(0, 6), (60, 2), (100, 2), (255, 2)]
+            .iter()
+            .flat_map(|&(a, n)| [0, 0, 0, a].repeat(n))
+            .collect();
+        let histogram = AlphaHistogram::from_rgba8(&buffer);
+        assert_eq!(histogram.coverage_threshold(), 100);
+        assert_eq!(histogram.coverage(100), 4.0 / 12.0);
+    }
+
+    #[test]
+    fn recommendations() -> Result<()> {
+        let options = AlphaAnalysisOptions::default();
+        let opaque = texture(|_, _| 255)?.suggest_alpha(&options)?;
+        assert_eq!(opaque.recommendation, AlphaRecommendation::Opaque);
+        assert_eq!(opaque.threshold, 0);
+        assert_eq!(opaque.format(true), Format::BC1 { srgb: true });
+
+        // mips are ignored, even though filtering softens the cutout's edges
+        let cutout = texture(|x, _| if x < 8 { 0 } else { 255 })?.suggest_alpha(&options)?;
+        assert_eq!(cutout.recommendation, AlphaRecommendation::PunchThrough);
+        assert_eq!(cutout.punch_through_error, 0.0);
+        assert_eq!(cutout.histogram.total(), 256);
+
+        let gradient = texture(|x, _| (x * 16) as u8)?.suggest_alpha(&options)?;
+        assert_eq!(gradient.recommendation, AlphaRecommendation::BC3);
+        assert_eq!(gradient.format(false), Format::BC3 { srgb: false });
+
+        let decal = texture(|x, y| if x < 4 && y < 4 { (x * 60) as u8 } else { 255 })?;
+        let decal = decal.suggest_alpha(&options)?;
+        assert_eq!(decal.recommendation, AlphaRecommendation::BC7);
+        Ok(())
+    }
+
+    #[test]
+    fn encode_suggested() -> Result<()> {
+        let cutout = texture(|x, y| if x + y < 12 { 4 } else { 250 })?;
+        let (encoded, suggestion) = cutout.encode_suggested_alpha(EncodeOptions::default())?;
+        assert_eq!(encoded.format, Format::BC1 { srgb: false });
+        assert_eq!(
+            suggestion
+                .encode_options(EncodeOptions::default())
+                .alpha_threshold,
+            suggestion.threshold
+        );
+
+        // every pixel should be either transparent or opaque on the same side of the cutoff
+        let decoded = encoded.decode()?;
+        let original = cutout.decode()?;
+        let (decoded, original) = (&decoded.primary().buffer, &original.primary().buffer);
+        for (d, o) in decoded.chunks(4).zip(original.chunks(4)) {
+            assert_eq!(d[3] == 255, o[3] >= suggestion.threshold);
+        }
+        Ok(())
+    }
+}
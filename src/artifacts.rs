@@ -0,0 +1,323 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Finding the blocks of a BC texture most likely to show compression artifacts.
+//!
+//! A BC block can only hold a few colors interpolated along a line, so blocks whose decoded pixels still vary
+//! a lot are the ones where detail didn't fit, and usually where banding and blockiness show up. Blocks whose
+//! pixels are all the same are solid, which every format stores well. A block whose endpoints are equal can
+//! only hold one color, so degenerate endpoints in a block that isn't solid mean the encoder gave up on it.
+//! Textures with many flagged blocks are candidates for a higher quality format, such as BC7 instead of BC1
+
+use itertools::iproduct;
+
+use crate::blocktexture::Block;
+use crate::bptc::bc7::{BC7Block, MODES};
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::error::{TextureError, TextureResult};
+use crate::format::Format;
+use crate::shape::CubeFace;
+use crate::stats::has_endpoints;
+use crate::texture::Texture;
+
+/// Limits used by [`Texture::artifact_report`] to flag blocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArtifactOptions {
+    /// The lowest variance of a block's decoded pixels for it to be flagged, as the mean squared distance
+    /// of each pixel's RGBA color from the block's average color, in 8-bit units
+    pub variance_threshold: f64,
+}
+
+impl Default for ArtifactOptions {
+    fn default() -> Self {
+        Self {
+            variance_threshold: 1000.0,
+        }
+    }
+}
+
+/// A block flagged by [`Texture::artifact_report`] for high variance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlaggedBlock {
+    /// Column of the block, counted in blocks from the left edge of the surface
+    pub x: u32,
+    /// Row of the block, counted in blocks from the top edge of the surface
+    pub y: u32,
+    /// Depth slice of the block, or 0 if the surface is not a volume
+    pub z: u32,
+    /// The variance of the block's decoded pixels. See [`ArtifactOptions::variance_threshold`]
+    pub variance: f64,
+}
+
+/// Artifact analysis for a single surface, from [`Texture::artifact_report`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurfaceArtifacts {
+    /// The array layer of the surface, if the texture is an array
+    pub layer: Option<usize>,
+
+    /// The cubemap face of the surface, if the texture is a cubemap
+    pub face: Option<CubeFace>,
+
+    /// The mip level of the surface, if the texture has mipmaps
+    pub mip: Option<usize>,
+
+    /// Dimensions of the surface
+    pub dimensions: Dimensions,
+
+    /// Total number of blocks in the surface
+    pub blocks: usize,
+
+    /// Number of blocks whose decoded pixels are all the same color
+    pub solid_blocks: usize,
+
+    /// Number of endpoint pairs in the surface whose two endpoints are equal, counting each channel block of
+    /// BC3 and BC5 and each subset of BC7 separately. Solid blocks are not counted, since equal endpoints are
+    /// the best way to store them
+    pub degenerate_endpoints: usize,
+
+    /// Blocks whose variance is at least [`ArtifactOptions::variance_threshold`], in the order they are stored
+    pub flagged: Vec<FlaggedBlock>,
+}
+
+impl SurfaceArtifacts {
+    /// The fraction of blocks flagged for high variance, from 0 to 1
+    pub fn flagged_fraction(&self) -> f64 {
+        match self.blocks {
+            0 => 0.0,
+            blocks => self.flagged.len() as f64 / blocks as f64,
+        }
+    }
+}
+
+/// Artifact analysis for every surface of a texture, from [`Texture::artifact_report`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArtifactReport {
+    /// Analysis of each surface, in the same order as [`TextureSlice::iter`](crate::shape::TextureSlice::iter)
+    pub surfaces: Vec<SurfaceArtifacts>,
+}
+
+impl ArtifactReport {
+    /// Total number of blocks in the texture
+    pub fn blocks(&self) -> usize {
+        self.surfaces.iter().map(|s| s.blocks).sum()
+    }
+
+    /// Total number of solid blocks in the texture
+    pub fn solid_blocks(&self) -> usize {
+        self.surfaces.iter().map(|s| s.solid_blocks).sum()
+    }
+
+    /// Total number of degenerate endpoint pairs in the texture
+    pub fn degenerate_endpoints(&self) -> usize {
+        self.surfaces.iter().map(|s| s.degenerate_endpoints).sum()
+    }
+
+    /// The fraction of blocks in the texture flagged for high variance, from 0 to 1
+    pub fn flagged_fraction(&self) -> f64 {
+        let flagged = self.surfaces.iter().map(|s| s.flagged.len()).sum::<usize>();
+        match self.blocks() {
+            0 => 0.0,
+            blocks => flagged as f64 / blocks as f64,
+        }
+    }
+}
+
+/// Number of endpoint pairs in a single block of `format` whose two endpoints are equal
+fn degenerate_pairs(format: Format, block: &[u8]) -> usize {
+    let color = |b: &[u8]| (b[0..2] == b[2..4]) as usize;
+    let channel = |b: &[u8]| (b[0] == b[1]) as usize;
+    match format {
+        Format::BC1 { .. } => color(block),
+        Format::BC2 { .. } => color(&block[8..]),
+        Format::BC3 { .. } => channel(block) + color(&block[8..]),
+        Format::BC4 { .. } => channel(block),
+        Format::BC5 { .. } => channel(block) + channel(&block[8..]),
+        Format::BC7 { .. } => {
+            let block = BC7Block::from_bytes(block.try_into().expect("BC7 blocks are 16 bytes"));
+            match block.mode() {
+                None => 0,
+                Some(mode) => block.colors()[..MODES[mode].subsets]
+                    .iter()
+                    .filter(|[e0, e1]| e0 == e1)
+                    .count(),
+            }
+        }
+        _ => unreachable!("degenerate endpoints are only counted for BC formats"),
+    }
+}
+
+/// The variance of a set of RGBA pixels. See [`ArtifactOptions::variance_threshold`]
+fn variance(pixels: &[[u8; 4]]) -> f64 {
+    let count = pixels.len() as f64;
+    let mean: [f64; 4] =
+        std::array::from_fn(|c| pixels.iter().map(|p| p[c] as f64).sum::<f64>() / count);
+    pixels
+        .iter()
+        .map(|p| (0..4).map(|c| (p[c] as f64 - mean[c]).powi(2)).sum::<f64>())
+        .sum::<f64>()
+        / count
+}
+
+impl Texture {
+    /// Flag the blocks of each surface that are likely to show compression artifacts, and count solid blocks
+    /// and degenerate endpoints. See the [module docs](crate::artifacts) for what each of these means.
+    /// Every surface is decoded to measure its blocks.
+    /// Returns [`TextureError::Format`] for formats other than BC1 through BC5 and BC7,
+    /// or an error if any surface does not match the texture's format
+    pub fn artifact_report(&self, options: &ArtifactOptions) -> TextureResult<ArtifactReport> {
+        if !has_endpoints(self.format) {
+            return Err(TextureError::Format(format!(
+                "Artifact analysis is only available for BC textures, not {:?}",
+                self.format
+            )));
+        }
+        self.check_format()?;
+        let block_size = self.format.size_for(Dimensions::try_from([1])?)?;
+        let decoded = self.decode()?;
+
+        let surfaces = self
+            .slice()
+            .iter()
+            .zip(decoded.slice().iter())
+            .map(|(s, d)| {
+                let dimensions = s.surface.dimensions();
+                let (width, height) = (dimensions.width(), dimensions.height());
+                let blocks = dimensions.blocks(self.format.block_dimensions()?);
+                let mut report = SurfaceArtifacts {
+                    layer: s.layer,
+                    face: s.face,
+                    mip: s.mip,
+                    dimensions,
+                    blocks: 0,
+                    solid_blocks: 0,
+                    degenerate_endpoints: 0,
+                    flagged: Vec::new(),
+                };
+
+                // blocks are stored slice by slice, then row by row
+                let positions =
+                    iproduct!(0..dimensions.depth(), 0..blocks.height(), 0..blocks.width());
+                for ((z, y, x), block) in positions.zip(s.surface.buffer.chunks_exact(block_size)) {
+                    let pixels = iproduct!(
+                        y * 4..(y * 4 + 4).min(height),
+                        x * 4..(x * 4 + 4).min(width)
+                    )
+                    .map(|(py, px)| {
+                        let i = (((z * height + py) * width + px) * 4) as usize;
+                        d.surface.buffer[i..i + 4].try_into().unwrap()
+                    })
+                    .collect::<Vec<[u8; 4]>>();
+
+                    report.blocks += 1;
+                    if pixels.iter().all(|p| *p == pixels[0]) {
+                        report.solid_blocks += 1;
+                        continue;
+                    }
+                    report.degenerate_endpoints += degenerate_pairs(self.format, block);
+                    let variance = variance(&pixels);
+                    if variance >= options.variance_threshold {
+                        report.flagged.push(FlaggedBlock { x, y, z, variance });
+                    }
+                }
+                Ok(report)
+            })
+            .collect::<TextureResult<_>>()?;
+        Ok(ArtifactReport { surfaces })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use anyhow::Result;
+
+    use crate::container::ContainerHeader;
+    use crate::dds::DDSHeader;
+    use crate::format::Quality;
+    use crate::shape::TextureShapeNode;
+    use crate::texture::Surface;
+
+    use super::*;
+
+    fn single(format: Format, dimensions: Dimensions, buffer: Vec<u8>) -> Result<Texture> {
+        Ok(Texture::new(
+            format,
+            TextureShapeNode::from_surface(Surface::new(dimensions, buffer)),
+        )?)
+    }
+
+    #[test]
+    fn bc1_blocks() -> Result<()> {
+        let blocks = [
+            // white and black checkerboard columns
+            [0xFF, 0xFF, 0x00, 0x00, 0x44, 0x44, 0x44, 0x44],
+            // red with equal endpoints, and a transparent top row
+            [0x00, 0xF8, 0x00, 0xF8, 0xFF, 0x00, 0x00, 0x00],
+            // solid blue
+            [0x1F, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00],
+        ];
+        let texture = single(
+            Format::BC1 { srgb: false },
+            Dimensions::try_from([12, 4])?,
+            blocks.concat(),
+        )?;
+        let report = texture.artifact_report(&Default::default())?;
+        let surface = &report.surfaces[0];
+        assert_eq!(surface.blocks, 3);
+        assert_eq!(surface.solid_blocks, 1);
+        assert_eq!(surface.degenerate_endpoints, 1);
+        assert_eq!(
+            surface
+                .flagged
+                .iter()
+                .map(|b| (b.x, b.y, b.z))
+                .collect::<Vec<_>>(),
+            [(0, 0, 0), (1, 0, 0)]
+        );
+        assert_eq!(surface.flagged[0].variance, 3.0 * 127.5 * 127.5);
+
+        let strict = ArtifactOptions {
+            variance_threshold: 30000.0,
+        };
+        let report = texture.artifact_report(&strict)?;
+        assert_eq!(report.surfaces[0].flagged.len(), 1);
+        assert_eq!(report.flagged_fraction(), 1.0 / 3.0);
+        Ok(())
+    }
+
+    #[test]
+    fn textures() -> Result<()> {
+        let dimensions = Dimensions::try_from([8, 8])?;
+        let solid = single(
+            Format::rgba8(false),
+            dimensions,
+            [200u8, 100, 50, 255].repeat(64),
+        )?;
+        for format in [Format::BC1 { srgb: false }, Format::BC7 { srgb: false }] {
+            let report = solid
+                .transcode(format, Quality::Fastest)?
+                .artifact_report(&Default::default())?;
+            assert_eq!(report.blocks(), 4, "{format:?}");
+            assert_eq!(report.solid_blocks(), 4, "{format:?}");
+            assert_eq!(report.degenerate_endpoints(), 0, "{format:?}");
+            assert_eq!(report.flagged_fraction(), 0.0, "{format:?}");
+        }
+        assert!(matches!(
+            solid.artifact_report(&Default::default()),
+            Err(TextureError::Format(_))
+        ));
+
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/images/dds/peppers16 bc1.dds"
+        );
+        let peppers = DDSHeader::read_texture(&mut File::open(path)?)?;
+        let report = peppers.artifact_report(&Default::default())?;
+        assert_eq!(report.surfaces.len(), 5);
+        assert_eq!(report.blocks(), 16 + 4 + 1 + 1 + 1);
+        assert!(report.surfaces.iter().all(|s| s.flagged.len() <= s.blocks));
+        Ok(())
+    }
+}
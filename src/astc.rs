@@ -0,0 +1,309 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The `.astc` container written by ARM's `astcenc`, holding a single 2D or 3D surface of ASTC blocks.
+//!
+//! The header is 16 bytes: a magic number, the block footprint as three bytes, and the width, height,
+//! and depth of the surface as 24-bit little-endian integers. The blocks follow immediately.
+//! The container doesn't record whether the surface is sRGB, so textures are always read as linear
+
+use std::io::{Read, Seek, Write};
+
+use binrw::binrw;
+use enumflags2::make_bitflags;
+
+use crate::capability::{ContainerCapabilities, FormatFamily, ShapeFeature};
+use crate::container::{packed_offsets, ContainerHeader};
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::format::Format;
+use crate::shape::{CubeFace, TextureShapeNode};
+use crate::texture::{SurfaceOffset, SurfaceOrder, SurfaceReader, Surfaces, Texture};
+
+/// Every 2D block footprint ASTC allows, as width and height
+pub const FOOTPRINTS_2D: [[u8; 2]; 14] = [
+    [4, 4],
+    [5, 4],
+    [5, 5],
+    [6, 5],
+    [6, 6],
+    [8, 5],
+    [8, 6],
+    [8, 8],
+    [10, 5],
+    [10, 6],
+    [10, 8],
+    [10, 10],
+    [12, 10],
+    [12, 12],
+];
+
+/// Every 3D block footprint ASTC allows, as width, height, and depth
+pub const FOOTPRINTS_3D: [[u8; 3]; 10] = [
+    [3, 3, 3],
+    [4, 3, 3],
+    [4, 4, 3],
+    [4, 4, 4],
+    [5, 4, 4],
+    [5, 5, 4],
+    [5, 5, 5],
+    [6, 5, 5],
+    [6, 6, 5],
+    [6, 6, 6],
+];
+
+/// Returns true if `block_dims` is one of the block footprints ASTC allows,
+/// listed in [`FOOTPRINTS_2D`] and [`FOOTPRINTS_3D`]
+pub fn is_valid_footprint(block_dims: Dimensions) -> bool {
+    let axes = |d: &[u8]| d.iter().map(|&a| a as u32).collect::<Vec<_>>();
+    let dims = block_dims.into_iter().collect::<Vec<_>>();
+    match block_dims {
+        Dimensions::_1D(_) => false,
+        Dimensions::_2D(_) => FOOTPRINTS_2D.iter().any(|f| axes(f) == dims),
+        Dimensions::_3D(_) => FOOTPRINTS_3D.iter().any(|f| axes(f) == dims),
+    }
+}
+
+/// The header of an `.astc` file. See the [module documentation](self) for the layout
+#[binrw]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[brw(little, magic = b"\x13\xAB\xA1\x5C")]
+pub struct ASTCHeader {
+    /// Width, height, and depth of a block in pixels. The depth is 1 for 2D blocks
+    pub block_dims: [u8; 3],
+    /// Width of the surface in pixels, as a 24-bit little-endian integer
+    pub width: [u8; 3],
+    /// Height of the surface in pixels, as a 24-bit little-endian integer
+    pub height: [u8; 3],
+    /// Depth of the surface in pixels, as a 24-bit little-endian integer. 1 for 2D surfaces
+    pub depth: [u8; 3],
+}
+
+/// Read a 24-bit little-endian integer
+fn read_u24([a, b, c]: [u8; 3]) -> u32 {
+    u32::from_le_bytes([a, b, c, 0])
+}
+
+/// Write a 24-bit little-endian integer, or return an error if it is too large
+fn write_u24(value: u32) -> TextureResult<[u8; 3]> {
+    match value.to_le_bytes() {
+        [a, b, c, 0] => Ok([a, b, c]),
+        _ => Err(TextureError::Capability(format!(
+            "ASTC files cannot store surfaces {value} pixels wide"
+        ))),
+    }
+}
+
+impl ContainerHeader for ASTCHeader {
+    type Args = ();
+
+    const CAPABILITIES: ContainerCapabilities = ContainerCapabilities {
+        formats: make_bitflags!(FormatFamily::{ASTC}),
+        shapes: make_bitflags!(ShapeFeature::{Volumes}),
+    };
+
+    fn read_surfaces<R: Read + Seek>(&self, reader: &mut R) -> TextureResult<Surfaces> {
+        let mut surface_reader = SurfaceReader {
+            format: self.format()?,
+            reader,
+        };
+        surface_reader.read_surface(self.dimensions()?)
+    }
+
+    fn write_surfaces<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        surfaces: &Surfaces,
+    ) -> TextureResult<()> {
+        let TextureShapeNode::Surface(surface) = surfaces else {
+            return Err(TextureError::Capability(
+                "ASTC files can only contain a single surface".to_string(),
+            ));
+        };
+        writer.write_all(&surface.buffer)?;
+        Ok(())
+    }
+
+    fn surface_offsets(&self) -> TextureResult<Box<dyn Iterator<Item = SurfaceOffset>>> {
+        packed_offsets(self, SurfaceOrder::LayersFacesMips)
+    }
+
+    fn from_texture_args(texture: &Texture, _args: &()) -> TextureResult<Self> {
+        let TextureShapeNode::Surface(surface) = &texture.surfaces else {
+            return Err(TextureError::Capability(
+                "ASTC files cannot contain mipmaps, cubemaps, or arrays".to_string(),
+            ));
+        };
+        let Format::ASTC { block_dims, .. } = texture.format else {
+            return Err(TextureError::Format(format!(
+                "ASTC files can only contain ASTC surfaces, not {:?}",
+                texture.format
+            )));
+        };
+        if !is_valid_footprint(block_dims) {
+            return Err(TextureError::Format(format!(
+                "{block_dims:?} is not a valid ASTC block footprint"
+            )));
+        }
+        if let Dimensions::_1D(_) = surface.dimensions {
+            return Err(TextureError::Capability(
+                "ASTC files cannot contain 1D surfaces".to_string(),
+            ));
+        }
+
+        let dims = surface.dimensions;
+        Ok(Self {
+            block_dims: [block_dims.width(), block_dims.height(), block_dims.depth()]
+                .map(|a| a as u8),
+            width: write_u24(dims.width())?,
+            height: write_u24(dims.height())?,
+            depth: write_u24(dims.depth())?,
+        })
+    }
+
+    fn dimensions(&self) -> TextureResult<Dimensions> {
+        let [width, height, depth] = [self.width, self.height, self.depth].map(read_u24);
+        Ok(match depth {
+            1 => Dimensions::try_from([width, height])?,
+            _ => Dimensions::try_from([width, height, depth])?,
+        })
+    }
+
+    fn layers(&self) -> TextureResult<Option<usize>> {
+        Ok(None)
+    }
+
+    fn faces(&self) -> TextureResult<Option<Vec<CubeFace>>> {
+        Ok(None)
+    }
+
+    fn mips(&self) -> TextureResult<Option<usize>> {
+        Ok(None)
+    }
+
+    fn format(&self) -> TextureResult<Format> {
+        let [width, height, depth] = self.block_dims.map(u32::from);
+        let block_dims = match depth {
+            1 => Dimensions::try_from([width, height]),
+            _ => Dimensions::try_from([width, height, depth]),
+        };
+        match block_dims {
+            Ok(block_dims) if is_valid_footprint(block_dims) => Ok(Format::ASTC {
+                block_dims,
+                srgb: false,
+            }),
+            _ => Err(TextureError::Format(format!(
+                "{width}x{height}x{depth} is not a valid ASTC block footprint"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+
+    use crate::dimensions::Dimensioned;
+    use crate::texture::Surface;
+
+    use super::*;
+
+    fn texture(block_dims: Dimensions, dimensions: Dimensions) -> Result<Texture> {
+        let format = Format::ASTC {
+            block_dims,
+            srgb: false,
+        };
+        let size = format.size_for(dimensions)?;
+        let buffer = (0..size).map(|i| i as u8).collect::<Vec<_>>();
+        Ok(Texture::new(
+            format,
+            TextureShapeNode::from_surface(Surface::new(dimensions, buffer)),
+        )?)
+    }
+
+    #[test]
+    fn sizes() -> Result<()> {
+        let format = |dims: [u32; 2]| -> Result<Format> {
+            Ok(Format::ASTC {
+                block_dims: Dimensions::try_from(dims)?,
+                srgb: false,
+            })
+        };
+        let dimensions = Dimensions::try_from([64, 30])?;
+        assert_eq!(format([4, 4])?.size_for(dimensions)?, 16 * 16 * 8);
+        assert_eq!(format([12, 12])?.size_for(dimensions)?, 16 * 6 * 3);
+        assert_eq!(format([8, 5])?.size_for(dimensions)?, 16 * 8 * 6);
+
+        let volume = Format::ASTC {
+            block_dims: Dimensions::try_from([3, 3, 3])?,
+            srgb: false,
+        };
+        let dimensions = Dimensions::try_from([8, 8, 4])?;
+        assert_eq!(volume.size_for(dimensions)?, 16 * 3 * 3 * 2);
+        assert_eq!(volume.block_dimensions()?, Dimensions::try_from([3, 3, 3])?);
+        Ok(())
+    }
+
+    #[test]
+    fn footprints() -> Result<()> {
+        assert!(is_valid_footprint(Dimensions::try_from([10, 8])?));
+        assert!(is_valid_footprint(Dimensions::try_from([5, 5, 4])?));
+        assert!(!is_valid_footprint(Dimensions::try_from([8, 10])?));
+        assert!(!is_valid_footprint(Dimensions::try_from([4, 4, 1])?));
+        assert!(!is_valid_footprint(Dimensions::try_from([4])?));
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        for (block_dims, dimensions) in [
+            (
+                Dimensions::try_from([6, 5])?,
+                Dimensions::try_from([13, 7])?,
+            ),
+            (
+                Dimensions::try_from([4, 4, 4])?,
+                Dimensions::try_from([8, 8, 5])?,
+            ),
+        ] {
+            let texture = texture(block_dims, dimensions)?;
+            let mut file = Cursor::new(Vec::new());
+            ASTCHeader::write_texture(&mut file, &texture)?;
+            let bytes = file.get_ref();
+            assert_eq!(bytes[..4], [0x13, 0xAB, 0xA1, 0x5C]);
+            assert_eq!(bytes[7..10], [dimensions.width() as u8, 0, 0]);
+            assert_eq!(bytes.len(), 16 + texture.format.size_for(dimensions)?);
+
+            file.set_position(0);
+            let read = ASTCHeader::read_texture(&mut file)?;
+            assert_eq!(read.format, texture.format);
+            assert_eq!(read.dimensions(), dimensions);
+            assert_eq!(
+                read.slice().try_into_surface().unwrap().buffer,
+                texture.slice().try_into_surface().unwrap().buffer
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        let mut header = vec![0x13, 0xAB, 0xA1, 0x5C, 7, 7, 1, 4, 0, 0, 4, 0, 0, 1, 0, 0];
+        header.extend([0; 16]);
+        assert!(ASTCHeader::read_texture(&mut Cursor::new(&header)).is_err());
+
+        header[4..6].copy_from_slice(&[4, 4]);
+        assert!(ASTCHeader::read_texture(&mut Cursor::new(&header)).is_ok());
+        assert!(ASTCHeader::read_texture(&mut Cursor::new(&header[..20])).is_err());
+
+        let bc1 = Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(Surface::new(Dimensions::try_from([4, 4])?, [0; 8])),
+        )?;
+        assert!(ASTCHeader::write_texture(&mut Cursor::new(Vec::new()), &bc1).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Sequential bit readers and writers for block codecs.
+//!
+//! Blocks are treated as a little-endian stream of bits: the first bit is the least significant bit
+//! of the first byte, and each field is read least significant bit first. This is the layout used by
+//! every BCn format, so mode bits, endpoints, and indices can be read in the order they are specified
+
+use bitvec::field::BitField;
+use bitvec::prelude::*;
+use funty::Integral;
+use thiserror::Error;
+
+use crate::pack::{fits, PackError};
+
+/// Errors from reading or writing a bitstream
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitError {
+    /// A field extends past the end of the stream
+    #[error("Cannot access {requested} bits with only {remaining} remaining")]
+    Overrun {
+        /// Length of the field in bits
+        requested: usize,
+        /// Bits left in the stream
+        remaining: usize,
+    },
+
+    /// A field is wider than the integer type it is read into or written from
+    #[error("Cannot access {requested} bits as a {bits}-bit integer")]
+    Width {
+        /// Length of the field in bits
+        requested: usize,
+        /// Width of the integer type
+        bits: u32,
+    },
+
+    /// A value does not fit in its field
+    #[error(transparent)]
+    Pack(#[from] PackError),
+}
+
+/// Result type for bitstream operations
+pub type BitResult<T = ()> = Result<T, BitError>;
+
+fn check_width<V: Integral>(n: usize) -> BitResult {
+    match n <= V::BITS as usize {
+        true => Ok(()),
+        false => Err(BitError::Width {
+            requested: n,
+            bits: V::BITS,
+        }),
+    }
+}
+
+/// Reads fields sequentially from a byte slice. See the [module documentation](self) for the bit order
+#[derive(Clone, Debug)]
+pub struct BitReader<'a> {
+    bits: &'a BitSlice<u8, Lsb0>,
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// Create a reader starting at the first bit of `bytes`
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bits: bytes.view_bits(),
+            position: 0,
+        }
+    }
+
+    /// The number of bits read so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The number of bits left to read
+    pub fn remaining(&self) -> usize {
+        self.bits.len() - self.position
+    }
+
+    fn field(&self, n: usize) -> BitResult<&'a BitSlice<u8, Lsb0>> {
+        match n <= self.remaining() {
+            true => Ok(&self.bits[self.position..self.position + n]),
+            false => Err(BitError::Overrun {
+                requested: n,
+                remaining: self.remaining(),
+            }),
+        }
+    }
+
+    /// Read the next `n` bits without advancing the reader.
+    /// Signed types are sign-extended from the top bit of the field
+    pub fn peek<V: Integral>(&self, n: usize) -> BitResult<V> {
+        check_width::<V>(n)?;
+        match n {
+            0 => Ok(V::ZERO),
+            n => Ok(self.field(n)?.load_le()),
+        }
+    }
+
+    /// Read the next `n` bits. Signed types are sign-extended from the top bit of the field
+    pub fn read<V: Integral>(&mut self, n: usize) -> BitResult<V> {
+        let value = self.peek(n)?;
+        self.position += n;
+        Ok(value)
+    }
+
+    /// Read a single bit
+    pub fn read_bool(&mut self) -> BitResult<bool> {
+        let bit = self.field(1)?[0];
+        self.position += 1;
+        Ok(bit)
+    }
+
+    /// Read `N` consecutive fields of `n` bits each
+    pub fn read_array<V: Integral, const N: usize>(&mut self, n: usize) -> BitResult<[V; N]> {
+        let mut values = [V::ZERO; N];
+        for value in &mut values {
+            *value = self.read(n)?;
+        }
+        Ok(values)
+    }
+
+    /// Skip the next `n` bits
+    pub fn skip(&mut self, n: usize) -> BitResult {
+        self.field(n)?;
+        self.position += n;
+        Ok(())
+    }
+
+    /// Skip ahead to the next multiple of `alignment` bits, if not already aligned
+    pub fn align(&mut self, alignment: usize) -> BitResult {
+        self.skip(self.position.next_multiple_of(alignment) - self.position)
+    }
+}
+
+/// Writes fields sequentially into a byte slice. See the [module documentation](self) for the bit order
+#[derive(Debug)]
+pub struct BitWriter<'a> {
+    bits: &'a mut BitSlice<u8, Lsb0>,
+    position: usize,
+}
+
+impl<'a> BitWriter<'a> {
+    /// Create a writer starting at the first bit of `bytes`
+    pub fn new(bytes: &'a mut [u8]) -> Self {
+        Self {
+            bits: bytes.view_bits_mut(),
+            position: 0,
+        }
+    }
+
+    /// The number of bits written so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The number of bits left to write
+    pub fn remaining(&self) -> usize {
+        self.bits.len() - self.position
+    }
+
+    fn field(&mut self, n: usize) -> BitResult<&mut BitSlice<u8, Lsb0>> {
+        let remaining = self.remaining();
+        match n <= remaining {
+            true => Ok(&mut self.bits[self.position..self.position + n]),
+            false => Err(BitError::Overrun {
+                requested: n,
+                remaining,
+            }),
+        }
+    }
+
+    /// Write `value` into the next `n` bits. Returns [`BitError::Pack`] if it doesn't fit,
+    /// in which case nothing is written. Signed values are written in two's complement
+    pub fn write<V: Integral>(&mut self, value: V, n: usize) -> BitResult {
+        check_width::<V>(n)?;
+        if !fits(value, n) {
+            return Err(PackError {
+                value: TryInto::<i128>::try_into(value).unwrap_or(i128::MAX),
+                bits: n,
+            }
+            .into());
+        }
+        if n > 0 {
+            self.field(n)?.store_le(value);
+        }
+        self.position += n;
+        Ok(())
+    }
+
+    /// Write a single bit
+    pub fn write_bool(&mut self, bit: bool) -> BitResult {
+        self.field(1)?.set(0, bit);
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Write each value in `values` into consecutive fields of `n` bits each
+    pub fn write_all<V: Integral>(
+        &mut self,
+        values: impl IntoIterator<Item = V>,
+        n: usize,
+    ) -> BitResult {
+        values.into_iter().try_for_each(|v| self.write(v, n))
+    }
+
+    /// Skip the next `n` bits, leaving them unchanged
+    pub fn skip(&mut self, n: usize) -> BitResult {
+        self.field(n)?;
+        self.position += n;
+        Ok(())
+    }
+
+    /// Skip ahead to the next multiple of `alignment` bits, if not already aligned
+    pub fn align(&mut self, alignment: usize) -> BitResult {
+        self.skip(self.position.next_multiple_of(alignment) - self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read() -> BitResult {
+        let bytes = [0b1011_0101u8, 0xFF, 0x01];
+        let mut reader = BitReader::new(&bytes);
+
+        assert!(reader.read_bool()?);
+        assert_eq!(reader.peek::<u8>(3)?, 0b010);
+        assert_eq!(reader.read::<u8>(3)?, 0b010);
+        assert_eq!(reader.read::<i8>(2)?, -1, "sign extended");
+        assert_eq!(reader.position(), 6);
+
+        // fields can cross byte boundaries
+        assert_eq!(reader.read::<u16>(10)?, 0b11_1111_1110);
+        reader.align(8)?;
+        assert_eq!(reader.position(), 16);
+        assert_eq!(reader.read::<u8>(8)?, 1);
+        assert_eq!(reader.remaining(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let bytes = [0u8; 2];
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(
+            reader.read::<u32>(17),
+            Err(BitError::Overrun {
+                requested: 17,
+                remaining: 16
+            })
+        );
+        assert_eq!(
+            reader.read::<u8>(9),
+            Err(BitError::Width {
+                requested: 9,
+                bits: 8
+            })
+        );
+        assert_eq!(reader.position(), 0, "failed reads don't advance");
+
+        let mut bytes = [0u8; 1];
+        let mut writer = BitWriter::new(&mut bytes);
+        assert!(matches!(writer.write(8u8, 3), Err(BitError::Pack(_))));
+        assert!(matches!(writer.write(-5i8, 3), Err(BitError::Pack(_))));
+        assert!(matches!(
+            writer.write(0u16, 9),
+            Err(BitError::Overrun { .. })
+        ));
+        assert_eq!(writer.position(), 0);
+    }
+
+    #[test]
+    fn roundtrip() -> BitResult {
+        let mut bytes = [0u8; 8];
+        let mut writer = BitWriter::new(&mut bytes);
+        writer.write(0b10u8, 2)?;
+        writer.write_bool(true)?;
+        writer.write(-3i16, 6)?;
+        writer.write(0x3FFu16, 10)?;
+        writer.align(32)?;
+        writer.write_all([1u8, 2, 3, 4, 5, 6, 7, 0], 3)?;
+        assert_eq!(writer.position(), 56);
+
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read::<u8>(2)?, 0b10);
+        assert!(reader.read_bool()?);
+        assert_eq!(reader.read::<i16>(6)?, -3);
+        assert_eq!(reader.read::<u16>(10)?, 0x3FF);
+        reader.align(32)?;
+        assert_eq!(reader.read_array::<u8, 8>(3)?, [1, 2, 3, 4, 5, 6, 7, 0]);
+        Ok(())
+    }
+}
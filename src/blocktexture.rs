@@ -1,14 +1,61 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use vector_victor::Matrix;
+
+use crate::color::Color;
+use crate::format::{Interpolation, Quality};
+
+/// A fixed-size block of encoded pixels, such as a BC1 block
 pub trait Block: Sized {
-    type Bytes: AsRef<[u8]>;
+    /// The encoded form of the block
+    type Bytes: AsRef<[u8]> + for<'a> TryFrom<&'a [u8]>;
     // = [u8; 8], etc. Many thanks to @kornel@mastodon.social
+    /// The size of the encoded block in bytes
     const SIZE: usize;
+    /// The width of the block in pixels
     const WIDTH: usize = 4;
+    /// The height of the block in pixels
     const HEIGHT: usize = 4;
 
+    /// Pack the block into bytes
     fn to_bytes(&self) -> Self::Bytes;
+    /// Unpack a block from bytes
     fn from_bytes(bytes: &Self::Bytes) -> Self;
 }
 
+/// A 4x4 block of RGBA pixels in row-major order, as consumed and produced by [`CodecBlock`]
+pub type Pixels = Matrix<Color, 4, 4>;
+
+/// A block that can be decoded to and encoded from pixels.
+///
+/// Implementing this trait is all a block format needs to be usable with generic surface codecs,
+/// which take care of splitting surfaces into blocks
+pub trait CodecBlock: Block {
+    /// Options controlling how blocks are encoded, such as the quality tier or which channel to use
+    type Options: Default + Clone + Send + Sync;
+
+    /// Decode the block to RGBA pixels. Channels the format doesn't store are
+    /// set to 0 for color and 255 for alpha
+    fn decode(&self) -> Pixels;
+
+    /// Decode the block to RGBA pixels with the given interpolation arithmetic.
+    /// Formats whose decoding doesn't depend on it use [`Self::decode`]
+    fn decode_with(&self, _interpolation: Interpolation) -> Pixels {
+        self.decode()
+    }
+
+    /// Encode RGBA pixels into a block, ignoring any channels the format doesn't store
+    fn encode(pixels: &Pixels, options: &Self::Options) -> Self;
+
+    /// Apply a quality tier to `options`, for codecs that are given a tier along with their options.
+    /// Formats without a quality setting keep the default, which ignores the tier
+    fn with_quality(options: &Self::Options, _quality: Quality) -> Self::Options {
+        options.clone()
+    }
+}
+
 struct BlockTexture<B>
 where
     B: Block,
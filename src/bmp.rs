@@ -0,0 +1,245 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use binrw::{binread, BinReaderExt};
+
+use crate::dimensions::{DimensionError, Dimensions};
+use crate::error::{HeaderError, TextureError, TextureResult};
+use crate::format::{AlphaFormat, ColorFormat, Format};
+use crate::shape::TextureShapeNode;
+use crate::texture::{Surface, Texture};
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+/// Size of `BITMAPCOREHEADER`, used by OS/2 bitmaps
+const CORE_HEADER_SIZE: u32 = 12;
+/// Size of `BITMAPINFOHEADER`, the "V3" header written by most tools
+const INFO_HEADER_SIZE: u32 = 40;
+
+#[binread]
+#[derive(Debug)]
+#[br(little, magic = b"BM")]
+struct FileHeader {
+    _file_size: u32,
+    _reserved: u32,
+    data_offset: u32,
+}
+
+/// The fields of every info header version that are needed to read the pixel data
+#[derive(Debug)]
+struct InfoHeader {
+    width: i32,
+    height: i32,
+    bit_count: u16,
+    compression: u32,
+    masks: Option<[u32; 4]>,
+}
+
+impl InfoHeader {
+    fn read<R: Read + Seek>(reader: &mut R) -> TextureResult<Self> {
+        let size: u32 = reader.read_le()?;
+        if size == CORE_HEADER_SIZE {
+            let (width, height, _planes, bit_count): (u16, i16, u16, u16) = reader.read_le()?;
+            return Ok(Self {
+                width: width as i32,
+                height: height as i32,
+                bit_count,
+                compression: BI_RGB,
+                masks: None,
+            });
+        }
+        if size < INFO_HEADER_SIZE {
+            return Err(TextureError::Format(format!(
+                "Unknown BMP info header size {size}"
+            )));
+        }
+
+        let (width, height, _planes, bit_count, compression): (i32, i32, u16, u16, u32) =
+            reader.read_le()?;
+        // skip image size, resolution, and palette sizes
+        reader.seek(SeekFrom::Current(20))?;
+
+        // V2 and later headers always contain the masks. V1 headers are followed by them when they are used
+        let mask_count = match (size, compression) {
+            (INFO_HEADER_SIZE, BI_BITFIELDS) => 3,
+            (INFO_HEADER_SIZE, BI_ALPHABITFIELDS) => 4,
+            (INFO_HEADER_SIZE, _) => 0,
+            (52, _) => 3,
+            _ => 4,
+        };
+        let mut masks = [0u32; 4];
+        for mask in &mut masks[..mask_count] {
+            *mask = reader.read_le()?;
+        }
+        let masks = matches!(compression, BI_BITFIELDS | BI_ALPHABITFIELDS).then_some(masks);
+
+        Ok(Self {
+            width,
+            height,
+            bit_count,
+            compression,
+            masks,
+        })
+    }
+
+    fn format(&self) -> TextureResult<Format> {
+        if !matches!(self.compression, BI_RGB | BI_BITFIELDS | BI_ALPHABITFIELDS) {
+            return Err(TextureError::Format(format!(
+                "Compressed BMP files are not supported (compression type {})",
+                self.compression
+            )));
+        }
+
+        let [r_mask, g_mask, b_mask, alpha_mask] = match (self.masks, self.bit_count) {
+            (Some(masks), 16 | 32) => masks,
+            (None, 16) => [0x7C00, 0x03E0, 0x001F, 0],
+            (None, 24 | 32) => [0xFF0000, 0x00FF00, 0x0000FF, 0],
+            (_, bits) => {
+                return Err(TextureError::Format(format!(
+                    "{bits}-bit BMP files are not supported"
+                )))
+            }
+        };
+
+        Ok(Format::Uncompressed {
+            pitch: self.bit_count as usize / 8,
+            color_format: ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                srgb: false,
+            },
+            alpha_format: match alpha_mask {
+                0 => AlphaFormat::Opaque,
+                alpha_mask => AlphaFormat::Straight { alpha_mask },
+            },
+        })
+    }
+}
+
+/// Read an uncompressed 16, 24, or 32-bit BMP file into a texture with a single surface.
+///
+/// The pixels are kept in the file's own layout, so the texture's format is a [`Format::Uncompressed`]
+/// with the bitmasks from the file. Rows are reordered top-to-bottom and their padding removed.
+/// Returns [`TextureError::Format`] for palettized or compressed files
+pub fn read_bmp<R: Read + Seek>(reader: &mut R) -> TextureResult<Texture> {
+    let start = reader.stream_position()?;
+    let file_header: FileHeader = reader
+        .read_le()
+        .map_err(|e| HeaderError::new(Some("BMP"), e))?;
+    let info = InfoHeader::read(reader).map_err(|e| e.in_container("BMP"))?;
+    let format = info.format()?;
+
+    let width = info.width.unsigned_abs();
+    let height = info.height.unsigned_abs();
+    let dimensions = Dimensions::try_from([width, height])?;
+
+    let pitch = info.bit_count as usize / 8;
+    let row_size = pitch * width as usize;
+    let padded_size = row_size.next_multiple_of(4);
+
+    let size = padded_size
+        .checked_mul(height as usize)
+        .ok_or(DimensionError::Overflow(dimensions))?;
+
+    reader.seek(SeekFrom::Start(start + file_header.data_offset as u64))?;
+    // read through `take` so a huge claimed size can't allocate more than the file contains
+    let mut data = Vec::new();
+    reader.take(size as u64).read_to_end(&mut data)?;
+    if data.len() != size {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let rows = data.chunks_exact(padded_size).map(|r| &r[..row_size]);
+    // a positive height means the rows are stored bottom-to-top
+    let buffer: Vec<u8> = match info.height > 0 {
+        true => rows.rev().flatten().copied().collect(),
+        false => rows.flatten().copied().collect(),
+    };
+
+    Texture::new(
+        format,
+        TextureShapeNode::from_surface(Surface::new(dimensions, buffer)),
+    )
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+    use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+
+    use crate::dimensions::Dimensioned;
+    use crate::texture::Texture;
+
+    use super::*;
+
+    fn decode(texture: &Texture) -> Result<RgbaImage> {
+        let surface = texture.slice().try_into_surface().unwrap();
+        let image = texture
+            .format
+            .decoder()?
+            .decode_buffer(surface.buffer.clone(), surface.dimensions)?;
+        Ok(image.into_rgba8())
+    }
+
+    #[test]
+    fn image_interop() -> Result<()> {
+        // odd widths, so that rows are padded
+        let rgb = RgbImage::from_fn(5, 3, |x, y| image::Rgb([x as u8 * 50, y as u8 * 80, 7]));
+        let rgba = RgbaImage::from_fn(5, 3, |x, y| {
+            image::Rgba([x as u8 * 50, y as u8 * 80, 7, 100 + x as u8])
+        });
+
+        for image in [DynamicImage::ImageRgb8(rgb), DynamicImage::ImageRgba8(rgba)] {
+            let mut file = Cursor::new(Vec::new());
+            image.write_to(&mut file, ImageFormat::Bmp)?;
+            file.set_position(0);
+
+            let texture = read_bmp(&mut file)?;
+            assert_eq!(texture.dimensions(), Dimensions::try_from([5, 3])?);
+            assert_eq!(decode(&texture)?, image.to_rgba8());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn v3_16bit_top_down() -> Result<()> {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"BM");
+        file.extend_from_slice(&0u32.to_le_bytes()); // file size, ignored
+        file.extend_from_slice(&0u32.to_le_bytes()); // reserved
+        file.extend_from_slice(&54u32.to_le_bytes()); // data offset
+        file.extend_from_slice(&40u32.to_le_bytes()); // info header size
+        file.extend_from_slice(&1i32.to_le_bytes()); // width
+        file.extend_from_slice(&(-2i32).to_le_bytes()); // height, top-down
+        file.extend_from_slice(&1u16.to_le_bytes()); // planes
+        file.extend_from_slice(&16u16.to_le_bytes()); // bit count
+        file.extend_from_slice(&[0; 24]); // compression, sizes, resolution, and palette
+        file.extend_from_slice(&[0x00, 0x7C, 0, 0]); // red, then padding
+        file.extend_from_slice(&[0x1F, 0x00, 0, 0]); // blue, then padding
+
+        let texture = read_bmp(&mut Cursor::new(file))?;
+        let image = decode(&texture)?;
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 1).0, [0, 0, 255, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn palettized() -> Result<()> {
+        let image = DynamicImage::ImageLuma8(image::GrayImage::new(4, 4));
+        let mut file = Cursor::new(Vec::new());
+        image.write_to(&mut file, ImageFormat::Bmp)?;
+        file.set_position(0);
+
+        assert!(matches!(read_bmp(&mut file), Err(TextureError::Format(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,808 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use vector_victor::Matrix;
+
+use crate::bitstream::{BitReader, BitWriter};
+use crate::blocktexture::{Block, CodecBlock, Pixels};
+use crate::color::Color;
+use crate::format::Quality;
+
+/// The layout of a BC7 mode
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ModeInfo {
+    /// Number of subsets, each with its own pair of endpoints
+    pub subsets: usize,
+    /// Bits used to select a partition of the pixels into subsets
+    pub partition_bits: usize,
+    /// Bits used to select a channel to swap with alpha
+    pub rotation_bits: usize,
+    /// Bits used to select which index set is used for color, in modes with two index sets
+    pub index_selection_bits: usize,
+    /// Bits per color channel of each endpoint, not including p-bits
+    pub color_bits: usize,
+    /// Bits per alpha channel of each endpoint, not including p-bits. 0 for modes without alpha
+    pub alpha_bits: usize,
+    /// If every endpoint has its own p-bit
+    pub endpoint_pbits: bool,
+    /// If each subset has a p-bit shared by both of its endpoints
+    pub shared_pbits: bool,
+    /// Bits per index in the primary index set
+    pub index_bits: usize,
+    /// Bits per index in the secondary index set, or 0 for modes with one index set
+    pub index2_bits: usize,
+}
+
+/// The layout of each of the 8 BC7 modes
+pub const MODES: [ModeInfo; 8] = [
+    ModeInfo {
+        subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 3,
+        index2_bits: 0,
+    },
+    ModeInfo {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_pbits: false,
+        shared_pbits: true,
+        index_bits: 3,
+        index2_bits: 0,
+    },
+    ModeInfo {
+        subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 0,
+    },
+    ModeInfo {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 0,
+    },
+    ModeInfo {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 3,
+    },
+    ModeInfo {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 2,
+    },
+    ModeInfo {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 4,
+        index2_bits: 0,
+    },
+    ModeInfo {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 0,
+    },
+];
+
+/// 2-subset partitions, with bit `i` set if pixel `i` is in the second subset
+const PARTITIONS2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+    0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+    0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+    0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// 3-subset partitions, with bits `2i` and `2i + 1` holding the subset of pixel `i`
+const PARTITIONS3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050,
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250,
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500,
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200,
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, 0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50,
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600,
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580, 0xAA141414, 0x96960000,
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254,
+];
+
+/// The anchor pixel of the second subset of each 2-subset partition
+const ANCHORS2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, //
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2, //
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, //
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The anchor pixels of the second and third subsets of each 3-subset partition
+const ANCHORS3: [[u8; 2]; 64] = [
+    [3, 15],
+    [3, 8],
+    [15, 8],
+    [15, 3],
+    [8, 15],
+    [3, 15],
+    [15, 3],
+    [15, 8],
+    [8, 15],
+    [8, 15],
+    [6, 15],
+    [6, 15],
+    [6, 15],
+    [5, 15],
+    [3, 15],
+    [3, 8],
+    [3, 15],
+    [3, 8],
+    [8, 15],
+    [15, 3],
+    [3, 15],
+    [3, 8],
+    [6, 15],
+    [10, 8],
+    [5, 3],
+    [8, 15],
+    [8, 6],
+    [6, 10],
+    [8, 15],
+    [5, 15],
+    [15, 10],
+    [15, 8],
+    [8, 15],
+    [15, 3],
+    [3, 15],
+    [5, 10],
+    [6, 10],
+    [10, 8],
+    [8, 9],
+    [15, 10],
+    [15, 6],
+    [3, 15],
+    [15, 8],
+    [5, 15],
+    [15, 3],
+    [15, 6],
+    [15, 6],
+    [15, 8],
+    [3, 15],
+    [15, 3],
+    [5, 15],
+    [5, 15],
+    [5, 15],
+    [8, 15],
+    [5, 15],
+    [10, 15],
+    [5, 15],
+    [10, 15],
+    [8, 15],
+    [13, 15],
+    [15, 3],
+    [12, 15],
+    [3, 15],
+    [3, 8],
+];
+
+/// Interpolation weights out of 64 for 2, 3, and 4-bit indices
+const WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weights(bits: usize) -> &'static [u32] {
+    match bits {
+        2 => &WEIGHTS2,
+        3 => &WEIGHTS3,
+        _ => &WEIGHTS4,
+    }
+}
+
+fn interpolate(e0: u8, e1: u8, weight: u32) -> u8 {
+    (((64 - weight) * e0 as u32 + weight * e1 as u32 + 32) >> 6) as u8
+}
+
+/// Expand a `bits`-wide value to 8 bits by replicating its high bits into the low bits
+fn unquantize(value: u8, bits: usize) -> u8 {
+    let value = (value as u32) << (8 - bits);
+    (value | value >> bits) as u8
+}
+
+/// The subset pixel `i` belongs to, in the given partition of a mode with `subsets` subsets
+pub fn subset(subsets: usize, partition: usize, i: usize) -> usize {
+    match subsets {
+        2 => (PARTITIONS2[partition] >> i & 1) as usize,
+        3 => (PARTITIONS3[partition] >> (2 * i) & 3) as usize,
+        _ => 0,
+    }
+}
+
+/// The anchor pixel of each subset in the given partition, whose index has an implied leading 0 bit.
+/// The first subset's anchor is always pixel 0
+pub fn anchors(subsets: usize, partition: usize) -> [usize; 3] {
+    match subsets {
+        2 => [0, ANCHORS2[partition] as usize, 0],
+        3 => [
+            0,
+            ANCHORS3[partition][0] as usize,
+            ANCHORS3[partition][1] as usize,
+        ],
+        _ => [0; 3],
+    }
+}
+
+/// A single 4x4 BC7 block, in any of its 8 modes.
+///
+/// Endpoints and p-bits are stored as they are in the block, so a block is packed into exactly
+/// the bytes it was unpacked from. The one exception is a block in the reserved mode 8,
+/// which decodes to transparent black and is always packed as all zeros
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BC7Block {
+    /// The mode of the block, from 0 to 7, or 8 for the reserved mode
+    mode: usize,
+    partition: usize,
+    rotation: u8,
+    index_selection: bool,
+    /// Quantized endpoints of each subset, as RGBA
+    endpoints: [[[u8; 4]; 2]; 3],
+    /// The p-bit of each endpoint of each subset. Shared p-bits are stored in both endpoints
+    pbits: [[u8; 2]; 3],
+    indices: [u8; 16],
+    indices2: [u8; 16],
+}
+
+impl BC7Block {
+    /// The reserved mode, which decodes to transparent black
+    pub const RESERVED: BC7Block = BC7Block {
+        mode: 8,
+        partition: 0,
+        rotation: 0,
+        index_selection: false,
+        endpoints: [[[0; 4]; 2]; 3],
+        pbits: [[0; 2]; 3],
+        indices: [0; 16],
+        indices2: [0; 16],
+    };
+
+    /// The mode of the block, from 0 to 7, or [`None`] for the reserved mode
+    pub fn mode(&self) -> Option<usize> {
+        (self.mode < 8).then_some(self.mode)
+    }
+
+    /// The endpoints of each subset, expanded to 8-bit RGBA. Modes without alpha have opaque endpoints
+    pub fn colors(&self) -> [[Color; 2]; 3] {
+        let Some(mode) = self.mode() else {
+            return [[Color::default(); 2]; 3];
+        };
+        let info = MODES[mode];
+        let pbit = info.endpoint_pbits || info.shared_pbits;
+        std::array::from_fn(|s| {
+            std::array::from_fn(|e| {
+                Color::vec(std::array::from_fn(|c| {
+                    let bits = if c == 3 {
+                        info.alpha_bits
+                    } else {
+                        info.color_bits
+                    };
+                    let value = self.endpoints[s][e][c];
+                    match (bits, pbit) {
+                        (0, _) => u8::MAX,
+                        (bits, true) => unquantize(value << 1 | self.pbits[s][e], bits + 1),
+                        (bits, false) => unquantize(value, bits),
+                    }
+                }))
+            })
+        })
+    }
+
+    /// Decode the block to RGBA pixels
+    pub fn decode_pixels(&self) -> Pixels {
+        let Some(mode) = self.mode() else {
+            return Matrix::fill(Color::default());
+        };
+        let info = MODES[mode];
+        let colors = self.colors();
+
+        // in modes with two index sets, the index selection bit chooses which one is for color
+        let (color_indices, color_bits, alpha_indices, alpha_bits) =
+            match (info.index2_bits, self.index_selection) {
+                (0, _) => (
+                    &self.indices,
+                    info.index_bits,
+                    &self.indices,
+                    info.index_bits,
+                ),
+                (_, false) => (
+                    &self.indices,
+                    info.index_bits,
+                    &self.indices2,
+                    info.index2_bits,
+                ),
+                (_, true) => (
+                    &self.indices2,
+                    info.index2_bits,
+                    &self.indices,
+                    info.index_bits,
+                ),
+            };
+
+        (0..16)
+            .map(|i| {
+                let [e0, e1] = colors[subset(info.subsets, self.partition, i)];
+                let color_weight = weights(color_bits)[color_indices[i] as usize];
+                let alpha_weight = weights(alpha_bits)[alpha_indices[i] as usize];
+                let mut pixel = Color::vec(std::array::from_fn(|c| match c {
+                    3 => interpolate(e0[c], e1[c], alpha_weight),
+                    _ => interpolate(e0[c], e1[c], color_weight),
+                }));
+                // rotation swaps alpha with one of the color channels
+                if self.rotation > 0 {
+                    let c = self.rotation as usize - 1;
+                    (pixel[c], pixel[3]) = (pixel[3], pixel[c]);
+                }
+                pixel
+            })
+            .collect()
+    }
+}
+
+impl Block for BC7Block {
+    type Bytes = [u8; 16];
+    const SIZE: usize = 16;
+
+    fn to_bytes(&self) -> Self::Bytes {
+        let mut bytes: Self::Bytes = [0; 16];
+        let Some(mode) = self.mode() else {
+            return bytes;
+        };
+        let info = MODES[mode];
+        let mut writer = BitWriter::new(&mut bytes);
+        let anchors = anchors(info.subsets, self.partition);
+        let channels = if info.alpha_bits > 0 { 4 } else { 3 };
+
+        // every field is sized by the mode, so none of these writes can fail
+        let mut write = |value: u8, bits: usize| {
+            writer.write(value, bits).expect("BC7 fields fit the mode");
+        };
+        write(1 << mode, mode + 1);
+        write(self.partition as u8, info.partition_bits);
+        write(self.rotation, info.rotation_bits);
+        write(self.index_selection as u8, info.index_selection_bits);
+        for c in 0..channels {
+            let bits = if c == 3 {
+                info.alpha_bits
+            } else {
+                info.color_bits
+            };
+            for subset in &self.endpoints[..info.subsets] {
+                for endpoint in subset {
+                    write(endpoint[c], bits);
+                }
+            }
+        }
+        for pbits in &self.pbits[..info.subsets] {
+            match (info.endpoint_pbits, info.shared_pbits) {
+                (true, _) => pbits.iter().for_each(|&p| write(p, 1)),
+                (_, true) => write(pbits[0], 1),
+                _ => {}
+            }
+        }
+        for (i, &index) in self.indices.iter().enumerate() {
+            write(index, info.index_bits - anchors.contains(&i) as usize);
+        }
+        if info.index2_bits > 0 {
+            for (i, &index) in self.indices2.iter().enumerate() {
+                write(index, info.index2_bits - (i == 0) as usize);
+            }
+        }
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &Self::Bytes) -> Self {
+        let mut reader = BitReader::new(bytes);
+
+        // the mode is the number of 0 bits before the first 1 bit
+        let Some(mode) = (0..8).find(|_| reader.read_bool().unwrap()) else {
+            return Self::RESERVED;
+        };
+        let info = MODES[mode];
+        let channels = if info.alpha_bits > 0 { 4 } else { 3 };
+
+        // every mode adds up to exactly 128 bits, so none of these reads can fail
+        let mut read = |bits: usize| -> u8 { reader.read(bits).unwrap() };
+        let mut block = Self {
+            mode,
+            partition: read(info.partition_bits) as usize,
+            rotation: read(info.rotation_bits),
+            index_selection: read(info.index_selection_bits) != 0,
+            ..Self::RESERVED
+        };
+        for c in 0..channels {
+            let bits = if c == 3 {
+                info.alpha_bits
+            } else {
+                info.color_bits
+            };
+            for subset in &mut block.endpoints[..info.subsets] {
+                for endpoint in subset {
+                    endpoint[c] = read(bits);
+                }
+            }
+        }
+        for pbits in &mut block.pbits[..info.subsets] {
+            match (info.endpoint_pbits, info.shared_pbits) {
+                (true, _) => pbits.iter_mut().for_each(|p| *p = read(1)),
+                (_, true) => *pbits = [read(1); 2],
+                _ => {}
+            }
+        }
+        let anchors = anchors(info.subsets, block.partition);
+        for (i, index) in block.indices.iter_mut().enumerate() {
+            *index = read(info.index_bits - anchors.contains(&i) as usize);
+        }
+        if info.index2_bits > 0 {
+            for (i, index) in block.indices2.iter_mut().enumerate() {
+                *index = read(info.index2_bits - (i == 0) as usize);
+            }
+        }
+        block
+    }
+}
+
+/// Options for encoding [`BC7Block`]s
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BC7Options {
+    /// How hard to search for endpoints. Every block is encoded in mode 6, with a single subset and
+    /// 4-bit indices for both color and alpha, starting from a PCA fit of the RGBA pixels:
+    ///
+    /// * [`Quality::Fastest`] uses the PCA fit as-is
+    /// * [`Quality::Normal`] also refits the endpoints to the chosen indices by least squares
+    /// * [`Quality::High`] and [`Quality::Exhaustive`] repeat the refit until it stops improving, up to 8 times
+    pub quality: Quality,
+}
+
+/// Squared error between two colors, over every channel
+fn distance(a: &Color, b: &Color) -> u32 {
+    a.elements()
+        .zip(b.elements())
+        .map(|(&a, &b)| (a.abs_diff(b) as u32).pow(2))
+        .sum()
+}
+
+impl BC7Block {
+    /// Quantize an 8-bit RGBA endpoint to mode 6's 7 bits per channel and a p-bit,
+    /// choosing whichever p-bit is closer
+    fn quantize_mode6(endpoint: [f32; 4]) -> ([u8; 4], u8) {
+        (0..2u8)
+            .map(|p| {
+                let q = endpoint.map(|v| ((v - p as f32) / 2.0).round().clamp(0.0, 127.0) as u8);
+                let error: f32 = (0..4)
+                    .map(|c| ((q[c] << 1 | p) as f32 - endpoint[c]).powi(2))
+                    .sum();
+                (q, p, error)
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(q, p, _)| (q, p))
+            .unwrap()
+    }
+
+    /// Make a mode 6 block with the given 8-bit endpoints, choosing the closest index for each pixel.
+    /// Returns the block and its total squared error
+    fn fit_mode6(pixels: &Pixels, endpoints: [[f32; 4]; 2]) -> (Self, u32) {
+        let [(q0, p0), (q1, p1)] = endpoints.map(Self::quantize_mode6);
+        let mut block = Self {
+            mode: 6,
+            ..Self::RESERVED
+        };
+        block.endpoints[0] = [q0, q1];
+        block.pbits[0] = [p0, p1];
+
+        let [e0, e1] = block.colors()[0];
+        let palette: [Color; 16] = std::array::from_fn(|i| {
+            Color::vec(std::array::from_fn(|c| {
+                interpolate(e0[c], e1[c], WEIGHTS4[i])
+            }))
+        });
+        let mut error = 0;
+        for (index, pixel) in block.indices.iter_mut().zip(pixels.elements()) {
+            let (i, e) = palette
+                .iter()
+                .map(|p| distance(p, pixel))
+                .enumerate()
+                .min_by_key(|&(_, e)| e)
+                .unwrap();
+            *index = i as u8;
+            error += e;
+        }
+
+        // the anchor index has no high bit, so swap the endpoints if it would need one
+        if block.indices[0] >= 8 {
+            block.endpoints[0].swap(0, 1);
+            block.pbits[0].swap(0, 1);
+            block.indices.iter_mut().for_each(|i| *i = 15 - *i);
+        }
+        (block, error)
+    }
+
+    /// The endpoints of the line through `pixels` along their principal axis, covering every pixel
+    fn pca_endpoints(pixels: &Pixels) -> [[f32; 4]; 2] {
+        let points: Vec<[f32; 4]> = pixels
+            .elements()
+            .map(|p| std::array::from_fn(|c| p[c] as f32))
+            .collect();
+        let mean: [f32; 4] =
+            std::array::from_fn(|c| points.iter().map(|p| p[c]).sum::<f32>() / 16.0);
+        let centered: Vec<[f32; 4]> = points
+            .iter()
+            .map(|p| std::array::from_fn(|c| p[c] - mean[c]))
+            .collect();
+        let covariance: [[f32; 4]; 4] = std::array::from_fn(|a| {
+            std::array::from_fn(|b| centered.iter().map(|p| p[a] * p[b]).sum())
+        });
+
+        // power iteration, starting from the diagonal so it isn't orthogonal to the principal axis
+        let mut axis = [1.0f32; 4];
+        for _ in 0..8 {
+            let next: [f32; 4] =
+                std::array::from_fn(|a| (0..4).map(|b| covariance[a][b] * axis[b]).sum::<f32>());
+            let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if length < f32::EPSILON {
+                break;
+            }
+            axis = next.map(|v| v / length);
+        }
+
+        let projections = centered
+            .iter()
+            .map(|p| (0..4).map(|c| p[c] * axis[c]).sum::<f32>());
+        let (min, max) = projections.fold((0.0f32, 0.0f32), |(lo, hi), t| (lo.min(t), hi.max(t)));
+        [min, max].map(|t| std::array::from_fn(|c| (mean[c] + axis[c] * t).clamp(0.0, 255.0)))
+    }
+
+    /// Solve for the endpoints that best fit `pixels` given the indices of `block`, by least squares
+    fn refit_mode6(pixels: &Pixels, block: &Self) -> Option<[[f32; 4]; 2]> {
+        let (mut a, mut b, mut c) = (0.0f32, 0.0f32, 0.0f32);
+        let mut d0 = [0.0f32; 4];
+        let mut d1 = [0.0f32; 4];
+        for (&index, pixel) in block.indices.iter().zip(pixels.elements()) {
+            let w = WEIGHTS4[index as usize] as f32 / 64.0;
+            a += (1.0 - w) * (1.0 - w);
+            b += (1.0 - w) * w;
+            c += w * w;
+            for ch in 0..4 {
+                d0[ch] += (1.0 - w) * pixel[ch] as f32;
+                d1[ch] += w * pixel[ch] as f32;
+            }
+        }
+        let determinant = a * c - b * b;
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        Some([
+            std::array::from_fn(|ch| ((c * d0[ch] - b * d1[ch]) / determinant).clamp(0.0, 255.0)),
+            std::array::from_fn(|ch| ((a * d1[ch] - b * d0[ch]) / determinant).clamp(0.0, 255.0)),
+        ])
+    }
+
+    /// Encode RGBA pixels in mode 6, searching as hard as `options` asks.
+    /// See [`BC7Options::quality`]
+    pub fn encode_pixels(pixels: &Pixels, options: &BC7Options) -> Self {
+        let mut best = Self::fit_mode6(pixels, Self::pca_endpoints(pixels));
+        let refits = match options.quality {
+            Quality::Fastest => 0,
+            Quality::Normal => 1,
+            Quality::High | Quality::Exhaustive => 8,
+        };
+        for _ in 0..refits {
+            let Some(endpoints) = Self::refit_mode6(pixels, &best.0) else {
+                break;
+            };
+            let candidate = Self::fit_mode6(pixels, endpoints);
+            if candidate.1 >= best.1 {
+                break;
+            }
+            best = candidate;
+        }
+        best.0
+    }
+}
+
+impl CodecBlock for BC7Block {
+    type Options = BC7Options;
+
+    fn decode(&self) -> Pixels {
+        self.decode_pixels()
+    }
+
+    fn encode(pixels: &Pixels, options: &Self::Options) -> Self {
+        Self::encode_pixels(pixels, options)
+    }
+
+    fn with_quality(_options: &Self::Options, quality: Quality) -> Self::Options {
+        BC7Options { quality }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes of a block in `mode`, with every other bit filled in from `seed`
+    fn random_block(mode: usize, seed: u64) -> [u8; 16] {
+        let mut state = seed;
+        let mut bytes: [u8; 16] = std::array::from_fn(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        });
+        let bits = u128::from_le_bytes(bytes);
+        let bits = (bits >> (mode + 1) << (mode + 1)) | 1 << mode;
+        bytes = bits.to_le_bytes();
+        bytes
+    }
+
+    #[test]
+    fn tables() {
+        // every anchor belongs to the subset it anchors
+        for p in 0..64 {
+            assert_eq!(subset(2, p, anchors(2, p)[1]), 1, "partition {p}");
+            let [_, a1, a2] = anchors(3, p);
+            assert_eq!(
+                (subset(3, p, a1), subset(3, p, a2)),
+                (1, 2),
+                "partition {p}"
+            );
+            // the first pixel is always in the first subset
+            assert_eq!((subset(2, p, 0), subset(3, p, 0)), (0, 0));
+        }
+    }
+
+    #[test]
+    fn modes() {
+        for (mode, info) in MODES.iter().enumerate() {
+            // every mode fills exactly 128 bits
+            let channels = if info.alpha_bits > 0 { 4 } else { 3 };
+            let endpoint_bits =
+                info.subsets * 2 * (3 * info.color_bits + (channels - 3) * info.alpha_bits);
+            let pbits =
+                info.subsets * (info.endpoint_pbits as usize * 2 + info.shared_pbits as usize);
+            let index_bits =
+                16 * info.index_bits - info.subsets + (16 * info.index2_bits).saturating_sub(1);
+            let total = mode
+                + 1
+                + info.partition_bits
+                + info.rotation_bits
+                + info.index_selection_bits
+                + endpoint_bits
+                + pbits
+                + index_bits;
+            assert_eq!(total, 128, "mode {mode}");
+
+            for seed in 0..16 {
+                let bytes = random_block(mode, seed);
+                let block = BC7Block::from_bytes(&bytes);
+                assert_eq!(block.mode(), Some(mode));
+                assert_eq!(block.to_bytes(), bytes, "mode {mode}");
+            }
+        }
+
+        let reserved = BC7Block::from_bytes(&[0; 16]);
+        assert_eq!(reserved.mode(), None);
+        assert!(reserved.decode().elements().all(|p| *p == Color::default()));
+    }
+
+    #[test]
+    fn decode_mode6() {
+        // black and white endpoints with p-bits 0 and 1, and indices counting up from 0 to 15
+        let mut block = BC7Block {
+            mode: 6,
+            ..BC7Block::RESERVED
+        };
+        block.endpoints[0] = [[0; 4], [127; 4]];
+        block.pbits[0] = [0, 1];
+        block.indices = std::array::from_fn(|i| i as u8);
+        let block = BC7Block::from_bytes(&block.to_bytes());
+
+        let pixels = block.decode();
+        for (i, p) in pixels.elements().enumerate() {
+            let v = interpolate(0, 255, WEIGHTS4[i]);
+            assert_eq!(*p, Color::vec([v; 4]));
+        }
+    }
+
+    #[test]
+    fn decode_mode4() {
+        // mode 4 with rotation 1 (alpha and red swapped) and the index selection bit set,
+        // so the 3-bit indices are for color and the 2-bit indices are for alpha, which ends up in red
+        let mut block = BC7Block {
+            mode: 4,
+            rotation: 1,
+            index_selection: true,
+            ..BC7Block::RESERVED
+        };
+        block.endpoints[0] = [[0, 0, 0, 0], [31, 31, 31, 63]];
+        block.indices = [1; 16];
+        block.indices2 = [2; 16];
+        let block = BC7Block::from_bytes(&block.to_bytes());
+        assert_eq!(block.mode(), Some(4));
+
+        let alpha = interpolate(0, 255, WEIGHTS2[1]);
+        let color = interpolate(0, 255, WEIGHTS3[2]);
+        let expected = Color::vec([alpha, color, color, color]);
+        assert!(block.decode().elements().all(|p| *p == expected));
+    }
+
+    #[test]
+    fn codec() {
+        // a gradient in color and alpha, which mode 6 can represent closely
+        let pixels: Pixels = (0..16u8)
+            .map(|i| Color::vec([i * 16, 255 - i * 16, 60, 128 + i * 8]))
+            .collect();
+        for quality in [Quality::Fastest, Quality::Normal, Quality::High] {
+            let block = BC7Block::encode(&pixels, &BC7Options { quality });
+            assert_eq!(block.mode(), Some(6));
+            let block = BC7Block::from_bytes(&block.to_bytes());
+            for (d, p) in block.decode().elements().zip(pixels.elements()) {
+                for c in 0..4 {
+                    assert!(d[c].abs_diff(p[c]) <= 6, "{d:?} != {p:?} at {quality}");
+                }
+            }
+        }
+
+        // solid blocks are only off by the shared p-bit
+        let solid = Matrix::fill(Color::vec([10, 200, 77, 255]));
+        let block = BC7Block::encode(&solid, &BC7Options::default());
+        for (d, p) in block.decode().elements().zip(solid.elements()) {
+            assert!((0..4).all(|c| d[c].abs_diff(p[c]) <= 1), "{d:?} != {p:?}");
+        }
+    }
+}
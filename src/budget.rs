@@ -0,0 +1,296 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use itertools::Itertools;
+
+use crate::dimensions::Dimensions;
+use crate::error::TextureResult;
+use crate::format::{Format, Quality};
+use crate::shape::TextureShape;
+use crate::texture::Texture;
+
+/// The ways [`Texture::fit_to_budget`] is allowed to shrink a texture
+#[derive(Clone, Debug, Default)]
+pub struct BudgetStrategy {
+    /// Whether the largest mips may be removed
+    pub drop_mips: bool,
+
+    /// Formats the texture may be transcoded to, in order of preference.
+    /// If empty, the texture is never transcoded
+    pub formats: Vec<Format>,
+
+    /// Quality tier to use when transcoding
+    pub quality: Quality,
+
+    /// When both dropping mips and transcoding are allowed, try every format before dropping any mips.
+    /// By default mips are dropped first, since the remaining mips are left untouched
+    pub transcode_first: bool,
+}
+
+/// What [`Texture::fit_to_budget`] did to a texture
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BudgetReport {
+    /// Payload size of the original texture in bytes
+    pub original_size: usize,
+
+    /// Payload size of the resulting texture in bytes
+    pub final_size: usize,
+
+    /// Number of mips removed from the top of the mip chain
+    pub dropped_mips: usize,
+
+    /// The format the texture was transcoded to, if it was transcoded
+    pub transcoded: Option<Format>,
+
+    /// Whether the resulting texture fits in the budget.
+    /// If not, the texture was shrunk as much as the strategy allows
+    pub fits: bool,
+}
+
+/// Alignment rules a GPU applies when laying out a texture in memory, for [`Texture::gpu_memory_estimate`].
+/// All alignments are in bytes, and 1 means no alignment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlignmentRules {
+    /// Alignment of each row of pixels or blocks in a surface
+    pub row_pitch: usize,
+
+    /// Alignment of the start of each surface (subresource)
+    pub subresource: usize,
+
+    /// Alignment of the size of the whole texture allocation
+    pub resource: usize,
+}
+
+impl AlignmentRules {
+    /// No alignment, so the estimate is the same as [`Texture::payload_size`]
+    pub const NONE: Self = Self {
+        row_pitch: 1,
+        subresource: 1,
+        resource: 1,
+    };
+
+    /// D3D12's pitch and placement alignments for texture data (256 and 512 bytes),
+    /// in a resource placed with the default 64KiB alignment
+    pub const D3D12: Self = Self {
+        row_pitch: 256,
+        subresource: 512,
+        resource: 64 * 1024,
+    };
+
+    /// Common Vulkan alignments: tightly packed rows, 16-byte aligned subresources,
+    /// and 4KiB allocation granularity. Actual values are device-specific
+    pub const VULKAN: Self = Self {
+        row_pitch: 1,
+        subresource: 16,
+        resource: 4 * 1024,
+    };
+}
+
+impl Default for AlignmentRules {
+    fn default() -> Self {
+        Self::D3D12
+    }
+}
+
+impl Texture {
+    /// Estimate how much GPU memory the texture uses under the given alignment rules.
+    ///
+    /// Each row of every surface is padded to [`AlignmentRules::row_pitch`], each surface to
+    /// [`AlignmentRules::subresource`], and the total to [`AlignmentRules::resource`].
+    /// Rows of block compressed formats are rows of blocks. Drivers may add their own padding or
+    /// tiling on top of this, so the result is a lower bound rather than an exact figure.
+    ///
+    /// Returns [`TextureError::Format`](crate::error::TextureError::Format) for [`Format::Unknown`],
+    /// since the size of a row is not known
+    pub fn gpu_memory_estimate(&self, rules: &AlignmentRules) -> TextureResult<usize> {
+        let total = self
+            .slice()
+            .iter()
+            .map(|s| {
+                let dimensions = s.surface.dimensions;
+                let row = self
+                    .format
+                    .size_for(Dimensions::try_from([dimensions.width(), 1])?)?;
+                // no known format has empty rows, but an empty row would hold no data at all
+                let rows = s.surface.buffer.len().checked_div(row).unwrap_or(0);
+                Ok((row.next_multiple_of(rules.row_pitch) * rows)
+                    .next_multiple_of(rules.subresource))
+            })
+            .sum::<TextureResult<usize>>()?;
+        Ok(total.next_multiple_of(rules.resource))
+    }
+
+    /// Shrink the texture by dropping mips and/or transcoding to a smaller format, as allowed by `strategy`,
+    /// until its payload is no larger than `budget` bytes.
+    ///
+    /// Every combination is evaluated from surface dimensions alone, and the first one that fits
+    /// (in the order described by [`BudgetStrategy`]) is applied, so at most one transcode is performed.
+    /// If nothing fits, the smallest combination is applied instead and [`BudgetReport::fits`] is false
+    pub fn fit_to_budget(
+        &self,
+        budget: usize,
+        strategy: &BudgetStrategy,
+    ) -> TextureResult<(Texture, BudgetReport)> {
+        let max_dropped = match strategy.drop_mips {
+            true => self.mips().unwrap_or(1) - 1,
+            false => 0,
+        };
+        let formats = std::iter::once(self.format)
+            .chain(strategy.formats.iter().copied())
+            .collect_vec();
+
+        let candidates = match strategy.transcode_first {
+            false => formats
+                .iter()
+                .flat_map(|&f| (0..=max_dropped).map(move |n| (f, n)))
+                .collect_vec(),
+            true => (0..=max_dropped)
+                .flat_map(|n| formats.iter().map(move |&f| (f, n)))
+                .collect_vec(),
+        };
+
+        let (format, dropped, fits) = match candidates
+            .iter()
+            .find(|(f, n)| self.predicted_size(*f, *n) <= budget)
+        {
+            Some(&(f, n)) => (f, n, true),
+            None => {
+                let (f, n) = *candidates
+                    .iter()
+                    .min_by_key(|(f, n)| self.predicted_size(*f, *n))
+                    .unwrap(); // candidates always contains the original texture
+                (f, n, false)
+            }
+        };
+
+        let mut surfaces = self.surfaces().clone();
+        surfaces.strip_top_mips(dropped)?;
+        let mut texture = Texture::new(self.format, surfaces)?;
+        if format != self.format {
+            texture = texture.transcode(format, strategy.quality)?;
+        }
+
+        let report = BudgetReport {
+            original_size: self.payload_size(),
+            final_size: texture.payload_size(),
+            dropped_mips: dropped,
+            transcoded: (format != self.format).then_some(format),
+            fits,
+        };
+        Ok((texture, report))
+    }
+
+    /// The payload size of this texture if it were in `format` with its `dropped` largest mips removed
+    fn predicted_size(&self, format: Format, dropped: usize) -> usize {
+        self.slice()
+            .iter()
+            .filter(|s| s.mip.unwrap_or(0) >= dropped)
+            // a size too large to represent can never fit in a budget
+            .map(|s| format.size_for(s.surface.dimensions).unwrap_or(usize::MAX))
+            .fold(0, usize::saturating_add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use anyhow::Result;
+
+    use crate::container::ContainerHeader;
+    use crate::dds::DDSHeader;
+    use crate::dimensions::Dimensioned;
+    use crate::error::TextureError;
+
+    use super::*;
+
+    fn read_peppers(format_name: &str) -> Result<Texture> {
+        let path = format!(
+            "{}/tests/images/dds/peppers16 {format_name}.dds",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        Ok(DDSHeader::read_texture(&mut File::open(path)?)?)
+    }
+
+    #[test]
+    fn drop_mips() -> Result<()> {
+        // 16x16 BC1 with 5 mips is 128 + 32 + 8 + 8 + 8 bytes
+        let texture = read_peppers("bc1")?;
+        let strategy = BudgetStrategy {
+            drop_mips: true,
+            ..Default::default()
+        };
+
+        let (fitted, report) = texture.fit_to_budget(200, &strategy)?;
+        assert_eq!(report.original_size, 184);
+        assert_eq!(report.dropped_mips, 0, "texture already fits");
+
+        let (fitted_small, report) = texture.fit_to_budget(60, &strategy)?;
+        assert!(report.fits);
+        assert_eq!(report.dropped_mips, 1);
+        assert_eq!(report.final_size, 56);
+        assert_eq!(fitted_small.dimensions(), Dimensions::try_from([8, 8])?);
+        assert_eq!(fitted_small.mips(), Some(4));
+        assert_eq!(fitted.payload_size(), 184);
+
+        let (fitted, report) = texture.fit_to_budget(1, &strategy)?;
+        assert!(!report.fits);
+        assert_eq!(report.dropped_mips, 4);
+        assert_eq!(fitted.payload_size(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn transcode_without_codec() -> Result<()> {
+        let texture = read_peppers("rgb")?;
+        let strategy = BudgetStrategy {
+            formats: vec![Format::BC2 { srgb: false }],
+            ..Default::default()
+        };
+
+        // fits without transcoding, so no codec is needed
+        let (_, report) = texture.fit_to_budget(usize::MAX, &strategy)?;
+        assert_eq!(report.transcoded, None);
+
+        let result = texture.fit_to_budget(200, &strategy);
+        assert!(matches!(result, Err(TextureError::Format(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn gpu_memory() -> Result<()> {
+        let texture = read_peppers("bc1")?;
+        assert_eq!(
+            texture.gpu_memory_estimate(&AlignmentRules::NONE)?,
+            texture.payload_size()
+        );
+
+        // 16x16 BC1 has 4 rows of 32 bytes, padded to 256 bytes each, and every other mip is a
+        // single row. Each surface is then padded to 512 bytes
+        let rules = AlignmentRules {
+            resource: 1,
+            ..AlignmentRules::D3D12
+        };
+        assert_eq!(texture.gpu_memory_estimate(&rules)?, 1024 + 4 * 512);
+        assert_eq!(
+            texture.gpu_memory_estimate(&AlignmentRules::D3D12)?,
+            64 * 1024
+        );
+        assert_eq!(
+            texture.gpu_memory_estimate(&AlignmentRules::VULKAN)?,
+            4 * 1024
+        );
+
+        // opaque payloads have no known row size
+        let unknown = Texture {
+            format: Format::Unknown { four_cc: *b"ABCD" },
+            ..texture
+        };
+        assert!(matches!(
+            unknown.gpu_memory_estimate(&AlignmentRules::D3D12),
+            Err(TextureError::Format(_))
+        ));
+        Ok(())
+    }
+}
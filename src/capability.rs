@@ -0,0 +1,349 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! What each format and container supports, for rejecting a conversion before any work is done.
+//!
+//! [`Format::capabilities`] lists what a format can do, such as being decoded or encoded, and
+//! [`ContainerHeader::CAPABILITIES`] lists the format families and texture shapes a container can store.
+//! The container table is coarse: a texture it rejects can never be written, but a texture it accepts
+//! can still be rejected for details like channel masks or block footprints when the header is built
+
+use enumflags2::{bitflags, make_bitflags, BitFlags};
+use strum::VariantArray;
+
+use crate::container::{container_name, ContainerHeader};
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::format::{ColorFormat, Format};
+use crate::shape::{CubeFace, TextureShape};
+use crate::texture::Texture;
+
+/// Something a format can do. See [`Format::capabilities`]
+#[bitflags]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatCapability {
+    /// Surfaces can be decoded to images with [`Format::decoder`]
+    Decode,
+    /// Images can be encoded to surfaces with [`Format::encoder`]
+    Encode,
+    /// The format has an sRGB encoded variant
+    Srgb,
+    /// Surfaces can be 3D
+    Volume,
+}
+
+/// A kind of format, ignoring its parameters. Containers list the families they can store
+#[bitflags]
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatFamily {
+    /// [`Format::BC1`]
+    BC1,
+    /// [`Format::BC2`]
+    BC2,
+    /// [`Format::BC3`]
+    BC3,
+    /// [`Format::BC4`]
+    BC4,
+    /// [`Format::BC5`]
+    BC5,
+    /// [`Format::BC7`]
+    BC7,
+    /// [`Format::ETC2RGB8`]
+    ETC2RGB8,
+    /// [`Format::ETC2RGBA8`]
+    ETC2RGBA8,
+    /// [`Format::EACR11`]
+    EACR11,
+    /// [`Format::EACRG11`]
+    EACRG11,
+    /// [`Format::ASTC`]
+    ASTC,
+    /// [`Format::Uncompressed`]
+    Uncompressed,
+    /// [`Format::Channels`]
+    Channels,
+    /// [`Format::Unknown`]
+    Unknown,
+}
+
+impl FormatFamily {
+    /// The family of `format`
+    pub const fn of(format: &Format) -> Self {
+        match format {
+            Format::BC1 { .. } => Self::BC1,
+            Format::BC2 { .. } => Self::BC2,
+            Format::BC3 { .. } => Self::BC3,
+            Format::BC4 { .. } => Self::BC4,
+            Format::BC5 { .. } => Self::BC5,
+            Format::BC7 { .. } => Self::BC7,
+            Format::ETC2RGB8 { .. } => Self::ETC2RGB8,
+            Format::ETC2RGBA8 { .. } => Self::ETC2RGBA8,
+            Format::EACR11 { .. } => Self::EACR11,
+            Format::EACRG11 { .. } => Self::EACRG11,
+            Format::ASTC { .. } => Self::ASTC,
+            Format::Uncompressed { .. } => Self::Uncompressed,
+            Format::Channels { .. } => Self::Channels,
+            Format::Unknown { .. } => Self::Unknown,
+        }
+    }
+}
+
+/// A texture shape a container may be unable to store. See [`ContainerCapabilities`]
+#[bitflags]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeFeature {
+    /// More than one mip level
+    Mips,
+    /// More than one array layer
+    Arrays,
+    /// Cubemaps with all 6 faces
+    Cubemaps,
+    /// Cubemaps missing some faces
+    PartialCubemaps,
+    /// 1D surfaces
+    Lines,
+    /// 3D surfaces
+    Volumes,
+}
+
+impl ShapeFeature {
+    /// A description of the feature for error messages, such as "mipmaps"
+    pub const fn description(&self) -> &'static str {
+        match self {
+            ShapeFeature::Mips => "mipmaps",
+            ShapeFeature::Arrays => "arrays",
+            ShapeFeature::Cubemaps => "cubemaps",
+            ShapeFeature::PartialCubemaps => "incomplete cubemaps",
+            ShapeFeature::Lines => "1D surfaces",
+            ShapeFeature::Volumes => "3D surfaces",
+        }
+    }
+}
+
+/// The format families and texture shapes a container can store. See [`ContainerHeader::CAPABILITIES`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContainerCapabilities {
+    /// Families of formats the container can store
+    pub formats: BitFlags<FormatFamily>,
+    /// Shapes the container can store. 2D surfaces without mips, layers, or faces are always supported
+    pub shapes: BitFlags<ShapeFeature>,
+}
+
+impl ContainerCapabilities {
+    /// A container that can store anything, the default for containers outside this crate
+    pub const ALL: Self = Self {
+        formats: BitFlags::ALL,
+        shapes: BitFlags::ALL,
+    };
+
+    /// A container that can only store a single 2D surface of the given formats
+    pub const fn single_surface(formats: BitFlags<FormatFamily>) -> Self {
+        Self {
+            formats,
+            shapes: BitFlags::EMPTY,
+        }
+    }
+
+    /// Returns true if the container can store surfaces in `format`
+    pub fn supports_format(&self, format: &Format) -> bool {
+        self.formats.contains(FormatFamily::of(format))
+    }
+
+    /// Returns true if the container can store textures with `feature`
+    pub fn supports(&self, feature: ShapeFeature) -> bool {
+        self.shapes.contains(feature)
+    }
+}
+
+/// The shape features used by a texture,
+/// or an error if its tree of surfaces is malformed
+fn shape_features(texture: &Texture) -> TextureResult<BitFlags<ShapeFeature>> {
+    let mut features = BitFlags::empty();
+    if texture.mips().is_some_and(|m| m > 1) {
+        features |= ShapeFeature::Mips;
+    }
+    if texture.layers().is_some_and(|l| l > 1) {
+        features |= ShapeFeature::Arrays;
+    }
+    match texture.faces() {
+        Some(faces) if faces.len() == CubeFace::VARIANTS.len() => {
+            features |= ShapeFeature::Cubemaps
+        }
+        Some(_) => features |= ShapeFeature::PartialCubemaps,
+        None => {}
+    }
+    match texture.try_dimensions()? {
+        Dimensions::_1D(_) => features |= ShapeFeature::Lines,
+        Dimensions::_3D(_) => features |= ShapeFeature::Volumes,
+        Dimensions::_2D(_) => {}
+    }
+    Ok(features)
+}
+
+impl Format {
+    /// Everything this format can do. Decoding and encoding depend on which codecs this crate has,
+    /// so BC2 can be decoded but not encoded, and ETC2, EAC, and ASTC can only be passed through
+    pub fn capabilities(&self) -> BitFlags<FormatCapability> {
+        use FormatCapability::*;
+        let codec = make_bitflags!(FormatCapability::{Decode | Encode | Volume});
+        match self {
+            Format::BC1 { .. } | Format::BC3 { .. } | Format::BC7 { .. } => codec | Srgb,
+            Format::BC2 { .. } => Decode | Volume | Srgb,
+            Format::BC4 { .. } | Format::BC5 { .. } | Format::Channels { .. } => codec,
+            Format::Uncompressed {
+                color_format: ColorFormat::RGB { .. },
+                ..
+            } => codec | Srgb,
+            Format::Uncompressed { .. } => codec,
+            Format::ETC2RGB8 { .. } | Format::ETC2RGBA8 { .. } | Format::ASTC { .. } => {
+                Volume | Srgb
+            }
+            Format::EACR11 { .. } | Format::EACRG11 { .. } => Volume.into(),
+            Format::Unknown { .. } => BitFlags::empty(),
+        }
+    }
+
+    /// If surfaces in this format can be 3D. Only [`Format::Unknown`] can't, since it has no known size
+    pub fn supports_3d(&self) -> bool {
+        self.capabilities().contains(FormatCapability::Volume)
+    }
+
+    /// If this format has an sRGB encoded variant. See [`Format::srgb`] for whether it is sRGB encoded
+    pub fn supports_srgb(&self) -> bool {
+        self.capabilities().contains(FormatCapability::Srgb)
+    }
+
+    /// If surfaces in this format can be decoded
+    pub fn supports_decode(&self) -> bool {
+        self.capabilities().contains(FormatCapability::Decode)
+    }
+
+    /// If images can be encoded to this format
+    pub fn supports_encode(&self) -> bool {
+        self.capabilities().contains(FormatCapability::Encode)
+    }
+
+    /// If the container with header `H` can store surfaces in this format.
+    /// See [`ContainerHeader::CAPABILITIES`]
+    pub fn container_supported_by<H>(&self) -> bool
+    where
+        H: ContainerHeader,
+        for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+        for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+    {
+        H::CAPABILITIES.supports_format(self)
+    }
+}
+
+impl Texture {
+    /// Check that the container with header `H` can store this texture's format and shape,
+    /// without building a header. Returns [`TextureError::Format`] if it can't store the format,
+    /// or [`TextureError::Capability`] naming the first shape feature it can't store.
+    /// [`ContainerHeader::write_texture`] runs this check before writing anything
+    pub fn check_container<H>(&self) -> TextureResult<()>
+    where
+        H: ContainerHeader,
+        for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+        for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+    {
+        let capabilities = H::CAPABILITIES;
+        let name = container_name::<H>();
+        if !capabilities.supports_format(&self.format) {
+            return Err(TextureError::Format(format!(
+                "{name} files cannot contain {:?} surfaces",
+                FormatFamily::of(&self.format)
+            )));
+        }
+        let unsupported = shape_features(self)? & !capabilities.shapes;
+        match unsupported.iter().next() {
+            Some(feature) => Err(TextureError::Capability(format!(
+                "{name} files cannot contain {}",
+                feature.description()
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+
+    use crate::astc::ASTCHeader;
+    use crate::dds::DDSHeader;
+    use crate::shape::TextureShapeNode;
+    use crate::texture::Surface;
+
+    use super::*;
+
+    fn texture(format: Format, dimensions: Dimensions, mips: bool) -> Result<Texture> {
+        let surface = |d: Dimensions| {
+            TextureShapeNode::from_surface(Surface::new(d, vec![0; format.size_for(d).unwrap()]))
+        };
+        let surfaces = match mips {
+            true => TextureShapeNode::try_from_mips(dimensions.mips().map(surface))?,
+            false => surface(dimensions),
+        };
+        Ok(Texture::new(format, surfaces)?)
+    }
+
+    #[test]
+    fn formats() {
+        let bc2 = Format::BC2 { srgb: false };
+        assert!(bc2.supports_decode() && !bc2.supports_encode());
+        assert!(bc2.supports_srgb() && bc2.supports_3d());
+        assert!(!Format::BC4 { signed: true }.supports_srgb());
+        assert!(Format::rgba8(false).supports_srgb());
+        assert!(!Format::ETC2RGB8 { srgb: false }.supports_decode());
+
+        let unknown = Format::Unknown { four_cc: *b"ABCD" };
+        assert!(unknown.capabilities().is_empty());
+        assert_eq!(FormatFamily::of(&unknown), FormatFamily::Unknown);
+        assert!(!unknown.container_supported_by::<DDSHeader>());
+        assert!(Format::BC7 { srgb: true }.container_supported_by::<DDSHeader>());
+        assert!(!Format::BC7 { srgb: true }.container_supported_by::<ASTCHeader>());
+        assert!(!Format::EACR11 { signed: false }.container_supported_by::<DDSHeader>());
+    }
+
+    #[test]
+    fn containers() -> Result<()> {
+        let bc1 = texture(
+            Format::BC1 { srgb: false },
+            Dimensions::try_from([8, 8])?,
+            true,
+        )?;
+        assert!(bc1.check_container::<DDSHeader>().is_ok());
+        assert!(matches!(
+            bc1.check_container::<ASTCHeader>(),
+            Err(TextureError::Format(_))
+        ));
+
+        let astc = |block_dims: [u32; 3], dimensions: [u32; 3], mips| -> Result<Texture> {
+            let format = Format::ASTC {
+                block_dims: Dimensions::try_from(block_dims)?,
+                srgb: false,
+            };
+            texture(format, Dimensions::try_from(dimensions)?, mips)
+        };
+        let result = astc([4, 4, 4], [8, 8, 8], true)?.check_container::<ASTCHeader>();
+        assert!(
+            matches!(&result, Err(TextureError::Capability(m)) if m == "ASTC files cannot contain mipmaps"),
+            "{result:?}"
+        );
+        assert!(astc([4, 4, 4], [8, 8, 8], false)?
+            .check_container::<ASTCHeader>()
+            .is_ok());
+
+        // the container is rejected before anything is written
+        let mut file = Cursor::new(Vec::new());
+        assert!(ASTCHeader::write_texture(&mut file, &bc1).is_err());
+        assert!(file.get_ref().is_empty());
+        Ok(())
+    }
+}
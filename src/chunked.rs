@@ -0,0 +1,887 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A crate-native container built for random access and appending, rather than compatibility.
+//!
+//! A chunked file is a fixed-size [`ChunkedHeader`](crate::chunked::ChunkedHeader), followed by one chunk per surface, followed by
+//! a table of contents listing where each chunk is and which surface it holds. Because the table of
+//! contents is at the end, new layers can be appended by overwriting it (see [`ChunkedIndex::append_layers`](crate::chunked::ChunkedIndex::append_layers)),
+//! and any single surface can be read without reading the others (see [`ChunkedIndex::read_surface`](crate::chunked::ChunkedIndex::read_surface)).
+//! Each chunk can optionally be compressed on its own.
+//!
+//! Offsets in the table of contents are relative to the end of the header, so chunked files can be
+//! embedded in other files.
+//! All fields are little-endian.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use binrw::{binrw, BinReaderExt, BinWriterExt};
+use enumflags2::{make_bitflags, BitFlags};
+use itertools::Itertools;
+use strum::VariantArray;
+
+use crate::capability::{ContainerCapabilities, FormatFamily};
+use crate::container::ContainerHeader;
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::error::{TextureError, TextureResult};
+use crate::format::{AlphaFormat, ChannelType, ColorFormat, Format};
+use crate::shape::{CubeFace, TextureShape, TextureShapeNode};
+use crate::texture::{Surface, Surfaces, Texture};
+
+/// The current version of the chunked container
+pub const CHUNKED_VERSION: u16 = 1;
+
+/// Size of a [`ChunkedHeader`] in bytes, including its magic
+pub const HEADER_SIZE: u64 = 4 + 2 + 24 + 12 + 4 + 4 + 1 + 8;
+
+/// How a chunk's contents are compressed
+#[binrw]
+#[brw(repr = u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Stored as-is
+    #[default]
+    None = 0,
+    /// Compressed with DEFLATE. Requires the `deflate` feature
+    Deflate = 1,
+}
+
+impl Compression {
+    fn compress(self, data: &[u8]) -> TextureResult<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(not(feature = "deflate"))]
+            Compression::Deflate => Err(TextureError::Capability(
+                "DEFLATE compression requires the `deflate` feature".to_string(),
+            )),
+        }
+    }
+
+    fn decompress(self, data: &[u8], size: usize) -> TextureResult<Vec<u8>> {
+        let decompressed = match self {
+            Compression::None => data.to_vec(),
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                // DEFLATE can't expand data by more than 1032:1, so a hostile size can't force a huge allocation
+                let mut decompressed =
+                    Vec::with_capacity(size.min(data.len().saturating_mul(1032)));
+                flate2::read::DeflateDecoder::new(data)
+                    .take(size as u64 + 1)
+                    .read_to_end(&mut decompressed)?;
+                decompressed
+            }
+            #[cfg(not(feature = "deflate"))]
+            Compression::Deflate => {
+                return Err(TextureError::Capability(
+                    "DEFLATE compression requires the `deflate` feature".to_string(),
+                ))
+            }
+        };
+        match decompressed.len() == size {
+            true => Ok(decompressed),
+            false => Err(TextureError::Other(format!(
+                "Chunk decompressed to {} bytes instead of {size}",
+                decompressed.len()
+            ))),
+        }
+    }
+}
+
+/// A [`Format`] in a fixed-size binary form
+#[binrw]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FormatRecord {
+    /// 0 for uncompressed, 1 through 5 and 7 for BC1 through BC5 and BC7, 8 for typed channels, 9 for ASTC,
+    /// or 10 through 13 for ETC2 RGB8, ETC2 RGBA8, EAC R11, and EAC RG11
+    pub kind: u8,
+    /// 1 if the format is sRGB or signed
+    pub flags: u8,
+    /// Size of a pixel in bytes for uncompressed formats, or the number of channels for typed channels
+    pub pitch: u16,
+    /// 0 for RGB, 1 for YUV, 2 for luminance, or 3 for no color channels.
+    /// For typed channels, 0 through 5 for UNorm8, UNorm16, SNorm8, SNorm16, Float16, and Float32
+    pub color: u8,
+    /// 0 for custom, 1 for straight, 2 for premultiplied, or 3 for opaque alpha
+    pub alpha: u8,
+    /// Bitmasks of the three color channels, then the alpha channel. Unused masks are 0.
+    /// For ASTC, the width, height, and depth of a block, with a depth of 0 for 2D blocks
+    pub masks: [u32; 4],
+    /// Reserved, always 0
+    pub reserved: u16,
+}
+
+impl FormatRecord {
+    /// Store a format in binary form
+    pub fn from_format(format: Format) -> TextureResult<Self> {
+        let block = |kind, flag: bool| Self {
+            kind,
+            flags: flag as u8,
+            ..Default::default()
+        };
+        Ok(match format {
+            Format::BC1 { srgb } => block(1, srgb),
+            Format::BC2 { srgb } => block(2, srgb),
+            Format::BC3 { srgb } => block(3, srgb),
+            Format::BC4 { signed } => block(4, signed),
+            Format::BC5 { signed } => block(5, signed),
+            Format::BC7 { srgb } => block(7, srgb),
+            Format::ETC2RGB8 { srgb } => block(10, srgb),
+            Format::ETC2RGBA8 { srgb } => block(11, srgb),
+            Format::EACR11 { signed } => block(12, signed),
+            Format::EACRG11 { signed } => block(13, signed),
+            Format::ASTC { block_dims, srgb } => Self {
+                masks: match block_dims {
+                    Dimensions::_3D(_) => [
+                        block_dims.width(),
+                        block_dims.height(),
+                        block_dims.depth(),
+                        0,
+                    ],
+                    _ => [block_dims.width(), block_dims.height(), 0, 0],
+                },
+                ..block(9, srgb)
+            },
+            Format::Uncompressed {
+                pitch,
+                color_format,
+                alpha_format,
+            } => {
+                let (color, flags, [m0, m1, m2]) = match color_format {
+                    ColorFormat::RGB {
+                        r_mask,
+                        g_mask,
+                        b_mask,
+                        srgb,
+                    } => (0, srgb as u8, [r_mask, g_mask, b_mask]),
+                    ColorFormat::YUV {
+                        y_mask,
+                        u_mask,
+                        v_mask,
+                    } => (1, 0, [y_mask, u_mask, v_mask]),
+                    ColorFormat::L { l_mask } => (2, 0, [l_mask, 0, 0]),
+                    ColorFormat::None => (3, 0, [0; 3]),
+                };
+                let (alpha, alpha_mask) = match alpha_format {
+                    AlphaFormat::Custom { alpha_mask } => (0, alpha_mask),
+                    AlphaFormat::Straight { alpha_mask } => (1, alpha_mask),
+                    AlphaFormat::Premultiplied { alpha_mask } => (2, alpha_mask),
+                    AlphaFormat::Opaque => (3, 0),
+                };
+                Self {
+                    kind: 0,
+                    flags,
+                    pitch: pitch.try_into().map_err(|_| {
+                        TextureError::Capability(format!("Pixel pitch {pitch} is too large"))
+                    })?,
+                    color,
+                    alpha,
+                    masks: [m0, m1, m2, alpha_mask],
+                    reserved: 0,
+                }
+            }
+            Format::Channels {
+                channel_type,
+                channels,
+            } => Self {
+                kind: 8,
+                pitch: channels.try_into().map_err(|_| {
+                    TextureError::Capability(format!("{channels} channels is too many"))
+                })?,
+                color: match channel_type {
+                    ChannelType::UNorm8 => 0,
+                    ChannelType::UNorm16 => 1,
+                    ChannelType::SNorm8 => 2,
+                    ChannelType::SNorm16 => 3,
+                    ChannelType::Float16 => 4,
+                    ChannelType::Float32 => 5,
+                },
+                ..Default::default()
+            },
+            Format::Unknown { .. } => {
+                return Err(TextureError::Format(format!(
+                    "Cannot store unknown format {format:?}"
+                )))
+            }
+        })
+    }
+
+    /// Get the format stored in this record
+    pub fn format(&self) -> TextureResult<Format> {
+        let flag = self.flags & 1 != 0;
+        let [m0, m1, m2, alpha_mask] = self.masks;
+        let invalid = || TextureError::Format(format!("Invalid format record {self:?}"));
+        Ok(match self.kind {
+            1 => Format::BC1 { srgb: flag },
+            2 => Format::BC2 { srgb: flag },
+            3 => Format::BC3 { srgb: flag },
+            4 => Format::BC4 { signed: flag },
+            5 => Format::BC5 { signed: flag },
+            7 => Format::BC7 { srgb: flag },
+            10 => Format::ETC2RGB8 { srgb: flag },
+            11 => Format::ETC2RGBA8 { srgb: flag },
+            12 => Format::EACR11 { signed: flag },
+            13 => Format::EACRG11 { signed: flag },
+            9 => Format::ASTC {
+                block_dims: match [m0, m1, m2] {
+                    [width, height, 0] => Dimensions::try_from([width, height]),
+                    axes => Dimensions::try_from(axes),
+                }
+                .map_err(|_| invalid())?,
+                srgb: flag,
+            },
+            0 => Format::Uncompressed {
+                pitch: self.pitch as usize,
+                color_format: match self.color {
+                    0 => ColorFormat::RGB {
+                        r_mask: m0,
+                        g_mask: m1,
+                        b_mask: m2,
+                        srgb: flag,
+                    },
+                    1 => ColorFormat::YUV {
+                        y_mask: m0,
+                        u_mask: m1,
+                        v_mask: m2,
+                    },
+                    2 => ColorFormat::L { l_mask: m0 },
+                    3 => ColorFormat::None,
+                    _ => return Err(invalid()),
+                },
+                alpha_format: match self.alpha {
+                    0 => AlphaFormat::Custom { alpha_mask },
+                    1 => AlphaFormat::Straight { alpha_mask },
+                    2 => AlphaFormat::Premultiplied { alpha_mask },
+                    3 => AlphaFormat::Opaque,
+                    _ => return Err(invalid()),
+                },
+            },
+            8 => Format::Channels {
+                channel_type: match self.color {
+                    0 => ChannelType::UNorm8,
+                    1 => ChannelType::UNorm16,
+                    2 => ChannelType::SNorm8,
+                    3 => ChannelType::SNorm16,
+                    4 => ChannelType::Float16,
+                    5 => ChannelType::Float32,
+                    _ => return Err(invalid()),
+                },
+                channels: self.pitch as usize,
+            },
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+/// The header of a chunked container. See the [module documentation](self) for the layout of the file
+#[binrw]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[brw(little, magic = b"QTXC")]
+pub struct ChunkedHeader {
+    /// Version of the container, currently always [`CHUNKED_VERSION`]
+    #[br(assert(version == CHUNKED_VERSION, "Unsupported chunked container version {}", version))]
+    pub version: u16,
+    /// The format of every surface
+    pub format: FormatRecord,
+    /// Width, height, and depth of the largest surface. Unused axes are 0
+    pub dimensions: [u32; 3],
+    /// Number of mips, or 0 if the texture has no mipmap structure
+    pub mips: u32,
+    /// Number of array layers, or 0 if the texture is not an array
+    pub layers: u32,
+    /// Bitmask of the cubemap faces present, with bit 0 for +X through bit 5 for -Z, or 0 if the texture is not a cubemap
+    pub faces: u8,
+    /// Offset of the table of contents from the end of the header
+    pub toc_offset: u64,
+    /// Compression applied to chunks when writing. Not stored in the file, since each chunk records its own
+    #[brw(ignore)]
+    pub compression: Compression,
+}
+
+/// An entry in the table of contents, describing one chunk
+#[binrw]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[brw(little)]
+pub struct ChunkEntry {
+    /// The array layer of the surface, or 0 if the texture is not an array
+    pub layer: u32,
+    /// The cubemap face of the surface as an index into [`CubeFace::VARIANTS`], or 0 if the texture is not a cubemap
+    pub face: u8,
+    /// The mip level of the surface, or 0 if the texture has no mipmap structure
+    pub mip: u32,
+    /// How the chunk is compressed
+    pub compression: Compression,
+    /// Offset of the chunk from the end of the header
+    pub offset: u64,
+    /// Size of the chunk in the file
+    pub stored_size: u64,
+    /// Size of the surface once decompressed
+    pub size: u64,
+}
+
+#[binrw]
+#[brw(little)]
+struct Toc {
+    #[br(temp)]
+    #[bw(calc = entries.len() as u32)]
+    count: u32,
+    #[br(count = count)]
+    entries: Vec<ChunkEntry>,
+}
+
+/// Arguments for writing a chunked container
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkedArgs {
+    /// Compression to apply to each chunk. Chunks that don't get smaller are stored uncompressed
+    pub compression: Compression,
+}
+
+impl ChunkedHeader {
+    fn face_list(&self) -> Option<Vec<CubeFace>> {
+        (self.faces != 0).then(|| {
+            CubeFace::VARIANTS
+                .iter()
+                .copied()
+                .filter(|&f| self.faces & (1 << f as u8) != 0)
+                .collect()
+        })
+    }
+
+    fn mip_dimensions(&self, mip: u32) -> TextureResult<Dimensions> {
+        self.dimensions()?
+            .mips()
+            .nth(mip as usize)
+            .ok_or_else(|| TextureError::Other(format!("Chunk has invalid mip level {mip}")))
+    }
+}
+
+/// The position `offset` bytes after `base`, or an error if an offset read from a file overflows
+fn position(base: u64, offset: u64) -> TextureResult<SeekFrom> {
+    base.checked_add(offset)
+        .map(SeekFrom::Start)
+        .ok_or_else(|| TextureError::Other(format!("Offset {offset} is past the end of any file")))
+}
+
+/// Write `surfaces` as chunks starting at `offset` bytes after the end of the header, returning their
+/// table of contents entries. `first_layer` is added to the layer index of each entry
+fn write_chunks<W: Write + Seek>(
+    writer: &mut W,
+    surfaces: &Surfaces,
+    compression: Compression,
+    mut offset: u64,
+    first_layer: u32,
+) -> TextureResult<Vec<ChunkEntry>> {
+    surfaces
+        .slice()
+        .iter()
+        .map(|s| {
+            let compressed = compression.compress(&s.surface.buffer)?;
+            let (compression, data) = match compressed.len() < s.surface.buffer.len() {
+                true => (compression, &compressed[..]),
+                false => (Compression::None, &s.surface.buffer[..]),
+            };
+            writer.write_all(data)?;
+            let entry = ChunkEntry {
+                layer: first_layer + s.layer.unwrap_or(0) as u32,
+                face: s.face.map_or(0, |f| f as u8),
+                mip: s.mip.unwrap_or(0) as u32,
+                compression,
+                offset,
+                stored_size: data.len() as u64,
+                size: s.surface.buffer.len() as u64,
+            };
+            offset += entry.stored_size;
+            Ok(entry)
+        })
+        .collect()
+}
+
+impl ContainerHeader for ChunkedHeader {
+    type Args = ChunkedArgs;
+
+    const CAPABILITIES: ContainerCapabilities = ContainerCapabilities {
+        formats: make_bitflags!(FormatFamily::{BC1 | BC2 | BC3 | BC4 | BC5 | BC7 | ETC2RGB8 | ETC2RGBA8 | EACR11 | EACRG11 | ASTC | Uncompressed | Channels}),
+        shapes: BitFlags::ALL,
+    };
+
+    fn read_surfaces<R: Read + Seek>(&self, reader: &mut R) -> TextureResult<Surfaces> {
+        ChunkedIndex::read_after_header(self.clone(), reader)?.read_all(reader)
+    }
+
+    fn write_surfaces<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        surfaces: &Surfaces,
+    ) -> TextureResult<()> {
+        // the table of contents is only known once the chunks are written,
+        // so the header written before this is patched afterwards
+        let base = writer.stream_position()?;
+        let entries = write_chunks(writer, surfaces, self.compression, 0, 0)?;
+        let toc_offset = writer.stream_position()? - base;
+        writer.write_le(&Toc { entries })?;
+        let end = writer.stream_position()?;
+
+        let header = ChunkedHeader {
+            toc_offset,
+            ..self.clone()
+        };
+        writer.seek(SeekFrom::Start(base - HEADER_SIZE))?;
+        writer.write_le(&header)?;
+        writer.seek(SeekFrom::Start(end))?;
+        Ok(())
+    }
+
+    fn from_texture_args(texture: &Texture, args: &ChunkedArgs) -> TextureResult<Self> {
+        let dimensions = texture.try_dimensions()?;
+        let mut padded = [0u32; 3];
+        for (p, d) in padded.iter_mut().zip(dimensions.as_ref()) {
+            *p = d.get();
+        }
+        Ok(Self {
+            version: CHUNKED_VERSION,
+            format: FormatRecord::from_format(texture.format)?,
+            dimensions: padded,
+            mips: texture.mips().unwrap_or(0) as u32,
+            layers: texture.layers().unwrap_or(0) as u32,
+            faces: texture
+                .faces()
+                .map_or(0, |f| f.iter().fold(0, |m, &f| m | 1 << f as u8)),
+            toc_offset: 0,
+            compression: args.compression,
+        })
+    }
+
+    fn dimensions(&self) -> TextureResult<Dimensions> {
+        let axes = self.dimensions.iter().copied().take_while(|&d| d != 0);
+        Ok(Dimensions::try_from(axes.collect_vec())?)
+    }
+
+    fn layers(&self) -> TextureResult<Option<usize>> {
+        Ok((self.layers != 0).then_some(self.layers as usize))
+    }
+
+    fn faces(&self) -> TextureResult<Option<Vec<CubeFace>>> {
+        Ok(self.face_list())
+    }
+
+    fn mips(&self) -> TextureResult<Option<usize>> {
+        Ok((self.mips != 0).then_some(self.mips as usize))
+    }
+
+    fn format(&self) -> TextureResult<Format> {
+        self.format.format()
+    }
+}
+
+/// The header and table of contents of a chunked container, for reading individual surfaces
+/// or appending layers without reading the whole file
+#[derive(Debug, Clone)]
+pub struct ChunkedIndex {
+    /// The container's header
+    pub header: ChunkedHeader,
+    /// Every chunk in the container, in the order they are listed in the table of contents
+    pub chunks: Vec<ChunkEntry>,
+    /// Position of the end of the header in the stream the index was read from
+    base: u64,
+}
+
+impl ChunkedIndex {
+    /// Read the header and table of contents of a chunked container starting at the reader's position
+    pub fn read<R: Read + Seek>(reader: &mut R) -> TextureResult<Self> {
+        let header = ChunkedHeader::read_header(reader)?;
+        Self::read_after_header(header, reader)
+    }
+
+    fn read_after_header<R: Read + Seek>(
+        header: ChunkedHeader,
+        reader: &mut R,
+    ) -> TextureResult<Self> {
+        let base = reader.stream_position()?;
+        reader.seek(position(base, header.toc_offset)?)?;
+        let toc: Toc = reader.read_le()?;
+        Ok(Self {
+            header,
+            chunks: toc.entries,
+            base,
+        })
+    }
+
+    /// Find the chunk holding a surface. Indices for structures the texture doesn't have are ignored
+    pub fn find(&self, layer: usize, face: CubeFace, mip: usize) -> Option<&ChunkEntry> {
+        let layer = if self.header.layers == 0 { 0 } else { layer };
+        let face = if self.header.faces == 0 {
+            0
+        } else {
+            face as u8
+        };
+        let mip = if self.header.mips == 0 { 0 } else { mip };
+        self.chunks
+            .iter()
+            .find(|c| c.layer as usize == layer && c.face == face && c.mip as usize == mip)
+    }
+
+    /// Read and decompress the surface in a single chunk
+    pub fn read_surface<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        chunk: &ChunkEntry,
+    ) -> TextureResult<Surface> {
+        let dimensions = self.header.mip_dimensions(chunk.mip)?;
+        let expected = self.header.format()?.size_for(dimensions)?;
+        if chunk.size as usize != expected {
+            return Err(TextureError::Other(format!(
+                "Chunk for mip {} is {} bytes instead of {expected}",
+                chunk.mip, chunk.size
+            )));
+        }
+        // read through `take` so a chunk claiming a huge stored size can't allocate more than the file contains
+        let mut stored = Vec::new();
+        reader.seek(position(self.base, chunk.offset)?)?;
+        (&mut *reader)
+            .take(chunk.stored_size)
+            .read_to_end(&mut stored)?;
+        if stored.len() as u64 != chunk.stored_size {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let buffer = chunk.compression.decompress(&stored, expected)?;
+        Ok(Surface::new(dimensions, buffer))
+    }
+
+    /// Read every surface, arranged in the shape described by the header
+    pub fn read_all<R: Read + Seek>(&self, reader: &mut R) -> TextureResult<Surfaces> {
+        let mut chunks = BTreeMap::new();
+        for chunk in &self.chunks {
+            let key = (chunk.layer, chunk.face, chunk.mip);
+            if chunks.insert(key, chunk).is_some() {
+                return Err(TextureError::Other(format!(
+                    "Multiple chunks for layer {}, face {}, mip {}",
+                    chunk.layer, chunk.face, chunk.mip
+                )));
+            }
+        }
+        let mut read = |layer: u32, face: u8, mip: u32| -> TextureResult<Surfaces> {
+            let chunk = chunks.get(&(layer, face, mip)).ok_or_else(|| {
+                TextureError::Other(format!(
+                    "Missing chunk for layer {layer}, face {face}, mip {mip}"
+                ))
+            })?;
+            Ok(TextureShapeNode::from_surface(
+                self.read_surface(reader, chunk)?,
+            ))
+        };
+
+        let mut read_mips = |layer, face| -> TextureResult<Surfaces> {
+            match self.header.mips {
+                0 => read(layer, face, 0),
+                n => Ok(Surfaces::try_from_mips(
+                    (0..n)
+                        .map(|m| read(layer, face, m))
+                        .try_collect::<_, Vec<_>, _>()?,
+                )?),
+            }
+        };
+        let mut read_faces = |layer| -> TextureResult<Surfaces> {
+            match self.header.face_list() {
+                None => read_mips(layer, 0),
+                Some(faces) => Ok(Surfaces::try_from_faces(
+                    faces
+                        .into_iter()
+                        .map(|f| Ok((f, read_mips(layer, f as u8)?)))
+                        .collect::<TextureResult<Vec<_>>>()?,
+                )?),
+            }
+        };
+        match self.header.layers {
+            0 => read_faces(0),
+            n => Ok(Surfaces::try_from_layers(
+                (0..n).map(&mut read_faces).try_collect::<_, Vec<_>, _>()?,
+            )?),
+        }
+    }
+
+    /// Append layers to an array texture in a chunked container, writing their chunks over the old
+    /// table of contents and then writing a new one. Every other chunk is left untouched.
+    /// `layers` must be an array of layers with the same format, faces, mips, and dimensions as the texture.
+    ///
+    /// `file` must be positioned at the start of the container, as it was when the index was read
+    pub fn append_layers<F: Read + Write + Seek>(
+        &mut self,
+        file: &mut F,
+        layers: &Texture,
+        compression: Compression,
+    ) -> TextureResult<()> {
+        layers.check_format()?;
+        let dimensions = self.header.dimensions()?;
+        let compatible = self.header.layers != 0
+            && layers.layers().is_some()
+            && layers.format == self.header.format()?
+            && layers.dimensions() == dimensions
+            && layers.mips().unwrap_or(0) as u32 == self.header.mips
+            && layers.faces() == self.header.face_list();
+        if !compatible {
+            return Err(TextureError::Shape(crate::shape::ShapeError::NonUniform(
+                "shape, format, or dimensions",
+            )));
+        }
+
+        file.seek(position(self.base, self.header.toc_offset)?)?;
+        let entries = write_chunks(
+            file,
+            &layers.surfaces,
+            compression,
+            self.header.toc_offset,
+            self.header.layers,
+        )?;
+        self.chunks.extend(entries);
+        self.header.layers += layers.layers().unwrap_or(0) as u32;
+        self.header.toc_offset = file.stream_position()? - self.base;
+        file.write_le(&Toc {
+            entries: self.chunks.clone(),
+        })?;
+
+        file.seek(SeekFrom::Start(self.base - HEADER_SIZE))?;
+        file.write_le(&self.header)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    /// An array of `layers` 8x8 cubemaps with full mip chains, in an uncompressed format.
+    /// Every byte of a surface is the same, and compressible
+    fn cube_array(layers: usize, first_tag: u8) -> Result<Texture> {
+        let format = Format::Uncompressed {
+            pitch: 2,
+            color_format: ColorFormat::L { l_mask: 0xFF },
+            alpha_format: AlphaFormat::Premultiplied { alpha_mask: 0xFF00 },
+        };
+        let mut tag = first_tag;
+        let layers = (0..layers)
+            .map(|_| {
+                let faces = CubeFace::VARIANTS.iter().map(|&f| {
+                    let mips = Dimensions::try_from([8, 8]).unwrap().mips().map(|d| {
+                        tag = tag.wrapping_add(1);
+                        TextureShapeNode::from_surface(Surface::new(
+                            d,
+                            vec![tag; format.size_for(d).unwrap()],
+                        ))
+                    });
+                    (f, Surfaces::try_from_mips(mips.collect_vec()).unwrap())
+                });
+                Surfaces::try_from_faces(faces.collect_vec())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Texture::new(format, Surfaces::try_from_layers(layers)?)?)
+    }
+
+    fn assert_same(a: &Texture, b: &Texture) {
+        assert_eq!(a.format, b.format);
+        assert_eq!(a.layers(), b.layers());
+        assert_eq!(a.faces(), b.faces());
+        assert_eq!(a.mips(), b.mips());
+        for (a, b) in a.slice().iter().zip(b.slice().iter()) {
+            assert_eq!((a.layer, a.face, a.mip), (b.layer, b.face, b.mip));
+            assert_eq!(a.surface.dimensions(), b.surface.dimensions());
+            assert_eq!(a.surface.buffer, b.surface.buffer);
+        }
+    }
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        let texture = cube_array(2, 0)?;
+        let mut file = Cursor::new(Vec::new());
+        ChunkedHeader::write_texture(&mut file, &texture)?;
+        file.set_position(0);
+        assert_same(&ChunkedHeader::read_texture(&mut file)?, &texture);
+
+        // the registry recognizes the container by its magic
+        file.set_position(0);
+        assert_same(&crate::registry::read_any_texture(&mut file)?, &texture);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn compressed() -> Result<()> {
+        let texture = cube_array(1, 0)?;
+        let mut plain = Cursor::new(Vec::new());
+        ChunkedHeader::write_texture(&mut plain, &texture)?;
+        let mut compressed = Cursor::new(Vec::new());
+        let args = ChunkedArgs {
+            compression: Compression::Deflate,
+        };
+        ChunkedHeader::write_texture_args(&mut compressed, &texture, &args)?;
+        assert!(compressed.get_ref().len() < plain.get_ref().len());
+
+        compressed.set_position(0);
+        let index = ChunkedIndex::read(&mut compressed)?;
+        let mip0 = index.find(0, CubeFace::PositiveX, 0).unwrap();
+        assert_eq!(mip0.compression, Compression::Deflate);
+        let mip4 = index.find(0, CubeFace::PositiveX, 3).unwrap();
+        assert_eq!(
+            mip4.compression,
+            Compression::None,
+            "tiny chunks don't shrink"
+        );
+
+        compressed.set_position(0);
+        assert_same(&ChunkedHeader::read_texture(&mut compressed)?, &texture);
+        Ok(())
+    }
+
+    #[test]
+    fn random_access() -> Result<()> {
+        let texture = cube_array(2, 0)?;
+        let mut file = Cursor::new(Vec::new());
+        ChunkedHeader::write_texture(&mut file, &texture)?;
+
+        file.set_position(0);
+        let index = ChunkedIndex::read(&mut file)?;
+        assert_eq!(index.chunks.len(), 2 * 6 * 4);
+        let chunk = index.find(1, CubeFace::NegativeY, 2).unwrap();
+        let surface = index.read_surface(&mut file, chunk)?;
+        let expected = texture
+            .slice()
+            .iter()
+            .find(|s| s.layer == Some(1) && s.face == Some(CubeFace::NegativeY) && s.mip == Some(2))
+            .unwrap()
+            .surface;
+        assert_eq!(surface.dimensions(), Dimensions::try_from([2, 2])?);
+        assert_eq!(surface.buffer, expected.buffer);
+        Ok(())
+    }
+
+    #[test]
+    fn hostile_sizes() -> Result<()> {
+        let texture = cube_array(1, 0)?;
+        let mut file = Cursor::new(Vec::new());
+        ChunkedHeader::write_texture(&mut file, &texture)?;
+        file.set_position(0);
+        let index = ChunkedIndex::read(&mut file)?;
+
+        // a stored size far larger than the file must fail without trying to allocate it
+        let huge = ChunkEntry {
+            stored_size: u64::MAX,
+            ..index.chunks[0]
+        };
+        assert!(index.read_surface(&mut file, &huge).is_err());
+        let wrong = ChunkEntry {
+            size: index.chunks[0].size * 2,
+            ..index.chunks[0]
+        };
+        assert!(index.read_surface(&mut file, &wrong).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn append() -> Result<()> {
+        let texture = cube_array(2, 0)?;
+        let extra = cube_array(1, 100)?;
+        let mut file = Cursor::new(Vec::new());
+        ChunkedHeader::write_texture(&mut file, &texture)?;
+
+        file.set_position(0);
+        let mut index = ChunkedIndex::read(&mut file)?;
+        file.set_position(0);
+        index.append_layers(&mut file, &extra, Compression::None)?;
+
+        file.set_position(0);
+        let appended = ChunkedHeader::read_texture(&mut file)?;
+        assert_eq!(appended.layers(), Some(3));
+        let layers = texture
+            .surfaces()
+            .iter_layers()
+            .chain(extra.surfaces().iter_layers())
+            .map(|(_, l)| l);
+        let combined = Texture::new(texture.format, Surfaces::try_from_layers(layers)?)?;
+        assert_same(&appended, &combined);
+
+        let mismatched = Texture::new(
+            texture.format,
+            cube_array(1, 0)?.surfaces().get_mip(0).unwrap(),
+        )?;
+        assert!(index
+            .append_layers(&mut file, &mismatched, Compression::None)
+            .is_err());
+        Ok(())
+    }
+
+    /// ETC2 textures have no codec, but can be stored, have mips stripped, and be stored again
+    #[test]
+    fn etc2_passthrough() -> Result<()> {
+        let format = Format::ETC2RGBA8 { srgb: true };
+        let mips = Dimensions::try_from([16, 8])?.mips().map(|d| {
+            let size = format.size_for(d).unwrap();
+            TextureShapeNode::from_surface(Surface::new(d, vec![size as u8; size]))
+        });
+        let texture = Texture::new(format, Surfaces::try_from_mips(mips.collect_vec())?)?;
+        assert_eq!(texture.payload_size(), 16 * (8 + 2 + 1 + 1 + 1));
+
+        let mut file = Cursor::new(Vec::new());
+        ChunkedHeader::write_texture(&mut file, &texture)?;
+        file.set_position(0);
+        let mut read = ChunkedHeader::read_texture(&mut file)?;
+        assert_same(&read, &texture);
+
+        read.surfaces.strip_top_mips(2)?;
+        assert_eq!(read.dimensions(), Dimensions::try_from([4, 2])?);
+        let mut file = Cursor::new(Vec::new());
+        ChunkedHeader::write_texture(&mut file, &read)?;
+        file.set_position(0);
+        assert_same(&ChunkedHeader::read_texture(&mut file)?, &read);
+        Ok(())
+    }
+
+    #[test]
+    fn formats() -> Result<()> {
+        let formats = [
+            Format::BC1 { srgb: true },
+            Format::BC5 { signed: true },
+            cube_array(1, 0)?.format,
+            Format::Uncompressed {
+                pitch: 4,
+                color_format: ColorFormat::RGB {
+                    r_mask: 0xFF0000,
+                    g_mask: 0xFF00,
+                    b_mask: 0xFF,
+                    srgb: true,
+                },
+                alpha_format: AlphaFormat::Opaque,
+            },
+            Format::Channels {
+                channel_type: ChannelType::Float16,
+                channels: 4,
+            },
+            Format::ASTC {
+                block_dims: Dimensions::try_from([6, 5])?,
+                srgb: true,
+            },
+            Format::ASTC {
+                block_dims: Dimensions::try_from([4, 4, 3])?,
+                srgb: false,
+            },
+            Format::ETC2RGB8 { srgb: true },
+            Format::ETC2RGBA8 { srgb: false },
+            Format::EACR11 { signed: true },
+            Format::EACRG11 { signed: false },
+        ];
+        for format in formats {
+            assert_eq!(FormatRecord::from_format(format)?.format()?, format);
+        }
+        Ok(())
+    }
+}
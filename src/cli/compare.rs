@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `quicktex compare`: measure the difference between two textures, for codec quality regression testing
+
+use std::path::PathBuf;
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+
+use quicktex::metrics::{ComparisonReport, SurfaceMetrics};
+
+use super::{face_name, read_input, CliResult};
+
+pub fn command() -> Command {
+    Command::new("compare")
+        .about("Compare two textures of the same shape, printing the PSNR, MSE, and maximum error of every surface")
+        .arg(
+            Arg::new("reference")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Texture to compare against, in any container quicktex can read"),
+        )
+        .arg(
+            Arg::new("distorted")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Texture to measure, such as an encoded copy of the reference"),
+        )
+        .arg(
+            Arg::new("min-psnr")
+                .long("min-psnr")
+                .value_parser(value_parser!(f64))
+                .help("Fail if the PSNR of any surface is below this many decibels"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Print a JSON object instead of a table. Infinite PSNRs are written as null"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> CliResult {
+    let read = |name| -> CliResult<_> {
+        let path = args.get_one::<PathBuf>(name).unwrap();
+        read_input(path)
+    };
+    let (reference, distorted) = (read("reference")?, read("distorted")?);
+    let report = reference.compare(&distorted)?;
+
+    match args.get_flag("json") {
+        true => print!("{}", json(&report)),
+        false => print!("{}", table(&report)),
+    }
+
+    match args.get_one::<f64>("min-psnr") {
+        Some(&min) if report.min_psnr() < min => Err(format!(
+            "lowest surface PSNR {:.2} dB is below {min:.2} dB",
+            report.min_psnr()
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Name a surface by its location, such as `layer 1 +x mip 2`, or `surface` for a single surface
+fn location(s: &SurfaceMetrics) -> String {
+    let mut parts = Vec::new();
+    if let Some(layer) = s.layer {
+        parts.push(format!("layer {layer}"));
+    }
+    if let Some(face) = s.face {
+        parts.push(face_name(face).to_string());
+    }
+    if let Some(mip) = s.mip {
+        parts.push(format!("mip {mip}"));
+    }
+    match parts.is_empty() {
+        true => "surface".to_string(),
+        false => parts.join(" "),
+    }
+}
+
+fn table(report: &ComparisonReport) -> String {
+    let rows: Vec<[String; 5]> = (report.surfaces.iter())
+        .map(|s| {
+            [
+                location(s),
+                format!("{:?}", s.dimensions),
+                format!("{:.2}", s.psnr()),
+                format!("{:.6}", s.mse),
+                format!("{:.4}", s.max_error),
+            ]
+        })
+        .chain(std::iter::once([
+            "total".to_string(),
+            String::new(),
+            format!("{:.2}", report.psnr()),
+            format!("{:.6}", report.mse()),
+            format!("{:.4}", report.max_error()),
+        ]))
+        .collect();
+    let header = ["surface", "dims", "psnr", "mse", "max error"].map(str::to_string);
+    let mut widths = header.each_ref().map(String::len);
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.len());
+        }
+    }
+
+    let mut output = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = (row.iter().zip(widths))
+            .enumerate()
+            .map(|(i, (cell, w))| match i {
+                // locations and dimensions are left aligned, numbers are right aligned
+                0 | 1 => format!("{cell:<w$}"),
+                _ => format!("{cell:>w$}"),
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+/// A PSNR as a JSON number, or `null` if it is infinite
+fn json_psnr(psnr: f64) -> String {
+    match psnr.is_finite() {
+        true => format!("{psnr}"),
+        false => "null".to_string(),
+    }
+}
+
+fn json(report: &ComparisonReport) -> String {
+    let option = |o: Option<usize>| o.map_or("null".to_string(), |v| v.to_string());
+    let surfaces: Vec<String> = (report.surfaces.iter())
+        .map(|s| {
+            let face = s.face.map_or("null".to_string(), |f| format!("\"{}\"", face_name(f)));
+            format!(
+                "{{\"layer\":{},\"face\":{face},\"mip\":{},\"dimensions\":[{}],\"psnr\":{},\"mse\":{},\"max_error\":{}}}",
+                option(s.layer),
+                option(s.mip),
+                s.dimensions.into_iter().map(|d| d.to_string()).collect::<Vec<_>>().join(","),
+                json_psnr(s.psnr()),
+                s.mse,
+                s.max_error,
+            )
+        })
+        .collect();
+    format!(
+        "{{\"psnr\":{},\"min_psnr\":{},\"mse\":{},\"max_error\":{},\"surfaces\":[{}]}}\n",
+        json_psnr(report.psnr()),
+        json_psnr(report.min_psnr()),
+        report.mse(),
+        report.max_error(),
+        surfaces.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use quicktex::format::{Format, Quality};
+    use quicktex::texture::Texture;
+
+    use super::*;
+
+    #[test]
+    fn output() -> CliResult {
+        let image = image::RgbaImage::from_fn(8, 8, |x, _| image::Rgba([x as u8 * 30, 0, 0, 255]));
+        let reference =
+            Texture::from_image(&image.into(), Format::rgba8(false), true, Quality::Normal)?;
+        let distorted = reference.transcode(Format::BC1 { srgb: false }, Quality::Normal)?;
+        let report = reference.compare(&distorted)?;
+
+        let table = table(&report);
+        assert_eq!(table.lines().count(), 6, "header, 4 mips, and a total");
+        assert!(table.lines().nth(1).unwrap().starts_with("mip 0    8x8"));
+        assert!(table.lines().last().unwrap().starts_with("total"));
+
+        let json = json(&reference.compare(&reference)?);
+        assert!(json.starts_with("{\"psnr\":null,\"min_psnr\":null,\"mse\":0,"));
+        assert!(json.contains("{\"layer\":null,\"face\":null,\"mip\":3,\"dimensions\":[1,1],"));
+        Ok(())
+    }
+}
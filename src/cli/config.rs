@@ -0,0 +1,348 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `quicktex.toml` cooking profiles.
+//!
+//! A config file has an optional `[defaults]` table and any number of `[[rule]]` tables,
+//! each with the same keys:
+//!
+//! ```toml
+//! [defaults]
+//! format = "bc1-srgb"
+//!
+//! [[rule]]
+//! pattern = "**/*_normal.png"
+//! format = "bc5"
+//!
+//! [[rule]]
+//! pattern = "ui/**"
+//! format = "rgba8"
+//! srgb = true
+//! mips = false
+//! ```
+//!
+//! Settings are resolved in order from the command-line defaults, the `[defaults]` table,
+//! any options given explicitly on the command line, and finally the first rule whose `pattern`
+//! matches the image's path relative to the source directory
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+
+use quicktex::format::{ColorFormat, Format, Quality};
+
+use super::{parse_format, CliResult};
+
+/// Name of the config file looked for in the root of the source directory
+pub const CONFIG_NAME: &str = "quicktex.toml";
+
+/// Container file types that textures can be written to
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Container {
+    #[default]
+    Dds,
+}
+
+impl Container {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Dds => "dds",
+        }
+    }
+}
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dds" => Ok(Container::Dds),
+            _ => Err(format!("unknown container '{s}'. Expected dds")),
+        }
+    }
+}
+
+/// The settings used to cook a single image
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub format: Format,
+    pub quality: Quality,
+    pub mips: bool,
+    pub container: Container,
+}
+
+/// Which settings were given explicitly on the command line, and so override `[defaults]`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Explicit {
+    pub format: bool,
+    pub quality: bool,
+    pub mips: bool,
+}
+
+/// One table of settings in a config file. Every key is optional
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    /// Glob matched against paths relative to the source directory. Required in rules, and not allowed in defaults
+    pattern: Option<String>,
+    format: Option<String>,
+    srgb: Option<bool>,
+    mips: Option<bool>,
+    quality: Option<String>,
+    container: Option<String>,
+}
+
+impl Profile {
+    fn apply(&self, settings: &mut Settings, explicit: Explicit) -> CliResult {
+        if let Some(format) = self.format.as_deref().filter(|_| !explicit.format) {
+            settings.format = parse_format(format)?;
+        }
+        if let Some(srgb) = self.srgb.filter(|_| !explicit.format) {
+            settings.format = with_srgb(settings.format, srgb)?;
+        }
+        if let Some(mips) = self.mips.filter(|_| !explicit.mips) {
+            settings.mips = mips;
+        }
+        if let Some(quality) = self.quality.as_deref().filter(|_| !explicit.quality) {
+            settings.quality = quality.parse().map_err(|_| {
+                format!(
+                    "unknown quality '{quality}'. Expected fastest, normal, high, or exhaustive"
+                )
+            })?;
+        }
+        if let Some(container) = &self.container {
+            settings.container = container.parse()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    defaults: Profile,
+    #[serde(default)]
+    rule: Vec<Profile>,
+}
+
+/// A parsed and validated config file
+#[derive(Debug, Default)]
+pub struct Config {
+    defaults: Profile,
+    rules: Vec<(Pattern, Profile)>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> CliResult<Self> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    pub fn parse(contents: &str) -> CliResult<Self> {
+        let file: ConfigFile = toml::from_str(contents)?;
+
+        if file.defaults.pattern.is_some() {
+            return Err("[defaults] cannot have a pattern".into());
+        }
+
+        let rules = file
+            .rule
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| -> CliResult<_> {
+                let pattern = rule
+                    .pattern
+                    .as_deref()
+                    .ok_or(format!("rule {} has no pattern", i + 1))?;
+                Ok((Pattern::new(pattern)?, rule))
+            })
+            .collect::<CliResult<_>>()?;
+
+        let config = Self {
+            defaults: file.defaults,
+            rules,
+        };
+
+        // resolve every profile once, so that invalid values are reported immediately
+        // rather than the first time a file matches
+        let base = Settings {
+            format: Format::BC1 { srgb: false },
+            quality: Quality::default(),
+            mips: true,
+            container: Container::default(),
+        };
+        config
+            .defaults
+            .apply(&mut base.clone(), Explicit::default())?;
+        for (pattern, rule) in &config.rules {
+            rule.apply(&mut base.clone(), Explicit::default())
+                .map_err(|e| format!("rule '{pattern}': {e}"))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Resolve the settings for the image at `relative`, which is relative to the source directory.
+    /// Since the source directory has been stripped, `relative` never has the `\\?\` prefix of a verbatim
+    /// Windows path, which patterns would not match
+    pub fn resolve(
+        &self,
+        cli: &Settings,
+        explicit: Explicit,
+        relative: &Path,
+    ) -> CliResult<Settings> {
+        let mut settings = cli.clone();
+        self.defaults.apply(&mut settings, explicit)?;
+
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        if let Some((_, rule)) = self
+            .rules
+            .iter()
+            // match against a lossy string, so names that aren't valid UTF-8 can still match wildcards
+            .find(|(p, _)| p.matches_with(&relative.to_string_lossy(), options))
+        {
+            rule.apply(&mut settings, Explicit::default())?;
+        }
+        Ok(settings)
+    }
+}
+
+/// Set whether a format's color channels are sRGB encoded
+fn with_srgb(format: Format, srgb: bool) -> CliResult<Format> {
+    Ok(match format {
+        Format::BC1 { .. } => Format::BC1 { srgb },
+        Format::BC2 { .. } => Format::BC2 { srgb },
+        Format::BC3 { .. } => Format::BC3 { srgb },
+        Format::BC7 { .. } => Format::BC7 { srgb },
+        Format::ETC2RGB8 { .. } => Format::ETC2RGB8 { srgb },
+        Format::ETC2RGBA8 { .. } => Format::ETC2RGBA8 { srgb },
+        Format::Uncompressed {
+            pitch,
+            color_format:
+                ColorFormat::RGB {
+                    r_mask,
+                    g_mask,
+                    b_mask,
+                    ..
+                },
+            alpha_format,
+        } => Format::Uncompressed {
+            pitch,
+            color_format: ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                srgb,
+            },
+            alpha_format,
+        },
+        _ if !srgb => format,
+        _ => return Err(format!("{format:?} cannot be sRGB").into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [defaults]
+        format = "bc1"
+        quality = "high"
+
+        [[rule]]
+        pattern = "**/*_n.png"
+        format = "bc5"
+
+        [[rule]]
+        pattern = "ui/**"
+        format = "bgra8"
+        srgb = true
+        mips = false
+    "#;
+
+    fn cli() -> Settings {
+        Settings {
+            format: parse_format("rgba8").unwrap(),
+            quality: Quality::Normal,
+            mips: true,
+            container: Container::Dds,
+        }
+    }
+
+    #[test]
+    fn resolve() -> CliResult {
+        let config = Config::parse(CONFIG)?;
+        let explicit = Explicit::default();
+
+        let settings = config.resolve(&cli(), explicit, Path::new("rock.png"))?;
+        assert_eq!(settings.format, Format::BC1 { srgb: false });
+        assert_eq!(settings.quality, Quality::High);
+
+        let settings = config.resolve(&cli(), explicit, Path::new("rock/rock_n.png"))?;
+        assert_eq!(settings.format, Format::BC5 { signed: false });
+
+        let settings = config.resolve(&cli(), explicit, Path::new("ui/icons/a_n.png"))?;
+        assert_eq!(settings.format, parse_format("bc5")?, "first rule wins");
+
+        let settings = config.resolve(&cli(), explicit, Path::new("ui/icons/a.png"))?;
+        assert_eq!(settings.format, parse_format("bgra8-srgb")?);
+        assert!(!settings.mips);
+
+        // explicit options override defaults, but not rules
+        let explicit = Explicit {
+            format: true,
+            ..Default::default()
+        };
+        let settings = config.resolve(&cli(), explicit, Path::new("rock.png"))?;
+        assert_eq!(settings.format, cli().format);
+        let settings = config.resolve(&cli(), explicit, Path::new("rock_n.png"))?;
+        assert_eq!(settings.format, Format::BC5 { signed: false });
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_non_utf8() -> CliResult {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let config = Config::parse(CONFIG)?;
+        let relative = Path::new(OsStr::from_bytes(b"rock/caf\xe9_n.png"));
+        let settings = config.resolve(&cli(), Explicit::default(), relative)?;
+        assert_eq!(settings.format, Format::BC5 { signed: false });
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_long_path() -> CliResult {
+        let config = Config::parse(CONFIG)?;
+        let relative: PathBuf = ["a_fairly_long_directory_name"; 12]
+            .iter()
+            .chain(&["rock_n.png"])
+            .collect();
+        assert!(relative.as_os_str().len() > 260);
+        let settings = config.resolve(&cli(), Explicit::default(), &relative)?;
+        assert_eq!(settings.format, Format::BC5 { signed: false });
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Config::parse("[defaults]\nformat = \"bc9\"").is_err());
+        assert!(Config::parse("[defaults]\nformt = \"bc1\"").is_err());
+        assert!(Config::parse("[[rule]]\nformat = \"bc1\"").is_err());
+        assert!(Config::parse("[[rule]]\npattern = \"*\"\nformat = \"bc4\"\nsrgb = true").is_err());
+        assert!(Config::parse("[[rule]]\npattern = \"*\"\ncontainer = \"ktx\"").is_err());
+    }
+}
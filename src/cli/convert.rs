@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `quicktex convert`: convert a single texture to another format, size, or container
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+
+use quicktex::conversion::ConvertOptions;
+use quicktex::format::{Format, Quality};
+use quicktex::registry::registry;
+
+use super::{parse_format, read_input, threads_arg, CliResult, FORMAT_NAMES};
+
+pub fn command() -> Command {
+    Command::new("convert")
+        .about("Convert a texture to another format, size, or container")
+        .arg(
+            Arg::new("input")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Texture to convert, in any container quicktex can read"),
+        )
+        .arg(
+            Arg::new("output")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("File to write, in the container matching its extension"),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_parser(parse_format)
+                .help(format!(
+                    "Output format. One of {FORMAT_NAMES}. Defaults to the input's format"
+                )),
+        )
+        .arg(
+            Arg::new("quality")
+                .short('q')
+                .long("quality")
+                .default_value("normal")
+                .value_parser(value_parser!(Quality))
+                .help("Encoder quality: fastest, normal, high, or exhaustive"),
+        )
+        .arg(
+            Arg::new("strip-mips")
+                .long("strip-mips")
+                .default_value("0")
+                .value_parser(value_parser!(usize))
+                .help("Number of mips to remove from the top of the mip chain"),
+        )
+        .arg(
+            Arg::new("max-dimension")
+                .long("max-dimension")
+                .value_parser(value_parser!(u32).range(1..))
+                .help("Shrink the texture until no axis is larger than this"),
+        )
+        .arg(
+            Arg::new("make-tileable")
+                .long("make-tileable")
+                .value_name("BAND")
+                .num_args(0..=1)
+                .default_missing_value("0.125")
+                .value_parser(value_parser!(f32))
+                .help(
+                    "Cross-fade the edges of every surface so it tiles seamlessly, \
+                    blending a band BAND of its size wide. Defaults to 0.125",
+                ),
+        )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .action(ArgAction::SetTrue)
+                .help("Measure the error introduced by encoding, and print its PSNR"),
+        )
+        .arg(threads_arg())
+}
+
+pub fn run(args: &ArgMatches) -> CliResult {
+    let input = args.get_one::<PathBuf>("input").unwrap();
+    let output = args.get_one::<PathBuf>("output").unwrap();
+    let options = ConvertOptions {
+        format: args.get_one::<Format>("format").copied(),
+        quality: *args.get_one::<Quality>("quality").unwrap(),
+        strip_mips: *args.get_one::<usize>("strip-mips").unwrap(),
+        max_dimension: args.get_one::<u32>("max-dimension").copied(),
+        tileable: args.get_one::<f32>("make-tileable").copied(),
+        metrics: args.get_flag("metrics"),
+        threads: args.get_one::<usize>("threads").copied(),
+    };
+
+    let registry = registry();
+    let extension = output
+        .extension()
+        .map(|e| e.to_string_lossy())
+        .unwrap_or_default();
+    let write = registry
+        .by_extension(&extension)
+        .and_then(|e| e.write)
+        .ok_or_else(|| format!("can't write textures to '.{extension}' files"))?;
+
+    let texture = read_input(input)?;
+    let (texture, report) = texture.convert(&options)?;
+    write(&mut BufWriter::new(File::create(output)?), &texture)?;
+    println!("{}: {report}", output.display());
+    Ok(())
+}
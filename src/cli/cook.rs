@@ -0,0 +1,509 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `quicktex cook`: convert a directory of images into DDS textures,
+//! skipping any image whose contents and settings haven't changed since it was last cooked.
+//! Settings can be chosen per-image with a config file, see [`super::config`]
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use clap::parser::ValueSource;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use image::ImageFormat;
+
+use quicktex::container::ContainerHeader;
+use quicktex::dds::DDSHeader;
+use quicktex::format::{EncodeOptions, Format, Quality};
+use quicktex::texture::Texture;
+
+use super::config::{Config, Container, Explicit, Settings, CONFIG_NAME};
+use super::{format_name, parse_format, threads_arg, CliResult, FORMAT_NAMES};
+
+/// Name of the cache file written to the root of the output directory
+const CACHE_NAME: &str = ".quicktex-cache";
+
+pub fn command() -> Command {
+    Command::new("cook")
+        .about("Convert a directory of images into DDS textures")
+        .arg(
+            Arg::new("source")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Directory of source images, searched recursively"),
+        )
+        .arg(
+            Arg::new("output")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help(
+                    "Directory to write textures to, mirroring the layout of the source directory",
+                ),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .default_value("rgba8")
+                .value_parser(parse_format)
+                .help(format!("Output format. One of {FORMAT_NAMES}")),
+        )
+        .arg(
+            Arg::new("quality")
+                .short('q')
+                .long("quality")
+                .default_value("normal")
+                .value_parser(value_parser!(Quality))
+                .help("Encoder quality: fastest, normal, high, or exhaustive"),
+        )
+        .arg(
+            Arg::new("no-mips")
+                .long("no-mips")
+                .action(ArgAction::SetTrue)
+                .help("Don't generate mipmaps"),
+        )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .value_parser(value_parser!(PathBuf))
+                .help(format!(
+                    "Config file with per-image settings. Defaults to {CONFIG_NAME} in the source directory, if present"
+                )),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Cook every image, even if it is unchanged since it was last cooked"),
+        )
+        .arg(
+            Arg::new("watch")
+                .short('w')
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .help("Keep running, and cook images again whenever they change"),
+        )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .default_value("500")
+                .value_parser(value_parser!(u64))
+                .help("How often to check for changes in watch mode, in milliseconds"),
+        )
+        .arg(threads_arg())
+}
+
+pub fn run(args: &ArgMatches) -> CliResult {
+    let source = args.get_one::<PathBuf>("source").unwrap();
+    let output = args.get_one::<PathBuf>("output").unwrap();
+    let settings = Settings {
+        format: *args.get_one::<Format>("format").unwrap(),
+        quality: *args.get_one::<Quality>("quality").unwrap(),
+        mips: !args.get_flag("no-mips"),
+        container: Container::default(),
+    };
+    let explicit = |id| args.value_source(id) == Some(ValueSource::CommandLine);
+    let explicit = Explicit {
+        format: explicit("format"),
+        quality: explicit("quality"),
+        mips: explicit("no-mips"),
+    };
+    let config_path = match args.get_one::<PathBuf>("config") {
+        Some(path) => Some(path.clone()),
+        None => Some(source.join(CONFIG_NAME)).filter(|p| p.exists()),
+    };
+
+    let mut cooker = Cooker::new(source.clone(), output.clone(), settings, explicit)?;
+    if let Some(path) = &config_path {
+        cooker.config = Config::load(path)?;
+    }
+    cooker.threads = args.get_one::<usize>("threads").copied();
+    if args.get_flag("force") {
+        cooker.cache.entries.clear();
+    }
+
+    if !args.get_flag("watch") {
+        let stats = cooker.cook_all(|_| true)?;
+        println!("{stats}");
+        return match stats.failed {
+            0 => Ok(()),
+            n => Err(format!("{n} images failed to cook").into()),
+        };
+    }
+
+    // watch mode: poll modification times, rather than depending on platform-specific file events
+    let interval = Duration::from_millis(*args.get_one::<u64>("interval").unwrap());
+    let mut modified: HashMap<PathBuf, SystemTime> = HashMap::new();
+    let modified_time = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut config_time = config_path.as_deref().and_then(modified_time);
+    println!("Watching {} for changes", source.display());
+    loop {
+        if let Some(path) = &config_path {
+            let time = modified_time(path);
+            if time != config_time {
+                config_time = time;
+                match Config::load(path) {
+                    Ok(config) => {
+                        println!("Reloaded {}", path.display());
+                        cooker.config = config;
+                        // settings may have changed for any image, the cache will skip the ones that haven't
+                        modified.clear();
+                    }
+                    Err(e) => eprintln!("error: {e}"),
+                }
+            }
+        }
+
+        let stats = cooker.cook_all(|path| {
+            let Some(time) = modified_time(path) else {
+                return false;
+            };
+            modified.insert(path.to_owned(), time) != Some(time)
+        });
+        match stats {
+            Ok(stats) if stats.cooked + stats.failed > 0 => println!("{stats}"),
+            Ok(_) => {}
+            // the source directory may be briefly unreadable while files are moved around, so keep watching
+            Err(e) => eprintln!("error: {e}"),
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Counts of what happened during one pass over the source directory
+#[derive(Clone, Copy, Debug, Default)]
+struct CookStats {
+    cooked: usize,
+    unchanged: usize,
+    failed: usize,
+}
+
+impl std::fmt::Display for CookStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} cooked, {} unchanged, {} failed",
+            self.cooked, self.unchanged, self.failed
+        )
+    }
+}
+
+/// Map of source paths (relative to the source directory) to the hash of their contents and settings
+/// when they were last cooked, stored as one `<hash>\t<path>` line per entry with paths escaped by [`escape_path`]
+#[derive(Debug, Default)]
+struct Cache {
+    entries: BTreeMap<PathBuf, u64>,
+}
+
+impl Cache {
+    fn load(path: &Path) -> Self {
+        // a missing or unreadable cache just means everything gets cooked again
+        let Ok(contents) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        let entries = contents
+            .lines()
+            .filter_map(|line| {
+                let (hash, path) = line.split_once('\t')?;
+                Some((unescape_path(path)?, u64::from_str_radix(hash, 16).ok()?))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    fn save(&self, path: &Path) -> CliResult {
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|(p, h)| format!("{h:016x}\t{}\n", escape_path(p)))
+            .collect();
+        // write to a temporary file first, so an interrupted write can't leave a corrupt cache
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, contents)?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+}
+
+/// Write a path as a single line of text without losing anything, even if it isn't valid UTF-8.
+/// `%`, control characters, and bytes that aren't part of valid UTF-8 are written as `%XX`
+fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '%' | '\0'..='\x1F' | '\x7F' => escaped += &format!("%{:02X}", c as u32),
+                c => escaped.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            escaped += &format!("%{b:02X}");
+        }
+    }
+    escaped
+}
+
+/// Read a path written by [`escape_path`], or [`None`] if it is malformed or can't be represented on this platform
+fn unescape_path(escaped: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut rest = escaped.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        if b == b'%' {
+            let hex = std::str::from_utf8(rest.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &rest[2..];
+        } else {
+            bytes.push(b);
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+    // other platforms don't have a safe way to build a path from arbitrary bytes,
+    // so entries for names that aren't valid UTF-8 are dropped and those images cooked again
+    #[cfg(not(unix))]
+    {
+        String::from_utf8(bytes).ok().map(PathBuf::from)
+    }
+}
+
+/// 64-bit FNV-1a, used because it is tiny and, unlike [`std::hash::DefaultHasher`],
+/// guaranteed to be stable between runs and Rust versions
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+
+/// Version of [`settings_key`]. Bump it whenever the key's layout changes, or the same settings start
+/// producing different output, so every image is cooked again
+const SETTINGS_VERSION: u32 = 1;
+
+/// Serialize the settings that affect a cooked texture for the cache hash. Unlike their `Debug` representation,
+/// this only changes when [`SETTINGS_VERSION`] does
+fn settings_key(settings: &Settings) -> String {
+    format!(
+        "v{SETTINGS_VERSION};format={};quality={};mips={};container={}",
+        format_name(settings.format),
+        settings.quality,
+        settings.mips,
+        settings.container.extension()
+    )
+}
+
+struct Cooker {
+    source: PathBuf,
+    output: PathBuf,
+    /// Settings from the command line, which are overridden by the config file
+    settings: Settings,
+    explicit: Explicit,
+    config: Config,
+    cache: Cache,
+    /// Number of threads to encode with, or [`None`] for one per CPU core
+    threads: Option<usize>,
+}
+
+impl Cooker {
+    fn new(
+        source: PathBuf,
+        output: PathBuf,
+        settings: Settings,
+        explicit: Explicit,
+    ) -> CliResult<Self> {
+        if !source.is_dir() {
+            return Err(format!("{} is not a directory", source.display()).into());
+        }
+        fs::create_dir_all(&output)?;
+        let cache = Cache::load(&output.join(CACHE_NAME));
+        Ok(Self {
+            source,
+            output,
+            settings,
+            explicit,
+            config: Config::default(),
+            cache,
+            threads: None,
+        })
+    }
+
+    /// Cook every image in the source directory for which `filter` returns true
+    fn cook_all(&mut self, mut filter: impl FnMut(&Path) -> bool) -> CliResult<CookStats> {
+        let mut images = Vec::new();
+        // canonical paths on Windows have a `\\?\` verbatim prefix, so this is only compared
+        // with other canonical paths, and never joined or stripped
+        find_images(&self.source, &self.output.canonicalize()?, &mut images)?;
+        images.sort();
+
+        let mut stats = CookStats::default();
+        for image in images.into_iter().filter(|p| filter(p)) {
+            let relative = image.strip_prefix(&self.source)?.to_owned();
+            match self.cook(&image, &relative) {
+                Ok(true) => {
+                    stats.cooked += 1;
+                    println!("{}", relative.display());
+                }
+                Ok(false) => stats.unchanged += 1,
+                Err(e) => {
+                    stats.failed += 1;
+                    eprintln!("{}: {e}", relative.display());
+                }
+            }
+        }
+
+        if stats.cooked > 0 {
+            self.cache.save(&self.output.join(CACHE_NAME))?;
+        }
+        Ok(stats)
+    }
+
+    /// Cook a single image, returning false if it was skipped because it is unchanged
+    fn cook(&mut self, path: &Path, relative: &Path) -> CliResult<bool> {
+        let settings = self
+            .config
+            .resolve(&self.settings, self.explicit, relative)?;
+        let contents = fs::read(path)?;
+        let hash = fnv1a(
+            fnv1a(FNV_OFFSET, &contents),
+            settings_key(&settings).as_bytes(),
+        );
+        let output = self
+            .output
+            .join(relative)
+            .with_extension(settings.container.extension());
+
+        if self.cache.entries.get(relative) == Some(&hash) && output.exists() {
+            return Ok(false);
+        }
+
+        let image = image::load_from_memory(&contents)?;
+        let options = EncodeOptions::default()
+            .quality(settings.quality)
+            .threads(self.threads);
+        let texture = Texture::from_image_with(&image, settings.format, settings.mips, options)?;
+
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(&output)?);
+        match settings.container {
+            Container::Dds => DDSHeader::write_texture(&mut writer, &texture)?,
+        }
+
+        self.cache.entries.insert(relative.to_owned(), hash);
+        Ok(true)
+    }
+}
+
+/// Recursively collect every file in `dir` with an image extension, skipping `exclude`
+/// (which must be canonical) so that an output directory inside the source directory isn't searched
+fn find_images(dir: &Path, exclude: &Path, images: &mut Vec<PathBuf>) -> CliResult {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path.canonicalize()? != exclude {
+                find_images(&path, exclude, images)?;
+            }
+        } else if ImageFormat::from_path(&path).is_ok_and(|f| f.reading_enabled()) {
+            images.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_paths() {
+        for path in [
+            "textures/rock.png",
+            "100% done\tnew\nline.png",
+            "caf\u{e9}/\u{1F5FF}.png",
+        ] {
+            let path = Path::new(path);
+            assert!(!escape_path(path).contains(['\t', '\n']));
+            assert_eq!(unescape_path(&escape_path(path)).as_deref(), Some(path));
+        }
+        assert_eq!(escape_path(Path::new("50%.png")), "50%25.png");
+        assert_eq!(unescape_path("bad%2"), None);
+        assert_eq!(unescape_path("bad%zz"), None);
+    }
+
+    #[test]
+    fn stable_settings_key() -> CliResult {
+        let settings = Settings {
+            format: parse_format("bgra8-srgb")?,
+            quality: Quality::High,
+            mips: false,
+            container: Container::Dds,
+        };
+        // changing this string invalidates every cache, so bump SETTINGS_VERSION along with it
+        assert_eq!(
+            settings_key(&settings),
+            "v1;format=bgra8-srgb;quality=high;mips=false;container=dds"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn long_paths() -> CliResult {
+        // longer than Windows' 260 character limit. std::fs adds the `\\?\` prefix that lifts the limit
+        // to long paths itself, so cooking doesn't need to
+        let dir = tempfile::tempdir()?;
+        let nested: PathBuf = ["a_fairly_long_directory_name"; 10].iter().collect();
+        let source = dir.path().join("source");
+        let output = dir.path().join("output");
+        fs::create_dir_all(source.join(&nested))?;
+        image::RgbaImage::new(8, 8).save(source.join(&nested).join("rock.png"))?;
+        assert!(output.join(&nested).as_os_str().len() > 260);
+
+        let settings = Settings {
+            format: Format::BC1 { srgb: false },
+            quality: Quality::Fastest,
+            mips: true,
+            container: Container::Dds,
+        };
+        let cook = || -> CliResult<CookStats> {
+            let mut cooker = Cooker::new(
+                source.clone(),
+                output.clone(),
+                settings.clone(),
+                Explicit::default(),
+            )?;
+            cooker.cook_all(|_| true)
+        };
+        assert_eq!(cook()?.cooked, 1);
+        assert!(output.join(&nested).join("rock.dds").exists());
+        assert_eq!(cook()?.unchanged, 1, "the cache entry is found again");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cache_non_utf8() -> CliResult {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir()?;
+        let path = Path::new(OsStr::from_bytes(b"caf\xe9\xff.png"));
+        assert_eq!(escape_path(path), "caf%E9%FF.png");
+
+        let mut cache = Cache::default();
+        cache.entries.insert(path.to_owned(), 42);
+        cache.save(&dir.path().join(CACHE_NAME))?;
+        let loaded = Cache::load(&dir.path().join(CACHE_NAME));
+        assert_eq!(loaded.entries.get(path), Some(&42));
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `quicktex decompress`: decode every surface of a texture to PNGs,
+//! or to raw RGBA dumps with a JSON sidecar describing their layout
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use image::ImageFormat;
+
+use quicktex::dimensions::Dimensioned;
+use quicktex::format::{DecodeOptions, Interpolation};
+use quicktex::shape::TextureIterResult;
+use quicktex::texture::Surface;
+
+use super::{face_name, json_string, read_input, threads_arg, CliResult};
+
+pub fn command() -> Command {
+    Command::new("decompress")
+        .about("Decode every surface of a texture to PNG images or raw RGBA")
+        .arg(
+            Arg::new("input")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Texture to decode, in any container quicktex can read"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_parser(value_parser!(PathBuf))
+                .help("Directory to write surfaces to. Defaults to the directory of the input"),
+        )
+        .arg(Arg::new("raw").long("raw").action(ArgAction::SetTrue).help(
+            "Write tightly packed 8-bit RGBA instead of PNGs, \
+                    with a JSON file describing each surface",
+        ))
+        .arg(
+            Arg::new("interpolation")
+                .long("interpolation")
+                .default_value("reference")
+                .value_parser(value_parser!(Interpolation))
+                .help("BC1-BC3 color interpolation to match: reference, nvidia, amd, or ideal"),
+        )
+        .arg(threads_arg())
+}
+
+pub fn run(args: &ArgMatches) -> CliResult {
+    let input = args.get_one::<PathBuf>("input").unwrap();
+    let output = match args.get_one::<PathBuf>("output") {
+        Some(output) => output.clone(),
+        None => input.parent().map(Path::to_owned).unwrap_or_default(),
+    };
+    let raw = args.get_flag("raw");
+    // output names are built from the stem as an `OsStr`, so names that aren't valid UTF-8 survive unchanged
+    let stem = input
+        .file_stem()
+        .ok_or_else(|| format!("{} is not a file", input.display()))?;
+
+    let texture = read_input(input)?;
+    let options = DecodeOptions::default()
+        .interpolation(*args.get_one::<Interpolation>("interpolation").unwrap())
+        .threads(args.get_one::<usize>("threads").copied());
+    let decoder = texture.format.decoder_with(&options)?;
+    fs::create_dir_all(&output)?;
+
+    let mut entries = Vec::new();
+    for surface in texture.slice().iter() {
+        let mut file = surface_name(stem, &surface);
+        let image =
+            decoder.decode_buffer(surface.surface.buffer.clone(), surface.surface.dimensions())?;
+        match raw {
+            true => {
+                file.push(".rgba");
+                fs::write(output.join(&file), image.to_rgba8().as_raw())?;
+            }
+            false => {
+                file.push(".png");
+                image.save_with_format(output.join(&file), ImageFormat::Png)?;
+            }
+        };
+        println!("{}", output.join(&file).display());
+        entries.push(sidecar_entry(
+            &file.to_string_lossy(),
+            &surface,
+            image.width(),
+            image.height(),
+        ));
+    }
+
+    if raw {
+        let sidecar = format!(
+            "{{\n  \"source\": {},\n  \"format\": {},\n  \"pixel_format\": \"rgba8\",\n  \"surfaces\": [\n{}\n  ]\n}}\n",
+            json_string(&input.display().to_string()),
+            json_string(&format!("{:?}", texture.format)),
+            entries.join(",\n"),
+        );
+        let mut file = stem.to_owned();
+        file.push(".json");
+        fs::write(output.join(file), sidecar)?;
+    }
+    Ok(())
+}
+
+/// Name a surface after its texture and position, e.g. `sky_layer1_+x_mip2`.
+/// Structures the texture doesn't have are left out
+fn surface_name(stem: &OsStr, surface: &TextureIterResult<&Surface>) -> OsString {
+    let mut name = stem.to_owned();
+    if let Some(layer) = surface.layer {
+        name.push(format!("_layer{layer}"));
+    }
+    if let Some(face) = surface.face {
+        name.push(format!("_{}", face_name(face)));
+    }
+    if let Some(mip) = surface.mip {
+        name.push(format!("_mip{mip}"));
+    }
+    name
+}
+
+/// One object in the sidecar's `surfaces` array. Missing structures are written as `null`
+fn sidecar_entry(
+    file: &str,
+    surface: &TextureIterResult<&Surface>,
+    width: u32,
+    height: u32,
+) -> String {
+    let or_null = |v: Option<String>| v.unwrap_or_else(|| "null".to_string());
+    format!(
+        "    {{ \"file\": {}, \"layer\": {}, \"face\": {}, \"mip\": {}, \"width\": {width}, \"height\": {height}, \"row_pitch\": {} }}",
+        json_string(file),
+        or_null(surface.layer.map(|l| l.to_string())),
+        or_null(surface.face.map(|f| json_string(face_name(f)))),
+        or_null(surface.mip.map(|m| m.to_string())),
+        width * 4,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use quicktex::container::ContainerHeader;
+    use quicktex::dds::DDSHeader;
+    use quicktex::format::Quality;
+    use quicktex::texture::Texture;
+
+    use super::super::parse_format;
+    use super::*;
+
+    #[test]
+    fn raw_and_png() -> CliResult {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("gradient.dds");
+        let image = image::RgbaImage::from_fn(8, 4, |x, y| {
+            image::Rgba([x as u8 * 30, y as u8 * 60, 0, 255])
+        });
+        let texture = Texture::from_image(
+            &image.clone().into(),
+            parse_format("rgba8")?,
+            true,
+            Quality::Normal,
+        )?;
+        DDSHeader::write_texture(&mut File::create(&input)?, &texture)?;
+
+        let run_with = |args: &[&str]| run(&command().try_get_matches_from(args)?);
+        let out = dir.path().join("out");
+        let input_arg = input.to_str().unwrap();
+        let out_arg = out.to_str().unwrap();
+
+        run_with(&["decompress", input_arg, "-o", out_arg])?;
+        let mip0 = image::open(out.join("gradient_mip0.png"))?.into_rgba8();
+        assert_eq!(mip0, image);
+        assert!(out.join("gradient_mip3.png").exists());
+
+        run_with(&["decompress", input_arg, "-o", out_arg, "--raw"])?;
+        assert_eq!(fs::read(out.join("gradient_mip0.rgba"))?, image.into_raw());
+        assert_eq!(fs::read(out.join("gradient_mip1.rgba"))?.len(), 4 * 2 * 4);
+        let sidecar = fs::read_to_string(out.join("gradient.json"))?;
+        assert!(sidecar.contains(r#""file": "gradient_mip1.rgba", "layer": null, "face": null, "mip": 1, "width": 4, "height": 2, "row_pitch": 16"#));
+        Ok(())
+    }
+
+    #[test]
+    fn long_path() -> CliResult {
+        // longer than Windows' 260 character limit, which std::fs lifts for long paths itself
+        let dir = tempfile::tempdir()?;
+        let nested = dir.path().join(
+            ["a_fairly_long_directory_name"; 10]
+                .iter()
+                .collect::<PathBuf>(),
+        );
+        fs::create_dir_all(&nested)?;
+        let input = nested.join("gradient.dds");
+        assert!(input.as_os_str().len() > 260);
+        let texture = Texture::from_image(
+            &image::RgbaImage::new(4, 4).into(),
+            parse_format("rgba8")?,
+            false,
+            Quality::Normal,
+        )?;
+        DDSHeader::write_texture(&mut File::create(&input)?, &texture)?;
+
+        run(&command().try_get_matches_from([OsStr::new("decompress"), input.as_os_str()])?)?;
+        assert!(nested.join("gradient.png").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_name() -> CliResult {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join(OsStr::from_bytes(b"caf\xe9.dds"));
+        let image = image::RgbaImage::new(4, 4);
+        let texture = Texture::from_image(
+            &image.into(),
+            parse_format("rgba8")?,
+            false,
+            Quality::Normal,
+        )?;
+        DDSHeader::write_texture(&mut File::create(&input)?, &texture)?;
+
+        let args = [
+            OsStr::new("decompress"),
+            input.as_os_str(),
+            OsStr::new("--raw"),
+        ];
+        run(&command().try_get_matches_from(args)?)?;
+        assert!(dir.path().join(OsStr::from_bytes(b"caf\xe9.rgba")).exists());
+        let sidecar = fs::read_to_string(dir.path().join(OsStr::from_bytes(b"caf\xe9.json")))?;
+        assert!(sidecar.contains("\"file\": \"caf\u{FFFD}.rgba\""));
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `quicktex ls`: summarize every texture in a directory as a table, CSV, or JSON,
+//! for auditing texture budgets
+
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+
+use quicktex::budget::AlignmentRules;
+use quicktex::dimensions::Dimensioned;
+use quicktex::registry::{registry, ContainerRegistry};
+use quicktex::shape::TextureShape;
+use quicktex::texture::Texture;
+
+use super::{format_name, json_string, CliResult};
+
+/// Columns that can be sorted by
+const SORT_KEYS: [&str; 5] = ["name", "format", "dims", "size", "vram"];
+
+pub fn command() -> Command {
+    Command::new("ls")
+        .about("List the textures in a directory, with their format, shape, and size")
+        .arg(
+            Arg::new("paths")
+                .required(true)
+                .num_args(1..)
+                .value_parser(value_parser!(PathBuf))
+                .help("Textures or directories of textures to list, searched recursively"),
+        )
+        .arg(
+            Arg::new("sort")
+                .short('s')
+                .long("sort")
+                .default_value("name")
+                .value_parser(SORT_KEYS)
+                .help("Column to sort by. Sizes sort largest first"),
+        )
+        .arg(
+            Arg::new("reverse")
+                .short('r')
+                .long("reverse")
+                .action(ArgAction::SetTrue)
+                .help("Reverse the sort order"),
+        )
+        .arg(
+            Arg::new("alignment")
+                .short('a')
+                .long("alignment")
+                .default_value("d3d12")
+                .value_parser(["d3d12", "vulkan", "none"])
+                .help("GPU alignment rules used to estimate VRAM usage"),
+        )
+        .arg(
+            Arg::new("csv")
+                .long("csv")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("json")
+                .help("Print comma separated values instead of a table, with sizes in bytes"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Print a JSON array instead of a table, with sizes in bytes"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> CliResult {
+    let registry = registry();
+    let rules = match args.get_one::<String>("alignment").unwrap().as_str() {
+        "vulkan" => AlignmentRules::VULKAN,
+        "none" => AlignmentRules::NONE,
+        _ => AlignmentRules::D3D12,
+    };
+    let mut files = Vec::new();
+    for path in args.get_many::<PathBuf>("paths").unwrap() {
+        match path.is_dir() {
+            true => find_textures(&registry, path, &mut files)?,
+            false => files.push(path.clone()),
+        }
+    }
+    files.sort();
+
+    let mut entries = Vec::new();
+    let mut failed = 0;
+    for path in files {
+        match Entry::read(&registry, &path, &rules) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: {e}", path.display());
+            }
+        }
+    }
+
+    sort(&mut entries, args.get_one::<String>("sort").unwrap());
+    if args.get_flag("reverse") {
+        entries.reverse();
+    }
+
+    let output = match (args.get_flag("csv"), args.get_flag("json")) {
+        (true, _) => csv(&entries),
+        (_, true) => json(&entries),
+        _ => table(&entries),
+    };
+    print!("{output}");
+
+    match failed {
+        0 => Ok(()),
+        n => Err(format!("{n} files could not be read").into()),
+    }
+}
+
+/// Summary of a single texture file
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    name: String,
+    format: String,
+    dims: String,
+    /// Pixel count of the largest surface, used for sorting by dimensions
+    pixels: u64,
+    mips: usize,
+    faces: usize,
+    layers: usize,
+    /// Size of the file on disk
+    size: u64,
+    /// Estimated GPU memory used by the texture's surfaces
+    vram: u64,
+}
+
+impl Entry {
+    fn read(registry: &ContainerRegistry, path: &Path, rules: &AlignmentRules) -> CliResult<Self> {
+        let texture = registry.read_any(&mut BufReader::new(File::open(path)?))?;
+        Self::new(
+            path.display().to_string(),
+            &texture,
+            fs::metadata(path)?.len(),
+            rules,
+        )
+    }
+
+    fn new(name: String, texture: &Texture, size: u64, rules: &AlignmentRules) -> CliResult<Self> {
+        let dimensions = texture.dimensions();
+        Ok(Self {
+            name,
+            format: format_name(texture.format),
+            dims: format!("{dimensions:?}"),
+            pixels: dimensions.product() as u64,
+            mips: texture.mips().unwrap_or(1),
+            faces: texture.faces().map_or(1, |f| f.len()),
+            layers: texture.layers().unwrap_or(1),
+            size,
+            vram: texture.gpu_memory_estimate(rules)? as u64,
+        })
+    }
+}
+
+fn sort(entries: &mut [Entry], key: &str) {
+    match key {
+        "format" => entries.sort_by(|a, b| a.format.cmp(&b.format)),
+        "dims" => entries.sort_by_key(|e| Reverse(e.pixels)),
+        "size" => entries.sort_by_key(|e| Reverse(e.size)),
+        "vram" => entries.sort_by_key(|e| Reverse(e.vram)),
+        _ => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+}
+
+/// Format a size in bytes with a binary unit, e.g. `1.5 MiB`
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn table(entries: &[Entry]) -> String {
+    let header = [
+        "name", "format", "dims", "mips", "faces", "layers", "size", "vram",
+    ]
+    .map(str::to_string);
+    let mut rows = vec![header];
+    rows.extend(entries.iter().map(|e| {
+        [
+            e.name.clone(),
+            e.format.clone(),
+            e.dims.clone(),
+            e.mips.to_string(),
+            e.faces.to_string(),
+            e.layers.to_string(),
+            human_size(e.size),
+            human_size(e.vram),
+        ]
+    }));
+    let total_size = entries.iter().map(|e| e.size).sum();
+    let total_vram = entries.iter().map(|e| e.vram).sum();
+    let mut total = [(); 8].map(|_| String::new());
+    total[0] = format!("{} textures", entries.len());
+    total[6] = human_size(total_size);
+    total[7] = human_size(total_vram);
+    rows.push(total);
+
+    let widths: Vec<usize> = (0..8)
+        .map(|c| rows.iter().map(|r| r[c].chars().count()).max().unwrap_or(0))
+        .collect();
+    let mut output = String::new();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(c, (cell, &width))| match c {
+                // names and formats are left aligned, numbers are right aligned
+                0..=2 => format!("{cell:<width$}"),
+                _ => format!("{cell:>width$}"),
+            })
+            .collect();
+        output += line.join("  ").trim_end();
+        output.push('\n');
+    }
+    output
+}
+
+/// Quote a CSV field if it contains a separator, quote, or newline
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+fn csv(entries: &[Entry]) -> String {
+    let mut output = "name,format,dims,mips,faces,layers,size,vram\n".to_string();
+    for e in entries {
+        output += &format!(
+            "{},{},{},{},{},{},{},{}\n",
+            csv_field(&e.name),
+            csv_field(&e.format),
+            csv_field(&e.dims),
+            e.mips,
+            e.faces,
+            e.layers,
+            e.size,
+            e.vram
+        );
+    }
+    output
+}
+
+fn json(entries: &[Entry]) -> String {
+    let objects: Vec<String> = entries
+        .iter()
+        .map(|e| {
+            format!(
+                "  {{ \"name\": {}, \"format\": {}, \"dims\": {}, \"mips\": {}, \"faces\": {}, \"layers\": {}, \"size\": {}, \"vram\": {} }}",
+                json_string(&e.name),
+                json_string(&e.format),
+                json_string(&e.dims),
+                e.mips,
+                e.faces,
+                e.layers,
+                e.size,
+                e.vram
+            )
+        })
+        .collect();
+    match objects.is_empty() {
+        true => "[]\n".to_string(),
+        false => format!("[\n{}\n]\n", objects.join(",\n")),
+    }
+}
+
+/// Recursively collect every file in `dir` with an extension of a readable container
+fn find_textures(registry: &ContainerRegistry, dir: &Path, files: &mut Vec<PathBuf>) -> CliResult {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_textures(registry, &path, files)?;
+        } else if path
+            .extension()
+            .and_then(|e| registry.by_extension(&e.to_string_lossy()))
+            .is_some_and(|c| c.read.is_some())
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use quicktex::container::ContainerHeader;
+    use quicktex::dds::DDSHeader;
+    use quicktex::format::Quality;
+
+    use super::super::parse_format;
+    use super::*;
+
+    fn texture(size: u32, mips: bool) -> CliResult<Texture> {
+        let image = image::RgbaImage::new(size, size).into();
+        Ok(Texture::from_image(
+            &image,
+            parse_format("bgra8")?,
+            mips,
+            Quality::Normal,
+        )?)
+    }
+
+    #[test]
+    fn list_directory() -> CliResult {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("sub"))?;
+        DDSHeader::write_texture(
+            &mut File::create(dir.path().join("big.dds"))?,
+            &texture(16, true)?,
+        )?;
+        DDSHeader::write_texture(
+            &mut File::create(dir.path().join("sub/small.dds"))?,
+            &texture(4, false)?,
+        )?;
+        fs::write(dir.path().join("notes.txt"), "not a texture")?;
+
+        let registry = registry();
+        let mut files = Vec::new();
+        find_textures(&registry, dir.path(), &mut files)?;
+        assert_eq!(files.len(), 2, "only texture extensions are listed");
+        let mut entries = files
+            .iter()
+            .map(|f| Entry::read(&registry, f, &AlignmentRules::NONE))
+            .collect::<CliResult<Vec<_>>>()?;
+
+        sort(&mut entries, "vram");
+        assert!(entries[0].name.ends_with("big.dds"));
+        assert_eq!(entries[0].dims, "16x16");
+        assert_eq!(entries[0].mips, 5);
+        assert_eq!(entries[0].vram, 4 * (256 + 64 + 16 + 4 + 1));
+        assert_eq!(entries[1].mips, 1);
+
+        let csv = csv(&entries);
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().nth(2).unwrap().ends_with(",4x4,1,1,1,192,64"));
+        assert!(json(&entries).contains("\"vram\": 1364"));
+        assert!(table(&entries).contains("2 textures"));
+        Ok(())
+    }
+
+    #[test]
+    fn names() -> CliResult {
+        for name in [
+            "bc1-srgb",
+            "bc4-snorm",
+            "bc5",
+            "bgra8",
+            "rgb8-srgb",
+            "l8",
+            "rgba16f",
+            "etc2-rgba8-srgb",
+            "eac-rg11-snorm",
+        ] {
+            assert_eq!(format_name(parse_format(name)?), name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(human_size(1000), "1000 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 << 30), "3.0 GiB");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+    }
+}
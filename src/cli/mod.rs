@@ -0,0 +1,183 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use clap::{value_parser, Arg};
+
+use quicktex::format::{AlphaFormat, ChannelType, ColorFormat, Format};
+use quicktex::registry::read_any_texture;
+use quicktex::shape::CubeFace;
+use quicktex::Texture;
+use strum::VariantArray;
+
+pub mod compare;
+pub mod config;
+pub mod convert;
+pub mod cook;
+pub mod decompress;
+pub mod ls;
+pub mod montage;
+pub mod show;
+
+pub type CliResult<T = ()> = Result<T, Box<dyn Error>>;
+
+/// Read a texture from a file of any registered container. With the `miette` feature, errors reading the texture
+/// are wrapped in a [`FileReport`](quicktex::diagnostics::FileReport) pointing at the offending bytes
+pub fn read_input(path: &Path) -> CliResult<Texture> {
+    let result = read_any_texture(&mut BufReader::new(File::open(path)?));
+    #[cfg(feature = "miette")]
+    let result = result.map_err(|e| match std::fs::read(path) {
+        Ok(bytes) => {
+            quicktex::diagnostics::FileReport::new(e, path.display().to_string(), &bytes).into()
+        }
+        Err(_) => Box::<dyn Error>::from(e),
+    });
+    Ok(result?)
+}
+
+/// Names accepted by [`parse_format`], for use in help text
+pub const FORMAT_NAMES: &str =
+    "bc1, bc2, bc3, bc4, bc5, bc7, etc2-rgb8, etc2-rgba8, eac-r11, eac-rg11, \
+    rgba8, bgra8, rgb8, bgr8, l8, rgba16, rgba16f, rgba32f. \
+    Color formats accept an '-srgb' suffix, and bc4/bc5/eac accept a '-snorm' suffix";
+
+/// Parse a format name such as `bc1-srgb` or `bgra8`. See [`FORMAT_NAMES`]
+pub fn parse_format(name: &str) -> Result<Format, String> {
+    let name = name.to_ascii_lowercase();
+    let (base, srgb) = match name.strip_suffix("-srgb") {
+        Some(base) => (base, true),
+        None => (name.as_str(), false),
+    };
+    let (base, signed) = match base.strip_suffix("-snorm") {
+        Some(base) if !srgb => (base, true),
+        _ => (base, false),
+    };
+
+    let rgb = |r_mask, g_mask, b_mask| ColorFormat::RGB {
+        r_mask,
+        g_mask,
+        b_mask,
+        srgb,
+    };
+    let alpha = AlphaFormat::Straight {
+        alpha_mask: 0xFF000000,
+    };
+    let uncompressed = |pitch, color_format, alpha_format| Format::Uncompressed {
+        pitch,
+        color_format,
+        alpha_format,
+    };
+    let channels = |channel_type| Format::Channels {
+        channel_type,
+        channels: 4,
+    };
+
+    let format = match (base, srgb, signed) {
+        ("bc1", _, false) => Format::BC1 { srgb },
+        ("bc2", _, false) => Format::BC2 { srgb },
+        ("bc3", _, false) => Format::BC3 { srgb },
+        ("bc4", false, _) => Format::BC4 { signed },
+        ("bc5", false, _) => Format::BC5 { signed },
+        ("bc7", _, false) => Format::BC7 { srgb },
+        ("etc2-rgb8", _, false) => Format::ETC2RGB8 { srgb },
+        ("etc2-rgba8", _, false) => Format::ETC2RGBA8 { srgb },
+        ("eac-r11", false, _) => Format::EACR11 { signed },
+        ("eac-rg11", false, _) => Format::EACRG11 { signed },
+        ("rgba8", _, false) => uncompressed(4, rgb(0xFF, 0xFF00, 0xFF0000), alpha),
+        ("bgra8", _, false) => uncompressed(4, rgb(0xFF0000, 0xFF00, 0xFF), alpha),
+        ("rgb8", _, false) => uncompressed(3, rgb(0xFF, 0xFF00, 0xFF0000), AlphaFormat::Opaque),
+        ("bgr8", _, false) => uncompressed(3, rgb(0xFF0000, 0xFF00, 0xFF), AlphaFormat::Opaque),
+        ("l8", false, false) => {
+            uncompressed(1, ColorFormat::L { l_mask: 0xFF }, AlphaFormat::Opaque)
+        }
+        ("rgba16", false, false) => channels(ChannelType::UNorm16),
+        ("rgba16f", false, false) => channels(ChannelType::Float16),
+        ("rgba32f", false, false) => channels(ChannelType::Float32),
+        _ => {
+            return Err(format!(
+                "unknown format '{name}'. Expected one of {FORMAT_NAMES}"
+            ))
+        }
+    };
+    Ok(format)
+}
+
+/// Name a format the way [`parse_format`] accepts it, such as `bc1-srgb`.
+/// Uncompressed formats with no name fall back to their debug representation
+pub fn format_name(format: Format) -> String {
+    let suffix = |srgb: bool, signed: bool| match (srgb, signed) {
+        (true, _) => "-srgb",
+        (_, true) => "-snorm",
+        _ => "",
+    };
+    match format {
+        Format::BC1 { srgb } => format!("bc1{}", suffix(srgb, false)),
+        Format::BC2 { srgb } => format!("bc2{}", suffix(srgb, false)),
+        Format::BC3 { srgb } => format!("bc3{}", suffix(srgb, false)),
+        Format::BC4 { signed } => format!("bc4{}", suffix(false, signed)),
+        Format::BC5 { signed } => format!("bc5{}", suffix(false, signed)),
+        Format::BC7 { srgb } => format!("bc7{}", suffix(srgb, false)),
+        Format::ETC2RGB8 { srgb } => format!("etc2-rgb8{}", suffix(srgb, false)),
+        Format::ETC2RGBA8 { srgb } => format!("etc2-rgba8{}", suffix(srgb, false)),
+        Format::EACR11 { signed } => format!("eac-r11{}", suffix(false, signed)),
+        Format::EACRG11 { signed } => format!("eac-rg11{}", suffix(false, signed)),
+        _ => [
+            "rgba8", "bgra8", "rgb8", "bgr8", "l8", "rgba16", "rgba16f", "rgba32f",
+        ]
+        .into_iter()
+        .flat_map(|n| [n.to_string(), format!("{n}-srgb")])
+        .find(|n| parse_format(n) == Ok(format))
+        .unwrap_or_else(|| format!("{format:?}")),
+    }
+}
+
+/// Short name of a cubemap face, such as `+x`, for use in file names and output
+pub fn face_name(face: CubeFace) -> &'static str {
+    match face {
+        CubeFace::PositiveX => "+x",
+        CubeFace::NegativeX => "-x",
+        CubeFace::PositiveY => "+y",
+        CubeFace::NegativeY => "-y",
+        CubeFace::PositiveZ => "+z",
+        CubeFace::NegativeZ => "-z",
+    }
+}
+
+/// Parse a cubemap face name such as `+x` or `-Z`. See [`face_name`]
+pub fn parse_face(name: &str) -> Result<CubeFace, String> {
+    CubeFace::VARIANTS
+        .iter()
+        .copied()
+        .find(|&f| face_name(f).eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown face '{name}'. Expected one of +x, -x, +y, -y, +z, -z"))
+}
+
+/// Quote and escape a string as a JSON string literal
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The `--threads` option shared by every command that encodes or decodes
+pub fn threads_arg() -> Arg {
+    Arg::new("threads")
+        .short('j')
+        .long("threads")
+        .value_parser(value_parser!(usize))
+        .help("Number of threads to encode and decode with. Defaults to one per CPU core")
+}
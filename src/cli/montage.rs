@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `quicktex montage`: tile every surface of a texture into a labeled contact sheet image
+
+use std::path::PathBuf;
+
+use clap::{value_parser, Arg, ArgMatches, Command};
+
+use super::{read_input, CliResult};
+
+pub fn command() -> Command {
+    Command::new("montage")
+        .about("Tile every layer, face, and mip of a texture into a single labeled image")
+        .arg(
+            Arg::new("input")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Texture to tile, in any container quicktex can read"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_parser(value_parser!(PathBuf))
+                .help("Image to write, in any format the image crate can write from its extension. Defaults to <input>.montage.png"),
+        )
+        .arg(
+            Arg::new("columns")
+                .short('c')
+                .long("columns")
+                .default_value("6")
+                .value_parser(value_parser!(u32).range(1..))
+                .help("Number of surfaces in each row of the image"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> CliResult {
+    let input = args.get_one::<PathBuf>("input").unwrap();
+    let output = match args.get_one::<PathBuf>("output") {
+        Some(output) => output.clone(),
+        None => input.with_extension("montage.png"),
+    };
+    let columns = *args.get_one::<u32>("columns").unwrap() as usize;
+
+    let texture = read_input(input)?;
+    texture.montage(columns)?.save(&output)?;
+    println!("{}", output.display());
+    Ok(())
+}
@@ -0,0 +1,183 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `quicktex show`: preview a surface of a texture in the terminal,
+//! drawing two pixels per character with truecolor half-block characters
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use clap::{value_parser, Arg, ArgMatches, Command};
+use image::imageops::FilterType;
+use image::{Rgba, RgbaImage};
+
+use quicktex::dimensions::Dimensioned;
+use quicktex::shape::CubeFace;
+
+use super::{face_name, parse_face, read_input, CliResult};
+
+/// Width of the preview if it isn't given and `COLUMNS` isn't set
+const DEFAULT_COLUMNS: u32 = 80;
+
+pub fn command() -> Command {
+    Command::new("show")
+        .about("Preview a surface of a texture in the terminal")
+        .arg(
+            Arg::new("input")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("Texture to preview, in any container quicktex can read"),
+        )
+        .arg(
+            Arg::new("mip")
+                .short('m')
+                .long("mip")
+                .default_value("0")
+                .value_parser(value_parser!(usize))
+                .help("Mip level to show"),
+        )
+        .arg(
+            Arg::new("face")
+                .long("face")
+                .value_parser(parse_face)
+                .allow_hyphen_values(true)
+                .help("Cubemap face to show: +x, -x, +y, -y, +z, or -z. Defaults to +x"),
+        )
+        .arg(
+            Arg::new("layer")
+                .short('l')
+                .long("layer")
+                .default_value("0")
+                .value_parser(value_parser!(usize))
+                .help("Array layer to show"),
+        )
+        .arg(
+            Arg::new("width")
+                .short('w')
+                .long("width")
+                .value_parser(value_parser!(u32).range(1..))
+                .help("Maximum width in characters. Defaults to the terminal width"),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> CliResult {
+    let input = args.get_one::<PathBuf>("input").unwrap();
+    let mip = *args.get_one::<usize>("mip").unwrap();
+    let layer = *args.get_one::<usize>("layer").unwrap();
+    let face = args.get_one::<CubeFace>("face").copied();
+    let columns = args.get_one::<u32>("width").copied().unwrap_or_else(|| {
+        std::env::var("COLUMNS")
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(DEFAULT_COLUMNS)
+    });
+
+    let texture = read_input(input)?;
+    let surface = texture
+        .slice()
+        .iter()
+        .find(|s| {
+            let face_matches = match face {
+                Some(face) => s.face == Some(face),
+                None => s.face.unwrap_or_default() == CubeFace::default(),
+            };
+            s.mip.unwrap_or(0) == mip && s.layer.unwrap_or(0) == layer && face_matches
+        })
+        .ok_or_else(|| {
+            let face = face.map_or(String::new(), |f| format!(", face {}", face_name(f)));
+            format!(
+                "{} has no surface at mip {mip}, layer {layer}{face}",
+                input.display()
+            )
+        })?;
+
+    let image = texture
+        .format
+        .decoder()?
+        .decode_buffer(surface.surface.buffer.clone(), surface.surface.dimensions())?
+        .into_rgba8();
+    println!(
+        "{}: {:?} {:?}",
+        input.display(),
+        texture.format,
+        surface.surface.dimensions()
+    );
+    print!("{}", render(&image, columns));
+    Ok(())
+}
+
+/// Composite a pixel over a checkerboard, so transparency is visible in the preview
+fn flatten(pixel: Rgba<u8>, x: u32, y: u32) -> [u8; 3] {
+    let background = match (x / 2 + y / 2) % 2 {
+        0 => 0x66u16,
+        _ => 0x99u16,
+    };
+    let [r, g, b, a] = pixel.0.map(u16::from);
+    [r, g, b].map(|c| ((c * a + background * (255 - a) + 127) / 255) as u8)
+}
+
+/// Render an image as lines of half-block characters, no more than `columns` wide.
+/// Each character shows two pixels, the top as the foreground color and the bottom as the background.
+/// Images wider than `columns` are downscaled, keeping their aspect ratio, but smaller images are never enlarged
+fn render(image: &RgbaImage, columns: u32) -> String {
+    let image = match image.width() > columns {
+        true => {
+            let height = (image.height() as u64 * columns as u64 / image.width() as u64).max(1);
+            image::imageops::resize(image, columns, height as u32, FilterType::Triangle)
+        }
+        false => image.clone(),
+    };
+
+    let mut output = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let [r, g, b] = flatten(*image.get_pixel(x, y), x, y);
+            write!(output, "\x1b[38;2;{r};{g};{b}m").unwrap();
+            // an odd number of rows leaves the bottom half of the last line empty
+            match y + 1 < image.height() {
+                true => {
+                    let [r, g, b] = flatten(*image.get_pixel(x, y + 1), x, y + 1);
+                    write!(output, "\x1b[48;2;{r};{g};{b}m").unwrap();
+                }
+                false => output.push_str("\x1b[49m"),
+            }
+            output.push('▀');
+        }
+        output.push_str("\x1b[0m\n");
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_blocks() {
+        let image = RgbaImage::from_fn(2, 3, |x, y| Rgba([x as u8 * 255, y as u8 * 100, 0, 255]));
+        let lines: Vec<_> = render(&image, 80).lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 2, "two rows of pixels per line");
+        assert_eq!(
+            lines[0],
+            "\x1b[38;2;0;0;0m\x1b[48;2;0;100;0m▀\x1b[38;2;255;0;0m\x1b[48;2;255;100;0m▀\x1b[0m"
+        );
+        assert_eq!(
+            lines[1],
+            "\x1b[38;2;0;200;0m\x1b[49m▀\x1b[38;2;255;200;0m\x1b[49m▀\x1b[0m"
+        );
+
+        // wide images are downscaled to fit
+        let wide = RgbaImage::from_pixel(200, 100, Rgba([10, 20, 30, 255]));
+        let rendered = render(&wide, 50);
+        assert_eq!(rendered.lines().count(), 13);
+        assert!(rendered.lines().all(|l| l.matches('▀').count() == 50));
+    }
+
+    #[test]
+    fn transparency() {
+        assert_eq!(flatten(Rgba([255, 0, 0, 255]), 0, 0), [255, 0, 0]);
+        assert_eq!(flatten(Rgba([255, 0, 0, 0]), 0, 0), [0x66; 3]);
+        assert_eq!(flatten(Rgba([255, 0, 0, 0]), 2, 0), [0x99; 3]);
+    }
+}
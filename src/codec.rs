@@ -0,0 +1,647 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Generic surface codecs for block formats.
+//!
+//! Block formats only implement [`CodecBlock`](crate::blocktexture::CodecBlock) for a single block, and [`decode_surface`](crate::codec::decode_surface) and
+//! [`encode_surface`](crate::codec::encode_surface) take care of splitting a surface into rows of blocks and processing them in parallel.
+//!
+//! # Edge blocks
+//!
+//! Surfaces whose dimensions are not a multiple of the block size still store whole blocks
+//! on their right and bottom edges. These are handled the same way for every format:
+//!
+//! * When encoding, pixels past the edge of the image are filled by clamping their coordinates to the image,
+//!   repeating its last column and row. This keeps padding from pulling endpoints away from the real pixels,
+//!   unlike padding with black or transparent pixels.
+//! * When decoding, pixels past the edge of the surface are discarded.
+//!
+//! # 3D surfaces
+//!
+//! Each depth slice of a 3D surface is stored as an independent 2D surface, one after another,
+//! padded to whole blocks on each slice but not in depth. A 3D surface decodes to a single image
+//! with its slices stacked vertically, which has the same layout as an uncompressed 3D surface.
+//! [`decode_slices`](crate::codec::decode_slices) and [`encode_slices`](crate::codec::encode_slices) work with a separate image per slice instead.
+
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use image::{DynamicImage, GrayImage, RgbaImage};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use strum::VariantArray;
+
+use crate::blocktexture::{Block, CodecBlock, Pixels};
+use crate::color::Color;
+use crate::dimensions::{DimensionError, Dimensions};
+use crate::error::{TextureError, TextureResult};
+use crate::format::{Decoder, Encoder, Interpolation, Quality, RoiQuality};
+use crate::texture::Surface;
+
+/// Number of bytes needed for a row of blocks covering `width` pixels
+fn block_row_size<B: Block>(width: u32) -> usize {
+    (width as usize).div_ceil(B::WIDTH) * B::SIZE
+}
+
+/// Number of bytes needed for a single 2D slice of `dimensions`
+fn slice_size<B: Block>(dimensions: Dimensions) -> usize {
+    block_row_size::<B>(dimensions.width()) * (dimensions.height() as usize).div_ceil(B::HEIGHT)
+}
+
+fn check_size<B: Block>(surface: &Surface) -> TextureResult<()> {
+    let dimensions = surface.dimensions;
+    let expected = block_row_size::<B>(dimensions.width())
+        .checked_mul((dimensions.height() as usize).div_ceil(B::HEIGHT))
+        .and_then(|s| s.checked_mul(dimensions.depth() as usize))
+        .ok_or(DimensionError::Overflow(dimensions))?;
+    match surface.buffer.len() == expected {
+        true => Ok(()),
+        false => Err(TextureError::Other(format!(
+            "Buffer has {} bytes, but {expected} are required",
+            surface.buffer.len()
+        ))),
+    }
+}
+
+/// The height of an image holding every depth slice of `dimensions` stacked vertically
+pub(crate) fn stacked_height(dimensions: Dimensions) -> TextureResult<u32> {
+    Ok(dimensions
+        .height()
+        .checked_mul(dimensions.depth())
+        .ok_or(DimensionError::Overflow(dimensions))?)
+}
+
+/// Decode a single 2D slice of blocks, which must already be the right size
+fn decode_slice<B: CodecBlock>(
+    buffer: &[u8],
+    width: u32,
+    height: u32,
+    interpolation: Interpolation,
+) -> RgbaImage {
+    let row_size = block_row_size::<B>(width);
+    let pixel_row_size = width as usize * 4;
+    let mut pixels = vec![0u8; pixel_row_size * height as usize];
+    pixels
+        .par_chunks_mut(pixel_row_size * B::HEIGHT)
+        .zip(buffer.par_chunks(row_size))
+        .for_each(|(dst, src)| {
+            for (bx, bytes) in src.chunks_exact(B::SIZE).enumerate() {
+                let Ok(bytes) = B::Bytes::try_from(bytes) else {
+                    unreachable!("chunks are exactly one block long")
+                };
+                let block = B::from_bytes(&bytes).decode_with(interpolation);
+                for (y, dst_row) in dst.chunks_exact_mut(pixel_row_size).enumerate() {
+                    for x in 0..B::WIDTH.min(width as usize - bx * B::WIDTH) {
+                        let offset = (bx * B::WIDTH + x) * 4;
+                        for (d, s) in dst_row[offset..offset + 4]
+                            .iter_mut()
+                            .zip(block[(y, x)].elements())
+                        {
+                            *d = *s;
+                        }
+                    }
+                }
+            }
+        });
+
+    RgbaImage::from_raw(width, height, pixels).expect("buffer is sized for the image")
+}
+
+/// Decode every depth slice of a surface into one image with the slices stacked vertically.
+/// The buffer must already be the right size
+fn decode_stacked<B: CodecBlock>(
+    buffer: &[u8],
+    dimensions: Dimensions,
+    interpolation: Interpolation,
+) -> TextureResult<RgbaImage> {
+    let (width, height) = (dimensions.width(), dimensions.height());
+    let image_height = stacked_height(dimensions)?;
+    if dimensions.depth() == 1 {
+        return Ok(decode_slice::<B>(buffer, width, height, interpolation));
+    }
+    let pixels = buffer
+        .chunks_exact(slice_size::<B>(dimensions))
+        .flat_map(|slice| decode_slice::<B>(slice, width, height, interpolation).into_raw())
+        .collect();
+    Ok(RgbaImage::from_raw(width, image_height, pixels).expect("buffer is sized for the image"))
+}
+
+/// Decode a surface made of `B` blocks into an RGBA image. Block rows are decoded in parallel.
+/// 1D surfaces are a single row of blocks, and decode to an image 1 pixel tall.
+/// 3D surfaces decode to their slices stacked vertically, as described in the [module documentation](self#3d-surfaces).
+///
+/// Blocks on the right and bottom edges that extend past the surface are cropped,
+/// as described in the [module documentation](self#edge-blocks).
+/// Returns an error if the surface's buffer is the wrong size for its dimensions
+pub fn decode_surface<B: CodecBlock>(surface: &Surface) -> TextureResult<RgbaImage> {
+    decode_surface_with::<B>(surface, Interpolation::default())
+}
+
+/// Decode a surface made of `B` blocks into an RGBA image with the given interpolation arithmetic.
+/// See [`decode_surface`]
+pub fn decode_surface_with<B: CodecBlock>(
+    surface: &Surface,
+    interpolation: Interpolation,
+) -> TextureResult<RgbaImage> {
+    check_size::<B>(surface)?;
+    decode_stacked::<B>(&surface.buffer, surface.dimensions, interpolation)
+}
+
+/// Decode a surface made of `B` blocks into one RGBA image per depth slice.
+/// Each slice of a 3D surface is stored as an independent 2D surface, one after another.
+/// 1D and 2D surfaces decode to a single image, the same as [`decode_surface`]
+pub fn decode_slices<B: CodecBlock>(surface: &Surface) -> TextureResult<Vec<RgbaImage>> {
+    decode_slices_with::<B>(surface, Interpolation::default())
+}
+
+/// Decode a surface made of `B` blocks into one RGBA image per depth slice with the given interpolation arithmetic.
+/// See [`decode_slices`]
+pub fn decode_slices_with<B: CodecBlock>(
+    surface: &Surface,
+    interpolation: Interpolation,
+) -> TextureResult<Vec<RgbaImage>> {
+    check_size::<B>(surface)?;
+    let dimensions = surface.dimensions;
+    let (width, height) = (dimensions.width(), dimensions.height());
+    Ok(surface
+        .buffer
+        .chunks_exact(slice_size::<B>(dimensions))
+        .map(|slice| decode_slice::<B>(slice, width, height, interpolation))
+        .collect())
+}
+
+/// Gather the pixels of the block at (`bx`, `by`), clamping coordinates past the edges of the image
+fn block_pixels<B: Block>(image: &RgbaImage, bx: usize, by: usize) -> Pixels {
+    let (width, height) = image.dimensions();
+    (0..B::HEIGHT)
+        .flat_map(|y| (0..B::WIDTH).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let x = ((bx * B::WIDTH + x) as u32).min(width - 1);
+            let y = ((by * B::HEIGHT + y) as u32).min(height - 1);
+            Color::vec(image.get_pixel(x, y).0)
+        })
+        .collect()
+}
+
+/// Encode a single image into a 2D slice of blocks
+fn encode_slice<B: CodecBlock>(image: &RgbaImage, options: &B::Options) -> Vec<u8> {
+    encode_slice_with::<B>(image, |_, _| options.clone())
+}
+
+/// Encode a single image into a 2D slice of blocks, with options chosen for each block by `options(bx, by)`
+fn encode_slice_with<B: CodecBlock>(
+    image: &RgbaImage,
+    options: impl Fn(usize, usize) -> B::Options + Sync,
+) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let row_size = block_row_size::<B>(width);
+    let mut buffer = vec![0u8; row_size * (height as usize).div_ceil(B::HEIGHT)];
+
+    buffer
+        .par_chunks_mut(row_size)
+        .enumerate()
+        .for_each(|(by, dst)| {
+            for (bx, bytes) in dst.chunks_exact_mut(B::SIZE).enumerate() {
+                let pixels = block_pixels::<B>(image, bx, by);
+                let options = options(bx, by);
+                bytes.copy_from_slice(B::encode(&pixels, &options).to_bytes().as_ref());
+            }
+        });
+    buffer
+}
+
+/// The highest weight covering the block at (`bx`, `by`) of an image of the given size,
+/// with `weights` stretched to cover the whole image
+fn block_weight<B: Block>(
+    weights: &GrayImage,
+    width: u32,
+    height: u32,
+    bx: usize,
+    by: usize,
+) -> u8 {
+    let (ww, wh) = weights.dimensions();
+    let range = |b: usize, size: u32, block: usize, wsize: u32| {
+        let start = (b * block) as u64;
+        let end = ((b + 1) * block).min(size as usize) as u64;
+        let first = (start * wsize as u64 / size as u64) as u32;
+        let last = (end * wsize as u64).div_ceil(size as u64) as u32;
+        first..last.max(first + 1).min(wsize)
+    };
+    let xs = range(bx, width, B::WIDTH, ww);
+    range(by, height, B::HEIGHT, wh)
+        .flat_map(|y| xs.clone().map(move |x| (x, y)))
+        .map(|(x, y)| weights.get_pixel(x, y).0[0])
+        .max()
+        .unwrap_or(0)
+}
+
+/// Encode an RGBA image into a 2D surface made of `B` blocks. Block rows are encoded in parallel.
+///
+/// Blocks on the right and bottom edges that extend past the image are padded by repeating
+/// the last column and row of the image, as described in the [module documentation](self#edge-blocks)
+pub fn encode_surface<B: CodecBlock>(image: &RgbaImage, options: &B::Options) -> Surface {
+    let dimensions =
+        Dimensions::try_from([image.width(), image.height()]).expect("images are never empty");
+    Surface::new(dimensions, encode_slice::<B>(image, options))
+}
+
+/// Encode an RGBA image into a 2D surface made of `B` blocks, choosing the quality tier of each block
+/// from the highest of the `weights` covering it. The weights are stretched to cover the whole image,
+/// so the same mask can be used for every mip of a texture. Returns an error if `weights` is empty
+pub fn encode_surface_weighted<B: CodecBlock>(
+    image: &RgbaImage,
+    options: &B::Options,
+    weights: &GrayImage,
+    roi: &RoiQuality,
+) -> TextureResult<Surface> {
+    if weights.width() == 0 || weights.height() == 0 {
+        return Err(TextureError::Other("Weight mask is empty".to_string()));
+    }
+    let buffer = encode_slice_weighted::<B>(image, options, weights, roi);
+    Ok(Surface::new(
+        Dimensions::try_from([image.width(), image.height()])?,
+        buffer,
+    ))
+}
+
+/// Encode a single image into a 2D slice of blocks, with quality tiers chosen from `weights`,
+/// which must not be empty
+fn encode_slice_weighted<B: CodecBlock>(
+    image: &RgbaImage,
+    options: &B::Options,
+    weights: &GrayImage,
+    roi: &RoiQuality,
+) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let qualities: Vec<_> = Quality::VARIANTS
+        .iter()
+        .map(|&q| B::with_quality(options, q))
+        .collect();
+    encode_slice_with::<B>(image, |bx, by| {
+        let quality = roi.for_weight(block_weight::<B>(weights, width, height, bx, by));
+        qualities[quality as usize].clone()
+    })
+}
+
+/// Encode a stack of equally-sized RGBA images into a 3D surface made of `B` blocks,
+/// with each image stored as an independent 2D slice. A single image produces a 2D surface.
+/// Returns an error if `slices` is empty or the images are not all the same size
+pub fn encode_slices<B: CodecBlock>(
+    slices: &[RgbaImage],
+    options: &B::Options,
+) -> TextureResult<Surface> {
+    let Some(first) = slices.first() else {
+        return Err(TextureError::Other("No slices to encode".to_string()));
+    };
+    let (width, height) = first.dimensions();
+    if slices.iter().any(|s| s.dimensions() != (width, height)) {
+        return Err(TextureError::Other(
+            "Slices of a 3D surface must all be the same size".to_string(),
+        ));
+    }
+    let dimensions = match slices.len() {
+        1 => Dimensions::try_from([width, height])?,
+        depth => Dimensions::try_from([width, height, depth as u32])?,
+    };
+    let buffer = slices
+        .iter()
+        .flat_map(|s| encode_slice::<B>(s, options))
+        .collect::<Vec<_>>();
+    Ok(Surface::new(dimensions, buffer))
+}
+
+/// A [`Decoder`] and [`Encoder`] for any [`CodecBlock`], using [`decode_surface_with`] and [`encode_surface`].
+/// Blocks are processed in rayon's global thread pool, unless the codec has its own from [`Self::with_threads`]
+pub struct BlockCodec<B: CodecBlock> {
+    options: B::Options,
+    interpolation: Interpolation,
+    pool: Option<ThreadPool>,
+    block: PhantomData<B>,
+}
+
+impl<B: CodecBlock> BlockCodec<B> {
+    /// Create a codec that encodes blocks using `options`
+    pub fn new(options: B::Options) -> Self {
+        Self {
+            options,
+            interpolation: Interpolation::default(),
+            pool: None,
+            block: PhantomData,
+        }
+    }
+
+    /// Decode blocks with the given interpolation arithmetic
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// Process blocks in a thread pool of this many threads owned by the codec, instead of rayon's global pool.
+    /// [`None`] uses the global pool. Returns an error if the thread pool can't be created
+    pub fn with_threads(mut self, threads: Option<usize>) -> TextureResult<Self> {
+        self.pool = threads
+            .map(|n| ThreadPoolBuilder::new().num_threads(n).build())
+            .transpose()
+            .map_err(|e| TextureError::Other(format!("Can't create a thread pool: {e}")))?;
+        Ok(self)
+    }
+
+    /// Run `f` in the codec's thread pool, if it has one
+    fn run<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+        match &self.pool {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+}
+
+impl<B: CodecBlock> Default for BlockCodec<B> {
+    fn default() -> Self {
+        Self::new(B::Options::default())
+    }
+}
+
+impl<B: CodecBlock> Decoder for BlockCodec<B> {
+    fn decode_buffer(
+        &self,
+        buffer: Rc<[u8]>,
+        dimensions: Dimensions,
+    ) -> TextureResult<DynamicImage> {
+        let surface = Surface::new(dimensions, buffer);
+        check_size::<B>(&surface)?;
+        let (buffer, interpolation): (&[u8], _) = (&surface.buffer, self.interpolation);
+        let image = self.run(|| decode_stacked::<B>(buffer, dimensions, interpolation))?;
+        Ok(DynamicImage::ImageRgba8(image))
+    }
+}
+
+impl<B: CodecBlock> Encoder for BlockCodec<B> {
+    fn encode_buffer(&self, image: DynamicImage, quality: Quality) -> Rc<[u8]> {
+        let options = B::with_quality(&self.options, quality);
+        let image = image.into_rgba8();
+        self.run(|| encode_slice::<B>(&image, &options)).into()
+    }
+
+    fn encode_buffer_weighted(
+        &self,
+        image: DynamicImage,
+        weights: &GrayImage,
+        roi: &RoiQuality,
+    ) -> Rc<[u8]> {
+        // an empty mask has no important regions
+        if weights.width() == 0 || weights.height() == 0 {
+            return self.encode_buffer(image, roi.background);
+        }
+        let (image, options) = (image.into_rgba8(), &self.options);
+        self.run(|| encode_slice_weighted::<B>(&image, options, weights, roi))
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use itertools::Itertools;
+
+    use crate::dimensions::Dimensioned;
+    use crate::format::{EncodeOptions, Format};
+    use crate::s3tc::bc1::{BC1Block, BC1Options};
+    use crate::s3tc::bc3::BC3Block;
+    use crate::s3tc::bc4::{BC4Block, BC4Options};
+    use crate::shape::{TextureShape, TextureShapeNode};
+    use crate::texture::Texture;
+
+    use super::*;
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        // a checkerboard in red, 3 blocks wide and 2 tall.
+        // Each block only has 2 distinct values, so BC4 is lossless here
+        let image = RgbaImage::from_fn(12, 8, |x, y| {
+            image::Rgba([((x + y) % 2 * 100 + x / 4 * 50) as u8, 0, 0, 255])
+        });
+        let surface = encode_surface::<BC4Block>(&image, &BC4Options::default());
+        assert_eq!(surface.buffer.len(), 3 * 2 * 8);
+
+        let decoded = decode_surface::<BC4Block>(&surface)?;
+        assert_eq!(decoded, image);
+        Ok(())
+    }
+
+    /// Check the edge policy for `B` on an image of the given size
+    fn edges<B: CodecBlock>(width: u32, height: u32) -> Result<()> {
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x * 50) as u8, (y * 30) as u8, 200, 255 - (x + y) as u8])
+        });
+        let surface = encode_surface::<B>(&image, &B::Options::default());
+        let blocks = width.div_ceil(4) * height.div_ceil(4);
+        assert_eq!(surface.buffer.len(), blocks as usize * B::SIZE);
+
+        // encoding clamps, so it matches encoding the image padded out to whole blocks by hand
+        let padded = RgbaImage::from_fn(
+            width.next_multiple_of(4),
+            height.next_multiple_of(4),
+            |x, y| *image.get_pixel(x.min(width - 1), y.min(height - 1)),
+        );
+        let padded_surface = encode_surface::<B>(&padded, &B::Options::default());
+        assert_eq!(surface.buffer, padded_surface.buffer);
+
+        // decoding crops, so it matches the top left of the padded surface decoded
+        let decoded = decode_surface::<B>(&surface)?;
+        let padded_decoded = decode_surface::<B>(&padded_surface)?;
+        assert_eq!(decoded.dimensions(), (width, height));
+        assert_eq!(
+            decoded,
+            image::imageops::crop_imm(&padded_decoded, 0, 0, width, height).to_image()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn edges_bc4() -> Result<()> {
+        edges::<BC4Block>(1, 1)?;
+        edges::<BC4Block>(2, 2)?;
+        edges::<BC4Block>(5, 7)?;
+
+        // a single pixel is replicated across the whole block, so it survives exactly
+        let image = RgbaImage::from_pixel(1, 1, image::Rgba([123, 0, 0, 255]));
+        let surface = encode_surface::<BC4Block>(&image, &BC4Options::default());
+        assert_eq!(decode_surface::<BC4Block>(&surface)?, image);
+        Ok(())
+    }
+
+    #[test]
+    fn edges_bc1() -> Result<()> {
+        edges::<BC1Block>(1, 1)?;
+        edges::<BC1Block>(6, 3)?;
+
+        // a transparent pixel uses 3-color mode, and decodes as transparent black
+        let image = RgbaImage::from_fn(4, 4, |x, _| match x {
+            0 => image::Rgba([10, 20, 30, 0]),
+            _ => image::Rgba([255, 255, 255, 255]),
+        });
+        let decoded =
+            decode_surface::<BC1Block>(&encode_surface::<BC1Block>(&image, &Default::default()))?;
+        assert_eq!(decoded.get_pixel(0, 0).0, [0, 0, 0, 0]);
+        assert_eq!(decoded.get_pixel(1, 0).0, [255, 255, 255, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn edges_bc3() -> Result<()> {
+        edges::<BC3Block>(3, 9)
+    }
+
+    #[test]
+    fn weighted() -> Result<()> {
+        // noisy, so the quality tier makes a difference, and weighted only in the left half
+        let image = RgbaImage::from_fn(16, 8, |x, y| {
+            let [a, b, c, _] = (x * 31 + y * 17).wrapping_mul(2654435761).to_le_bytes();
+            image::Rgba([a, a / 2 + b / 2, c, 255])
+        });
+        let weights = GrayImage::from_fn(2, 1, |x, _| image::Luma([(1 - x as u8) * 255]));
+        let roi = RoiQuality::default();
+        let options = BC1Options::default();
+        let surface = encode_surface_weighted::<BC1Block>(&image, &options, &weights, &roi)?;
+
+        let encode = |quality| {
+            encode_surface::<BC1Block>(&image, &BC1Block::with_quality(&options, quality)).buffer
+        };
+        let (exhaustive, fastest) = (encode(Quality::Exhaustive), encode(Quality::Fastest));
+        // each half of the surface is a 2x2 square of blocks, with 2 blocks in each half of a row
+        for (i, block) in surface.buffer.chunks(8).enumerate() {
+            let expected = match i % 4 < 2 {
+                true => &exhaustive,
+                false => &fastest,
+            };
+            assert_eq!(block, &expected[i * 8..i * 8 + 8], "block {i}");
+        }
+
+        // a mask smaller than a block still covers it
+        assert_eq!(block_weight::<BC1Block>(&weights, 3, 3, 0, 0), 255);
+        assert!(
+            encode_surface_weighted::<BC1Block>(&image, &options, &GrayImage::new(0, 0), &roi)
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_size() -> Result<()> {
+        let surface = Surface::new(Dimensions::try_from([8, 8])?, [0u8; 8]);
+        assert!(decode_surface::<BC4Block>(&surface).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn interpolation() -> Result<()> {
+        // a BC3 block of white and black endpoints, with every pixel on code 2, behind a solid alpha block
+        let mut bytes = [0u8; 16];
+        bytes[..2].copy_from_slice(&[255, 255]);
+        bytes[8..16].copy_from_slice(&[0xFF, 0xFF, 0x00, 0x00, 0xAA, 0xAA, 0xAA, 0xAA]);
+        let surface = Surface::new(Dimensions::try_from([4, 4])?, bytes);
+
+        let reference = decode_surface::<BC3Block>(&surface)?;
+        assert!(reference.pixels().all(|p| p.0 == [170, 170, 170, 255]));
+        let codec = BlockCodec::<BC3Block>::default().with_interpolation(Interpolation::Amd);
+        let amd = codec.decode_buffer(surface.buffer.clone(), surface.dimensions)?;
+        assert!(amd.to_rgba8().pixels().all(|p| p.0 == [167, 167, 167, 255]));
+
+        // 3D surfaces use the same arithmetic for every slice
+        let volume = Surface::new(Dimensions::try_from([4, 4, 2])?, bytes.repeat(2));
+        let slices = decode_slices_with::<BC3Block>(&volume, Interpolation::Amd)?;
+        assert_eq!(slices.len(), 2);
+        assert!(slices
+            .iter()
+            .flat_map(|s| s.pixels())
+            .all(|p| p.0 == [167, 167, 167, 255]));
+        let amd = codec.decode_buffer(volume.buffer.clone(), volume.dimensions)?;
+        assert_eq!(amd.height(), 8);
+        assert!(amd.to_rgba8().pixels().all(|p| p.0 == [167, 167, 167, 255]));
+        Ok(())
+    }
+
+    #[test]
+    fn slices_1d_and_3d() -> Result<()> {
+        let format = Format::BC4 { signed: false };
+
+        // a 1D texture is a single row of blocks, so 10 pixels need 3 blocks
+        let line = Dimensions::try_from([10])?;
+        assert_eq!(format.size_for(line)?, 3 * 8);
+        let image = RgbaImage::from_fn(10, 1, |x, _| image::Rgba([(x % 2 * 200) as u8, 0, 0, 255]));
+        let encoded = encode_surface::<BC4Block>(&image, &BC4Options::default());
+        let surface = Surface::new(line, encoded.buffer);
+        Texture::new(format, TextureShapeNode::from_surface(surface.clone()))?;
+        assert_eq!(decode_surface::<BC4Block>(&surface)?, image);
+
+        // a 3D texture is a stack of independent 2D slices, padded to whole blocks on each slice but not in depth
+        let volume = Dimensions::try_from([6, 6, 3])?;
+        assert_eq!(format.size_for(volume)?, 2 * 2 * 3 * 8);
+        let slices = (0..3u8)
+            .map(|z| RgbaImage::from_pixel(6, 6, image::Rgba([z * 50, 0, 0, 255])))
+            .collect_vec();
+        let surface = encode_slices::<BC4Block>(&slices, &BC4Options::default())?;
+        assert_eq!(surface.dimensions(), volume);
+        Texture::new(format, TextureShapeNode::from_surface(surface.clone()))?;
+        assert_eq!(decode_slices::<BC4Block>(&surface)?, slices);
+
+        // as a single image, the slices are stacked vertically
+        let stacked = slices.iter().flat_map(|s| s.as_raw().clone()).collect_vec();
+        assert_eq!(decode_surface::<BC4Block>(&surface)?.into_raw(), stacked);
+
+        // mipmaps of a volume shrink in depth too, down to a single 1x1x1 block
+        let mips = volume.mips().collect_vec();
+        assert_eq!(mips.last().map(|d| format.size_for(*d).unwrap()), Some(8));
+        Ok(())
+    }
+
+    /// 3D textures encode and decode through [`Texture::encode`] and [`Texture::decode`] one slice at a time
+    #[test]
+    fn texture_3d() -> Result<()> {
+        let volume = Dimensions::try_from([6, 6, 3])?;
+        let slices = (0..3u8)
+            .map(|z| RgbaImage::from_pixel(6, 6, image::Rgba([z * 50, 0, 0, 255])))
+            .collect_vec();
+        let stacked = slices.iter().flat_map(|s| s.as_raw().clone()).collect_vec();
+        let rgba = Texture::new(
+            Format::rgba8(false),
+            TextureShapeNode::from_surface(Surface::new(volume, stacked.clone())),
+        )?;
+
+        let bc4 = rgba.encode(Format::BC4 { signed: false }, EncodeOptions::default())?;
+        assert_eq!(bc4.dimensions(), volume);
+        let expected = encode_slices::<BC4Block>(&slices, &BC4Options::default())?;
+        assert_eq!(bc4.primary().buffer, expected.buffer);
+
+        let decoded = bc4.decode()?;
+        assert_eq!(decoded.format, Format::rgba8(false));
+        assert_eq!(decoded.dimensions(), volume);
+        assert_eq!(decoded.primary().buffer.as_ref(), stacked);
+        Ok(())
+    }
+
+    #[test]
+    fn threads() -> Result<()> {
+        let image = RgbaImage::from_fn(32, 16, |x, y| {
+            image::Rgba([(x * 8) as u8, (y * 16) as u8, ((x ^ y) * 8) as u8, 255])
+        });
+        let global = BlockCodec::<BC1Block>::default();
+        let scoped = BlockCodec::<BC1Block>::default().with_threads(Some(2))?;
+        assert_eq!(scoped.run(rayon::current_num_threads), 2);
+        assert_eq!(
+            global.run(rayon::current_num_threads),
+            rayon::current_num_threads()
+        );
+
+        // the thread count never changes the result
+        let encoded = scoped.encode_buffer(image.clone().into(), Quality::Normal);
+        assert_eq!(encoded, global.encode_buffer(image.into(), Quality::Normal));
+        let dimensions = Dimensions::try_from([32, 16])?;
+        assert_eq!(
+            scoped.decode_buffer(encoded.clone(), dimensions)?,
+            global.decode_buffer(encoded, dimensions)?
+        );
+        Ok(())
+    }
+}
@@ -5,16 +5,25 @@
 use bitvec::prelude::*;
 use vector_victor::Vector;
 
+/// A single 8-bit color channel
 pub type Channel = u8;
+/// An RGBA color
 pub type Color = Vector<Channel, 4>;
 
+/// Channel accessors and packing for [`Color`]
 pub trait ColorImpl {
+    /// The red channel
     fn r(&self) -> &Channel;
+    /// The green channel
     fn g(&self) -> &Channel;
+    /// The blue channel
     fn b(&self) -> &Channel;
+    /// The alpha channel
     fn a(&self) -> &Channel;
 
+    /// Unpack an opaque color from a 16-bit RGB565 value
     fn from_565(packed: u16) -> Self;
+    /// Pack the color channels into a 16-bit RGB565 value, discarding alpha
     fn to_565(&self) -> u16;
 }
 
@@ -36,10 +45,12 @@ impl ColorImpl for Color {
 
     fn from_565(packed: u16) -> Self {
         let bits = packed.view_bits::<Msb0>();
-        // TODO: Fix rounding for 565
-        let r: Channel = bits[0..5].load_le::<u8>() << 3;
-        let g: Channel = bits[5..11].load_le::<u8>() << 2;
-        let b: Channel = bits[11..16].load_le::<u8>() << 3;
+        // replicate the high bits into the low bits, so 0x1F expands to 0xFF rather than 0xF8
+        let expand5 = |v: u8| (v << 3) | (v >> 2);
+        let expand6 = |v: u8| (v << 2) | (v >> 4);
+        let r: Channel = expand5(bits[0..5].load_le::<u8>());
+        let g: Channel = expand6(bits[5..11].load_le::<u8>());
+        let b: Channel = expand5(bits[11..16].load_le::<u8>());
         let a: Channel = u8::MAX;
 
         Color::vec([r, g, b, a])
@@ -2,29 +2,95 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::fmt::Debug;
-use std::io::{Read, Seek, Write};
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 
-use binrw::{BinRead, BinReaderExt, BinWrite, BinWriterExt};
+use binrw::{BinRead, BinReaderExt, BinWrite, BinWriterExt, Endian};
 
+use crate::capability::ContainerCapabilities;
 use crate::dimensions::Dimensions;
-use crate::error::TextureResult;
+use crate::error::{HeaderError, TextureError, TextureResult};
 use crate::format::Format;
-use crate::shape::CubeFace;
-use crate::texture::{Surfaces, Texture};
+use crate::shape::{CubeFace, TextureShapeNode};
+use crate::texture::{Surface, SurfaceCounts, SurfaceOffset, SurfaceOrder, Surfaces, Texture};
 
 /// A header for a texture container. Contains information about dimensions, shape, and texture format,
 /// but does not contain any actual texture data.
+///
+/// ```
+/// use std::io::Cursor;
+/// use quicktex::container::ContainerHeader;
+/// use quicktex::dds::DDSHeader;
+/// use quicktex::dimensions::Dimensions;
+/// use quicktex::format::Format;
+/// use quicktex::shape::{TextureShape, TextureShapeNode};
+/// use quicktex::texture::{Surface, Texture};
+///
+/// // a full BC1 mip chain for an 8x8 texture, tagging each mip's bytes with its level
+/// let format = Format::BC1 { srgb: true };
+/// let mips = Dimensions::try_from([8, 8])?
+///     .mips()
+///     .enumerate()
+///     .map(|(i, d)| TextureShapeNode::from_surface(Surface::new(d, vec![i as u8; format.size_for(d).unwrap()])));
+/// let texture = Texture::new(format, TextureShapeNode::try_from_mips(mips)?)?;
+///
+/// let mut file = Cursor::new(Vec::new());
+/// DDSHeader::write_texture(&mut file, &texture)?;
+/// file.set_position(0);
+/// let read = DDSHeader::read_texture(&mut file)?;
+///
+/// assert_eq!(read.mips(), Some(4));
+/// for (a, b) in read.slice().iter().zip(texture.slice().iter()) {
+///     assert_eq!(a.mip, b.mip);
+///     assert_eq!(a.surface.buffer, b.surface.buffer);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// # Untrusted input
+///
+/// Reading the containers built into quicktex, directly or with [`read_any_texture`](crate::registry::read_any_texture),
+/// never panics on malformed or hostile files. Every problem is returned as an error, and surfaces are never
+/// allocated for more data than the file actually contains, so reading files from untrusted downloads is safe.
+/// This is tested by reading a corpus of corrupted files, and the targets in `fuzz/` check it continuously
 pub trait ContainerHeader: Sized + Clone + Debug + BinRead + BinWrite
 where
     for<'a> <Self as BinRead>::Args<'a>: Default,
     for<'a> <Self as BinWrite>::Args<'a>: Default,
 {
+    /// Arguments controlling how a header is generated when writing a texture
     type Args: Default;
 
-    /// Read a texture in this container type using the provided reader. The header object is not exposed
+    /// The format families and texture shapes this container can store, checked before a texture is written.
+    /// Defaults to everything, leaving all checks to [`Self::from_texture_args`]
+    const CAPABILITIES: ContainerCapabilities = ContainerCapabilities::ALL;
+
+    /// Read only the header using the provided reader, leaving the reader positioned after it.
+    /// Parse errors are returned as [`TextureError::Header`]
+    /// with the container's name set, along with the field and offset where the error occurred when known
+    fn read_header<R: Read + Seek>(reader: &mut R) -> TextureResult<Self> {
+        let name = container_name::<Self>();
+        let endian = Self::read_endian(reader)?;
+        reader
+            .read_type(endian)
+            .map_err(|e| HeaderError::new(Some(name), e).into())
+    }
+
+    /// Detect the byte order of the header at the reader's position, leaving the reader where it was.
+    /// Containers are little-endian unless they override this
+    fn read_endian<R: Read + Seek>(_reader: &mut R) -> TextureResult<Endian> {
+        Ok(Endian::Little)
+    }
+
+    /// The byte order this header is written in. See [`Self::read_endian`]
+    fn endian(&self) -> Endian {
+        Endian::Little
+    }
+
+    /// Read a texture in this container type using the provided reader. The header object is not exposed.
+    /// Malformed files return an error instead of panicking, see [Untrusted input](Self#untrusted-input)
     fn read_texture<R: Read + Seek>(reader: &mut R) -> TextureResult<Texture> {
-        let header: Self = reader.read_le()?;
+        let header = Self::read_header(reader)?;
         header.to_texture(reader)
     }
 
@@ -35,7 +101,9 @@ where
     }
 
     /// Write a texture in this container type using the provided writer and [`Self::Args`].
-    /// The header object is not exposed
+    /// The header object is not exposed.
+    /// Returns an error before anything is written if any surface does not match the texture's format,
+    /// or if the container can't store the texture's format or shape. See [`Texture::check_container`]
     fn write_texture_args<W>(
         writer: &mut W,
         texture: &Texture,
@@ -44,9 +112,93 @@ where
     where
         W: Write + Seek,
     {
+        texture.check_format()?;
+        texture.check_container::<Self>()?;
         let header: Self = Self::from_texture_args(texture, args)?;
-        writer.write_le(&header)?;
-        header.write_surfaces(writer, texture.clone().surfaces)
+        writer.write_type(&header, header.endian())?;
+        header.write_surfaces(writer, &texture.surfaces)
+    }
+
+    /// Read a texture in this container type using the provided reader, along with any [`Diagnostic`]s
+    /// about problems in the header that were worked around. See [`Self::diagnostics`]
+    fn read_texture_with_diagnostics<R: Read + Seek>(
+        reader: &mut R,
+    ) -> TextureResult<(Texture, Vec<Diagnostic>)> {
+        Self::read_texture_options(reader, &Default::default())
+    }
+
+    /// Read a texture in this container type using the provided reader and [`ReadOptions`],
+    /// along with any [`Diagnostic`]s about problems that were worked around
+    fn read_texture_options<R: Read + Seek>(
+        reader: &mut R,
+        options: &ReadOptions,
+    ) -> TextureResult<(Texture, Vec<Diagnostic>)> {
+        let header = Self::read_header(reader)?;
+        let mut diagnostics = header.diagnostics();
+        let texture = match header.format()? {
+            format @ Format::Unknown { four_cc } if options.passthrough_unknown => {
+                // the header can't say how large a payload in an unknown format is,
+                // so read the rest of the stream, through `take` so it can't grow past the length seen here
+                let start = reader.stream_position()?;
+                let end = reader.seek(SeekFrom::End(0))?;
+                reader.seek(SeekFrom::Start(start))?;
+                let mut buffer = Vec::new();
+                reader
+                    .take(end.saturating_sub(start))
+                    .read_to_end(&mut buffer)?;
+                diagnostics.push(Diagnostic::UnknownFormat { four_cc });
+                let surface = Surface::new(header.dimensions()?, buffer);
+                // not through Texture::new, since an unknown format has no size to check the buffer against
+                Texture {
+                    format,
+                    surfaces: TextureShapeNode::from_surface(surface),
+                }
+            }
+            _ => header.to_texture(reader)?,
+        };
+        Ok((texture, diagnostics))
+    }
+
+    /// Read a texture in this container type using the provided reader, calling `hooks` around the header
+    /// and on every surface read. See [`ContainerHooks`]
+    fn read_texture_with<R, K>(reader: &mut R, hooks: &mut K) -> TextureResult<Texture>
+    where
+        R: Read + Seek,
+        K: ContainerHooks<Self>,
+    {
+        hooks.before_read_header(reader)?;
+        let mut header = Self::read_header(reader)?;
+        hooks.after_read_header(reader, &mut header)?;
+
+        let format = header.format()?;
+        let surfaces = header
+            .read_surfaces(reader)?
+            .try_map(|s| hooks.read_payload(&header, s))?;
+        Texture::new(format, surfaces)
+    }
+
+    /// Write a texture in this container type using the provided writer and [`Self::Args`],
+    /// calling `hooks` around the header and on every surface written. See [`ContainerHooks`]
+    fn write_texture_with<W, K>(
+        writer: &mut W,
+        texture: &Texture,
+        args: &<Self as ContainerHeader>::Args,
+        hooks: &mut K,
+    ) -> TextureResult<()>
+    where
+        W: Write + Seek,
+        K: ContainerHooks<Self>,
+    {
+        texture.check_format()?;
+        let mut header: Self = Self::from_texture_args(texture, args)?;
+        hooks.before_write_header(writer, &mut header)?;
+        writer.write_type(&header, header.endian())?;
+        hooks.after_write_header(writer, &header)?;
+
+        let surfaces = texture
+            .surfaces
+            .try_map(|s| hooks.write_payload(&header, s))?;
+        header.write_surfaces(writer, &surfaces)
     }
 
     /// read the surfaces associated with this header using the provided reader
@@ -56,7 +208,7 @@ where
     fn write_surfaces<W: Write + Seek>(
         &self,
         writer: &mut W,
-        surfaces: Surfaces,
+        surfaces: &Surfaces,
     ) -> TextureResult<()>;
 
     /// Convert this header into a texture using the provided reader
@@ -91,4 +243,393 @@ where
 
     /// Get the texture format indicated by this container header
     fn format(&self) -> TextureResult<Format>;
+
+    /// Where each surface described by this header is stored in the file, in the order they are stored,
+    /// as offsets from the start of the header. Engines can use this to upload surfaces straight from a
+    /// memory-mapped file without reading them into [`Surfaces`]. The bytes are exactly as stored,
+    /// so surfaces in big-endian DDS files still have their pixels byte-swapped.
+    /// Returns [`TextureError::Capability`] for containers that don't store surfaces uncompressed at
+    /// offsets known from the header alone, such as QOI and the chunked container
+    fn surface_offsets(&self) -> TextureResult<Box<dyn Iterator<Item = SurfaceOffset>>> {
+        Err(TextureError::Capability(format!(
+            "{} files do not store surfaces at fixed offsets",
+            container_name::<Self>()
+        )))
+    }
+
+    /// Problems found while reading this header that did not prevent it from being read.
+    /// Headers created from a texture never have any diagnostics
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        Vec::new()
+    }
+}
+
+/// The name of the container with header `H` for error messages, such as `DDS` for `DDSHeader`
+pub(crate) fn container_name<H>() -> &'static str {
+    let name = std::any::type_name::<H>()
+        .rsplit("::")
+        .next()
+        .unwrap_or_default();
+    name.strip_suffix("Header").unwrap_or(name)
+}
+
+/// Offsets of every surface described by `header`, for containers that store surfaces in `order`
+/// with no padding immediately after the header. See [`ContainerHeader::surface_offsets`]
+pub(crate) fn packed_offsets<H: ContainerHeader>(
+    header: &H,
+    order: SurfaceOrder,
+) -> TextureResult<Box<dyn Iterator<Item = SurfaceOffset>>>
+where
+    for<'a> <H as BinRead>::Args<'a>: Default,
+    for<'a> <H as BinWrite>::Args<'a>: Default,
+{
+    // headers can have variable-length parts, so measure by writing it out
+    let mut written = Cursor::new(Vec::new());
+    written.write_type(header, header.endian())?;
+    let counts = SurfaceCounts {
+        dimensions: header.dimensions()?,
+        layers: header.layers()?,
+        faces: header.faces()?,
+        mips: header.mips()?,
+    };
+    order.offsets(header.format()?, &counts, written.position())
+}
+
+/// Options controlling how [`ContainerHeader::read_texture_options`] handles unusual files.
+/// New options may be added in the future, so create them from [`Default`] with the builder methods
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// Load files in an unrecognized format instead of returning an error.
+    /// The texture has the format [`Format::Unknown`] and a single surface with the texture's dimensions,
+    /// holding everything after the header as-is, and a [`Diagnostic::UnknownFormat`] is reported.
+    /// This lets batch jobs skip over exotic textures without aborting.
+    ///
+    /// Such a texture is the one exception to every surface being the size its format requires,
+    /// so anything that needs that size, like writing, decoding, or [`Texture::check_format`],
+    /// returns [`TextureError::Format`] for it
+    pub passthrough_unknown: bool,
+}
+
+impl ReadOptions {
+    /// Set if files in an unrecognized format are loaded instead of returning an error.
+    /// See [`Self::passthrough_unknown`]
+    pub fn passthrough_unknown(mut self, passthrough: bool) -> Self {
+        self.passthrough_unknown = passthrough;
+        self
+    }
+}
+
+/// A problem in a file that was worked around while reading it, rather than treated as an error.
+/// See [`ContainerHeader::diagnostics`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A flags field had bits set that aren't defined by the container format, and they were ignored
+    UnknownBits {
+        /// The name of the field
+        field: &'static str,
+        /// The unknown bits
+        bits: u32,
+    },
+
+    /// The texture's format was not recognized, and its contents were loaded as an opaque payload.
+    /// See [`ReadOptions::passthrough_unknown`]
+    UnknownFormat {
+        /// The FourCC code of the format
+        four_cc: [u8; 4],
+    },
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::UnknownBits { field, bits } => {
+                write!(f, "Ignored unknown bits {bits:#010X} in {field}")
+            }
+            Diagnostic::UnknownFormat { four_cc } => write!(
+                f,
+                "Loaded unknown format '{}' without decoding it",
+                String::from_utf8_lossy(four_cc)
+            ),
+        }
+    }
+}
+
+/// Extension points for wrapping an existing container, such as platform-specific formats that
+/// add their own data around a standard header or store surfaces in a different layout.
+/// Every method does nothing by default, and `()` implements this trait for any header with no hooks.
+///
+/// Hooks are used by [`ContainerHeader::read_texture_with`] and [`ContainerHeader::write_texture_with`],
+/// so a wrapper only needs to implement the parts that differ while reusing the container's surface
+/// reading and writing. Payload hooks are given surfaces that are exactly the size required by the
+/// texture's format, and must return surfaces of the same size
+pub trait ContainerHooks<H> {
+    /// Called before the header is read, for example to read and check a wrapper's own header
+    fn before_read_header<R: Read + Seek>(&mut self, _reader: &mut R) -> TextureResult<()> {
+        Ok(())
+    }
+
+    /// Called after the header is read, before any surfaces are read.
+    /// The header may be modified, for example to replace a format that the wrapper stores elsewhere
+    fn after_read_header<R: Read + Seek>(
+        &mut self,
+        _reader: &mut R,
+        _header: &mut H,
+    ) -> TextureResult<()> {
+        Ok(())
+    }
+
+    /// Called before the header is written. The header may be modified before it is written
+    fn before_write_header<W: Write + Seek>(
+        &mut self,
+        _writer: &mut W,
+        _header: &mut H,
+    ) -> TextureResult<()> {
+        Ok(())
+    }
+
+    /// Called after the header is written, before any surfaces are written
+    fn after_write_header<W: Write + Seek>(
+        &mut self,
+        _writer: &mut W,
+        _header: &H,
+    ) -> TextureResult<()> {
+        Ok(())
+    }
+
+    /// Transform each surface after it is read, for example to untile or decrypt it
+    fn read_payload(&mut self, _header: &H, surface: &Surface) -> TextureResult<Surface> {
+        Ok(surface.clone())
+    }
+
+    /// Transform each surface before it is written, for example to tile or encrypt it
+    fn write_payload(&mut self, _header: &H, surface: &Surface) -> TextureResult<Surface> {
+        Ok(surface.clone())
+    }
+}
+
+impl<H> ContainerHooks<H> for () {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+
+    use strum::VariantArray;
+
+    use crate::dds::DDSHeader;
+    use crate::error::TextureError;
+    use crate::pvr::PVRHeader;
+    use crate::shape::TextureShape;
+
+    use super::*;
+
+    /// A made-up wrapper that prefixes the file with a magic number and obfuscates the surfaces
+    struct Wrapper;
+
+    const MAGIC: &[u8; 4] = b"WRAP";
+
+    impl Wrapper {
+        fn xor(surface: &Surface) -> Surface {
+            let buffer = surface.buffer.iter().map(|b| b ^ 0x5A).collect::<Vec<_>>();
+            Surface::new(surface.dimensions, buffer)
+        }
+    }
+
+    impl ContainerHooks<DDSHeader> for Wrapper {
+        fn before_read_header<R: Read + Seek>(&mut self, reader: &mut R) -> TextureResult<()> {
+            let mut magic = [0u8; 4];
+            reader.read_exact(&mut magic)?;
+            match &magic == MAGIC {
+                true => Ok(()),
+                false => Err(TextureError::Other("missing wrapper magic".to_string())),
+            }
+        }
+
+        fn before_write_header<W: Write + Seek>(
+            &mut self,
+            writer: &mut W,
+            _header: &mut DDSHeader,
+        ) -> TextureResult<()> {
+            Ok(writer.write_all(MAGIC)?)
+        }
+
+        fn read_payload(
+            &mut self,
+            _header: &DDSHeader,
+            surface: &Surface,
+        ) -> TextureResult<Surface> {
+            Ok(Self::xor(surface))
+        }
+
+        fn write_payload(
+            &mut self,
+            _header: &DDSHeader,
+            surface: &Surface,
+        ) -> TextureResult<Surface> {
+            Ok(Self::xor(surface))
+        }
+    }
+
+    #[test]
+    fn hooks() -> Result<()> {
+        let dimensions = Dimensions::try_from([4, 4])?;
+        let surface = Surface::new(dimensions, (0..8).collect::<Vec<u8>>());
+        let texture = Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(surface.clone()),
+        )?;
+
+        let mut file = Cursor::new(Vec::new());
+        DDSHeader::write_texture_with(&mut file, &texture, &Default::default(), &mut Wrapper)?;
+        let data = file.get_ref();
+        assert_eq!(&data[..8], b"WRAPDDS ");
+        assert_eq!(data[data.len() - 8..], Wrapper::xor(&surface).buffer[..]);
+
+        // without hooks, the file is not a valid DDS file
+        file.set_position(0);
+        assert!(DDSHeader::read_texture(&mut file).is_err());
+
+        file.set_position(0);
+        let read = DDSHeader::read_texture_with(&mut file, &mut Wrapper)?;
+        assert_eq!(
+            read.slice().try_into_surface().unwrap().buffer,
+            surface.buffer
+        );
+
+        // () is a no-op
+        let mut file = Cursor::new(Vec::new());
+        DDSHeader::write_texture_with(&mut file, &texture, &Default::default(), &mut ())?;
+        file.set_position(0);
+        let read = DDSHeader::read_texture_with(&mut file, &mut ())?;
+        assert_eq!(
+            read.slice().try_into_surface().unwrap().buffer,
+            surface.buffer
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_malformed() -> Result<()> {
+        let format = Format::BC1 { srgb: false };
+        let surface = |size: u32| -> Result<Surfaces> {
+            let dimensions = Dimensions::try_from([size, size])?;
+            Ok(TextureShapeNode::from_surface(Surface::new(
+                dimensions,
+                vec![0; format.size_for(dimensions)?],
+            )))
+        };
+        // built by hand instead of through Texture::new, so the shape is never validated
+        let malformed = [
+            TextureShapeNode::Array(vec![
+                surface(8)?,
+                TextureShapeNode::MipMap(vec![surface(8)?, surface(4)?]),
+            ]),
+            TextureShapeNode::Array(vec![]),
+        ];
+        for surfaces in malformed {
+            let texture = Texture { format, surfaces };
+            let mut file = Cursor::new(Vec::new());
+            assert!(DDSHeader::write_texture(&mut file, &texture).is_err());
+            assert!(DDSHeader::write_texture_with(
+                &mut file,
+                &texture,
+                &Default::default(),
+                &mut ()
+            )
+            .is_err());
+            assert!(file.get_ref().is_empty(), "nothing is written");
+        }
+        Ok(())
+    }
+
+    /// Check that every surface offset of a texture written as `H` points at that surface's bytes
+    fn check_offsets<H: ContainerHeader>(texture: &Texture, order: SurfaceOrder) -> Result<()>
+    where
+        for<'a> <H as BinRead>::Args<'a>: Default,
+        for<'a> <H as BinWrite>::Args<'a>: Default,
+    {
+        let mut file = Cursor::new(Vec::new());
+        H::write_texture(&mut file, texture)?;
+        file.set_position(0);
+        let header = H::read_header(&mut file)?;
+        let data = file.into_inner();
+
+        let offsets = header.surface_offsets()?.collect::<Vec<_>>();
+        let expected = order.ordered(&texture.surfaces);
+        assert_eq!(offsets.len(), expected.len());
+        for (offset, surface) in offsets.iter().zip(expected) {
+            assert_eq!(
+                (offset.layer, offset.face, offset.mip),
+                (surface.layer, surface.face, surface.mip)
+            );
+            let start = offset.offset as usize;
+            assert_eq!(
+                data[start..start + offset.length],
+                surface.surface.buffer[..]
+            );
+        }
+        let last = offsets.last().unwrap();
+        assert_eq!(last.offset as usize + last.length, data.len());
+        Ok(())
+    }
+
+    #[test]
+    fn surface_offsets() -> Result<()> {
+        let format = Format::BC1 { srgb: false };
+        let mut tag = 0u8;
+        let mut mips = || -> Result<Surfaces> {
+            let mips = Dimensions::try_from([8, 8])?.mips().map(|d| {
+                tag += 1;
+                TextureShapeNode::from_surface(Surface::new(
+                    d,
+                    vec![tag; format.size_for(d).unwrap()],
+                ))
+            });
+            Ok(Surfaces::try_from_mips(mips)?)
+        };
+        let mut faces = || -> Result<Surfaces> {
+            let faces = CubeFace::VARIANTS
+                .iter()
+                .map(|&f| Ok((f, mips()?)))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Surfaces::try_from_faces(faces)?)
+        };
+        let layers = vec![faces()?, faces()?];
+        let texture = Texture::new(format, Surfaces::try_from_layers(layers)?)?;
+
+        check_offsets::<DDSHeader>(&texture, SurfaceOrder::LayersFacesMips)?;
+        check_offsets::<PVRHeader>(&texture, SurfaceOrder::MipsLayersFaces)?;
+
+        // a single surface has no layer, face, or mip
+        let surface = texture.slice().iter().next().unwrap().surface.clone();
+        let single = Texture::new(format, TextureShapeNode::from_surface(surface))?;
+        check_offsets::<DDSHeader>(&single, SurfaceOrder::LayersFacesMips)?;
+        let offset = DDSHeader::from_texture(&single)?
+            .surface_offsets()?
+            .next()
+            .unwrap();
+        assert_eq!((offset.layer, offset.face, offset.mip), (None, None, None));
+        assert_eq!(offset.offset, 128);
+        Ok(())
+    }
+
+    #[cfg(feature = "chunked")]
+    #[test]
+    fn surface_offsets_unsupported() -> Result<()> {
+        use crate::chunked::ChunkedHeader;
+
+        let surface = Surface::new(Dimensions::try_from([4, 4])?, vec![0u8; 8]);
+        let texture = Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(surface),
+        )?;
+        let header = ChunkedHeader::from_texture(&texture)?;
+        assert!(matches!(
+            header.surface_offsets(),
+            Err(TextureError::Capability(_))
+        ));
+        Ok(())
+    }
 }
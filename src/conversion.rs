@@ -0,0 +1,325 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::error::{TextureError, TextureResult};
+use crate::format::{DecodeOptions, EncodeOptions, Format, Quality};
+use crate::resize::resize;
+use crate::shape::TextureShape;
+use crate::texture::{Surface, Texture};
+use crate::tiling::make_tileable;
+
+/// Options for [`Texture::convert`]
+#[derive(Clone, Debug, Default)]
+pub struct ConvertOptions {
+    /// Format to convert to, or [`None`] to keep the texture's current format
+    pub format: Option<Format>,
+
+    /// Quality tier to use when encoding
+    pub quality: Quality,
+
+    /// Number of mips to remove from the top of the mip chain
+    pub strip_mips: usize,
+
+    /// Maximum size of the largest mip along any axis. Mipmapped textures are shrunk by removing mips,
+    /// which requires no decoding. Other textures are decoded and resized, keeping their aspect ratio
+    pub max_dimension: Option<u32>,
+
+    /// Cross-fade the edges of every surface so it tiles seamlessly before it is encoded,
+    /// with a band this fraction of the surface's size wide. See [`make_tileable`]
+    pub tileable: Option<f32>,
+
+    /// Measure the error introduced by encoding, by decoding each encoded surface again
+    /// and comparing it with its source
+    pub metrics: bool,
+
+    /// Number of threads to decode and encode with. See [`EncodeOptions::threads`]
+    pub threads: Option<usize>,
+}
+
+/// Error introduced by a conversion, measured over every channel of every encoded surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QualityMetrics {
+    /// Mean squared error, in 8-bit units
+    pub mse: f64,
+
+    /// Peak signal-to-noise ratio in decibels. Infinite if the conversion was lossless
+    pub psnr: f64,
+}
+
+impl QualityMetrics {
+    fn from_error(squared_error: u64, samples: u64) -> Self {
+        let mse = match samples {
+            0 => 0.0,
+            n => squared_error as f64 / n as f64,
+        };
+        let psnr = 10.0 * (255.0f64.powi(2) / mse).log10();
+        Self { mse, psnr }
+    }
+}
+
+/// What [`Texture::convert`] did to a texture
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConversionReport {
+    /// Format of the original texture
+    pub source_format: Format,
+
+    /// Format of the resulting texture
+    pub target_format: Format,
+
+    /// Number of surfaces that were decoded
+    pub decoded: usize,
+
+    /// Number of surfaces that were encoded
+    pub encoded: usize,
+
+    /// Dimensions of the texture before and after resizing, if it was resized
+    pub resized: Option<(Dimensions, Dimensions)>,
+
+    /// Number of mips removed from the top of the mip chain
+    pub stripped_mips: usize,
+
+    /// Total time taken by the conversion
+    pub elapsed: Duration,
+
+    /// Error introduced by the conversion, if [`ConvertOptions::metrics`] was set
+    pub metrics: Option<QualityMetrics>,
+}
+
+impl Display for ConversionReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} -> {:?}", self.source_format, self.target_format)?;
+        write!(
+            f,
+            ", {} surfaces decoded, {} encoded",
+            self.decoded, self.encoded
+        )?;
+        if self.stripped_mips > 0 {
+            write!(f, ", {} mips stripped", self.stripped_mips)?;
+        }
+        if let Some((from, to)) = self.resized {
+            write!(f, ", resized from {from:?} to {to:?}")?;
+        }
+        if let Some(metrics) = self.metrics {
+            write!(f, ", PSNR {:.2} dB", metrics.psnr)?;
+        }
+        write!(f, " in {:?}", self.elapsed)
+    }
+}
+
+impl Texture {
+    /// Convert the texture as described by `options`, returning the new texture and a report of what was done.
+    ///
+    /// Surfaces are only decoded and re-encoded if the texture changes format, has to be resized, or is made tileable,
+    /// so removing mips from a texture in a format without a codec is still possible.
+    /// Returns [`TextureError::Format`] if a codec is needed but either format does not have one
+    pub fn convert(&self, options: &ConvertOptions) -> TextureResult<(Texture, ConversionReport)> {
+        let start = Instant::now();
+        let target = options.format.unwrap_or(self.format);
+
+        let mut surfaces = self.surfaces.clone();
+        surfaces.strip_top_mips(options.strip_mips)?;
+        let mut stripped_mips = options.strip_mips;
+
+        let mut resized = None;
+        if let Some(max) = options.max_dimension {
+            if max == 0 {
+                return Err(TextureError::Other(
+                    "Maximum dimension must be nonzero".to_string(),
+                ));
+            }
+            let largest = |d: Dimensions| d.into_iter().max().unwrap_or(1);
+            let dimensions = surfaces.dimensions();
+            if largest(dimensions) > max {
+                match surfaces.mips() {
+                    // mip chains always go down to 1x1, so there is always a mip small enough
+                    Some(_) => {
+                        let n = dimensions.mips().position(|d| largest(d) <= max).unwrap();
+                        surfaces.strip_top_mips(n)?;
+                        stripped_mips += n;
+                    }
+                    None => {
+                        let scale = max as f64 / largest(dimensions) as f64;
+                        let scaled = dimensions
+                            .into_iter()
+                            .map(|d| ((d as f64 * scale).round() as u32).max(1))
+                            .collect::<Vec<_>>();
+                        resized = Some((dimensions, Dimensions::try_from(scaled)?));
+                    }
+                }
+            }
+        }
+
+        let mut decoded = 0;
+        let mut encoded = 0;
+        let mut squared_error = 0u64;
+        let mut samples = 0u64;
+        if target != self.format || resized.is_some() || options.tileable.is_some() {
+            let decode_options = DecodeOptions::default().threads(options.threads);
+            let decoder = self.format.decoder_with(&decode_options)?;
+            let encoder =
+                target.encoder_with(&EncodeOptions::default().threads(options.threads))?;
+            let verifier = match options.metrics {
+                true => Some(target.decoder_with(&decode_options)?),
+                false => None,
+            };
+
+            surfaces = surfaces.try_map(|s| -> TextureResult<Surface> {
+                let mut image = decoder.decode_buffer(s.buffer.clone(), s.dimensions)?;
+                decoded += 1;
+                let mut dimensions = s.dimensions;
+                if let Some((_, to)) = resized {
+                    image = resize(&image, to.width(), to.height(), self.format.srgb());
+                    dimensions = to;
+                }
+                if let Some(band) = options.tileable {
+                    image = make_tileable(&image.into_rgba8(), band).into();
+                }
+
+                let buffer = encoder.encode_buffer(image.clone(), options.quality);
+                encoded += 1;
+
+                if let Some(verifier) = &verifier {
+                    let result = verifier.decode_buffer(buffer.clone(), dimensions)?;
+                    let (source, result) = (image.into_rgba8(), result.into_rgba8());
+                    for (a, b) in source.as_raw().iter().zip(result.as_raw()) {
+                        squared_error += (a.abs_diff(*b) as u64).pow(2);
+                    }
+                    samples += source.as_raw().len() as u64;
+                }
+                Ok(Surface::new(dimensions, buffer))
+            })?;
+        }
+
+        let texture = Texture::new(target, surfaces)?;
+        let report = ConversionReport {
+            source_format: self.format,
+            target_format: target,
+            decoded,
+            encoded,
+            resized,
+            stripped_mips,
+            elapsed: start.elapsed(),
+            metrics: options
+                .metrics
+                .then(|| QualityMetrics::from_error(squared_error, samples)),
+        };
+        Ok((texture, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use anyhow::Result;
+    use image::{DynamicImage, RgbaImage};
+
+    use crate::container::ContainerHeader;
+    use crate::dds::DDSHeader;
+    use crate::format::{AlphaFormat, ColorFormat};
+
+    use super::*;
+
+    fn read_peppers(format_name: &str) -> Result<Texture> {
+        let path = format!(
+            "{}/tests/images/dds/peppers16 {format_name}.dds",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        Ok(DDSHeader::read_texture(&mut File::open(path)?)?)
+    }
+
+    fn bgra8() -> Format {
+        Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0x00FF0000,
+                g_mask: 0x0000FF00,
+                b_mask: 0x000000FF,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xFF000000,
+            },
+        }
+    }
+
+    #[test]
+    fn transcode() -> Result<()> {
+        let texture = read_peppers("rgb")?;
+        let options = ConvertOptions {
+            format: Some(bgra8()),
+            metrics: true,
+            ..Default::default()
+        };
+
+        let (converted, report) = texture.convert(&options)?;
+        assert_eq!(converted.format, bgra8());
+        assert_eq!(converted.mips(), Some(5));
+        assert_eq!((report.decoded, report.encoded), (5, 5));
+        assert_eq!(report.stripped_mips, 0);
+        assert_eq!(report.resized, None);
+
+        // adding an opaque alpha channel is lossless
+        let metrics = report.metrics.unwrap();
+        assert_eq!(metrics.mse, 0.0);
+        assert!(metrics.psnr.is_infinite());
+        Ok(())
+    }
+
+    #[test]
+    fn shrink_mips() -> Result<()> {
+        // BC1 has no codec, but removing mips doesn't need one
+        let texture = read_peppers("bc1")?;
+        let options = ConvertOptions {
+            max_dimension: Some(5),
+            ..Default::default()
+        };
+
+        let (converted, report) = texture.convert(&options)?;
+        assert_eq!(converted.dimensions(), Dimensions::try_from([4, 4])?);
+        assert_eq!(report.stripped_mips, 2);
+        assert_eq!((report.decoded, report.encoded), (0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn resize() -> Result<()> {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(16, 8));
+        let texture = Texture::from_image(&image, bgra8(), false, Quality::default())?;
+        let options = ConvertOptions {
+            max_dimension: Some(4),
+            ..Default::default()
+        };
+
+        let (converted, report) = texture.convert(&options)?;
+        let expected = Dimensions::try_from([4, 2])?;
+        assert_eq!(converted.dimensions(), expected);
+        assert_eq!(report.resized, Some((texture.dimensions(), expected)));
+        assert_eq!(converted.payload_size(), 4 * 2 * 4);
+        assert_eq!(report.metrics, None);
+        Ok(())
+    }
+
+    #[test]
+    fn tileable() -> Result<()> {
+        let image = RgbaImage::from_fn(8, 8, |x, _| image::Rgba([x as u8 * 32, 0, 0, 255]));
+        let texture =
+            Texture::from_image(&image.clone().into(), bgra8(), false, Quality::default())?;
+        let options = ConvertOptions {
+            format: Some(Format::rgba8(false)),
+            tileable: Some(0.25),
+            ..Default::default()
+        };
+
+        let (converted, report) = texture.convert(&options)?;
+        assert_eq!((report.decoded, report.encoded), (1, 1));
+        let surface = converted.slice().try_into_surface().unwrap();
+        assert_eq!(surface.buffer[..], make_tileable(&image, 0.25).as_raw()[..]);
+        Ok(())
+    }
+}
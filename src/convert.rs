@@ -0,0 +1,356 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Repacking uncompressed surfaces between channel layouts, such as BGRA8 to RGBA8 or RGB565 to RGBA8.
+//!
+//! Each channel is moved from its bitmask in the source format to its bitmask in the destination,
+//! and rescaled if the two masks have different widths. Channels of the same width are copied exactly,
+//! without the round trip through 8 bits that decoding and encoding would take.
+//! Luminance is copied to every color channel when converting to RGB, and RGB is reduced to Rec. 709 luma
+//! when converting to luminance. Missing color channels are 0, and missing alpha is opaque.
+//! The sRGB flag is not acted on, so sRGB values are moved as-is without being re-encoded.
+//!
+//! [`byteswap`](crate::convert::byteswap) converts 16 and 32-bit values between little and big-endian,
+//! for payloads from big-endian containers or in network byte order
+
+use crate::error::{TextureError, TextureResult};
+use crate::format::{AlphaFormat, ColorFormat, Format};
+use crate::texture::{Surface, Texture};
+
+/// The channel masks of an uncompressed format
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    pitch: usize,
+    color_format: ColorFormat,
+    alpha_mask: Option<u32>,
+}
+
+impl Layout {
+    fn new(format: Format) -> TextureResult<Self> {
+        let Format::Uncompressed {
+            pitch,
+            color_format,
+            alpha_format,
+        } = format
+        else {
+            return Err(TextureError::Format(format!(
+                "{format:?} is not an uncompressed format"
+            )));
+        };
+        if pitch == 0 || pitch > 4 {
+            return Err(TextureError::Format(format!(
+                "Uncompressed pixels must be 1 to 4 bytes, not {pitch}"
+            )));
+        }
+        if let ColorFormat::YUV { .. } = color_format {
+            return Err(TextureError::Format(
+                "YUV formats are not supported".to_string(),
+            ));
+        }
+        let alpha_mask = match alpha_format {
+            AlphaFormat::Custom { alpha_mask }
+            | AlphaFormat::Straight { alpha_mask }
+            | AlphaFormat::Premultiplied { alpha_mask } => Some(alpha_mask),
+            AlphaFormat::Opaque => None,
+        };
+        Ok(Self {
+            pitch,
+            color_format,
+            alpha_mask,
+        })
+    }
+}
+
+/// A channel value read from a pixel, along with the largest value its mask can hold
+#[derive(Clone, Copy, Debug)]
+struct Channel {
+    value: u64,
+    max: u64,
+}
+
+impl Channel {
+    const ZERO: Channel = Channel { value: 0, max: 1 };
+    const ONE: Channel = Channel { value: 1, max: 1 };
+
+    fn read(pixel: u32, mask: u32) -> Self {
+        if mask == 0 {
+            return Self::ZERO;
+        }
+        Self {
+            value: ((pixel & mask) >> mask.trailing_zeros()) as u64,
+            max: (mask >> mask.trailing_zeros()) as u64,
+        }
+    }
+
+    fn write(self, mask: u32) -> u32 {
+        if mask == 0 {
+            return 0;
+        }
+        let max = (mask >> mask.trailing_zeros()) as u64;
+        let value = (self.value * max + self.max / 2) / self.max;
+        (value as u32) << mask.trailing_zeros()
+    }
+
+    /// Rec. 709 luma of three channels, with the precision of the widest one
+    fn luma(rgb: [Channel; 3]) -> Self {
+        let max = rgb.iter().map(|c| c.max).max().unwrap_or(1);
+        let weights = [0.2126, 0.7152, 0.0722];
+        let luma: f64 = (rgb.iter().zip(weights))
+            .map(|(c, w)| c.value as f64 / c.max as f64 * w)
+            .sum();
+        Self {
+            value: (luma * max as f64).round() as u64,
+            max,
+        }
+    }
+}
+
+/// Repack a buffer of uncompressed pixels from the layout of `from` to the layout of `to`.
+/// Returns [`TextureError::Format`] if either format is not uncompressed or uses YUV,
+/// or [`TextureError::Other`] if the buffer is not a whole number of pixels
+pub fn repack(buffer: &[u8], from: Format, to: Format) -> TextureResult<Vec<u8>> {
+    let (src, dst) = (Layout::new(from)?, Layout::new(to)?);
+    if !buffer.len().is_multiple_of(src.pitch) {
+        return Err(TextureError::Other(format!(
+            "Buffer has {} bytes, which is not a whole number of {}-byte pixels",
+            buffer.len(),
+            src.pitch
+        )));
+    }
+
+    let mut repacked = Vec::with_capacity(buffer.len() / src.pitch * dst.pitch);
+    for src_bytes in buffer.chunks_exact(src.pitch) {
+        let mut bytes = [0u8; 4];
+        bytes[..src.pitch].copy_from_slice(src_bytes);
+        let pixel = u32::from_le_bytes(bytes);
+
+        let rgb = match src.color_format {
+            ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                ..
+            } => [r_mask, g_mask, b_mask].map(|m| Channel::read(pixel, m)),
+            ColorFormat::L { l_mask } => [Channel::read(pixel, l_mask); 3],
+            ColorFormat::None | ColorFormat::YUV { .. } => [Channel::ZERO; 3],
+        };
+        let alpha = src
+            .alpha_mask
+            .map_or(Channel::ONE, |m| Channel::read(pixel, m));
+
+        let mut packed = match dst.color_format {
+            ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                ..
+            } => rgb[0].write(r_mask) | rgb[1].write(g_mask) | rgb[2].write(b_mask),
+            ColorFormat::L { l_mask } => match src.color_format {
+                ColorFormat::L { .. } => rgb[0].write(l_mask),
+                _ => Channel::luma(rgb).write(l_mask),
+            },
+            ColorFormat::None | ColorFormat::YUV { .. } => 0,
+        };
+        if let Some(alpha_mask) = dst.alpha_mask {
+            packed |= alpha.write(alpha_mask);
+        }
+        repacked.extend_from_slice(&packed.to_le_bytes()[..dst.pitch]);
+    }
+    Ok(repacked)
+}
+
+/// Reverse the bytes of every `channel_width`-byte value in `buffer`, converting it between little and big-endian.
+/// A width of 1 leaves the buffer unchanged.
+/// Returns [`TextureError::Other`] if the width is not 1, 2, 4, or 8, or the buffer is not a whole number of values
+pub fn byteswap(buffer: &mut [u8], channel_width: usize) -> TextureResult<()> {
+    if ![1, 2, 4, 8].contains(&channel_width) {
+        return Err(TextureError::Other(format!(
+            "Cannot swap the bytes of {channel_width}-byte values"
+        )));
+    }
+    if !buffer.len().is_multiple_of(channel_width) {
+        return Err(TextureError::Other(format!(
+            "Buffer has {} bytes, which is not a whole number of {channel_width}-byte values",
+            buffer.len()
+        )));
+    }
+    if channel_width > 1 {
+        buffer
+            .chunks_exact_mut(channel_width)
+            .for_each(<[u8]>::reverse);
+    }
+    Ok(())
+}
+
+impl Surface {
+    /// Copy this surface with the bytes of every `channel_width`-byte value reversed. See [`byteswap`]
+    pub fn byteswap(&self, channel_width: usize) -> TextureResult<Surface> {
+        let mut buffer = self.buffer.to_vec();
+        byteswap(&mut buffer, channel_width)?;
+        Ok(Surface::new(self.dimensions, buffer))
+    }
+
+    /// Repack this surface from the uncompressed layout of `from` to that of `to`. See [`repack`]
+    pub fn repack(&self, from: Format, to: Format) -> TextureResult<Surface> {
+        self.check_format(from)?;
+        Ok(Surface::new(
+            self.dimensions,
+            repack(&self.buffer, from, to)?,
+        ))
+    }
+}
+
+impl Texture {
+    /// Copy this texture with every surface converted between little and big-endian,
+    /// swapping values of [`Format::swap_width`] bytes. Textures whose swap width is 1 are copied unchanged
+    pub fn byteswap(&self) -> TextureResult<Texture> {
+        let width = self.format.swap_width();
+        let surfaces = self.surfaces.try_map(|s| s.byteswap(width))?;
+        Texture::new(self.format, surfaces)
+    }
+
+    /// Repack every surface of an uncompressed texture into the layout of `format`. See [`repack`]
+    pub fn repack(&self, format: Format) -> TextureResult<Texture> {
+        let surfaces = self.surfaces.try_map(|s| s.repack(self.format, format))?;
+        Texture::new(format, surfaces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::dimensions::Dimensions;
+
+    use super::*;
+
+    fn uncompressed(pitch: usize, masks: [u32; 4]) -> Format {
+        let [r_mask, g_mask, b_mask, alpha_mask] = masks;
+        Format::Uncompressed {
+            pitch,
+            color_format: ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                srgb: false,
+            },
+            alpha_format: match alpha_mask {
+                0 => AlphaFormat::Opaque,
+                alpha_mask => AlphaFormat::Straight { alpha_mask },
+            },
+        }
+    }
+
+    fn l8() -> Format {
+        Format::Uncompressed {
+            pitch: 1,
+            color_format: ColorFormat::L { l_mask: 0xFF },
+            alpha_format: AlphaFormat::Opaque,
+        }
+    }
+
+    #[test]
+    fn bgra8_to_rgba8() -> Result<()> {
+        let bgra8 = uncompressed(4, [0xFF0000, 0xFF00, 0xFF, 0xFF000000]);
+        let rgba8 = Format::rgba8(false);
+        let bgra = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(repack(&bgra, bgra8, rgba8)?, [3, 2, 1, 4, 7, 6, 5, 8]);
+        assert_eq!(repack(&repack(&bgra, bgra8, rgba8)?, rgba8, bgra8)?, bgra);
+        Ok(())
+    }
+
+    #[test]
+    fn rgb565() -> Result<()> {
+        let rgb565 = uncompressed(2, [0xF800, 0x07E0, 0x001F, 0]);
+        let rgba8 = Format::rgba8(false);
+        // magenta, and the darkest red and green
+        let pixels = [0x1F, 0xF8, 0x00, 0x08, 0x20, 0x00];
+        assert_eq!(
+            repack(&pixels, rgb565, rgba8)?,
+            [255, 0, 255, 255, 8, 0, 0, 255, 0, 4, 0, 255]
+        );
+        assert_eq!(
+            repack(&repack(&pixels, rgb565, rgba8)?, rgba8, rgb565)?,
+            pixels
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn luminance() -> Result<()> {
+        let rgb8 = uncompressed(3, [0xFF, 0xFF00, 0xFF0000, 0]);
+        assert_eq!(
+            repack(&[0, 100, 255], l8(), rgb8)?,
+            [0, 0, 0, 100, 100, 100, 255, 255, 255]
+        );
+        assert_eq!(repack(&[255, 255, 255, 0, 255, 0], rgb8, l8())?, [255, 182]);
+
+        let a8 = Format::Uncompressed {
+            pitch: 1,
+            color_format: ColorFormat::None,
+            alpha_format: AlphaFormat::Straight { alpha_mask: 0xFF },
+        };
+        assert_eq!(repack(&[7], a8, Format::rgba8(false))?, [0, 0, 0, 7]);
+        assert_eq!(repack(&[7], l8(), a8)?, [255]);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let rgba8 = Format::rgba8(false);
+        assert!(repack(&[0; 8], Format::BC1 { srgb: false }, rgba8).is_err());
+        assert!(repack(&[0; 6], rgba8, l8()).is_err());
+        assert!(repack(&[0; 8], uncompressed(8, [0xFF, 0, 0, 0]), rgba8).is_err());
+    }
+
+    #[test]
+    fn swap_bytes() -> Result<()> {
+        let mut buffer = [1, 2, 3, 4, 5, 6, 7, 8];
+        byteswap(&mut buffer, 2)?;
+        assert_eq!(buffer, [2, 1, 4, 3, 6, 5, 8, 7]);
+        byteswap(&mut buffer, 4)?;
+        assert_eq!(buffer, [3, 4, 1, 2, 7, 8, 5, 6]);
+        byteswap(&mut buffer, 1)?;
+        assert_eq!(buffer, [3, 4, 1, 2, 7, 8, 5, 6]);
+
+        assert!(byteswap(&mut buffer, 3).is_err());
+        assert!(byteswap(&mut buffer[..6], 4).is_err());
+
+        let float = 1.5f32.to_be_bytes();
+        let surface = Surface::new(Dimensions::try_from([1, 1])?, float).byteswap(4)?;
+        assert_eq!(f32::from_le_bytes(surface.buffer[..].try_into()?), 1.5);
+        Ok(())
+    }
+
+    #[test]
+    fn swap_widths() {
+        assert_eq!(Format::rgba8(false).swap_width(), 4);
+        assert_eq!(uncompressed(2, [0xF800, 0x07E0, 0x001F, 0]).swap_width(), 2);
+        assert_eq!(uncompressed(3, [0xFF, 0xFF00, 0xFF0000, 0]).swap_width(), 1);
+        assert_eq!(Format::BC1 { srgb: false }.swap_width(), 1);
+        let rgba16f = Format::Channels {
+            channel_type: crate::format::ChannelType::Float16,
+            channels: 4,
+        };
+        assert_eq!(rgba16f.swap_width(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn texture() -> Result<()> {
+        use crate::format::Quality;
+        use crate::shape::TextureShape;
+
+        let image = image::RgbaImage::from_fn(8, 8, |x, y| image::Rgba([x as u8, y as u8, 9, 200]));
+        let texture =
+            Texture::from_image(&image.into(), Format::rgba8(false), true, Quality::Normal)?;
+        let bgrx8 = uncompressed(4, [0xFF0000, 0xFF00, 0xFF, 0]);
+        let repacked = texture.repack(bgrx8)?;
+        assert_eq!(repacked.format, bgrx8);
+        assert_eq!(repacked.iter_mips().count(), 4);
+        assert_eq!(repacked.primary().dimensions, Dimensions::try_from([8, 8])?);
+        assert_eq!(&repacked.primary().buffer[4..8], [9, 0, 1, 0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,383 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Converting between cubemap textures and single images containing all six faces,
+//! laid out as a cross or a strip, and assembling cubemaps from individual faces
+
+use image::{imageops, DynamicImage, GenericImageView};
+use itertools::Itertools;
+use strum::VariantArray;
+
+use crate::error::{TextureError, TextureResult};
+use crate::format::{Format, Quality};
+use crate::shape::{CubeFace, TextureShape};
+use crate::texture::{Surface, Surfaces, Texture};
+
+/// A way of arranging the six faces of a cubemap in a single image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, VariantArray)]
+pub enum CubeLayout {
+    /// A 4x3 grid of faces. The middle row is -X, +Z, +X, -Z, with +Y above and -Y below +Z
+    HorizontalCross,
+    /// A 3x4 grid of faces. Like [`Self::HorizontalCross`], but -Z is below -Y and rotated 180°
+    VerticalCross,
+    /// A 6x1 row of faces, in the order +X, -X, +Y, -Y, +Z, -Z
+    HorizontalStrip,
+    /// A 1x6 column of faces, in the order +X, -X, +Y, -Y, +Z, -Z
+    VerticalStrip,
+}
+
+impl CubeLayout {
+    /// The number of faces across and down the layout
+    pub fn grid(self) -> (u32, u32) {
+        match self {
+            CubeLayout::HorizontalCross => (4, 3),
+            CubeLayout::VerticalCross => (3, 4),
+            CubeLayout::HorizontalStrip => (6, 1),
+            CubeLayout::VerticalStrip => (1, 6),
+        }
+    }
+
+    /// The column and row of `face` in the layout, and whether it is rotated 180°
+    pub fn position(self, face: CubeFace) -> (u32, u32, bool) {
+        use CubeFace::*;
+        match self {
+            CubeLayout::HorizontalCross | CubeLayout::VerticalCross => match face {
+                PositiveY => (1, 0, false),
+                NegativeX => (0, 1, false),
+                PositiveZ => (1, 1, false),
+                PositiveX => (2, 1, false),
+                NegativeY => (1, 2, false),
+                NegativeZ => match self {
+                    CubeLayout::HorizontalCross => (3, 1, false),
+                    _ => (1, 3, true),
+                },
+            },
+            CubeLayout::HorizontalStrip => (face as u32, 0, false),
+            CubeLayout::VerticalStrip => (0, face as u32, false),
+        }
+    }
+
+    /// Guess the layout of an image from its aspect ratio, if it matches exactly one layout
+    pub fn detect(width: u32, height: u32) -> Option<Self> {
+        Self::VARIANTS
+            .iter()
+            .copied()
+            .find(|l| l.face_size(width, height).is_ok())
+    }
+
+    /// The size of each face in an image of the given size,
+    /// or an error if the image can't be evenly divided into square faces
+    fn face_size(self, width: u32, height: u32) -> TextureResult<u32> {
+        let (columns, rows) = self.grid();
+        let divisible = width.is_multiple_of(columns) && height.is_multiple_of(rows);
+        match divisible && width / columns == height / rows {
+            true if width > 0 => Ok(width / columns),
+            _ => Err(TextureError::Other(format!(
+                "{width}x{height} image cannot be divided into square faces as a {self:?}"
+            ))),
+        }
+    }
+}
+
+/// Split an image into the six faces of a cubemap, in the order of [`CubeFace::VARIANTS`]
+pub fn split_cube_image(
+    image: &DynamicImage,
+    layout: CubeLayout,
+) -> TextureResult<Vec<(CubeFace, DynamicImage)>> {
+    let size = layout.face_size(image.width(), image.height())?;
+    Ok(CubeFace::VARIANTS
+        .iter()
+        .map(|&face| {
+            let (column, row, rotated) = layout.position(face);
+            let face_image = image.view(column * size, row * size, size, size).to_image();
+            let face_image = DynamicImage::from(face_image);
+            (
+                face,
+                if rotated {
+                    face_image.rotate180()
+                } else {
+                    face_image
+                },
+            )
+        })
+        .collect())
+}
+
+/// Combine the six faces of a cubemap into a single image. Unused cells of cross layouts are transparent.
+/// Returns an error if a face is missing or duplicated, or the faces are not all the same square size
+pub fn join_cube_image(
+    faces: &[(CubeFace, DynamicImage)],
+    layout: CubeLayout,
+) -> TextureResult<DynamicImage> {
+    let sorted = faces.iter().sorted_by_key(|(f, _)| *f).collect_vec();
+    if sorted
+        .iter()
+        .map(|(f, _)| *f)
+        .ne(CubeFace::VARIANTS.iter().copied())
+    {
+        return Err(TextureError::Other(
+            "A cube image needs exactly one of each face".to_string(),
+        ));
+    }
+    let size = sorted[0].1.width();
+    if sorted
+        .iter()
+        .any(|(_, i)| i.width() != size || i.height() != size)
+    {
+        return Err(TextureError::Other(
+            "Cubemap faces must all be the same square size".to_string(),
+        ));
+    }
+
+    let (columns, rows) = layout.grid();
+    let mut image = image::RgbaImage::new(columns * size, rows * size);
+    for (face, face_image) in sorted {
+        let (column, row, rotated) = layout.position(*face);
+        let face_image = match rotated {
+            true => face_image.rotate180(),
+            false => face_image.clone(),
+        };
+        imageops::replace(
+            &mut image,
+            &face_image.into_rgba8(),
+            (column * size) as i64,
+            (row * size) as i64,
+        );
+    }
+    Ok(image.into())
+}
+
+impl Texture {
+    /// Assemble a cubemap from six surfaces already encoded in `format`, one for each face.
+    /// Returns an error if a face is repeated, or the faces are not square and all the same size
+    pub fn from_cube_faces(faces: [(CubeFace, Surface); 6], format: Format) -> TextureResult<Self> {
+        Self::from_partial_cube_faces(faces, format)
+    }
+
+    /// Assemble a cubemap from any non-empty subset of its faces, as surfaces already encoded in `format`.
+    /// Only legacy DDS headers can store cubemaps with missing faces, so writing one with a DX10 header fails.
+    /// Returns an error if a face is repeated, or the faces are not square and all the same size
+    pub fn from_partial_cube_faces(
+        faces: impl IntoIterator<Item = (CubeFace, Surface)>,
+        format: Format,
+    ) -> TextureResult<Self> {
+        let faces = faces
+            .into_iter()
+            .map(|(face, surface)| {
+                let dimensions = surface.dimensions;
+                if dimensions.width() != dimensions.height() || dimensions.depth() != 1 {
+                    return Err(TextureError::Other(format!(
+                        "Cubemap faces must be square, but {face:?} is {dimensions:?}"
+                    )));
+                }
+                Ok((face, Surfaces::from_surface(surface)))
+            })
+            .collect::<TextureResult<Vec<_>>>()?;
+        Self::new(format, Surfaces::try_from_faces(faces)?)
+    }
+
+    /// Encode a cube image into a new cubemap texture in `format`. If `layout` is [`None`], it is
+    /// detected from the image's aspect ratio. If `mips` is set, each face gets a full mip chain
+    pub fn from_cube_image(
+        image: &DynamicImage,
+        layout: Option<CubeLayout>,
+        format: Format,
+        mips: bool,
+        quality: Quality,
+    ) -> TextureResult<Self> {
+        let layout = layout
+            .or_else(|| CubeLayout::detect(image.width(), image.height()))
+            .ok_or_else(|| {
+                TextureError::Other(format!(
+                    "{}x{} image does not match any cube layout",
+                    image.width(),
+                    image.height()
+                ))
+            })?;
+        let faces = split_cube_image(image, layout)?
+            .into_iter()
+            .map(|(face, image)| {
+                let texture = Texture::from_image(&image, format, mips, quality)?;
+                Ok((face, texture.into_surfaces()))
+            })
+            .collect::<TextureResult<Vec<_>>>()?;
+        Self::new(format, Surfaces::try_from_faces(faces)?)
+    }
+
+    /// Decode one mip of a cubemap texture into a single cube image.
+    /// Array textures use their first layer.
+    /// Returns an error if the texture is not a complete cubemap, or its format can't be decoded
+    pub fn to_cube_image(&self, layout: CubeLayout, mip: usize) -> TextureResult<DynamicImage> {
+        if self.faces().map_or(0, |f| f.len()) != CubeFace::VARIANTS.len() {
+            return Err(TextureError::Capability(
+                "Only complete cubemaps can be converted to cube images".to_string(),
+            ));
+        }
+        let decoder = self.format.decoder()?;
+        let faces = self
+            .slice()
+            .iter()
+            .filter(|s| s.mip.unwrap_or(0) == mip && s.layer.unwrap_or(0) == 0)
+            .map(|s| {
+                let image =
+                    decoder.decode_buffer(s.surface.buffer.clone(), s.surface.dimensions)?;
+                Ok((s.face.unwrap(), image))
+            })
+            .collect::<TextureResult<Vec<_>>>()?;
+        join_cube_image(&faces, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use image::{Rgba, RgbaImage};
+
+    use crate::dimensions::{Dimensioned, Dimensions};
+
+    use super::*;
+
+    fn rgba8() -> Format {
+        Format::Uncompressed {
+            pitch: 4,
+            color_format: crate::format::ColorFormat::RGB {
+                r_mask: 0xFF,
+                g_mask: 0xFF00,
+                b_mask: 0xFF0000,
+                srgb: false,
+            },
+            alpha_format: crate::format::AlphaFormat::Straight {
+                alpha_mask: 0xFF000000,
+            },
+        }
+    }
+
+    /// An image of six 4x4 faces, where each pixel's red channel is its face index
+    /// and green and blue are its position within the face
+    fn cube_image(layout: CubeLayout) -> Result<DynamicImage> {
+        let faces = CubeFace::VARIANTS
+            .iter()
+            .map(|&f| {
+                let image =
+                    RgbaImage::from_fn(4, 4, |x, y| Rgba([f as u8 * 40, x as u8, y as u8, 255]));
+                (f, image.into())
+            })
+            .collect_vec();
+        Ok(join_cube_image(&faces, layout)?)
+    }
+
+    #[test]
+    fn layouts() -> Result<()> {
+        for &layout in CubeLayout::VARIANTS {
+            let image = cube_image(layout)?;
+            let (columns, rows) = layout.grid();
+            assert_eq!(image.dimensions(), (columns * 4, rows * 4));
+            assert_eq!(
+                CubeLayout::detect(image.width(), image.height()),
+                Some(layout)
+            );
+
+            for (face, face_image) in split_cube_image(&image, layout)? {
+                let face_image = face_image.into_rgba8();
+                assert_eq!(face_image.get_pixel(0, 0).0, [face as u8 * 40, 0, 0, 255]);
+                assert_eq!(face_image.get_pixel(3, 1).0, [face as u8 * 40, 3, 1, 255]);
+            }
+        }
+
+        // the -Z face of a vertical cross is upside down
+        let cross = cube_image(CubeLayout::VerticalCross)?.into_rgba8();
+        assert_eq!(cross.get_pixel(4, 12).0, [5 * 40, 3, 3, 255]);
+        assert_eq!(cross.get_pixel(0, 0).0, [0; 4], "unused cells are empty");
+        Ok(())
+    }
+
+    #[test]
+    fn texture_roundtrip() -> Result<()> {
+        let image = cube_image(CubeLayout::HorizontalCross)?;
+        let texture = Texture::from_cube_image(&image, None, rgba8(), true, Quality::Normal)?;
+        assert_eq!(texture.faces().map(|f| f.len()), Some(6));
+        assert_eq!(texture.mips(), Some(3));
+        assert_eq!(texture.dimensions(), Dimensions::try_from([4, 4])?);
+
+        let strip = texture.to_cube_image(CubeLayout::HorizontalStrip, 0)?;
+        assert_eq!(
+            strip.into_rgba8(),
+            cube_image(CubeLayout::HorizontalStrip)?.into_rgba8()
+        );
+        assert_eq!(
+            texture
+                .to_cube_image(CubeLayout::VerticalCross, 2)?
+                .dimensions(),
+            (3, 4)
+        );
+
+        let flat = Texture::from_image(&image, rgba8(), false, Quality::Normal)?;
+        assert!(flat.to_cube_image(CubeLayout::HorizontalCross, 0).is_err());
+        assert!(Texture::from_cube_image(
+            &RgbaImage::new(5, 3).into(),
+            None,
+            rgba8(),
+            false,
+            Quality::Normal
+        )
+        .is_err());
+        Ok(())
+    }
+
+    /// A 4x4 surface filled with the face's index
+    fn face_surface(face: CubeFace) -> Result<Surface> {
+        Ok(Surface::new(
+            Dimensions::try_from([4, 4])?,
+            [face as u8; 4 * 4 * 4],
+        ))
+    }
+
+    #[test]
+    fn from_faces() -> Result<()> {
+        use std::io::Cursor;
+
+        use crate::container::ContainerHeader;
+        use crate::dds::{DDSHeader, DDSHeaderArgs, DDSHeaderMode};
+
+        let faces = std::array::from_fn(|i| {
+            let face = CubeFace::VARIANTS[i];
+            (face, face_surface(face).unwrap())
+        });
+        let cube = Texture::from_cube_faces(faces, rgba8())?;
+        let mut file = Cursor::new(Vec::new());
+        DDSHeader::write_texture(&mut file, &cube)?;
+        file.set_position(0);
+        let read = DDSHeader::read_texture(&mut file)?;
+        assert_eq!(read.faces(), Some(CubeFace::VARIANTS.to_vec()));
+        for (face, surface) in read.iter_faces() {
+            assert_eq!(surface.primary().buffer[0], face.unwrap() as u8);
+        }
+
+        // partial cubemaps can only be written with legacy headers
+        let partial =
+            [CubeFace::PositiveY, CubeFace::NegativeZ].map(|f| (f, face_surface(f).unwrap()));
+        let partial = Texture::from_partial_cube_faces(partial, rgba8())?;
+        let mut file = Cursor::new(Vec::new());
+        DDSHeader::write_texture(&mut file, &partial)?;
+        file.set_position(0);
+        let read = DDSHeader::read_texture(&mut file)?;
+        assert_eq!(
+            read.faces(),
+            Some(vec![CubeFace::PositiveY, CubeFace::NegativeZ])
+        );
+        let dx10 = DDSHeaderArgs::default().mode(DDSHeaderMode::ForceDX10);
+        assert!(
+            DDSHeader::write_texture_args(&mut Cursor::new(Vec::new()), &partial, &dx10).is_err()
+        );
+
+        let repeated = [CubeFace::PositiveX; 2].map(|f| (f, face_surface(f).unwrap()));
+        assert!(Texture::from_partial_cube_faces(repeated, rgba8()).is_err());
+        let rectangle = Surface::new(Dimensions::try_from([4, 2])?, [0; 4 * 2 * 4]);
+        assert!(
+            Texture::from_partial_cube_faces([(CubeFace::PositiveX, rectangle)], rgba8()).is_err()
+        );
+        assert!(Texture::from_partial_cube_faces([], rgba8()).is_err());
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
 use crate::dimensions::{DimensionError, Dimensions};
 use crate::error::{TextureError, TextureResult};
-use crate::format::Format;
+use crate::format::{AlphaFormat, ChannelType, ColorFormat, Format};
 use binrw::{BinRead, BinWrite};
 
+/// A format from a DX10 header, using the values of Direct3D's `DXGI_FORMAT` enum.
+/// Variants are named after the `DXGI_FORMAT` constants they represent
+#[allow(missing_docs)]
 #[derive(BinRead, BinWrite, Debug, Copy, Clone, PartialEq, Eq)]
-#[brw(little, repr = u32)]
+#[brw(repr = u32)]
 pub enum DXGIFormat {
     Unknown = 0,
     R32G32B32A32 = 1,
@@ -125,27 +128,292 @@ pub enum DXGIFormat {
     P208 = 130,
     V208 = 131,
     V408 = 132,
+    ASTC4X4 = 133,
+    ASTC4X4UNorm = 134,
+    ASTC4X4UNormSRGB = 135,
+    ASTC5X4 = 137,
+    ASTC5X4UNorm = 138,
+    ASTC5X4UNormSRGB = 139,
+    ASTC5X5 = 141,
+    ASTC5X5UNorm = 142,
+    ASTC5X5UNormSRGB = 143,
+    ASTC6X5 = 145,
+    ASTC6X5UNorm = 146,
+    ASTC6X5UNormSRGB = 147,
+    ASTC6X6 = 149,
+    ASTC6X6UNorm = 150,
+    ASTC6X6UNormSRGB = 151,
+    ASTC8X5 = 153,
+    ASTC8X5UNorm = 154,
+    ASTC8X5UNormSRGB = 155,
+    ASTC8X6 = 157,
+    ASTC8X6UNorm = 158,
+    ASTC8X6UNormSRGB = 159,
+    ASTC8X8 = 161,
+    ASTC8X8UNorm = 162,
+    ASTC8X8UNormSRGB = 163,
+    ASTC10X5 = 165,
+    ASTC10X5UNorm = 166,
+    ASTC10X5UNormSRGB = 167,
+    ASTC10X6 = 169,
+    ASTC10X6UNorm = 170,
+    ASTC10X6UNormSRGB = 171,
+    ASTC10X8 = 173,
+    ASTC10X8UNorm = 174,
+    ASTC10X8UNormSRGB = 175,
+    ASTC10X10 = 177,
+    ASTC10X10UNorm = 178,
+    ASTC10X10UNormSRGB = 179,
+    ASTC12X10 = 181,
+    ASTC12X10UNorm = 182,
+    ASTC12X10UNormSRGB = 183,
+    ASTC12X12 = 185,
+    ASTC12X12UNorm = 186,
+    ASTC12X12UNormSRGB = 187,
 }
 
+/// Uncompressed DXGI formats that can be described by channel bitmasks, with the size of each pixel in bytes,
+/// the red, green, blue, and alpha masks, and if the format is sRGB. A mask of 0 means the channel is missing
+#[rustfmt::skip]
+const UNCOMPRESSED: [(DXGIFormat, usize, [u32; 4], bool); 15] = {
+    use DXGIFormat::*;
+    [
+        (R8G8B8A8UNorm,     4, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], false),
+        (R8G8B8A8UNormSRGB, 4, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], true),
+        (B8G8R8A8UNorm,     4, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], false),
+        (B8G8R8A8UNormSRGB, 4, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], true),
+        (B8G8R8X8UNorm,     4, [0xFF0000, 0xFF00, 0xFF, 0], false),
+        (B8G8R8X8UNormSRGB, 4, [0xFF0000, 0xFF00, 0xFF, 0], true),
+        (R10G10B10A2UNorm,  4, [0x3FF, 0xFFC00, 0x3FF00000, 0xC0000000], false),
+        (R16G16UNorm,       4, [0xFFFF, 0xFFFF0000, 0, 0], false),
+        (R8G8UNorm,         2, [0xFF, 0xFF00, 0, 0], false),
+        (R16UNorm,          2, [0xFFFF, 0, 0, 0], false),
+        (B5G6R5UNorm,       2, [0xF800, 0x07E0, 0x001F, 0], false),
+        (B5G5R5A1UNorm,     2, [0x7C00, 0x03E0, 0x001F, 0x8000], false),
+        (B4G4R4A4UNorm,     2, [0x0F00, 0x00F0, 0x000F, 0xF000], false),
+        (R8UNorm,           1, [0xFF, 0, 0, 0], false),
+        (A8UNorm,           1, [0, 0, 0, 0xFF], false),
+    ]
+};
+
+/// DXGI formats with channels of a single type that can't be described by bitmasks, with the type and number of channels
+#[rustfmt::skip]
+const CHANNELS: [(DXGIFormat, ChannelType, usize); 14] = {
+    use ChannelType::*;
+    use DXGIFormat::*;
+    [
+        (R32G32B32A32Float, Float32, 4),
+        (R32G32B32Float,    Float32, 3),
+        (R32G32Float,       Float32, 2),
+        (R32Float,          Float32, 1),
+        (R16G16B16A16Float, Float16, 4),
+        (R16G16Float,       Float16, 2),
+        (R16Float,          Float16, 1),
+        (R16G16B16A16UNorm, UNorm16, 4),
+        (R16G16B16A16SNorm, SNorm16, 4),
+        (R16G16SNorm,       SNorm16, 2),
+        (R16SNorm,          SNorm16, 1),
+        (R8G8B8A8SNorm,     SNorm8,  4),
+        (R8G8SNorm,         SNorm8,  2),
+        (R8SNorm,           SNorm8,  1),
+    ]
+};
+
+/// ASTC DXGI formats, as the typeless, UNorm, and sRGB formats for each 2D block footprint
+#[rustfmt::skip]
+const ASTC: [(DXGIFormat, DXGIFormat, DXGIFormat, [u32; 2]); 14] = {
+    use DXGIFormat::*;
+    [
+        (ASTC4X4, ASTC4X4UNorm, ASTC4X4UNormSRGB, [4, 4]),
+        (ASTC5X4, ASTC5X4UNorm, ASTC5X4UNormSRGB, [5, 4]),
+        (ASTC5X5, ASTC5X5UNorm, ASTC5X5UNormSRGB, [5, 5]),
+        (ASTC6X5, ASTC6X5UNorm, ASTC6X5UNormSRGB, [6, 5]),
+        (ASTC6X6, ASTC6X6UNorm, ASTC6X6UNormSRGB, [6, 6]),
+        (ASTC8X5, ASTC8X5UNorm, ASTC8X5UNormSRGB, [8, 5]),
+        (ASTC8X6, ASTC8X6UNorm, ASTC8X6UNormSRGB, [8, 6]),
+        (ASTC8X8, ASTC8X8UNorm, ASTC8X8UNormSRGB, [8, 8]),
+        (ASTC10X5, ASTC10X5UNorm, ASTC10X5UNormSRGB, [10, 5]),
+        (ASTC10X6, ASTC10X6UNorm, ASTC10X6UNormSRGB, [10, 6]),
+        (ASTC10X8, ASTC10X8UNorm, ASTC10X8UNormSRGB, [10, 8]),
+        (ASTC10X10, ASTC10X10UNorm, ASTC10X10UNormSRGB, [10, 10]),
+        (ASTC12X10, ASTC12X10UNorm, ASTC12X10UNormSRGB, [12, 10]),
+        (ASTC12X12, ASTC12X12UNorm, ASTC12X12UNormSRGB, [12, 12]),
+    ]
+};
+
+/// Get the format for a DXGI format. For uncompressed formats with an alpha channel,
+/// `alpha_mode` chooses the [`AlphaFormat`], with [`AlphaMode::Unknown`] read as straight alpha
 pub(crate) fn try_into_format(
     dxgi_format: &DXGIFormat,
     alpha_mode: &AlphaMode,
 ) -> TextureResult<Format> {
-    // todo: DX10 header formats are not currently supported
-    Err(TextureError::Format(
-        "DX10 header formats are not currently supported".into(),
-    ))
+    use DXGIFormat::*;
+    // typeless formats are read as their UNorm equivalents
+    let dxgi_format = match dxgi_format {
+        R8G8B8A8 => &R8G8B8A8UNorm,
+        B8G8R8A8 => &B8G8R8A8UNorm,
+        B8G8R8X8 => &B8G8R8X8UNorm,
+        R10G10B10A2 => &R10G10B10A2UNorm,
+        R16G16B16A16 => &R16G16B16A16UNorm,
+        R16G16 => &R16G16UNorm,
+        R8G8 => &R8G8UNorm,
+        R16 => &R16UNorm,
+        R8 => &R8UNorm,
+        f => f,
+    };
+    if let Some((.., srgb_format, dims)) =
+        (ASTC.iter()).find(|(t, u, s, _)| [t, u, s].contains(&dxgi_format))
+    {
+        return Ok(Format::ASTC {
+            block_dims: Dimensions::try_from(*dims)?,
+            srgb: dxgi_format == srgb_format,
+        });
+    }
+    match dxgi_format {
+        BC1 | BC1UNorm => Ok(Format::BC1 { srgb: false }),
+        BC1UNormSRGB => Ok(Format::BC1 { srgb: true }),
+        BC2 | BC2UNorm => Ok(Format::BC2 { srgb: false }),
+        BC2UNormSRGB => Ok(Format::BC2 { srgb: true }),
+        BC3 | BC3UNorm => Ok(Format::BC3 { srgb: false }),
+        BC3UNormSRGB => Ok(Format::BC3 { srgb: true }),
+        BC4 | BC4UNorm => Ok(Format::BC4 { signed: false }),
+        BC4SNorm => Ok(Format::BC4 { signed: true }),
+        BC5 | BC5UNorm => Ok(Format::BC5 { signed: false }),
+        BC5SNorm => Ok(Format::BC5 { signed: true }),
+        BC7 | BC7UNorm => Ok(Format::BC7 { srgb: false }),
+        BC7UNormSRGB => Ok(Format::BC7 { srgb: true }),
+        R11G11B10Float => Err(TextureError::Format(format!(
+            "DX10 header format {dxgi_format:?} packs floats of different sizes, which is not currently supported"
+        ))),
+        _ => {
+            if let Some((_, channel_type, channels)) =
+                CHANNELS.iter().find(|(f, ..)| f == dxgi_format)
+            {
+                return Ok(Format::Channels {
+                    channel_type: *channel_type,
+                    channels: *channels,
+                });
+            }
+            let (_, pitch, [r_mask, g_mask, b_mask, alpha_mask], srgb) = UNCOMPRESSED
+                .iter()
+                .find(|(f, ..)| f == dxgi_format)
+                .ok_or_else(|| {
+                    TextureError::Format(format!(
+                        "DX10 header format {dxgi_format:?} is not currently supported"
+                    ))
+                })?;
+            Ok(Format::Uncompressed {
+                pitch: *pitch,
+                color_format: match [r_mask, g_mask, b_mask] {
+                    [0, 0, 0] => ColorFormat::None,
+                    _ => ColorFormat::RGB {
+                        r_mask: *r_mask,
+                        g_mask: *g_mask,
+                        b_mask: *b_mask,
+                        srgb: *srgb,
+                    },
+                },
+                alpha_format: match (*alpha_mask, alpha_mode) {
+                    (0, _) | (_, AlphaMode::Opaque) => AlphaFormat::Opaque,
+                    (alpha_mask, AlphaMode::Unknown | AlphaMode::Straight) => {
+                        AlphaFormat::Straight { alpha_mask }
+                    }
+                    (alpha_mask, AlphaMode::Premultiplied) => {
+                        AlphaFormat::Premultiplied { alpha_mask }
+                    }
+                    (alpha_mask, AlphaMode::Custom) => AlphaFormat::Custom { alpha_mask },
+                },
+            })
+        }
+    }
 }
 
+/// Get the DXGI format and alpha mode for a format. Block compressed formats are written with [`AlphaMode::Unknown`],
+/// and uncompressed formats with the alpha mode matching their [`AlphaFormat`]
 pub(crate) fn try_from_format(format: Format) -> TextureResult<(DXGIFormat, AlphaMode)> {
-    // todo: DX10 header formats are not currently supported
-    Err(TextureError::Format(
-        "DX10 header formats are not currently supported".into(),
-    ))
+    use DXGIFormat::*;
+    let unsupported =
+        || TextureError::Format(format!("{format:?} cannot be stored in a DX10 header"));
+    let dxgi_format = match format {
+        Format::BC1 { srgb: false } => BC1UNorm,
+        Format::BC1 { srgb: true } => BC1UNormSRGB,
+        Format::BC2 { srgb: false } => BC2UNorm,
+        Format::BC2 { srgb: true } => BC2UNormSRGB,
+        Format::BC3 { srgb: false } => BC3UNorm,
+        Format::BC3 { srgb: true } => BC3UNormSRGB,
+        Format::BC4 { signed: false } => BC4UNorm,
+        Format::BC4 { signed: true } => BC4SNorm,
+        Format::BC5 { signed: false } => BC5UNorm,
+        Format::BC5 { signed: true } => BC5SNorm,
+        Format::BC7 { srgb: false } => BC7UNorm,
+        Format::BC7 { srgb: true } => BC7UNormSRGB,
+        Format::Uncompressed {
+            pitch,
+            color_format,
+            alpha_format,
+        } => {
+            let ([r_mask, g_mask, b_mask], srgb) = match color_format {
+                ColorFormat::RGB {
+                    r_mask,
+                    g_mask,
+                    b_mask,
+                    srgb,
+                } => ([r_mask, g_mask, b_mask], srgb),
+                ColorFormat::None => ([0; 3], false),
+                _ => return Err(unsupported()),
+            };
+            let (alpha_mask, alpha_mode) = match alpha_format {
+                AlphaFormat::Custom { alpha_mask } => (alpha_mask, AlphaMode::Custom),
+                AlphaFormat::Straight { alpha_mask } => (alpha_mask, AlphaMode::Straight),
+                AlphaFormat::Premultiplied { alpha_mask } => (alpha_mask, AlphaMode::Premultiplied),
+                AlphaFormat::Opaque => (0, AlphaMode::Opaque),
+            };
+            let color = |(_, p, m, s): &&(DXGIFormat, usize, [u32; 4], bool)| {
+                (*p, [m[0], m[1], m[2]], *s) == (pitch, [r_mask, g_mask, b_mask], srgb)
+            };
+            let entry = UNCOMPRESSED
+                .iter()
+                .filter(color)
+                .find(|(.., m, _)| m[3] == alpha_mask)
+                // opaque textures can also use a format with alpha, since the alpha mode says to ignore it
+                .or_else(|| match alpha_format {
+                    AlphaFormat::Opaque => UNCOMPRESSED.iter().find(color),
+                    _ => None,
+                })
+                .ok_or_else(unsupported)?;
+            return Ok((entry.0, alpha_mode));
+        }
+        Format::ASTC { block_dims, srgb } => {
+            let (_, unorm, srgb_format, _) = ASTC
+                .iter()
+                .find(|(.., dims)| Dimensions::try_from(*dims).ok() == Some(block_dims))
+                .ok_or_else(unsupported)?;
+            match srgb {
+                true => *srgb_format,
+                false => *unorm,
+            }
+        }
+        Format::Channels {
+            channel_type,
+            channels,
+        } => {
+            let (dxgi_format, ..) = CHANNELS
+                .iter()
+                .find(|(_, t, c)| (*t, *c) == (channel_type, channels))
+                .ok_or_else(unsupported)?;
+            let alpha_mode = match channels {
+                4 => AlphaMode::Straight,
+                _ => AlphaMode::Opaque,
+            };
+            return Ok((*dxgi_format, alpha_mode));
+        }
+        _ => return Err(unsupported()),
+    };
+    Ok((dxgi_format, AlphaMode::Unknown))
 }
 
 #[derive(BinRead, BinWrite, Debug, Copy, Clone, PartialEq, Eq)]
-#[brw(little, repr = u32)]
+#[brw(repr = u32)]
 pub(crate) enum Dimensionality {
     Texture1D = 2,
     Texture2D = 3,
@@ -177,13 +445,19 @@ impl Dimensionality {
     }
 }
 
+/// How the alpha channel of a DX10 header's format is interpreted
 #[derive(BinRead, BinWrite, Debug, Copy, Clone, PartialEq, Eq)]
-#[brw(little, repr = u32)]
+#[brw(repr = u32)]
 pub enum AlphaMode {
+    /// Alpha interpretation is unknown, and assumed to be straight
     Unknown = 0,
+    /// Straight alpha
     Straight = 1,
+    /// Premultiplied alpha
     Premultiplied = 2,
+    /// Alpha is all fully opaque
     Opaque = 3,
+    /// The alpha channel is a 4th channel that does not represent transparency
     Custom = 4,
 }
 
@@ -0,0 +1,515 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Generated DDS fixtures, so tests don't depend on files made by external tools.
+//!
+//! The cubemaps built here mimic `tests/images/dds/cubemap.dds`, made with nvassemble:
+//! each face is filled with its own background color, with magenta in the first and last pixel.
+//! Pixels are packed into bytes by hand for each [`MaskLayout`] instead of with the uncompressed codec,
+//! so the reader and decoder are checked against an independent interpretation of the masks.
+//!
+//! [`TexconvFixture`] files are laid out byte by byte the way DirectXTex's texconv writes them, covering legacy
+//! and DX10 headers with mips, arrays, cubemaps, and volumes. [`texconv_metadata`] reads headers following the
+//! rules of DirectXTex's `DecodeDDSHeader`, to check that files written by this crate would load in texconv
+
+use std::io::Cursor;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use strum::VariantArray;
+
+use crate::container::ContainerHeader;
+use crate::dimensions::Dimensions;
+use crate::format::{AlphaFormat, ColorFormat, Format};
+use crate::shape::{CubeFace, TextureShape, TextureShapeNode};
+use crate::texture::{Surface, Surfaces, Texture};
+
+use super::DDSHeader;
+
+/// The color of the first and last pixel of every face
+pub const MAGENTA: [u8; 4] = [0xFF, 0x00, 0xFF, 0xFF];
+
+/// The background color of each face of the nvassemble cubemap, as RGBA
+pub fn face_color(face: CubeFace) -> [u8; 4] {
+    match face {
+        CubeFace::PositiveX => [0xFF, 0xBC, 0xBC, 0xFF], // red
+        CubeFace::NegativeX => [0xBC, 0xFF, 0xFF, 0xFF], // cyan
+        CubeFace::PositiveY => [0xBC, 0xFF, 0xBC, 0xFF], // green
+        CubeFace::NegativeY => [0xFF, 0xBC, 0xFF, 0xFF], // magenta
+        CubeFace::PositiveZ => [0xBC, 0xBC, 0xFF, 0xFF], // blue
+        CubeFace::NegativeZ => [0xFF, 0xFF, 0xBC, 0xFF], // yellow
+    }
+}
+
+/// The order of the channels of an uncompressed pixel in memory
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MaskLayout {
+    /// 24-bit RGB, as written by nvassemble
+    Rgb,
+    /// 24-bit BGR
+    Bgr,
+    /// 32-bit RGBA
+    Rgba,
+    /// 32-bit BGRA, the native layout of D3D9 A8R8G8B8
+    Bgra,
+}
+
+impl MaskLayout {
+    /// Parse a layout from its lowercase name, for parameterized tests
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "rgb" => MaskLayout::Rgb,
+            "bgr" => MaskLayout::Bgr,
+            "rgba" => MaskLayout::Rgba,
+            "bgra" => MaskLayout::Bgra,
+            _ => unreachable!("unknown mask layout {name}"),
+        }
+    }
+
+    /// The index of the red, green, blue, and alpha channels in each pixel, if present
+    pub fn offsets(self) -> [Option<usize>; 4] {
+        match self {
+            MaskLayout::Rgb => [Some(0), Some(1), Some(2), None],
+            MaskLayout::Bgr => [Some(2), Some(1), Some(0), None],
+            MaskLayout::Rgba => [Some(0), Some(1), Some(2), Some(3)],
+            MaskLayout::Bgra => [Some(2), Some(1), Some(0), Some(3)],
+        }
+    }
+
+    /// The bitmasks of the red, green, blue, and alpha channels, as they should appear in a DDS header
+    pub fn masks(self) -> [u32; 4] {
+        self.offsets().map(|o| o.map_or(0, |o| 0xFF << (o * 8)))
+    }
+
+    /// The number of bytes in each pixel
+    pub fn pitch(self) -> usize {
+        self.offsets().iter().flatten().count()
+    }
+
+    /// The uncompressed format with this layout.
+    /// Alpha is [`AlphaFormat::Custom`], since a legacy DDS header can't say how alpha is used
+    pub fn format(self) -> Format {
+        let [r_mask, g_mask, b_mask, alpha_mask] = self.masks();
+        Format::Uncompressed {
+            pitch: self.pitch(),
+            color_format: ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                srgb: false,
+            },
+            alpha_format: match alpha_mask {
+                0 => AlphaFormat::Opaque,
+                alpha_mask => AlphaFormat::Custom { alpha_mask },
+            },
+        }
+    }
+
+    /// Pack an RGBA color into the bytes of a single pixel
+    pub fn pack(self, color: [u8; 4]) -> Vec<u8> {
+        let mut pixel = vec![0u8; self.pitch()];
+        for (c, offset) in self.offsets().into_iter().enumerate() {
+            if let Some(offset) = offset {
+                pixel[offset] = color[c];
+            }
+        }
+        pixel
+    }
+}
+
+/// Build a cubemap of `size`x`size` faces in the style of the nvassemble cubemap, packed with `layout`
+pub fn cubemap(layout: MaskLayout, size: u32) -> Result<Texture> {
+    let dimensions = Dimensions::try_from([size, size])?;
+    let pixels = (size * size) as usize;
+    let faces = CubeFace::VARIANTS.iter().map(|&face| {
+        let buffer = (0..pixels)
+            .flat_map(|p| match p {
+                0 => layout.pack(MAGENTA),
+                p if p == pixels - 1 => layout.pack(MAGENTA),
+                _ => layout.pack(face_color(face)),
+            })
+            .collect::<Vec<u8>>();
+        (
+            face,
+            TextureShapeNode::from_surface(Surface::new(dimensions, buffer)),
+        )
+    });
+    Ok(Texture::new(
+        layout.format(),
+        Surfaces::try_from_faces(faces)?,
+    )?)
+}
+
+/// Write a generated cubemap to an in-memory DDS file
+pub fn cubemap_dds(layout: MaskLayout, size: u32) -> Result<Vec<u8>> {
+    let mut file = Cursor::new(Vec::new());
+    DDSHeader::write_texture(&mut file, &cubemap(layout, size)?)?;
+    Ok(file.into_inner())
+}
+
+/// Check that a decoded RGBA8 cubemap has the colors of the nvassemble cubemap,
+/// with the background sampled at pixel `background` of each face
+#[cfg(feature = "image")]
+pub fn check_cubemap_colors(decoded: &Texture, background: impl Fn(CubeFace) -> usize) {
+    assert_eq!(decoded.format, Format::rgba8(false));
+    for (face, surface) in decoded.iter_faces() {
+        let face = face.expect("texture should be a cubemap");
+        let buffer = &surface.primary().buffer;
+        let pixel = |p: usize| &buffer[p * 4..p * 4 + 4];
+
+        assert_eq!(pixel(0), MAGENTA, "first pixel of {face:?} is not magenta");
+        assert_eq!(
+            pixel(buffer.len() / 4 - 1),
+            MAGENTA,
+            "last pixel of {face:?} is not magenta"
+        );
+        assert_eq!(
+            pixel(background(face)),
+            face_color(face),
+            "background color incorrect for {face:?}"
+        );
+    }
+}
+
+/// `DDSD_HEIGHT`, set for every texture but 1D ones
+const DDSD_HEIGHT: u32 = 0x2;
+/// `DDSD_PITCH`, set with the row pitch of uncompressed textures
+const DDSD_PITCH: u32 = 0x8;
+/// `DDSD_MIPMAPCOUNT`, which texconv always sets, even for a single mip
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+/// `DDSD_LINEARSIZE`, set with the size of the top mip of compressed textures
+const DDSD_LINEARSIZE: u32 = 0x8_0000;
+/// `DDSD_DEPTH`, which marks a volume texture
+const DDSD_DEPTH: u32 = 0x80_0000;
+/// `DDSCAPS2_CUBEMAP` along with all 6 `DDSCAPS2_CUBEMAP_*` face bits
+const CUBEMAP_ALLFACES: u32 = 0xFE00;
+/// `DDSCAPS2_VOLUME`
+const CAPS2_VOLUME: u32 = 0x20_0000;
+/// `D3D11_RESOURCE_MISC_TEXTURECUBE`
+const MISC_TEXTURECUBE: u32 = 0x4;
+
+/// The pixel formats used by the texconv fixtures, named after their `DXGI_FORMAT` constants
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TexconvFormat {
+    /// `DXT1` in a legacy header
+    BC1UNorm,
+    /// Only representable in a DX10 header
+    BC7UNormSRGB,
+    /// `DDSPF_A8B8G8R8` in a legacy header
+    R8G8B8A8UNorm,
+}
+
+impl TexconvFormat {
+    /// The `DXGI_FORMAT` value of this format
+    fn dxgi(self) -> u32 {
+        match self {
+            TexconvFormat::BC1UNorm => 71,
+            TexconvFormat::BC7UNormSRGB => 99,
+            TexconvFormat::R8G8B8A8UNorm => 28,
+        }
+    }
+
+    /// The size in bytes of a single 2D slice, computed the way DirectXTex's `ComputePitch` does
+    fn slice_size(self, width: u32, height: u32) -> usize {
+        let blocks = |n: u32| n.div_ceil(4).max(1) as usize;
+        match self {
+            TexconvFormat::BC1UNorm => blocks(width) * blocks(height) * 8,
+            TexconvFormat::BC7UNormSRGB => blocks(width) * blocks(height) * 16,
+            TexconvFormat::R8G8B8A8UNorm => width as usize * height as usize * 4,
+        }
+    }
+}
+
+/// The metadata DirectXTex reads from a DDS file, mirroring its `TexMetadata`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TexconvMetadata {
+    /// Width of the top mip
+    pub width: u32,
+    /// Height of the top mip, 1 for 1D textures
+    pub height: u32,
+    /// Depth of the top mip, 1 for 1D and 2D textures
+    pub depth: u32,
+    /// Number of 2D images, counting each face of each cubemap
+    pub array_size: u32,
+    /// Number of mips, at least 1
+    pub mip_levels: u32,
+    /// If the array is made of cubemaps
+    pub cubemap: bool,
+    /// The number of axes
+    pub dimension: u8,
+    /// The pixel format
+    pub format: TexconvFormat,
+}
+
+impl TexconvMetadata {
+    /// The size of the data following the header, in the order DirectXTex reads it:
+    /// every mip of the first image, then every mip of the next
+    pub fn payload_size(&self) -> usize {
+        let mips: usize = (0..self.mip_levels)
+            .map(|m| {
+                let size = |n: u32| (n >> m).max(1);
+                let slice = self.format.slice_size(size(self.width), size(self.height));
+                slice * size(self.depth) as usize
+            })
+            .sum();
+        mips * self.array_size as usize
+    }
+}
+
+/// DDS files laid out the way DirectXTex's texconv writes them, built byte by byte from their metadata
+/// instead of with [`DDSHeader`], so the reader is checked against files it had no part in making
+#[derive(Clone, Copy, Debug, Eq, PartialEq, VariantArray)]
+pub enum TexconvFixture {
+    /// A legacy BC1 texture with a width that isn't a multiple of the block size
+    LegacyMips,
+    /// A legacy RGBA8 cubemap with mips
+    LegacyCubemap,
+    /// A legacy BC1 volume, whose mips shrink in depth
+    LegacyVolume,
+    /// An sRGB BC7 texture with mips, which needs a DX10 header
+    DX10Mips,
+    /// A BC1 array of 3 layers
+    DX10Array,
+    /// A single BC1 cubemap, with a DX10 array size of 1
+    DX10Cubemap,
+    /// An RGBA8 array of 2 cubemaps, with a DX10 array size of 2
+    DX10CubemapArray,
+    /// An RGBA8 volume
+    DX10Volume,
+    /// An RGBA8 1D array, without `DDSD_HEIGHT`
+    DX10Array1D,
+}
+
+impl TexconvFixture {
+    /// Parse a fixture from its name, for parameterized tests
+    pub fn from_name(name: &str) -> Self {
+        *Self::VARIANTS
+            .iter()
+            .find(|f| f.name() == name)
+            .unwrap_or_else(|| unreachable!("unknown texconv fixture {name}"))
+    }
+
+    /// The fixture's name in snake case
+    pub fn name(self) -> &'static str {
+        match self {
+            TexconvFixture::LegacyMips => "legacy_mips",
+            TexconvFixture::LegacyCubemap => "legacy_cubemap",
+            TexconvFixture::LegacyVolume => "legacy_volume",
+            TexconvFixture::DX10Mips => "dx10_mips",
+            TexconvFixture::DX10Array => "dx10_array",
+            TexconvFixture::DX10Cubemap => "dx10_cubemap",
+            TexconvFixture::DX10CubemapArray => "dx10_cubemap_array",
+            TexconvFixture::DX10Volume => "dx10_volume",
+            TexconvFixture::DX10Array1D => "dx10_array_1d",
+        }
+    }
+
+    /// If texconv writes this fixture with a DX10 header
+    pub fn dx10(self) -> bool {
+        !matches!(
+            self,
+            TexconvFixture::LegacyMips
+                | TexconvFixture::LegacyCubemap
+                | TexconvFixture::LegacyVolume
+        )
+    }
+
+    /// The metadata texconv would report for this fixture
+    pub fn metadata(self) -> TexconvMetadata {
+        use TexconvFormat::*;
+        let (width, height, depth, array_size, mip_levels, cubemap, dimension, format) = match self
+        {
+            TexconvFixture::LegacyMips => (12, 8, 1, 1, 4, false, 2, BC1UNorm),
+            TexconvFixture::LegacyCubemap => (4, 4, 1, 6, 3, true, 2, R8G8B8A8UNorm),
+            TexconvFixture::LegacyVolume => (8, 8, 4, 1, 4, false, 3, BC1UNorm),
+            TexconvFixture::DX10Mips => (10, 6, 1, 1, 4, false, 2, BC7UNormSRGB),
+            TexconvFixture::DX10Array => (8, 8, 1, 3, 4, false, 2, BC1UNorm),
+            TexconvFixture::DX10Cubemap => (8, 8, 1, 6, 1, true, 2, BC1UNorm),
+            TexconvFixture::DX10CubemapArray => (4, 4, 1, 12, 3, true, 2, R8G8B8A8UNorm),
+            TexconvFixture::DX10Volume => (4, 4, 4, 1, 3, false, 3, R8G8B8A8UNorm),
+            TexconvFixture::DX10Array1D => (16, 1, 1, 2, 5, false, 1, R8G8B8A8UNorm),
+        };
+        TexconvMetadata {
+            width,
+            height,
+            depth,
+            array_size,
+            mip_levels,
+            cubemap,
+            dimension,
+            format,
+        }
+    }
+
+    /// The format this fixture should be read as. Legacy headers can't say how alpha is used,
+    /// so their alpha is [`AlphaFormat::Custom`]
+    pub fn format(self) -> Format {
+        match (self.dx10(), self.metadata().format) {
+            (_, TexconvFormat::BC1UNorm) => Format::BC1 { srgb: false },
+            (_, TexconvFormat::BC7UNormSRGB) => Format::BC7 { srgb: true },
+            (true, TexconvFormat::R8G8B8A8UNorm) => Format::rgba8(false),
+            (false, TexconvFormat::R8G8B8A8UNorm) => MaskLayout::Rgba.format(),
+        }
+    }
+
+    /// The fixture's file, following DirectXTex's `_EncodeDDSHeader`, with a payload of arbitrary bytes
+    pub fn bytes(self) -> Vec<u8> {
+        let meta = self.metadata();
+        let compressed = meta.format != TexconvFormat::R8G8B8A8UNorm;
+        let mut flags = 0x1007 | DDSD_MIPMAPCOUNT; // DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT
+        let mut caps = 0x1000; // DDSCAPS_TEXTURE
+        let mut caps2 = 0;
+        if meta.mip_levels > 1 {
+            caps |= 0x40_0008; // DDSCAPS_COMPLEX | DDSCAPS_MIPMAP
+        }
+        if meta.cubemap {
+            caps |= 0x8;
+            caps2 |= CUBEMAP_ALLFACES;
+        }
+        if meta.dimension == 3 {
+            flags |= DDSD_DEPTH;
+            caps2 |= CAPS2_VOLUME;
+        }
+        let pitch = match compressed {
+            true => {
+                flags |= DDSD_LINEARSIZE;
+                meta.format.slice_size(meta.width, meta.height) as u32
+            }
+            false => {
+                flags |= DDSD_PITCH;
+                meta.width * 4
+            }
+        };
+
+        let mut file = b"DDS ".to_vec();
+        let mut push = |values: &[u32]| {
+            for v in values {
+                file.extend_from_slice(&v.to_le_bytes());
+            }
+        };
+        let depth = match meta.dimension {
+            3 => meta.depth,
+            _ => 0,
+        };
+        push(&[124, flags, meta.height, meta.width, pitch, depth]);
+        push(&[meta.mip_levels]);
+        push(&[0; 11]);
+        match (self.dx10(), meta.format) {
+            (true, _) => push(&[32, 0x4, u32::from_le_bytes(*b"DX10"), 0, 0, 0, 0, 0]),
+            (false, TexconvFormat::BC1UNorm) => {
+                push(&[32, 0x4, u32::from_le_bytes(*b"DXT1"), 0, 0, 0, 0, 0])
+            }
+            (false, _) => push(&[32, 0x41, 0, 32, 0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000]),
+        }
+        push(&[caps, caps2, 0, 0, 0]);
+        if self.dx10() {
+            let (misc, array_size) = match meta.cubemap {
+                true => (MISC_TEXTURECUBE, meta.array_size / 6),
+                false => (0, meta.array_size),
+            };
+            let dimension = meta.dimension as u32 + 1;
+            push(&[meta.format.dxgi(), dimension, misc, array_size, 0]);
+        }
+        file.extend((0..meta.payload_size()).map(|i| (i * 31 + 7) as u8));
+        file
+    }
+}
+
+/// Read the metadata of a DDS file the way DirectXTex's `DecodeDDSHeader` does, returning an error wherever
+/// texconv would refuse to load the file, including when it is too short for its payload.
+/// Only the pixel formats in [`TexconvFormat`] are recognized
+pub fn texconv_metadata(file: &[u8]) -> Result<TexconvMetadata> {
+    let field = |i: usize| -> Result<u32> {
+        let bytes = file
+            .get(4 + i * 4..8 + i * 4)
+            .ok_or(anyhow!("file is too short"))?;
+        Ok(u32::from_le_bytes(bytes.try_into()?))
+    };
+    ensure!(file.starts_with(b"DDS "), "bad magic");
+    ensure!(field(0)? == 124 && field(18)? == 32, "bad header size");
+    let (flags, height, width, depth) = (field(1)?, field(2)?, field(3)?, field(5)?);
+    let mip_levels = field(6)?.max(1);
+    let (pf_flags, fourcc, caps2) = (field(19)?, field(20)?, field(27)?);
+
+    let mut header_size = 128;
+    let meta = if pf_flags & 0x4 != 0 && fourcc == u32::from_le_bytes(*b"DX10") {
+        header_size = 148;
+        let (dxgi, dimension, misc, array_size) = (field(31)?, field(32)?, field(33)?, field(34)?);
+        ensure!(array_size != 0, "DX10 array size is 0");
+        let format = [
+            TexconvFormat::BC1UNorm,
+            TexconvFormat::BC7UNormSRGB,
+            TexconvFormat::R8G8B8A8UNorm,
+        ]
+        .into_iter()
+        .find(|f| f.dxgi() == dxgi)
+        .ok_or(anyhow!("unknown DXGI format {dxgi}"))?;
+        let cubemap = dimension == 3 && misc & MISC_TEXTURECUBE != 0;
+        let (height, depth) = match dimension {
+            2 => {
+                ensure!(
+                    flags & DDSD_HEIGHT == 0 || height == 1,
+                    "1D texture has a height"
+                );
+                (1, 1)
+            }
+            3 => (height, 1),
+            4 => {
+                ensure!(flags & DDSD_DEPTH != 0, "volume without DDSD_DEPTH");
+                ensure!(array_size == 1, "volume array");
+                (height, depth)
+            }
+            _ => bail!("bad resource dimension {dimension}"),
+        };
+        TexconvMetadata {
+            width,
+            height,
+            depth,
+            array_size: array_size * if cubemap { 6 } else { 1 },
+            mip_levels,
+            cubemap,
+            dimension: (dimension - 1) as u8,
+            format,
+        }
+    } else {
+        let masks = [field(22)?, field(23)?, field(24)?, field(25)?];
+        let format = match (pf_flags, fourcc) {
+            (0x4, f) if f == u32::from_le_bytes(*b"DXT1") => TexconvFormat::BC1UNorm,
+            (0x41, 0) if field(21)? == 32 && masks == [0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000] => {
+                TexconvFormat::R8G8B8A8UNorm
+            }
+            _ => bail!("unknown legacy pixel format"),
+        };
+        let (depth, dimension, cubemap) = match (flags & DDSD_DEPTH != 0, caps2 & CUBEMAP_ALLFACES)
+        {
+            (true, _) => (depth, 3, false),
+            (false, 0) => (1, 2, false),
+            (false, CUBEMAP_ALLFACES) => (1, 2, true),
+            (false, _) => bail!("partial cubemap"),
+        };
+        TexconvMetadata {
+            width,
+            height,
+            depth,
+            array_size: if cubemap { 6 } else { 1 },
+            mip_levels,
+            cubemap,
+            dimension,
+            format,
+        }
+    };
+
+    let largest = match meta.dimension {
+        3 => meta.width.max(meta.height).max(meta.depth),
+        _ => meta.width.max(meta.height),
+    };
+    ensure!(
+        meta.mip_levels <= largest.ilog2() + 1,
+        "too many mips for the texture size"
+    );
+    ensure!(
+        file.len() >= header_size + meta.payload_size(),
+        "file is too short for its payload"
+    );
+    Ok(meta)
+}
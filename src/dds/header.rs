@@ -2,9 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use binrw::binrw;
+use std::io::{Read, Seek};
+
+use binrw::{binrw, BinResult, Endian};
 use enumflags2::{bitflags, BitFlags};
 
+use crate::container::Diagnostic;
 use crate::shape::CubeFace;
 
 use super::dx10_header::DX10HeaderIntermediate;
@@ -71,22 +74,23 @@ impl Caps2 {
     }
 }
 
-pub(super) fn cubemap_order(face: &CubeFace) -> usize {
-    CAPS_CUBEMAP_MAP
-        .iter()
-        .position(|(_, rface)| *rface == *face)
-        .expect("Invalid cubemap face")
-}
-
 #[binrw]
-#[derive(Debug, Copy, Clone)]
-#[brw(little, magic = b"DDS ")]
+#[derive(Debug, Clone)]
+// the magic is read as a number so it matches in either byte order
+#[brw(magic = 0x2053_4444u32)]
 pub(super) struct DDSHeaderIntermediate {
+    #[br(parse_with = current_endian)]
+    #[bw(ignore)]
+    pub endian: Endian,
     #[br(temp)]
     #[bw(calc = 124u32)]
     _size: u32,
-    #[br(try_map = BitFlags::from_bits)]
-    #[bw(map = | bf | bf.bits())]
+    // flags are read with unknown bits removed, since real files often set reserved or undocumented bits
+    #[br(temp)]
+    #[bw(calc = flags.bits())]
+    flags_bits: u32,
+    #[br(calc = BitFlags::from_bits_truncate(flags_bits))]
+    #[bw(ignore)]
     pub flags: BitFlags<DDSFlags>,
     pub height: u32,
     pub width: u32,
@@ -95,15 +99,38 @@ pub(super) struct DDSHeaderIntermediate {
     pub mipmap_count: u32,
     #[brw(pad_before = 44)]
     pub pixel_format: PixelFormat,
-    #[br(try_map = BitFlags::from_bits)]
-    #[bw(map = | bf | bf.bits())]
+    #[br(temp)]
+    #[bw(calc = caps1.bits())]
+    caps1_bits: u32,
+    #[br(calc = BitFlags::from_bits_truncate(caps1_bits))]
+    #[bw(ignore)]
     pub caps1: BitFlags<Caps1>,
-    #[br(try_map = BitFlags::from_bits)]
-    #[bw(map = | bf | bf.bits())]
+    #[br(temp)]
+    #[bw(calc = caps2.bits())]
+    caps2_bits: u32,
+    #[br(calc = BitFlags::from_bits_truncate(caps2_bits))]
+    #[bw(ignore)]
     pub caps2: BitFlags<Caps2>,
     pub caps3: u32,
     #[brw(pad_after = 4)]
     pub caps4: u32,
     #[br(if (pixel_format.is_dx10()))]
     pub dx10_header: Option<DX10HeaderIntermediate>,
+    /// Diagnostics for bits removed from the flags and caps fields
+    #[br(calc = [
+        ("flags", flags_bits & !flags.bits()),
+        ("caps", caps1_bits & !caps1.bits()),
+        ("caps2", caps2_bits & !caps2.bits()),
+    ]
+    .into_iter()
+    .filter(|(_, bits)| *bits != 0)
+    .map(|(field, bits)| Diagnostic::UnknownBits { field, bits })
+    .collect())]
+    #[bw(ignore)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The byte order the header is being read in, which comes from [`ContainerHeader::read_endian`](crate::container::ContainerHeader::read_endian)
+fn current_endian<R: Read + Seek>(_reader: &mut R, endian: Endian, _args: ()) -> BinResult<Endian> {
+    Ok(endian)
 }
@@ -0,0 +1,380 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Conversions between textures and the types of other DDS crates, for projects using them alongside this one.
+//!
+//! * With the `ddsfile` feature, [`ddsfile::Dds`] converts to and from [`Texture`]. Headers are converted
+//!   through [`DDSHeader`], so a `Dds` is read exactly like a file with the same bytes
+//! * With the `image_dds` feature, [`image_dds::Surface`] converts to and from [`Texture`]. `image_dds` surfaces
+//!   can't tell a cubemap from an array, so surfaces with exactly 6 layers are read as cubemaps,
+//!   the same as `image_dds` does. Cubemaps are written as 6 layers each, so cubemap arrays read back as arrays,
+//!   and 1D textures are written as 2D
+//!
+//! Surface data is copied in both directions, without decoding or re-encoding any pixels.
+//!
+//! ```
+//! # #[cfg(feature = "ddsfile")] {
+//! use quicktex::prelude::*;
+//! use quicktex::{Surface, TextureShapeNode};
+//!
+//! let surface = Surface::new(Dimensions::try_from([8, 8])?, vec![0u8; 32]);
+//! let texture = Texture::new(Format::BC1 { srgb: false }, TextureShapeNode::from_surface(surface))?;
+//!
+//! let dds = ddsfile::Dds::try_from(&texture)?;
+//! assert_eq!(dds.get_width(), 8);
+//! assert_eq!(Texture::try_from(&dds)?.dimensions(), texture.dimensions());
+//! # }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#[cfg(feature = "ddsfile")]
+use std::io::Cursor;
+
+#[cfg(feature = "ddsfile")]
+use binrw::BinWriterExt;
+
+#[cfg(feature = "image_dds")]
+use strum::VariantArray;
+
+#[cfg(feature = "ddsfile")]
+use crate::container::ContainerHeader;
+#[cfg(feature = "image_dds")]
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+#[cfg(feature = "image_dds")]
+use crate::shape::{CubeFace, TextureShape};
+use crate::texture::Texture;
+#[cfg(feature = "image_dds")]
+use crate::texture::{SurfaceCounts, SurfaceOrder, SurfaceReader, SurfaceWriter};
+
+#[cfg(feature = "image_dds")]
+use super::dx10_header::{try_from_format, try_into_format};
+#[cfg(feature = "ddsfile")]
+use super::DDSHeader;
+#[cfg(feature = "image_dds")]
+use super::{AlphaMode, DXGIFormat};
+
+#[cfg(feature = "ddsfile")]
+fn ddsfile_error(error: ddsfile::Error) -> TextureError {
+    TextureError::Other(format!("ddsfile: {error}"))
+}
+
+/// Read a `Dds` the same way as a DDS file with the same header and data
+#[cfg(feature = "ddsfile")]
+impl TryFrom<&ddsfile::Dds> for Texture {
+    type Error = TextureError;
+
+    fn try_from(dds: &ddsfile::Dds) -> TextureResult<Self> {
+        let mut header = b"DDS ".to_vec();
+        dds.header.write(&mut header).map_err(ddsfile_error)?;
+        if let Some(header10) = &dds.header10 {
+            header10.write(&mut header).map_err(ddsfile_error)?;
+        }
+        let header = DDSHeader::read_header(&mut Cursor::new(header))?;
+        header.to_texture(&mut Cursor::new(&dds.data))
+    }
+}
+
+/// Build a `Dds` with the header [`DDSHeader::write_texture`] would write.
+/// Returns the same errors as writing the texture to a file
+#[cfg(feature = "ddsfile")]
+impl TryFrom<&Texture> for ddsfile::Dds {
+    type Error = TextureError;
+
+    fn try_from(texture: &Texture) -> TextureResult<Self> {
+        texture.check_format()?;
+        texture.check_container::<DDSHeader>()?;
+        let header = DDSHeader::from_texture(texture)?;
+        let mut bytes = Cursor::new(Vec::new());
+        header.write_surfaces(&mut bytes, &texture.surfaces)?;
+        let data = bytes.into_inner();
+
+        let mut bytes = Cursor::new(Vec::new());
+        bytes.write_le(&header)?;
+        let mut reader = &bytes.get_ref()[4..];
+        let dds_header = ddsfile::Header::read(&mut reader).map_err(ddsfile_error)?;
+        let header10 = match header {
+            DDSHeader::DX10 { .. } => {
+                Some(ddsfile::Header10::read(&mut reader).map_err(ddsfile_error)?)
+            }
+            DDSHeader::Legacy { .. } => None,
+        };
+        Ok(ddsfile::Dds {
+            header: dds_header,
+            header10,
+            data,
+        })
+    }
+}
+
+/// `image_dds` formats and the DXGI formats they store. `Bgr8Unorm` has no DXGI equivalent
+#[cfg(feature = "image_dds")]
+const IMAGE_DDS_FORMATS: [(image_dds::ImageFormat, DXGIFormat); 37] = {
+    use image_dds::ImageFormat as I;
+    use DXGIFormat::*;
+    [
+        (I::R8Unorm, R8UNorm),
+        (I::R8Snorm, R8SNorm),
+        (I::Rg8Unorm, R8G8UNorm),
+        (I::Rg8Snorm, R8G8SNorm),
+        (I::Rgba8Unorm, R8G8B8A8UNorm),
+        (I::Rgba8UnormSrgb, R8G8B8A8UNormSRGB),
+        (I::Rgba8Snorm, R8G8B8A8SNorm),
+        (I::Rgba16Float, R16G16B16A16Float),
+        (I::Rgba32Float, R32G32B32A32Float),
+        (I::Bgra8Unorm, B8G8R8A8UNorm),
+        (I::Bgra8UnormSrgb, B8G8R8A8UNormSRGB),
+        (I::Bgra4Unorm, B4G4R4A4UNorm),
+        (I::Bgr5A1Unorm, B5G5R5A1UNorm),
+        (I::BC1RgbaUnorm, BC1UNorm),
+        (I::BC1RgbaUnormSrgb, BC1UNormSRGB),
+        (I::BC2RgbaUnorm, BC2UNorm),
+        (I::BC2RgbaUnormSrgb, BC2UNormSRGB),
+        (I::BC3RgbaUnorm, BC3UNorm),
+        (I::BC3RgbaUnormSrgb, BC3UNormSRGB),
+        (I::BC4RUnorm, BC4UNorm),
+        (I::BC4RSnorm, BC4SNorm),
+        (I::BC5RgUnorm, BC5UNorm),
+        (I::BC5RgSnorm, BC5SNorm),
+        (I::BC6hRgbUfloat, BC6HUF16),
+        (I::BC6hRgbSfloat, BC6HSF16),
+        (I::BC7RgbaUnorm, BC7UNorm),
+        (I::BC7RgbaUnormSrgb, BC7UNormSRGB),
+        (I::R16Unorm, R16UNorm),
+        (I::R16Snorm, R16SNorm),
+        (I::Rg16Unorm, R16G16UNorm),
+        (I::Rg16Snorm, R16G16SNorm),
+        (I::Rgba16Unorm, R16G16B16A16UNorm),
+        (I::Rgba16Snorm, R16G16B16A16SNorm),
+        (I::R16Float, R16Float),
+        (I::Rg16Float, R16G16Float),
+        (I::R32Float, R32Float),
+        (I::Rg32Float, R32G32Float),
+    ]
+};
+
+/// Read an `image_dds` surface, whose data is ordered by layer and then mip.
+/// Surfaces with 6 layers are read as cubemaps
+#[cfg(feature = "image_dds")]
+impl<T: AsRef<[u8]>> TryFrom<&image_dds::Surface<T>> for Texture {
+    type Error = TextureError;
+
+    fn try_from(surface: &image_dds::Surface<T>) -> TextureResult<Self> {
+        let (_, dxgi_format) = IMAGE_DDS_FORMATS
+            .iter()
+            .find(|(f, _)| *f == surface.image_format)
+            .ok_or_else(|| {
+                TextureError::Format(format!("{:?} has no DXGI format", surface.image_format))
+            })?;
+        let format = try_into_format(dxgi_format, &AlphaMode::Unknown)?;
+        let dimensions = match surface.depth {
+            1 => Dimensions::try_from([surface.width, surface.height])?,
+            depth => Dimensions::try_from([surface.width, surface.height, depth])?,
+        };
+        let (layers, faces) = match surface.layers {
+            6 => (None, Some(CubeFace::VARIANTS.into())),
+            layers => ((layers > 1).then_some(layers as usize), None),
+        };
+        let counts = SurfaceCounts {
+            dimensions,
+            layers,
+            faces,
+            mips: (surface.mipmaps > 1).then_some(surface.mipmaps as usize),
+        };
+        let mut reader = SurfaceReader {
+            format,
+            reader: &mut surface.data.as_ref(),
+        };
+        let surfaces = reader.read_shaped(SurfaceOrder::LayersFacesMips, &counts)?;
+        Texture::new(format, surfaces)
+    }
+}
+
+/// Copy a texture into an `image_dds` surface, with each cubemap written as 6 layers.
+/// Returns [`TextureError::Format`] for formats `image_dds` doesn't have and for premultiplied alpha,
+/// and [`TextureError::Capability`] for cubemaps missing any faces
+#[cfg(feature = "image_dds")]
+impl TryFrom<&Texture> for image_dds::Surface<Vec<u8>> {
+    type Error = TextureError;
+
+    fn try_from(texture: &Texture) -> TextureResult<Self> {
+        texture.check_format()?;
+        let unsupported = || {
+            TextureError::Format(format!(
+                "{:?} cannot be stored in an image_dds surface",
+                texture.format
+            ))
+        };
+        let (dxgi_format, alpha_mode) = try_from_format(texture.format)?;
+        if alpha_mode == AlphaMode::Premultiplied {
+            return Err(unsupported());
+        }
+        let (image_format, _) = IMAGE_DDS_FORMATS
+            .iter()
+            .find(|(_, f)| *f == dxgi_format)
+            .ok_or_else(unsupported)?;
+
+        let faces = match texture.faces() {
+            Some(faces) if faces.len() != CubeFace::VARIANTS.len() => {
+                return Err(TextureError::Capability(
+                    "image_dds surfaces cannot store partial cubemaps".to_string(),
+                ))
+            }
+            Some(faces) => faces.len(),
+            None => 1,
+        };
+        let dimensions = texture.try_dimensions()?;
+        let mut data = Vec::new();
+        SurfaceWriter::new(&mut data, 0)
+            .write_shaped(SurfaceOrder::LayersFacesMips, &texture.surfaces)?;
+        Ok(image_dds::Surface {
+            width: dimensions.width(),
+            height: dimensions.height(),
+            depth: dimensions.depth(),
+            layers: (texture.layers().unwrap_or(1) * faces) as u32,
+            mipmaps: texture.mips().unwrap_or(1) as u32,
+            image_format: *image_format,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+    use generic_parameterize::parameterize;
+
+    use crate::container::ContainerHeader;
+    use crate::dds::fixtures::TexconvFixture;
+    use crate::dds::DDSHeader;
+    use crate::dimensions::{Dimensioned, Dimensions};
+    use crate::shape::{CubeFace, TextureShape};
+    use crate::texture::{SurfaceOrder, Texture};
+
+    /// Textures read from every texconv fixture, covering legacy and DX10 headers, arrays, cubemaps, and volumes
+    fn read_fixture(name: &str) -> Result<Texture> {
+        let bytes = TexconvFixture::from_name(name).bytes();
+        Ok(DDSHeader::read_texture(&mut Cursor::new(bytes))?)
+    }
+
+    /// Every surface of a texture in file order, with its position in the tree
+    #[allow(clippy::type_complexity)]
+    fn surfaces(
+        texture: &Texture,
+    ) -> Vec<(
+        Option<usize>,
+        Option<CubeFace>,
+        Option<usize>,
+        Dimensions,
+        Vec<u8>,
+    )> {
+        SurfaceOrder::LayersFacesMips
+            .ordered(&texture.surfaces)
+            .into_iter()
+            .map(|s| {
+                (
+                    s.layer,
+                    s.face,
+                    s.mip,
+                    s.surface.dimensions(),
+                    s.surface.buffer.to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    /// Read a fixture through `ddsfile`, and check that converting back writes the same file as [`DDSHeader`]
+    #[cfg(feature = "ddsfile")]
+    #[parameterize(
+        name = ["legacy_mips", "legacy_cubemap", "legacy_volume", "dx10_mips", "dx10_array", "dx10_cubemap",
+            "dx10_cubemap_array", "dx10_volume", "dx10_array_1d"],
+        fmt = "ddsfile_{name}"
+    )]
+    #[test]
+    fn roundtrip_ddsfile(name: &str) -> Result<()> {
+        let fixture = TexconvFixture::from_name(name);
+        let meta = fixture.metadata();
+        let dds = ddsfile::Dds::read(Cursor::new(fixture.bytes()))?;
+        assert_eq!(dds.get_num_mipmap_levels(), meta.mip_levels);
+        assert_eq!(dds.get_depth(), meta.depth);
+
+        let texture = Texture::try_from(&dds)?;
+        let expected = read_fixture(name)?;
+        assert_eq!(texture.format, expected.format);
+        assert_eq!(surfaces(&texture), surfaces(&expected));
+
+        let dds = ddsfile::Dds::try_from(&texture)?;
+        let mut converted = Vec::new();
+        dds.write(&mut converted)?;
+        let mut written = Cursor::new(Vec::new());
+        DDSHeader::write_texture(&mut written, &texture)?;
+        assert_eq!(converted, written.into_inner());
+        Ok(())
+    }
+
+    /// Convert a fixture to an `image_dds` surface and back, checking that `image_dds` finds each surface
+    /// where this crate put it
+    #[cfg(feature = "image_dds")]
+    #[parameterize(
+        name = ["legacy_mips", "legacy_cubemap", "legacy_volume", "dx10_mips", "dx10_array", "dx10_cubemap",
+            "dx10_cubemap_array", "dx10_volume", "dx10_array_1d"],
+        fmt = "image_dds_{name}"
+    )]
+    #[test]
+    fn roundtrip_image_dds(name: &str) -> Result<()> {
+        use crate::format::{AlphaFormat, ColorFormat, Format};
+
+        let meta = TexconvFixture::from_name(name).metadata();
+        let texture = read_fixture(name)?;
+        let surface = image_dds::Surface::try_from(&texture)?;
+        assert_eq!(
+            (surface.width, surface.height, surface.depth),
+            (meta.width, meta.height, meta.depth)
+        );
+        assert_eq!(
+            (surface.layers, surface.mipmaps),
+            (meta.array_size, meta.mip_levels)
+        );
+
+        let faces = texture.faces().map_or(1, |f| f.len());
+        for (layer, face, mip, _, buffer) in surfaces(&texture) {
+            let layer = layer.unwrap_or(0) * faces + face.map_or(0, |f| f as usize);
+            let slice = surface
+                .get(layer as u32, 0, mip.unwrap_or(0) as u32)
+                .unwrap();
+            assert!(buffer.starts_with(slice), "surface {layer} {mip:?} differs");
+        }
+
+        let read = Texture::try_from(&surface)?;
+        // legacy headers read alpha as a custom channel, which image_dds can't express
+        let format = match texture.format {
+            Format::Uncompressed {
+                pitch,
+                color_format: color_format @ ColorFormat::RGB { .. },
+                alpha_format: AlphaFormat::Custom { alpha_mask },
+            } => Format::Uncompressed {
+                pitch,
+                color_format,
+                alpha_format: AlphaFormat::Straight { alpha_mask },
+            },
+            format => format,
+        };
+        assert_eq!(read.format, format);
+        match (meta.cubemap && meta.array_size > 6, meta.dimension) {
+            // cubemap arrays come back as plain arrays
+            (true, _) => assert_eq!(read.layers(), Some(meta.array_size as usize)),
+            // and 1D textures as 2D textures 1 pixel tall
+            (false, 1) => {
+                assert_eq!(read.dimensions(), Dimensions::try_from([meta.width, 1])?);
+                let buffers =
+                    |t: &Texture| surfaces(t).into_iter().map(|s| s.4).collect::<Vec<_>>();
+                assert_eq!(buffers(&read), buffers(&texture));
+            }
+            (false, _) => assert_eq!(surfaces(&read), surfaces(&texture)),
+        }
+        Ok(())
+    }
+}
@@ -2,50 +2,95 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::io::{Read, Seek, Write};
-
-use binrw::binrw;
-use enumflags2::{BitFlags, make_bitflags};
+//! This is the crate's only DDS implementation. Its public surface is:
+//!
+//! * [`DDSHeader`](crate::dds::DDSHeader), which reads and writes textures through [`ContainerHeader`](crate::container::ContainerHeader)
+//! * [`DDSHeaderArgs`](crate::dds::DDSHeaderArgs) and [`DDSHeaderMode`](crate::dds::DDSHeaderMode), controlling which kind of header is written
+//! * [`PixelFormat`](crate::dds::PixelFormat), [`FourCC`](crate::dds::FourCC), [`DXGIFormat`](crate::dds::DXGIFormat),
+//!   and [`AlphaMode`](crate::dds::AlphaMode), the raw format fields of a header
+//! * [`strip_top_mips`](crate::dds::strip_top_mips), for rewriting files without decoding them
+//! * `interop`, converting textures to and from the types of the `ddsfile` and `image_dds` crates,
+//!   with the features of the same names
+//!
+//! The binary layouts of the header structures are private,
+//! and are converted to and from [`DDSHeader`](crate::dds::DDSHeader) when reading and writing.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use binrw::{binrw, BinWriterExt, Endian};
+use enumflags2::{make_bitflags, BitFlags};
 use itertools::Itertools;
 use strum::VariantArray;
 
-use dx10_header::{AlphaMode, DX10HeaderIntermediate, DXGIFormat};
-use header::{Caps1, DDSHeaderIntermediate};
+use dx10_header::DX10HeaderIntermediate;
 use header::DDSFlags;
-use pixel_format::PixelFormat;
+use header::{Caps1, DDSHeaderIntermediate};
 
-use crate::container::ContainerHeader;
-use crate::dimensions::{Dimensioned, Dimensions};
+use crate::capability::{ContainerCapabilities, FormatFamily};
+use crate::container::{packed_offsets, ContainerHeader, Diagnostic};
+use crate::dimensions::{DimensionError, Dimensions};
 use crate::error::{TextureError, TextureResult};
 use crate::format::Format;
 use crate::shape::{CubeFace, TextureShape};
-use crate::texture::{SurfaceReader, Surfaces, Texture};
+use crate::texture::{
+    SurfaceCounts, SurfaceOffset, SurfaceOrder, SurfaceReader, SurfaceWriter, Surfaces, Texture,
+};
 
 mod dx10_header;
 mod header;
+#[cfg(any(feature = "ddsfile", feature = "image_dds"))]
+pub mod interop;
 mod pixel_format;
 
+pub use dx10_header::{AlphaMode, DXGIFormat};
+pub use pixel_format::{FourCC, PixelFormat};
+
+#[cfg(test)]
+mod fixtures;
 #[cfg(test)]
 mod tests;
 
+/// The header of a DDS file, in either legacy or DX10 form
 #[binrw]
 #[derive(Debug, Clone)]
 #[br(try_map = DDSHeaderIntermediate::try_into)]
 #[bw(try_map = | h: & DDSHeader | DDSHeaderIntermediate::try_from( h.clone() ))]
 pub enum DDSHeader {
+    /// A header with only a legacy pixel format
     Legacy {
+        /// Dimensions of the largest mip
         dimensions: Dimensions,
+        /// Number of mips, if the texture is mipmapped
         mips: Option<u32>,
+        /// Cubemap faces present, if the texture is a cubemap
         faces: Option<Vec<CubeFace>>,
+        /// The pixel format of the texture
         format: PixelFormat,
+        /// Byte order of the header and of 16 and 32-bit uncompressed pixels.
+        /// Big-endian files are written by some console tools
+        endian: Endian,
+        /// Problems found while reading the header. See [`ContainerHeader::diagnostics`]
+        diagnostics: Vec<Diagnostic>,
     },
+    /// A header with a DX10 extension header
     DX10 {
+        /// Dimensions of the largest mip
         dimensions: Dimensions,
+        /// Number of mips, if the texture is mipmapped
         mips: Option<u32>,
+        /// Number of array layers, if the texture is an array
         layers: Option<u32>,
+        /// If the texture is a cubemap. DX10 cubemaps always have all 6 faces
         is_cubemap: bool,
+        /// The DXGI format of the texture
         dxgi_format: DXGIFormat,
+        /// How the alpha channel is interpreted
         alpha_mode: AlphaMode,
+        /// Byte order of the header and of 16 and 32-bit uncompressed pixels.
+        /// Big-endian files are written by some console tools
+        endian: Endian,
+        /// Problems found while reading the header. See [`ContainerHeader::diagnostics`]
+        diagnostics: Vec<Diagnostic>,
     },
 }
 
@@ -53,9 +98,11 @@ impl TryFrom<DDSHeaderIntermediate> for DDSHeader {
     type Error = TextureError;
 
     fn try_from(raw: DDSHeaderIntermediate) -> TextureResult<Self> {
-        // MipmapCount flag might not be set, so count a mipmapcount value greater than 1 as equivalent
+        // MipmapCount flag might not be set, so count a mipmapcount value greater than 1 as equivalent.
+        // texconv sets the flag with a count of 1 for textures without mips, so treat that as no mipmap
         let mips = (raw.flags.contains(DDSFlags::MipmapCount) || raw.mipmap_count > 1)
-            .then_some(raw.mipmap_count);
+            .then_some(raw.mipmap_count)
+            .filter(|&m| m != 1);
 
         if let Some(dx10header) = raw.dx10_header {
             let dimensions = dx10header
@@ -66,6 +113,8 @@ impl TryFrom<DDSHeaderIntermediate> for DDSHeader {
                 l => Some(l),
             };
 
+            check_volume(dimensions, layers.is_some(), dx10header.cube)?;
+
             Ok(DDSHeader::DX10 {
                 dimensions,
                 mips,
@@ -73,9 +122,14 @@ impl TryFrom<DDSHeaderIntermediate> for DDSHeader {
                 is_cubemap: dx10header.cube,
                 dxgi_format: dx10header.dxgi_format,
                 alpha_mode: dx10header.alpha_mode,
+                endian: raw.endian,
+                diagnostics: raw.diagnostics,
             })
         } else {
-            let dimensions = if raw.flags.contains(DDSFlags::Depth) {
+            // some writers only set one of the depth flag or the volume cap
+            let is_volume =
+                raw.flags.contains(DDSFlags::Depth) || raw.caps2.contains(header::Caps2::Volume);
+            let dimensions = if is_volume {
                 Dimensions::try_from([raw.width, raw.height, raw.depth])?
             } else {
                 Dimensions::try_from([raw.width, raw.height])?
@@ -86,17 +140,30 @@ impl TryFrom<DDSHeaderIntermediate> for DDSHeader {
                     .filter_map(header::Caps2::to_cubemap_face)
                     .collect_vec(),
             );
+            check_volume(dimensions, false, faces.is_some())?;
 
             Ok(DDSHeader::Legacy {
                 dimensions,
                 mips,
                 faces,
                 format: raw.pixel_format,
+                endian: raw.endian,
+                diagnostics: raw.diagnostics,
             })
         }
     }
 }
 
+/// DDS volume textures can't be arrays or cubemaps, and each mip's depth slices are stored together
+fn check_volume(dimensions: Dimensions, is_array: bool, is_cubemap: bool) -> TextureResult<()> {
+    match dimensions {
+        Dimensions::_3D(_) if is_array || is_cubemap => Err(TextureError::Capability(
+            "Volume textures cannot be arrays or cubemaps in DDS files".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
 impl TryFrom<DDSHeader> for DDSHeaderIntermediate {
     type Error = TextureError;
 
@@ -106,6 +173,7 @@ impl TryFrom<DDSHeader> for DDSHeaderIntermediate {
         let mut caps2 = BitFlags::<header::Caps2>::default();
 
         let format = header.format();
+        let endian = header.endian();
         let (dimensions, mips, pixel_format, dx10_header) = match header {
             DDSHeader::Legacy {
                 dimensions,
@@ -160,23 +228,32 @@ impl TryFrom<DDSHeader> for DDSHeaderIntermediate {
             // uncompressed format
             Ok(Format::Uncompressed { pitch, .. }) => {
                 flags |= DDSFlags::Pitch;
-                pitch as u32 * dimensions.width()
+                u32::try_from(pitch)
+                    .ok()
+                    .and_then(|p| p.checked_mul(dimensions.width()))
+                    .ok_or(DimensionError::Overflow(dimensions))?
             }
+            // unknown format, just leave as 0 and hope the receiver doesn't mind.
+            // this probably cant be encountered in normal use unless an API user
+            // makes a DDS header from scratch
+            Ok(Format::Unknown { .. }) | Err(TextureError::Format(_)) => 0,
             // compressed format
             Ok(format) => {
                 flags |= DDSFlags::LinearSize;
-                format.size_for(dimensions) as u32
+                u32::try_from(format.size_for(dimensions)?)
+                    .map_err(|_| DimensionError::Overflow(dimensions))?
             }
-            // unknown format, just leave as 0 and hope the receiver doesn't mind.
-            // this probably cant be encountered in normal use unless an API user
-            // makes a DDS header from scratch
-            Err(TextureError::Format(_)) => 0,
             // unexpected error: rethrow
             Err(err) => return Err(err),
         };
 
         let depth = match dimensions {
-            Dimensions::_3D([_, _, depth]) => depth.into(),
+            Dimensions::_3D([_, _, depth]) => {
+                flags |= DDSFlags::Depth;
+                caps1 |= Caps1::Complex;
+                caps2 |= header::Caps2::Volume;
+                depth.into()
+            }
             _ => 0,
         };
 
@@ -191,6 +268,7 @@ impl TryFrom<DDSHeader> for DDSHeaderIntermediate {
         };
 
         Ok(DDSHeaderIntermediate {
+            endian,
             flags,
             height: dimensions.height(),
             width: dimensions.width(),
@@ -203,18 +281,32 @@ impl TryFrom<DDSHeader> for DDSHeaderIntermediate {
             caps3: 0,
             caps4: 0,
             dx10_header,
+            diagnostics: Vec::new(),
         })
     }
 }
 
 impl DDSHeader {
-    fn for_texture_legacy(texture: &Texture) -> TextureResult<Self> {
+    /// Reverse the bytes of every pixel if this is a big-endian file with a 16 or 32-bit uncompressed format,
+    /// or of every channel if it has 16 or 32-bit typed channels. See [`Format::swap_width`]
+    fn swap_pixels(&self, surfaces: &Surfaces) -> TextureResult<Surfaces> {
+        let width = match (self.endian(), self.format()) {
+            (Endian::Big, Ok(format)) => format.swap_width(),
+            _ => 1,
+        };
+        if width == 1 {
+            return Ok(surfaces.clone());
+        }
+        surfaces.try_map(|s| s.byteswap(width))
+    }
+
+    fn for_texture_legacy(texture: &Texture, endian: Endian) -> TextureResult<Self> {
         if texture.layers().is_some() {
             return Err(TextureError::Capability(
                 "Texture arrays are not supported by legacy DDS headers".to_string(),
             ));
         }
-        let dimensions = texture.dimensions();
+        let dimensions = texture.surfaces.try_dimensions()?;
         let mips: Option<u32> = texture.mips().map(|m| m as u32);
         let faces = texture.faces();
         let format: PixelFormat = texture.format.try_into()?;
@@ -224,11 +316,13 @@ impl DDSHeader {
             mips,
             faces,
             format,
+            endian,
+            diagnostics: Vec::new(),
         })
     }
 
-    fn for_texture_dx10(texture: &Texture) -> TextureResult<Self> {
-        let dimensions = texture.dimensions();
+    fn for_texture_dx10(texture: &Texture, endian: Endian) -> TextureResult<Self> {
+        let dimensions = texture.surfaces.try_dimensions()?;
         let mips: Option<u32> = texture.mips().map(|m| m as u32);
         let layers: Option<u32> = texture.layers().map(|m| m as u32);
         let is_cubemap = match texture.faces() {
@@ -249,79 +343,133 @@ impl DDSHeader {
             is_cubemap,
             dxgi_format,
             alpha_mode,
+            endian,
+            diagnostics: Vec::new(),
         })
     }
 }
 
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+/// Which kind of header to write for a texture
 pub enum DDSHeaderMode {
+    /// Use a legacy header when the texture can be represented by one, otherwise use DX10
     #[default]
     PreferLegacy,
+    /// Always use a legacy header, returning an error if the texture cannot be represented by one
     ForceLegacy,
+    /// Always use a DX10 header
     ForceDX10,
 }
 
-#[derive(Clone, Debug, Default)]
+/// Arguments for writing a DDS file.
+/// New options may be added in the future, so create them from [`Default`] with the builder methods:
+///
+/// ```
+/// use binrw::Endian;
+/// use quicktex::dds::{DDSHeaderArgs, DDSHeaderMode};
+///
+/// let args = DDSHeaderArgs::default().mode(DDSHeaderMode::ForceDX10).endian(Endian::Big);
+/// assert_eq!(args.mode, DDSHeaderMode::ForceDX10);
+/// ```
+#[non_exhaustive]
+#[derive(Clone, Debug)]
 pub struct DDSHeaderArgs {
+    /// Which kind of header to write
     pub mode: DDSHeaderMode,
+    /// Byte order to write the header and 16 and 32-bit uncompressed pixels in. Defaults to little-endian,
+    /// which is what every PC tool expects
+    pub endian: Endian,
+}
+
+impl Default for DDSHeaderArgs {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            endian: Endian::Little,
+        }
+    }
+}
+
+impl DDSHeaderArgs {
+    /// Set which kind of header to write
+    pub fn mode(mut self, mode: DDSHeaderMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the byte order to write in
+    pub fn endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
 }
 
 impl ContainerHeader for DDSHeader {
     type Args = DDSHeaderArgs;
 
+    const CAPABILITIES: ContainerCapabilities = ContainerCapabilities {
+        formats: make_bitflags!(FormatFamily::{BC1 | BC2 | BC3 | BC4 | BC5 | BC7 | ASTC | Uncompressed | Channels}),
+        shapes: BitFlags::ALL,
+    };
+
     fn read_surfaces<R: Read + Seek>(&self, reader: &mut R) -> TextureResult<Surfaces> {
         let mut surface_reader = SurfaceReader {
             format: self.format()?,
             reader,
         };
-        let layers = self.layers()?;
-        let faces = self.faces()?.map(|f| {
-            f.into_iter()
-                .sorted_by_key(header::cubemap_order)
-                .collect_vec()
-        });
-        let mips = self.mips()?;
-
-        // DDS files are ordered as Array(Cubemap(Mipmap(Surface)))
-        // yes this is confusing I couldn't figure out how to abstract it
-        surface_reader.read_layers(self.dimensions()?, layers, |r: &mut SurfaceReader<R>, d| {
-            r.read_faces(d, faces.clone(), |r: &mut SurfaceReader<R>, d| {
-                r.read_mips(d, mips, SurfaceReader::<R>::read_surface)
-            })
-        })
+        let counts = SurfaceCounts {
+            dimensions: self.dimensions()?,
+            layers: self.layers()?,
+            faces: self.faces()?,
+            mips: self.mips()?,
+        };
+        let surfaces = surface_reader.read_shaped(SurfaceOrder::LayersFacesMips, &counts)?;
+        self.swap_pixels(&surfaces)
     }
 
     fn write_surfaces<W: Write + Seek>(
         &self,
         writer: &mut W,
-        surfaces: Surfaces,
+        surfaces: &Surfaces,
     ) -> TextureResult<()> {
-        for (_, layer) in surfaces.iter_layers() {
-            for (_, face) in layer
-                .iter_faces()
-                .sorted_by_key(|(c, _)| c.map_or(0, |c| header::cubemap_order(&c)))
-            {
-                for (_, mip) in face.iter_mips() {
-                    writer.write(
-                        &*mip
-                            .try_into_surface()
-                            .expect("Innermost shape is not a surface")
-                            .buffer,
-                    )?;
-                }
-            }
+        let offset = writer.stream_position()?;
+        let surfaces = self.swap_pixels(surfaces)?;
+        SurfaceWriter::new(writer, offset).write_shaped(SurfaceOrder::LayersFacesMips, &surfaces)
+    }
+
+    fn surface_offsets(&self) -> TextureResult<Box<dyn Iterator<Item = SurfaceOffset>>> {
+        packed_offsets(self, SurfaceOrder::LayersFacesMips)
+    }
+
+    fn read_endian<R: Read + Seek>(reader: &mut R) -> TextureResult<Endian> {
+        // big-endian files store the magic as a reversed u32 like every other field
+        let start = reader.stream_position()?;
+        let mut magic = [0u8; 4];
+        let big = reader.read_exact(&mut magic).is_ok() && &magic == b" SDD";
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(if big { Endian::Big } else { Endian::Little })
+    }
+
+    fn endian(&self) -> Endian {
+        match self {
+            DDSHeader::Legacy { endian, .. } | DDSHeader::DX10 { endian, .. } => *endian,
         }
-        Ok(())
     }
 
     fn from_texture_args(
         texture: &Texture,
         args: &<Self as ContainerHeader>::Args,
     ) -> TextureResult<Self> {
+        check_volume(
+            texture.surfaces.try_dimensions()?,
+            texture.layers().is_some(),
+            texture.faces().is_some(),
+        )?;
+
         if args.mode != DDSHeaderMode::ForceDX10 {
             // try to make a legacy header
 
-            match Self::for_texture_legacy(texture) {
+            match Self::for_texture_legacy(texture, args.endian) {
                 Ok(header) => return Ok(header),
 
                 // cant try again, return
@@ -336,7 +484,7 @@ impl ContainerHeader for DDSHeader {
         }
 
         // try to make a DX10 header
-        Self::for_texture_dx10(texture)
+        Self::for_texture_dx10(texture, args.endian)
     }
 
     fn dimensions(&self) -> TextureResult<Dimensions> {
@@ -386,4 +534,78 @@ impl ContainerHeader for DDSHeader {
             } => dx10_header::try_into_format(dxgi_format, alpha_mode),
         }
     }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            DDSHeader::Legacy { diagnostics, .. } | DDSHeader::DX10 { diagnostics, .. } => {
+                diagnostics.clone()
+            }
+        }
+    }
+}
+
+/// Rewrite a DDS file with its `n` largest mips removed, without decoding any surfaces.
+/// The header is adjusted to the new dimensions and mip count,
+/// and the remaining surface data is copied from `reader` to `writer` as-is.
+///
+/// Returns [`TextureError::Capability`] if the texture does not have more than `n` mips,
+/// since at least one mip must remain,
+/// or [`TextureError::Other`] if the header claims more mips than its dimensions allow
+pub fn strip_top_mips<R, W>(reader: &mut R, writer: &mut W, n: usize) -> TextureResult<()>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let mut header = DDSHeader::read_header(reader)?;
+    let format = header.format()?;
+    let dimensions = header.dimensions()?;
+    let mip_count = header.mips()?.unwrap_or(1);
+    let max_mips = dimensions.mips().count();
+    if mip_count > max_mips {
+        return Err(TextureError::Other(format!(
+            "Header claims {mip_count} mips, but a {dimensions:?} texture has at most {max_mips}"
+        )));
+    }
+    if n >= mip_count {
+        return Err(TextureError::Capability(format!(
+            "Cannot remove {n} mips from a texture with {mip_count}"
+        )));
+    }
+
+    let sizes = dimensions
+        .mips()
+        .take(mip_count)
+        .map(|d| Ok(format.size_for(d)? as u64))
+        .collect::<TextureResult<Vec<_>>>()?;
+    let (skipped, kept) = sizes.split_at(n);
+    let skipped: u64 = skipped.iter().sum();
+    let kept: u64 = kept.iter().sum();
+
+    // surfaces are stored as Array(Cubemap(Mipmap(Surface))), so each face of each layer has its own mip chain
+    let chains = header.layers()?.unwrap_or(1) * header.faces()?.map_or(1, |f| f.len());
+
+    // n < mip_count <= max_mips, so mip n exists
+    let new_dimensions = dimensions.mips().nth(n).unwrap();
+    let new_mips = Some((mip_count - n) as u32);
+    match &mut header {
+        DDSHeader::Legacy {
+            dimensions, mips, ..
+        }
+        | DDSHeader::DX10 {
+            dimensions, mips, ..
+        } => {
+            *dimensions = new_dimensions;
+            *mips = new_mips;
+        }
+    }
+    writer.write_type(&header, header.endian())?;
+
+    for _ in 0..chains {
+        reader.seek(SeekFrom::Current(skipped as i64))?;
+        let copied = std::io::copy(&mut reader.take(kept), writer)?;
+        if copied != kept {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+    }
+    Ok(())
 }
@@ -5,7 +5,7 @@ use binrw::prelude::*;
 use enumflags2::{bitflags, BitFlags};
 
 use crate::error::TextureError;
-use crate::format::{AlphaFormat, ColorFormat, Format};
+use crate::format::{AlphaFormat, ChannelType, ColorFormat, Format};
 
 /// Bit flags for identifying various information in a [`PixelFormatIntermediate`] object. Not exposed to the API.
 #[bitflags]
@@ -38,10 +38,14 @@ struct PixelFormatIntermediate {
 
 /// A four byte format code. Usually an ASCII-like string but sometimes a u32.
 /// For maximum compatibility it's just stored as a byte string, but printed as text in `Debug` if
-/// it's valid UTF-8
+/// it's valid UTF-8. It's read as a u32, so big-endian files that store it reversed give the same bytes
 #[binrw]
 #[derive(Clone, Copy, PartialEq, Eq, Default)]
-pub struct FourCC(pub [u8; 4]);
+pub struct FourCC(
+    #[br(map = u32::to_le_bytes)]
+    #[bw(map = | b: & [u8; 4] | u32::from_le_bytes( * b))]
+    pub [u8; 4],
+);
 
 impl Debug for FourCC {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -67,10 +71,15 @@ impl From<&[u8; 4]> for FourCC {
 #[bw(map = | pf: & PixelFormat | PixelFormatIntermediate::from( * pf))]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum PixelFormat {
+    /// A compressed format identified by its FourCC code
     FourCC(FourCC),
+    /// An uncompressed format described by channel bitmasks
     Uncompressed {
+        /// Size of a single pixel in bits
         bit_count: u32,
+        /// Layout of the color channels
         color_format: ColorFormat,
+        /// Layout of the alpha channel
         alpha_format: AlphaFormat,
     },
 }
@@ -195,6 +204,84 @@ impl From<PixelFormat> for PixelFormatIntermediate {
     }
 }
 
+/// Uncompressed D3DFMT codes that old D3DX tooling writes as numeric FourCCs, with the size of each pixel in bytes,
+/// the red (or luminance), green, blue, and alpha masks, and if the format is luminance.
+/// A mask of 0 means the channel is missing
+#[rustfmt::skip]
+const D3DFMT: [(u32, usize, [u32; 4], bool); 20] = [
+    (20, 3, [0xFF0000, 0xFF00, 0xFF, 0], false),                   // R8G8B8
+    (21, 4, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], false),          // A8R8G8B8
+    (22, 4, [0xFF0000, 0xFF00, 0xFF, 0], false),                   // X8R8G8B8
+    (23, 2, [0xF800, 0x07E0, 0x001F, 0], false),                   // R5G6B5
+    (24, 2, [0x7C00, 0x03E0, 0x001F, 0], false),                   // X1R5G5B5
+    (25, 2, [0x7C00, 0x03E0, 0x001F, 0x8000], false),              // A1R5G5B5
+    (26, 2, [0x0F00, 0x00F0, 0x000F, 0xF000], false),              // A4R4G4B4
+    (27, 1, [0xE0, 0x1C, 0x03, 0], false),                         // R3G3B2
+    (28, 1, [0, 0, 0, 0xFF], false),                               // A8
+    (29, 2, [0xE0, 0x1C, 0x03, 0xFF00], false),                    // A8R3G3B2
+    (30, 2, [0x0F00, 0x00F0, 0x000F, 0], false),                   // X4R4G4B4
+    (31, 4, [0x3FF, 0xFFC00, 0x3FF00000, 0xC0000000], false),      // A2B10G10R10
+    (32, 4, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], false),          // A8B8G8R8
+    (33, 4, [0xFF, 0xFF00, 0xFF0000, 0], false),                   // X8B8G8R8
+    (34, 4, [0xFFFF, 0xFFFF0000, 0, 0], false),                    // G16R16
+    (35, 4, [0x3FF00000, 0xFFC00, 0x3FF, 0xC0000000], false),      // A2R10G10B10
+    (50, 1, [0xFF, 0, 0, 0], true),                                // L8
+    (51, 2, [0xFF, 0, 0, 0xFF00], true),                           // A8L8
+    (52, 1, [0x0F, 0, 0, 0xF0], true),                             // A4L4
+    (81, 2, [0xFFFF, 0, 0, 0], true),                              // L16
+];
+
+/// D3DFMT codes with channels of a single type that can't be described by bitmasks, with the type and number of channels
+#[rustfmt::skip]
+const D3DFMT_CHANNELS: [(u32, ChannelType, usize); 11] = {
+    use ChannelType::*;
+    [
+        (36,  UNorm16, 4), // A16B16G16R16
+        (60,  SNorm8,  2), // V8U8
+        (63,  SNorm8,  4), // Q8W8V8U8
+        (64,  SNorm16, 2), // V16U16
+        (110, SNorm16, 4), // Q16W16V16U16
+        (111, Float16, 1), // R16F
+        (112, Float16, 2), // G16R16F
+        (113, Float16, 4), // A16B16G16R16F
+        (114, Float32, 1), // R32F
+        (115, Float32, 2), // G32R32F
+        (116, Float32, 4), // A32B32G32R32F
+    ]
+};
+
+/// Get the uncompressed format for a numeric D3DFMT FourCC, if it is one
+fn d3dfmt_format(four_cc: FourCC) -> Option<Format> {
+    let code = u32::from_le_bytes(four_cc.0);
+    if let Some((_, channel_type, channels)) = D3DFMT_CHANNELS.iter().find(|(c, ..)| *c == code) {
+        return Some(Format::Channels {
+            channel_type: *channel_type,
+            channels: *channels,
+        });
+    }
+    let (_, pitch, [r_mask, g_mask, b_mask, alpha_mask], luminance) =
+        D3DFMT.iter().find(|(c, ..)| *c == code)?;
+    Some(Format::Uncompressed {
+        pitch: *pitch,
+        color_format: match ([r_mask, g_mask, b_mask], luminance) {
+            (_, true) => ColorFormat::L { l_mask: *r_mask },
+            ([0, 0, 0], false) => ColorFormat::None,
+            (_, false) => ColorFormat::RGB {
+                r_mask: *r_mask,
+                g_mask: *g_mask,
+                b_mask: *b_mask,
+                srgb: false,
+            },
+        },
+        alpha_format: match alpha_mask {
+            0 => AlphaFormat::Opaque,
+            alpha_mask => AlphaFormat::Custom {
+                alpha_mask: *alpha_mask,
+            },
+        },
+    })
+}
+
 impl TryFrom<PixelFormat> for Format {
     type Error = TextureError;
 
@@ -213,9 +300,9 @@ impl TryFrom<PixelFormat> for Format {
                     b"BC4S" => Ok(BC4 { signed: true }), // BC4 Signed
                     b"ATI2" | b"BC5U" => Ok(BC5 { signed: false }), // BC5 Unsigned
                     b"BC5S" => Ok(BC5 { signed: true }), // BC5 Signed
-                    four_cc => Err(TextureError::Format(format!(
-                        "Unknown FourCC code: '{four_cc:?}'",
-                    ))),
+                    four_cc => Ok(
+                        d3dfmt_format(FourCC(*four_cc)).unwrap_or(Unknown { four_cc: *four_cc })
+                    ),
                 }
             }
             PixelFormat::Uncompressed {
@@ -243,6 +330,12 @@ impl TryFrom<Format> for PixelFormat {
     type Error = TextureError;
 
     fn try_from(format: Format) -> Result<Self, Self::Error> {
+        // there is no sRGB flag or FourCC, so writing one would silently drop it
+        if format.srgb() {
+            return Err(TextureError::Format(format!(
+                "PixelFormat cannot express sRGB formats: {format:?}"
+            )));
+        }
         #[allow(unreachable_patterns)]
         match format {
             Format::BC1 { .. } => Ok(PixelFormat::FourCC(b"DXT1".into())),
@@ -269,6 +362,7 @@ impl TryFrom<Format> for PixelFormat {
 }
 
 impl PixelFormat {
+    /// If this pixel format indicates that the real format is stored in a DX10 header
     pub fn is_dx10(&self) -> bool {
         match self {
             PixelFormat::FourCC(FourCC(four_cc)) if four_cc == b"DX10" => true,
@@ -276,6 +370,7 @@ impl PixelFormat {
         }
     }
 
+    /// The pixel format used by files with a DX10 header
     pub fn dx10() -> Self {
         PixelFormat::FourCC(FourCC(*b"DX10"))
     }
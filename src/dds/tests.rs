@@ -10,15 +10,15 @@ use std::fs::File;
 use std::io::Read;
 
 use anyhow::Result;
-use binrw::{BinRead, BinWrite};
+use binrw::{BinWrite, Endian};
 use generic_parameterize::parameterize;
 
 use crate::container::ContainerHeader;
 use crate::dimensions::{Dimensioned, Dimensions};
-use crate::format::{AlphaFormat, ColorFormat, Format};
+use crate::format::{AlphaFormat, ChannelType, ColorFormat, Format};
 use crate::shape::{CubeFace, TextureShape};
 
-use super::DDSHeader;
+use super::{fixtures, strip_top_mips, DDSHeader};
 
 const DDS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/images/dds");
 
@@ -152,7 +152,7 @@ fn roundtrip(texpath: String) -> Result<()> {
     let mut outbuffer_writer = Cursor::new(&mut outbuffer);
     let out_header = DDSHeader::from_texture(&in_texture)?;
     out_header.write(&mut outbuffer_writer)?;
-    out_header.write_surfaces(&mut outbuffer_writer, in_texture.surfaces)?;
+    out_header.write_surfaces(&mut outbuffer_writer, &in_texture.surfaces)?;
 
     assert_eq!(outbuffer[..4], b"DDS "[..], "Magic bytes are not 'DDS '");
     assert_eq!(
@@ -188,3 +188,1225 @@ fn roundtrip_cubemap() -> Result<()> {
     let cubepath = format!("{DDS_DIR}/cubemap.dds");
     roundtrip(cubepath)
 }
+
+#[test]
+#[cfg(feature = "image")]
+/// Decode the nvassemble cubemap, and compare it to the colors of the generated fixtures
+fn decode_cubemap() -> Result<()> {
+    let mut reader = File::open(format!("{DDS_DIR}/cubemap.dds"))?;
+    let decoded = DDSHeader::read_texture(&mut reader)?.decode()?;
+    fixtures::check_cubemap_colors(&decoded, |face| match face {
+        CubeFace::PositiveZ | CubeFace::NegativeZ => 31,
+        _ => 32,
+    });
+    Ok(())
+}
+
+#[parameterize(layout = ["rgb", "bgr", "rgba", "bgra"], fmt = "generated_cubemap_{layout}")]
+#[test]
+/// Read generated cubemaps with each channel order, checking the masks written to the header
+/// and how the reader and decoder interpret them
+fn generated_cubemap(layout: &str) -> Result<()> {
+    use std::io::Cursor;
+
+    let layout = fixtures::MaskLayout::from_name(layout);
+    let file = fixtures::cubemap_dds(layout, 8)?;
+
+    // legacy pixel format: bit count, then red, green, blue, and alpha masks
+    let field = |offset: usize| u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+    assert_eq!(field(88), layout.pitch() as u32 * 8, "bit count");
+    assert_eq!(
+        [field(92), field(96), field(100), field(104)],
+        layout.masks(),
+        "channel masks"
+    );
+
+    let texture = DDSHeader::read_texture(&mut Cursor::new(&file))?;
+    assert_eq!(texture.format, layout.format());
+    assert_eq!(texture.faces().map(|f| f.len()), Some(6));
+    assert_eq!(texture.dimensions(), Dimensions::try_from([8, 8])?);
+
+    #[cfg(feature = "image")]
+    fixtures::check_cubemap_colors(&texture.decode()?, |_| 1);
+    Ok(())
+}
+
+#[parameterize(
+    layout = ["rgba", "bgra"],
+    fmt = "dx10_uncompressed_{layout}"
+)]
+#[test]
+/// Uncompressed layouts that DXGI can describe round-trip through a DX10 header
+fn dx10_uncompressed(layout: &str) -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::dds::{DDSHeaderArgs, DDSHeaderMode, DXGIFormat};
+
+    let layout = fixtures::MaskLayout::from_name(layout);
+    let texture = fixtures::cubemap(layout, 8)?;
+    let args = DDSHeaderArgs::default().mode(DDSHeaderMode::ForceDX10);
+    let mut file = Cursor::new(Vec::new());
+    DDSHeader::write_texture_args(&mut file, &texture, &args)?;
+
+    file.set_position(0);
+    let expected = match layout {
+        fixtures::MaskLayout::Rgba => DXGIFormat::R8G8B8A8UNorm,
+        _ => DXGIFormat::B8G8R8A8UNorm,
+    };
+    assert!(matches!(
+        DDSHeader::read_header(&mut file)?,
+        DDSHeader::DX10 { dxgi_format, .. } if dxgi_format == expected
+    ));
+
+    file.set_position(0);
+    let read = DDSHeader::read_texture(&mut file)?;
+    assert_eq!(read.format, layout.format());
+    #[cfg(feature = "image")]
+    fixtures::check_cubemap_colors(&read.decode()?, |_| 1);
+    Ok(())
+}
+
+#[test]
+/// DXGI formats are read as the equivalent channel masks, float and 16-bit formats as typed channels,
+/// and packed float formats are rejected
+fn dxgi_uncompressed_formats() -> Result<()> {
+    use super::dx10_header::try_into_format;
+    use super::{AlphaMode, DXGIFormat};
+
+    let format = |f| try_into_format(&f, &AlphaMode::Unknown);
+    assert_eq!(
+        format(DXGIFormat::R8G8B8A8UNormSRGB)?,
+        Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0xFF,
+                g_mask: 0xFF00,
+                b_mask: 0xFF0000,
+                srgb: true
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xFF000000
+            },
+        }
+    );
+    assert_eq!(format(DXGIFormat::R8G8)?, format(DXGIFormat::R8G8UNorm)?);
+    assert!(matches!(
+        format(DXGIFormat::R10G10B10A2UNorm)?,
+        Format::Uncompressed {
+            color_format: ColorFormat::RGB {
+                b_mask: 0x3FF00000,
+                ..
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xC0000000
+            },
+            ..
+        }
+    ));
+    assert!(matches!(
+        format(DXGIFormat::A8UNorm)?,
+        Format::Uncompressed {
+            pitch: 1,
+            color_format: ColorFormat::None,
+            ..
+        }
+    ));
+    assert_eq!(
+        format(DXGIFormat::R16G16B16A16Float)?,
+        Format::Channels {
+            channel_type: ChannelType::Float16,
+            channels: 4
+        }
+    );
+    assert_eq!(
+        format(DXGIFormat::R32Float)?,
+        Format::Channels {
+            channel_type: ChannelType::Float32,
+            channels: 1
+        }
+    );
+    assert_eq!(
+        format(DXGIFormat::R16G16B16A16)?,
+        format(DXGIFormat::R16G16B16A16UNorm)?
+    );
+    assert!(format(DXGIFormat::R11G11B10Float).is_err());
+    Ok(())
+}
+
+#[test]
+/// The DX10 alpha mode of uncompressed formats is read into their alpha format, and written back out
+fn dxgi_alpha_modes() -> Result<()> {
+    use super::dx10_header::{try_from_format, try_into_format};
+    use super::{AlphaMode, DXGIFormat};
+
+    let alpha_mask = 0xFF000000;
+    for (mode, alpha_format) in [
+        (AlphaMode::Straight, AlphaFormat::Straight { alpha_mask }),
+        (
+            AlphaMode::Premultiplied,
+            AlphaFormat::Premultiplied { alpha_mask },
+        ),
+        (AlphaMode::Custom, AlphaFormat::Custom { alpha_mask }),
+    ] {
+        let format = try_into_format(&DXGIFormat::B8G8R8A8UNorm, &mode)?;
+        assert!(
+            matches!(format, Format::Uncompressed { alpha_format: a, .. } if a == alpha_format),
+            "{mode:?} read as {format:?}"
+        );
+        assert_eq!(
+            try_from_format(format)?,
+            (DXGIFormat::B8G8R8A8UNorm, mode),
+            "{mode:?} was not preserved"
+        );
+    }
+
+    // opaque alpha drops the alpha channel, so it's written without one
+    let format = try_into_format(&DXGIFormat::B8G8R8A8UNorm, &AlphaMode::Opaque)?;
+    assert_eq!(
+        try_from_format(format)?,
+        (DXGIFormat::B8G8R8X8UNorm, AlphaMode::Opaque)
+    );
+
+    // formats without alpha are always opaque, and written as such
+    let format = try_into_format(&DXGIFormat::B8G8R8X8UNorm, &AlphaMode::Premultiplied)?;
+    assert!(matches!(
+        format,
+        Format::Uncompressed {
+            alpha_format: AlphaFormat::Opaque,
+            ..
+        }
+    ));
+    assert_eq!(
+        try_from_format(format)?,
+        (DXGIFormat::B8G8R8X8UNorm, AlphaMode::Opaque)
+    );
+
+    // opaque RGBA has no DXGI format without alpha, so it uses the alpha mode instead
+    let opaque = Format::Uncompressed {
+        pitch: 4,
+        color_format: ColorFormat::RGB {
+            r_mask: 0xFF,
+            g_mask: 0xFF00,
+            b_mask: 0xFF0000,
+            srgb: false,
+        },
+        alpha_format: AlphaFormat::Opaque,
+    };
+    assert_eq!(
+        try_from_format(opaque)?,
+        (DXGIFormat::R8G8B8A8UNorm, AlphaMode::Opaque)
+    );
+    Ok(())
+}
+
+#[test]
+/// Read a BC3 texture made using nvcompress, with a width that is not a multiple of the block size.
+fn read_drill() -> Result<()> {
+    let mut reader = File::open(format!("{DDS_DIR}/drill.dds"))?;
+    let texture = DDSHeader::read_texture(&mut reader)?;
+
+    assert_eq!(texture.format, Format::BC3 { srgb: false });
+    assert_eq!(texture.dimensions(), Dimensions::try_from([110, 256])?);
+    assert_eq!(
+        texture.mips(),
+        Some(9),
+        "nvcompress generates a full mip chain"
+    );
+    assert_eq!(texture.faces(), None);
+    assert_eq!(texture.layers(), None);
+    for (mip, surface) in texture.iter_mips() {
+        let mip = mip.unwrap();
+        let surface = surface.try_into_surface().unwrap();
+        let expected = Dimensions::try_from([(110 >> mip).max(1), (256 >> mip).max(1)])?;
+        assert_eq!(
+            surface.dimensions(),
+            expected,
+            "Incorrect dimensions for mip {mip}"
+        );
+    }
+
+    // make sure there's no more data to read
+    let mut remainder = Vec::new();
+    reader.read_to_end(&mut remainder)?;
+    assert_eq!(remainder.len(), 0, "Data left unread in file");
+    Ok(())
+}
+
+#[test]
+fn roundtrip_drill() -> Result<()> {
+    roundtrip(format!("{DDS_DIR}/drill.dds"))
+}
+
+/// Read files laid out the way texconv writes them, and check that their metadata is read the way DirectXTex reads it
+#[parameterize(
+    fixture = ["legacy_mips", "legacy_cubemap", "legacy_volume", "dx10_mips", "dx10_array", "dx10_cubemap",
+        "dx10_cubemap_array", "dx10_volume", "dx10_array_1d"],
+    fmt = "read_texconv_{fixture}"
+)]
+#[test]
+fn read_texconv(fixture: &str) -> Result<()> {
+    use std::io::Cursor;
+
+    let fixture = fixtures::TexconvFixture::from_name(fixture);
+    let meta = fixture.metadata();
+    let bytes = fixture.bytes();
+    assert_eq!(fixtures::texconv_metadata(&bytes)?, meta);
+
+    let expected = match meta.dimension {
+        1 => Dimensions::try_from([meta.width])?,
+        2 => Dimensions::try_from([meta.width, meta.height])?,
+        _ => Dimensions::try_from([meta.width, meta.height, meta.depth])?,
+    };
+    let layers = meta.array_size / if meta.cubemap { 6 } else { 1 };
+    let mut reader = Cursor::new(&bytes);
+    match (fixture.dx10(), DDSHeader::read_header(&mut reader)?) {
+        (
+            false,
+            DDSHeader::Legacy {
+                dimensions, faces, ..
+            },
+        ) => {
+            assert_eq!(dimensions, expected);
+            assert_eq!(faces.map(|f| f.len()), meta.cubemap.then_some(6));
+        }
+        (
+            true,
+            DDSHeader::DX10 {
+                dimensions,
+                layers: header_layers,
+                is_cubemap,
+                ..
+            },
+        ) => {
+            assert_eq!(dimensions, expected);
+            assert_eq!(header_layers.unwrap_or(1), layers);
+            assert_eq!(is_cubemap, meta.cubemap);
+        }
+        (_, header) => panic!("read the wrong kind of header: {header:?}"),
+    }
+
+    reader.set_position(0);
+    let texture = DDSHeader::read_texture(&mut reader)?;
+    assert_eq!(texture.format, fixture.format());
+    assert_eq!(texture.dimensions(), expected);
+    assert_eq!(texture.mips().unwrap_or(1), meta.mip_levels as usize);
+    assert_eq!(texture.layers(), (layers > 1).then_some(layers as usize));
+    assert_eq!(texture.faces().map(|f| f.len()), meta.cubemap.then_some(6));
+    assert_eq!(
+        reader.position() as usize,
+        bytes.len(),
+        "Data left unread in file"
+    );
+    Ok(())
+}
+
+/// Rewrite texconv-style files, and check that texconv would read back the same metadata and surfaces
+#[parameterize(
+    fixture = ["legacy_mips", "legacy_cubemap", "legacy_volume", "dx10_mips", "dx10_array", "dx10_cubemap",
+        "dx10_cubemap_array", "dx10_volume", "dx10_array_1d"],
+    fmt = "roundtrip_texconv_{fixture}"
+)]
+#[test]
+fn roundtrip_texconv(fixture: &str) -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::dds::{DDSHeaderArgs, DDSHeaderMode};
+
+    let fixture = fixtures::TexconvFixture::from_name(fixture);
+    let meta = fixture.metadata();
+    let bytes = fixture.bytes();
+    let texture = DDSHeader::read_texture(&mut Cursor::new(&bytes))?;
+
+    let (mode, header_size) = match fixture.dx10() {
+        true => (DDSHeaderMode::ForceDX10, 148),
+        false => (DDSHeaderMode::ForceLegacy, 128),
+    };
+    let mut file = Cursor::new(Vec::new());
+    DDSHeader::write_texture_args(&mut file, &texture, &DDSHeaderArgs::default().mode(mode))?;
+    let written = file.into_inner();
+
+    assert_eq!(fixtures::texconv_metadata(&written)?, meta);
+    assert_eq!(written.len(), header_size + meta.payload_size());
+    assert_eq!(
+        written[header_size..],
+        bytes[header_size..],
+        "Surface data doesnt match"
+    );
+    Ok(())
+}
+
+#[parameterize(format_name=["bc1", "bc4", "lumi", "rgb"], fmt="strip_mips_peppers16_{format_name}")]
+#[test]
+fn strip_mips(format_name: &str) -> Result<()> {
+    use std::io::Cursor;
+
+    let texpath = format!("{DDS_DIR}/peppers16 {format_name}.dds");
+    let original = DDSHeader::read_texture(&mut File::open(&texpath)?)?;
+
+    let mut stripped = Cursor::new(Vec::new());
+    strip_top_mips(&mut File::open(&texpath)?, &mut stripped, 2)?;
+    stripped.set_position(0);
+    let texture = DDSHeader::read_texture(&mut stripped)?;
+
+    assert_eq!(texture.format, original.format);
+    assert_eq!(texture.mips(), Some(3));
+    assert_eq!(texture.dimensions(), Dimensions::try_from([4, 4])?);
+    for (mip, (_, surface)) in texture.iter_mips().enumerate() {
+        let expected = original.get_mip(mip + 2).unwrap().primary();
+        assert_eq!(
+            surface.primary().buffer,
+            expected.buffer,
+            "mip {mip} differs"
+        );
+    }
+
+    let error = strip_top_mips(&mut File::open(&texpath)?, &mut Cursor::new(Vec::new()), 5);
+    assert!(error.is_err(), "can't remove every mip");
+
+    // a header claiming more mips than a 16x16 texture can have must be rejected, not panic
+    let mut bytes = Vec::new();
+    File::open(&texpath)?.read_to_end(&mut bytes)?;
+    // the mip count is the 7th field of the header, after the magic
+    bytes[28..32].copy_from_slice(&20u32.to_le_bytes());
+    let error = strip_top_mips(&mut Cursor::new(bytes), &mut Cursor::new(Vec::new()), 10);
+    assert!(error.is_err(), "mip count larger than the mip chain");
+    Ok(())
+}
+
+#[test]
+fn hostile_dimensions() -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::dimensions::DimensionError;
+    use crate::error::TextureError;
+
+    let mut bytes = Vec::new();
+    File::open(format!("{DDS_DIR}/peppers16 bc1.dds"))?.read_to_end(&mut bytes)?;
+    // height and width are the 4th and 5th fields of the header, after the magic
+    bytes[12..20].copy_from_slice(&[0xFF; 8]);
+
+    // the claimed surface is far larger than the file, which must fail without trying to allocate it
+    assert!(DDSHeader::read_texture(&mut Cursor::new(&bytes)).is_err());
+
+    // a DX10 header claiming billions of array layers must fail once the file runs out,
+    // without allocating anything for the layers that aren't there
+    let mut file = Cursor::new(Vec::new());
+    let texture =
+        DDSHeader::read_texture(&mut File::open(format!("{DDS_DIR}/peppers16 bc1.dds"))?)?;
+    let args = super::DDSHeaderArgs::default().mode(super::DDSHeaderMode::ForceDX10);
+    DDSHeader::write_texture_args(&mut file, &texture, &args)?;
+    // array_size is the 4th field of the DX10 header, which follows the 128 byte legacy header
+    let mut bytes = file.into_inner();
+    bytes[140..144].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(DDSHeader::read_texture(&mut Cursor::new(&bytes)).is_err());
+
+    let volume = Dimensions::try_from([u32::MAX; 3])?;
+    assert!(matches!(
+        Format::BC1 { srgb: false }.size_for(volume),
+        Err(TextureError::Dimensions(DimensionError::Overflow(_)))
+    ));
+    Ok(())
+}
+
+#[test]
+fn unknown_bits() -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::container::Diagnostic;
+
+    let texpath = format!("{DDS_DIR}/peppers16 bc1.dds");
+    let mut bytes = Vec::new();
+    File::open(&texpath)?.read_to_end(&mut bytes)?;
+    // set an undefined bit in the flags field, and DDSCAPS_ALPHA in the caps field
+    bytes[8] |= 0x10;
+    bytes[108] |= 0x02;
+
+    let original = DDSHeader::read_texture(&mut File::open(&texpath)?)?;
+    let (texture, diagnostics) =
+        DDSHeader::read_texture_with_diagnostics(&mut Cursor::new(&bytes))?;
+    assert_eq!(texture.format, original.format);
+    assert_eq!(texture.mips(), original.mips());
+    assert_eq!(
+        diagnostics,
+        [
+            Diagnostic::UnknownBits {
+                field: "flags",
+                bits: 0x10
+            },
+            Diagnostic::UnknownBits {
+                field: "caps",
+                bits: 0x02
+            },
+        ]
+    );
+
+    let (_, diagnostics) = DDSHeader::read_texture_with_diagnostics(&mut File::open(&texpath)?)?;
+    assert!(diagnostics.is_empty());
+    Ok(())
+}
+
+#[test]
+fn header_error_context() -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::error::TextureError;
+
+    let mut bytes = Vec::new();
+    File::open(format!("{DDS_DIR}/peppers16 bc1.dds"))?.read_to_end(&mut bytes)?;
+
+    // the file ends in the middle of the width field
+    let Err(TextureError::Header(error)) = DDSHeader::read_texture(&mut Cursor::new(&bytes[..18]))
+    else {
+        panic!("truncated header should fail to parse")
+    };
+    assert_eq!(error.container, Some("DDS"));
+    assert_eq!(error.field.as_deref(), Some("width"));
+
+    bytes[0] = b'X';
+    let Err(TextureError::Header(error)) = DDSHeader::read_texture(&mut Cursor::new(&bytes)) else {
+        panic!("bad magic should fail to parse")
+    };
+    assert_eq!(error.offset, Some(0));
+    assert!(error
+        .to_string()
+        .starts_with("Error in DDS header at byte 0x0: bad magic"));
+    Ok(())
+}
+
+#[test]
+fn unknown_fourcc() -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::container::{Diagnostic, ReadOptions};
+    use crate::error::TextureError;
+
+    let mut bytes = Vec::new();
+    File::open(format!("{DDS_DIR}/peppers16 bc1.dds"))?.read_to_end(&mut bytes)?;
+    bytes[84..88].copy_from_slice(b"QTX1");
+
+    assert!(matches!(
+        DDSHeader::read_texture(&mut Cursor::new(&bytes)),
+        Err(TextureError::Format(_))
+    ));
+
+    let options = ReadOptions::default().passthrough_unknown(true);
+    let (texture, diagnostics) =
+        DDSHeader::read_texture_options(&mut Cursor::new(&bytes), &options)?;
+    assert_eq!(texture.format, Format::Unknown { four_cc: *b"QTX1" });
+    assert_eq!(
+        diagnostics,
+        [Diagnostic::UnknownFormat { four_cc: *b"QTX1" }]
+    );
+    let surface = texture.slice().try_into_surface().unwrap();
+    assert_eq!(surface.dimensions(), Dimensions::try_from([16, 16])?);
+    assert_eq!(surface.buffer[..], bytes[128..]);
+
+    // opaque textures can't be written back out
+    assert!(DDSHeader::write_texture(&mut Cursor::new(Vec::new()), &texture).is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "image")]
+fn d3dfmt_fourcc() -> Result<()> {
+    use std::io::Cursor;
+
+    use super::{FourCC, PixelFormat};
+
+    // old D3DX tooling writes D3DFMT codes as numeric FourCCs
+    let file = |code: u32, pixel: &[u8]| -> Result<Vec<u8>> {
+        let header = DDSHeader::Legacy {
+            dimensions: Dimensions::try_from([2, 2])?,
+            mips: None,
+            faces: None,
+            format: PixelFormat::FourCC(FourCC(code.to_le_bytes())),
+            endian: Endian::Little,
+            diagnostics: Vec::new(),
+        };
+        let mut file = Cursor::new(Vec::new());
+        header.write(&mut file)?;
+        file.get_mut().extend(pixel.repeat(4));
+        Ok(file.into_inner())
+    };
+
+    // D3DFMT_A8R8G8B8
+    let bytes = file(21, &[0x30, 0x20, 0x10, 0x80])?;
+    let texture = DDSHeader::read_texture(&mut Cursor::new(&bytes))?;
+    assert_eq!(
+        texture.format,
+        Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0xFF0000,
+                g_mask: 0xFF00,
+                b_mask: 0xFF,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Custom {
+                alpha_mask: 0xFF000000
+            },
+        }
+    );
+    let decoded = texture.decode()?;
+    let surface = decoded.slice().try_into_surface().unwrap();
+    assert_eq!(surface.buffer[..4], [0x10, 0x20, 0x30, 0x80]);
+
+    // D3DFMT_A8L8
+    let bytes = file(51, &[0x40, 0xC0])?;
+    let texture = DDSHeader::read_texture(&mut Cursor::new(&bytes))?;
+    assert_eq!(
+        texture.format,
+        Format::Uncompressed {
+            pitch: 2,
+            color_format: ColorFormat::L { l_mask: 0xFF },
+            alpha_format: AlphaFormat::Custom { alpha_mask: 0xFF00 },
+        }
+    );
+
+    // D3DFMT_A16B16G16R16F has half float channels
+    let bytes = file(113, &[0x00, 0x3C, 0x00, 0x44, 0x00, 0x00, 0x00, 0x3C])?;
+    let texture = DDSHeader::read_texture(&mut Cursor::new(&bytes))?;
+    assert_eq!(
+        texture.format,
+        Format::Channels {
+            channel_type: ChannelType::Float16,
+            channels: 4
+        }
+    );
+    let decoded = texture.format.decoder()?.decode_buffer(
+        texture.primary().buffer.clone(),
+        texture.primary().dimensions(),
+    )?;
+    assert_eq!(decoded.to_rgba32f().get_pixel(1, 1).0, [1.0, 4.0, 0.0, 1.0]);
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "image")]
+fn big_endian() -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::dds::{DDSHeaderArgs, DDSHeaderMode};
+    use crate::format::Quality;
+    use crate::registry::read_any_texture;
+    use crate::texture::{SurfaceOrder, Texture};
+
+    // a console tool's copy of a file has every u32 of the header reversed
+    let mut bytes = Vec::new();
+    File::open(format!("{DDS_DIR}/peppers16 bc1.dds"))?.read_to_end(&mut bytes)?;
+    let mut swapped = bytes.clone();
+    swapped[..128].chunks_exact_mut(4).for_each(<[u8]>::reverse);
+    assert_eq!(&swapped[..4], b" SDD");
+    let header = DDSHeader::read_header(&mut Cursor::new(&swapped))?;
+    assert_eq!(header.endian(), Endian::Big);
+    let texture = DDSHeader::read_texture(&mut Cursor::new(&swapped))?;
+    let expected = DDSHeader::read_texture(&mut Cursor::new(&bytes))?;
+    assert_eq!(texture.format, Format::BC1 { srgb: false });
+    assert_eq!(
+        texture.payload(SurfaceOrder::FACE_MAJOR),
+        expected.payload(SurfaceOrder::FACE_MAJOR)
+    );
+    assert_eq!(
+        read_any_texture(&mut Cursor::new(&swapped))?.format,
+        texture.format
+    );
+
+    // 32-bit pixels are stored reversed as well, for both kinds of header
+    let texture = Texture::from_image(
+        &image::RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 4])).into(),
+        Format::rgba8(false),
+        false,
+        Quality::Normal,
+    )?;
+    for mode in [DDSHeaderMode::ForceLegacy, DDSHeaderMode::ForceDX10] {
+        let args = DDSHeaderArgs::default().mode(mode).endian(Endian::Big);
+        let mut file = Cursor::new(Vec::new());
+        DDSHeader::write_texture_args(&mut file, &texture, &args)?;
+        let file = file.into_inner();
+        assert_eq!(&file[..4], b" SDD");
+        assert_eq!(&file[file.len() - 4..], [4, 3, 2, 1]);
+
+        let read = DDSHeader::read_texture(&mut Cursor::new(&file))?;
+        assert!(matches!(read.format, Format::Uncompressed { pitch: 4, .. }));
+        assert_eq!(read.primary().buffer, texture.primary().buffer);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "image")]
+/// BC7 is only described by DX10 headers, so round-tripping it goes through the DXGI format mapping
+fn roundtrip_bc7() -> Result<()> {
+    use std::io::Cursor;
+
+    use super::DXGIFormat;
+    use crate::texture::Texture;
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/images/peppers16.png");
+    let image = image::open(path)?;
+    let texture = Texture::from_image(
+        &image,
+        Format::BC7 { srgb: true },
+        true,
+        crate::format::Quality::Normal,
+    )?;
+
+    let mut file = Cursor::new(Vec::new());
+    DDSHeader::write_texture(&mut file, &texture)?;
+    file.set_position(0);
+    let header = DDSHeader::read_header(&mut file)?;
+    assert!(matches!(
+        header,
+        DDSHeader::DX10 {
+            dxgi_format: DXGIFormat::BC7UNormSRGB,
+            ..
+        }
+    ));
+
+    file.set_position(0);
+    let read = DDSHeader::read_texture(&mut file)?;
+    assert_eq!(read.format, texture.format);
+    assert_eq!(read.mips(), Some(5));
+    assert!(read
+        .slice()
+        .iter()
+        .zip(texture.slice().iter())
+        .all(|(a, b)| a.surface.buffer == b.surface.buffer));
+
+    // and decodes close to the original
+    let decoded = read.decode()?;
+    let mip0 = decoded.surfaces().get_mip(0).unwrap();
+    let buffer = &mip0.try_into_surface().unwrap().buffer;
+    let original = image.into_rgba8();
+    let error = buffer
+        .iter()
+        .zip(original.as_raw())
+        .map(|(a, b)| a.abs_diff(*b) as f64)
+        .sum::<f64>()
+        / buffer.len() as f64;
+    assert!(error < 12.0, "mean error of {error} is too high");
+    Ok(())
+}
+
+const SNAPSHOT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots/dds");
+
+/// Compare header bytes against a golden hex dump in [`SNAPSHOT_DIR`].
+/// Set `QUICKTEX_UPDATE_SNAPSHOTS` to rewrite the dumps after an intended change, and review the diff
+fn check_snapshot(name: &str, header: &[u8]) -> Result<()> {
+    use anyhow::Context;
+    use itertools::Itertools;
+
+    let dump = header
+        .chunks(16)
+        .enumerate()
+        .map(|(i, row)| {
+            let bytes = row.iter().map(|b| format!("{b:02x}")).join(" ");
+            format!("{:04x}: {bytes}\n", i * 16)
+        })
+        .collect::<String>();
+    let path = format!("{SNAPSHOT_DIR}/{name}.hex");
+    if std::env::var_os("QUICKTEX_UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(SNAPSHOT_DIR)?;
+        std::fs::write(&path, dump)?;
+        return Ok(());
+    }
+    let expected = std::fs::read_to_string(&path).with_context(|| {
+        format!("Missing snapshot {path}, run with QUICKTEX_UPDATE_SNAPSHOTS=1 to create it")
+    })?;
+    assert_eq!(dump, expected, "header bytes for {name} changed");
+    Ok(())
+}
+
+fn snapshot_format(name: &str) -> Format {
+    match name {
+        "bc1" => Format::BC1 { srgb: false },
+        "bc3" => Format::BC3 { srgb: false },
+        "bc4" => Format::BC4 { signed: false },
+        "bc5" => Format::BC5 { signed: false },
+        "rgba8" => Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0xFF,
+                g_mask: 0xFF00,
+                b_mask: 0xFF0000,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xFF000000,
+            },
+        },
+        "l8" => Format::Uncompressed {
+            pitch: 1,
+            color_format: ColorFormat::L { l_mask: 0xFF },
+            alpha_format: AlphaFormat::Opaque,
+        },
+        _ => unreachable!("unknown snapshot format {name}"),
+    }
+}
+
+fn snapshot_texture(shape_name: &str, format: Format) -> Result<crate::texture::Texture> {
+    use strum::VariantArray;
+
+    use crate::shape::TextureShapeNode;
+    use crate::texture::{Surface, Surfaces, Texture};
+
+    let surface = |d: Dimensions| {
+        TextureShapeNode::from_surface(Surface::new(d, vec![0u8; format.size_for(d).unwrap()]))
+    };
+    let mips = |d: Dimensions| Surfaces::try_from_mips(d.mips().map(surface));
+    let faces = |faces: &[CubeFace], node: &Surfaces| {
+        Surfaces::try_from_faces(faces.iter().map(|f| (*f, node.clone())))
+    };
+    let surfaces = match shape_name {
+        "single" => surface(Dimensions::try_from([16, 16])?),
+        "mips" => mips(Dimensions::try_from([16, 16])?)?,
+        "cubemap" => faces(CubeFace::VARIANTS, &surface(Dimensions::try_from([8, 8])?))?,
+        "cubemap_mips" => faces(CubeFace::VARIANTS, &mips(Dimensions::try_from([8, 8])?)?)?,
+        "partial_cubemap" => faces(
+            &[CubeFace::PositiveX, CubeFace::NegativeZ],
+            &surface(Dimensions::try_from([8, 8])?),
+        )?,
+        "volume" => surface(Dimensions::try_from([8, 8, 4])?),
+        _ => unreachable!("unknown snapshot shape {shape_name}"),
+    };
+    Ok(Texture::new(format, surfaces)?)
+}
+
+#[parameterize(
+    shape = ["single", "mips", "cubemap", "cubemap_mips", "partial_cubemap", "volume"],
+    format_name = ["bc1", "bc3", "bc4", "bc5", "rgba8", "l8"],
+    fmt = "header_snapshot_{shape}_{format_name}"
+)]
+#[test]
+fn header_snapshot(shape: &str, format_name: &str) -> Result<()> {
+    use std::io::Cursor;
+
+    let texture = snapshot_texture(shape, snapshot_format(format_name))?;
+    let mut file = Cursor::new(Vec::new());
+    DDSHeader::from_texture(&texture)?.write(&mut file)?;
+    assert_eq!(file.get_ref().len(), 128);
+    check_snapshot(&format!("{shape}_{format_name}"), file.get_ref())
+}
+
+#[test]
+fn dx10_header_snapshots() -> Result<()> {
+    use std::io::Cursor;
+
+    use super::{AlphaMode, DXGIFormat, FourCC, PixelFormat};
+
+    let dx10 = |dimensions: [u32; 3], mips, layers, is_cubemap, dxgi_format| -> Result<_> {
+        let dimensions = match dimensions {
+            [w, 1, 1] => Dimensions::try_from([w])?,
+            [w, h, 1] => Dimensions::try_from([w, h])?,
+            d => Dimensions::try_from(d)?,
+        };
+        Ok(DDSHeader::DX10 {
+            dimensions,
+            mips,
+            layers,
+            is_cubemap,
+            dxgi_format,
+            alpha_mode: AlphaMode::Unknown,
+            endian: Endian::Little,
+            diagnostics: Vec::new(),
+        })
+    };
+    let headers = [
+        (
+            "dx10_array_mips",
+            dx10(
+                [16, 16, 1],
+                Some(5),
+                Some(3),
+                false,
+                DXGIFormat::BC1UNormSRGB,
+            )?,
+        ),
+        (
+            "dx10_cubemap",
+            dx10([8, 8, 1], None, None, true, DXGIFormat::R8G8B8A8UNorm)?,
+        ),
+        (
+            "dx10_cubemap_array",
+            dx10([8, 8, 1], Some(4), Some(2), true, DXGIFormat::BC3UNorm)?,
+        ),
+        (
+            "dx10_volume",
+            dx10([8, 8, 4], None, None, false, DXGIFormat::B8G8R8A8UNorm)?,
+        ),
+        (
+            "dx10_1d_array",
+            dx10([16, 1, 1], None, Some(2), false, DXGIFormat::R8G8B8A8UNorm)?,
+        ),
+    ];
+    for (name, header) in headers {
+        let mut file = Cursor::new(Vec::new());
+        header.write(&mut file)?;
+        assert_eq!(file.get_ref().len(), 148);
+        check_snapshot(name, file.get_ref())?;
+    }
+
+    // a hand-made header with a format this crate doesn't know is still written, with no linear size
+    let unknown = DDSHeader::Legacy {
+        dimensions: Dimensions::try_from([16, 16])?,
+        mips: None,
+        faces: None,
+        format: PixelFormat::FourCC(FourCC(*b"QTX1")),
+        endian: Endian::Little,
+        diagnostics: Vec::new(),
+    };
+    let mut file = Cursor::new(Vec::new());
+    unknown.write(&mut file)?;
+    check_snapshot("unknown_fourcc", file.get_ref())
+}
+
+#[test]
+#[cfg(feature = "image")]
+/// Decode a BC1 block in 3-color mode, where code 3 is transparent black
+fn decode_3color_black() -> Result<()> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/3color_black.dds");
+    let texture = DDSHeader::read_texture(&mut File::open(path)?)?;
+    assert_eq!(texture.format, Format::BC1 { srgb: false });
+
+    let decoded = texture.decode()?;
+    assert_eq!(decoded.format, Format::rgba8(false));
+    let surface = decoded.slice().try_into_surface().unwrap();
+    let row: Vec<_> = surface.buffer[..16].chunks(4).collect();
+    // green and red endpoints, with codes 3, 1, 2, 0 in every row
+    assert_eq!(
+        row,
+        [
+            [0, 0, 0, 0],
+            [255, 0, 0, 255],
+            [128, 128, 0, 255],
+            [0, 255, 0, 255]
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "image")]
+/// Decoding BC1 should come close to the uncompressed original. The image is small and noisy, so BC1 errors are large
+fn decode_peppers16_bc1() -> Result<()> {
+    let read = |name: &str| -> Result<_> {
+        let mut reader = File::open(format!("{DDS_DIR}/peppers16 {name}.dds"))?;
+        let texture = DDSHeader::read_texture(&mut reader)?.decode()?;
+        // compare every mip, in order
+        Ok(texture
+            .slice()
+            .iter()
+            .flat_map(|s| s.surface.buffer.to_vec())
+            .collect::<Vec<_>>())
+    };
+    let (bc1, rgb) = (read("bc1")?, read("rgb")?);
+    assert_eq!(bc1.len(), rgb.len());
+    let error = bc1
+        .iter()
+        .zip(rgb.iter())
+        .map(|(a, b)| a.abs_diff(*b) as f64)
+        .sum::<f64>()
+        / bc1.len() as f64;
+    assert!(error < 12.0, "mean error of {error} is too high");
+    Ok(())
+}
+
+#[cfg(feature = "image")]
+#[parameterize(format_name = ["bc4", "bc5"], fmt = "decode_peppers16_{format_name}")]
+#[test]
+/// Decoding BC4 and BC5 should come close to the red and green channels of the uncompressed original
+fn decode_peppers16_channels(format_name: &str) -> Result<()> {
+    let read = |name: &str| -> Result<_> {
+        let mut reader = File::open(format!("{DDS_DIR}/peppers16 {name}.dds"))?;
+        let texture = DDSHeader::read_texture(&mut reader)?.decode()?;
+        Ok(texture
+            .slice()
+            .iter()
+            .flat_map(|s| s.surface.buffer.to_vec())
+            .collect::<Vec<_>>())
+    };
+    let (decoded, rgb) = (read(format_name)?, read("rgb")?);
+    assert_eq!(decoded.len(), rgb.len());
+    let channels = if format_name == "bc4" { 1 } else { 2 };
+    for c in 0..channels {
+        let error = decoded
+            .iter()
+            .zip(rgb.iter())
+            .skip(c)
+            .step_by(4)
+            .map(|(a, b)| a.abs_diff(*b) as f64)
+            .sum::<f64>()
+            / (decoded.len() / 4) as f64;
+        assert!(
+            error < 8.0,
+            "mean error of {error} in channel {c} is too high"
+        );
+    }
+    // channels the format doesn't store are black and opaque
+    assert!(decoded.chunks(4).all(|p| p[2] == 0 && p[3] == 255));
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "image")]
+/// Decode the BC3 drill texture, and check it against the image it was made from
+fn decode_drill() -> Result<()> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/images/drill.png");
+    let original = image::open(path)?.into_rgba8();
+    let mean_error = |texture: &crate::texture::Texture| -> Result<f64> {
+        let mip = texture.surfaces().get_mip(0).unwrap();
+        let surface = mip.try_into_surface().unwrap().decode(texture.format)?;
+        assert_eq!(surface.buffer.len(), original.as_raw().len());
+        Ok(surface
+            .buffer
+            .iter()
+            .zip(original.as_raw())
+            .map(|(a, b)| a.abs_diff(*b) as f64)
+            .sum::<f64>()
+            / surface.buffer.len() as f64)
+    };
+
+    let mut reader = File::open(format!("{DDS_DIR}/drill.dds"))?;
+    let texture = DDSHeader::read_texture(&mut reader)?;
+    let error = mean_error(&texture)?;
+    assert!(error < 4.0, "mean error of {error} is too high");
+
+    // re-encoding the decoded texture stays close to the original
+    let encoded = texture.transcode(Format::BC3 { srgb: false }, crate::format::Quality::Normal)?;
+    assert_eq!(encoded.payload_size(), texture.payload_size());
+    let error = mean_error(&encoded)?;
+    assert!(
+        error < 6.0,
+        "mean error of {error} after re-encoding is too high"
+    );
+    Ok(())
+}
+
+#[parameterize(header = ["legacy", "dx10"], fmt = "volume_{header}")]
+#[test]
+fn volume(header: &str) -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::error::TextureError;
+    use crate::texture::{Surface, Surfaces, Texture};
+
+    use crate::dds::{DDSHeaderArgs, DDSHeaderMode};
+
+    let format = Format::BC1 { srgb: false };
+    let dimensions = Dimensions::try_from([8, 8, 4])?;
+    // fill each mip with its own index, so reading the wrong amount of data for any mip is caught
+    let mips = dimensions.mips().enumerate().map(|(m, d)| {
+        Surfaces::from_surface(Surface::new(d, vec![m as u8; format.size_for(d).unwrap()]))
+    });
+    let texture = Texture::new(format, Surfaces::try_from_mips(mips)?)?;
+
+    let args = DDSHeaderArgs::default().mode(match header {
+        "legacy" => DDSHeaderMode::ForceLegacy,
+        _ => DDSHeaderMode::ForceDX10,
+    });
+    let mut file = Cursor::new(Vec::new());
+    DDSHeader::write_texture_args(&mut file, &texture, &args)?;
+    file.set_position(0);
+    let read = DDSHeader::read_texture(&mut file)?;
+
+    assert_eq!(read.mips(), Some(4));
+    for (m, (_, mip)) in read.iter_mips().enumerate() {
+        let surface = mip.primary();
+        assert_eq!(surface.dimensions(), dimensions.mips().nth(m).unwrap());
+        // each mip has half the depth slices of the one before it
+        assert_eq!(surface.buffer.len(), [128, 16, 8, 8][m]);
+        assert!(
+            surface.buffer.iter().all(|&b| b == m as u8),
+            "mip {m} differs"
+        );
+    }
+
+    let array = Texture::new(
+        format,
+        Surfaces::try_from_layers([texture.surfaces.clone(), texture.surfaces.clone()])?,
+    )?;
+    assert!(matches!(
+        DDSHeader::write_texture_args(&mut Cursor::new(Vec::new()), &array, &args),
+        Err(TextureError::Capability(_))
+    ));
+    Ok(())
+}
+
+/// Legacy headers can't express sRGB, so sRGB textures are written with DX10 headers instead of losing it
+#[test]
+fn srgb_dx10() -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::dds::{DDSHeaderArgs, DDSHeaderMode};
+    use crate::error::TextureError;
+    use crate::texture::{Surface, Surfaces, Texture};
+
+    use super::DXGIFormat;
+
+    for (format, dxgi_format) in [
+        (Format::BC1 { srgb: true }, DXGIFormat::BC1UNormSRGB),
+        (Format::BC3 { srgb: true }, DXGIFormat::BC3UNormSRGB),
+        (Format::rgba8(true), DXGIFormat::R8G8B8A8UNormSRGB),
+    ] {
+        let dimensions = Dimensions::try_from([4, 4])?;
+        let surface = Surface::new(dimensions, vec![0; format.size_for(dimensions)?]);
+        let texture = Texture::new(format, Surfaces::from_surface(surface))?;
+
+        let header = DDSHeader::from_texture(&texture)?;
+        assert!(
+            matches!(header, DDSHeader::DX10 { dxgi_format: d, .. } if d == dxgi_format),
+            "{format:?} wrote {header:?}"
+        );
+        let mut file = Cursor::new(Vec::new());
+        DDSHeader::write_texture(&mut file, &texture)?;
+        file.set_position(0);
+        assert_eq!(DDSHeader::read_texture(&mut file)?.format, format);
+
+        let legacy = DDSHeaderArgs::default().mode(DDSHeaderMode::ForceLegacy);
+        assert!(matches!(
+            DDSHeader::from_texture_args(&texture, &legacy),
+            Err(TextureError::Format(_))
+        ));
+    }
+    Ok(())
+}
+
+/// Textures with float and 16-bit channels are written with DX10 headers and read back unchanged
+#[cfg(feature = "image")]
+#[parameterize(endian = ["little", "big"], fmt = "channels_{endian}")]
+#[test]
+fn channels(endian: &str) -> Result<()> {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, Rgba, Rgba32FImage};
+
+    use crate::dds::{DDSHeaderArgs, DXGIFormat};
+    use crate::format::Quality;
+    use crate::texture::{SurfaceOrder, Texture};
+
+    let endian = match endian {
+        "big" => Endian::Big,
+        _ => Endian::Little,
+    };
+    let image = DynamicImage::from(Rgba32FImage::from_fn(4, 4, |x, y| {
+        Rgba([x as f32 * 4.0, y as f32 / 4.0, -0.5, 1.0])
+    }));
+    for (channel_type, channels, dxgi_format) in [
+        (ChannelType::Float32, 4, DXGIFormat::R32G32B32A32Float),
+        (ChannelType::Float16, 2, DXGIFormat::R16G16Float),
+        (ChannelType::UNorm16, 4, DXGIFormat::R16G16B16A16UNorm),
+        (ChannelType::SNorm8, 1, DXGIFormat::R8SNorm),
+    ] {
+        let format = Format::Channels {
+            channel_type,
+            channels,
+        };
+        let texture = Texture::from_image(&image, format, true, Quality::Normal)?;
+        let header = DDSHeader::from_texture(&texture)?;
+        assert!(
+            matches!(header, DDSHeader::DX10 { dxgi_format: d, .. } if d == dxgi_format),
+            "{format:?} wrote {header:?}"
+        );
+
+        let mut file = Cursor::new(Vec::new());
+        let args = DDSHeaderArgs::default().endian(endian);
+        DDSHeader::write_texture_args(&mut file, &texture, &args)?;
+        file.set_position(0);
+        let read = DDSHeader::read_texture(&mut file)?;
+        assert_eq!(read.format, format);
+        assert_eq!(read.mips(), texture.mips());
+        assert_eq!(
+            read.payload(SurfaceOrder::FACE_MAJOR),
+            texture.payload(SurfaceOrder::FACE_MAJOR)
+        );
+    }
+
+    // HDR values above 1 survive the trip
+    let texture = Texture::from_image(
+        &image,
+        Format::Channels {
+            channel_type: ChannelType::Float32,
+            channels: 4,
+        },
+        false,
+        Quality::Normal,
+    )?;
+    let surface = texture.primary();
+    let decoded =
+        (texture.format.decoder()?).decode_buffer(surface.buffer.clone(), surface.dimensions())?;
+    assert_eq!(
+        decoded.to_rgba32f().get_pixel(3, 0).0,
+        [12.0, 0.0, -0.5, 1.0]
+    );
+    Ok(())
+}
+
+/// ASTC textures are written with DX10 headers, so `.astc` payloads can be repackaged as DDS files
+#[test]
+fn astc_dx10() -> Result<()> {
+    use std::io::Cursor;
+
+    use crate::astc::ASTCHeader;
+    use crate::texture::{Surface, Surfaces, Texture};
+
+    use super::DXGIFormat;
+
+    for (dims, srgb, dxgi_format) in [
+        ([4, 4], false, DXGIFormat::ASTC4X4UNorm),
+        ([10, 8], true, DXGIFormat::ASTC10X8UNormSRGB),
+        ([12, 12], false, DXGIFormat::ASTC12X12UNorm),
+    ] {
+        let format = Format::ASTC {
+            block_dims: Dimensions::try_from(dims)?,
+            srgb,
+        };
+        let dimensions = Dimensions::try_from([20, 20])?;
+        let buffer = (0..format.size_for(dimensions)?)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let texture = Texture::new(
+            format,
+            Surfaces::from_surface(Surface::new(dimensions, buffer)),
+        )?;
+
+        let header = DDSHeader::from_texture(&texture)?;
+        assert!(
+            matches!(header, DDSHeader::DX10 { dxgi_format: d, .. } if d == dxgi_format),
+            "{format:?} wrote {header:?}"
+        );
+        let mut astc = Cursor::new(Vec::new());
+        ASTCHeader::write_texture(&mut astc, &texture)?;
+        astc.set_position(0);
+        let mut dds = Cursor::new(Vec::new());
+        DDSHeader::write_texture(&mut dds, &ASTCHeader::read_texture(&mut astc)?)?;
+        dds.set_position(0);
+        let read = DDSHeader::read_texture(&mut dds)?;
+        assert_eq!(
+            read.format,
+            Format::ASTC {
+                block_dims: Dimensions::try_from(dims)?,
+                srgb: false
+            },
+            "ASTC files are always linear"
+        );
+        assert_eq!(
+            read.slice().try_into_surface().unwrap().buffer,
+            texture.slice().try_into_surface().unwrap().buffer
+        );
+    }
+
+    let volume = Format::ASTC {
+        block_dims: Dimensions::try_from([4, 4, 4])?,
+        srgb: false,
+    };
+    let dimensions = Dimensions::try_from([4, 4, 4])?;
+    let texture = Texture::new(
+        volume,
+        Surfaces::from_surface(Surface::new(dimensions, vec![0; 16])),
+    )?;
+    assert!(
+        DDSHeader::from_texture(&texture).is_err(),
+        "DXGI has no 3D ASTC formats"
+    );
+    Ok(())
+}
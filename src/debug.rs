@@ -0,0 +1,182 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Debug overlays that make it visible in-engine which mip level is being sampled,
+//! or how a texture's UVs are laid out
+
+use image::{DynamicImage, Rgba};
+
+use crate::dimensions::Dimensioned;
+use crate::error::TextureResult;
+use crate::format::Quality;
+use crate::texture::{Surface, Texture};
+
+/// Tint colors for each mip level, starting from mip 0. Mips past the end of the list reuse the last color
+pub const MIP_TINTS: [[u8; 3]; 8] = [
+    [255, 0, 0],
+    [255, 128, 0],
+    [255, 255, 0],
+    [0, 255, 0],
+    [0, 255, 255],
+    [0, 0, 255],
+    [255, 0, 255],
+    [255, 255, 255],
+];
+
+/// The pattern a debug overlay blends onto each mip
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugPattern {
+    /// A solid color per mip level, from [`MIP_TINTS`]
+    #[default]
+    MipTint,
+    /// A gradient with red increasing along U and green increasing along V, the same on every mip
+    UvGradient,
+}
+
+/// Options for [`Texture::with_debug_overlay`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugOverlay {
+    /// The pattern to overlay
+    pub pattern: DebugPattern,
+    /// How strongly the pattern replaces the original colors, from 0 (unchanged) to 1 (only the pattern).
+    /// Alpha is always left unchanged
+    pub strength: f32,
+    /// Quality tier used to re-encode each surface
+    pub quality: Quality,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            pattern: DebugPattern::default(),
+            strength: 0.5,
+            quality: Quality::default(),
+        }
+    }
+}
+
+impl DebugOverlay {
+    /// The overlay color of pixel (`x`, `y`) in a `width` by `height` surface at mip level `mip`
+    fn color(&self, mip: usize, x: u32, y: u32, width: u32, height: u32) -> [u8; 3] {
+        match self.pattern {
+            DebugPattern::MipTint => MIP_TINTS[mip.min(MIP_TINTS.len() - 1)],
+            DebugPattern::UvGradient => {
+                // sample at pixel centers, so a single pixel mip is half way along each axis
+                let uv = |p: u32, size: u32| ((p * 2 + 1) * 255 / (size * 2)) as u8;
+                [uv(x, width), uv(y, height), 0]
+            }
+        }
+    }
+
+    fn apply(&self, image: DynamicImage, mip: usize) -> DynamicImage {
+        let strength = self.strength.clamp(0.0, 1.0);
+        let mut image = image.into_rgba8();
+        let (width, height) = image.dimensions();
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let [r, g, b] = self.color(mip, x, y, width, height);
+            let blend =
+                |c: u8, o: u8| (c as f32 * (1.0 - strength) + o as f32 * strength).round() as u8;
+            let Rgba([pr, pg, pb, pa]) = *pixel;
+            *pixel = Rgba([blend(pr, r), blend(pg, g), blend(pb, b), pa]);
+        }
+        image.into()
+    }
+}
+
+impl Texture {
+    /// Blend a debug pattern onto every surface of the texture, decoding and re-encoding each surface
+    /// in the texture's own format. The mip level of each surface is found from its dimensions,
+    /// so every face and layer of a mip gets the same overlay.
+    /// Returns [`TextureError::Format`](crate::error::TextureError::Format) if the format does not have a codec
+    pub fn with_debug_overlay(&self, overlay: &DebugOverlay) -> TextureResult<Self> {
+        let decoder = self.format.decoder()?;
+        let encoder = self.format.encoder()?;
+        let top = self.dimensions();
+        let surfaces = self.surfaces.try_map(|s| -> TextureResult<Surface> {
+            let mip = top.mips().position(|d| d == s.dimensions).unwrap_or(0);
+            let image = decoder.decode_buffer(s.buffer.clone(), s.dimensions)?;
+            let image = overlay.apply(image, mip);
+            Ok(Surface::new(
+                s.dimensions,
+                encoder.encode_buffer(image, overlay.quality),
+            ))
+        })?;
+        Self::new(self.format, surfaces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use image::RgbaImage;
+
+    use crate::format::{AlphaFormat, ColorFormat, Format};
+
+    use super::*;
+
+    fn rgba8() -> Format {
+        Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0xFF,
+                g_mask: 0xFF00,
+                b_mask: 0xFF0000,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xFF000000,
+            },
+        }
+    }
+
+    fn mip(texture: &Texture, mip: usize) -> Result<RgbaImage> {
+        let surface = texture
+            .slice()
+            .iter()
+            .find(|s| s.mip == Some(mip))
+            .unwrap()
+            .surface;
+        Ok(texture
+            .format
+            .decoder()?
+            .decode_buffer(surface.buffer.clone(), surface.dimensions)?
+            .into_rgba8())
+    }
+
+    #[test]
+    fn mip_tint() -> Result<()> {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 100]));
+        let texture = Texture::from_image(&image.into(), rgba8(), true, Quality::Normal)?;
+
+        let tinted = texture.with_debug_overlay(&DebugOverlay {
+            strength: 1.0,
+            ..Default::default()
+        })?;
+        for (m, [r, g, b]) in MIP_TINTS.into_iter().enumerate().take(3) {
+            assert!(mip(&tinted, m)?.pixels().all(|p| p.0 == [r, g, b, 100]));
+        }
+
+        let half = texture.with_debug_overlay(&DebugOverlay::default())?;
+        assert_eq!(mip(&half, 1)?.get_pixel(0, 0).0, [128, 64, 0, 100]);
+        Ok(())
+    }
+
+    #[test]
+    fn uv_gradient() -> Result<()> {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+        let texture = Texture::from_image(&image.into(), rgba8(), true, Quality::Normal)?;
+        let overlay = DebugOverlay {
+            pattern: DebugPattern::UvGradient,
+            strength: 1.0,
+            ..Default::default()
+        };
+        let gradient = texture.with_debug_overlay(&overlay)?;
+
+        let mip0 = mip(&gradient, 0)?;
+        assert_eq!(mip0.get_pixel(0, 0).0, [31, 31, 0, 255]);
+        assert_eq!(mip0.get_pixel(3, 1).0, [223, 95, 0, 255]);
+        assert_eq!(mip(&gradient, 2)?.get_pixel(0, 0).0, [127, 127, 0, 255]);
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Readable error reports through the `miette` crate. Requires the `miette` feature.
+//!
+//! [`TextureError`] implements [`miette::Diagnostic`], giving every error a code like `quicktex::header::magic`
+//! and help text suggesting a fix. Errors don't know which file they came from,
+//! so [`FileReport`](crate::diagnostics::FileReport) pairs an error with the file's bytes,
+//! and shows header errors with a hex dump of the bytes around the offending offset
+
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceCode, SourceSpan};
+
+use crate::dimensions::DimensionError;
+use crate::error::{HeaderError, TextureError};
+
+/// Number of bytes shown on each line of a [`FileReport`](crate::diagnostics::FileReport) hex dump
+const DUMP_WIDTH: usize = 16;
+
+/// Number of lines shown before the offending byte in a [`FileReport`] hex dump
+const DUMP_CONTEXT: usize = 4;
+
+impl HeaderError {
+    /// The error code for this error, such as `quicktex::header::magic`
+    fn code(&self) -> &'static str {
+        use binrw::Error::*;
+        match self.source.root_cause() {
+            BadMagic { .. } => "quicktex::header::magic",
+            Io(e) if e.kind() == ErrorKind::UnexpectedEof => "quicktex::header::truncated",
+            AssertFail { .. } => "quicktex::header::invalid",
+            _ => "quicktex::header",
+        }
+    }
+
+    /// Help text for this error
+    fn help(&self) -> String {
+        use binrw::Error::*;
+        let container = self.container.unwrap_or("texture");
+        match self.source.root_cause() {
+            BadMagic { .. } => format!("The file is not a {container} file, or its start is corrupted"),
+            Io(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                "The file ends partway through its header, so it is probably truncated".to_string()
+            }
+            _ => format!(
+                "The {container} header has a value quicktex doesn't support, or the file is corrupted"
+            ),
+        }
+    }
+}
+
+impl Diagnostic for TextureError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let code = match self {
+            TextureError::Header(e) => e.code(),
+            TextureError::IO(e) if e.kind() == ErrorKind::UnexpectedEof => "quicktex::truncated",
+            TextureError::IO(_) => "quicktex::io",
+            TextureError::Dimensions(_) => "quicktex::dimensions",
+            TextureError::Shape(_) => "quicktex::shape",
+            TextureError::Format(_) => "quicktex::format",
+            TextureError::SurfaceSize { .. } => "quicktex::surface_size",
+            TextureError::Capability(_) => "quicktex::capability",
+            TextureError::Other(_) => "quicktex::other",
+        };
+        Some(Box::new(code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let help = match self {
+            TextureError::Header(e) => e.help(),
+            TextureError::IO(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                "The file ends before all of its surfaces, so it is probably truncated".to_string()
+            }
+            TextureError::Dimensions(DimensionError::Overflow(_)) => {
+                "The texture is too large to fit in memory".to_string()
+            }
+            TextureError::Dimensions(_) => {
+                "Textures have 1 to 3 axes, each at least 1 pixel".to_string()
+            }
+            TextureError::Shape(_) => "Each mip must be half the size of the last down to 1x1, \
+                and every face and layer must have the same size, mips, and format"
+                .to_string(),
+            TextureError::Format(_) => "Check that the file is a texture, or convert it to a supported format \
+                with `quicktex convert --format`"
+                .to_string(),
+            TextureError::SurfaceSize { .. } => {
+                "The surface data doesn't match its format and dimensions. Check that the right format was chosen"
+                    .to_string()
+            }
+            TextureError::Capability(_) => {
+                "Write the texture to a container that supports it, such as DDS or qtx".to_string()
+            }
+            TextureError::IO(_) | TextureError::Other(_) => return None,
+        };
+        Some(Box::new(help))
+    }
+}
+
+/// A [`TextureError`] along with the file it came from, for showing the bytes an error points at.
+/// Header errors with a known offset are labeled in a hex dump of the file around that offset,
+/// and truncated files are labeled at their last byte
+#[derive(Debug)]
+pub struct FileReport {
+    /// The error being reported
+    pub error: TextureError,
+    source_code: NamedSource<String>,
+    label: Option<(String, SourceSpan)>,
+}
+
+impl FileReport {
+    /// Report `error`, which happened while reading `bytes` from the file called `name`
+    pub fn new(error: TextureError, name: impl AsRef<str>, bytes: &[u8]) -> Self {
+        let (offset, label) = match &error {
+            TextureError::Header(
+                e @ HeaderError {
+                    offset: Some(offset),
+                    field,
+                    ..
+                },
+            ) => {
+                let label = match field {
+                    Some(field) => format!("in `{field}`"),
+                    None if e.code() == "quicktex::header::magic" => "unexpected magic".to_string(),
+                    None => "here".to_string(),
+                };
+                (Some(*offset as usize), label)
+            }
+            TextureError::Header(e) if e.code() == "quicktex::header::truncated" => {
+                (bytes.len().checked_sub(1), "file ends here".to_string())
+            }
+            TextureError::IO(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                (bytes.len().checked_sub(1), "file ends here".to_string())
+            }
+            _ => (None, String::new()),
+        };
+        let offset = offset.map(|o| o.min(bytes.len().saturating_sub(1)));
+        let (dump, span) = match offset {
+            Some(offset) if !bytes.is_empty() => {
+                let (dump, span) = hex_dump(bytes, offset);
+                (dump, Some(span))
+            }
+            _ => (String::new(), None),
+        };
+        Self {
+            error,
+            source_code: NamedSource::new(name, dump),
+            label: span.map(|s| (label, s)),
+        }
+    }
+}
+
+/// A hex dump of `bytes` around `offset`, and the span of `offset`'s byte in the dump.
+/// Each line starts with the offset of its first byte, so the dump reads like `xxd` output
+fn hex_dump(bytes: &[u8], offset: usize) -> (String, SourceSpan) {
+    let first_line = (offset / DUMP_WIDTH).saturating_sub(DUMP_CONTEXT);
+    let last_line = offset / DUMP_WIDTH + 1;
+    let mut dump = String::new();
+    let mut span = SourceSpan::from((0, 0));
+    for line in first_line..=last_line {
+        let start = line * DUMP_WIDTH;
+        let Some(chunk) = bytes.get(start..(start + DUMP_WIDTH).min(bytes.len())) else {
+            break;
+        };
+        if chunk.is_empty() {
+            break;
+        }
+        dump.push_str(&format!("{start:08x} "));
+        for (i, byte) in chunk.iter().enumerate() {
+            dump.push(' ');
+            if start + i == offset {
+                span = SourceSpan::from((dump.len(), 2));
+            }
+            dump.push_str(&format!("{byte:02x}"));
+        }
+        dump.push('\n');
+    }
+    (dump, span)
+}
+
+impl Display for FileReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for FileReport {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl Diagnostic for FileReport {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.error.code()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.error.help()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.label
+            .as_ref()
+            .map(|_| &self.source_code as &dyn SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let (label, span) = self.label.as_ref()?;
+        Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+            Some(label.clone()),
+            *span,
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+
+    use crate::container::ContainerHeader;
+    use crate::dds::DDSHeader;
+    use crate::registry::read_any_texture;
+
+    use super::*;
+
+    fn render(report: FileReport) -> String {
+        let mut output = String::new();
+        miette::GraphicalReportHandler::new_themed(miette::GraphicalTheme::unicode_nocolor())
+            .render_report(&mut output, &report)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn header() -> Result<()> {
+        let mut bytes = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fuzz/seeds/dx10_bc7_srgb.dds"
+        ))?;
+        bytes[0] = b'X';
+        let error = DDSHeader::read_texture(&mut Cursor::new(&bytes)).unwrap_err();
+        assert_eq!(error.code().unwrap().to_string(), "quicktex::header::magic");
+        assert!(error.help().unwrap().to_string().contains("not a DDS file"));
+
+        let output = render(FileReport::new(error, "broken.dds", &bytes));
+        assert!(output.contains("quicktex::header::magic"), "{output}");
+        assert!(output.contains("broken.dds"), "{output}");
+        assert!(output.contains("00000000  58 44 53 20"), "{output}");
+        assert!(output.contains("unexpected magic"), "{output}");
+        Ok(())
+    }
+
+    #[test]
+    fn truncated() -> Result<()> {
+        let bytes = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fuzz/seeds/rgba8_mips.pvr"
+        ))?;
+        let short = &bytes[..bytes.len() - 2];
+        let error = read_any_texture(&mut Cursor::new(short)).unwrap_err();
+        assert_eq!(error.code().unwrap().to_string(), "quicktex::truncated");
+
+        let report = FileReport::new(error, "short.pvr", short);
+        let (label, span) = report.label.clone().unwrap();
+        assert_eq!(label, "file ends here");
+        let dump = report.source_code.inner();
+        assert_eq!(&dump[span.offset()..span.offset() + 2], "c9");
+
+        let error = TextureError::Capability("no".to_string());
+        let output = render(FileReport::new(error, "a.qoi", &bytes));
+        assert!(output.contains("quicktex::capability"), "{output}");
+        assert!(!output.contains("a.qoi"), "{output}");
+        Ok(())
+    }
+
+    #[test]
+    fn dump() {
+        let bytes = (0..=255u8).collect::<Vec<_>>();
+        let (dump, span) = hex_dump(&bytes, 0x93);
+        assert!(dump.starts_with("00000050  50 51"));
+        assert_eq!(dump.lines().count(), 6);
+        assert_eq!(&dump[span.offset()..span.offset() + 2], "93");
+    }
+}
@@ -12,25 +12,37 @@ use thiserror::Error;
 
 use crate::util::AsSlice;
 
+/// Errors that can occur when constructing [`Dimensions`]
 #[derive(Error, Debug)]
 pub enum DimensionError {
+    /// Dimensions must have 1, 2, or 3 axes
     #[error("Dimensions cannot be created with a dimensionality of {0}")]
     Dimensionality(usize),
 
+    /// An axis was zero or out of range
     #[error("Invalid {0}: {1}")]
     Invalid(&'static str, TryFromIntError),
+
+    /// A size computed from the dimensions does not fit in a `usize`
+    #[error("Size of a {0:?} surface overflows")]
+    Overflow(Dimensions),
 }
 
+/// The size of a texture or surface, with 1, 2 or 3 non-zero axes
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum Dimensions {
+    /// Width only
     _1D(NonZeroU32),
+    /// Width and height
     _2D([NonZeroU32; 2]),
+    /// Width, height, and depth
     _3D([NonZeroU32; 3]),
 }
 
 static DIMENSION_NAMES: [&'static str; 3] = ["width", "height", "depth"];
 
 impl Dimensions {
+    /// The number of axes
     pub fn len(self) -> usize {
         match self {
             Dimensions::_1D(_) => 1,
@@ -39,6 +51,7 @@ impl Dimensions {
         }
     }
 
+    /// The size along the first axis
     pub fn width(self) -> u32 {
         match self {
             Dimensions::_1D(width) => width.into(),
@@ -47,6 +60,7 @@ impl Dimensions {
         }
     }
 
+    /// The size along the second axis, or 1 for 1D dimensions
     pub fn height(self) -> u32 {
         match self {
             Dimensions::_1D(_) => 1,
@@ -55,6 +69,7 @@ impl Dimensions {
         }
     }
 
+    /// The size along the third axis, or 1 for 1D and 2D dimensions
     pub fn depth(self) -> u32 {
         match self {
             Dimensions::_3D([.., depth]) => depth.into(),
@@ -62,18 +77,29 @@ impl Dimensions {
         }
     }
 
+    /// The total number of pixels.
+    /// This can overflow for very large dimensions, so sizes derived from untrusted input should use [`Self::try_product`]
     pub fn product(self) -> u32 {
         self.into_iter().product::<u32>()
     }
 
+    /// The total number of pixels, or [`DimensionError::Overflow`] if it does not fit in a `usize`
+    pub fn try_product(self) -> Result<usize, DimensionError> {
+        self.into_iter()
+            .try_fold(1usize, |p, d| p.checked_mul(d as usize))
+            .ok_or(DimensionError::Overflow(self))
+    }
+
+    /// Iterate over the dimensions of a full mip chain, starting with `self` and ending at 1 along every axis
     pub fn mips(self) -> MipDimensionIterator {
         MipDimensionIterator {
             current: Some(self),
         }
     }
 
+    /// The number of blocks of size `block` needed to cover these dimensions, rounding up along each axis
     pub fn blocks(self, block: Dimensions) -> Dimensions {
-        let rounding_divide = |(size, bsize)| -> u32 { (size + (bsize - 1)) / bsize };
+        let rounding_divide = |(size, bsize): (u32, u32)| -> u32 { size.div_ceil(bsize) };
 
         let result_vec = self
             .into_iter()
@@ -85,6 +111,27 @@ impl Dimensions {
             .try_into()
             .expect("Dimensions somehow changed size")
     }
+
+    /// The number of blocks of size `D` needed to cover these dimensions, rounding up along each axis.
+    /// Unlike [`Self::blocks`], the block size is known at compile time and the result always has
+    /// the same number of axes as `self`
+    pub fn blocks_of<D: StaticDimensions>(self) -> Dimensions {
+        let axis = |size: NonZeroU32, block: u32| {
+            // a non-zero size divided by a non-zero block size and rounded up is never zero
+            NonZeroU32::new(size.get().div_ceil(block)).unwrap_or(NonZeroU32::MIN)
+        };
+        match self {
+            Dimensions::_1D(width) => Dimensions::_1D(axis(width, D::WIDTH)),
+            Dimensions::_2D([width, height]) => {
+                Dimensions::_2D([axis(width, D::WIDTH), axis(height, D::HEIGHT)])
+            }
+            Dimensions::_3D([width, height, depth]) => Dimensions::_3D([
+                axis(width, D::WIDTH),
+                axis(height, D::HEIGHT),
+                axis(depth, D::DEPTH),
+            ]),
+        }
+    }
 }
 
 impl Debug for Dimensions {
@@ -127,6 +174,7 @@ impl Into<Vec<u32>> for Dimensions {
     }
 }
 
+/// Error for dimensions with an unsupported number of axes
 #[derive(Error, Debug, Eq, PartialEq)]
 #[error("Dimensions cannot be created with a dimensionality of {0}")]
 pub struct DimensionLengthError(usize);
@@ -139,11 +187,11 @@ impl TryFrom<&[u32]> for Dimensions {
             .map(|(i, d)| NonZeroU32::try_from(*d).map_err(|e| DimensionError::Invalid(i, e)))
             .try_collect()?;
 
-        match inner.len() {
-            1 => Ok(Dimensions::_1D(inner[0])),
-            2 => Ok(Dimensions::_2D(inner.try_into().unwrap())),
-            3 => Ok(Dimensions::_3D(inner.try_into().unwrap())),
-            l => Err(DimensionError::Dimensionality(l)),
+        match inner[..] {
+            [width] => Ok(Dimensions::_1D(width)),
+            [width, height] => Ok(Dimensions::_2D([width, height])),
+            [width, height, depth] => Ok(Dimensions::_3D([width, height, depth])),
+            _ => Err(DimensionError::Dimensionality(inner.len())),
         }
     }
 }
@@ -163,6 +211,7 @@ impl<const N: usize> TryFrom<[u32; N]> for Dimensions {
     }
 }
 
+/// Iterator over the dimensions of a mip chain. See [`Dimensions::mips`]
 pub struct MipDimensionIterator {
     current: Option<Dimensions>,
 }
@@ -171,24 +220,71 @@ impl Iterator for MipDimensionIterator {
     type Item = Dimensions;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let current = self.current;
-        let next: Vec<_> = current?.into();
-
-        if next.iter().all(|&x| x <= 1) {
-            self.current = None; // after mips are all 1, the chain terminates
-        } else {
-            let next: Vec<_> = next.into_iter().map(|x| u32::max(x / 2, 1)).collect();
-
-            self.current = Some(
-                next.try_into()
-                    .expect("Error converting vec back to dimension"),
-            );
-        }
+        let current = self.current?;
+        let half = |x: NonZeroU32| NonZeroU32::new(x.get() / 2).unwrap_or(NonZeroU32::MIN);
+
+        self.current = match current {
+            // after mips are all 1, the chain terminates
+            _ if current.into_iter().all(|x| x <= 1) => None,
+            Dimensions::_1D(width) => Some(Dimensions::_1D(half(width))),
+            Dimensions::_2D(axes) => Some(Dimensions::_2D(axes.map(half))),
+            Dimensions::_3D(axes) => Some(Dimensions::_3D(axes.map(half))),
+        };
+
+        Some(current)
+    }
+}
+
+/// Dimensions known at compile time, such as the size of a compression block or a fixed icon size.
+/// Sizes are associated constants, so code that is generic over a `StaticDimensions` type
+/// doesn't need to construct, check, or unwrap a [`Dimensions`] value at runtime.
+///
+/// ```
+/// use quicktex::dimensions::{Dimensions, Dims2, StaticDimensions};
+///
+/// type Block = Dims2<4, 4>;
+/// assert_eq!(Block::PRODUCT, 16);
+/// assert_eq!(Block::dimensions(), Dimensions::try_from([4, 4])?);
+/// assert_eq!(Dimensions::try_from([10, 3])?.blocks_of::<Block>(), Dimensions::try_from([3, 1])?);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait StaticDimensions: Copy + Default + Debug {
+    /// The size along the first axis
+    const WIDTH: u32;
+    /// The size along the second axis
+    const HEIGHT: u32;
+    /// The size along the third axis
+    const DEPTH: u32 = 1;
+    /// The total number of pixels
+    const PRODUCT: u32 = Self::WIDTH * Self::HEIGHT * Self::DEPTH;
+
+    /// These dimensions as a runtime [`Dimensions`] value
+    fn dimensions() -> Dimensions;
+}
+
+/// 2D dimensions known at compile time. See [`StaticDimensions`].
+/// Using a zero width or height is a compile-time error
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Dims2<const W: u32, const H: u32>;
+
+impl<const W: u32, const H: u32> Dims2<W, H> {
+    const NON_ZERO: [NonZeroU32; 2] = match (NonZeroU32::new(W), NonZeroU32::new(H)) {
+        (Some(width), Some(height)) => [width, height],
+        _ => panic!("Static dimensions must be non-zero"),
+    };
+}
+
+impl<const W: u32, const H: u32> StaticDimensions for Dims2<W, H> {
+    const WIDTH: u32 = W;
+    const HEIGHT: u32 = H;
 
-        current
+    fn dimensions() -> Dimensions {
+        Dimensions::_2D(Self::NON_ZERO)
     }
 }
 
+/// Types with [`Dimensions`]
 pub trait Dimensioned {
+    /// The dimensions of this object
     fn dimensions(&self) -> Dimensions;
 }
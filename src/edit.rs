@@ -0,0 +1,305 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Edit sessions with undo and revert, for building interactive texture editors.
+//!
+//! Surfaces share their buffers between clones of a texture, so an [`EditSession`](crate::edit::EditSession) only copies the one
+//! surface being modified by each edit. Every edit keeps the buffer it replaced for undoing it, and the
+//! session keeps the texture as it was at the last commit for reverting, so unmodified surfaces are never
+//! duplicated no matter how many edits are made
+
+use std::rc::Rc;
+
+use crate::error::{TextureError, TextureResult};
+use crate::shape::{CubeFace, TextureIterResult};
+use crate::texture::{Surface, Texture};
+
+/// The position of a single surface within a texture, as yielded by [`TextureShape::iter`](crate::shape::TextureShape::iter)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SurfaceLocation {
+    /// The array layer of the surface, if the texture is an array
+    pub layer: Option<usize>,
+    /// The cubemap face of the surface, if the texture is a cubemap
+    pub face: Option<CubeFace>,
+    /// The mip level of the surface, if the texture has mipmaps
+    pub mip: Option<usize>,
+}
+
+impl<S> From<&TextureIterResult<S>> for SurfaceLocation {
+    fn from(result: &TextureIterResult<S>) -> Self {
+        Self {
+            layer: result.layer,
+            face: result.face,
+            mip: result.mip,
+        }
+    }
+}
+
+/// A single edit, holding the buffer it replaced
+#[derive(Clone, Debug)]
+struct Edit {
+    location: SurfaceLocation,
+    buffer: Rc<[u8]>,
+}
+
+/// A texture being edited, which records each modification to its surfaces so it can be undone,
+/// redone, reverted, or committed
+#[derive(Clone, Debug)]
+pub struct EditSession {
+    base: Texture,
+    current: Texture,
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl EditSession {
+    /// Start editing a texture
+    pub fn new(texture: Texture) -> Self {
+        Self {
+            base: texture.clone(),
+            current: texture,
+            undo: vec![],
+            redo: vec![],
+        }
+    }
+
+    /// The texture with every edit applied
+    pub fn texture(&self) -> &Texture {
+        &self.current
+    }
+
+    /// The texture as it was when the session started or was last committed
+    pub fn base(&self) -> &Texture {
+        &self.base
+    }
+
+    /// Finish editing, returning the texture with every edit applied
+    pub fn into_texture(self) -> Texture {
+        self.current
+    }
+
+    /// The locations of every surface in the texture, in the order of [`TextureShape::iter`](crate::shape::TextureShape::iter)
+    pub fn locations(&self) -> Vec<SurfaceLocation> {
+        self.current.slice().iter().map(|s| (&s).into()).collect()
+    }
+
+    /// The surface at `location`, with every edit applied
+    pub fn surface(&self, location: SurfaceLocation) -> TextureResult<&Surface> {
+        self.current
+            .slice()
+            .iter()
+            .find(|s| SurfaceLocation::from(s) == location)
+            .map(|s| s.surface)
+            .ok_or_else(|| missing(location))
+    }
+
+    /// Modify the encoded contents of the surface at `location` in place.
+    /// Only that surface is copied, and the previous contents are kept so the edit can be undone
+    pub fn edit<F>(&mut self, location: SurfaceLocation, f: F) -> TextureResult<()>
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        let mut buffer = self.surface(location)?.buffer.to_vec();
+        f(&mut buffer);
+        self.replace(location, buffer)
+    }
+
+    /// Replace the encoded contents of the surface at `location`.
+    /// Returns [`TextureError::SurfaceSize`] if the buffer is the wrong size for the surface and format
+    pub fn replace(
+        &mut self,
+        location: SurfaceLocation,
+        buffer: impl Into<Rc<[u8]>>,
+    ) -> TextureResult<()> {
+        let surface = Surface::new(self.surface(location)?.dimensions, buffer);
+        surface.check_format(self.current.format)?;
+        let previous = self.swap(location, surface.buffer)?;
+        self.undo.push(Edit {
+            location,
+            buffer: previous,
+        });
+        self.redo.clear();
+        Ok(())
+    }
+
+    /// Undo the most recent edit, returning the location of the surface it changed,
+    /// or [`None`] if there is nothing to undo
+    pub fn undo(&mut self) -> Option<SurfaceLocation> {
+        let edit = self.undo.pop()?;
+        let buffer = self.swap(edit.location, edit.buffer).ok()?;
+        self.redo.push(Edit {
+            location: edit.location,
+            buffer,
+        });
+        Some(edit.location)
+    }
+
+    /// Redo the most recently undone edit, returning the location of the surface it changed,
+    /// or [`None`] if there is nothing to redo. Making a new edit clears everything that can be redone
+    pub fn redo(&mut self) -> Option<SurfaceLocation> {
+        let edit = self.redo.pop()?;
+        let buffer = self.swap(edit.location, edit.buffer).ok()?;
+        self.undo.push(Edit {
+            location: edit.location,
+            buffer,
+        });
+        Some(edit.location)
+    }
+
+    /// The number of edits that can be undone
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// The number of edits that can be redone
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// The locations of every surface that differs from [`Self::base`].
+    /// Surfaces are compared by buffer identity, so a surface edited back to its original contents still counts
+    pub fn modified(&self) -> Vec<SurfaceLocation> {
+        let base = self.base.slice();
+        self.current
+            .slice()
+            .iter()
+            .zip(base.iter())
+            .filter(|(c, b)| !Rc::ptr_eq(&c.surface.buffer, &b.surface.buffer))
+            .map(|(c, _)| (&c).into())
+            .collect()
+    }
+
+    /// If any surface differs from [`Self::base`]
+    pub fn is_modified(&self) -> bool {
+        !self.modified().is_empty()
+    }
+
+    /// Discard every edit since the session started or was last committed, along with the undo history
+    pub fn revert(&mut self) {
+        self.current = self.base.clone();
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Keep every edit, making the current texture the new [`Self::base`] and clearing the undo history
+    pub fn commit(&mut self) {
+        self.base = self.current.clone();
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Put `buffer` into the surface at `location`, returning the buffer it replaced
+    fn swap(&mut self, location: SurfaceLocation, buffer: Rc<[u8]>) -> TextureResult<Rc<[u8]>> {
+        let surface = self
+            .current
+            .surfaces
+            .get_surface_mut(location.layer, location.face, location.mip)
+            .ok_or_else(|| missing(location))?;
+        Ok(std::mem::replace(&mut surface.buffer, buffer))
+    }
+}
+
+fn missing(location: SurfaceLocation) -> TextureError {
+    TextureError::Other(format!("Texture has no surface at {location:?}"))
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use anyhow::Result;
+    use image::{Rgba, RgbaImage};
+
+    use crate::format::{Format, Quality};
+    use crate::shape::TextureShape;
+    use crate::texture::SurfaceOrder;
+
+    use super::*;
+
+    fn texture() -> Result<Texture> {
+        let image = RgbaImage::from_fn(8, 8, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        Ok(Texture::from_image(
+            &image.into(),
+            Format::rgba8(false),
+            true,
+            Quality::Normal,
+        )?)
+    }
+
+    fn mip(mip: usize) -> SurfaceLocation {
+        SurfaceLocation {
+            mip: Some(mip),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn undo_redo() -> Result<()> {
+        let mut session = EditSession::new(texture()?);
+        assert_eq!(session.locations(), (0..4).map(mip).collect::<Vec<_>>());
+        assert!(!session.is_modified());
+
+        let green = session.surface(mip(1))?.buffer[1];
+        session.edit(mip(1), |b| b[0] = 200)?;
+        session.edit(mip(1), |b| b[1] = 201)?;
+        session.replace(mip(3), [1, 2, 3, 4])?;
+        assert_eq!(session.modified(), [mip(1), mip(3)]);
+        assert_eq!(&session.surface(mip(1))?.buffer[..2], [200, 201]);
+        // untouched surfaces still share their buffers with the original
+        assert!(Rc::ptr_eq(
+            &session.surface(mip(0))?.buffer,
+            &session.base().primary().buffer
+        ));
+
+        assert_eq!(session.undo(), Some(mip(3)));
+        assert_eq!(session.undo(), Some(mip(1)));
+        assert_eq!(session.surface(mip(1))?.buffer[..2], [200, green]);
+        assert_eq!(session.redo(), Some(mip(1)));
+        assert_eq!(session.surface(mip(1))?.buffer[..2], [200, 201]);
+        assert_eq!((session.undo_len(), session.redo_len()), (2, 1));
+
+        session.edit(mip(2), |b| b[0] = 7)?;
+        assert_eq!(session.redo(), None);
+        while session.undo().is_some() {}
+        assert_eq!(
+            session.texture().payload(SurfaceOrder::FACE_MAJOR),
+            texture()?.payload(SurfaceOrder::FACE_MAJOR)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn revert_commit() -> Result<()> {
+        let mut session = EditSession::new(texture()?);
+        session.edit(mip(0), |b| b.fill(9))?;
+        session.revert();
+        assert!(!session.is_modified());
+        assert_eq!(session.undo(), None);
+
+        session.edit(mip(0), |b| b.fill(9))?;
+        session.commit();
+        assert!(!session.is_modified());
+        assert_eq!(session.undo_len(), 0);
+        session.edit(mip(0), |b| b[0] = 1)?;
+        session.revert();
+        assert!(session
+            .into_texture()
+            .primary()
+            .buffer
+            .iter()
+            .all(|&v| v == 9));
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        let mut session = EditSession::new(texture()?);
+        assert!(session.edit(mip(4), |_| {}).is_err());
+        assert!(session.edit(SurfaceLocation::default(), |_| {}).is_err());
+        assert!(matches!(
+            session.replace(mip(0), [0; 4]),
+            Err(TextureError::SurfaceSize { .. })
+        ));
+        assert_eq!(session.undo_len(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::error::TextureResult;
+use crate::shape::CubeFace;
+use crate::texture::Texture;
+
+/// Compression statistics for a single surface, from [`Texture::entropy_report`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurfaceEntropy {
+    /// The array layer of the surface, if the texture is an array
+    pub layer: Option<usize>,
+
+    /// The cubemap face of the surface, if the texture is a cubemap
+    pub face: Option<CubeFace>,
+
+    /// The mip level of the surface, if the texture has mipmaps
+    pub mip: Option<usize>,
+
+    /// Dimensions of the surface
+    pub dimensions: Dimensions,
+
+    /// Size of the encoded surface in bytes
+    pub size: usize,
+
+    /// Average size of a texel in bytes, including any padding of partial blocks
+    pub bytes_per_texel: f64,
+
+    /// Order-0 Shannon entropy of the encoded bytes, from 0 (every byte is the same) to 8 bits per byte
+    pub entropy: f64,
+
+    /// Number of distinct blocks (or pixels, for uncompressed formats) in the surface.
+    /// Repeated blocks are what dictionary-based supercompression like zstd exploits best
+    pub unique_blocks: usize,
+
+    /// Total number of blocks (or pixels) in the surface
+    pub blocks: usize,
+}
+
+impl SurfaceEntropy {
+    /// Estimated size in bytes after entropy coding, using [`Self::entropy`] as the cost of each byte.
+    /// This ignores correlation between bytes, so real supercompressors often do better
+    pub fn estimated_size(&self) -> usize {
+        (self.size as f64 * self.entropy / 8.0).ceil() as usize
+    }
+
+    /// Estimated fraction of the surface saved by supercompression, from 0 (no gain) to 1
+    pub fn estimated_savings(&self) -> f64 {
+        match self.size {
+            0 => 0.0,
+            size => 1.0 - self.estimated_size() as f64 / size as f64,
+        }
+    }
+}
+
+/// Compression statistics for every surface of a texture, from [`Texture::entropy_report`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntropyReport {
+    /// Statistics for each surface, in the same order as [`TextureSlice::iter`](crate::shape::TextureSlice::iter)
+    pub surfaces: Vec<SurfaceEntropy>,
+}
+
+impl EntropyReport {
+    /// Total size of every surface in bytes
+    pub fn size(&self) -> usize {
+        self.surfaces.iter().map(|s| s.size).sum()
+    }
+
+    /// Total estimated size of every surface after supercompression. See [`SurfaceEntropy::estimated_size`]
+    pub fn estimated_size(&self) -> usize {
+        self.surfaces.iter().map(|s| s.estimated_size()).sum()
+    }
+
+    /// Estimated fraction of the texture saved by supercompression, from 0 (no gain) to 1
+    pub fn estimated_savings(&self) -> f64 {
+        match self.size() {
+            0 => 0.0,
+            size => 1.0 - self.estimated_size() as f64 / size as f64,
+        }
+    }
+}
+
+/// Order-0 Shannon entropy of `bytes`, in bits per byte
+fn shannon_entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in bytes {
+        counts[b as usize] += 1;
+    }
+    let total = bytes.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+impl Texture {
+    /// Measure how compressible each surface of the texture is, to help decide which textures
+    /// are worth moving to a supercompressed container.
+    /// Returns an error if any surface does not match the texture's format
+    pub fn entropy_report(&self) -> TextureResult<EntropyReport> {
+        self.check_format()?;
+        let block_size = self.format.size_for(Dimensions::try_from([1])?)?;
+        let surfaces = self
+            .slice()
+            .iter()
+            .map(|s| {
+                let buffer = &s.surface.buffer;
+                let dimensions = s.surface.dimensions();
+                SurfaceEntropy {
+                    layer: s.layer,
+                    face: s.face,
+                    mip: s.mip,
+                    dimensions,
+                    size: buffer.len(),
+                    bytes_per_texel: buffer.len() as f64 / dimensions.product() as f64,
+                    entropy: shannon_entropy(buffer),
+                    unique_blocks: buffer.chunks(block_size).collect::<HashSet<_>>().len(),
+                    blocks: buffer.len() / block_size,
+                }
+            })
+            .collect();
+        Ok(EntropyReport { surfaces })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::format::Format;
+    use crate::shape::{TextureShape, TextureShapeNode};
+    use crate::texture::{Surface, Surfaces};
+
+    use super::*;
+
+    #[test]
+    fn report() -> Result<()> {
+        // mips 0 and 3 are copies of one block, mips 1 and 2 are a block of every byte value from 0 to 7
+        let format = Format::BC1 { srgb: false };
+        let mips = [
+            Surface::new(Dimensions::try_from([8, 8])?, vec![7u8; 32]),
+            Surface::new(Dimensions::try_from([4, 4])?, (0..8).collect::<Vec<u8>>()),
+            Surface::new(Dimensions::try_from([2, 2])?, (0..8).collect::<Vec<u8>>()),
+            Surface::new(Dimensions::try_from([1, 1])?, vec![0u8; 8]),
+        ];
+        let surfaces = Surfaces::try_from_mips(mips.map(TextureShapeNode::from_surface))?;
+        let report = Texture::new(format, surfaces)?.entropy_report()?;
+
+        let [solid, varied, small, _] = &report.surfaces[..] else {
+            panic!("expected 4 surfaces")
+        };
+        assert_eq!(solid.entropy, 0.0);
+        assert_eq!((solid.unique_blocks, solid.blocks), (1, 4));
+        assert_eq!(solid.estimated_savings(), 1.0);
+        assert_eq!(solid.bytes_per_texel, 0.5);
+
+        assert_eq!(varied.entropy, 3.0);
+        assert_eq!(varied.estimated_size(), 3);
+        assert_eq!(small.mip, Some(2));
+        assert_eq!(small.bytes_per_texel, 2.0, "partial blocks are padded");
+
+        assert_eq!(report.size(), 56);
+        assert_eq!(report.estimated_size(), 6);
+        Ok(())
+    }
+}
@@ -2,34 +2,172 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::dimensions::DimensionError;
+use std::fmt::{Display, Formatter};
+
+use crate::dimensions::{DimensionError, Dimensions};
+use binrw::error::BacktraceFrame;
+use itertools::Itertools;
 use thiserror::Error;
 
+use crate::format::Format;
 use crate::shape::ShapeError;
 use crate::texture::Texture;
 
+/// Errors that can occur while reading, writing, or manipulating a [`Texture`]
 #[derive(Error, Debug)]
 pub enum TextureError {
-    #[error("Error in file header: {0}")]
-    Header(#[from] binrw::error::Error),
+    /// A container header could not be parsed or written
+    #[error(transparent)]
+    Header(#[from] HeaderError),
 
+    /// Surface data could not be read or written
     #[error("IO error in file contents: {0}")]
     IO(#[from] std::io::Error),
 
+    /// Invalid texture dimensions
     #[error(transparent)]
     Dimensions(#[from] DimensionError),
 
+    /// Invalid texture shape
     #[error(transparent)]
     Shape(#[from] ShapeError),
 
+    /// The texture's format is not supported by the operation or container
     #[error("Unsupported format: {0}")]
     Format(String),
 
+    #[error("{dimensions:?} surface has {actual} bytes, but {format:?} requires {expected}")]
+    /// A surface's buffer is the wrong size for its dimensions and format
+    SurfaceSize {
+        /// The format of the texture
+        format: Format,
+        /// The dimensions of the offending surface
+        dimensions: Dimensions,
+        /// The buffer size required by `format`
+        expected: usize,
+        /// The actual buffer size
+        actual: usize,
+    },
+
+    /// The texture cannot be represented by the container
     #[error("Texture exceeds container's capabilities: {0}")]
     Capability(String),
 
+    /// Any other error
     #[error("Other error: {0}")]
     Other(String),
 }
 
+impl TextureError {
+    /// Set the container of a [`TextureError::Header`] error, if it is not already known.
+    /// Other errors are returned unchanged
+    pub fn in_container(self, container: &'static str) -> Self {
+        match self {
+            TextureError::Header(e) => TextureError::Header(e.in_container(container)),
+            e => e,
+        }
+    }
+}
+
+impl From<binrw::Error> for TextureError {
+    fn from(err: binrw::Error) -> Self {
+        TextureError::Header(HeaderError::new(None, err))
+    }
+}
+
+/// An error parsing or writing a container header, with as much context as could be recovered
+/// about where in the header it happened
+#[derive(Error, Debug)]
+pub struct HeaderError {
+    /// The type of container, if known
+    pub container: Option<&'static str>,
+
+    /// Byte offset in the stream where the error occurred, if known
+    pub offset: Option<u64>,
+
+    /// The field that could not be parsed, as a path of field names from the outermost structure
+    /// inward (e.g. `pixel_format.four_cc`), if known
+    pub field: Option<String>,
+
+    /// The underlying error
+    #[source]
+    pub source: binrw::Error,
+}
+
+impl HeaderError {
+    /// Wrap an error from binrw, recovering the offset and field from it where possible
+    pub fn new(container: Option<&'static str>, source: binrw::Error) -> Self {
+        use binrw::Error::*;
+        let offset = match source.root_cause() {
+            BadMagic { pos, .. }
+            | AssertFail { pos, .. }
+            | Custom { pos, .. }
+            | NoVariantMatch { pos }
+            | EnumErrors { pos, .. } => Some(*pos),
+            _ => None,
+        };
+        // binrw adds a frame reading "While parsing field 'name' in Type" for each field the error passed through
+        let field = match &source {
+            Backtrace(backtrace) => {
+                let fields = backtrace
+                    .frames
+                    .iter()
+                    .rev()
+                    .filter_map(|frame| match frame {
+                        BacktraceFrame::Full { message, .. } | BacktraceFrame::Message(message) => {
+                            Some(message)
+                        }
+                        _ => None,
+                    })
+                    .filter_map(|m| m.strip_prefix("While parsing field '")?.split('\'').next())
+                    .collect_vec();
+                (!fields.is_empty()).then(|| fields.join("."))
+            }
+            _ => None,
+        };
+        Self {
+            container,
+            offset,
+            field,
+            source,
+        }
+    }
+
+    /// Set the type of container, if it is not already known
+    pub fn in_container(mut self, container: &'static str) -> Self {
+        self.container.get_or_insert(container);
+        self
+    }
+}
+
+impl Display for HeaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use binrw::Error::*;
+        write!(f, "Error in {} header", self.container.unwrap_or("file"))?;
+        if let Some(field) = &self.field {
+            write!(f, " field `{field}`")?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {offset:#x}")?;
+        }
+        // binrw's own messages include the offset, and backtraces span several lines, so only the cause is shown
+        match self.source.root_cause() {
+            BadMagic { found, .. } => write!(f, ": bad magic {found:?}"),
+            AssertFail { message, .. } => write!(f, ": {message}"),
+            Custom { err, .. } => write!(f, ": {err}"),
+            NoVariantMatch { .. } => write!(f, ": no variants matched"),
+            EnumErrors { variant_errors, .. } => write!(
+                f,
+                ": no variants matched ({})",
+                variant_errors
+                    .iter()
+                    .map(|(name, e)| format!("{name}: {}", e.root_cause()))
+                    .join(", ")
+            ),
+            other => write!(f, ": {other}"),
+        }
+    }
+}
+
+/// Result type for texture operations, defaulting to returning a [`Texture`]
 pub type TextureResult<T = Texture> = Result<T, TextureError>;
@@ -3,115 +3,622 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use std::fmt::Debug;
+#[cfg(feature = "image")]
 use std::rc::Rc;
 
-use image::DynamicImage;
+#[cfg(feature = "image")]
+use image::{DynamicImage, GrayImage};
+use strum::{Display, EnumString, VariantArray};
 
-use crate::dimensions::Dimensions;
-use crate::error::TextureResult;
+#[cfg(feature = "image")]
+use crate::bptc::bc7::{BC7Block, BC7Options};
+#[cfg(feature = "image")]
+use crate::codec::BlockCodec;
+use crate::dimensions::{DimensionError, Dimensions, Dims2, StaticDimensions};
+use crate::error::{TextureError, TextureResult};
+#[cfg(feature = "image")]
+use crate::s3tc::bc1::{BC1Block, BC1Options};
+#[cfg(feature = "image")]
+use crate::s3tc::bc2::BC2Block;
+#[cfg(feature = "image")]
+use crate::s3tc::bc3::{BC3Block, BC3Options};
+#[cfg(feature = "image")]
+use crate::s3tc::bc4::{BC4Block, SignedBC4Block};
+#[cfg(feature = "image")]
+use crate::s3tc::bc5::{BC5Block, SignedBC5Block};
+#[cfg(feature = "image")]
+use crate::uncompressed::{ChannelCodec, UncompressedCodec};
 
+/// Speed/quality tradeoff shared by every encoder in the crate.
+///
+/// Each codec maps these tiers onto its own search strategy, but the ordering is the same
+/// everywhere: a higher tier is never faster and is never expected to be lower quality.
+/// Encoders are deterministic for a given input and quality tier, regardless of thread count,
+/// so identical inputs always produce byte-identical outputs.
+///
+/// Tiers parse from and display as their lowercase names (e.g. `"high"`),
+/// so CLI flags and config files can use them directly.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Display,
+    EnumString,
+    VariantArray,
+)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum Quality {
+    /// Single-pass endpoint selection with no refinement. Faster than [`Normal`](Quality::Normal),
+    /// with visibly worse gradients. Useful for previews and iteration.
+    Fastest,
+
+    /// A single refinement pass after the initial endpoint fit.
+    /// Good quality for most content at interactive speeds.
+    #[default]
+    Normal,
+
+    /// Multiple refinement passes with a wider endpoint search.
+    /// Slower than [`Normal`](Quality::Normal) for a small but measurable quality improvement.
+    High,
+
+    /// Exhaustive search wherever a codec supports it. Much slower than [`High`](Quality::High),
+    /// and is intended for final asset builds.
+    Exhaustive,
+}
+
+/// Arithmetic used to interpolate the palette of BC1, BC2, and BC3 color blocks when decoding.
+///
+/// The D3D specification allows some error when interpolating between endpoints, and GPU vendors
+/// round differently, so the same block can decode to slightly different colors on different hardware.
+/// Decoding with the arithmetic of a specific GPU makes ground-truth comparisons match it exactly.
+/// Every other format decodes the same way in every mode.
+///
+/// Modes parse from and display as their lowercase names (e.g. `"nvidia"`)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Display, EnumString, VariantArray)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum Interpolation {
+    /// Expand the endpoints to 8 bits, then interpolate and round to the nearest value,
+    /// like the D3D reference decoder
+    #[default]
+    Reference,
+
+    /// The arithmetic of NVIDIA GPUs, which interpolate red and blue from their 5-bit endpoints,
+    /// and weigh green slightly towards the nearer endpoint
+    Nvidia,
+
+    /// The arithmetic of AMD GPUs, which use weights of 42/64 and 22/64 instead of thirds
+    Amd,
+
+    /// Interpolate the exact endpoint values as real numbers, and only round the result to 8 bits
+    Ideal,
+}
+
+/// Options for decoding textures, shared by every format's decoder.
+/// Options a format has no use for are ignored.
+/// New options may be added in the future, so create them from [`Default`] with the builder methods
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DecodeOptions {
+    /// The interpolation arithmetic of BC1, BC2, and BC3 color blocks
+    pub interpolation: Interpolation,
+    /// The number of threads to decode with, in a thread pool owned by the decoder.
+    /// [`None`] uses rayon's global thread pool. Defaults to [`None`]
+    pub threads: Option<usize>,
+}
+
+impl DecodeOptions {
+    /// Set the interpolation arithmetic. See [`Self::interpolation`]
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// Set the number of threads. See [`Self::threads`]
+    pub fn threads(mut self, threads: Option<usize>) -> Self {
+        self.threads = threads;
+        self
+    }
+}
+
+/// Options for encoding textures, shared by every format's encoder.
+/// Options a format has no use for are ignored.
+/// New options may be added in the future, so create them from [`Default`] with the builder methods
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EncodeOptions {
+    /// The speed/quality tradeoff
+    pub quality: Quality,
+    /// Pixels with alpha below this are encoded as transparent in formats with 1-bit alpha, like BC1.
+    /// 0 disables transparency. Defaults to 128
+    pub alpha_threshold: u8,
+    /// The number of threads to encode with, in a thread pool owned by the encoder,
+    /// for applications that manage their own threads. [`None`] uses rayon's global thread pool,
+    /// which has a thread per CPU core and is shared with the rest of the process. Defaults to [`None`]
+    pub threads: Option<usize>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            quality: Quality::default(),
+            alpha_threshold: 128,
+            threads: None,
+        }
+    }
+}
+
+impl EncodeOptions {
+    /// Set the quality tier. See [`Self::quality`]
+    pub fn quality(mut self, quality: Quality) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Set the alpha threshold for 1-bit alpha. See [`Self::alpha_threshold`]
+    pub fn alpha_threshold(mut self, threshold: u8) -> Self {
+        self.alpha_threshold = threshold;
+        self
+    }
+
+    /// Set the number of threads. See [`Self::threads`]
+    pub fn threads(mut self, threads: Option<usize>) -> Self {
+        self.threads = threads;
+        self
+    }
+}
+
+/// Quality tiers for region-of-interest weighted encoding, where a weight mask marks
+/// the important parts of an image. See [`Texture::encode_weighted`](crate::texture::Texture::encode_weighted)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RoiQuality {
+    /// Quality tier for blocks where any weight is at least [`Self::threshold`]
+    pub important: Quality,
+    /// Quality tier for every other block
+    pub background: Quality,
+    /// The lowest weight that makes a block important
+    pub threshold: u8,
+}
+
+impl Default for RoiQuality {
+    fn default() -> Self {
+        Self {
+            important: Quality::Exhaustive,
+            background: Quality::Fastest,
+            threshold: 128,
+        }
+    }
+}
+
+impl RoiQuality {
+    /// The quality tier for a block whose highest weight is `weight`
+    pub fn for_weight(&self, weight: u8) -> Quality {
+        match weight >= self.threshold {
+            true => self.important,
+            false => self.background,
+        }
+    }
+}
+
+/// Encodes images into buffers of a particular [`Format`]
+#[cfg(feature = "image")]
 pub trait Encoder {
-    fn encode_buffer(&self, image: DynamicImage) -> Rc<[u8]>;
+    /// Encode `image` into a new buffer at the given quality tier.
+    /// The image is a single 1D or 2D slice, [`Texture::encode`](crate::texture::Texture::encode) encodes
+    /// each slice of a 3D surface separately
+    fn encode_buffer(&self, image: DynamicImage, quality: Quality) -> Rc<[u8]>;
+
+    /// Encode `image` into a new buffer, choosing the quality tier of each block from the weights under it.
+    /// `weights` is scaled to cover the whole image, so one mask can be used for every mip.
+    /// Encoders without a per-block search encode the whole image at [`RoiQuality::important`]
+    fn encode_buffer_weighted(
+        &self,
+        image: DynamicImage,
+        _weights: &GrayImage,
+        roi: &RoiQuality,
+    ) -> Rc<[u8]> {
+        self.encode_buffer(image, roi.important)
+    }
 }
 
+/// Decodes buffers of a particular [`Format`] into images
+#[cfg(feature = "image")]
 pub trait Decoder {
-    fn decode_buffer(&self, buffer: Rc<[u8]>) -> TextureResult<DynamicImage>;
+    /// Decode `buffer`, containing a surface with the given dimensions, into an image.
+    /// 3D surfaces decode to their depth slices stacked vertically, in an image `depth` times as tall as the surface
+    fn decode_buffer(
+        &self,
+        buffer: Rc<[u8]>,
+        dimensions: Dimensions,
+    ) -> TextureResult<DynamicImage>;
 }
 
+/// How the alpha channel of an uncompressed format is interpreted
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum AlphaFormat {
     /// Any alpha channel content is being used as a 4th channel
     /// and is not intended to represent transparency (straight or premultiplied).
     /// This is the default for unknown alpha channel types.
-    Custom { alpha_mask: u32 },
+    Custom {
+        /// Bitmask of the alpha channel within a pixel
+        alpha_mask: u32,
+    },
 
     /// Any alpha channel content is presumed to use straight alpha.
-    Straight { alpha_mask: u32 },
+    Straight {
+        /// Bitmask of the alpha channel within a pixel
+        alpha_mask: u32,
+    },
 
     /// Any alpha channel content is using premultiplied alpha.
-    Premultiplied { alpha_mask: u32 },
+    Premultiplied {
+        /// Bitmask of the alpha channel within a pixel
+        alpha_mask: u32,
+    },
 
     /// Any alpha channel content is all set to fully opaque.
     Opaque,
 }
 
+/// The color channels of an uncompressed format
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ColorFormat {
     /// RGB color channels
     RGB {
+        /// Bitmask of the red channel within a pixel
         r_mask: u32,
+        /// Bitmask of the green channel within a pixel
         g_mask: u32,
+        /// Bitmask of the blue channel within a pixel
         b_mask: u32,
+        /// If the color channels are sRGB encoded
         srgb: bool,
     },
 
     /// YUV color channels
     YUV {
+        /// Bitmask of the luma channel within a pixel
         y_mask: u32,
+        /// Bitmask of the U chroma channel within a pixel
         u_mask: u32,
+        /// Bitmask of the V chroma channel within a pixel
         v_mask: u32,
     },
 
     /// Luminance-only color channels
-    L { l_mask: u32 },
+    L {
+        /// Bitmask of the luminance channel within a pixel
+        l_mask: u32,
+    },
 
     /// No color information, e.g. alpha only
     None,
 }
 
+/// The type of every channel in a [`Format::Channels`] pixel
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ChannelType {
+    /// 8-bit unsigned integers, normalized to 0 through 1
+    UNorm8,
+    /// 16-bit unsigned integers, normalized to 0 through 1
+    UNorm16,
+    /// 8-bit signed integers, normalized to -1 through 1
+    SNorm8,
+    /// 16-bit signed integers, normalized to -1 through 1
+    SNorm16,
+    /// 16-bit half precision floats
+    Float16,
+    /// 32-bit single precision floats
+    Float32,
+}
+
+impl ChannelType {
+    /// The size of a single channel in bytes
+    pub const fn size(&self) -> usize {
+        match self {
+            ChannelType::UNorm8 | ChannelType::SNorm8 => 1,
+            ChannelType::UNorm16 | ChannelType::SNorm16 | ChannelType::Float16 => 2,
+            ChannelType::Float32 => 4,
+        }
+    }
+}
+
+/// The encoding of a texture's surfaces
 #[non_exhaustive]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Format {
+    /// BC1 (DXT1) block compression, with RGB color and 1-bit alpha
     BC1 {
+        /// If the color channels are sRGB encoded
         srgb: bool,
     },
+    /// BC2 (DXT3) block compression, with BC1 color and explicit 4-bit alpha
     BC2 {
+        /// If the color channels are sRGB encoded
         srgb: bool,
     },
+    /// BC3 (DXT5) block compression, with BC1 color and interpolated alpha
     BC3 {
+        /// If the color channels are sRGB encoded
         srgb: bool,
     },
+    /// BC4 (ATI1) block compression, with a single channel
     BC4 {
+        /// If the channel is signed
         signed: bool,
     },
+    /// BC5 (ATI2) block compression, with two independent channels
     BC5 {
+        /// If the channels are signed
+        signed: bool,
+    },
+    /// BC7 (BPTC) block compression, with RGBA color in one of 8 modes per block
+    BC7 {
+        /// If the color channels are sRGB encoded
+        srgb: bool,
+    },
+    /// ETC2 block compression, with RGB color in 8-byte blocks
+    ETC2RGB8 {
+        /// If the color channels are sRGB encoded
+        srgb: bool,
+    },
+    /// ETC2 block compression, with an ETC2 color block and an EAC alpha block in 16-byte blocks
+    ETC2RGBA8 {
+        /// If the color channels are sRGB encoded
+        srgb: bool,
+    },
+    /// EAC block compression, with a single 11-bit channel in 8-byte blocks
+    EACR11 {
+        /// If the channel is signed
         signed: bool,
     },
+    /// EAC block compression, with two independent 11-bit channels in 16-byte blocks
+    EACRG11 {
+        /// If the channels are signed
+        signed: bool,
+    },
+    /// ASTC block compression, with 16-byte blocks covering a variable 2D or 3D footprint of pixels.
+    /// There is no codec for ASTC, but its surfaces can be moved between containers
+    ASTC {
+        /// The size of a block in pixels, from 4x4 to 12x12 for 2D blocks or 3x3x3 to 6x6x6 for 3D blocks.
+        /// See [`astc::is_valid_footprint`](crate::astc::is_valid_footprint)
+        block_dims: Dimensions,
+        /// If the color channels are sRGB encoded
+        srgb: bool,
+    },
+    /// Uncompressed pixels, described by channel bitmasks
     Uncompressed {
+        /// Size of a single pixel in bytes
         pitch: usize,
+        /// Layout of the color channels
         color_format: ColorFormat,
+        /// Layout and interpretation of the alpha channel
         alpha_format: AlphaFormat,
     },
+    /// Uncompressed pixels of 1 to 4 channels of the same type, in RGBA order.
+    /// Unlike [`Format::Uncompressed`], channels can be wider than 8 bits or floating point, for HDR textures.
+    /// Missing color channels decode as 0, and a missing alpha channel as fully opaque
+    Channels {
+        /// The type of every channel
+        channel_type: ChannelType,
+        /// The number of channels in a pixel, from 1 for red only to 4 for red, green, blue, and alpha
+        channels: usize,
+    },
+    /// A format the container identified by a FourCC code, but that this crate does not recognize.
+    /// Surfaces in an unknown format have no known size and can't be decoded or written,
+    /// so they can only be loaded as an opaque payload. See [`ReadOptions`](crate::container::ReadOptions)
+    Unknown {
+        /// The FourCC code of the format
+        four_cc: [u8; 4],
+    },
     // Not yet supported, but might be in the future:
-    // * ASTC, ETC, BC7
+    // * BC6H
     // * Basis and other super compression schemes (would contain a boxed format for the inner)
     // * Video formats like YUV 4:2:2, but I don't think anyone actually uses these.
-    // UNORM/UINT/SNORM/SINT/FLOAT? even if its just for round trip
+    // UINT/SINT channels? even if its just for round trip
 }
 
 impl Format {
-    pub fn size_for(&self, dimensions: Dimensions) -> usize {
-        use Format::*;
+    /// 8-bit RGBA with straight alpha, in that byte order. This is the format surfaces are decoded to
+    pub const fn rgba8(srgb: bool) -> Self {
+        Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0xFF,
+                g_mask: 0xFF00,
+                b_mask: 0xFF0000,
+                srgb,
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xFF000000,
+            },
+        }
+    }
+
+    /// If the color channels are sRGB encoded
+    pub fn srgb(&self) -> bool {
+        match self {
+            Format::BC1 { srgb }
+            | Format::BC2 { srgb }
+            | Format::BC3 { srgb }
+            | Format::BC7 { srgb }
+            | Format::ETC2RGB8 { srgb }
+            | Format::ETC2RGBA8 { srgb }
+            | Format::ASTC { srgb, .. } => *srgb,
+            Format::Uncompressed {
+                color_format: ColorFormat::RGB { srgb, .. },
+                ..
+            } => *srgb,
+            _ => false,
+        }
+    }
+
+    /// The size in bytes of the values whose bytes are reversed when changing byte order:
+    /// the whole pixel for 16 and 32-bit uncompressed formats, and a single channel for typed channels.
+    /// This is 1 for every other format, which are made of bytes or little-endian blocks
+    pub fn swap_width(&self) -> usize {
         match self {
-            BC1 { .. } | BC4 { .. } => {
-                8 * dimensions
-                    .blocks(Dimensions::try_from([4, 4]).unwrap())
-                    .product() as usize
+            Format::Uncompressed { pitch: 2, .. } => 2,
+            Format::Uncompressed { pitch: 4, .. } => 4,
+            Format::Channels { channel_type, .. } => channel_type.size(),
+            _ => 1,
+        }
+    }
+
+    /// The size in bytes of a surface with the given dimensions in this format,
+    /// or [`DimensionError::Overflow`] if it does not fit in a `usize`.
+    /// Returns [`TextureError::Format`] for [`Format::Unknown`]
+    pub fn size_for(&self, dimensions: Dimensions) -> TextureResult<usize> {
+        use Format::*;
+        let blocks = || dimensions.blocks_of::<Dims2<4, 4>>().try_product();
+        let (unit, count) = match self {
+            BC1 { .. } | BC4 { .. } | ETC2RGB8 { .. } | EACR11 { .. } => (8, blocks()?),
+            BC2 { .. } | BC3 { .. } | BC5 { .. } | BC7 { .. } => (16, blocks()?),
+            ETC2RGBA8 { .. } | EACRG11 { .. } => (16, blocks()?),
+            ASTC { block_dims, .. } => (16, dimensions.blocks(*block_dims).try_product()?),
+            Uncompressed { pitch, .. } => (*pitch, dimensions.try_product()?),
+            Channels {
+                channel_type,
+                channels,
+            } => (
+                (channel_type.size())
+                    .checked_mul(*channels)
+                    .ok_or(DimensionError::Overflow(dimensions))?,
+                dimensions.try_product()?,
+            ),
+            Unknown { four_cc } => {
+                return Err(TextureError::Format(format!(
+                    "Surfaces in unknown format '{}' have no known size",
+                    String::from_utf8_lossy(four_cc)
+                )))
             }
-            BC2 { .. } | BC3 { .. } | BC5 { .. } => {
-                16 * dimensions
-                    .blocks(Dimensions::try_from([4, 4]).unwrap())
-                    .product() as usize
+        };
+        Ok(unit
+            .checked_mul(count)
+            .ok_or(DimensionError::Overflow(dimensions))?)
+    }
+
+    /// The size of a single block in pixels, which is 1x1 for uncompressed formats.
+    /// Returns [`TextureError::Format`] for [`Format::Unknown`]
+    pub fn block_dimensions(&self) -> TextureResult<Dimensions> {
+        match self {
+            Format::Uncompressed { .. } | Format::Channels { .. } => {
+                Ok(Dims2::<1, 1>::dimensions())
             }
-            Uncompressed { pitch, .. } => *pitch * dimensions.product() as usize,
+            Format::ASTC { block_dims, .. } => Ok(*block_dims),
+            Format::Unknown { .. } => Err(TextureError::Format(format!(
+                "Block size of {self:?} is unknown"
+            ))),
+            _ => Ok(Dims2::<4, 4>::dimensions()),
         }
     }
 
-    pub fn decoder(&self) -> Box<dyn Decoder> {
-        todo!()
+    /// Get a decoder for this format with the default options,
+    /// or return [`TextureError::Format`] if decoding this format is not supported
+    #[cfg(feature = "image")]
+    pub fn decoder(&self) -> TextureResult<Box<dyn Decoder>> {
+        self.decoder_with(&DecodeOptions::default())
     }
 
-    pub fn encoder(&self) -> Box<dyn Encoder> {
-        todo!()
+    /// Get a decoder for this format configured by `options`,
+    /// or return [`TextureError::Format`] if decoding this format is not supported
+    #[cfg(feature = "image")]
+    pub fn decoder_with(&self, options: &DecodeOptions) -> TextureResult<Box<dyn Decoder>> {
+        let (interpolation, threads) = (options.interpolation, options.threads);
+        match self {
+            Format::Uncompressed { .. } => Ok(Box::new(UncompressedCodec::new(*self)?)),
+            Format::Channels { .. } => Ok(Box::new(ChannelCodec::new(*self)?)),
+            Format::BC1 { .. } => Ok(Box::new(
+                BlockCodec::<BC1Block>::default()
+                    .with_interpolation(interpolation)
+                    .with_threads(threads)?,
+            )),
+            Format::BC2 { .. } => Ok(Box::new(
+                BlockCodec::<BC2Block>::default()
+                    .with_interpolation(interpolation)
+                    .with_threads(threads)?,
+            )),
+            Format::BC3 { .. } => Ok(Box::new(
+                BlockCodec::<BC3Block>::default()
+                    .with_interpolation(interpolation)
+                    .with_threads(threads)?,
+            )),
+            Format::BC4 { signed: false } => Ok(Box::new(
+                BlockCodec::<BC4Block>::default().with_threads(threads)?,
+            )),
+            Format::BC4 { signed: true } => Ok(Box::new(
+                BlockCodec::<SignedBC4Block>::default().with_threads(threads)?,
+            )),
+            Format::BC5 { signed: false } => Ok(Box::new(
+                BlockCodec::<BC5Block>::default().with_threads(threads)?,
+            )),
+            Format::BC5 { signed: true } => Ok(Box::new(
+                BlockCodec::<SignedBC5Block>::default().with_threads(threads)?,
+            )),
+            Format::BC7 { .. } => Ok(Box::new(
+                BlockCodec::<BC7Block>::default().with_threads(threads)?,
+            )),
+            _ => Err(TextureError::Format(format!(
+                "Decoding {self:?} is not supported"
+            ))),
+        }
+    }
+
+    /// Get an encoder for this format with the default options,
+    /// or return [`TextureError::Format`] if encoding this format is not supported
+    #[cfg(feature = "image")]
+    pub fn encoder(&self) -> TextureResult<Box<dyn Encoder>> {
+        self.encoder_with(&EncodeOptions::default())
+    }
+
+    /// Get an encoder for this format configured by `options`,
+    /// or return [`TextureError::Format`] if encoding this format is not supported.
+    /// The tier passed to [`Encoder::encode_buffer`] takes precedence over [`EncodeOptions::quality`]
+    #[cfg(feature = "image")]
+    pub fn encoder_with(&self, options: &EncodeOptions) -> TextureResult<Box<dyn Encoder>> {
+        let threads = options.threads;
+        match self {
+            Format::Uncompressed { .. } => Ok(Box::new(UncompressedCodec::new(*self)?)),
+            Format::Channels { .. } => Ok(Box::new(ChannelCodec::new(*self)?)),
+            Format::BC1 { .. } => Ok(Box::new(
+                BlockCodec::<BC1Block>::new(BC1Options {
+                    quality: options.quality,
+                    alpha_threshold: options.alpha_threshold,
+                })
+                .with_threads(threads)?,
+            )),
+            Format::BC3 { .. } => Ok(Box::new(
+                BlockCodec::<BC3Block>::new(BC3Options {
+                    quality: options.quality,
+                })
+                .with_threads(threads)?,
+            )),
+            Format::BC4 { signed: false } => Ok(Box::new(
+                BlockCodec::<BC4Block>::default().with_threads(threads)?,
+            )),
+            Format::BC4 { signed: true } => Ok(Box::new(
+                BlockCodec::<SignedBC4Block>::default().with_threads(threads)?,
+            )),
+            Format::BC5 { signed: false } => Ok(Box::new(
+                BlockCodec::<BC5Block>::default().with_threads(threads)?,
+            )),
+            Format::BC5 { signed: true } => Ok(Box::new(
+                BlockCodec::<SignedBC5Block>::default().with_threads(threads)?,
+            )),
+            Format::BC7 { .. } => Ok(Box::new(
+                BlockCodec::<BC7Block>::new(BC7Options {
+                    quality: options.quality,
+                })
+                .with_threads(threads)?,
+            )),
+            _ => Err(TextureError::Format(format!(
+                "Encoding {self:?} is not supported"
+            ))),
+        }
     }
 }
@@ -0,0 +1,233 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Content hashes of textures, for finding duplicates among textures loaded from different sources.
+//!
+//! [`Texture::semantic_hash`] hashes what a texture contains rather than how it was stored,
+//! so two textures hash the same if they hold the same pixels in the same shape, even if:
+//!
+//! * They were read from containers with different headers, such as legacy and DX10 DDS headers
+//! * Their uncompressed formats order the channels differently, like RGBA8 and BGRA8,
+//!   or one has unused padding bits the other doesn't, like XRGB8 and RGB8
+//! * One has an alpha channel marked [`Custom`](crate::format::AlphaFormat::Custom)
+//!   and the other [`Straight`](crate::format::AlphaFormat::Straight)
+//! * One is a single surface and the other is an array of one layer, or a mip chain of one mip
+//!
+//! Differences that change how the pixels are interpreted are not ignored: sRGB and linear textures,
+//! premultiplied and straight alpha, and channels of different bit depths all hash differently.
+//! The hash is stable between runs and versions of Rust, but not guaranteed to be stable
+//! between versions of this crate
+
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::error::TextureResult;
+use crate::format::{AlphaFormat, ColorFormat, Format};
+use crate::texture::Texture;
+use crate::util::Fnv1a;
+
+/// An uncompressed format in canonical form: which channels it has, in a fixed order, and their masks
+struct CanonicalLayout {
+    pitch: usize,
+    /// Channel tag and mask for each channel with a non-zero mask, in canonical order
+    channels: Vec<(u8, u32)>,
+}
+
+impl CanonicalLayout {
+    fn new(pitch: usize, color_format: ColorFormat, alpha_format: AlphaFormat) -> Self {
+        let color = match color_format {
+            ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                ..
+            } => vec![(b'R', r_mask), (b'G', g_mask), (b'B', b_mask)],
+            ColorFormat::YUV {
+                y_mask,
+                u_mask,
+                v_mask,
+            } => vec![(b'Y', y_mask), (b'U', u_mask), (b'V', v_mask)],
+            ColorFormat::L { l_mask } => vec![(b'L', l_mask)],
+            ColorFormat::None => vec![],
+        };
+        let alpha = match alpha_format {
+            AlphaFormat::Custom { alpha_mask } | AlphaFormat::Straight { alpha_mask } => {
+                Some((b'A', alpha_mask))
+            }
+            AlphaFormat::Premultiplied { alpha_mask } => Some((b'P', alpha_mask)),
+            AlphaFormat::Opaque => None,
+        };
+        let channels = color
+            .into_iter()
+            .chain(alpha)
+            .filter(|&(_, mask)| mask != 0)
+            .collect();
+        Self { pitch, channels }
+    }
+
+    /// Bits needed to hold `mask` once it is shifted down to bit 0
+    fn width(mask: u32) -> u32 {
+        u32::BITS - (mask >> mask.trailing_zeros()).leading_zeros()
+    }
+
+    fn hash_descriptor(&self, hasher: &mut Fnv1a) {
+        for &(tag, mask) in &self.channels {
+            hasher.update(&[tag, Self::width(mask) as u8]);
+        }
+    }
+
+    /// Hash each pixel as its channel values in canonical order, ignoring any bits outside the masks
+    fn hash_pixels(&self, buffer: &[u8], hasher: &mut Fnv1a) {
+        let bytes = self.pitch.min(4);
+        for src in buffer.chunks_exact(self.pitch) {
+            let mut pixel = [0u8; 4];
+            pixel[..bytes].copy_from_slice(&src[..bytes]);
+            let pixel = u32::from_le_bytes(pixel);
+            for &(_, mask) in &self.channels {
+                hasher.update(&((pixel & mask) >> mask.trailing_zeros()).to_le_bytes());
+            }
+        }
+    }
+}
+
+fn hash_dimensions(dimensions: Dimensions, hasher: &mut Fnv1a) {
+    for d in [dimensions.width(), dimensions.height(), dimensions.depth()] {
+        hasher.update(&d.to_le_bytes());
+    }
+}
+
+impl Texture {
+    /// A 64-bit hash of the texture's format, shape, and pixels, ignoring how they were described.
+    /// See the [module documentation](crate::hash) for which differences are ignored.
+    /// Returns an error if any surface does not match the texture's format
+    pub fn semantic_hash(&self) -> TextureResult<u64> {
+        self.check_format()?;
+        let mut hasher = Fnv1a::default();
+
+        let layout = match self.format {
+            Format::Uncompressed {
+                pitch,
+                color_format,
+                alpha_format,
+            } => {
+                let layout = CanonicalLayout::new(pitch, color_format, alpha_format);
+                hasher.update(b"U");
+                layout.hash_descriptor(&mut hasher);
+                Some(layout)
+            }
+            Format::Unknown { four_cc } => {
+                hasher.update(b"?");
+                hasher.update(&four_cc);
+                None
+            }
+            format => {
+                // block formats have no alternate descriptions, so their debug names are canonical
+                hasher.update(format!("{format:?}").as_bytes());
+                None
+            }
+        };
+        hasher.update(&[self.format.srgb() as u8]);
+
+        for s in self.slice().iter() {
+            // a missing layer or mip is the same as the first of one
+            hasher.update(&(s.layer.unwrap_or(0) as u64).to_le_bytes());
+            hasher.update(&[s.face.map_or(u8::MAX, |f| f as u8)]);
+            hasher.update(&(s.mip.unwrap_or(0) as u64).to_le_bytes());
+            hash_dimensions(s.surface.dimensions(), &mut hasher);
+            match &layout {
+                Some(layout) => layout.hash_pixels(&s.surface.buffer, &mut hasher),
+                None => hasher.update(&s.surface.buffer),
+            }
+        }
+        Ok(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::shape::{TextureShape, TextureShapeNode};
+    use crate::texture::{Surface, Surfaces};
+
+    use super::*;
+
+    fn uncompressed(pitch: usize, masks: [u32; 4], srgb: bool) -> Format {
+        Format::Uncompressed {
+            pitch,
+            color_format: ColorFormat::RGB {
+                r_mask: masks[0],
+                g_mask: masks[1],
+                b_mask: masks[2],
+                srgb,
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: masks[3],
+            },
+        }
+    }
+
+    fn texture(format: Format, bytes: Vec<u8>) -> Result<Texture> {
+        let surface = Surface::new(Dimensions::try_from([2, 1])?, bytes);
+        Ok(Texture::new(
+            format,
+            TextureShapeNode::from_surface(surface),
+        )?)
+    }
+
+    #[test]
+    fn channel_order() -> Result<()> {
+        let rgba = texture(
+            uncompressed(4, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], false),
+            vec![1, 2, 3, 4, 5, 6, 7, 8],
+        )?;
+        let bgra = texture(
+            uncompressed(4, [0xFF0000, 0xFF00, 0xFF, 0xFF000000], false),
+            vec![3, 2, 1, 4, 7, 6, 5, 8],
+        )?;
+        assert_eq!(rgba.semantic_hash()?, bgra.semantic_hash()?);
+
+        // different pixels, or the same pixels marked sRGB
+        let other = texture(rgba.format, vec![1, 2, 3, 4, 5, 6, 7, 9])?;
+        assert_ne!(rgba.semantic_hash()?, other.semantic_hash()?);
+        let srgb = texture(
+            uncompressed(4, [0xFF, 0xFF00, 0xFF0000, 0xFF000000], true),
+            vec![1, 2, 3, 4, 5, 6, 7, 8],
+        )?;
+        assert_ne!(rgba.semantic_hash()?, srgb.semantic_hash()?);
+        Ok(())
+    }
+
+    #[test]
+    fn padding() -> Result<()> {
+        let rgb = texture(
+            uncompressed(3, [0xFF, 0xFF00, 0xFF0000, 0], false),
+            vec![1, 2, 3, 4, 5, 6],
+        )?;
+        let rgbx = texture(
+            uncompressed(4, [0xFF, 0xFF00, 0xFF0000, 0], false),
+            vec![1, 2, 3, 99, 4, 5, 6, 0],
+        )?;
+        assert_eq!(rgb.semantic_hash()?, rgbx.semantic_hash()?);
+        Ok(())
+    }
+
+    #[test]
+    fn shape() -> Result<()> {
+        let format = Format::BC1 { srgb: false };
+        let surface = Surface::new(Dimensions::try_from([4, 4])?, vec![5u8; 8]);
+        let single = Texture::new(format, TextureShapeNode::from_surface(surface.clone()))?;
+        let array = Texture::new(
+            format,
+            Surfaces::try_from_layers([TextureShapeNode::from_surface(surface.clone())])?,
+        )?;
+        assert_eq!(array.layers(), Some(1));
+        assert_eq!(single.semantic_hash()?, array.semantic_hash()?);
+
+        let bc4 = Texture::new(
+            Format::BC4 { signed: false },
+            TextureShapeNode::from_surface(surface),
+        )?;
+        assert_ne!(single.semantic_hash()?, bc4.semantic_hash()?);
+        Ok(())
+    }
+}
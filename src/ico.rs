@@ -0,0 +1,192 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{Seek, Write};
+
+use binrw::{binwrite, BinWriterExt};
+use image::RgbaImage;
+
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::shape::TextureShape;
+use crate::texture::Texture;
+
+/// Largest width or height an ICO entry can have
+const MAX_SIZE: u32 = 256;
+
+#[binwrite]
+#[bw(little)]
+struct IconDir {
+    #[bw(calc = 0)]
+    _reserved: u16,
+    #[bw(calc = 1)]
+    _image_type: u16,
+    count: u16,
+}
+
+#[binwrite]
+#[bw(little)]
+struct IconDirEntry {
+    /// 0 means 256
+    #[bw(map = |w: &u32| *w as u8)]
+    width: u32,
+    #[bw(map = |h: &u32| *h as u8)]
+    height: u32,
+    #[bw(calc = [0, 0])]
+    _palette: [u8; 2],
+    #[bw(calc = 1)]
+    _planes: u16,
+    #[bw(calc = 32)]
+    _bit_count: u16,
+    size: u32,
+    offset: u32,
+}
+
+/// Write each mip of a texture that is no larger than 256x256 as one entry of a multi-resolution ICO file,
+/// stored as 32-bit BGRA bitmaps.
+///
+/// The texture must be a 2D texture or mip chain, without cubemap faces or array layers,
+/// and its format must have a decoder.
+/// Returns [`TextureError::Capability`] if every mip is too large to be an icon
+pub fn write_ico<W: Write + Seek>(writer: &mut W, texture: &Texture) -> TextureResult<()> {
+    if texture.layers().is_some() || texture.faces().is_some() {
+        return Err(TextureError::Capability(
+            "ICO files cannot contain cubemaps or arrays".to_string(),
+        ));
+    }
+    let dimensions = texture.try_dimensions()?;
+    let Dimensions::_2D(_) = dimensions else {
+        return Err(TextureError::Capability(format!(
+            "ICO files can only contain 2D images, not {dimensions:?}"
+        )));
+    };
+
+    let decoder = texture.format.decoder()?;
+    let images = texture
+        .slice()
+        .try_iter()?
+        .map(|s| s.surface)
+        .filter(|s| s.dimensions.width() <= MAX_SIZE && s.dimensions.height() <= MAX_SIZE)
+        .map(|s| -> TextureResult<RgbaImage> {
+            Ok(decoder
+                .decode_buffer(s.buffer.clone(), s.dimensions)?
+                .into_rgba8())
+        })
+        .collect::<TextureResult<Vec<_>>>()?;
+    if images.is_empty() {
+        return Err(TextureError::Capability(format!(
+            "ICO entries can be at most {MAX_SIZE}x{MAX_SIZE}, and a {dimensions:?} texture has no mips that small"
+        )));
+    }
+
+    let bitmaps = images.iter().map(bitmap).collect::<Vec<_>>();
+    writer.write_le(&IconDir {
+        count: images.len() as u16,
+    })?;
+    let mut offset = 6 + 16 * images.len() as u32;
+    for (image, bitmap) in images.iter().zip(&bitmaps) {
+        writer.write_le(&IconDirEntry {
+            width: image.width(),
+            height: image.height(),
+            size: bitmap.len() as u32,
+            offset,
+        })?;
+        offset += bitmap.len() as u32;
+    }
+    for bitmap in &bitmaps {
+        writer.write_all(bitmap)?;
+    }
+    Ok(())
+}
+
+/// Encode an image as an ICO bitmap: a `BITMAPINFOHEADER` with a doubled height, bottom-to-top BGRA rows,
+/// and an empty 1-bit AND mask, since transparency comes from the alpha channel
+fn bitmap(image: &RgbaImage) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let mask_row_size = (width as usize).div_ceil(32) * 4;
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&40u32.to_le_bytes());
+    data.extend_from_slice(&(width as i32).to_le_bytes());
+    data.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&32u16.to_le_bytes());
+    // no compression, and no image size, resolution, or palette
+    data.extend_from_slice(&[0; 24]);
+
+    for row in image.rows().rev() {
+        for &image::Rgba([r, g, b, a]) in row {
+            data.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+    data.resize(data.len() + mask_row_size * height as usize, 0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+    use image::{DynamicImage, ImageFormat};
+
+    use crate::format::{AlphaFormat, ColorFormat, Format, Quality};
+
+    use super::*;
+
+    fn rgba8() -> Format {
+        Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0x0000FF,
+                g_mask: 0x00FF00,
+                b_mask: 0xFF0000,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xFF000000,
+            },
+        }
+    }
+
+    #[test]
+    fn mip_chain() -> Result<()> {
+        let image = RgbaImage::from_fn(512, 256, |x, y| {
+            image::Rgba([x as u8, y as u8, (x ^ y) as u8, 128 + (y / 2) as u8])
+        });
+        let texture = Texture::from_image(
+            &DynamicImage::ImageRgba8(image),
+            rgba8(),
+            true,
+            Quality::default(),
+        )?;
+
+        let mut file = Cursor::new(Vec::new());
+        write_ico(&mut file, &texture)?;
+        let data = file.into_inner();
+
+        // 512x256 is skipped, leaving 256x128 down to 1x1
+        assert_eq!(u16::from_le_bytes([data[4], data[5]]), 9);
+        assert_eq!(&data[6..8], &[0, 128], "256 is stored as 0");
+
+        // the image crate decodes the largest entry
+        let decoded = image::load_from_memory_with_format(&data, ImageFormat::Ico)?;
+        let mip = texture.get_mip(1).unwrap();
+        let surface = mip.slice().try_into_surface().unwrap();
+        let expected = rgba8()
+            .decoder()?
+            .decode_buffer(surface.buffer.clone(), surface.dimensions)?;
+        assert_eq!(decoded.to_rgba8(), expected.to_rgba8());
+        Ok(())
+    }
+
+    #[test]
+    fn too_large() -> Result<()> {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(300, 4));
+        let texture = Texture::from_image(&image, rgba8(), false, Quality::default())?;
+        let result = write_ico(&mut Cursor::new(Vec::new()), &texture);
+        assert!(matches!(result, Err(TextureError::Capability(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Conversions between textures and images from the [`image`] crate.
+//!
+//! These cover the usual pipeline of loading a PNG or TGA, compressing it, and writing it to a container,
+//! and extracting surfaces from a container back to image files, without handling pixels by hand.
+//! Files are read and written in whatever format the `image` crate picks for them, from their contents
+//! when reading and from their extension when writing
+
+use std::path::Path;
+
+use image::{DynamicImage, ImageError};
+
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::format::{EncodeOptions, Format, Quality};
+use crate::shape::{TextureIterResult, TextureShape};
+use crate::texture::{Surface, Texture};
+
+/// IO errors keep their kind, and every other image error is described by its message
+fn image_error(error: ImageError) -> TextureError {
+    match error {
+        ImageError::IoError(e) => TextureError::IO(e),
+        e => TextureError::Other(e.to_string()),
+    }
+}
+
+impl Surface {
+    /// Encode an image into a new 2D surface in `format`, configured by `options`.
+    /// Returns [`TextureError::Format`] if `format` does not have an encoder
+    pub fn from_image(
+        image: &DynamicImage,
+        format: Format,
+        options: EncodeOptions,
+    ) -> TextureResult<Self> {
+        let dimensions = Dimensions::try_from([image.width(), image.height()])?;
+        let encoder = format.encoder_with(&options)?;
+        Ok(Self::new(
+            dimensions,
+            encoder.encode_buffer(image.clone(), options.quality),
+        ))
+    }
+
+    /// Decode this surface from `format` into an image.
+    /// Returns [`TextureError::Format`] if the format does not have a decoder, or the buffer is the wrong size
+    pub fn to_image(&self, format: Format) -> TextureResult<DynamicImage> {
+        self.check_format(format)?;
+        format
+            .decoder()?
+            .decode_buffer(self.buffer.clone(), self.dimensions)
+    }
+}
+
+impl Texture {
+    /// Decode the texture's primary surface, the largest mip of its first layer and face, into an image
+    pub fn to_image(&self) -> TextureResult<DynamicImage> {
+        self.primary().to_image(self.format)
+    }
+
+    /// Decode every surface of the texture into an image, along with where in the texture it came from,
+    /// in the same order as [`TextureSlice::iter`](crate::shape::TextureSlice::iter)
+    pub fn to_images(&self) -> TextureResult<Vec<TextureIterResult<DynamicImage>>> {
+        let decoder = self.format.decoder()?;
+        self.slice()
+            .iter()
+            .map(|s| {
+                Ok(TextureIterResult {
+                    layer: s.layer,
+                    face: s.face,
+                    mip: s.mip,
+                    surface: decoder
+                        .decode_buffer(s.surface.buffer.clone(), s.surface.dimensions)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Open an image file, such as a PNG or TGA, and encode it into a new texture like [`Self::from_image_with`]
+    pub fn open_image(
+        path: impl AsRef<Path>,
+        format: Format,
+        mips: bool,
+        options: EncodeOptions,
+    ) -> TextureResult<Self> {
+        let image = image::open(path).map_err(image_error)?;
+        Self::from_image_with(&image, format, mips, options)
+    }
+
+    /// Decode the texture's primary surface and save it as an image file,
+    /// in the format chosen by the extension of `path`, such as `.png` or `.tga`
+    pub fn save_image(&self, path: impl AsRef<Path>) -> TextureResult<()> {
+        self.to_image()?.save(path).map_err(image_error)
+    }
+}
+
+/// Store an image uncompressed in a single surface, as [`Format::rgba8`]
+impl TryFrom<&DynamicImage> for Texture {
+    type Error = TextureError;
+
+    fn try_from(image: &DynamicImage) -> TextureResult<Self> {
+        Texture::from_image(image, Format::rgba8(false), false, Quality::default())
+    }
+}
+
+/// Decode a texture's primary surface. See [`Texture::to_image`]
+impl TryFrom<&Texture> for DynamicImage {
+    type Error = TextureError;
+
+    fn try_from(texture: &Texture) -> TextureResult<Self> {
+        texture.to_image()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn gradient() -> DynamicImage {
+        RgbaImage::from_fn(16, 8, |x, y| Rgba([x as u8 * 16, y as u8 * 32, 64, 255])).into()
+    }
+
+    #[test]
+    fn conversions() -> Result<()> {
+        let image = gradient();
+        let texture = Texture::try_from(&image)?;
+        assert_eq!(texture.format, Format::rgba8(false));
+        assert_eq!(
+            DynamicImage::try_from(&texture)?.to_rgba8(),
+            image.to_rgba8()
+        );
+
+        let bc1 = Format::BC1 { srgb: false };
+        let surface = Surface::from_image(&image, bc1, EncodeOptions::default())?;
+        assert_eq!(surface.buffer.len(), 4 * 2 * 8);
+        assert_eq!(surface.to_image(bc1)?.dimensions(), (16, 8));
+        assert!(surface.to_image(Format::BC3 { srgb: false }).is_err());
+
+        let mipped = Texture::from_image(&image, bc1, true, Quality::Normal)?;
+        let images = mipped.to_images()?;
+        assert_eq!(images.len(), 5);
+        assert_eq!(images[2].mip, Some(2));
+        assert_eq!(images[2].surface.dimensions(), (4, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let texture = Texture::try_from(&gradient())?;
+        for name in ["gradient.png", "gradient.tga"] {
+            let path = dir.path().join(name);
+            texture.save_image(&path)?;
+            let opened =
+                Texture::open_image(&path, Format::rgba8(false), false, Default::default())?;
+            assert_eq!(
+                opened.to_image()?.to_rgba8(),
+                gradient().to_rgba8(),
+                "{name}"
+            );
+        }
+
+        assert!(matches!(
+            Texture::open_image(
+                dir.path().join("missing.png"),
+                Format::rgba8(false),
+                false,
+                Default::default()
+            ),
+            Err(TextureError::IO(_))
+        ));
+        assert!(texture
+            .save_image(dir.path().join("gradient.unknown"))
+            .is_err());
+        Ok(())
+    }
+}
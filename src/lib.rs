@@ -2,15 +2,157 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-mod blocktexture;
+//! Reading, writing, and converting GPU textures.
+//!
+//! A [`Texture`] is a [`Format`] plus a tree of encoded [`Surface`]s, shaped by any combination of mipmaps,
+//! cubemap faces, and array layers (see [`TextureShape`](shape::TextureShape)). Textures are read from and
+//! written to files through a [`ContainerHeader`] such as [`DDSHeader`].
+//! The most commonly used types are re-exported at the crate root, which is the supported import path for them,
+//! and the [`prelude`] additionally imports the traits needed to use them in one line.
+//!
+//! ```
+//! use std::io::Cursor;
+//! use quicktex::prelude::*;
+//! use quicktex::{Surface, TextureShapeNode};
+//!
+//! // an 8x8 BC1 surface is 4 blocks of 8 bytes each
+//! let dimensions = Dimensions::try_from([8, 8])?;
+//! let surface = Surface::new(dimensions, (0..32).collect::<Vec<u8>>());
+//! let texture = Texture::new(Format::BC1 { srgb: false }, TextureShapeNode::from_surface(surface))?;
+//!
+//! let mut file = Cursor::new(Vec::new());
+//! DDSHeader::write_texture(&mut file, &texture)?;
+//!
+//! file.set_position(0);
+//! let read = DDSHeader::read_texture(&mut file)?;
+//! assert_eq!(read.format, texture.format);
+//! assert_eq!(read.dimensions(), dimensions);
+//! assert_eq!(read.slice().try_into_surface().unwrap().buffer, texture.slice().try_into_surface().unwrap().buffer);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#![deny(missing_docs)]
+
+/// Choosing the smallest format that meets a quality target
+#[cfg(feature = "image")]
+pub mod adaptive;
+/// Alpha histogram analysis, for choosing between punch-through and full alpha formats
+#[cfg(feature = "image")]
+pub mod alpha;
+/// Finding blocks likely to show compression artifacts in BC textures
+#[cfg(feature = "image")]
+pub mod artifacts;
+/// The `.astc` container for single ASTC surfaces
+pub mod astc;
+pub mod bitstream;
+/// Encoded blocks, and the traits block codecs implement
+pub mod blocktexture;
+/// Reading legacy BMP images into uncompressed surfaces
+pub mod bmp;
+/// BPTC (BC7) block formats
+pub mod bptc;
+/// Fitting textures into a memory budget
+#[cfg(feature = "image")]
+pub mod budget;
+/// Which formats and texture shapes each format and container supports
+pub mod capability;
+/// A crate-native container with random access to surfaces and appendable layers
+#[cfg(feature = "chunked")]
+pub mod chunked;
+/// Generic surface codecs built from per-block codecs
+#[cfg(feature = "image")]
+pub mod codec;
+/// Color types used by the block codecs
 pub mod color;
+/// Traits for reading and writing textures in container files
 pub mod container;
+/// Converting textures between formats and sizes, with a report of what was done
+#[cfg(feature = "image")]
+pub mod conversion;
+/// Repacking uncompressed surfaces between channel layouts
+pub mod convert;
+/// Converting between cubemaps and single images in cross or strip layouts, and assembling cubemaps from faces
+#[cfg(feature = "image")]
+pub mod cubemap;
+/// The DirectDraw Surface (DDS) container
 pub mod dds;
+/// Debug overlays for visualizing mip levels and UVs
+#[cfg(feature = "image")]
+pub mod debug;
+/// Error codes, help text, and labeled hex dumps for error reports through `miette`
+#[cfg(feature = "miette")]
+pub mod diagnostics;
+/// Texture and surface dimensions
 pub mod dimensions;
+/// Edit sessions with undo, redo, and revert for textures, copying only the surfaces that change
+pub mod edit;
+/// Entropy and compressibility statistics for deciding which textures to supercompress
+pub mod entropy;
+/// Error types for texture operations
 pub mod error;
+/// Texture formats, encoders, and decoders
 pub mod format;
+/// Content hashes of textures that ignore how their format was described
+pub mod hash;
+/// Exporting mip chains as multi-resolution ICO files
+#[cfg(feature = "image")]
+pub mod ico;
+/// Conversions between textures and images, for loading and saving PNG and TGA files
+#[cfg(feature = "image")]
+pub mod images;
+/// 3D color lookup tables, stacked from 2D slices or read from `.cube` files
+pub mod lut;
+/// Comparing textures by PSNR, MSE, and maximum channel error
+#[cfg(feature = "image")]
+pub mod metrics;
+/// Incremental mip updates after a region of the top mip changes
+#[cfg(feature = "image")]
+pub mod mips;
+/// Contact sheets of every surface in a texture
+#[cfg(feature = "image")]
+pub mod montage;
+/// Bit packing helpers for block codecs
 pub mod pack;
+/// Binary patches between versions of a texture
+pub mod patch;
+/// Commonly used traits and types, for glob importing
+pub mod prelude;
+/// The PowerVR (PVR) version 3 container used by mobile asset pipelines
+pub mod pvr;
+/// The QOI image format, for single uncompressed RGB8 or RGBA8 surfaces
+#[cfg(feature = "qoi")]
+pub mod qoi;
+/// Registry of container types, for reading files without knowing their type in advance
+pub mod registry;
+/// Gamma-correct image resizing
+#[cfg(feature = "image")]
+pub mod resize;
+/// S3TC (BC1-BC5) block formats
 pub mod s3tc;
+/// Texture shapes: mipmaps, cubemaps, and arrays
 pub mod shape;
+/// Encoding texture arrays so that similar layers share identical blocks
+#[cfg(feature = "image")]
+pub mod sharing;
+/// Copying textures into GPU staging buffers with row pitch and alignment requirements
+pub mod staging;
+/// Approximate color statistics of BC textures from their block endpoints
+pub mod stats;
+/// Textures and their surfaces
 pub mod texture;
+/// Making textures tile seamlessly
+#[cfg(feature = "image")]
+pub mod tiling;
+#[cfg(feature = "image")]
+mod uncompressed;
 mod util;
+/// Texture files wrapped in gzip or zstd compressed streams
+pub mod wrapped;
+
+pub use container::ContainerHeader;
+pub use dds::DDSHeader;
+pub use dimensions::Dimensions;
+pub use error::{TextureError, TextureResult};
+pub use format::{Format, Quality};
+pub use shape::TextureShapeNode;
+pub use texture::{Surface, Surfaces, Texture};
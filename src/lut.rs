@@ -0,0 +1,343 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! 3D color lookup tables, as used for color grading.
+//!
+//! LUTs are commonly shipped as volume textures, with red along the width, green along the height,
+//! and blue along the depth. They are authored either as a sequence of 2D slices, which can be stacked
+//! into a volume with [`Texture::stack_slices`], or as `.cube` files, which can be read and written with [`CubeLut`](crate::lut::CubeLut)
+
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+
+use itertools::Itertools;
+
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::format::Format;
+#[cfg(feature = "image")]
+use crate::shape::TextureShape;
+use crate::shape::TextureShapeNode;
+use crate::texture::{Surface, Texture};
+
+impl Texture {
+    /// Stack a sequence of 2D textures into the depth slices of a single 3D texture, without re-encoding them.
+    /// Returns an error if there are no slices, or if they are not all single 2D surfaces with the same
+    /// dimensions and format
+    pub fn stack_slices(slices: &[Texture]) -> TextureResult<Self> {
+        let first = slices
+            .first()
+            .ok_or_else(|| TextureError::Other("Can't stack zero slices".to_string()))?;
+        let (format, dimensions) = (first.format, first.surfaces.try_dimensions()?);
+        let mut buffer = Vec::new();
+        for slice in slices {
+            let surface = match &slice.surfaces {
+                TextureShapeNode::Surface(s) => s,
+                _ => {
+                    return Err(TextureError::Capability(
+                        "Only single surfaces can be stacked into a 3D texture".to_string(),
+                    ))
+                }
+            };
+            if slice.format != format || surface.dimensions != dimensions {
+                return Err(TextureError::Other(
+                    "Slices must all have the same format and dimensions".to_string(),
+                ));
+            }
+            if !matches!(dimensions, Dimensions::_2D(_)) {
+                return Err(TextureError::Capability(format!(
+                    "Only 2D slices can be stacked, not {dimensions:?}"
+                )));
+            }
+            buffer.extend_from_slice(&surface.buffer);
+        }
+
+        let depth = u32::try_from(slices.len())
+            .map_err(|_| TextureError::Other("Too many slices to stack".to_string()))?;
+        let dimensions = Dimensions::try_from([dimensions.width(), dimensions.height(), depth])?;
+        Self::new(
+            format,
+            TextureShapeNode::from_surface(Surface::new(dimensions, buffer)),
+        )
+    }
+
+    /// Split a 3D texture into a 2D texture for each of its depth slices, the reverse of [`Self::stack_slices`].
+    /// Returns an error if the texture is not a single 3D surface
+    pub fn unstack_slices(&self) -> TextureResult<Vec<Texture>> {
+        let surface = match &self.surfaces {
+            TextureShapeNode::Surface(s) if matches!(s.dimensions, Dimensions::_3D(_)) => s,
+            _ => {
+                return Err(TextureError::Capability(
+                    "Only single 3D surfaces can be split into slices".to_string(),
+                ))
+            }
+        };
+        let dimensions =
+            Dimensions::try_from([surface.dimensions.width(), surface.dimensions.height()])?;
+        let size = self.format.size_for(dimensions)?;
+        surface
+            .buffer
+            .chunks(size)
+            .map(|slice| {
+                Self::new(
+                    self.format,
+                    TextureShapeNode::from_surface(Surface::new(dimensions, slice)),
+                )
+            })
+            .collect()
+    }
+}
+
+/// A 3D LUT in the `.cube` format, with a table of `size`³ RGB entries
+#[derive(Clone, Debug, PartialEq)]
+pub struct CubeLut {
+    /// The title of the LUT, if it has one
+    pub title: Option<String>,
+    /// The number of entries along each axis
+    pub size: usize,
+    /// The input value mapped to the first entry along each axis, usually 0
+    pub domain_min: [f32; 3],
+    /// The input value mapped to the last entry along each axis, usually 1
+    pub domain_max: [f32; 3],
+    /// The output color of each entry, with red changing fastest and blue slowest
+    pub table: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    /// Parse a `.cube` file. 1D LUTs are not supported
+    pub fn parse(text: &str) -> TextureResult<Self> {
+        let error = |line: usize, message: &str| {
+            TextureError::Other(format!(
+                "Invalid .cube file on line {}: {message}",
+                line + 1
+            ))
+        };
+        let floats = |line: usize, values: &[&str]| -> TextureResult<[f32; 3]> {
+            let values: Vec<f32> = values
+                .iter()
+                .map(|v| v.parse::<f32>())
+                .try_collect()
+                .map_err(|_| error(line, "expected a number"))?;
+            values
+                .try_into()
+                .map_err(|_| error(line, "expected 3 values"))
+        };
+
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words = line.split_whitespace().collect_vec();
+            match words[0] {
+                "TITLE" => {
+                    let quoted = line["TITLE".len()..].trim();
+                    title = Some(quoted.trim_matches('"').to_string());
+                }
+                "LUT_3D_SIZE" => {
+                    let n = words
+                        .get(1)
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .filter(|n| (2..=256).contains(n))
+                        .ok_or_else(|| error(i, "LUT size must be between 2 and 256"))?;
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => return Err(error(i, "1D LUTs are not supported")),
+                "DOMAIN_MIN" => domain_min = floats(i, &words[1..])?,
+                "DOMAIN_MAX" => domain_max = floats(i, &words[1..])?,
+                _ => table.push(floats(i, &words)?),
+            }
+        }
+
+        let size = size.ok_or_else(|| {
+            TextureError::Other("Invalid .cube file: missing LUT_3D_SIZE".to_string())
+        })?;
+        if table.len() != size.pow(3) {
+            return Err(TextureError::Other(format!(
+                "Invalid .cube file: expected {} entries, found {}",
+                size.pow(3),
+                table.len()
+            )));
+        }
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// Read a `.cube` file. See [`Self::parse`]
+    pub fn read<R: Read>(reader: &mut R) -> TextureResult<Self> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Self::parse(&text)
+    }
+
+    /// Write the LUT as a `.cube` file
+    pub fn write<W: Write>(&self, writer: &mut W) -> TextureResult<()> {
+        let mut text = String::new();
+        let triple = |[r, g, b]: [f32; 3]| format!("{r:.6} {g:.6} {b:.6}");
+        if let Some(title) = &self.title {
+            writeln!(text, "TITLE \"{title}\"").unwrap();
+        }
+        writeln!(text, "LUT_3D_SIZE {}", self.size).unwrap();
+        writeln!(text, "DOMAIN_MIN {}", triple(self.domain_min)).unwrap();
+        writeln!(text, "DOMAIN_MAX {}", triple(self.domain_max)).unwrap();
+        for entry in &self.table {
+            writeln!(text, "{}", triple(*entry)).unwrap();
+        }
+        writer.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Convert the LUT into a `size`³ volume texture in [`Format::rgba8`], with opaque alpha.
+    /// Entries are clamped between 0 and 1 and quantized to 8 bits. The domain is not stored
+    pub fn to_texture(&self) -> TextureResult<Texture> {
+        let size = u32::try_from(self.size)
+            .map_err(|_| TextureError::Other("LUT is too large".to_string()))?;
+        let dimensions = Dimensions::try_from([size, size, size])?;
+        let quantize = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let buffer = self
+            .table
+            .iter()
+            .flat_map(|&[r, g, b]| [quantize(r), quantize(g), quantize(b), u8::MAX])
+            .collect_vec();
+        Texture::new(
+            Format::rgba8(false),
+            TextureShapeNode::from_surface(Surface::new(dimensions, buffer)),
+        )
+    }
+
+    /// Convert a cube-shaped volume texture into a LUT, decoding each slice.
+    /// The domain is always 0 to 1. Returns an error if the texture is not a single 3D surface with
+    /// equal width, height, and depth, or if its format can't be decoded
+    #[cfg(feature = "image")]
+    pub fn from_texture(texture: &Texture) -> TextureResult<Self> {
+        let dimensions = texture.surfaces.try_dimensions()?;
+        let size = dimensions.width();
+        if dimensions.height() != size || dimensions.depth() != size {
+            return Err(TextureError::Other(format!(
+                "A {dimensions:?} texture is not a cube-shaped LUT"
+            )));
+        }
+        let decoder = texture.format.decoder()?;
+        let mut table = Vec::with_capacity((size as usize).pow(3));
+        for slice in texture.unstack_slices()? {
+            let surface = slice.primary();
+            let image = decoder.decode_buffer(surface.buffer, surface.dimensions)?;
+            let unquantize = |v: u8| v as f32 / 255.0;
+            table.extend(
+                image
+                    .into_rgba8()
+                    .pixels()
+                    .map(|p| [unquantize(p[0]), unquantize(p[1]), unquantize(p[2])]),
+            );
+        }
+        Ok(Self {
+            title: None,
+            size: size as usize,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::dimensions::Dimensioned;
+    use crate::shape::TextureShape;
+
+    use super::*;
+
+    /// The identity LUT, which maps every color to itself
+    fn identity(size: usize) -> CubeLut {
+        let step = |i: usize| i as f32 / (size - 1) as f32;
+        CubeLut {
+            title: Some("identity".to_string()),
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table: (0..size.pow(3))
+                .map(|i| [step(i % size), step(i / size % size), step(i / size / size)])
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn cube_file_roundtrip() -> Result<()> {
+        let lut = identity(3);
+        let mut file = Vec::new();
+        lut.write(&mut file)?;
+        let text = String::from_utf8(file.clone())?;
+        assert!(text.starts_with("TITLE \"identity\"\nLUT_3D_SIZE 3\n"));
+        assert_eq!(CubeLut::read(&mut &file[..])?, lut);
+
+        // comments and blank lines are skipped, and the domain is optional
+        let parsed = CubeLut::parse(
+            "# comment\n\nLUT_3D_SIZE 2\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n",
+        )?;
+        assert_eq!(parsed.table, identity(2).table);
+        assert_eq!(parsed.domain_max, [1.0; 3]);
+
+        assert!(CubeLut::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(CubeLut::parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+        assert!(CubeLut::parse("LUT_3D_SIZE 2\n0 0 zero\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn texture_roundtrip() -> Result<()> {
+        let lut = identity(4);
+        let texture = lut.to_texture()?;
+        assert_eq!(texture.dimensions(), Dimensions::try_from([4, 4, 4])?);
+        // red along the width, blue along the depth
+        let buffer = &texture.primary().buffer;
+        assert_eq!(buffer[4..8], [85, 0, 0, 255]);
+        assert_eq!(buffer[16 * 4 * 3..16 * 4 * 3 + 4], [0, 0, 255, 255]);
+
+        let read = CubeLut::from_texture(&texture)?;
+        assert_eq!(read.size, 4);
+        for (a, b) in read.table.iter().zip(&lut.table) {
+            assert!((0..3).all(|c| (a[c] - b[c]).abs() < 0.5 / 255.0));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stack_unstack() -> Result<()> {
+        let texture = identity(4).to_texture()?;
+        let slices = texture.unstack_slices()?;
+        assert_eq!(slices.len(), 4);
+        assert_eq!(slices[2].dimensions(), Dimensions::try_from([4, 4])?);
+
+        let stacked = Texture::stack_slices(&slices)?;
+        assert_eq!(stacked.dimensions(), texture.dimensions());
+        assert_eq!(stacked.primary().buffer, texture.primary().buffer);
+
+        // slices must match, and must be 2D
+        let small = Texture::new(
+            texture.format,
+            TextureShapeNode::from_surface(Surface::new(
+                Dimensions::try_from([2, 2])?,
+                vec![0u8; 16],
+            )),
+        )?;
+        assert!(Texture::stack_slices(&[slices[0].clone(), small]).is_err());
+        assert!(Texture::stack_slices(&[texture.clone()]).is_err());
+        assert!(Texture::stack_slices(&[]).is_err());
+        assert!(slices[0].unstack_slices().is_err());
+        Ok(())
+    }
+}
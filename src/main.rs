@@ -2,15 +2,47 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::fs::File;
+use std::process::ExitCode;
 
-use quicktex::container::ContainerHeader;
-use quicktex::dds;
+use clap::command;
 
-fn main() {
-    let mut the_file = File::open("/Users/drewcassidy/Downloads/cmft_cubemap.dds").unwrap();
-    let the_dds = dds::DDSHeader::read_texture(&mut the_file).unwrap();
-    println!("{the_dds:#?}");
-    let new_header = dds::DDSHeader::from_texture(&the_dds);
-    println!("{new_header:#?}");
+mod cli;
+
+fn main() -> ExitCode {
+    let matches = command!()
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(cli::compare::command())
+        .subcommand(cli::convert::command())
+        .subcommand(cli::cook::command())
+        .subcommand(cli::decompress::command())
+        .subcommand(cli::ls::command())
+        .subcommand(cli::montage::command())
+        .subcommand(cli::show::command())
+        .get_matches();
+
+    let result = match matches.subcommand() {
+        Some(("compare", args)) => cli::compare::run(args),
+        Some(("convert", args)) => cli::convert::run(args),
+        Some(("cook", args)) => cli::cook::run(args),
+        Some(("decompress", args)) => cli::decompress::run(args),
+        Some(("ls", args)) => cli::ls::run(args),
+        Some(("montage", args)) => cli::montage::run(args),
+        Some(("show", args)) => cli::show::run(args),
+        _ => unreachable!("clap should reject unknown subcommands"),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        #[cfg(feature = "miette")]
+        Err(e) if e.is::<quicktex::diagnostics::FileReport>() => {
+            let report = e.downcast::<quicktex::diagnostics::FileReport>().unwrap();
+            eprintln!("{:?}", miette::Report::new(*report));
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
 }
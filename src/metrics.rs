@@ -0,0 +1,225 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Comparing two textures of the same shape, for codec quality regression testing.
+//!
+//! Both textures are decoded to 32-bit float RGBA, so textures in different formats can be compared,
+//! and errors are measured in normalized units where 1.0 is the full range of an 8-bit channel.
+//! PSNR uses a peak of 1.0, so values above 1 in HDR textures can give an MSE larger than the peak
+
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::error::TextureResult;
+use crate::format::Decoder;
+use crate::shape::{CubeFace, ShapeError};
+use crate::texture::{Surface, Texture};
+
+/// Differences between a single pair of surfaces, from [`Texture::compare`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurfaceMetrics {
+    /// The array layer of the surface, if the textures are arrays
+    pub layer: Option<usize>,
+
+    /// The cubemap face of the surface, if the textures are cubemaps
+    pub face: Option<CubeFace>,
+
+    /// The mip level of the surface, if the textures have mipmaps
+    pub mip: Option<usize>,
+
+    /// Dimensions of the surface
+    pub dimensions: Dimensions,
+
+    /// Mean squared error over every channel of every pixel
+    pub mse: f64,
+
+    /// Largest absolute difference between any two corresponding channels
+    pub max_error: f64,
+}
+
+impl SurfaceMetrics {
+    /// Peak signal-to-noise ratio in decibels. Infinite if the surfaces are identical
+    pub fn psnr(&self) -> f64 {
+        psnr(self.mse)
+    }
+}
+
+/// Differences between every pair of surfaces in two textures, from [`Texture::compare`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComparisonReport {
+    /// Differences for each surface, in the same order as [`TextureSlice::iter`](crate::shape::TextureSlice::iter)
+    pub surfaces: Vec<SurfaceMetrics>,
+}
+
+impl ComparisonReport {
+    /// Mean squared error over every channel of every surface, weighted by the size of each surface
+    pub fn mse(&self) -> f64 {
+        let pixels: usize = (self.surfaces.iter())
+            .map(|s| s.dimensions.product() as usize)
+            .sum();
+        match pixels {
+            0 => 0.0,
+            pixels => {
+                let total: f64 = (self.surfaces.iter())
+                    .map(|s| s.mse * s.dimensions.product() as f64)
+                    .sum();
+                total / pixels as f64
+            }
+        }
+    }
+
+    /// Peak signal-to-noise ratio of the whole texture in decibels. Infinite if the textures are identical
+    pub fn psnr(&self) -> f64 {
+        psnr(self.mse())
+    }
+
+    /// The lowest PSNR of any surface, which is what a regression threshold usually cares about
+    pub fn min_psnr(&self) -> f64 {
+        (self.surfaces.iter())
+            .map(SurfaceMetrics::psnr)
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Largest absolute difference between any two corresponding channels in any surface
+    pub fn max_error(&self) -> f64 {
+        self.surfaces
+            .iter()
+            .map(|s| s.max_error)
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Peak signal-to-noise ratio in decibels of a mean squared error with a peak of 1.0
+fn psnr(mse: f64) -> f64 {
+    -10.0 * mse.log10()
+}
+
+/// Decode `surface` to 32-bit float RGBA
+fn decode(surface: &Surface, decoder: &dyn Decoder) -> TextureResult<Vec<f32>> {
+    let image = decoder.decode_buffer(surface.buffer.clone(), surface.dimensions())?;
+    Ok(image.into_rgba32f().into_raw())
+}
+
+impl Texture {
+    /// Measure how much `other` differs from this texture, surface by surface.
+    /// The textures can be in different formats, but must have the same shape and dimensions.
+    /// Returns [`ShapeError::NonUniform`] if they don't, or [`TextureError::Format`](crate::error::TextureError::Format)
+    /// if either format can't be decoded
+    pub fn compare(&self, other: &Texture) -> TextureResult<ComparisonReport> {
+        self.check_format()?;
+        other.check_format()?;
+        let (a_decoder, b_decoder) = (self.format.decoder()?, other.format.decoder()?);
+        let (a_slice, b_slice) = (self.slice(), other.slice());
+        let (a_iter, b_iter) = (a_slice.iter(), b_slice.iter());
+        if a_iter.len() != b_iter.len() {
+            return Err(ShapeError::NonUniform("shape").into());
+        }
+
+        let mut surfaces = Vec::new();
+        for (a, b) in a_iter.zip(b_iter) {
+            if (a.layer, a.face, a.mip) != (b.layer, b.face, b.mip) {
+                return Err(ShapeError::NonUniform("shape").into());
+            }
+            let dimensions = a.surface.dimensions();
+            if dimensions != b.surface.dimensions() {
+                return Err(ShapeError::NonUniform("dimensions").into());
+            }
+
+            let a_pixels = decode(a.surface, a_decoder.as_ref())?;
+            let b_pixels = decode(b.surface, b_decoder.as_ref())?;
+            let (mut squared, mut max_error) = (0.0f64, 0.0f64);
+            for (&a, &b) in a_pixels.iter().zip(&b_pixels) {
+                let error = (a as f64 - b as f64).abs();
+                squared += error * error;
+                max_error = max_error.max(error);
+            }
+            let mse = match a_pixels.len() {
+                0 => 0.0,
+                n => squared / n as f64,
+            };
+            surfaces.push(SurfaceMetrics {
+                layer: a.layer,
+                face: a.face,
+                mip: a.mip,
+                dimensions,
+                mse,
+                max_error,
+            });
+        }
+        Ok(ComparisonReport { surfaces })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::format::{ChannelType, Format, Quality};
+    use crate::shape::TextureShapeNode;
+
+    use super::*;
+
+    fn gradient() -> Result<Texture> {
+        let image = image::RgbaImage::from_fn(8, 8, |x, y| {
+            image::Rgba([x as u8 * 8, y as u8 * 8, 128, 255])
+        });
+        Ok(Texture::from_image(
+            &image.into(),
+            Format::rgba8(false),
+            true,
+            Quality::Normal,
+        )?)
+    }
+
+    #[test]
+    fn identical() -> Result<()> {
+        let texture = gradient()?;
+        let report = texture.compare(&texture)?;
+        assert_eq!(report.surfaces.len(), 4);
+        assert_eq!(report.mse(), 0.0);
+        assert_eq!(report.psnr(), f64::INFINITY);
+        assert_eq!(report.min_psnr(), f64::INFINITY);
+        assert_eq!(report.max_error(), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn across_formats() -> Result<()> {
+        let texture = gradient()?;
+        let encoded = texture.transcode(Format::BC1 { srgb: false }, Quality::Normal)?;
+        let report = texture.compare(&encoded)?;
+        assert!(report.psnr() > 30.0, "{}", report.psnr());
+        assert!(report.min_psnr() <= report.psnr());
+        assert!(report.max_error() > 0.0);
+        assert_eq!(report.surfaces[3].mip, Some(3));
+
+        let float = Format::Channels {
+            channel_type: ChannelType::Float32,
+            channels: 4,
+        };
+        let report = texture.compare(&texture.transcode(float, Quality::Normal)?)?;
+        assert!(report.max_error() < 1e-6, "{}", report.max_error());
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        let surface = |value| {
+            let surface = Surface::new(Dimensions::try_from([1, 1])?, vec![value, 0, 0, 255]);
+            Texture::new(
+                Format::rgba8(false),
+                TextureShapeNode::from_surface(surface),
+            )
+        };
+        let (black, red) = (surface(0)?, surface(255)?);
+        let report = black.compare(&red)?;
+        assert_eq!(report.max_error(), 1.0);
+        assert_eq!(report.mse(), 0.25);
+        assert!((report.psnr() - 6.02).abs() < 0.01);
+
+        assert!(matches!(
+            black.compare(&gradient()?),
+            Err(crate::error::TextureError::Shape(ShapeError::NonUniform(_)))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,341 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Incremental mip updates, for painting tools that change a small part of a large texture.
+//!
+//! After a region of mip 0 changes, [`Texture::update_mips_for_region`] only decodes, filters, and encodes
+//! the part of each lower mip that the change can reach through the filter, instead of the whole chain.
+//! Regions are widened to whole blocks, and each mip is filtered from a crop of mip 0 with enough margin
+//! that the result matches filtering the entire surface. Mips whose size doesn't divide the size of mip 0
+//! evenly, which only happens for dimensions that aren't powers of two, are regenerated in full
+
+use image::imageops::FilterType;
+
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::format::{Format, Quality};
+use crate::resize::resize_filtered;
+use crate::shape::TextureShape;
+use crate::texture::{Surface, Texture};
+
+/// A rectangle of pixels within a surface
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    /// The left edge, in pixels from the left of the surface
+    pub x: u32,
+    /// The top edge, in pixels from the top of the surface
+    pub y: u32,
+    /// The width in pixels
+    pub width: u32,
+    /// The height in pixels
+    pub height: u32,
+}
+
+impl Rect {
+    /// Create a new rectangle from its top left corner and size
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// If the rectangle contains no pixels
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The part of the rectangle inside a surface of the given width and height
+    fn clamp(self, width: u32, height: u32) -> Self {
+        let (x, y) = (self.x.min(width), self.y.min(height));
+        let right = self.x.saturating_add(self.width).min(width);
+        let bottom = self.y.saturating_add(self.height).min(height);
+        Self::new(x, y, right - x, bottom - y)
+    }
+
+    /// Grow the rectangle outwards to the edges of `block`-sized blocks, or of a surface of the given size
+    fn align(self, block: Dimensions, width: u32, height: u32) -> Self {
+        let (bw, bh) = (block.width(), block.height());
+        let x = self.x / bw * bw;
+        let y = self.y / bh * bh;
+        let right = (self.x + self.width).next_multiple_of(bw).min(width);
+        let bottom = (self.y + self.height).next_multiple_of(bh).min(height);
+        Self::new(x, y, right - x, bottom - y)
+    }
+}
+
+/// How far the filter reaches, in pixels of the smaller image
+fn support(filter: FilterType) -> u32 {
+    match filter {
+        FilterType::Nearest => 0,
+        FilterType::Triangle => 1,
+        FilterType::CatmullRom => 2,
+        FilterType::Gaussian | FilterType::Lanczos3 => 3,
+    }
+}
+
+/// The offset of `rect` within a surface's buffer for each row of blocks, and the length of each row
+fn block_rows(
+    dimensions: Dimensions,
+    format: Format,
+    rect: Rect,
+) -> TextureResult<Vec<(usize, usize)>> {
+    let block = format.block_dimensions()?;
+    let (bw, bh) = (block.width(), block.height());
+    let block_size = format.size_for(block)?;
+    let stride = dimensions.width().div_ceil(bw) as usize * block_size;
+    let start = (rect.x / bw) as usize * block_size;
+    let len = rect.width.div_ceil(bw) as usize * block_size;
+    Ok((rect.y / bh..(rect.y + rect.height).div_ceil(bh))
+        .map(|row| (row as usize * stride + start, len))
+        .collect())
+}
+
+/// Copy a block-aligned rectangle out of a surface into a surface of its own
+fn crop(surface: &Surface, format: Format, rect: Rect) -> TextureResult<Surface> {
+    let buffer = block_rows(surface.dimensions, format, rect)?
+        .into_iter()
+        .flat_map(|(offset, len)| surface.buffer[offset..offset + len].iter().copied())
+        .collect::<Vec<u8>>();
+    Ok(Surface::new(
+        Dimensions::try_from([rect.width, rect.height])?,
+        buffer,
+    ))
+}
+
+/// Copy a block-aligned rectangle into a surface, from the contents of `patch`
+fn splice(surface: &mut Surface, format: Format, rect: Rect, patch: &[u8]) -> TextureResult<()> {
+    let mut buffer = surface.buffer.to_vec();
+    let mut source = 0;
+    for (offset, len) in block_rows(surface.dimensions, format, rect)? {
+        buffer[offset..offset + len].copy_from_slice(&patch[source..source + len]);
+        source += len;
+    }
+    surface.buffer = buffer.into();
+    Ok(())
+}
+
+impl Texture {
+    /// Recompute the part of every lower mip that depends on `rect` of mip 0, after that region has changed,
+    /// filtering with `filter` like [`resize`](crate::resize::resize) does. Use [`FilterType::Triangle`] to
+    /// match the mips made by [`Self::from_image`]. Lower mips are filtered from the decoded contents of mip 0,
+    /// and the same region is updated in every layer and face.
+    ///
+    /// Returns the region of each mip that was rewritten, starting with `rect` clamped to mip 0.
+    /// Returns an error if the texture is 3D, or its format can't be decoded and encoded
+    pub fn update_mips_for_region(
+        &mut self,
+        rect: Rect,
+        filter: FilterType,
+    ) -> TextureResult<Vec<Rect>> {
+        let dimensions = self.surfaces.try_dimensions()?;
+        if dimensions.depth() > 1 {
+            return Err(TextureError::Capability(
+                "Mips of 3D textures cannot be updated by region".to_string(),
+            ));
+        }
+        let (width, height) = (dimensions.width(), dimensions.height());
+        let rect = rect.clamp(width, height);
+        let mut updated = vec![rect];
+        let Some(mips) = self.mips() else {
+            return Ok(updated);
+        };
+        if rect.is_empty() {
+            return Ok(updated);
+        }
+
+        let (decoder, encoder) = (self.format.decoder()?, self.format.encoder()?);
+        let block = self.format.block_dimensions()?;
+        let (format, srgb, margin) = (self.format, self.format.srgb(), support(filter) + 1);
+        let chains = (self.slice().iter())
+            .filter(|s| s.mip == Some(0))
+            .map(|s| (s.layer, s.face))
+            .collect::<Vec<_>>();
+
+        for mip in 1..mips {
+            let (mw, mh) = (width.max(1 << mip) >> mip, height.max(1 << mip) >> mip);
+            let (dirty, extended, source) = match width % mw == 0 && height % mh == 0 {
+                true => {
+                    let (rx, ry) = (width / mw, height / mh);
+                    let (x, y) = (rect.x / rx, rect.y / ry);
+                    let dirty = Rect::new(
+                        x.saturating_sub(margin),
+                        y.saturating_sub(margin),
+                        (rect.x + rect.width).div_ceil(rx) + margin - x.saturating_sub(margin),
+                        (rect.y + rect.height).div_ceil(ry) + margin - y.saturating_sub(margin),
+                    )
+                    .clamp(mw, mh)
+                    .align(block, mw, mh);
+                    // pixels near the edge of the dirty region are filtered from pixels outside it
+                    let extended = Rect::new(
+                        dirty.x.saturating_sub(margin),
+                        dirty.y.saturating_sub(margin),
+                        dirty.width + 2 * margin,
+                        dirty.height + 2 * margin,
+                    )
+                    .clamp(mw, mh)
+                    .align(block, mw, mh);
+                    let source = Rect::new(
+                        extended.x * rx,
+                        extended.y * ry,
+                        extended.width * rx,
+                        extended.height * ry,
+                    )
+                    .clamp(width, height);
+                    (dirty, extended, source)
+                }
+                false => {
+                    let full = Rect::new(0, 0, mw, mh);
+                    (full, full, Rect::new(0, 0, width, height))
+                }
+            };
+
+            for &(layer, face) in &chains {
+                let top = self
+                    .surfaces
+                    .get_surface_mut(layer, face, Some(0))
+                    .ok_or(TextureError::Other("Mip 0 is missing".to_string()))?;
+                let source = crop(top, format, source)?;
+                let image = decoder.decode_buffer(source.buffer, source.dimensions)?;
+                let filtered =
+                    resize_filtered(&image, extended.width, extended.height, srgb, filter);
+                let patch = filtered.crop_imm(
+                    dirty.x - extended.x,
+                    dirty.y - extended.y,
+                    dirty.width,
+                    dirty.height,
+                );
+                let patch = encoder.encode_buffer(patch, Quality::default());
+
+                let surface = self
+                    .surfaces
+                    .get_surface_mut(layer, face, Some(mip))
+                    .ok_or(TextureError::Other(format!("Mip {mip} is missing")))?;
+                splice(surface, format, dirty, &patch)?;
+            }
+            updated.push(dirty);
+        }
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use generic_parameterize::parameterize;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let v = (x * 7919 + y * 104729) as u8;
+            Rgba([v, v.wrapping_mul(3), x as u8, 255 - y as u8])
+        })
+    }
+
+    fn buffers(texture: &Texture) -> Vec<Vec<u8>> {
+        texture
+            .slice()
+            .iter()
+            .map(|s| s.surface.buffer.to_vec())
+            .collect()
+    }
+
+    /// Paint a square into mip 0 of `texture` and the source image, and update the mips incrementally
+    fn paint(
+        texture: &mut Texture,
+        image: &mut RgbaImage,
+        rect: Rect,
+        filter: FilterType,
+    ) -> Result<Vec<Rect>> {
+        for y in rect.y..(rect.y + rect.height).min(image.height()) {
+            for x in rect.x..(rect.x + rect.width).min(image.width()) {
+                image.put_pixel(x, y, Rgba([255, 0, 128, 200]));
+            }
+        }
+        let top = DynamicImage::from(image.clone());
+        let encoded = Texture::from_image(&top, texture.format, false, Quality::default())?;
+        let top = texture
+            .surfaces
+            .get_surface_mut(None, None, Some(0))
+            .unwrap();
+        top.buffer = encoded.primary().buffer;
+        Ok(texture.update_mips_for_region(rect, filter)?)
+    }
+
+    #[parameterize(srgb = [true, false], fmt = "matches_full_{srgb}")]
+    #[test]
+    /// Updating mips incrementally should give exactly the same result as regenerating the whole chain
+    fn matches_full(srgb: bool) -> Result<()> {
+        let format = Format::rgba8(srgb);
+        let mut image = noise(64, 32);
+        let mut texture =
+            Texture::from_image(&image.clone().into(), format, true, Quality::Normal)?;
+        let updated = paint(
+            &mut texture,
+            &mut image,
+            Rect::new(20, 9, 3, 2),
+            FilterType::Triangle,
+        )?;
+        let full = Texture::from_image(&image.into(), format, true, Quality::Normal)?;
+        assert_eq!(buffers(&texture), buffers(&full));
+
+        assert_eq!(updated.len(), 7);
+        assert_eq!(updated[0], Rect::new(20, 9, 3, 2));
+        assert_eq!(updated[1], Rect::new(8, 2, 6, 6));
+        assert!(updated[1].width < 32, "mip 1 should only be partly updated");
+        assert_eq!(updated[6], Rect::new(0, 0, 1, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn filters_and_formats() -> Result<()> {
+        // odd sizes regenerate mips that don't divide evenly, and other filters reach further
+        let mut image = noise(24, 18);
+        let format = Format::rgba8(false);
+        let mut texture =
+            Texture::from_image(&image.clone().into(), format, true, Quality::Normal)?;
+        let updated = paint(
+            &mut texture,
+            &mut image,
+            Rect::new(2, 3, 1, 1),
+            FilterType::CatmullRom,
+        )?;
+        assert_eq!(
+            updated[2],
+            Rect::new(0, 0, 6, 4),
+            "6x4 mip does not divide 24x18"
+        );
+        assert!(updated[1].width < 12);
+
+        // block formats update whole blocks
+        let bc1 = Format::BC1 { srgb: false };
+        let mut image = noise(64, 64);
+        let mut texture = Texture::from_image(&image.clone().into(), bc1, true, Quality::Normal)?;
+        let before = buffers(&texture);
+        let updated = paint(
+            &mut texture,
+            &mut image,
+            Rect::new(60, 60, 8, 8),
+            FilterType::Triangle,
+        )?;
+        assert_eq!(updated[0], Rect::new(60, 60, 4, 4));
+        assert_eq!(updated[1], Rect::new(28, 28, 4, 4));
+        let after = buffers(&texture);
+        assert_ne!(before[1], after[1]);
+        // the top left block of mip 1 is far from the change
+        assert_eq!(before[1][..8], after[1][..8]);
+
+        let mut flat = Texture::from_image(&image.into(), bc1, false, Quality::Normal)?;
+        assert_eq!(
+            flat.update_mips_for_region(Rect::new(0, 0, 4, 4), FilterType::Triangle)?
+                .len(),
+            1
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Contact sheets of every surface in a texture, for reviewing arrays, cubemaps, and mip chains at a glance
+
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::error::{TextureError, TextureResult};
+use crate::shape::{CubeFace, TextureIterResult};
+use crate::texture::{Surface, Texture};
+
+/// Pixels between cells, and around the edge of the sheet
+const PADDING: u32 = 2;
+/// Height of a glyph in the label font, in pixels
+const GLYPH_HEIGHT: u32 = 5;
+/// Width of a glyph in the label font, plus one pixel of spacing
+const GLYPH_ADVANCE: u32 = 4;
+
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const TEXT: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// A 3x5 pixel glyph, as 5 rows of 3 bits with the most significant bit on the left
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
+
+/// Draw `text` with its top left corner at (`x`, `y`), clipping anything outside the image
+fn draw_text(image: &mut RgbaImage, text: &str, x: u32, y: u32) {
+    for (i, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for column in 0..3 {
+                let (px, py) = (x + i as u32 * GLYPH_ADVANCE + column, y + row as u32);
+                if bits & (0b100 >> column) != 0 && px < image.width() && py < image.height() {
+                    image.put_pixel(px, py, TEXT);
+                }
+            }
+        }
+    }
+}
+
+fn label(surface: &TextureIterResult<&Surface>) -> String {
+    let face = |f: CubeFace| match f {
+        CubeFace::PositiveX => "+X",
+        CubeFace::NegativeX => "-X",
+        CubeFace::PositiveY => "+Y",
+        CubeFace::NegativeY => "-Y",
+        CubeFace::PositiveZ => "+Z",
+        CubeFace::NegativeZ => "-Z",
+    };
+    [
+        surface.layer.map(|l| format!("L{l}")),
+        surface.face.map(|f| face(f).to_string()),
+        surface.mip.map(|m| format!("M{m}")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+impl Texture {
+    /// Decode every surface of the texture and tile them into a single contact sheet image,
+    /// `columns` cells wide, in the same layer, face, then mip order as [`TextureSlice::iter`](crate::shape::TextureSlice::iter).
+    ///
+    /// Each cell is labeled with its layer (`L0`), face (`+X`), and mip (`M0`), leaving out
+    /// structures the texture doesn't have. Cells are sized to fit the largest surface,
+    /// with smaller surfaces in the top left corner.
+    /// Returns an error if `columns` is 0, or the texture's format can't be decoded
+    pub fn montage(&self, columns: usize) -> TextureResult<RgbaImage> {
+        if columns == 0 {
+            return Err(TextureError::Other(
+                "A montage needs at least one column".to_string(),
+            ));
+        }
+        let decoder = self.format.decoder()?;
+        let cells = self
+            .slice()
+            .iter()
+            .map(|s| {
+                let image =
+                    decoder.decode_buffer(s.surface.buffer.clone(), s.surface.dimensions)?;
+                Ok((label(&s), image.into_rgba8()))
+            })
+            .collect::<TextureResult<Vec<_>>>()?;
+
+        let label_height = GLYPH_HEIGHT + PADDING;
+        let cell_width = cells
+            .iter()
+            .map(|(l, i)| i.width().max(l.chars().count() as u32 * GLYPH_ADVANCE))
+            .max()
+            .unwrap_or(0);
+        let cell_height = cells.iter().map(|(_, i)| i.height()).max().unwrap_or(0) + label_height;
+        let columns = columns.min(cells.len()) as u32;
+        let rows = cells.len().div_ceil(columns as usize) as u32;
+
+        let mut sheet = RgbaImage::from_pixel(
+            PADDING + columns * (cell_width + PADDING),
+            PADDING + rows * (cell_height + PADDING),
+            BACKGROUND,
+        );
+        for (i, (label, image)) in cells.iter().enumerate() {
+            let x = PADDING + (i as u32 % columns) * (cell_width + PADDING);
+            let y = PADDING + (i as u32 / columns) * (cell_height + PADDING);
+            draw_text(&mut sheet, label, x, y);
+            imageops::replace(&mut sheet, image, x as i64, (y + label_height) as i64);
+        }
+        Ok(sheet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::format::{AlphaFormat, ColorFormat, Format, Quality};
+
+    use super::*;
+
+    #[test]
+    fn mip_chain() -> Result<()> {
+        let format = Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0xFF,
+                g_mask: 0xFF00,
+                b_mask: 0xFF0000,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xFF000000,
+            },
+        };
+        let image = RgbaImage::from_pixel(16, 16, Rgba([200, 0, 0, 255]));
+        let texture = Texture::from_image(&image.into(), format, true, Quality::Normal)?;
+
+        // 5 mips in 2 rows of 3 cells, each 16 pixels wide and 16 + 7 tall
+        let sheet = texture.montage(3)?;
+        assert_eq!(sheet.dimensions(), (2 + 3 * 18, 2 + 2 * 25));
+        assert_eq!(*sheet.get_pixel(2, 9), Rgba([200, 0, 0, 255]), "mip 0");
+        assert_eq!(
+            *sheet.get_pixel(20 + 1, 9 + 1),
+            Rgba([200, 0, 0, 255]),
+            "mip 1"
+        );
+        assert_eq!(*sheet.get_pixel(20 + 9, 9 + 9), BACKGROUND, "outside mip 1");
+        assert_eq!(*sheet.get_pixel(2, 2), TEXT, "label 'M0'");
+
+        assert_eq!(texture.montage(10)?.dimensions(), (2 + 5 * 18, 2 + 25));
+        assert!(texture.montage(0).is_err());
+        Ok(())
+    }
+}
@@ -7,10 +7,85 @@ use std::iter::{zip, Map};
 use bitvec::field::BitField;
 use bitvec::prelude::*;
 use funty::Integral;
+use thiserror::Error;
 
-pub trait Pack: IntoIterator {
-    fn pack_le<V: Integral + Into<isize>, U: IntoIterator<Item = V>>(self, unpacked: U);
-    fn pack_be<V: Integral + Into<isize>, U: IntoIterator<Item = V>>(self, unpacked: U);
+/// Error for a value that does not fit in the bit slice it is being packed into
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Value {value} cannot be packed into {bits} bits")]
+pub struct PackError {
+    /// The value being packed, saturated if it does not fit in an `i128`
+    pub value: i128,
+    /// The length of the destination slice
+    pub bits: usize,
+}
+
+/// Check if `value` can be stored in `bits` bits without losing information.
+/// Unsigned values must be in `0..2^bits`, and signed values in `-2^(bits-1)..2^(bits-1)`
+/// so that they can be sign-extended when unpacked
+pub fn fits<V: Integral>(value: V, bits: usize) -> bool {
+    if bits > V::BITS as usize {
+        return false;
+    }
+    let Ok(value) = TryInto::<i128>::try_into(value) else {
+        return bits == V::BITS as usize; // only u128 values above i128::MAX
+    };
+    if bits >= 127 {
+        return true;
+    }
+    match V::MIN == V::ZERO {
+        true => (0..1i128 << bits).contains(&value),
+        false => (-(1i128 << bits >> 1)..1i128 << bits >> 1).contains(&value),
+    }
+}
+
+fn check<V: Integral>(value: V, bits: usize) -> Result<(), PackError> {
+    match fits(value, bits) {
+        true => Ok(()),
+        false => Err(PackError {
+            value: TryInto::<i128>::try_into(value).unwrap_or(i128::MAX),
+            bits,
+        }),
+    }
+}
+
+/// Pack integers into an iterator of bit slices, one value per slice.
+///
+/// Values can be signed or unsigned, and slices can be up to the width of the value type.
+/// Signed values are stored in two's complement, so they can be read back with [`Unpack`] into the same type
+pub trait Pack: IntoIterator + Sized {
+    /// Pack each value of `unpacked` into the corresponding slice, little-endian.
+    /// Returns an error for the first value that doesn't fit in its slice. Values before it are still packed
+    fn try_pack_le<V: Integral, U: IntoIterator<Item = V>>(
+        self,
+        unpacked: U,
+    ) -> Result<(), PackError>;
+
+    /// Pack each value of `unpacked` into the corresponding slice, big-endian.
+    /// Returns an error for the first value that doesn't fit in its slice. Values before it are still packed
+    fn try_pack_be<V: Integral, U: IntoIterator<Item = V>>(
+        self,
+        unpacked: U,
+    ) -> Result<(), PackError>;
+
+    /// Pack each value of `unpacked` into the corresponding slice, little-endian,
+    /// keeping only the lowest bits of values that don't fit
+    fn pack_le_unchecked<V: Integral, U: IntoIterator<Item = V>>(self, unpacked: U);
+
+    /// Pack each value of `unpacked` into the corresponding slice, big-endian,
+    /// keeping only the lowest bits of values that don't fit
+    fn pack_be_unchecked<V: Integral, U: IntoIterator<Item = V>>(self, unpacked: U);
+
+    /// Pack each value of `unpacked` into the corresponding slice, little-endian.
+    /// Panics if a value doesn't fit in its slice
+    fn pack_le<V: Integral, U: IntoIterator<Item = V>>(self, unpacked: U) {
+        self.try_pack_le(unpacked).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Pack each value of `unpacked` into the corresponding slice, big-endian.
+    /// Panics if a value doesn't fit in its slice
+    fn pack_be<V: Integral, U: IntoIterator<Item = V>>(self, unpacked: U) {
+        self.try_pack_be(unpacked).unwrap_or_else(|e| panic!("{e}"))
+    }
 }
 
 impl<'a, T: BitStore, O: BitOrder, I> Pack for I
@@ -18,35 +93,46 @@ where
     I: IntoIterator<Item = &'a mut BitSlice<T, O>>,
     BitSlice<T, O>: BitField,
 {
-    fn pack_le<V: Integral + Into<isize>, U: IntoIterator<Item = V>>(self, unpacked: U) {
+    fn try_pack_le<V: Integral, U: IntoIterator<Item = V>>(
+        self,
+        unpacked: U,
+    ) -> Result<(), PackError> {
         for (src, dst) in zip(unpacked, self) {
-            assert!(
-                src.into() < (1 << (dst.len()) - 1) as isize,
-                "Input value {:X} cannot be packed into {} bits",
-                src,
-                dst.len()
-            );
+            check(src, dst.len())?;
             dst.store_le(src);
         }
+        Ok(())
     }
 
-    fn pack_be<V: Integral + Into<isize>, U: IntoIterator<Item = V>>(self, unpacked: U) {
+    fn try_pack_be<V: Integral, U: IntoIterator<Item = V>>(
+        self,
+        unpacked: U,
+    ) -> Result<(), PackError> {
         for (src, dst) in zip(unpacked, self) {
-            assert!(
-                src.into() < (1 << (dst.len()) - 1) as isize,
-                "Input value {:X} cannot be packed into {} bits",
-                src,
-                dst.len()
-            );
+            check(src, dst.len())?;
             dst.store_be(src);
         }
+        Ok(())
+    }
+
+    fn pack_le_unchecked<V: Integral, U: IntoIterator<Item = V>>(self, unpacked: U) {
+        zip(unpacked, self).for_each(|(src, dst)| dst.store_le(src));
+    }
+
+    fn pack_be_unchecked<V: Integral, U: IntoIterator<Item = V>>(self, unpacked: U) {
+        zip(unpacked, self).for_each(|(src, dst)| dst.store_be(src));
     }
 }
 
+/// Unpack integers from an iterator of bit slices, one value per slice.
+///
+/// Unpacking into a signed type sign-extends each value from the top bit of its slice
 pub trait Unpack: IntoIterator + Sized {
+    /// Unpack each slice as a little-endian integer
     fn unpack_le<V: Integral>(
         self,
     ) -> Map<<Self as IntoIterator>::IntoIter, fn(<Self as IntoIterator>::Item) -> V>;
+    /// Unpack each slice as a big-endian integer
     fn unpack_be<V: Integral>(
         self,
     ) -> Map<<Self as IntoIterator>::IntoIter, fn(<Self as IntoIterator>::Item) -> V>;
@@ -69,3 +155,115 @@ where
         self.into_iter().map(|b| b.load_be())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+
+    #[test]
+    fn fits_range() {
+        assert!(fits(3u8, 2));
+        assert!(!fits(4u8, 2));
+        assert!(fits(255u8, 8));
+        assert!(!fits(1u8, 9), "wider than the value type");
+        assert!(fits(0u8, 0));
+        assert!(!fits(1u8, 0));
+
+        assert!(fits(1i8, 2));
+        assert!(fits(-2i8, 2));
+        assert!(!fits(2i8, 2));
+        assert!(!fits(-3i8, 2));
+        assert!(fits(i16::MIN, 16));
+        assert!(fits(u64::MAX, 64));
+        assert!(fits(u128::MAX, 128));
+        assert!(!fits(u128::MAX, 127));
+    }
+
+    #[test]
+    fn roundtrip_unsigned() {
+        // 2-bit codes, covering the values the old range check rejected
+        let mut word = 0u8;
+        word.view_bits_mut::<Msb0>()
+            .chunks_mut(2)
+            .pack_le([0u8, 1, 2, 3]);
+        assert_eq!(word, 0b00_01_10_11);
+        let codes: Vec<u8> = word.view_bits::<Msb0>().chunks(2).unpack_le().collect();
+        assert_eq!(codes, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn roundtrip_wide() {
+        // 12 and 16-bit fields, as used by BC6H endpoints
+        let values = [0xABCu16, 0x123, 0xFFF];
+        let mut words = [0u16; 3];
+        words.view_bits_mut::<Lsb0>().chunks_mut(12).pack_le(values);
+        let unpacked: Vec<u16> = words
+            .view_bits::<Lsb0>()
+            .chunks(12)
+            .take(3)
+            .unpack_le()
+            .collect();
+        assert_eq!(unpacked, values);
+
+        let mut word = 0u32;
+        word.view_bits_mut::<Msb0>()
+            .chunks_mut(16)
+            .pack_be([0xBEEFu16, 0xCAFE]);
+        assert_eq!(word.to_be_bytes(), [0xBE, 0xEF, 0xCA, 0xFE]);
+        let unpacked: Vec<u16> = word.view_bits::<Msb0>().chunks(16).unpack_be().collect();
+        assert_eq!(unpacked, [0xBEEF, 0xCAFE]);
+    }
+
+    #[test]
+    fn roundtrip_signed() {
+        // every 5-bit signed delta
+        let values = (-16i16..16).collect_vec();
+        let mut words = [0u64; 3];
+        words
+            .view_bits_mut::<Lsb0>()
+            .chunks_mut(5)
+            .pack_le(values.iter().copied());
+        let unpacked: Vec<i16> = words
+            .view_bits::<Lsb0>()
+            .chunks(5)
+            .take(values.len())
+            .unpack_le()
+            .collect();
+        assert_eq!(unpacked, values);
+    }
+
+    #[test]
+    fn checked() {
+        let mut word = 0u8;
+        let result = word
+            .view_bits_mut::<Lsb0>()
+            .chunks_mut(2)
+            .try_pack_le([1u8, 4, 2]);
+        assert_eq!(result, Err(PackError { value: 4, bits: 2 }));
+        assert_eq!(word, 0b01, "values before the error are packed");
+
+        let result = word
+            .view_bits_mut::<Lsb0>()
+            .chunks_mut(3)
+            .try_pack_le([-5i8]);
+        assert_eq!(result, Err(PackError { value: -5, bits: 3 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Value 8 cannot be packed into 3 bits")]
+    fn checked_panics() {
+        let mut word = 0u8;
+        word.view_bits_mut::<Lsb0>().chunks_mut(3).pack_le([8u8]);
+    }
+
+    #[test]
+    fn unchecked() {
+        let mut word = 0u8;
+        word.view_bits_mut::<Lsb0>()
+            .chunks_mut(4)
+            .pack_le_unchecked([0x1Fu8, 0xF2]);
+        assert_eq!(word, 0x2F);
+    }
+}
@@ -0,0 +1,328 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Binary patches between two versions of a texture, so updates only need to ship the blocks that changed.
+//!
+//! A [`TexturePatch`](crate::patch::TexturePatch) lists, for each surface that changed, runs of consecutive blocks with their new contents.
+//! Blocks are compared as encoded bytes: a block of a BCn format, or a single pixel of an uncompressed format.
+//! Each surface patch records a hash of the surface it was made against and of the surface it produces,
+//! so applying a patch to the wrong texture is an error rather than silent corruption.
+//!
+//! ```
+//! use std::io::Cursor;
+//! use binrw::{BinReaderExt, BinWriterExt};
+//! use quicktex::dimensions::Dimensions;
+//! use quicktex::format::Format;
+//! use quicktex::patch::TexturePatch;
+//! use quicktex::shape::TextureShapeNode;
+//! use quicktex::texture::{Surface, Texture};
+//!
+//! // a 64x64 BC1 surface, with a single block changed
+//! let dimensions = Dimensions::try_from([64, 64])?;
+//! let mut bytes = vec![0u8; 2048];
+//! let old = Texture::new(Format::BC1 { srgb: false }, TextureShapeNode::from_surface(Surface::new(dimensions, bytes.clone())))?;
+//! bytes[800] = 1;
+//! let new = Texture::new(Format::BC1 { srgb: false }, TextureShapeNode::from_surface(Surface::new(dimensions, bytes)))?;
+//!
+//! let mut file = Cursor::new(Vec::new());
+//! file.write_le(&Texture::diff_patch(&old, &new)?)?;
+//! assert!(file.get_ref().len() < 64);
+//!
+//! file.set_position(0);
+//! let patch: TexturePatch = file.read_le()?;
+//! let patched = old.apply_patch(&patch)?;
+//! assert_eq!(patched.slice().try_into_surface().unwrap().buffer, new.slice().try_into_surface().unwrap().buffer);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::convert::Infallible;
+
+use binrw::binrw;
+
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::shape::TextureShape;
+use crate::texture::{Surface, Surfaces, Texture};
+use crate::util::fnv1a;
+
+/// The current version of the patch format
+pub const PATCH_VERSION: u16 = 1;
+
+/// Size in bytes of a [`BlockRun`] with no data. Unchanged gaps between changed blocks
+/// no larger than this are included in the surrounding run, since a new run would cost at least as much
+const RUN_OVERHEAD: usize = 8;
+
+/// A patch from one version of a texture to another with the same format and shape.
+/// See the [module documentation](crate::patch)
+#[binrw]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[brw(little, magic = b"QTXP")]
+pub struct TexturePatch {
+    /// Version of the patch format, currently always [`PATCH_VERSION`]
+    #[br(assert(version == PATCH_VERSION, "Unsupported patch version {}", version))]
+    pub version: u16,
+    /// Size of a block in bytes, used to check the patch is applied to a texture in a compatible format
+    pub block_size: u32,
+    #[br(temp)]
+    #[bw(calc = surfaces.len() as u32)]
+    count: u32,
+    /// Patches for each surface that changed. Unchanged surfaces are left out
+    #[br(count = count)]
+    pub surfaces: Vec<SurfacePatch>,
+}
+
+/// The changes to a single surface
+#[binrw]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[brw(little)]
+pub struct SurfacePatch {
+    /// Index of the surface, in the order surfaces are stored in the texture
+    pub index: u32,
+    /// Hash of the surface before patching
+    pub base_hash: u64,
+    /// Hash of the surface after patching
+    pub result_hash: u64,
+    #[br(temp)]
+    #[bw(calc = runs.len() as u32)]
+    count: u32,
+    /// Runs of changed blocks, in increasing order
+    #[br(count = count)]
+    pub runs: Vec<BlockRun>,
+}
+
+/// A run of consecutive blocks to overwrite
+#[binrw]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[brw(little)]
+pub struct BlockRun {
+    /// Index of the first block in the run
+    pub block: u32,
+    #[br(temp)]
+    #[bw(calc = data.len() as u32)]
+    len: u32,
+    /// The new contents of the blocks
+    #[br(count = len)]
+    pub data: Vec<u8>,
+}
+
+impl TexturePatch {
+    /// If the patch doesn't change anything
+    pub fn is_empty(&self) -> bool {
+        self.surfaces.is_empty()
+    }
+}
+
+/// The surfaces in a tree, in the order [`Surfaces::try_map`] visits them
+fn ordered(surfaces: &Surfaces) -> Vec<Surface> {
+    let mut ordered = Vec::new();
+    let _ = surfaces.try_map(|s| {
+        ordered.push(s.clone());
+        Ok::<_, Infallible>(s.clone())
+    });
+    ordered
+}
+
+/// Split two equally sized buffers into runs of differing `block_size` blocks
+fn diff_runs(old: &[u8], new: &[u8], block_size: usize) -> Vec<BlockRun> {
+    let mut runs: Vec<BlockRun> = Vec::new();
+    let changed = old
+        .chunks(block_size)
+        .zip(new.chunks(block_size))
+        .enumerate()
+        .filter(|(_, (o, n))| o != n)
+        .map(|(b, _)| b);
+    for block in changed {
+        let data = &new[block * block_size..(block + 1) * block_size];
+        match runs.last_mut() {
+            Some(run)
+                if (block - run.block as usize) * block_size - run.data.len() <= RUN_OVERHEAD =>
+            {
+                // extend the run over the gap, which is cheaper than starting a new one
+                let start = run.block as usize * block_size + run.data.len();
+                run.data.extend_from_slice(&new[start..block * block_size]);
+                run.data.extend_from_slice(data);
+            }
+            _ => runs.push(BlockRun {
+                block: block as u32,
+                data: data.to_vec(),
+            }),
+        }
+    }
+    runs
+}
+
+impl Texture {
+    /// Create a patch that turns `old` into `new`.
+    /// Returns an error if the two textures don't have the same format, shape, and dimensions
+    pub fn diff_patch(old: &Texture, new: &Texture) -> TextureResult<TexturePatch> {
+        if old.format != new.format {
+            return Err(TextureError::Format(format!(
+                "Cannot patch a {:?} texture into a {:?} texture",
+                old.format, new.format
+            )));
+        }
+        let (old_surfaces, new_surfaces) = (ordered(&old.surfaces), ordered(&new.surfaces));
+        let same_shape = old.layers() == new.layers()
+            && old.faces() == new.faces()
+            && old.mips() == new.mips()
+            && old_surfaces.len() == new_surfaces.len()
+            && old_surfaces
+                .iter()
+                .zip(&new_surfaces)
+                .all(|(o, n)| o.dimensions == n.dimensions);
+        if !same_shape {
+            return Err(TextureError::Other(
+                "Patches can only be made between textures with the same shape and dimensions"
+                    .to_string(),
+            ));
+        }
+        old.check_format()?;
+        new.check_format()?;
+
+        let block_size = block_size(old)?;
+        let surfaces = old_surfaces
+            .iter()
+            .zip(&new_surfaces)
+            .enumerate()
+            .filter(|(_, (o, n))| o.buffer != n.buffer)
+            .map(|(i, (o, n))| SurfacePatch {
+                index: i as u32,
+                base_hash: fnv1a(&o.buffer),
+                result_hash: fnv1a(&n.buffer),
+                runs: diff_runs(&o.buffer, &n.buffer, block_size),
+            })
+            .collect();
+        Ok(TexturePatch {
+            version: PATCH_VERSION,
+            block_size: block_size as u32,
+            surfaces,
+        })
+    }
+
+    /// Apply a patch made with [`Texture::diff_patch`], returning the patched texture.
+    /// Returns an error without modifying anything if the patch was not made against this texture
+    pub fn apply_patch(&self, patch: &TexturePatch) -> TextureResult<Texture> {
+        let mismatch = |reason: &str| {
+            TextureError::Other(format!("Patch does not apply to this texture: {reason}"))
+        };
+        let block_size = block_size(self)?;
+        if patch.block_size as usize != block_size {
+            return Err(mismatch("block size differs"));
+        }
+        let mut surfaces = ordered(&self.surfaces);
+        for surface_patch in &patch.surfaces {
+            let surface = surfaces
+                .get_mut(surface_patch.index as usize)
+                .ok_or_else(|| mismatch("surface index out of range"))?;
+            if fnv1a(&surface.buffer) != surface_patch.base_hash {
+                return Err(mismatch("surface contents differ"));
+            }
+            let mut buffer = surface.buffer.to_vec();
+            for run in &surface_patch.runs {
+                let start = run.block as usize * block_size;
+                buffer
+                    .get_mut(start..start + run.data.len())
+                    .ok_or_else(|| mismatch("block run out of range"))?
+                    .copy_from_slice(&run.data);
+            }
+            if fnv1a(&buffer) != surface_patch.result_hash {
+                return Err(mismatch("patched surface is corrupt"));
+            }
+            surface.buffer = buffer.into();
+        }
+
+        let mut patched = surfaces.into_iter();
+        let surfaces = self
+            .surfaces
+            .try_map(|_| Ok::<_, Infallible>(patched.next().unwrap()))
+            .unwrap_or_else(|e| match e {});
+        Texture::new(self.format, surfaces)
+    }
+}
+
+/// Size of a block of the texture's format in bytes
+fn block_size(texture: &Texture) -> TextureResult<usize> {
+    texture.format.size_for(Dimensions::try_from([1])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use itertools::Itertools;
+
+    use crate::format::Format;
+    use crate::shape::TextureShapeNode;
+
+    use super::*;
+
+    /// A BC1 texture with a full mip chain, with each mip's bytes set by `f(mip, byte index)`
+    fn texture(f: impl Fn(usize, usize) -> u8) -> Result<Texture> {
+        let format = Format::BC1 { srgb: false };
+        let mips = Dimensions::try_from([16, 16])?
+            .mips()
+            .enumerate()
+            .map(|(m, d)| {
+                let bytes = (0..format.size_for(d).unwrap())
+                    .map(|i| f(m, i))
+                    .collect_vec();
+                TextureShapeNode::from_surface(Surface::new(d, bytes))
+            });
+        Ok(Texture::new(format, Surfaces::try_from_mips(mips)?)?)
+    }
+
+    fn buffers(texture: &Texture) -> Vec<Vec<u8>> {
+        texture
+            .slice()
+            .iter()
+            .map(|s| s.surface.buffer.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        let old = texture(|_, i| i as u8)?;
+        // change block 1 and 3 of mip 0, and block 0 of mip 2
+        let new = texture(|m, i| match (m, i / 8) {
+            (0, 1) | (0, 3) | (2, 0) => 0xFF,
+            _ => i as u8,
+        })?;
+
+        let patch = Texture::diff_patch(&old, &new)?;
+        assert_eq!(patch.surfaces.len(), 2, "mips 1 and 3 are unchanged");
+        assert_eq!(
+            patch.surfaces[0].runs.len(),
+            1,
+            "the gap between changes is merged"
+        );
+        assert_eq!(patch.surfaces[0].runs[0].block, 1);
+        assert_eq!(patch.surfaces[0].runs[0].data.len(), 24);
+        assert_eq!(buffers(&old.apply_patch(&patch)?), buffers(&new));
+
+        assert!(Texture::diff_patch(&old, &old)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn separate_runs() {
+        let old = vec![0u8; 64];
+        let mut new = old.clone();
+        new[0] = 1;
+        new[40] = 1;
+        let runs = diff_runs(&old, &new, 8);
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[1].block, runs[1].data.len()), (5, 8));
+    }
+
+    #[test]
+    fn wrong_base() -> Result<()> {
+        let old = texture(|_, _| 0)?;
+        let new = texture(|_, _| 1)?;
+        let patch = Texture::diff_patch(&old, &new)?;
+        assert!(new.apply_patch(&patch).is_err());
+
+        let other_shape = Texture::new(old.format, old.surfaces().get_mip(0).unwrap())?;
+        assert!(Texture::diff_patch(&old, &other_shape).is_err());
+        Ok(())
+    }
+}
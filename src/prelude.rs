@@ -0,0 +1,28 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The traits and types needed by most code that uses this crate, glob imported with
+//! `use quicktex::prelude::*;`.
+//!
+//! Importing the traits brings their methods into scope, so calls like [`Texture::mips`](crate::shape::TextureShape::mips) and
+//! [`DDSHeader::read_texture`] work without naming where they come from.
+//! Items are only ever added to the prelude in a minor release, and never removed or renamed
+//! outside of a major release, so a glob import is not broken by upgrades.
+//!
+//! ```
+//! use quicktex::prelude::*;
+//!
+//! let texture = DDSHeader::read_texture(&mut std::fs::File::open("tests/images/dds/peppers16 bc1.dds")?)?;
+//! assert_eq!(texture.format, Format::BC1 { srgb: false });
+//! assert_eq!(texture.dimensions(), Dimensions::try_from([16, 16])?);
+//! assert_eq!(texture.mips(), Some(5));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub use crate::container::ContainerHeader;
+pub use crate::dds::DDSHeader;
+pub use crate::dimensions::{Dimensioned, Dimensions};
+pub use crate::format::Format;
+pub use crate::shape::TextureShape;
+pub use crate::texture::Texture;
@@ -0,0 +1,745 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The PowerVR (PVR) version 3 container used by PVRTexTool and many mobile engines.
+//!
+//! The header is 52 bytes of little-endian fields, followed by [`PVRMetadata`](crate::pvr::PVRMetadata) blocks and then the surfaces.
+//! Surfaces are stored mip by mip, and within each mip layer by layer, then face by face
+//! ([`SurfaceOrder::MipsLayersFaces`](crate::texture::SurfaceOrder::MipsLayersFaces)). The depth slices of a volume are stored together.
+//!
+//! The pixel format is a 64-bit value: either a compressed format ID in the low 32 bits,
+//! or the names of up to 4 channels as characters in the low 32 bits and their sizes in bits in the high 32 bits.
+//! Uncompressed channels that are all 8 bits are listed in memory order, and packed channels from the most significant bit.
+//! Uncompressed formats with 8-bit unsigned channels are always read as [`Format::Uncompressed`], even if they
+//! were written from [`Format::Channels`].
+//!
+//! PVR files can't say which faces of a cubemap are present, so only complete cubemaps are supported,
+//! and 1D surfaces are written as 2D surfaces one pixel tall
+
+use std::io::{Read, Seek, Write};
+
+use binrw::{binrw, BinRead, BinResult};
+use enumflags2::make_bitflags;
+use itertools::Itertools;
+use strum::VariantArray;
+
+use crate::astc::{FOOTPRINTS_2D, FOOTPRINTS_3D};
+use crate::capability::{ContainerCapabilities, FormatFamily, ShapeFeature};
+use crate::container::{packed_offsets, ContainerHeader};
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::format::{AlphaFormat, ChannelType, ColorFormat, Format};
+use crate::shape::{CubeFace, TextureShape};
+use crate::texture::{
+    SurfaceCounts, SurfaceOffset, SurfaceOrder, SurfaceReader, SurfaceWriter, Surfaces, Texture,
+};
+
+/// Flag set in [`PVRHeader::flags`] if color channels are premultiplied by alpha
+pub const FLAG_PREMULTIPLIED: u32 = 0x02;
+
+/// A metadata block from a PVR file. Blocks are identified by a FourCC and a key,
+/// and PVRTexTool's own blocks use the FourCC `PVR\x03`
+#[binrw]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[brw(little)]
+pub struct PVRMetadata {
+    /// The FourCC of whoever defined the block
+    pub four_cc: [u8; 4],
+    /// Identifies the block among those with the same FourCC
+    pub key: u32,
+    #[br(temp)]
+    #[bw(calc = data.len() as u32)]
+    size: u32,
+    /// The contents of the block
+    #[br(count = size)]
+    pub data: Vec<u8>,
+}
+
+impl PVRMetadata {
+    /// Size of the block in the file, including its FourCC, key, and size fields
+    pub fn size(&self) -> usize {
+        12 + self.data.len()
+    }
+}
+
+/// Read metadata blocks filling exactly `size` bytes
+#[binrw::parser(reader, endian)]
+fn read_metadata(size: u32) -> BinResult<Vec<PVRMetadata>> {
+    let start = reader.stream_position()?;
+    let end = start + size as u64;
+    let mut metadata = Vec::new();
+    while reader.stream_position()? < end {
+        metadata.push(PVRMetadata::read_options(reader, endian, ())?);
+    }
+    match reader.stream_position()? {
+        pos if pos == end => Ok(metadata),
+        pos => Err(binrw::Error::AssertFail {
+            pos,
+            message: format!("Metadata blocks overrun the metadata size of {size} bytes"),
+        }),
+    }
+}
+
+/// The header of a PVR version 3 file, including its metadata. See the [module documentation](self) for the layout
+#[binrw]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[brw(little, magic = b"PVR\x03")]
+pub struct PVRHeader {
+    /// Flags such as [`FLAG_PREMULTIPLIED`]
+    pub flags: u32,
+    /// The pixel format, as a compressed format ID or a list of channel names and sizes
+    pub pixel_format: u64,
+    /// 0 for linear, or 1 for sRGB
+    pub color_space: u32,
+    /// The type of each channel, such as 0 for unsigned normalized bytes or 12 for floats
+    pub channel_type: u32,
+    /// Height of the largest surface in pixels
+    pub height: u32,
+    /// Width of the largest surface in pixels
+    pub width: u32,
+    /// Depth of the largest surface in pixels, 1 for 2D surfaces
+    pub depth: u32,
+    /// Number of array layers, 1 if the texture is not an array
+    pub surfaces: u32,
+    /// Number of cubemap faces, 1 if the texture is not a cubemap
+    pub faces: u32,
+    /// Number of mips, 1 if the texture has no mipmaps
+    pub mips: u32,
+    #[br(temp)]
+    #[bw(calc = metadata.iter().map(PVRMetadata::size).sum::<usize>() as u32)]
+    metadata_size: u32,
+    /// Metadata blocks, which are kept when reading but do not affect the texture
+    #[br(parse_with = read_metadata, args(metadata_size))]
+    pub metadata: Vec<PVRMetadata>,
+}
+
+/// Arguments for writing a PVR file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PVRArgs {
+    /// Metadata blocks to write after the header
+    pub metadata: Vec<PVRMetadata>,
+}
+
+/// Channel types in [`PVRHeader::channel_type`]
+mod channel_type {
+    pub const UNORM8: u32 = 0;
+    pub const SNORM8: u32 = 1;
+    pub const UNORM16: u32 = 4;
+    pub const SNORM16: u32 = 5;
+    pub const UNORM32: u32 = 8;
+    pub const FLOAT: u32 = 12;
+}
+
+/// The first compressed format ID for a 2D ASTC footprint, in the order of [`FOOTPRINTS_2D`]
+const ASTC_2D: u64 = 27;
+/// The first compressed format ID for a 3D ASTC footprint, in the order of [`FOOTPRINTS_3D`]
+const ASTC_3D: u64 = 41;
+
+/// The name and mask of each uncompressed channel in a format, or [`None`] if PVR can't name its channels
+fn named_masks(color_format: ColorFormat, alpha_format: AlphaFormat) -> Option<Vec<(u8, u32)>> {
+    let mut channels = match color_format {
+        ColorFormat::RGB {
+            r_mask,
+            g_mask,
+            b_mask,
+            ..
+        } => vec![(b'r', r_mask), (b'g', g_mask), (b'b', b_mask)],
+        ColorFormat::L { l_mask } => vec![(b'l', l_mask)],
+        ColorFormat::None => vec![],
+        ColorFormat::YUV { .. } => return None,
+    };
+    match alpha_format {
+        AlphaFormat::Opaque => {}
+        AlphaFormat::Straight { alpha_mask }
+        | AlphaFormat::Premultiplied { alpha_mask }
+        | AlphaFormat::Custom { alpha_mask } => channels.push((b'a', alpha_mask)),
+    }
+    channels.retain(|&(_, mask)| mask != 0);
+    Some(channels)
+}
+
+/// Pack channel names and sizes into a pixel format
+fn pack_channels(channels: &[(u8, u32)]) -> u64 {
+    let (mut names, mut bits) = ([0u8; 4], [0u8; 4]);
+    for (i, &(name, size)) in channels.iter().enumerate() {
+        names[i] = name;
+        bits[i] = size as u8;
+    }
+    u32::from_le_bytes(names) as u64 | (u32::from_le_bytes(bits) as u64) << 32
+}
+
+/// Unpack the channel names and sizes of an uncompressed pixel format
+fn unpack_channels(pixel_format: u64) -> Vec<(u8, u32)> {
+    let names = (pixel_format as u32).to_le_bytes();
+    let bits = ((pixel_format >> 32) as u32).to_le_bytes();
+    (names.into_iter().zip(bits))
+        .take_while(|&(name, _)| name != 0)
+        .map(|(name, size)| (name, size as u32))
+        .collect()
+}
+
+impl PVRHeader {
+    /// The pixel format, channel type, and color space to store `format` with
+    fn pixel_format_for(format: Format) -> TextureResult<(u64, u32, u32)> {
+        let unsupported =
+            || TextureError::Format(format!("{format:?} cannot be stored in a PVR file"));
+        let srgb = format.srgb() as u32;
+        let signed = |signed: bool| match signed {
+            true => channel_type::SNORM8,
+            false => channel_type::UNORM8,
+        };
+        let (id, channel_type) = match format {
+            Format::BC1 { .. } => (7, channel_type::UNORM8),
+            Format::BC2 { .. } => (9, channel_type::UNORM8),
+            Format::BC3 { .. } => (11, channel_type::UNORM8),
+            Format::BC4 { signed: s } => (12, signed(s)),
+            Format::BC5 { signed: s } => (13, signed(s)),
+            Format::BC7 { .. } => (15, channel_type::UNORM8),
+            Format::ETC2RGB8 { .. } => (22, channel_type::UNORM8),
+            Format::ETC2RGBA8 { .. } => (23, channel_type::UNORM8),
+            Format::EACR11 { signed: s } => (25, signed(s)),
+            Format::EACRG11 { signed: s } => (26, signed(s)),
+            Format::ASTC { block_dims, .. } => {
+                let axes = block_dims.into_iter().map(|a| a as u8).collect::<Vec<_>>();
+                let id = match block_dims {
+                    Dimensions::_3D(_) => (FOOTPRINTS_3D.iter())
+                        .position(|f| f[..] == axes[..])
+                        .map(|i| ASTC_3D + i as u64),
+                    _ => (FOOTPRINTS_2D.iter())
+                        .position(|f| f[..] == axes[..])
+                        .map(|i| ASTC_2D + i as u64),
+                };
+                (id.ok_or_else(unsupported)?, channel_type::UNORM8)
+            }
+            Format::Channels {
+                channel_type: ty,
+                channels,
+            } => {
+                let bits = ty.size() as u32 * 8;
+                let names = b"rgba".get(..channels).ok_or_else(unsupported)?;
+                let channels = names.iter().map(|&n| (n, bits)).collect::<Vec<_>>();
+                let pvr_type = match ty {
+                    ChannelType::UNorm8 => channel_type::UNORM8,
+                    ChannelType::SNorm8 => channel_type::SNORM8,
+                    ChannelType::UNorm16 => channel_type::UNORM16,
+                    ChannelType::SNorm16 => channel_type::SNORM16,
+                    ChannelType::Float16 | ChannelType::Float32 => channel_type::FLOAT,
+                };
+                return Ok((pack_channels(&channels), pvr_type, 0));
+            }
+            Format::Uncompressed {
+                pitch,
+                color_format,
+                alpha_format,
+            } => {
+                let mut masks = named_masks(color_format, alpha_format).ok_or_else(unsupported)?;
+                masks.sort_by_key(|&(_, m)| m.trailing_zeros());
+
+                // channels must be contiguous and fill the pixel exactly
+                let mut expected = 0u32;
+                for &(_, mask) in &masks {
+                    let shifted = mask >> mask.trailing_zeros();
+                    if mask.trailing_zeros() != expected || shifted & (shifted + 1) != 0 {
+                        return Err(unsupported());
+                    }
+                    expected += mask.count_ones();
+                }
+                if expected as usize != pitch * 8 {
+                    return Err(unsupported());
+                }
+
+                // byte channels are listed in memory order, packed channels from the most significant bit
+                let bytes = masks.iter().all(|&(_, m)| m.count_ones() == 8);
+                if !bytes {
+                    masks.reverse();
+                }
+                let channels = (masks.iter())
+                    .map(|&(name, mask)| (name, mask.count_ones()))
+                    .collect::<Vec<_>>();
+                let pvr_type = match (bytes, pitch) {
+                    (false, 2) => channel_type::UNORM16,
+                    (false, 4) => channel_type::UNORM32,
+                    _ => channel_type::UNORM8,
+                };
+                return Ok((pack_channels(&channels), pvr_type, srgb));
+            }
+            Format::Unknown { .. } => return Err(unsupported()),
+        };
+        Ok((id, channel_type, srgb))
+    }
+
+    /// The format of an uncompressed pixel format with the given channels
+    fn uncompressed_format(&self, channels: &[(u8, u32)]) -> TextureResult<Format> {
+        let invalid = || {
+            TextureError::Format(format!(
+                "Unsupported PVR pixel format {:#018x} with channel type {}",
+                self.pixel_format, self.channel_type
+            ))
+        };
+        let total: u32 = channels.iter().map(|&(_, size)| size).sum();
+        let uniform = channels.iter().map(|&(_, size)| size).all_equal();
+        let rgba = channels
+            .iter()
+            .map(|&(n, _)| n)
+            .eq(b"rgba".iter().copied().take(channels.len()));
+
+        // typed channels, which uncompressed masks can't describe
+        let typed = match (self.channel_type, channels.first().map(|&(_, size)| size)) {
+            (channel_type::SNORM8, Some(8)) => Some(ChannelType::SNorm8),
+            (channel_type::UNORM16, Some(16)) => Some(ChannelType::UNorm16),
+            (channel_type::SNORM16, Some(16)) => Some(ChannelType::SNorm16),
+            (channel_type::FLOAT, Some(16)) => Some(ChannelType::Float16),
+            (channel_type::FLOAT, Some(32)) => Some(ChannelType::Float32),
+            _ => None,
+        };
+        if let Some(channel_type) = typed {
+            return match uniform && rgba {
+                true => Ok(Format::Channels {
+                    channel_type,
+                    channels: channels.len(),
+                }),
+                false => Err(invalid()),
+            };
+        }
+        if ![
+            channel_type::UNORM8,
+            channel_type::UNORM16,
+            channel_type::UNORM32,
+        ]
+        .contains(&self.channel_type)
+            || total == 0
+            || total > 32
+            || !total.is_multiple_of(8)
+        {
+            return Err(invalid());
+        }
+
+        let bytes = channels.iter().all(|&(_, size)| size == 8);
+        let mut shift = match bytes {
+            true => 0,
+            false => total,
+        };
+        let (mut rgb, mut l_mask, mut alpha_mask) = ([0u32; 3], 0, 0);
+        for &(name, size) in channels {
+            if !bytes {
+                shift -= size;
+            }
+            let mask = ((1u64 << size) - 1) as u32;
+            let mask = mask << shift;
+            let slot = match name {
+                b'r' => &mut rgb[0],
+                b'g' => &mut rgb[1],
+                b'b' => &mut rgb[2],
+                b'l' => &mut l_mask,
+                b'a' => &mut alpha_mask,
+                _ => return Err(invalid()),
+            };
+            if *slot != 0 {
+                return Err(invalid());
+            }
+            *slot = mask;
+            if bytes {
+                shift += size;
+            }
+        }
+
+        let color_format = match (rgb, l_mask) {
+            ([0, 0, 0], 0) => ColorFormat::None,
+            ([0, 0, 0], l_mask) => ColorFormat::L { l_mask },
+            ([r_mask, g_mask, b_mask], 0) => ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                srgb: self.color_space == 1,
+            },
+            _ => return Err(invalid()),
+        };
+        let alpha_format = match (alpha_mask, self.flags & FLAG_PREMULTIPLIED != 0) {
+            (0, _) => AlphaFormat::Opaque,
+            (alpha_mask, false) => AlphaFormat::Straight { alpha_mask },
+            (alpha_mask, true) => AlphaFormat::Premultiplied { alpha_mask },
+        };
+        Ok(Format::Uncompressed {
+            pitch: total as usize / 8,
+            color_format,
+            alpha_format,
+        })
+    }
+}
+
+impl ContainerHeader for PVRHeader {
+    type Args = PVRArgs;
+
+    const CAPABILITIES: ContainerCapabilities = ContainerCapabilities {
+        formats: make_bitflags!(FormatFamily::{BC1 | BC2 | BC3 | BC4 | BC5 | BC7 | ETC2RGB8 | ETC2RGBA8 | EACR11 | EACRG11 | ASTC | Uncompressed | Channels}),
+        shapes: make_bitflags!(ShapeFeature::{Mips | Arrays | Cubemaps | Lines | Volumes}),
+    };
+
+    fn read_surfaces<R: Read + Seek>(&self, reader: &mut R) -> TextureResult<Surfaces> {
+        let mut surface_reader = SurfaceReader {
+            format: self.format()?,
+            reader,
+        };
+        let counts = SurfaceCounts {
+            dimensions: self.dimensions()?,
+            layers: self.layers()?,
+            faces: self.faces()?,
+            mips: self.mips()?,
+        };
+        surface_reader.read_shaped(SurfaceOrder::MipsLayersFaces, &counts)
+    }
+
+    fn write_surfaces<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        surfaces: &Surfaces,
+    ) -> TextureResult<()> {
+        SurfaceWriter::new(writer, 0).write_shaped(SurfaceOrder::MipsLayersFaces, surfaces)
+    }
+
+    fn surface_offsets(&self) -> TextureResult<Box<dyn Iterator<Item = SurfaceOffset>>> {
+        packed_offsets(self, SurfaceOrder::MipsLayersFaces)
+    }
+
+    fn from_texture_args(texture: &Texture, args: &PVRArgs) -> TextureResult<Self> {
+        let faces = match texture.faces() {
+            None => 1,
+            Some(faces) if faces.len() == CubeFace::VARIANTS.len() => faces.len() as u32,
+            Some(_) => {
+                return Err(TextureError::Capability(
+                    "PVR files cannot contain incomplete cubemaps".to_string(),
+                ))
+            }
+        };
+        let (pixel_format, channel_type, color_space) = Self::pixel_format_for(texture.format)?;
+        let premultiplied = matches!(
+            texture.format,
+            Format::Uncompressed {
+                alpha_format: AlphaFormat::Premultiplied { .. },
+                ..
+            }
+        );
+        let dimensions = texture.try_dimensions()?;
+        Ok(Self {
+            flags: if premultiplied { FLAG_PREMULTIPLIED } else { 0 },
+            pixel_format,
+            color_space,
+            channel_type,
+            height: dimensions.height(),
+            width: dimensions.width(),
+            depth: dimensions.depth(),
+            surfaces: texture.layers().unwrap_or(1) as u32,
+            faces,
+            mips: texture.mips().unwrap_or(1) as u32,
+            metadata: args.metadata.clone(),
+        })
+    }
+
+    fn dimensions(&self) -> TextureResult<Dimensions> {
+        Ok(match self.depth {
+            1 => Dimensions::try_from([self.width, self.height])?,
+            depth => Dimensions::try_from([self.width, self.height, depth])?,
+        })
+    }
+
+    fn layers(&self) -> TextureResult<Option<usize>> {
+        match self.surfaces {
+            0 => Err(TextureError::Format("PVR file has 0 surfaces".to_string())),
+            1 => Ok(None),
+            surfaces => Ok(Some(surfaces as usize)),
+        }
+    }
+
+    fn faces(&self) -> TextureResult<Option<Vec<CubeFace>>> {
+        match self.faces {
+            1 => Ok(None),
+            6 => Ok(Some(CubeFace::VARIANTS.to_vec())),
+            faces => Err(TextureError::Capability(format!(
+                "PVR files with {faces} faces are not supported"
+            ))),
+        }
+    }
+
+    fn mips(&self) -> TextureResult<Option<usize>> {
+        match self.mips {
+            0 => Err(TextureError::Format("PVR file has 0 mips".to_string())),
+            1 => Ok(None),
+            mips => Ok(Some(mips as usize)),
+        }
+    }
+
+    fn format(&self) -> TextureResult<Format> {
+        if self.pixel_format >> 32 != 0 {
+            return self.uncompressed_format(&unpack_channels(self.pixel_format));
+        }
+        let srgb = self.color_space == 1;
+        let signed = self.channel_type == channel_type::SNORM8;
+        match self.pixel_format {
+            7 => Ok(Format::BC1 { srgb }),
+            9 => Ok(Format::BC2 { srgb }),
+            11 => Ok(Format::BC3 { srgb }),
+            12 => Ok(Format::BC4 { signed }),
+            13 => Ok(Format::BC5 { signed }),
+            15 => Ok(Format::BC7 { srgb }),
+            22 => Ok(Format::ETC2RGB8 { srgb }),
+            23 => Ok(Format::ETC2RGBA8 { srgb }),
+            25 => Ok(Format::EACR11 { signed }),
+            26 => Ok(Format::EACRG11 { signed }),
+            id @ ASTC_2D..ASTC_3D => {
+                let [w, h] = FOOTPRINTS_2D[(id - ASTC_2D) as usize].map(u32::from);
+                Ok(Format::ASTC {
+                    block_dims: Dimensions::try_from([w, h])?,
+                    srgb,
+                })
+            }
+            id if (ASTC_3D..ASTC_3D + FOOTPRINTS_3D.len() as u64).contains(&id) => {
+                let [w, h, d] = FOOTPRINTS_3D[(id - ASTC_3D) as usize].map(u32::from);
+                Ok(Format::ASTC {
+                    block_dims: Dimensions::try_from([w, h, d])?,
+                    srgb,
+                })
+            }
+            id => Err(TextureError::Format(format!(
+                "Unsupported PVR compressed format {id}"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+
+    use crate::dimensions::Dimensioned;
+    use crate::shape::TextureShapeNode;
+    use crate::texture::Surface;
+
+    use super::*;
+
+    /// A texture whose surfaces are each filled with a tag byte of `layer * 32 + face * 4 + mip`.
+    /// The texture is only an array if `layers` is more than 1, and `mips` gives it a full mipchain
+    fn tagged(format: Format, layers: usize, cube: bool, mips: bool) -> Result<Texture> {
+        let dimensions = Dimensions::try_from([8, 8])?;
+        let surface = |d: Dimensions, tag: u8| {
+            TextureShapeNode::from_surface(Surface::new(d, vec![tag; format.size_for(d).unwrap()]))
+        };
+        let mip_chain = |tag: u8| -> Result<Surfaces> {
+            match mips {
+                true => Ok(Surfaces::try_from_mips(
+                    (dimensions.mips().enumerate()).map(|(m, d)| surface(d, tag + m as u8)),
+                )?),
+                false => Ok(surface(dimensions, tag)),
+            }
+        };
+        let face = |tag: u8| -> Result<Surfaces> {
+            match cube {
+                true => Ok(Surfaces::try_from_faces(
+                    (CubeFace::VARIANTS.iter().enumerate())
+                        .map(|(f, &face)| Ok((face, mip_chain(tag + f as u8 * 4)?)))
+                        .collect::<Result<Vec<_>>>()?,
+                )?),
+                false => mip_chain(tag),
+            }
+        };
+        let surfaces = match layers {
+            1 => face(0)?,
+            _ => Surfaces::try_from_layers(
+                (0..layers)
+                    .map(|l| face(l as u8 * 32))
+                    .collect::<Result<Vec<_>>>()?,
+            )?,
+        };
+        Ok(Texture::new(format, surfaces)?)
+    }
+
+    fn roundtrip(texture: &Texture, args: &PVRArgs) -> Result<(Vec<u8>, Texture)> {
+        let mut file = Cursor::new(Vec::new());
+        PVRHeader::write_texture_args(&mut file, texture, args)?;
+        file.set_position(0);
+        let read = PVRHeader::read_texture(&mut file)?;
+        Ok((file.into_inner(), read))
+    }
+
+    #[test]
+    fn layout() -> Result<()> {
+        let texture = tagged(Format::BC1 { srgb: true }, 2, true, true)?;
+        let (bytes, read) = roundtrip(&texture, &Default::default())?;
+        assert_eq!(bytes[..4], *b"PVR\x03");
+        assert_eq!(bytes[8], 7, "BC1 format ID");
+        assert_eq!(bytes[16], 1, "sRGB color space");
+        assert_eq!(read.format, texture.format);
+        assert_eq!((read.layers(), read.mips()), (Some(2), Some(4)));
+        assert_eq!(read.faces().map(|f| f.len()), Some(6));
+
+        // every face of every layer of mip 0 comes first, then mip 1
+        let (mip0, header) = (8 * 4 * 2 * 6, 52);
+        assert_eq!(bytes[header], 0);
+        assert_eq!(bytes[header + 32], 4, "face 1 of layer 0");
+        assert_eq!(bytes[header + 32 * 6], 32, "face 0 of layer 1");
+        assert_eq!(bytes[header + mip0], 1, "mip 1 of face 0 of layer 0");
+        for (a, b) in read.slice().iter().zip(texture.slice().iter()) {
+            assert_eq!((a.layer, a.face, a.mip), (b.layer, b.face, b.mip));
+            assert_eq!(a.surface.buffer, b.surface.buffer);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn formats() -> Result<()> {
+        let rgb = |pitch, [r_mask, g_mask, b_mask]: [u32; 3], alpha_format| Format::Uncompressed {
+            pitch,
+            color_format: ColorFormat::RGB {
+                r_mask,
+                g_mask,
+                b_mask,
+                srgb: false,
+            },
+            alpha_format,
+        };
+        let formats = [
+            Format::rgba8(true),
+            rgb(
+                4,
+                [0xFF0000, 0xFF00, 0xFF],
+                AlphaFormat::Premultiplied {
+                    alpha_mask: 0xFF000000,
+                },
+            ),
+            rgb(2, [0xF800, 0x7E0, 0x1F], AlphaFormat::Opaque),
+            rgb(
+                2,
+                [0xF00, 0xF0, 0xF],
+                AlphaFormat::Straight { alpha_mask: 0xF000 },
+            ),
+            Format::Uncompressed {
+                pitch: 1,
+                color_format: ColorFormat::L { l_mask: 0xFF },
+                alpha_format: AlphaFormat::Opaque,
+            },
+            Format::Channels {
+                channel_type: ChannelType::Float16,
+                channels: 4,
+            },
+            Format::Channels {
+                channel_type: ChannelType::SNorm16,
+                channels: 2,
+            },
+            Format::BC4 { signed: true },
+            Format::BC7 { srgb: false },
+            Format::EACRG11 { signed: true },
+            Format::ETC2RGBA8 { srgb: true },
+            Format::ASTC {
+                block_dims: Dimensions::try_from([10, 8])?,
+                srgb: true,
+            },
+        ];
+        for format in formats {
+            let (_, read) = roundtrip(&tagged(format, 1, false, false)?, &Default::default())?;
+            assert_eq!(read.format, format);
+        }
+
+        let (bytes, _) = roundtrip(&tagged(formats[2], 1, false, false)?, &Default::default())?;
+        assert_eq!(bytes[8..16], *b"rgb\0\x05\x06\x05\0");
+        let (bytes, _) = roundtrip(&tagged(formats[1], 1, false, false)?, &Default::default())?;
+        assert_eq!(bytes[8..16], *b"bgra\x08\x08\x08\x08");
+        assert_eq!(bytes[4], FLAG_PREMULTIPLIED as u8);
+
+        let volume = Format::ASTC {
+            block_dims: Dimensions::try_from([4, 4, 4])?,
+            srgb: false,
+        };
+        let dimensions = Dimensions::try_from([8, 8, 4])?;
+        let surface = Surface::new(dimensions, vec![9; volume.size_for(dimensions)?]);
+        let texture = Texture::new(volume, TextureShapeNode::from_surface(surface))?;
+        let (bytes, read) = roundtrip(&texture, &Default::default())?;
+        assert_eq!(bytes[8], 44, "ASTC 4x4x4 format ID");
+        assert_eq!((read.format, read.dimensions()), (volume, dimensions));
+        Ok(())
+    }
+
+    #[test]
+    fn metadata() -> Result<()> {
+        let args = PVRArgs {
+            metadata: vec![
+                PVRMetadata {
+                    four_cc: *b"PVR\x03",
+                    key: 3,
+                    data: vec![0, 0, 0],
+                },
+                PVRMetadata {
+                    four_cc: *b"QTEX",
+                    key: 7,
+                    data: b"hello".to_vec(),
+                },
+            ],
+        };
+        let texture = tagged(Format::BC3 { srgb: false }, 1, false, true)?;
+        let (bytes, read) = roundtrip(&texture, &args)?;
+        assert_eq!(bytes[48..52], (12 + 3 + 12 + 5u32).to_le_bytes());
+        assert_eq!(read.mips(), Some(4));
+
+        let header = PVRHeader::read_header(&mut Cursor::new(&bytes))?;
+        assert_eq!(header.metadata, args.metadata);
+
+        // a block that runs past the metadata size is an error
+        let mut short = bytes.clone();
+        short[48] -= 1;
+        assert!(PVRHeader::read_texture(&mut Cursor::new(short)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        let dimensions = Dimensions::try_from([4, 4])?;
+        let surface = || TextureShapeNode::from_surface(Surface::new(dimensions, vec![0; 8]));
+        let partial = Texture::new(
+            Format::BC1 { srgb: false },
+            Surfaces::try_from_faces([
+                (CubeFace::PositiveX, surface()),
+                (CubeFace::NegativeZ, surface()),
+            ])?,
+        )?;
+        let mut file = Cursor::new(Vec::new());
+        assert!(matches!(
+            PVRHeader::write_texture(&mut file, &partial),
+            Err(TextureError::Capability(_))
+        ));
+
+        let yuv = Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::YUV {
+                y_mask: 0xFF,
+                u_mask: 0xFF00,
+                v_mask: 0xFF0000,
+            },
+            alpha_format: AlphaFormat::Opaque,
+        };
+        let gap = Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0xFF0000,
+                g_mask: 0xFF00,
+                b_mask: 0xFF,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Opaque,
+        };
+        for format in [yuv, gap] {
+            assert!(PVRHeader::pixel_format_for(format).is_err(), "{format:?}");
+        }
+
+        let (mut bytes, _) = roundtrip(
+            &tagged(Format::BC1 { srgb: false }, 1, false, false)?,
+            &Default::default(),
+        )?;
+        bytes[8] = 3; // PVRTC 4bpp
+        assert!(PVRHeader::read_texture(&mut Cursor::new(&bytes)).is_err());
+        bytes[8] = 7;
+        bytes[40] = 3; // 3 faces
+        assert!(PVRHeader::read_texture(&mut Cursor::new(&bytes)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{Read, Seek, Write};
+
+use binrw::binrw;
+use enumflags2::make_bitflags;
+
+use crate::capability::{ContainerCapabilities, FormatFamily};
+use crate::container::ContainerHeader;
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::format::{AlphaFormat, ColorFormat, Format};
+use crate::shape::{CubeFace, TextureShapeNode};
+use crate::texture::{Surface, Surfaces, Texture};
+
+const OP_INDEX: u8 = 0b0000_0000;
+const OP_DIFF: u8 = 0b0100_0000;
+const OP_LUMA: u8 = 0b1000_0000;
+const OP_RUN: u8 = 0b1100_0000;
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+const OP_MASK: u8 = 0b1100_0000;
+
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// The header of a QOI file. QOI files hold a single 2D surface in RGB8 or RGBA8,
+/// losslessly compressed in a way that is cheap to encode and decode
+#[binrw]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[brw(big, magic = b"qoif")]
+pub struct QOIHeader {
+    /// Width of the image in pixels
+    pub width: u32,
+    /// Height of the image in pixels
+    pub height: u32,
+    /// Number of channels, either 3 for RGB or 4 for RGBA
+    pub channels: u8,
+    /// 0 if the color channels are sRGB encoded, 1 if every channel is linear
+    pub colorspace: u8,
+}
+
+impl QOIHeader {
+    fn pixel_format(channels: u8, srgb: bool) -> Option<Format> {
+        let color_format = ColorFormat::RGB {
+            r_mask: 0x0000FF,
+            g_mask: 0x00FF00,
+            b_mask: 0xFF0000,
+            srgb,
+        };
+        match channels {
+            3 => Some(Format::Uncompressed {
+                pitch: 3,
+                color_format,
+                alpha_format: AlphaFormat::Opaque,
+            }),
+            4 => Some(Format::Uncompressed {
+                pitch: 4,
+                color_format,
+                alpha_format: AlphaFormat::Straight {
+                    alpha_mask: 0xFF000000,
+                },
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl ContainerHeader for QOIHeader {
+    type Args = ();
+
+    const CAPABILITIES: ContainerCapabilities =
+        ContainerCapabilities::single_surface(make_bitflags!(FormatFamily::{Uncompressed}));
+
+    fn read_surfaces<R: Read + Seek>(&self, reader: &mut R) -> TextureResult<Surfaces> {
+        let dimensions = self.dimensions()?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let buffer = decode(&data, dimensions.try_product()?, self.channels as usize)?;
+        Ok(TextureShapeNode::from_surface(Surface::new(
+            dimensions, buffer,
+        )))
+    }
+
+    fn write_surfaces<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        surfaces: &Surfaces,
+    ) -> TextureResult<()> {
+        let TextureShapeNode::Surface(surface) = surfaces else {
+            return Err(TextureError::Capability(
+                "QOI files can only contain a single surface".to_string(),
+            ));
+        };
+        writer.write_all(&encode(&surface.buffer, self.channels as usize))?;
+        Ok(())
+    }
+
+    fn from_texture_args(texture: &Texture, _args: &()) -> TextureResult<Self> {
+        let TextureShapeNode::Surface(surface) = &texture.surfaces else {
+            return Err(TextureError::Capability(
+                "QOI files cannot contain mipmaps, cubemaps, or arrays".to_string(),
+            ));
+        };
+        let [width, height] = match surface.dimensions {
+            Dimensions::_2D(d) => d.map(u32::from),
+            d => {
+                return Err(TextureError::Capability(format!(
+                    "QOI files can only contain 2D surfaces, not {d:?}"
+                )))
+            }
+        };
+
+        let srgb = match texture.format {
+            Format::Uncompressed {
+                color_format: ColorFormat::RGB { srgb, .. },
+                ..
+            } => srgb,
+            _ => false,
+        };
+        let channels = [3, 4]
+            .into_iter()
+            .find(|&c| QOIHeader::pixel_format(c, srgb) == Some(texture.format))
+            .ok_or_else(|| {
+                TextureError::Format(format!(
+                    "QOI files can only contain RGB8 or RGBA8 surfaces, not {:?}",
+                    texture.format
+                ))
+            })?;
+
+        Ok(Self {
+            width,
+            height,
+            channels,
+            colorspace: if srgb { 0 } else { 1 },
+        })
+    }
+
+    fn dimensions(&self) -> TextureResult<Dimensions> {
+        Ok(Dimensions::try_from([self.width, self.height])?)
+    }
+
+    fn layers(&self) -> TextureResult<Option<usize>> {
+        Ok(None)
+    }
+
+    fn faces(&self) -> TextureResult<Option<Vec<CubeFace>>> {
+        Ok(None)
+    }
+
+    fn mips(&self) -> TextureResult<Option<usize>> {
+        Ok(None)
+    }
+
+    fn format(&self) -> TextureResult<Format> {
+        QOIHeader::pixel_format(self.channels, self.colorspace == 0).ok_or_else(|| {
+            TextureError::Format(format!(
+                "QOI files must have 3 or 4 channels, not {}",
+                self.channels
+            ))
+        })
+    }
+}
+
+fn hash([r, g, b, a]: [u8; 4]) -> usize {
+    (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64
+}
+
+/// Decode `pixels` pixels from a QOI data stream into a buffer with `channels` bytes per pixel
+fn decode(data: &[u8], pixels: usize, channels: usize) -> TextureResult<Vec<u8>> {
+    let truncated = || TextureError::Other("QOI data ends before the last pixel".to_string());
+
+    // every byte of data decodes to at most 62 pixels, so don't trust `pixels` for the allocation
+    let mut buffer = Vec::with_capacity(pixels.min(data.len() * 62) * channels);
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = [0, 0, 0, 255u8];
+    let mut bytes = data.iter().copied();
+    let mut next = || bytes.next().ok_or_else(truncated);
+
+    let mut decoded = 0;
+    while decoded < pixels {
+        let op = next()?;
+        let mut run = 1;
+        match op {
+            OP_RGB => {
+                pixel = [next()?, next()?, next()?, pixel[3]];
+            }
+            OP_RGBA => {
+                pixel = [next()?, next()?, next()?, next()?];
+            }
+            _ => match op & OP_MASK {
+                OP_INDEX => pixel = index[op as usize],
+                OP_DIFF => {
+                    pixel[0] = pixel[0].wrapping_add((op >> 4) & 0b11).wrapping_sub(2);
+                    pixel[1] = pixel[1].wrapping_add((op >> 2) & 0b11).wrapping_sub(2);
+                    pixel[2] = pixel[2].wrapping_add(op & 0b11).wrapping_sub(2);
+                }
+                OP_LUMA => {
+                    let dg = (op & 0b11_1111).wrapping_sub(32);
+                    let rb = next()?;
+                    pixel[0] = pixel[0].wrapping_add(dg.wrapping_add(rb >> 4).wrapping_sub(8));
+                    pixel[1] = pixel[1].wrapping_add(dg);
+                    pixel[2] = pixel[2].wrapping_add(dg.wrapping_add(rb & 0b1111).wrapping_sub(8));
+                }
+                _ => run = (op & 0b11_1111) as usize + 1,
+            },
+        }
+
+        index[hash(pixel)] = pixel;
+        for _ in 0..run.min(pixels - decoded) {
+            buffer.extend_from_slice(&pixel[..channels]);
+        }
+        decoded += run;
+    }
+    Ok(buffer)
+}
+
+/// Encode a buffer with `channels` bytes per pixel into a QOI data stream, including the end marker
+fn encode(buffer: &[u8], channels: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut index = [[0u8; 4]; 64];
+    let mut prev = [0, 0, 0, 255u8];
+    let mut run = 0u8;
+
+    for chunk in buffer.chunks_exact(channels) {
+        let mut pixel = [0, 0, 0, 255u8];
+        pixel[..channels].copy_from_slice(chunk);
+
+        if pixel == prev {
+            run += 1;
+            if run == 62 {
+                data.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            data.push(OP_RUN | (run - 1));
+            run = 0;
+        }
+
+        let hash = hash(pixel);
+        if index[hash] == pixel {
+            data.push(OP_INDEX | hash as u8);
+        } else {
+            index[hash] = pixel;
+            if pixel[3] == prev[3] {
+                let [dr, dg, db] = [0, 1, 2].map(|i| pixel[i].wrapping_sub(prev[i]) as i8);
+                let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+                if [dr, dg, db].iter().all(|d| (-2..=1).contains(d)) {
+                    data.push(
+                        OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8,
+                    );
+                } else if (-32..=31).contains(&dg)
+                    && (-8..=7).contains(&dr_dg)
+                    && (-8..=7).contains(&db_dg)
+                {
+                    data.push(OP_LUMA | (dg + 32) as u8);
+                    data.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                } else {
+                    data.extend_from_slice(&[OP_RGB, pixel[0], pixel[1], pixel[2]]);
+                }
+            } else {
+                data.push(OP_RGBA);
+                data.extend_from_slice(&pixel);
+            }
+        }
+        prev = pixel;
+    }
+    if run > 0 {
+        data.push(OP_RUN | (run - 1));
+    }
+
+    data.extend_from_slice(&END_MARKER);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    fn gradient(channels: u8) -> Result<Texture> {
+        let dimensions = Dimensions::try_from([16, 8])?;
+        let buffer = (0..dimensions.product())
+            .flat_map(|i| {
+                // runs, small differences, and large jumps, to exercise every op
+                let v = (i / 4 * 3) as u8;
+                [
+                    v,
+                    v.wrapping_mul(7),
+                    255 - v,
+                    if i % 32 < 16 { 255 } else { v },
+                ]
+            })
+            .enumerate()
+            .filter(|(i, _)| i % 4 < channels as usize)
+            .map(|(_, b)| b)
+            .collect::<Vec<_>>();
+        let format = QOIHeader::pixel_format(channels, true).unwrap();
+        Ok(Texture::new(
+            format,
+            TextureShapeNode::from_surface(Surface::new(dimensions, buffer)),
+        )?)
+    }
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        for channels in [3u8, 4] {
+            let texture = gradient(channels)?;
+            let mut file = Cursor::new(Vec::new());
+            QOIHeader::write_texture(&mut file, &texture)?;
+            assert_eq!(&file.get_ref()[..4], b"qoif");
+            assert_eq!(file.get_ref()[12], channels);
+            assert!(file.get_ref().ends_with(&END_MARKER));
+
+            file.set_position(0);
+            let read = QOIHeader::read_texture(&mut file)?;
+            assert_eq!(read.format, texture.format);
+            assert_eq!(
+                read.slice().try_into_surface().unwrap().buffer,
+                texture.slice().try_into_surface().unwrap().buffer
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn image_interop() -> Result<()> {
+        // files written by the image crate's QOI encoder should decode identically
+        let image = image::RgbaImage::from_fn(9, 7, |x, y| {
+            image::Rgba([(x * 28) as u8, (y * 36) as u8, (x * y) as u8, 200 + x as u8])
+        });
+        let mut file = Cursor::new(Vec::new());
+        image.write_to(&mut file, image::ImageFormat::Qoi)?;
+
+        file.set_position(0);
+        let read = QOIHeader::read_texture(&mut file)?;
+        assert_eq!(
+            &read.slice().try_into_surface().unwrap().buffer[..],
+            image.as_raw().as_slice()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported() -> Result<()> {
+        let texture = Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(Surface::new(Dimensions::try_from([4, 4])?, [0; 8])),
+        )?;
+        let result = QOIHeader::write_texture(&mut Cursor::new(Vec::new()), &texture);
+        assert!(matches!(result, Err(TextureError::Format(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+use crate::container::ContainerHeader;
+use crate::dds::DDSHeader;
+use crate::error::{TextureError, TextureResult};
+use crate::texture::Texture;
+use crate::wrapped::{unwrap_reader, Wrapper};
+
+/// Number of bytes read from the start of a file and passed to each container's sniffer
+pub const SNIFF_LENGTH: usize = 16;
+
+/// A reader that can also seek, so that readers can be passed to containers as trait objects
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// A writer that can also seek, so that writers can be passed to containers as trait objects
+pub trait WriteSeek: Write + Seek {}
+impl<T: Write + Seek + ?Sized> WriteSeek for T {}
+
+/// Function that reads a texture from a container file
+pub type ReadFn = fn(&mut dyn ReadSeek) -> TextureResult<Texture>;
+
+/// Function that writes a texture to a container file
+pub type WriteFn = fn(&mut dyn WriteSeek, &Texture) -> TextureResult<()>;
+
+/// A container file type known to a [`ContainerRegistry`]
+#[derive(Clone, Debug)]
+pub struct ContainerEntry {
+    /// Short name of the container, such as `dds`
+    pub name: &'static str,
+
+    /// File extensions used by the container, without a leading `.`
+    pub extensions: &'static [&'static str],
+
+    /// Returns true if the given bytes, which are the first [`SNIFF_LENGTH`] bytes of a file
+    /// or the whole file if it is shorter, look like this container
+    pub sniff: fn(&[u8]) -> bool,
+
+    /// Reads a texture from the container, if reading is supported
+    pub read: Option<ReadFn>,
+
+    /// Writes a texture to the container, if writing is supported
+    pub write: Option<WriteFn>,
+}
+
+impl ContainerEntry {
+    /// Create an entry that reads and writes textures using a [`ContainerHeader`] implementation
+    pub fn for_header<H>(
+        name: &'static str,
+        extensions: &'static [&'static str],
+        sniff: fn(&[u8]) -> bool,
+    ) -> Self
+    where
+        H: ContainerHeader,
+        for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+        for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+    {
+        Self {
+            name,
+            extensions,
+            sniff,
+            read: Some(read_header::<H>),
+            write: Some(write_header::<H>),
+        }
+    }
+}
+
+fn read_header<H>(mut reader: &mut dyn ReadSeek) -> TextureResult<Texture>
+where
+    H: ContainerHeader,
+    for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+    for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+{
+    H::read_texture(&mut reader)
+}
+
+fn write_header<H>(mut writer: &mut dyn WriteSeek, texture: &Texture) -> TextureResult<()>
+where
+    H: ContainerHeader,
+    for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+    for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+{
+    H::write_texture(&mut writer, texture)
+}
+
+/// A list of container types, used to pick a container for a file by its contents or extension.
+///
+/// [`ContainerRegistry::default`] contains every container in this crate. Other crates can add
+/// their own with [`ContainerRegistry::register`], or add them to the global registry used by
+/// [`read_any_texture`] with [`register_container`]
+#[derive(Clone, Debug)]
+pub struct ContainerRegistry {
+    entries: Vec<ContainerEntry>,
+}
+
+impl ContainerRegistry {
+    /// Create a registry with no containers
+    pub fn empty() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a container to the registry. Containers registered later take priority,
+    /// so a built-in container can be replaced by registering another with the same magic bytes
+    pub fn register(&mut self, entry: ContainerEntry) {
+        self.entries.insert(0, entry);
+    }
+
+    /// Iterate over every container in the registry, in priority order
+    pub fn entries(&self) -> impl Iterator<Item = &ContainerEntry> {
+        self.entries.iter()
+    }
+
+    /// Find the container with the given name
+    pub fn by_name(&self, name: &str) -> Option<&ContainerEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Find a container that uses the given file extension, which should not have a leading `.`
+    pub fn by_extension(&self, extension: &str) -> Option<&ContainerEntry> {
+        self.entries.iter().find(|e| {
+            e.extensions
+                .iter()
+                .any(|x| x.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// Find a readable container whose sniffer accepts `bytes`
+    pub fn sniff(&self, bytes: &[u8]) -> Option<&ContainerEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.read.is_some() && (e.sniff)(bytes))
+    }
+
+    /// Read a texture from any readable container in the registry, chosen by the first bytes of the file.
+    /// Files wrapped in a compressed stream are unwrapped first, see [`crate::wrapped`].
+    /// Returns [`TextureError::Format`] if no container recognizes the file
+    pub fn read_any<R: Read + Seek>(&self, reader: &mut R) -> TextureResult<Texture> {
+        let start = reader.stream_position()?;
+        let mut bytes = Vec::with_capacity(SNIFF_LENGTH);
+        reader
+            .by_ref()
+            .take(SNIFF_LENGTH as u64)
+            .read_to_end(&mut bytes)?;
+        reader.seek(SeekFrom::Start(start))?;
+
+        if Wrapper::sniff(&bytes).is_some() {
+            let mut unwrapped = unwrap_reader(reader)?;
+            // only unwrap once, so a file that decompresses to itself can't recurse forever
+            if Wrapper::sniff(unwrapped.get_ref()).is_some() {
+                return Err(TextureError::Format(
+                    "Files wrapped more than once are not supported".to_string(),
+                ));
+            }
+            return self.read_any(&mut unwrapped);
+        }
+
+        let (entry, read) = self
+            .sniff(&bytes)
+            .and_then(|e| Some((e, e.read?)))
+            .ok_or_else(|| {
+                TextureError::Format("File is not in any known container format".to_string())
+            })?;
+        read(reader).map_err(|e| e.in_container(entry.name))
+    }
+}
+
+impl Default for ContainerRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        #[cfg(feature = "image")]
+        registry.register(ContainerEntry {
+            name: "ico",
+            extensions: &["ico"],
+            sniff: |b| b.starts_with(&[0, 0, 1, 0]),
+            read: None,
+            write: Some(|mut w, t| crate::ico::write_ico(&mut w, t)),
+        });
+        registry.register(ContainerEntry {
+            name: "bmp",
+            extensions: &["bmp"],
+            sniff: |b| b.starts_with(b"BM"),
+            read: Some(|mut r| crate::bmp::read_bmp(&mut r)),
+            write: None,
+        });
+        registry.register(ContainerEntry::for_header::<crate::astc::ASTCHeader>(
+            "astc",
+            &["astc"],
+            |b| b.starts_with(&[0x13, 0xAB, 0xA1, 0x5C]),
+        ));
+        registry.register(ContainerEntry::for_header::<crate::pvr::PVRHeader>(
+            "pvr",
+            &["pvr"],
+            |b| b.starts_with(b"PVR\x03"),
+        ));
+        #[cfg(feature = "qoi")]
+        registry.register(ContainerEntry::for_header::<crate::qoi::QOIHeader>(
+            "qoi",
+            &["qoi"],
+            |b| b.starts_with(b"qoif"),
+        ));
+        #[cfg(feature = "chunked")]
+        registry.register(ContainerEntry::for_header::<crate::chunked::ChunkedHeader>(
+            "qtx",
+            &["qtx"],
+            |b| b.starts_with(b"QTXC"),
+        ));
+        registry.register(ContainerEntry::for_header::<DDSHeader>(
+            "dds",
+            &["dds"],
+            |b| b.starts_with(b"DDS ") || b.starts_with(b" SDD"),
+        ));
+        registry
+    }
+}
+
+fn global() -> &'static RwLock<ContainerRegistry> {
+    static REGISTRY: OnceLock<RwLock<ContainerRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Add a container to the global registry used by [`read_any_texture`] and the command line tool
+pub fn register_container(entry: ContainerEntry) {
+    // the registry is never left half-modified, so it's still usable if another thread panicked holding the lock
+    global()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .register(entry);
+}
+
+/// Get a copy of the global registry, including every container added with [`register_container`]
+pub fn registry() -> ContainerRegistry {
+    global()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Read a texture from any container in the global registry, chosen by the first bytes of the file.
+/// See [`ContainerRegistry::read_any`]
+pub fn read_any_texture<R: Read + Seek>(reader: &mut R) -> TextureResult<Texture> {
+    registry().read_any(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+
+    use crate::dimensions::Dimensions;
+    use crate::format::Format;
+    use crate::shape::TextureShapeNode;
+    use crate::texture::Surface;
+
+    use super::*;
+
+    fn bc1() -> Result<Texture> {
+        let surface = Surface::new(Dimensions::try_from([4, 4])?, [7u8; 8]);
+        Ok(Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(surface),
+        )?)
+    }
+
+    #[test]
+    fn read_any() -> Result<()> {
+        let mut file = Cursor::new(Vec::new());
+        DDSHeader::write_texture(&mut file, &bc1()?)?;
+        file.set_position(0);
+        assert_eq!(
+            read_any_texture(&mut file)?.format,
+            Format::BC1 { srgb: false }
+        );
+
+        let mut unknown = Cursor::new(b"not a texture".to_vec());
+        assert!(matches!(
+            read_any_texture(&mut unknown),
+            Err(TextureError::Format(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn read_any_gzip() -> Result<()> {
+        use crate::wrapped::write_wrapped;
+
+        let mut file = Vec::new();
+        write_wrapped::<DDSHeader, _>(&mut file, &bc1()?, Some(Wrapper::Gzip))?;
+        let texture = read_any_texture(&mut Cursor::new(&file))?;
+        assert_eq!(texture.format, Format::BC1 { srgb: false });
+
+        let twice = Wrapper::Gzip.compress(&file)?;
+        assert!(read_any_texture(&mut Cursor::new(twice)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn register() -> Result<()> {
+        // a DDS file behind a 4-byte prefix
+        fn read_prefixed(reader: &mut dyn ReadSeek) -> TextureResult<Texture> {
+            reader.seek(SeekFrom::Current(4))?;
+            read_header::<DDSHeader>(reader)
+        }
+
+        let mut registry = ContainerRegistry::default();
+        registry.register(ContainerEntry {
+            name: "prefixed",
+            extensions: &["pdds"],
+            sniff: |b| b.starts_with(b"PFX!"),
+            read: Some(read_prefixed),
+            write: None,
+        });
+        assert_eq!(registry.by_extension("PDDS").unwrap().name, "prefixed");
+        assert_eq!(registry.by_extension("dds").unwrap().name, "dds");
+
+        let mut file = Cursor::new(b"PFX!".to_vec());
+        file.set_position(4);
+        DDSHeader::write_texture(&mut file, &bc1()?)?;
+        file.set_position(0);
+        assert!(ContainerRegistry::default().read_any(&mut file).is_err());
+        assert_eq!(
+            registry.read_any(&mut file)?.format,
+            Format::BC1 { srgb: false }
+        );
+        Ok(())
+    }
+
+    /// Read corrupted copies of every file in the fuzzing seed corpus, which must fail cleanly instead of panicking.
+    /// Files are truncated at every length, and have single bytes and runs of `0xFF` written over their headers,
+    /// which produces huge sizes, counts, and offsets
+    #[test]
+    fn malformed_corpus() -> Result<()> {
+        let read = |bytes: &[u8]| {
+            #[cfg_attr(not(feature = "image"), allow(unused_variables))]
+            if let Ok(texture) = read_any_texture(&mut Cursor::new(bytes)) {
+                #[cfg(feature = "image")]
+                let _ = texture.decode();
+            }
+        };
+        let seeds = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/seeds");
+        for entry in std::fs::read_dir(seeds)? {
+            let file = std::fs::read(entry?.path())?;
+            for len in 0..file.len() {
+                read(&file[..len]);
+            }
+            for i in 0..file.len().min(160) {
+                for value in [0x00, 0x01, 0x80, 0xFF] {
+                    let mut bytes = file.clone();
+                    bytes[i] = value;
+                    read(&bytes);
+                }
+                for width in [4, 8] {
+                    let mut bytes = file.clone();
+                    let run = i..(i + width).min(file.len());
+                    bytes[run].fill(0xFF);
+                    read(&bytes);
+                }
+            }
+        }
+        Ok(())
+    }
+}
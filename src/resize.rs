@@ -0,0 +1,216 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Gamma-correct image resizing, for mip generation and downscaling.
+//!
+//! Averaging sRGB encoded values directly darkens every mip, since the sRGB curve puts most of its range
+//! into dark values: a black and white checkerboard averages to 128, which displays as about 22% gray
+//! instead of 50%. When resizing sRGB images, the color channels are converted to linear light first,
+//! filtered, and converted back. Alpha is always linear, so it is filtered as-is
+
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgba32FImage};
+
+/// Convert an sRGB encoded value to linear light, between 0 and 1
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.0;
+    match v <= 0.04045 {
+        true => v / 12.92,
+        false => ((v + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+/// Convert a linear light value between 0 and 1 to sRGB, rounding to the nearest value
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let v = match v <= 0.0031308 {
+        true => v * 12.92,
+        false => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+    };
+    (v * 255.0).round() as u8
+}
+
+/// Resize `image` to exactly `width`x`height` with a triangle filter.
+/// If `srgb` is set, the color channels are filtered in linear light. The result is always 8-bit RGBA
+pub fn resize(image: &DynamicImage, width: u32, height: u32, srgb: bool) -> DynamicImage {
+    resize_filtered(image, width, height, srgb, FilterType::Triangle)
+}
+
+/// Resize `image` to exactly `width`x`height` with `filter`. See [`resize`]
+pub fn resize_filtered(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    srgb: bool,
+    filter: FilterType,
+) -> DynamicImage {
+    if !srgb {
+        return image.resize_exact(width, height, filter);
+    }
+
+    let rgba = image.to_rgba8();
+    let linear = Rgba32FImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        image::Rgba([
+            srgb_to_linear(r),
+            srgb_to_linear(g),
+            srgb_to_linear(b),
+            a as f32 / 255.0,
+        ])
+    });
+    let resized = image::imageops::resize(&linear, width, height, filter);
+    let encoded = image::RgbaImage::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = resized.get_pixel(x, y).0;
+        image::Rgba([
+            linear_to_srgb(r),
+            linear_to_srgb(g),
+            linear_to_srgb(b),
+            (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ])
+    });
+    encoded.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use generic_parameterize::parameterize;
+    use image::{Rgba, RgbaImage};
+
+    use crate::format::{Format, Quality};
+    use crate::shape::TextureShape;
+    use crate::texture::Texture;
+
+    use super::*;
+
+    const SNAPSHOT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots/mips");
+
+    /// A black and white checkerboard of single pixels
+    fn checkerboard(size: u32) -> DynamicImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            let v = if (x + y) % 2 == 0 { 0 } else { 255 };
+            Rgba([v, v, v, v])
+        })
+        .into()
+    }
+
+    #[test]
+    fn transfer_roundtrip() {
+        for v in 0..=255u8 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(v)), v);
+        }
+        assert_eq!(linear_to_srgb(0.5), 188);
+        assert_eq!(linear_to_srgb(-1.0), 0);
+        assert_eq!(linear_to_srgb(2.0), 255);
+    }
+
+    #[test]
+    fn checkerboard_average() {
+        let srgb = resize(&checkerboard(8), 1, 1, true).into_rgba8();
+        // half the light of white, and alpha is averaged linearly
+        assert_eq!(srgb.get_pixel(0, 0).0, [188, 188, 188, 128]);
+
+        let linear = resize(&checkerboard(8), 1, 1, false).into_rgba8();
+        assert!(linear
+            .get_pixel(0, 0)
+            .0
+            .iter()
+            .all(|v| v.abs_diff(128) <= 1));
+    }
+
+    /// A black and white checkerboard in red, a horizontal gradient from black to white in green,
+    /// and a hard edge from black to white in blue
+    fn gradient() -> DynamicImage {
+        RgbaImage::from_fn(64, 64, |x, y| {
+            let checker = if (x + y) % 2 == 0 { 0 } else { 255 };
+            let edge = if x < 32 { 0 } else { 255 };
+            Rgba([checker, x as u8 * 4, edge, 255])
+        })
+        .into()
+    }
+
+    /// The average of each channel of every mip of `texture`, in linear light if `srgb` is set
+    fn mip_averages(texture: &Texture, srgb: bool) -> Vec<[f32; 3]> {
+        texture
+            .iter_mips()
+            .map(|(_, mip)| {
+                let buffer = &mip.primary().buffer;
+                let pixels = buffer.len() / 4;
+                std::array::from_fn(|c| {
+                    let sum: f32 = (0..pixels)
+                        .map(|p| match srgb {
+                            true => srgb_to_linear(buffer[p * 4 + c]),
+                            false => buffer[p * 4 + c] as f32 / 255.0,
+                        })
+                        .sum();
+                    sum / pixels as f32
+                })
+            })
+            .collect()
+    }
+
+    #[parameterize(srgb = [true, false], fmt = "mip_brightness_{srgb}")]
+    #[test]
+    /// Every mip of a gradient should have the same average brightness as the original, measured in linear light
+    /// for sRGB textures and in encoded values for linear ones. Averaging sRGB values directly makes mips darker
+    fn mip_brightness(srgb: bool) -> Result<()> {
+        let texture = Texture::from_image(&gradient(), Format::rgba8(srgb), true, Quality::Normal)?;
+        let averages = mip_averages(&texture, srgb);
+        assert_eq!(averages.len(), 7);
+        // the triangle filter weighs the edges of the gradient unevenly when shrinking it by a lot,
+        // but by much less than the 0.1 that filtering sRGB values directly would darken it by
+        let tolerance = [0.005, 0.03, 0.005];
+        for (m, mip) in averages.iter().enumerate() {
+            for c in 0..3 {
+                assert!(
+                    (mip[c] - averages[0][c]).abs() < tolerance[c],
+                    "mip {m} channel {c} averages {} instead of {}",
+                    mip[c],
+                    averages[0][c]
+                );
+            }
+        }
+
+        // every pixel of every mip of the checkerboard is about half as bright as white, give or take
+        // the filter clamping at the edges, which is 188 in sRGB instead of the 128 of averaging the encoded values
+        let expected = if srgb { 188 } else { 128 };
+        for (m, mip) in texture.iter_mips() {
+            let buffer = &mip.primary().buffer;
+            if m != Some(0) {
+                assert!(
+                    buffer.iter().step_by(4).all(|r| r.abs_diff(expected) <= 4),
+                    "mip {m:?} of the checkerboard is not {expected}"
+                );
+            }
+        }
+
+        check_snapshot(
+            &format!("gradient_{}", if srgb { "srgb" } else { "linear" }),
+            &averages,
+        )
+    }
+
+    /// Compare the average values of each mip against a golden file in [`SNAPSHOT_DIR`].
+    /// Set `QUICKTEX_UPDATE_SNAPSHOTS` to rewrite the files after an intended change, and review the diff
+    fn check_snapshot(name: &str, averages: &[[f32; 3]]) -> Result<()> {
+        use anyhow::Context;
+
+        let dump = averages
+            .iter()
+            .enumerate()
+            .map(|(m, [r, g, b])| format!("mip {m}: {r:.4} {g:.4} {b:.4}\n"))
+            .collect::<String>();
+        let path = format!("{SNAPSHOT_DIR}/{name}.txt");
+        if std::env::var_os("QUICKTEX_UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(SNAPSHOT_DIR)?;
+            std::fs::write(&path, dump)?;
+            return Ok(());
+        }
+        let expected = std::fs::read_to_string(&path).with_context(|| {
+            format!("Missing snapshot {path}, run with QUICKTEX_UPDATE_SNAPSHOTS=1 to create it")
+        })?;
+        assert_eq!(dump, expected, "mip averages for {name} changed");
+        Ok(())
+    }
+}
@@ -1 +1,188 @@
-struct BC1Decoder {}
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use vector_victor::Matrix;
+
+use crate::color::{Color, ColorImpl};
+use crate::format::Interpolation;
+
+use super::BC1Block;
+
+impl BC1Block {
+    /// If the block uses the 3-color mode, where code 2 is the midpoint of the endpoints and code 3 is
+    /// transparent black. This is selected by storing the endpoints with the first not greater than the second
+    pub fn is_three_color(&self) -> bool {
+        self.colors[0].to_565() <= self.colors[1].to_565()
+    }
+
+    /// The 4 colors a code can select. In 4-color mode, 2 colors are interpolated at thirds
+    /// between the endpoints. In 3-color mode, the midpoint is interpolated and the last color is transparent
+    pub fn palette(&self) -> [Color; 4] {
+        self.palette_with(Interpolation::default())
+    }
+
+    /// The 4 colors a code can select, interpolated with the given arithmetic. See [`Self::palette`]
+    pub fn palette_with(&self, interpolation: Interpolation) -> [Color; 4] {
+        self.palette_for(self.is_three_color(), interpolation)
+    }
+
+    /// The palette in the given mode, regardless of the order of the endpoints.
+    /// The color half of a BC3 block is always in 4-color mode
+    pub(crate) fn palette_for(
+        &self,
+        three_color: bool,
+        interpolation: Interpolation,
+    ) -> [Color; 4] {
+        let [c0, c1] = self.colors;
+        let [raw0, raw1] = self.colors.map(|c| {
+            let packed = c.to_565() as u32;
+            [packed >> 11, (packed >> 5) & 0x3F, packed & 0x1F]
+        });
+        let lerp = |a: usize, b: usize, half: bool| {
+            let mut color = c0;
+            for (c, bits) in [5, 6, 5].into_iter().enumerate() {
+                let raw = [raw0[c], raw1[c]];
+                color[c] = interpolate(interpolation, raw[a], raw[b], bits, half);
+            }
+            color
+        };
+        match three_color {
+            false => [c0, c1, lerp(0, 1, false), lerp(1, 0, false)],
+            true => [c0, c1, lerp(0, 1, true), Color::vec([0, 0, 0, 0])],
+        }
+    }
+
+    /// Decode the block to RGBA pixels
+    pub fn decode_pixels(&self) -> Matrix<Color, 4, 4> {
+        self.decode_pixels_with(Interpolation::default())
+    }
+
+    /// Decode the block to RGBA pixels, interpolated with the given arithmetic
+    pub fn decode_pixels_with(&self, interpolation: Interpolation) -> Matrix<Color, 4, 4> {
+        self.decode_with(self.palette_with(interpolation))
+    }
+
+    /// Decode the block to opaque RGBA pixels in 4-color mode, as the color half of a BC2 or BC3 block
+    pub(crate) fn decode_four_color(&self, interpolation: Interpolation) -> Matrix<Color, 4, 4> {
+        self.decode_with(self.palette_for(false, interpolation))
+    }
+
+    fn decode_with(&self, palette: [Color; 4]) -> Matrix<Color, 4, 4> {
+        self.codes
+            .elements()
+            .map(|&c| palette[c as usize])
+            .collect()
+    }
+}
+
+/// Interpolate a single channel from the raw `bits`-bit endpoint values `a` and `b`,
+/// a third of the way to `b`, or halfway if `half` is set
+fn interpolate(interpolation: Interpolation, a: u32, b: u32, bits: u32, half: bool) -> u8 {
+    // replicate the high bits into the low bits, the same as Color::from_565
+    let expand = |v: u32| (v << (8 - bits)) | (v >> (2 * bits - 8));
+    let (ea, eb) = (expand(a), expand(b));
+    let value = match (interpolation, half) {
+        (Interpolation::Reference, false) => (2 * ea + eb + 1) / 3,
+        (Interpolation::Reference, true) => (ea + eb).div_ceil(2),
+        (Interpolation::Nvidia, false) if bits == 5 => (2 * a + b) * 22 / 8,
+        (Interpolation::Nvidia, true) if bits == 5 => (a + b) * 33 / 8,
+        (Interpolation::Nvidia, half) => {
+            let diff = eb as i32 - ea as i32;
+            let weight = if half { 128 } else { 80 };
+            ((256 * ea as i32 + diff / 4 + 128 + diff * weight) / 256) as u32
+        }
+        (Interpolation::Amd, false) => (42 * ea + 22 * eb + 32) >> 6,
+        (Interpolation::Amd, true) => (ea + eb + 1) >> 1,
+        (Interpolation::Ideal, half) => {
+            let max = ((1 << bits) - 1) as f32;
+            let t = if half { 0.5 } else { 1.0 / 3.0 };
+            let value = (a as f32 * (1.0 - t) + b as f32 * t) / max;
+            (value * 255.0).round() as u32
+        }
+    };
+    value as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::VariantArray;
+
+    use crate::blocktexture::Block;
+
+    use super::*;
+
+    #[test]
+    fn four_color() {
+        // white and black, with codes 0 through 3 in the first row
+        let block = BC1Block::from_bytes(&[0xFF, 0xFF, 0x00, 0x00, 0b11_10_01_00, 0, 0, 0]);
+        assert!(!block.is_three_color());
+        let pixels = block.decode_pixels();
+        let row: Vec<_> = (0..4).map(|x| pixels[(0, x)]).collect();
+        assert_eq!(
+            row,
+            [
+                Color::vec([255, 255, 255, 255]),
+                Color::vec([0, 0, 0, 255]),
+                Color::vec([170, 170, 170, 255]),
+                Color::vec([85, 85, 85, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn three_color() {
+        // the same endpoints swapped, which selects 3-color mode
+        let block = BC1Block::from_bytes(&[0x00, 0x00, 0xFF, 0xFF, 0b11_10_01_00, 0, 0, 0]);
+        assert!(block.is_three_color());
+        let pixels = block.decode_pixels();
+        assert_eq!(pixels[(0, 2)], Color::vec([128, 128, 128, 255]));
+        assert_eq!(pixels[(0, 3)], Color::vec([0, 0, 0, 0]));
+        assert_eq!(pixels[(1, 0)], Color::vec([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn interpolation() {
+        // white and black, in 4-color mode and swapped into 3-color mode
+        let four = BC1Block::from_bytes(&[0xFF, 0xFF, 0x00, 0x00, 0, 0, 0, 0]);
+        let three = BC1Block::from_bytes(&[0x00, 0x00, 0xFF, 0xFF, 0, 0, 0, 0]);
+        let rgb = |r, g, b| Color::vec([r, g, b, 255]);
+        let interpolated = |i| {
+            let [_, _, c2, c3] = four.palette_with(i);
+            [c2, c3, three.palette_with(i)[2]]
+        };
+        assert_eq!(
+            interpolated(Interpolation::Reference),
+            [rgb(170, 170, 170), rgb(85, 85, 85), rgb(128, 128, 128)]
+        );
+        // green is interpolated differently from red and blue
+        assert_eq!(
+            interpolated(Interpolation::Nvidia),
+            [rgb(170, 175, 170), rgb(85, 80, 85), rgb(127, 128, 127)]
+        );
+        assert_eq!(
+            interpolated(Interpolation::Amd),
+            [rgb(167, 167, 167), rgb(88, 88, 88), rgb(128, 128, 128)]
+        );
+        assert_eq!(
+            interpolated(Interpolation::Ideal),
+            [rgb(170, 170, 170), rgb(85, 85, 85), rgb(128, 128, 128)]
+        );
+
+        // every mode stays within a few values of the exact result, for every pair of endpoints
+        for bits in [5, 6] {
+            for (a, b, half) in (0..1 << bits)
+                .flat_map(|a| (0..1 << bits).flat_map(move |b| [(a, b, false), (a, b, true)]))
+            {
+                let ideal = interpolate(Interpolation::Ideal, a, b, bits, half);
+                for &i in Interpolation::VARIANTS {
+                    let value = interpolate(i, a, b, bits, half);
+                    assert!(
+                        value.abs_diff(ideal) <= 6,
+                        "{i} interpolates {a} and {b} to {value} instead of {ideal}"
+                    );
+                }
+            }
+        }
+    }
+}
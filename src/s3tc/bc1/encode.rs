@@ -1 +1,395 @@
-struct BC1Encoder {}
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use vector_victor::Matrix;
+
+use crate::color::{Color, ColorImpl};
+use crate::format::Quality;
+
+use super::{BC1Block, BC1Options};
+
+type Vec3 = [f32; 3];
+
+fn vec3(c: &Color) -> Vec3 {
+    [c[0] as f32, c[1] as f32, c[2] as f32]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: Vec3, s: f32) -> Vec3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+/// Round a color to the nearest RGB565 value, and expand it back to 8 bits per channel
+fn quantize(v: Vec3) -> Color {
+    let channel = |v: f32, max: f32| (v.clamp(0.0, 255.0) * max / 255.0).round() as u16;
+    let packed = channel(v[0], 31.0) << 11 | channel(v[1], 63.0) << 5 | channel(v[2], 31.0);
+    Color::from_565(packed)
+}
+
+/// Squared distance between the RGB channels of two colors
+fn distance(a: &Color, b: &Color) -> u32 {
+    (0..3).map(|c| (a[c].abs_diff(b[c]) as u32).pow(2)).sum()
+}
+
+/// The weight of the first endpoint for each code, in 4-color and 3-color mode.
+/// Code 3 in 3-color mode is transparent, and never used for fitting
+const WEIGHTS: [[f32; 4]; 2] = [[1.0, 0.0, 2.0 / 3.0, 1.0 / 3.0], [1.0, 0.0, 0.5, 0.0]];
+
+/// The opaque pixels of a block, and the statistics every fit needs
+struct Points {
+    points: Vec<Vec3>,
+    mean: Vec3,
+    axis: Vec3,
+}
+
+impl Points {
+    fn new(points: Vec<Vec3>) -> Self {
+        let n = points.len() as f32;
+        let mean = scale(points.iter().fold([0.0; 3], |s, &p| add(s, p)), 1.0 / n);
+
+        let mut covariance = [[0f32; 3]; 3];
+        for p in &points {
+            let d = sub(*p, mean);
+            for (i, row) in covariance.iter_mut().enumerate() {
+                for (j, c) in row.iter_mut().enumerate() {
+                    *c += d[i] * d[j];
+                }
+            }
+        }
+
+        // power iteration, starting from the diagonal of the bounding box so it converges quickly
+        let (min, max) = points.iter().fold(([255f32; 3], [0f32; 3]), |(lo, hi), p| {
+            (
+                [lo[0].min(p[0]), lo[1].min(p[1]), lo[2].min(p[2])],
+                [hi[0].max(p[0]), hi[1].max(p[1]), hi[2].max(p[2])],
+            )
+        });
+        let mut axis = sub(max, min);
+        for _ in 0..8 {
+            let next = covariance.map(|row| dot(row, axis));
+            let length = dot(next, next).sqrt();
+            if length < f32::EPSILON {
+                break;
+            }
+            axis = scale(next, 1.0 / length);
+        }
+        let length = dot(axis, axis).sqrt();
+        let axis = match length < f32::EPSILON {
+            true => [0.0; 3],
+            false => scale(axis, 1.0 / length),
+        };
+
+        Self { points, mean, axis }
+    }
+
+    /// Endpoints at the extremes of the points projected onto the principal axis
+    fn pca(&self) -> [Vec3; 2] {
+        let (min, max) = self
+            .points
+            .iter()
+            .map(|&p| dot(sub(p, self.mean), self.axis))
+            .fold((0f32, 0f32), |(lo, hi), t| (lo.min(t), hi.max(t)));
+        [
+            add(self.mean, scale(self.axis, max)),
+            add(self.mean, scale(self.axis, min)),
+        ]
+    }
+
+    /// Solve for the endpoints that best fit the points, given the weight of the first endpoint for each point.
+    /// Returns `None` if the weights don't determine both endpoints
+    fn least_squares(&self, weights: impl Iterator<Item = f32>) -> Option<([Vec3; 2], f32)> {
+        let (mut aa, mut bb, mut ab) = (0f32, 0f32, 0f32);
+        let (mut ax, mut bx) = ([0f32; 3], [0f32; 3]);
+        let mut xx = 0f32;
+        for (p, alpha) in self.points.iter().zip(weights) {
+            let beta = 1.0 - alpha;
+            aa += alpha * alpha;
+            bb += beta * beta;
+            ab += alpha * beta;
+            ax = add(ax, scale(*p, alpha));
+            bx = add(bx, scale(*p, beta));
+            xx += dot(*p, *p);
+        }
+        let determinant = aa * bb - ab * ab;
+        if determinant.abs() < 1e-6 {
+            return None;
+        }
+        let a = scale(sub(scale(ax, bb), scale(bx, ab)), 1.0 / determinant);
+        let b = scale(sub(scale(bx, aa), scale(ax, ab)), 1.0 / determinant);
+        let error = xx - 2.0 * (dot(a, ax) + dot(b, bx))
+            + aa * dot(a, a)
+            + 2.0 * ab * dot(a, b)
+            + bb * dot(b, b);
+        Some(([a, b], error))
+    }
+
+    /// Cluster fit: sort the points along the principal axis, and try every way of assigning them
+    /// in that order to the palette entries, from the second endpoint to the first
+    fn cluster_fit(&self, three_color: bool) -> Option<[Vec3; 2]> {
+        let mut order: Vec<usize> = (0..self.points.len()).collect();
+        order.sort_by(|&a, &b| {
+            let t = |i: usize| dot(self.points[i], self.axis);
+            t(a).total_cmp(&t(b))
+        });
+        let sorted = Self {
+            points: order.iter().map(|&i| self.points[i]).collect(),
+            ..*self
+        };
+
+        // palette weights in order from the second endpoint to the first
+        let steps: &[f32] = match three_color {
+            false => &[0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0],
+            true => &[0.0, 0.5, 1.0],
+        };
+        let n = sorted.points.len();
+        let mut best: Option<([Vec3; 2], f32)> = None;
+        let mut bounds = vec![0usize; steps.len() - 1];
+        loop {
+            // the weight of each point, from which cluster its index falls in
+            let weights = (0..n).map(|i| steps[bounds.iter().filter(|&&b| i >= b).count()]);
+            if let Some((endpoints, error)) = sorted.least_squares(weights) {
+                if best.is_none_or(|(_, e)| error < e) {
+                    best = Some((endpoints, error));
+                }
+            }
+
+            // advance to the next non-decreasing set of cluster boundaries
+            let Some(i) = (0..bounds.len()).rev().find(|&i| bounds[i] < n) else {
+                break;
+            };
+            bounds[i] += 1;
+            for j in i + 1..bounds.len() {
+                bounds[j] = bounds[i];
+            }
+        }
+        best.map(|(endpoints, _)| endpoints)
+    }
+}
+
+impl BC1Block {
+    /// Create a block from two endpoints, choosing the closest palette color for each pixel.
+    /// In 3-color mode, pixels with alpha below `threshold` use the transparent code 3
+    fn from_endpoints(
+        mut colors: [Color; 2],
+        pixels: &Matrix<Color, 4, 4>,
+        three_color: bool,
+        threshold: u8,
+    ) -> Self {
+        let [p0, p1] = colors.map(|c| c.to_565());
+        if (p0 > p1) == three_color {
+            colors.swap(0, 1);
+        }
+        let mut block = Self {
+            colors,
+            codes: Matrix::default(),
+        };
+        if !three_color && p0 == p1 {
+            // equal endpoints are always 3-color mode, but code 0 is the only color needed
+            return block;
+        }
+
+        let palette = block.palette();
+        let count = if three_color { 3u8 } else { 4u8 };
+        block.codes = pixels
+            .elements()
+            .map(|p| match three_color && *p.a() < threshold {
+                true => 3,
+                false => (0..count)
+                    .min_by_key(|&c| distance(&palette[c as usize], p))
+                    .unwrap(),
+            })
+            .collect();
+        block
+    }
+
+    /// Total squared error of the opaque pixels of a block
+    fn error(&self, pixels: &Matrix<Color, 4, 4>) -> u32 {
+        let palette = self.palette();
+        pixels
+            .elements()
+            .zip(self.codes.elements())
+            .filter(|&(_, &c)| palette[c as usize][3] != 0)
+            .map(|(p, &c)| distance(&palette[c as usize], p))
+            .sum()
+    }
+
+    /// Encode pixels, fitting endpoints as described in [`BC1Options::quality`].
+    /// If any pixel has alpha below the options' threshold, the block uses 3-color mode
+    /// and those pixels are transparent
+    pub fn encode_pixels(pixels: &Matrix<Color, 4, 4>, options: &BC1Options) -> Self {
+        Self::encode_color(pixels, options, true)
+    }
+
+    /// Encode pixels, using only the 4-color mode if `allow_three_color` is not set.
+    /// Alpha is ignored in that case, as for the color half of a BC3 block
+    pub(crate) fn encode_color(
+        pixels: &Matrix<Color, 4, 4>,
+        options: &BC1Options,
+        allow_three_color: bool,
+    ) -> Self {
+        let threshold = match allow_three_color {
+            true => options.alpha_threshold,
+            false => 0,
+        };
+        let transparent = pixels.elements().any(|p| *p.a() < threshold);
+        let opaque: Vec<Vec3> = pixels
+            .elements()
+            .filter(|p| *p.a() >= threshold)
+            .map(vec3)
+            .collect();
+        if opaque.is_empty() {
+            return Self {
+                colors: [Color::vec([0, 0, 0, 255]); 2],
+                codes: Matrix::fill(3),
+            };
+        }
+
+        let points = Points::new(opaque);
+        let encode = |[a, b]: [Vec3; 2], three_color: bool| {
+            Self::from_endpoints([quantize(a), quantize(b)], pixels, three_color, threshold)
+        };
+        let pca = encode(points.pca(), transparent);
+
+        // each tier adds candidates to the ones before it, so a higher tier is never worse
+        let mut candidates = Vec::new();
+        if options.quality >= Quality::Normal {
+            let weights = WEIGHTS[transparent as usize];
+            let codes = pca
+                .codes
+                .elements()
+                .zip(pixels.elements())
+                .filter(|(_, p)| *p.a() >= threshold)
+                .map(|(&c, _)| weights[c as usize])
+                .collect::<Vec<_>>();
+            candidates.extend(
+                points
+                    .least_squares(codes.into_iter())
+                    .map(|(endpoints, _)| encode(endpoints, transparent)),
+            );
+        }
+        if options.quality >= Quality::High {
+            candidates.extend(
+                points
+                    .cluster_fit(transparent)
+                    .map(|endpoints| encode(endpoints, transparent)),
+            );
+        }
+        if options.quality >= Quality::Exhaustive && allow_three_color && !transparent {
+            candidates.extend(points.cluster_fit(true).map(|e| encode(e, true)));
+        }
+
+        // the first candidate with the lowest error wins, so ties keep the simpler fit
+        std::iter::once(pca)
+            .chain(candidates)
+            .min_by_key(|block| block.error(pixels))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vertical gradient between two colors, which every fit should reproduce closely
+    fn gradient() -> Matrix<Color, 4, 4> {
+        (0..16)
+            .map(|i| {
+                let t = (i / 4) as u32;
+                Color::vec([
+                    (40 + t * 50) as u8,
+                    (200 - t * 40) as u8,
+                    (10 + t * 20) as u8,
+                    255,
+                ])
+            })
+            .collect()
+    }
+
+    /// Pixels scattered off a single line, where refinement has room to improve on PCA
+    fn noisy() -> Matrix<Color, 4, 4> {
+        (0..16u32)
+            .map(|i| {
+                let [a, b, c, _] = i.wrapping_mul(2654435761).to_le_bytes();
+                Color::vec([a / 2 + 64, a / 2 + b / 4, 255 - c / 3, 255])
+            })
+            .collect()
+    }
+
+    fn options(quality: Quality) -> BC1Options {
+        BC1Options {
+            quality,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn quality_tiers() {
+        for pixels in [gradient(), noisy()] {
+            let errors: Vec<u32> = [
+                Quality::Fastest,
+                Quality::Normal,
+                Quality::High,
+                Quality::Exhaustive,
+            ]
+            .map(|q| BC1Block::encode_pixels(&pixels, &options(q)).error(&pixels))
+            .into();
+            assert!(
+                errors.windows(2).all(|w| w[1] <= w[0]),
+                "higher tiers are never worse: {errors:?}"
+            );
+        }
+        let noisy = noisy();
+        let [fastest, high] = [Quality::Fastest, Quality::High]
+            .map(|q| BC1Block::encode_pixels(&noisy, &options(q)).error(&noisy));
+        assert!(high < fastest, "cluster fit improves on PCA");
+
+        let gradient = gradient();
+        let block = BC1Block::encode_pixels(&gradient, &options(Quality::High));
+        assert!(block.error(&gradient) < 16 * 3 * 16, "within 4 per channel");
+        assert!(!block.is_three_color());
+    }
+
+    #[test]
+    fn solid() {
+        let pixels = Matrix::fill(Color::vec([123, 45, 67, 255]));
+        let block = BC1Block::encode_pixels(&pixels, &options(Quality::High));
+        let decoded = block.decode_pixels();
+        for p in decoded.elements() {
+            assert!(distance(p, &pixels[(0, 0)]) < 3 * 16);
+        }
+    }
+
+    #[test]
+    fn transparency() {
+        let mut pixels = gradient();
+        pixels[(1, 2)][3] = 0;
+        let block = BC1Block::encode_pixels(&pixels, &BC1Options::default());
+        assert!(block.is_three_color());
+        assert_eq!(block.decode_pixels()[(1, 2)][3], 0);
+
+        // a threshold of 0 disables transparency
+        let opaque = BC1Block::encode_pixels(
+            &pixels,
+            &BC1Options {
+                alpha_threshold: 0,
+                ..Default::default()
+            },
+        );
+        assert!(opaque.decode_pixels().elements().all(|p| p[3] == 255));
+
+        let clear = BC1Block::encode_pixels(&Matrix::fill(Color::vec([0; 4])), &Default::default());
+        assert!(clear.decode_pixels().elements().all(|p| p[3] == 0));
+    }
+}
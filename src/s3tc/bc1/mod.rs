@@ -2,18 +2,17 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::iter::zip;
-
-use bitvec::prelude::*;
 use vector_victor::Matrix;
 
-use crate::blocktexture::Block;
+use crate::bitstream::{BitReader, BitWriter};
+use crate::blocktexture::{Block, CodecBlock, Pixels};
 use crate::color::{Color, ColorImpl};
-use crate::pack::{Pack, Unpack};
+use crate::format::{Interpolation, Quality};
 
 mod decode;
 mod encode;
 
+/// A single 4x4 BC1 block, with two endpoint colors and a 2-bit code per pixel
 #[derive(Copy, Clone)]
 pub struct BC1Block {
     colors: [Color; 2],
@@ -26,32 +25,29 @@ impl Block for BC1Block {
 
     fn to_bytes(&self) -> Self::Bytes {
         let mut bytes: Self::Bytes = [0; 8];
-        let bits = bytes.view_bits_mut::<Msb0>();
-
-        // store endpoints
-        bits[0..16].store_le(self.colors[0].to_565());
-        bits[16..32].store_le(self.colors[1].to_565());
+        let mut writer = BitWriter::new(&mut bytes);
 
-        // store codes
-        zip(self.codes.rows(), bits[32..].chunks_mut(8))
-            // pack each row of 2-bit values into reversed chunks
-            .for_each(|(src, dst)| dst.rchunks_mut(2).pack_be(src));
+        // endpoints, then a 2-bit code per pixel in row-major order
+        writer
+            .write(self.colors[0].to_565(), 16)
+            .and_then(|_| writer.write(self.colors[1].to_565(), 16))
+            .and_then(|_| writer.write_all(self.codes.elements().copied(), 2))
+            .expect("BC1 codes are 2 bits");
 
         bytes
     }
 
     fn from_bytes(bytes: &Self::Bytes) -> Self {
-        let bits = bytes.as_bits::<Msb0>();
+        let mut reader = BitReader::new(bytes);
 
-        // load endpoints
-        let color0 = Color::from_565(bits[0..16].load_le());
-        let color1 = Color::from_565(bits[16..32].load_le());
-
-        // load codes
-        let codes = Matrix::<u8, 4, 4>::from_rows(
-            // reverse each row of 2-bit numbers and collect them to a Vector
-            bits.chunks(8).map(|r| r.rchunks(2).unpack_le().collect()),
-        );
+        // a block is always exactly 64 bits, so none of these reads can fail
+        let color0 = Color::from_565(reader.read(16).unwrap());
+        let color1 = Color::from_565(reader.read(16).unwrap());
+        let codes = reader
+            .read_array::<u8, 16>(2)
+            .unwrap()
+            .into_iter()
+            .collect();
 
         Self {
             colors: [color0, color1],
@@ -59,3 +55,80 @@ impl Block for BC1Block {
         }
     }
 }
+
+/// Options for encoding [`BC1Block`]s
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BC1Options {
+    /// How hard to search for endpoints. Every tier starts from a PCA fit: the endpoints are the extremes
+    /// of the pixels projected onto their principal axis. Higher tiers add more candidates,
+    /// and keep whichever has the lowest error after quantizing the endpoints to RGB565:
+    ///
+    /// * [`Quality::Fastest`] uses the PCA fit as-is
+    /// * [`Quality::Normal`] also refits the endpoints to the codes chosen for the PCA fit, by least squares
+    /// * [`Quality::High`] also uses cluster fit, trying every way of splitting the pixels into palette entries
+    ///   in order along the principal axis, and solving for the best endpoints of each
+    /// * [`Quality::Exhaustive`] also tries cluster fit in 3-color mode for opaque blocks,
+    ///   which can represent some blocks better despite having one less color
+    pub quality: Quality,
+
+    /// Pixels with alpha below this are encoded as transparent, using the 3-color mode.
+    /// 0 disables transparency, so every block uses the 4-color mode
+    pub alpha_threshold: u8,
+}
+
+impl Default for BC1Options {
+    fn default() -> Self {
+        Self {
+            quality: Quality::default(),
+            alpha_threshold: 128,
+        }
+    }
+}
+
+impl CodecBlock for BC1Block {
+    type Options = BC1Options;
+
+    /// Decode the block to RGBA pixels. Transparent pixels in 3-color mode decode as transparent black
+    fn decode(&self) -> Pixels {
+        self.decode_pixels()
+    }
+
+    fn decode_with(&self, interpolation: Interpolation) -> Pixels {
+        self.decode_pixels_with(interpolation)
+    }
+
+    fn encode(pixels: &Pixels, options: &Self::Options) -> Self {
+        Self::encode_pixels(pixels, options)
+    }
+
+    fn with_quality(options: &Self::Options, quality: Quality) -> Self::Options {
+        BC1Options {
+            quality,
+            ..*options
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_roundtrip() {
+        // distinct 565 endpoints, and codes 0, 1, 2, 3 across the first row
+        let bytes = [0x1F, 0x00, 0x00, 0xF8, 0b11_10_01_00, 0xFF, 0x00, 0x55];
+        assert_eq!(bytes.len(), BC1Block::SIZE);
+        let block = BC1Block::from_bytes(&bytes);
+        assert_eq!(
+            block.codes.elements().count(),
+            BC1Block::WIDTH * BC1Block::HEIGHT
+        );
+        assert_eq!(block.colors[0].to_565(), 0x001F);
+        assert_eq!(block.colors[1].to_565(), 0xF800);
+        assert_eq!(
+            block.codes.elements().take(8).copied().collect::<Vec<_>>(),
+            [0, 1, 2, 3, 3, 3, 3, 3]
+        );
+        assert_eq!(block.to_bytes(), bytes);
+    }
+}
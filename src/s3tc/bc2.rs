@@ -0,0 +1,145 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use vector_victor::Matrix;
+
+use crate::bitstream::{BitReader, BitWriter};
+use crate::blocktexture::{Block, CodecBlock, Pixels};
+use crate::format::{Interpolation, Quality};
+use crate::s3tc::bc1::{BC1Block, BC1Options};
+
+/// A single 4x4 BC2 block, with explicit 4-bit alpha for each pixel followed by a BC1 color block
+pub struct BC2Block {
+    alpha: Matrix<u8, 4, 4>,
+    color: BC1Block,
+}
+
+impl BC2Block {
+    /// Get the 4-bit alpha value of each pixel
+    pub fn alpha(&self) -> Matrix<u8, 4, 4> {
+        self.alpha
+    }
+
+    /// Decode the alpha of each pixel to 8 bits
+    pub fn decode_alpha(&self) -> Matrix<u8, 4, 4> {
+        self.alpha.elements().map(|a| a * 17).collect()
+    }
+}
+
+impl Block for BC2Block {
+    type Bytes = [u8; 16];
+    const SIZE: usize = 16;
+
+    fn to_bytes(&self) -> Self::Bytes {
+        let mut bytes: Self::Bytes = [0; 16];
+
+        // a 4-bit alpha value per pixel in row-major order, starting in the low nibble
+        BitWriter::new(&mut bytes[0..8])
+            .write_all(self.alpha.elements().copied(), 4)
+            .expect("BC2 alpha values are 4 bits");
+        bytes[8..16].copy_from_slice(&self.color.to_bytes()[..]); // BC1 RGB
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &Self::Bytes) -> Self {
+        // the alpha half of a block is always exactly 64 bits, so this read can't fail
+        let alpha = BitReader::new(&bytes[0..8])
+            .read_array::<u8, 16>(4)
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        Self {
+            alpha,
+            color: BC1Block::from_bytes(&<[u8; 8]>::try_from(&bytes[8..16]).unwrap()), // BC1 RGB
+        }
+    }
+}
+
+/// Options for encoding [`BC2Block`]s
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BC2Options {
+    /// How hard to search for color endpoints. See [`BC1Options::quality`]
+    pub quality: Quality,
+}
+
+impl CodecBlock for BC2Block {
+    type Options = BC2Options;
+
+    /// Decode the block to RGBA pixels. Like BC3, the color block is always in 4-color mode,
+    /// whatever the order of its endpoints
+    fn decode(&self) -> Pixels {
+        self.decode_with(Interpolation::default())
+    }
+
+    fn decode_with(&self, interpolation: Interpolation) -> Pixels {
+        let mut pixels = self.color.decode_four_color(interpolation);
+        for (p, a) in pixels.elements_mut().zip(self.decode_alpha().elements()) {
+            p[3] = *a;
+        }
+        pixels
+    }
+
+    fn encode(pixels: &Pixels, options: &Self::Options) -> Self {
+        let color_options = BC1Options {
+            quality: options.quality,
+            ..Default::default()
+        };
+        Self {
+            // round to the nearest multiple of 17
+            alpha: pixels
+                .elements()
+                .map(|p| ((p[3] as u16 + 8) / 17) as u8)
+                .collect(),
+            color: BC1Block::encode_color(pixels, &color_options, false),
+        }
+    }
+
+    fn with_quality(_options: &Self::Options, quality: Quality) -> Self::Options {
+        BC2Options { quality }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+
+    use super::*;
+
+    #[test]
+    fn layout() {
+        // alpha increasing by 1 every pixel, then white and black color endpoints in
+        // 3-color order, which BC2 still decodes as 4-color
+        let mut bytes = [0u8; 16];
+        bytes[0..8].copy_from_slice(&[0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE]);
+        bytes[8..12].copy_from_slice(&[0x00, 0x00, 0xFF, 0xFF]);
+        bytes[12] = 0b11_10_01_00;
+        let block = BC2Block::from_bytes(&bytes);
+        assert_eq!(block.to_bytes(), bytes);
+        assert_eq!(block.alpha().elements().copied().max(), Some(15));
+
+        let pixels = block.decode();
+        assert_eq!(pixels[(0, 0)], Color::vec([0, 0, 0, 0]));
+        assert_eq!(pixels[(0, 1)], Color::vec([255, 255, 255, 17]));
+        assert_eq!(pixels[(0, 3)], Color::vec([170, 170, 170, 51]));
+        assert_eq!(pixels[(3, 3)], Color::vec([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn codec() {
+        // 4 evenly spaced colors, one per row, which fit the 4-color palette,
+        // and 16 alpha values, which are all multiples of 17
+        let pixels: Pixels = (0..16u8)
+            .map(|i| Color::vec([i / 4 * 80, 255 - i / 4 * 40, 40, i * 17]))
+            .collect();
+        let decoded = BC2Block::encode(&pixels, &BC2Options::default()).decode();
+        for (d, p) in decoded.elements().zip(pixels.elements()) {
+            assert_eq!(d[3], p[3]);
+            for c in 0..3 {
+                assert!(d[c].abs_diff(p[c]) <= 19, "{d:?} != {p:?}");
+            }
+        }
+    }
+}
@@ -2,10 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::blocktexture::Block;
-use crate::s3tc::bc1::BC1Block;
+use crate::blocktexture::{Block, CodecBlock, Pixels};
+use crate::format::{Interpolation, Quality};
+use crate::s3tc::bc1::{BC1Block, BC1Options};
 use crate::s3tc::bc4::BC4Block;
 
+/// A single 4x4 BC3 block, with a BC1 color block and a BC4 alpha block
 pub struct BC3Block(BC1Block, BC4Block);
 
 impl Block for BC3Block {
@@ -15,16 +17,98 @@ impl Block for BC3Block {
     //noinspection DuplicatedCode
     fn to_bytes(&self) -> Self::Bytes {
         let mut bytes: Self::Bytes = [0; 16];
-        bytes[0..8].copy_from_slice(&self.0.to_bytes()[..]); // BC1 RGB
-        bytes[8..16].copy_from_slice(&self.1.to_bytes()[..]); // BC4 Alpha
+        bytes[0..8].copy_from_slice(&self.1.to_bytes()[..]); // BC4 Alpha
+        bytes[8..16].copy_from_slice(&self.0.to_bytes()[..]); // BC1 RGB
 
         bytes
     }
 
     fn from_bytes(bytes: &Self::Bytes) -> Self {
         Self(
-            BC1Block::from_bytes(&<[u8; 8]>::try_from(&bytes[0..8]).unwrap()), // BC1 RGB
-            BC4Block::from_bytes(&<[u8; 8]>::try_from(&bytes[8..16]).unwrap()), // BC4 Alpha
+            BC1Block::from_bytes(&<[u8; 8]>::try_from(&bytes[8..16]).unwrap()), // BC1 RGB
+            BC4Block::from_bytes(&<[u8; 8]>::try_from(&bytes[0..8]).unwrap()),  // BC4 Alpha
         )
     }
 }
+
+/// Options for encoding [`BC3Block`]s
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BC3Options {
+    /// How hard to search for color endpoints. See [`BC1Options::quality`]
+    pub quality: Quality,
+}
+
+impl CodecBlock for BC3Block {
+    type Options = BC3Options;
+
+    /// Decode the block to RGBA pixels. The color block is always in 4-color mode,
+    /// whatever the order of its endpoints
+    fn decode(&self) -> Pixels {
+        self.decode_with(Interpolation::default())
+    }
+
+    fn decode_with(&self, interpolation: Interpolation) -> Pixels {
+        let mut pixels = self.0.decode_four_color(interpolation);
+        for (p, a) in pixels
+            .elements_mut()
+            .zip(self.1.decode_channel().elements())
+        {
+            p[3] = *a;
+        }
+        pixels
+    }
+
+    fn encode(pixels: &Pixels, options: &Self::Options) -> Self {
+        let color_options = BC1Options {
+            quality: options.quality,
+            ..Default::default()
+        };
+        Self(
+            BC1Block::encode_color(pixels, &color_options, false),
+            BC4Block::encode_channel(&pixels.elements().map(|p| p[3]).collect()),
+        )
+    }
+
+    fn with_quality(_options: &Self::Options, quality: Quality) -> Self::Options {
+        BC3Options { quality }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+
+    use super::*;
+
+    #[test]
+    fn layout() {
+        // alpha endpoints 255 and 0 with every code 0, then white and black color endpoints in
+        // 3-color order, which BC3 still decodes as 4-color
+        let mut bytes = [0u8; 16];
+        bytes[0] = 255;
+        bytes[8..12].copy_from_slice(&[0x00, 0x00, 0xFF, 0xFF]);
+        bytes[12] = 0b11_10_01_00;
+        let block = BC3Block::from_bytes(&bytes);
+        assert_eq!(block.to_bytes(), bytes);
+
+        let pixels = block.decode();
+        assert_eq!(pixels[(0, 0)], Color::vec([0, 0, 0, 255]));
+        assert_eq!(pixels[(0, 1)], Color::vec([255, 255, 255, 255]));
+        assert_eq!(pixels[(0, 3)], Color::vec([170, 170, 170, 255]));
+    }
+
+    #[test]
+    fn codec() {
+        // 4 evenly spaced colors, one per row, which fit the 4-color palette,
+        // and 16 alpha values, which are within half a step of the 8-value alpha palette
+        let pixels: Pixels = (0..16u8)
+            .map(|i| Color::vec([i / 4 * 80, 255 - i / 4 * 40, 40, i * 17]))
+            .collect();
+        let decoded = BC3Block::encode(&pixels, &BC3Options::default()).decode();
+        for (d, p) in decoded.elements().zip(pixels.elements()) {
+            for c in 0..4 {
+                assert!(d[c].abs_diff(p[c]) <= 19, "{d:?} != {p:?}");
+            }
+        }
+    }
+}
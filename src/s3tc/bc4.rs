@@ -2,60 +2,52 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::blocktexture::Block;
-use crate::pack::{Pack, Unpack};
-use bitvec::prelude::*;
-use std::iter::zip;
+use crate::bitstream::{BitReader, BitWriter};
+use crate::blocktexture::{Block, CodecBlock, Pixels};
+use crate::color::Color;
+use itertools::Itertools;
 use vector_victor::Matrix;
 
-pub struct BC4Block {
+/// A single 4x4 BC4 block, with two endpoint values and a 3-bit code per pixel.
+///
+/// `SIGNED` blocks (BC4 SNORM) store their endpoints as signed bytes, covering -127 to 127.
+/// They are decoded to and encoded from unsigned bytes remapped so that -1 is 0, 0 is 128, and 1 is 255
+pub struct BC4Block<const SIGNED: bool = false> {
     endpoints: [u8; 2],
     codes: Matrix<u8, 4, 4>,
 }
 
-impl Block for BC4Block {
+/// A signed (SNORM) [`BC4Block`]
+pub type SignedBC4Block = BC4Block<true>;
+
+impl<const SIGNED: bool> Block for BC4Block<SIGNED> {
     type Bytes = [u8; 8];
     const SIZE: usize = 8;
 
     fn to_bytes(&self) -> Self::Bytes {
         let mut bytes: Self::Bytes = [0; 8];
-        let bits = bytes.view_bits_mut::<Msb0>();
-
-        // store endpoints
-        bits[0..8].store_le(self.endpoints[0]);
-        bits[8..16].store_le(self.endpoints[1]);
+        let mut writer = BitWriter::new(&mut bytes);
 
-        // store codes into packed number
-        // BC4 code packing is annoying because some of the 3-bit values cross byte-boundaries,
-        // but those bytes are stored little-endian 🙄
-        let mut codes_packed: u64 = 0;
-        let codes_bits = codes_packed.view_bits_mut::<Msb0>();
-        zip(self.codes.rows(), codes_bits.chunks_mut(12))
-            // pack each row of 3-bit values into reversed chunks
-            .for_each(|(src, dst)| dst.rchunks_mut(3).pack_be(src));
-
-        // store packed codes
-        bits[16..].store_le(codes_packed);
+        // endpoints, then a 3-bit code per pixel in row-major order.
+        // Some codes cross byte boundaries, which the bitstream handles for us
+        writer
+            .write_all(self.endpoints, 8)
+            .and_then(|_| writer.write_all(self.codes.elements().copied(), 3))
+            .expect("BC4 codes are 3 bits");
 
         bytes
     }
 
     fn from_bytes(bytes: &Self::Bytes) -> Self {
-        let bits = bytes.as_bits::<Msb0>();
-
-        // load endpoints
-        let endpoint0: u8 = bits[0..8].load_le();
-        let endpoint1: u8 = bits[8..16].load_le();
-
-        // load codes
-        let codes_packed: u64 = bits[16..].load_le();
-        let codes_bits = codes_packed.view_bits::<Msb0>();
-        let codes = Matrix::<u8, 4, 4>::from_rows(
-            // reverse each row of 3-bit numbers and collect them to a Vector
-            codes_bits
-                .chunks(12)
-                .map(|r| r.rchunks(3).unpack_le().collect()),
-        );
+        let mut reader = BitReader::new(bytes);
+
+        // a block is always exactly 64 bits, so none of these reads can fail
+        let [endpoint0, endpoint1] = reader.read_array(8).unwrap();
+        let codes = reader
+            .read_array::<u8, 16>(3)
+            .unwrap()
+            .into_iter()
+            .collect();
 
         Self {
             endpoints: [endpoint0, endpoint1],
@@ -63,3 +55,224 @@ impl Block for BC4Block {
         }
     }
 }
+
+impl<const SIGNED: bool> BC4Block<SIGNED> {
+    /// The lowest value a block can hold
+    pub const MIN: i32 = if SIGNED { -127 } else { 0 };
+    /// The highest value a block can hold
+    pub const MAX: i32 = if SIGNED { 127 } else { 255 };
+
+    /// Convert an unsigned byte to a value in the block's range
+    pub fn from_unorm(byte: u8) -> i32 {
+        match SIGNED {
+            true => (byte as i32 * 254 + 127) / 255 - 127,
+            false => byte as i32,
+        }
+    }
+
+    /// Convert a value in the block's range to an unsigned byte
+    pub fn to_unorm(value: i32) -> u8 {
+        match SIGNED {
+            true => (((value + 127) * 255 + 127) / 254) as u8,
+            false => value as u8,
+        }
+    }
+
+    /// The 8 values a code can select, in the block's range. If the first endpoint is greater,
+    /// 6 values are interpolated between the endpoints. Otherwise 4 are, and the last two are
+    /// [`Self::MIN`] and [`Self::MAX`]. A signed endpoint of -128 is treated as -127
+    pub fn values(&self) -> [i32; 8] {
+        let [e0, e1] = self.endpoints.map(|e| match SIGNED {
+            true => (e as i8).max(-127) as i32,
+            false => e as i32,
+        });
+        let mut values = [e0, e1, 0, 0, 0, 0, Self::MIN, Self::MAX];
+        let steps = if e0 > e1 { 7 } else { 5 };
+        for i in 1..steps {
+            values[i as usize + 1] = (e0 * (steps - i) + e1 * i + steps / 2).div_euclid(steps);
+        }
+        values
+    }
+
+    /// The 8 values a code can select, as unsigned bytes. See [`Self::values`]
+    pub fn palette(&self) -> [u8; 8] {
+        self.values().map(Self::to_unorm)
+    }
+
+    /// Decode the block to a single channel
+    pub fn decode_channel(&self) -> Matrix<u8, 4, 4> {
+        let palette = self.palette();
+        self.codes
+            .elements()
+            .map(|&c| palette[c as usize])
+            .collect()
+    }
+
+    /// Encode a single channel, using the 8-value mode between its minimum and maximum.
+    /// Blocks containing [`Self::MIN`] or [`Self::MAX`] also try the 6-value mode between
+    /// their other values, and keep whichever has less error.
+    /// Solid blocks use the 6-value mode with equal endpoints, so every code is 0
+    pub fn encode_channel(values: &Matrix<u8, 4, 4>) -> Self {
+        let values: Vec<i32> = values.elements().map(|&v| Self::from_unorm(v)).collect();
+        let (min, max) = values.iter().minmax().into_option().unwrap();
+        let mut best = Self::fit(&values, [*max, *min]);
+
+        if values.iter().any(|&v| v == Self::MIN || v == Self::MAX) {
+            let inner = values.iter().filter(|&&v| v != Self::MIN && v != Self::MAX);
+            // a block of only extremes is still covered, by endpoints equal to one of them
+            let (lo, hi) = inner.minmax().into_option().unwrap_or((min, max));
+            let candidate = Self::fit(&values, [*lo, *hi]);
+            if candidate.1 < best.1 {
+                best = candidate;
+            }
+        }
+        best.0
+    }
+
+    /// Choose the closest code for each value with the given endpoints,
+    /// returning the block and its total squared error
+    fn fit(values: &[i32], endpoints: [i32; 2]) -> (Self, i32) {
+        let block = Self {
+            // truncating keeps the two's complement bits of signed endpoints
+            endpoints: endpoints.map(|e| e as u8),
+            codes: Matrix::default(),
+        };
+        let palette = block.values();
+        let mut error = 0;
+        let codes = values
+            .iter()
+            .map(|&v| {
+                let code = (0..8u8)
+                    .min_by_key(|&c| palette[c as usize].abs_diff(v))
+                    .unwrap();
+                error += (palette[code as usize] - v).pow(2);
+                code
+            })
+            .collect();
+        (Self { codes, ..block }, error)
+    }
+}
+
+/// Options for encoding [`BC4Block`]s
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BC4Options {
+    /// The channel to encode: 0 for red through 3 for alpha
+    pub channel: usize,
+}
+
+impl<const SIGNED: bool> CodecBlock for BC4Block<SIGNED> {
+    type Options = BC4Options;
+
+    /// Decode the block to the red channel
+    fn decode(&self) -> Pixels {
+        self.decode_channel()
+            .elements()
+            .map(|&v| Color::vec([v, 0, 0, u8::MAX]))
+            .collect()
+    }
+
+    fn encode(pixels: &Pixels, options: &Self::Options) -> Self {
+        Self::encode_channel(&pixels.elements().map(|p| p[options.channel]).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_roundtrip() {
+        // codes 0 through 7 in the first 8 pixels, the third of which crosses a byte boundary
+        let bytes = [0xF0, 0x10, 0b10001000, 0b11000110, 0b11111010, 0, 0, 0];
+        let block: BC4Block = BC4Block::from_bytes(&bytes);
+        assert_eq!(block.endpoints, [0xF0, 0x10]);
+        assert_eq!(
+            block.codes.elements().take(8).copied().collect::<Vec<_>>(),
+            (0..8).collect::<Vec<u8>>()
+        );
+        assert_eq!(block.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn palette() {
+        let block = |e0, e1| BC4Block::<false> {
+            endpoints: [e0, e1],
+            codes: Matrix::default(),
+        };
+        assert_eq!(block(70, 0).palette(), [70, 0, 60, 50, 40, 30, 20, 10]);
+        assert_eq!(block(0, 50).palette(), [0, 50, 10, 20, 30, 40, 0, 255]);
+    }
+
+    #[test]
+    fn signed_palette() {
+        let block = |e0: i8, e1: i8| SignedBC4Block {
+            endpoints: [e0 as u8, e1 as u8],
+            codes: Matrix::default(),
+        };
+        // the first endpoint is only greater when compared as signed values
+        assert_eq!(block(35, -35).values(), [35, -35, 25, 15, 5, -5, -15, -25]);
+        assert_eq!(
+            block(-50, 50).values(),
+            [-50, 50, -30, -10, 10, 30, -127, 127]
+        );
+        // -128 is the same as -127
+        assert_eq!(block(-128, 0).values(), block(-127, 0).values());
+        assert_eq!(block(-127, 127).palette()[0..2], [0, 255]);
+
+        // every signed value survives a trip through an unsigned byte
+        for v in -127..=127 {
+            assert_eq!(SignedBC4Block::from_unorm(SignedBC4Block::to_unorm(v)), v);
+        }
+        assert_eq!(SignedBC4Block::from_unorm(128), 0);
+    }
+
+    #[test]
+    fn codec() {
+        let values: Matrix<u8, 4, 4> = (0..16).map(|i| i * 17).collect();
+        let pixels: Pixels = values
+            .elements()
+            .map(|&v| Color::vec([0, v, 0, 255]))
+            .collect();
+
+        let block: BC4Block = BC4Block::encode(&pixels, &BC4Options { channel: 1 });
+        assert_eq!(block.endpoints, [255, 0]);
+
+        // every value is within half a step of the palette
+        let decoded = block.decode();
+        for (p, &v) in decoded.elements().zip(values.elements()) {
+            assert!(p[0].abs_diff(v) <= 19, "{} != {v}", p[0]);
+            assert_eq!(p[3], 255);
+        }
+
+        let solid: BC4Block = BC4Block::encode_channel(&Matrix::fill(99));
+        assert!(solid.decode_channel().elements().all(|&v| v == 99));
+    }
+
+    #[test]
+    fn extremes() {
+        // 0 and 255 are exact in the 6-value mode, leaving its interpolated values for the rest
+        let values: Matrix<u8, 4, 4> = [0, 255, 100, 110, 120, 150]
+            .into_iter()
+            .cycle()
+            .take(16)
+            .collect();
+        let block: BC4Block = BC4Block::encode_channel(&values);
+        assert_eq!(block.endpoints, [100, 150]);
+        assert_eq!(block.decode_channel(), values);
+    }
+
+    #[test]
+    fn signed_codec() {
+        // every other value is negative, which only decodes back correctly if endpoints are compared as signed
+        let values: Matrix<u8, 4, 4> = (0..16).map(|i| (i % 2) * 200 + i + 10).collect();
+        let block = SignedBC4Block::encode_channel(&values);
+        let [e0, e1] = block.endpoints.map(|e| e as i8);
+        assert!(e0 > e1 && e1 < 0, "{e0} {e1}");
+        for (d, v) in block.decode_channel().elements().zip(values.elements()) {
+            assert!(d.abs_diff(*v) <= 19, "{d} != {v}");
+        }
+
+        let solid = SignedBC4Block::encode_channel(&Matrix::fill(0));
+        assert!(solid.decode_channel().elements().all(|&v| v == 0));
+    }
+}
@@ -2,12 +2,18 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::blocktexture::Block;
+use crate::blocktexture::{Block, CodecBlock, Pixels};
+use crate::color::Color;
 use crate::s3tc::bc4::BC4Block;
 
-pub struct BC5Block(BC4Block, BC4Block);
+/// A single 4x4 BC5 block, with two BC4 blocks for the red and green channels.
+/// `SIGNED` blocks (BC5 SNORM) are made of signed BC4 blocks, see [`BC4Block`]
+pub struct BC5Block<const SIGNED: bool = false>(BC4Block<SIGNED>, BC4Block<SIGNED>);
 
-impl Block for BC5Block {
+/// A signed (SNORM) [`BC5Block`]
+pub type SignedBC5Block = BC5Block<true>;
+
+impl<const SIGNED: bool> Block for BC5Block<SIGNED> {
     type Bytes = [u8; 16];
     const SIZE: usize = 16;
 
@@ -27,3 +33,63 @@ impl Block for BC5Block {
         )
     }
 }
+
+/// Options for encoding [`BC5Block`]s
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BC5Options {
+    /// The channels to encode into the first and second BC4 blocks: 0 for red through 3 for alpha.
+    /// Defaults to red and green
+    pub channels: [usize; 2],
+}
+
+impl Default for BC5Options {
+    fn default() -> Self {
+        Self { channels: [0, 1] }
+    }
+}
+
+impl<const SIGNED: bool> CodecBlock for BC5Block<SIGNED> {
+    type Options = BC5Options;
+
+    /// Decode the block to the red and green channels
+    fn decode(&self) -> Pixels {
+        self.0
+            .decode_channel()
+            .elements()
+            .zip(self.1.decode_channel().elements())
+            .map(|(&r, &g)| Color::vec([r, g, 0, u8::MAX]))
+            .collect()
+    }
+
+    fn encode(pixels: &Pixels, options: &Self::Options) -> Self {
+        let [c0, c1] = options.channels;
+        Self(
+            BC4Block::encode_channel(&pixels.elements().map(|p| p[c0]).collect()),
+            BC4Block::encode_channel(&pixels.elements().map(|p| p[c1]).collect()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec() {
+        // a ramp in red and the reverse ramp in alpha, which should end up in green
+        let pixels: Pixels = (0..16u8)
+            .map(|i| Color::vec([i * 17, 77, 0, 255 - i * 17]))
+            .collect();
+        let options = BC5Options { channels: [0, 3] };
+        for decoded in [
+            BC5Block::<false>::encode(&pixels, &options).decode(),
+            SignedBC5Block::encode(&pixels, &options).decode(),
+        ] {
+            for (d, p) in decoded.elements().zip(pixels.elements()) {
+                assert!(d[0].abs_diff(p[0]) <= 19, "{d:?} != {p:?}");
+                assert!(d[1].abs_diff(p[3]) <= 19, "{d:?} != {p:?}");
+                assert_eq!((d[2], d[3]), (0, 255));
+            }
+        }
+    }
+}
@@ -2,7 +2,15 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+/// BC1 (DXT1) blocks
 pub mod bc1;
+/// BC2 (DXT3) blocks
+pub mod bc2;
+/// BC3 (DXT5) blocks
 pub mod bc3;
+/// BC4 (ATI1) blocks
 pub mod bc4;
+/// BC5 (ATI2) blocks
 pub mod bc5;
+/// Color adjustments applied directly to BC1 and BC3 endpoints
+pub mod recolor;
@@ -0,0 +1,263 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Color adjustments applied directly to the endpoints of BC1 and BC3 blocks.
+//!
+//! Every pixel in a block is an interpolation of the block's two endpoint colors, so applying an
+//! affine per-channel transform to the endpoints applies (nearly) the same transform to every pixel,
+//! without decoding or re-encoding the texture. Results differ from adjusting the decoded image
+//! by the rounding of the endpoints to RGB565, and by any clamping of interpolated colors.
+
+use crate::color::{Color, ColorImpl};
+use crate::error::{TextureError, TextureResult};
+use crate::format::Format;
+use crate::texture::{Surface, Texture};
+
+/// An affine transform of the red, green, and blue channels of a color.
+/// Alpha is always left alone.
+///
+/// Channel values are in 8-bit units. Each output channel `c` is
+/// `input[swizzle[c]] * scale[c] + offset[c]`, rounded and clamped to `0..=255`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EndpointTransform {
+    /// The input channel to read for each output channel
+    pub swizzle: [usize; 3],
+    /// The factor to multiply each output channel by
+    pub scale: [f32; 3],
+    /// The value to add to each output channel after scaling
+    pub offset: [f32; 3],
+}
+
+impl Default for EndpointTransform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl EndpointTransform {
+    /// A transform that does not change its input
+    pub fn identity() -> Self {
+        Self {
+            swizzle: [0, 1, 2],
+            scale: [1.0; 3],
+            offset: [0.0; 3],
+        }
+    }
+
+    /// Brighten (or darken, if negative) all channels by `amount`, as a fraction of the full channel range
+    pub fn brightness(amount: f32) -> Self {
+        Self {
+            offset: [amount * 255.0; 3],
+            ..Self::identity()
+        }
+    }
+
+    /// Scale the contrast of all channels by `factor` around the middle of the channel range.
+    /// A factor less than 1 reduces contrast, and a factor greater than 1 increases it
+    pub fn contrast(factor: f32) -> Self {
+        Self {
+            scale: [factor; 3],
+            offset: [127.5 * (1.0 - factor); 3],
+            ..Self::identity()
+        }
+    }
+
+    /// Rearrange the color channels, so that output channel `c` is read from input channel `swizzle[c]`.
+    /// For example, `[2, 1, 0]` swaps red and blue
+    ///
+    /// # Panics
+    ///
+    /// Panics if any channel index is greater than 2
+    pub fn swizzle(swizzle: [usize; 3]) -> Self {
+        assert!(
+            swizzle.iter().all(|&c| c < 3),
+            "Invalid channel index in swizzle {swizzle:?}"
+        );
+        Self {
+            swizzle,
+            ..Self::identity()
+        }
+    }
+
+    /// Compose two transforms into one that applies `self` and then `next`.
+    /// Unlike applying them one at a time, intermediate values are not clamped
+    pub fn then(self, next: Self) -> Self {
+        let mut result = Self::identity();
+        for c in 0..3 {
+            let src = next.swizzle[c];
+            result.swizzle[c] = self.swizzle[src];
+            result.scale[c] = next.scale[c] * self.scale[src];
+            result.offset[c] = next.scale[c] * self.offset[src] + next.offset[c];
+        }
+        result
+    }
+
+    /// Apply this transform to a single color
+    pub fn apply(&self, color: Color) -> Color {
+        let mut result = color;
+        for c in 0..3 {
+            let value = color[self.swizzle[c]] as f32 * self.scale[c] + self.offset[c];
+            result[c] = value.round().clamp(0.0, 255.0) as u8;
+        }
+        result
+    }
+}
+
+/// Recolor a single 8-byte BC1 color block in place.
+///
+/// Which of the 4-color and 3-color-plus-transparent modes a block uses depends on the order of its
+/// endpoints, so if the transform reorders them, the endpoints are swapped back and the indices
+/// are remapped to match. When `always_four_color` is set (the color half of a BC3 block)
+/// the endpoint order does not matter and the indices are never modified
+fn recolor_color_block(block: &mut [u8], transform: &EndpointTransform, always_four_color: bool) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let mut n0 = transform.apply(Color::from_565(c0)).to_565();
+    let mut n1 = transform.apply(Color::from_565(c1)).to_565();
+    let mut indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    if !always_four_color {
+        if c0 > c1 {
+            // 4-color mode: 0 <-> 1 and 2 <-> 3 when swapping
+            if n0 < n1 {
+                (n0, n1) = (n1, n0);
+                indices ^= 0x5555_5555;
+            } else if n0 == n1 {
+                // equal endpoints would switch to 3-color mode and turn index 3 transparent,
+                // but every color in the palette is the same anyway
+                indices = 0;
+            }
+        } else if n0 > n1 {
+            // 3-color mode: 0 <-> 1 when swapping, and 2 (midpoint) and 3 (transparent) stay put
+            (n0, n1) = (n1, n0);
+            indices ^= !(indices >> 1) & 0x5555_5555;
+        }
+    }
+
+    block[0..2].copy_from_slice(&n0.to_le_bytes());
+    block[2..4].copy_from_slice(&n1.to_le_bytes());
+    block[4..8].copy_from_slice(&indices.to_le_bytes());
+}
+
+/// Recolor a buffer of BC1 blocks in place. See [`EndpointTransform`]
+pub fn recolor_bc1(buffer: &mut [u8], transform: &EndpointTransform) {
+    buffer
+        .chunks_exact_mut(8)
+        .for_each(|block| recolor_color_block(block, transform, false))
+}
+
+/// Recolor a buffer of BC3 blocks in place. The alpha half of each block is not modified.
+/// See [`EndpointTransform`]
+pub fn recolor_bc3(buffer: &mut [u8], transform: &EndpointTransform) {
+    buffer
+        .chunks_exact_mut(16)
+        // BC3 blocks store their alpha block first, followed by the color block
+        .for_each(|block| recolor_color_block(&mut block[8..16], transform, true))
+}
+
+/// Recolor every surface of a BC1 or BC3 texture.
+/// Returns [`TextureError::Format`] for any other format
+pub fn recolor(texture: &Texture, transform: &EndpointTransform) -> TextureResult<Texture> {
+    let recolor_buffer = match texture.format {
+        Format::BC1 { .. } => recolor_bc1,
+        Format::BC3 { .. } => recolor_bc3,
+        format => {
+            return Err(TextureError::Format(format!(
+                "Endpoint recoloring is only supported for BC1 and BC3 textures, not {format:?}"
+            )))
+        }
+    };
+
+    let surfaces = texture.surfaces().try_map(|s| -> TextureResult<Surface> {
+        let mut buffer = s.buffer.to_vec();
+        recolor_buffer(&mut buffer, transform);
+        Ok(Surface::new(s.dimensions, buffer))
+    })?;
+    Texture::new(texture.format, surfaces)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use anyhow::Result;
+
+    use crate::container::ContainerHeader;
+    use crate::dds::DDSHeader;
+
+    use super::*;
+
+    fn read_bc1() -> Result<Texture> {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/images/dds/peppers16 bc1.dds"
+        );
+        Ok(DDSHeader::read_texture(&mut File::open(path)?)?)
+    }
+
+    fn buffers(texture: &Texture) -> Vec<Vec<u8>> {
+        texture
+            .slice()
+            .iter()
+            .map(|s| s.surface.buffer.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn identity_is_lossless() -> Result<()> {
+        let texture = read_bc1()?;
+        let recolored = recolor(&texture, &EndpointTransform::identity())?;
+        assert_eq!(buffers(&recolored), buffers(&texture));
+        Ok(())
+    }
+
+    #[test]
+    fn swizzle_roundtrip() -> Result<()> {
+        let texture = read_bc1()?;
+        let swap = EndpointTransform::swizzle([2, 1, 0]);
+        let swapped = recolor(&texture, &swap)?;
+        assert_ne!(buffers(&swapped), buffers(&texture));
+        assert_eq!(buffers(&recolor(&swapped, &swap)?), buffers(&texture));
+        Ok(())
+    }
+
+    #[test]
+    fn mode_preserved() {
+        let red = Color::vec([248, 0, 0, 255]).to_565();
+        let blue = Color::vec([0, 0, 248, 255]).to_565();
+        let swap = EndpointTransform::swizzle([2, 1, 0]);
+
+        // 4-color block with indices 0, 1, 2, 3 in the first row
+        let mut block = [0u8; 8];
+        block[0..2].copy_from_slice(&red.to_le_bytes());
+        block[2..4].copy_from_slice(&blue.to_le_bytes());
+        block[4] = 0b11_10_01_00;
+        recolor_bc1(&mut block, &swap);
+        assert!(
+            u16::from_le_bytes([block[0], block[1]]) > u16::from_le_bytes([block[2], block[3]])
+        );
+        assert_eq!(block[4], 0b10_11_00_01);
+
+        // 3-color block with indices 0, 1, 2, 3 in the first row
+        let mut block = [0u8; 8];
+        block[0..2].copy_from_slice(&blue.to_le_bytes());
+        block[2..4].copy_from_slice(&red.to_le_bytes());
+        block[4] = 0b11_10_01_00;
+        recolor_bc1(&mut block, &swap);
+        assert!(
+            u16::from_le_bytes([block[0], block[1]]) <= u16::from_le_bytes([block[2], block[3]])
+        );
+        assert_eq!(block[4], 0b11_10_00_01);
+    }
+
+    #[test]
+    fn compose() {
+        // values chosen so that there is no rounding or clamping between steps
+        let color = Color::vec([100, 120, 140, 50]);
+        let a = EndpointTransform::contrast(0.6);
+        let b = EndpointTransform::swizzle([1, 2, 0]).then(EndpointTransform::brightness(0.2));
+        assert_eq!(a.then(b).apply(color), b.apply(a.apply(color)));
+        assert_eq!(a.apply(color)[3], 50, "alpha is untouched");
+    }
+}
@@ -0,0 +1,119 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::iter::FusedIterator;
+use std::mem;
+use std::vec;
+
+use crate::shape::{CubeFace, TextureIndex, TextureIterResult, TextureShape};
+
+/// Iterator over one structure of a texture, along with the index of each item.
+/// Returned by [`TextureShape::iter_layers`], [`TextureShape::iter_faces`], and [`TextureShape::iter_mips`].
+///
+/// If the texture does not have the iterated structure, this yields a single item `(None, texture.clone())`
+pub struct ShapeIter<'a, T, I> {
+    texture: &'a T,
+    indices: Option<vec::IntoIter<I>>,
+    pad: bool,
+    index: fn(I) -> TextureIndex,
+}
+
+/// Iterator over the array layers of a texture
+pub type LayerIter<'a, T> = ShapeIter<'a, T, usize>;
+
+/// Iterator over the cubemap faces of a texture
+pub type FaceIter<'a, T> = ShapeIter<'a, T, CubeFace>;
+
+/// Iterator over the mips of a texture
+pub type MipIter<'a, T> = ShapeIter<'a, T, usize>;
+
+impl<'a, T, I> ShapeIter<'a, T, I> {
+    /// Create a new iterator over `indices`, which should be [`None`] if the structure is not present
+    pub(super) fn new(
+        texture: &'a T,
+        indices: Option<Vec<I>>,
+        index: fn(I) -> TextureIndex,
+    ) -> Self {
+        Self {
+            texture,
+            indices: indices.map(Vec::into_iter),
+            pad: true,
+            index,
+        }
+    }
+
+    fn item(&self, index: Option<I>) -> (Option<I>, T)
+    where
+        T: TextureShape,
+        I: Copy,
+    {
+        let texture = match index {
+            Some(i) => self
+                .texture
+                .get((self.index)(i))
+                .expect("Texture is missing an index it reported"),
+            None => self.texture.clone(),
+        };
+        (index, texture)
+    }
+}
+
+impl<T: TextureShape, I: Copy> Iterator for ShapeIter<'_, T, I> {
+    type Item = (Option<I>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = match &mut self.indices {
+            Some(indices) => Some(indices.next()?),
+            None => mem::take(&mut self.pad).then_some(None)?,
+        };
+        Some(self.item(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match &self.indices {
+            Some(indices) => indices.len(),
+            None => self.pad as usize,
+        };
+        (len, Some(len))
+    }
+}
+
+impl<T: TextureShape, I: Copy> DoubleEndedIterator for ShapeIter<'_, T, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let index = match &mut self.indices {
+            Some(indices) => Some(indices.next_back()?),
+            None => mem::take(&mut self.pad).then_some(None)?,
+        };
+        Some(self.item(index))
+    }
+}
+
+impl<T: TextureShape, I: Copy> ExactSizeIterator for ShapeIter<'_, T, I> {}
+
+impl<T: TextureShape, I: Copy> FusedIterator for ShapeIter<'_, T, I> {}
+
+/// Iterator over every surface in a texture. Returned by [`TextureShape::iter`]
+pub struct SurfaceIter<S>(pub(super) vec::IntoIter<TextureIterResult<S>>);
+
+impl<S> Iterator for SurfaceIter<S> {
+    type Item = TextureIterResult<S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<S> DoubleEndedIterator for SurfaceIter<S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back()
+    }
+}
+
+impl<S> ExactSizeIterator for SurfaceIter<S> {}
+
+impl<S> FusedIterator for SurfaceIter<S> {}
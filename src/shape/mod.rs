@@ -0,0 +1,1129 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use itertools::Itertools;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use strum::{Display, VariantArray};
+use thiserror::Error;
+
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::shape::ShapeError::*;
+use crate::util::AsSlice;
+
+pub use iter::{FaceIter, LayerIter, MipIter, ShapeIter, SurfaceIter};
+pub use slice::{SliceFaceIter, SliceIndexIter, SliceIter, SliceSurfaceIter, TextureSlice};
+
+mod iter;
+mod slice;
+
+#[cfg(test)]
+mod tests;
+
+/// Errors that can occur when constructing or traversing a [`TextureShape`]
+#[derive(Debug, Error)]
+pub enum ShapeError {
+    /// Textures being combined do not all share the named property
+    #[error("Non-uniform {0} in provided textures")]
+    NonUniform(&'static str),
+
+    /// Textures being combined already contain the structure being formed
+    #[error("Tried to form {0} out of textures that already have {0}s")]
+    Nested(&'static str),
+
+    /// Mip dimensions do not halve down to 1x1
+    #[error("Textures do not have dimensions that form a valid mipchain")]
+    InvalidMipChain,
+
+    /// The same cubemap face was provided more than once
+    #[error("Multiple textures provided for the same cubemap face")]
+    DuplicateFaces,
+
+    /// A structure was formed from no textures
+    #[error("{0} cannot be empty")]
+    Empty(&'static str),
+
+    /// A shape tree violates the [`TextureShape`] guarantees
+    #[error("Malformed texture shape: {0}")]
+    Malformed(&'static str),
+
+    /// A list of layer indices is not a permutation of the texture's layers
+    #[error("Invalid layer permutation: {0}")]
+    InvalidPermutation(&'static str),
+
+    /// An index is out of range, or refers to a structure the texture does not have
+    #[error("Invalid index: {0}")]
+    InvalidIndex(&'static str),
+}
+
+/// Result type for shape operations
+pub type ShapeResult<T = ()> = Result<T, ShapeError>;
+
+/// A violated invariant found by one of the `try_from_*_all` constructors, such as
+/// [`TextureShapeNode::try_from_mips_all`], along with the child it was found at
+#[derive(Debug, Error)]
+pub struct ShapeViolation {
+    /// The mip, face, or layer the violation was found at,
+    /// or [`None`] if it concerns the whole structure, like an empty or truncated mipchain
+    pub index: Option<TextureIndex>,
+    /// The violated invariant
+    #[source]
+    pub error: ShapeError,
+}
+
+impl std::fmt::Display for ShapeViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(TextureIndex::Mip(m)) => write!(f, "mip {m}: {}", self.error),
+            Some(TextureIndex::Face(face)) => write!(f, "face {face:?}: {}", self.error),
+            Some(TextureIndex::Layer(l)) => write!(f, "layer {l}: {}", self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+/// Every violated invariant found by one of the `try_from_*_all` constructors.
+/// Always contains at least one violation
+#[derive(Debug, Error)]
+pub struct ShapeErrors {
+    /// The violations, grouped by the invariant violated and then in child order
+    pub violations: Vec<ShapeViolation>,
+}
+
+impl std::fmt::Display for ShapeErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.violations.iter().join("; "))
+    }
+}
+
+impl ShapeErrors {
+    /// Return `Ok(value)` if `violations` is empty, otherwise an error containing them
+    pub(crate) fn check<T>(violations: Vec<ShapeViolation>, value: T) -> Result<T, Self> {
+        match violations.is_empty() {
+            true => Ok(value),
+            false => Err(Self { violations }),
+        }
+    }
+}
+
+/// The face index of one face of a cubemap
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, PartialOrd, Ord, VariantArray)]
+#[repr(usize)]
+pub enum CubeFace {
+    /// The face facing +X
+    #[default]
+    PositiveX,
+    /// The face facing -X
+    NegativeX,
+    /// The face facing +Y
+    PositiveY,
+    /// The face facing -Y
+    NegativeY,
+    /// The face facing +Z
+    PositiveZ,
+    /// The face facing -Z
+    NegativeZ,
+}
+
+/// An index into one structure of a [`TextureShape`]
+#[derive(Copy, Clone, Debug, Display)]
+pub enum TextureIndex {
+    /// A cubemap face
+    Face(CubeFace),
+    /// A mip level, where 0 is the largest
+    Mip(usize),
+    /// An array layer
+    Layer(usize),
+}
+
+impl TextureIndex {
+    /// Return the next index
+    ///
+    /// For mips and faces, this is just the index + 1.
+    ///
+    /// For faces, this is the next face in the `CubemapFace` enum, wrapping around to `PositiveX`
+    /// when it reaches the end
+    fn next(&self) -> TextureIndex {
+        match self {
+            TextureIndex::Face(f) => {
+                let mut face_index = *f as usize;
+                face_index += 1;
+                face_index %= CubeFace::VARIANTS.len();
+
+                TextureIndex::Face(CubeFace::VARIANTS[face_index])
+            }
+            TextureIndex::Mip(m) => TextureIndex::Mip(m + 1),
+            TextureIndex::Layer(l) => TextureIndex::Layer(l + 1),
+        }
+    }
+}
+
+/// One surface yielded by [`TextureShape::iter`], along with its position in the texture
+#[derive(Clone, Debug)]
+pub struct TextureIterResult<S> {
+    /// The array layer of the surface, if the texture is an array
+    pub layer: Option<usize>,
+    /// The cubemap face of the surface, if the texture is a cubemap
+    pub face: Option<CubeFace>,
+    /// The mip level of the surface, if the texture has mipmaps
+    pub mip: Option<usize>,
+    /// The surface itself
+    pub surface: S,
+}
+
+/// A trait for a shaped texture, allowing slicing by face, layer, or mip.
+/// A texture is made up of multiple surfaces,
+/// and can contain any combination of mipmaps, cubemaps, or array structures.
+///
+/// A TextureShape has several guarantees:
+/// * A TextureShape contains at least one surface
+/// * a TextureShape only contains exactly zero or one of a mipmap structure, a cubemap structure,
+/// or an array structure. It is impossible to have an array of arrays or a cube of cubes.
+/// * all the surfaces with the same mip have matching dimensions
+/// * all the surfaces with mip\[i+1] have dimensions half that of mip\[i]
+///
+/// TextureShape is implemented with [`TextureShapeNode`],  made up of a tree structure.
+/// All other types implementing this trait within this crate wrap that type.
+pub trait TextureShape: Clone + Dimensioned {
+    /// The type of the innermost surfaces
+    type Surface;
+
+    /// Get a texture made of all the surfaces that match the passed index. If there are no matching
+    /// surfaces, or the indexed structure is not present in the texture, this will return [`None`]
+    fn get(&self, index: TextureIndex) -> Option<Self>;
+
+    /// Get all array layers matching the given layer index
+    /// If `self` does not contain an array structure, or no layers match the index, this will return [`None`]
+    fn get_layer(&self, index: usize) -> Option<Self> {
+        self.get(TextureIndex::Layer(index))
+    }
+
+    /// Get the cubemap face matching the given face index.
+    /// If `self` does not contain a cube structure, or now faces match the index, this will return [`None`]
+    fn get_face(&self, index: CubeFace) -> Option<Self> {
+        self.get(TextureIndex::Face(index))
+    }
+
+    /// Get all mips matching the given mip index
+    /// If `self` does not contain a mip structure, or no mips match the index, this will return [`None`]
+    fn get_mip(&self, index: usize) -> Option<Self> {
+        self.get(TextureIndex::Mip(index))
+    }
+
+    /// Try to create a new texture from an iterator of textures that represents a mipmap.
+    /// Returns an error if any of the following are true:
+    /// * iter contains no textures
+    /// * any of the provided textures already has a mipmap
+    /// * the provided textures do not have uniform faces or layers
+    /// * the provided textures do not have dimensions matching a mipchain,
+    /// where each mip has half the dimensions of the last. See [`Dimensions::mips`]
+    fn try_from_mips<I: IntoIterator<Item = Self>>(iter: I) -> ShapeResult<Self>;
+
+    /// Try to create a new texture from an iterator of textures that represents a cubemap.
+    /// Returns an error if any of the following are true:
+    /// * iter contains no textures
+    /// * any of the provided textures already has a cubemap
+    /// * the provided textures do not have uniform mips, layers, or dimensions
+    /// * multiple textures are provided for the same cube face
+    fn try_from_faces<I: IntoIterator<Item = (CubeFace, Self)>>(iter: I) -> ShapeResult<Self>;
+
+    /// Try to create a new texture from an iterator of textures that represents an array
+    /// Returns an error if any of the following are true:
+    /// * iter contains no textures
+    /// * any of the provided textures already has an array
+    /// * the provided textures do not have uniform faces, mips, or dimensions
+    fn try_from_layers<I: IntoIterator<Item = Self>>(iter: I) -> ShapeResult<Self>;
+
+    /// Get the number of mips in the texture
+    fn mips(&self) -> Option<usize>;
+
+    /// Get the number of layers in the texture
+    fn layers(&self) -> Option<usize>;
+
+    /// Get a Vec of the cubemap faces in the texture
+    fn faces(&self) -> Option<Vec<CubeFace>>;
+
+    /// Returns an optional iterator over this texture's layers. if `self` does not contain an
+    /// array structrue this returns [`None`]
+    fn try_iter_layers(&self) -> Option<impl Iterator<Item = Self>> {
+        Some((0..self.layers()?).map(|l| self.get_layer(l).unwrap()))
+    }
+
+    /// Returns an optional iterator over this texture's faces. If `self` does not contain
+    /// a cubemap structure this returns [`None`]
+    fn try_iter_faces(&self) -> Option<impl Iterator<Item = (CubeFace, Self)>> {
+        Some(
+            self.faces()?
+                .into_iter()
+                .map(|f| (f, self.get_face(f).unwrap())),
+        )
+    }
+
+    /// Returns an iterator over this texture's mips, if a mipmap structure exists
+    fn try_iter_mips(&self) -> Option<impl Iterator<Item = Self>> {
+        Some((0..self.mips()?).map(|m| self.get_mip(m).unwrap()))
+    }
+
+    /// Iterate over the layers of the texture.
+    /// If this texture has an array structure, returns each layer along with its index.
+    /// Otherwise, this iterator returns a single item `(None, self.clone())`
+    fn iter_layers(&self) -> LayerIter<'_, Self> {
+        ShapeIter::new(
+            self,
+            self.layers().map(|l| (0..l).collect()),
+            TextureIndex::Layer,
+        )
+    }
+
+    /// Iterate over the cubemap faces of the texture.
+    /// If this texture has a cubemap structure, returns each face along with its index.
+    /// Otherwise, this iterator returns a single item `(None, self.clone())`
+    fn iter_faces(&self) -> FaceIter<'_, Self> {
+        ShapeIter::new(self, self.faces(), TextureIndex::Face)
+    }
+
+    /// Iterate over the mips of the texture.
+    /// If this texture has a mipmap structure, returns each mip along with its index.
+    /// Otherwise, this iterator returns a single item `(None, self.clone())`
+    fn iter_mips(&self) -> MipIter<'_, Self> {
+        ShapeIter::new(
+            self,
+            self.mips().map(|m| (0..m).collect()),
+            TextureIndex::Mip,
+        )
+    }
+
+    /// Iterate over all the surfaces in the texture, returning the layer, face, and mip index for
+    /// each one if present.
+    /// Panics if the texture's structure is malformed. See [`Self::try_iter`] for a fallible version
+    fn iter(&self) -> SurfaceIter<Self::Surface> {
+        self.try_iter().expect("Malformed texture shape")
+    }
+
+    /// Iterate over all the surfaces in the texture like [`Self::iter`].
+    /// Returns an error if the texture's structure is malformed,
+    /// such as an array built by hand whose layers have different shapes
+    fn try_iter(&self) -> ShapeResult<SurfaceIter<Self::Surface>> {
+        // catches empty structures, which would otherwise iterate over nothing
+        self.try_primary()?;
+
+        let get = |t: &Self, index: Option<TextureIndex>| match index {
+            Some(i) => t
+                .get(i)
+                .ok_or(Malformed("texture is missing an index it reported")),
+            None => Ok(t.clone()),
+        };
+        let mut surfaces = Vec::new();
+        for mip in optional_indices(self.mips().map(|m| (0..m).collect())) {
+            let t = get(self, mip.map(TextureIndex::Mip))?;
+            for face in optional_indices(t.faces()) {
+                let t = get(&t, face.map(TextureIndex::Face))?;
+                for layer in optional_indices(t.layers().map(|l| (0..l).collect())) {
+                    let surface = get(&t, layer.map(TextureIndex::Layer))?
+                        .try_into_surface()
+                        .ok_or(Malformed("innermost shape is not a surface"))?;
+                    surfaces.push(TextureIterResult {
+                        mip,
+                        face,
+                        layer,
+                        surface,
+                    });
+                }
+            }
+        }
+
+        Ok(SurfaceIter(surfaces.into_iter()))
+    }
+
+    /// Returns this texture as a single surface, if it only has one. Otherwise returns [`None`]
+    fn try_into_surface(self) -> Option<Self::Surface>;
+
+    /// Returns the number of surfaces present in the texture
+    fn len(&self) -> usize {
+        let len = self.mips().unwrap_or(1)
+            * self.layers().unwrap_or(1)
+            * self.faces().map_or(1, |c| c.len());
+
+        assert!(len > 0);
+        len
+    }
+
+    /// Returns if this texture represents a single surface
+    fn is_surface(&self) -> bool {
+        self.len() == 1
+    }
+
+    /// Returns the primary surface of the texture
+    /// This is defined as layer 0, mip 0, and the first cubemap face present,
+    /// if any, in order of the definition of [`CubeFace`].
+    /// Returns an error if the texture's structure is malformed,
+    /// such as an empty cubemap or array built by hand
+    fn try_primary(&self) -> ShapeResult<Self::Surface> {
+        let mut ret = match self.faces() {
+            Some(faces) => {
+                let first = faces.into_iter().min().ok_or(Empty("cube"))?;
+                self.get_face(first)
+                    .ok_or(Malformed("cubemap face is missing"))?
+            }
+            None => self.clone(),
+        };
+
+        ret = ret.get_layer(0).unwrap_or(ret);
+        ret = ret.get_mip(0).unwrap_or(ret);
+
+        ret.try_into_surface()
+            .ok_or(Malformed("primary surface could not be found"))
+    }
+
+    /// Returns the primary surface of the texture
+    /// This is defined as layer 0, mip 0, and the first cubemap face present,
+    /// if any, in order of the definition of [`CubeFace`].
+    /// Panics if the texture's structure is malformed. See [`Self::try_primary`] for a fallible version
+    fn primary(&self) -> Self::Surface {
+        self.try_primary().expect("Malformed texture shape")
+    }
+}
+
+/// Every index of a structure, or a single [`None`] if the structure is not present
+fn optional_indices<I>(indices: Option<Vec<I>>) -> Vec<Option<I>> {
+    match indices {
+        Some(indices) => indices.into_iter().map(Some).collect(),
+        None => vec![None],
+    }
+}
+
+/// One node of a texture shape data structure
+#[derive(Clone, Debug)]
+pub enum TextureShapeNode<S: Sized + Clone + Dimensioned> {
+    /// A node representing a texture array
+    Array(Vec<Self>),
+
+    /// A node representing a cubemap. Faces are always kept in the order they are defined in [`CubeFace`]
+    CubeMap(BTreeMap<CubeFace, Self>),
+
+    /// A node representing a mipmap
+    MipMap(Vec<Self>),
+
+    /// A node representing a single surface
+    Surface(S),
+}
+
+impl<'a, S> TextureShapeNode<S>
+where
+    S: Clone + Dimensioned + 'a,
+{
+    /// Create a new texture with a single surface
+    pub fn from_surface(surface: S) -> Self {
+        Self::Surface(surface)
+    }
+
+    /// Borrow the first child of this node, or [`None`] if this node is a surface or is empty
+    fn first_child(&self) -> Option<&Self> {
+        match self {
+            TextureShapeNode::Array(l) => l.first(),
+            TextureShapeNode::CubeMap(f) => f.values().next(),
+            TextureShapeNode::MipMap(m) => m.first(),
+            TextureShapeNode::Surface(_) => None,
+        }
+    }
+
+    /// Walk down the first child of each node until `f` returns a value.
+    /// Since all children of a node have the same shape, this finds the first instance of a structure in the tree
+    fn find_first<T>(&'a self, f: impl Fn(&'a Self) -> Option<T>) -> Option<T> {
+        let mut node = self;
+        loop {
+            if let Some(t) = f(node) {
+                return Some(t);
+            }
+            node = node.first_child()?;
+        }
+    }
+
+    /// Get the dimensions of the first surface in the tree,
+    /// or return an error if the tree contains an empty array, cubemap, or mipmap
+    pub fn try_dimensions(&'a self) -> ShapeResult<Dimensions> {
+        self.find_first(|n| match n {
+            TextureShapeNode::Surface(s) => Some(s.dimensions()),
+            _ => None,
+        })
+        .ok_or(Malformed("texture contains no surfaces"))
+    }
+
+    /// Borrow a read-only view of the whole tree. See [`TextureSlice`]
+    pub fn slice(&'a self) -> TextureSlice<'a, S> {
+        TextureSlice::new(self)
+    }
+
+    /// Check for uniformity of a closure result across an iterator
+    fn uniformity_check<I, F, T>(iter: I, f: F, s: &'static str) -> ShapeResult
+    where
+        I: Iterator<Item = &'a Self>,
+        F: FnMut(&Self) -> T,
+        T: PartialEq,
+    {
+        if iter.map(f).all_equal() {
+            Ok(())
+        } else {
+            Err(NonUniform(s))
+        }
+    }
+
+    /// Check for nesting by iterating over textures and ensuring a closure returns [None]
+    fn nesting_check<I, F, T>(iter: I, f: F, s: &'static str) -> ShapeResult
+    where
+        I: Iterator<Item = &'a Self>,
+        F: FnMut(&Self) -> Option<T>,
+        T: PartialEq,
+    {
+        if iter.map(f).flatten().next().is_some() {
+            Err(Nested(s))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Summary of the changes made by [`TextureShapeNode::normalize`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NormalizeReport {
+    /// Number of single-layer arrays that were replaced by their only layer
+    pub collapsed_arrays: usize,
+
+    /// Number of single-mip mipmaps that were replaced by their only mip
+    pub collapsed_mipmaps: usize,
+}
+
+impl NormalizeReport {
+    /// Returns true if normalization did not modify the tree
+    pub fn is_unchanged(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl<S> TextureShapeNode<S>
+where
+    S: Clone + Dimensioned,
+{
+    /// Check the invariants of a list of textures that are about to be made into a mipmap
+    fn check_mips(mips: &[Self]) -> ShapeResult {
+        // get dimensions of first mip, while also making sure len > 0
+        let dimensions = mips.first().ok_or(Empty("mipmap"))?.dimensions();
+
+        // check that dimensions follow the mip chain
+        if !mips.iter().map(Self::dimensions).eq(dimensions.mips()) {
+            return Err(InvalidMipChain);
+        }
+
+        // check for non-uniformity and nesting
+        Self::uniformity_check(mips.iter(), Self::layers, "layers")?;
+        Self::uniformity_check(mips.iter(), Self::faces, "faces")?;
+        Self::nesting_check(mips.iter(), Self::mips, "mipmap")
+    }
+
+    /// Check the invariants of a set of textures that are about to be made into a cubemap
+    fn check_faces(faces: &BTreeMap<CubeFace, Self>) -> ShapeResult {
+        // Check for emptiness
+        if faces.is_empty() {
+            return Err(Empty("cube"));
+        }
+
+        // check for non-uniformity and nesting
+        Self::uniformity_check(faces.values(), Self::dimensions, "dimensions")?;
+        Self::uniformity_check(faces.values(), Self::mips, "mips")?;
+        Self::uniformity_check(faces.values(), Self::layers, "layers")?;
+        Self::nesting_check(faces.values(), Self::faces, "cube")
+    }
+
+    /// Check the invariants of a list of textures that are about to be made into an array
+    fn check_layers(layers: &[Self]) -> ShapeResult {
+        // Check for emptiness
+        if layers.is_empty() {
+            return Err(Empty("array"));
+        }
+
+        // check for non-uniformity and nesting
+        Self::uniformity_check(layers.iter(), Self::dimensions, "dimensions")?;
+        Self::uniformity_check(layers.iter(), Self::mips, "mips")?;
+        Self::uniformity_check(layers.iter(), Self::faces, "faces")?;
+        Self::nesting_check(layers.iter(), Self::layers, "array")
+    }
+
+    /// Every child whose `f` differs from the first child's
+    fn uniformity_violations<F, T>(
+        children: &[(TextureIndex, &Self)],
+        mut f: F,
+        s: &'static str,
+    ) -> Vec<ShapeViolation>
+    where
+        F: FnMut(&Self) -> T,
+        T: PartialEq,
+    {
+        let Some((_, first)) = children.first() else {
+            return Vec::new();
+        };
+        let expected = f(first);
+        (children.iter())
+            .filter(|(_, child)| f(child) != expected)
+            .map(|&(index, _)| ShapeViolation {
+                index: Some(index),
+                error: NonUniform(s),
+            })
+            .collect()
+    }
+
+    /// Every child that already has the structure `f` returns
+    fn nesting_violations<F, T>(
+        children: &[(TextureIndex, &Self)],
+        mut f: F,
+        s: &'static str,
+    ) -> Vec<ShapeViolation>
+    where
+        F: FnMut(&Self) -> Option<T>,
+    {
+        (children.iter())
+            .filter(|(_, child)| f(child).is_some())
+            .map(|&(index, _)| ShapeViolation {
+                index: Some(index),
+                error: Nested(s),
+            })
+            .collect()
+    }
+
+    /// Every violated invariant of a list of textures that are about to be made into a mipmap
+    fn mip_violations(mips: &[Self]) -> Vec<ShapeViolation> {
+        let Some(first) = mips.first() else {
+            return vec![ShapeViolation {
+                index: None,
+                error: Empty("mipmap"),
+            }];
+        };
+        let children = (mips.iter().enumerate())
+            .map(|(i, m)| (TextureIndex::Mip(i), m))
+            .collect_vec();
+
+        // each mip is compared to its place in the chain, so one wrong mip doesn't flag the rest
+        let mut chain = first.dimensions().mips();
+        let mut violations = (children.iter())
+            .filter(|(_, mip)| chain.next() != Some(mip.dimensions()))
+            .map(|&(index, _)| ShapeViolation {
+                index: Some(index),
+                error: InvalidMipChain,
+            })
+            .collect_vec();
+        if chain.next().is_some() {
+            violations.push(ShapeViolation {
+                index: None,
+                error: InvalidMipChain,
+            });
+        }
+
+        violations.extend(Self::uniformity_violations(
+            &children,
+            Self::layers,
+            "layers",
+        ));
+        violations.extend(Self::uniformity_violations(&children, Self::faces, "faces"));
+        violations.extend(Self::nesting_violations(&children, Self::mips, "mipmap"));
+        violations
+    }
+
+    /// Every violated invariant shared by cubemaps and arrays in a list of textures about to be made into one
+    fn child_violations(
+        children: &[(TextureIndex, &Self)],
+        structure: &'static str,
+    ) -> Vec<ShapeViolation> {
+        if children.is_empty() {
+            return vec![ShapeViolation {
+                index: None,
+                error: Empty(structure),
+            }];
+        }
+        let mut violations = Self::uniformity_violations(children, Self::dimensions, "dimensions");
+        violations.extend(Self::uniformity_violations(children, Self::mips, "mips"));
+        violations
+    }
+
+    /// Like [`TextureShape::try_from_mips`], but returns every violated invariant instead of only the first,
+    /// such as each mip whose dimensions don't fit the mipchain. Useful for reporting problems with user input
+    pub fn try_from_mips_all<I: IntoIterator<Item = Self>>(iter: I) -> Result<Self, ShapeErrors> {
+        let mips = iter.into_iter().collect_vec();
+        ShapeErrors::check(Self::mip_violations(&mips), ())?;
+        Ok(Self::MipMap(mips))
+    }
+
+    /// Like [`TextureShape::try_from_faces`], but returns every violated invariant instead of only the first.
+    /// Every repeat of a face is reported as [`ShapeError::DuplicateFaces`], and only the first is checked
+    pub fn try_from_faces_all<I>(iter: I) -> Result<Self, ShapeErrors>
+    where
+        I: IntoIterator<Item = (CubeFace, Self)>,
+    {
+        let mut faces = BTreeMap::new();
+        let mut violations = Vec::new();
+        for (face, t) in iter {
+            match faces.entry(face) {
+                Entry::Occupied(_) => violations.push(ShapeViolation {
+                    index: Some(TextureIndex::Face(face)),
+                    error: DuplicateFaces,
+                }),
+                Entry::Vacant(v) => {
+                    v.insert(t);
+                }
+            }
+        }
+
+        let children = (faces.iter())
+            .map(|(&f, t)| (TextureIndex::Face(f), t))
+            .collect_vec();
+        violations.extend(Self::child_violations(&children, "cube"));
+        violations.extend(Self::uniformity_violations(
+            &children,
+            Self::layers,
+            "layers",
+        ));
+        violations.extend(Self::nesting_violations(&children, Self::faces, "cube"));
+        ShapeErrors::check(violations, ())?;
+        Ok(Self::CubeMap(faces))
+    }
+
+    /// Like [`TextureShape::try_from_layers`], but returns every violated invariant instead of only the first
+    pub fn try_from_layers_all<I: IntoIterator<Item = Self>>(iter: I) -> Result<Self, ShapeErrors> {
+        let layers = iter.into_iter().collect_vec();
+        let children = (layers.iter().enumerate())
+            .map(|(i, l)| (TextureIndex::Layer(i), l))
+            .collect_vec();
+        let mut violations = Self::child_violations(&children, "array");
+        violations.extend(Self::uniformity_violations(&children, Self::faces, "faces"));
+        violations.extend(Self::nesting_violations(&children, Self::layers, "array"));
+        ShapeErrors::check(violations, ())?;
+        Ok(Self::Array(layers))
+    }
+
+    /// Check every node in the tree against the invariants documented on [`TextureShape`].
+    /// This is useful after building or editing a tree by hand instead of through the `try_from_*` constructors
+    pub fn validate(&self) -> ShapeResult {
+        match self {
+            TextureShapeNode::Surface(_) => Ok(()),
+            TextureShapeNode::Array(layers) => {
+                layers.iter().try_for_each(Self::validate)?;
+                Self::check_layers(layers)
+            }
+            TextureShapeNode::CubeMap(faces) => {
+                faces.values().try_for_each(Self::validate)?;
+                Self::check_faces(faces)
+            }
+            TextureShapeNode::MipMap(mips) => {
+                mips.iter().try_for_each(Self::validate)?;
+                Self::check_mips(mips)
+            }
+        }
+    }
+
+    /// Simplify the tree in place by collapsing any array or mipmap with only one element into that element,
+    /// then [`validate`](Self::validate) the result. The tree is left unchanged if the result is invalid.
+    /// Cubemaps are never collapsed, since a single-face cubemap is still meaningful to some containers.
+    pub fn normalize(&mut self) -> ShapeResult<NormalizeReport> {
+        let mut report = NormalizeReport::default();
+        let mut normalized = self.clone();
+        normalized.normalize_inner(&mut report);
+        normalized.validate()?;
+        *self = normalized;
+        Ok(report)
+    }
+
+    fn normalize_inner(&mut self, report: &mut NormalizeReport) {
+        // normalize children first, so collapses propagate upwards
+        match self {
+            TextureShapeNode::Array(children) | TextureShapeNode::MipMap(children) => {
+                children.iter_mut().for_each(|t| t.normalize_inner(report))
+            }
+            TextureShapeNode::CubeMap(faces) => {
+                faces.values_mut().for_each(|t| t.normalize_inner(report))
+            }
+            TextureShapeNode::Surface(_) => {}
+        }
+
+        match self {
+            TextureShapeNode::Array(layers) if layers.len() == 1 => {
+                report.collapsed_arrays += 1;
+                *self = layers.remove(0);
+            }
+            TextureShapeNode::MipMap(mips) if mips.len() == 1 => {
+                report.collapsed_mipmaps += 1;
+                *self = mips.remove(0);
+            }
+            _ => {}
+        }
+    }
+
+    /// Build a tree with the same shape by applying `f` to every surface, stopping at the first error.
+    /// `f` should preserve the dimensions of each surface, otherwise the result may not be a valid shape
+    pub fn try_map<T, E, F>(&self, mut f: F) -> Result<TextureShapeNode<T>, E>
+    where
+        T: Clone + Dimensioned,
+        F: FnMut(&S) -> Result<T, E>,
+    {
+        self.try_map_inner(&mut f)
+    }
+
+    /// Remove the `n` largest mips from every mipmap in the tree.
+    /// Returns [`ShapeError::Empty`] without modifying the tree if that would remove every mip,
+    /// or if `n` is nonzero and the tree has no mipmaps
+    pub fn strip_top_mips(&mut self, n: usize) -> ShapeResult {
+        if n == 0 {
+            return Ok(());
+        }
+        if self.mips().unwrap_or(1) <= n {
+            return Err(Empty("mipmap"));
+        }
+        self.strip_top_mips_inner(n);
+        Ok(())
+    }
+
+    /// Reorder the layers of every array in the tree, so that layer `i` of the result is layer `permutation[i]`
+    /// of the original. Only the tree is rearranged, so no surface data is copied or re-encoded.
+    /// Returns [`ShapeError::InvalidPermutation`] without modifying the tree if the tree has no array,
+    /// or if `permutation` does not contain every layer index exactly once
+    pub fn reorder_layers(&mut self, permutation: &[usize]) -> ShapeResult {
+        let layers = self
+            .layers()
+            .ok_or(InvalidPermutation("texture has no array"))?;
+        if permutation.len() != layers {
+            return Err(InvalidPermutation(
+                "length does not match the number of layers",
+            ));
+        }
+        let mut seen = vec![false; layers];
+        for &l in permutation {
+            match seen.get_mut(l) {
+                None => return Err(InvalidPermutation("layer index out of range")),
+                Some(true) => return Err(InvalidPermutation("layer index repeated")),
+                Some(s) => *s = true,
+            }
+        }
+        self.reorder_layers_inner(permutation);
+        Ok(())
+    }
+
+    /// Stably sort the layers of the tree by the key `f` returns for each one, given the layer's index and
+    /// a tree of all its surfaces as from [`TextureShape::get_layer`].
+    /// Returns the permutation that was applied, as passed to [`Self::reorder_layers`], which maps each new
+    /// layer index to its old one. Returns [`ShapeError::InvalidPermutation`] if the tree has no array
+    pub fn sort_layers_by<K, F>(&mut self, mut f: F) -> ShapeResult<Vec<usize>>
+    where
+        K: Ord,
+        F: FnMut(usize, &Self) -> K,
+    {
+        let layers = self
+            .layers()
+            .ok_or(InvalidPermutation("texture has no array"))?;
+        let keys: Vec<K> = (0..layers)
+            .map(|l| {
+                let layer = self
+                    .get_layer(l)
+                    .ok_or(Malformed("array layer is missing"))?;
+                Ok(f(l, &layer))
+            })
+            .try_collect()?;
+        let permutation = (0..layers)
+            .sorted_by(|a, b| keys[*a].cmp(&keys[*b]))
+            .collect_vec();
+        self.reorder_layers(&permutation)?;
+        Ok(permutation)
+    }
+
+    /// Add a layer to the end of the tree's array. `layer` is a tree of all the layer's surfaces,
+    /// as from [`TextureShape::get_layer`]. See [`Self::insert_layer`]
+    pub fn push_layer(&mut self, layer: Self) -> ShapeResult {
+        let index = self.layers().ok_or(InvalidIndex("texture has no array"))?;
+        self.insert_layer(index, layer)
+    }
+
+    /// Insert a layer into the tree's array at `index`, shifting later layers up.
+    /// If the array is nested inside mipmaps or cubemaps, it is moved to the root of the tree first.
+    /// Returns [`ShapeError::InvalidIndex`] if the tree has no array or `index` is past the end,
+    /// or another error without modifying the tree if `layer` does not match the other layers
+    pub fn insert_layer(&mut self, index: usize, layer: Self) -> ShapeResult {
+        let layers = self.layers_mut()?;
+        if index > layers.len() {
+            return Err(InvalidIndex("layer index out of range"));
+        }
+        Self::check_layer(layers, &layer)?;
+        layers.insert(index, layer);
+        Ok(())
+    }
+
+    /// Remove the layer at `index` from the tree's array, returning it as a tree of its own.
+    /// Returns [`ShapeError::InvalidIndex`] if the tree has no array or `index` is out of range,
+    /// or [`ShapeError::Empty`] if it is the only layer
+    pub fn remove_layer(&mut self, index: usize) -> ShapeResult<Self> {
+        let layers = self.layers_mut()?;
+        if index >= layers.len() {
+            return Err(InvalidIndex("layer index out of range"));
+        }
+        if layers.len() == 1 {
+            return Err(Empty("array"));
+        }
+        Ok(layers.remove(index))
+    }
+
+    /// Replace the layer at `index` in the tree's array, returning the old layer.
+    /// Returns [`ShapeError::InvalidIndex`] if the tree has no array or `index` is out of range,
+    /// or another error without modifying the tree if `layer` does not match the other layers
+    pub fn replace_layer(&mut self, index: usize, layer: Self) -> ShapeResult<Self> {
+        let layers = self.layers_mut()?;
+        if index >= layers.len() {
+            return Err(InvalidIndex("layer index out of range"));
+        }
+        Self::check_layer(layers, &layer)?;
+        Ok(std::mem::replace(&mut layers[index], layer))
+    }
+
+    /// Replace mip `index` of a tree whose root is a mipmap, returning the old mip.
+    /// The new mip must have the same dimensions, faces, and layers as the one it replaces.
+    /// Returns [`ShapeError::InvalidIndex`] if the root is not a mipmap or `index` is out of range,
+    /// or another error without modifying the tree if `mip` does not fit the mip chain
+    pub fn replace_mip(&mut self, index: usize, mip: Self) -> ShapeResult<Self> {
+        let TextureShapeNode::MipMap(mips) = self else {
+            return Err(InvalidIndex("texture has no mipmap at its root"));
+        };
+        let old = mips
+            .get(index)
+            .ok_or(InvalidIndex("mip index out of range"))?;
+        mip.validate()?;
+        if mip.dimensions() != old.dimensions() {
+            return Err(InvalidMipChain);
+        }
+        Self::uniformity_check([old, &mip].into_iter(), Self::layers, "layers")?;
+        Self::uniformity_check([old, &mip].into_iter(), Self::faces, "faces")?;
+        Self::nesting_check([&mip].into_iter(), Self::mips, "mipmap")?;
+        Ok(std::mem::replace(&mut mips[index], mip))
+    }
+
+    /// Mutably borrow the tree's layers, moving its array to the root first if it is nested
+    fn layers_mut(&mut self) -> ShapeResult<&mut Vec<Self>> {
+        if !matches!(self, TextureShapeNode::Array(_)) {
+            let count = self.layers().ok_or(InvalidIndex("texture has no array"))?;
+            let layers = (0..count)
+                .map(|l| self.get_layer(l).ok_or(Malformed("array layer is missing")))
+                .try_collect()?;
+            *self = TextureShapeNode::Array(layers);
+        }
+        match self {
+            TextureShapeNode::Array(layers) => Ok(layers),
+            _ => unreachable!("array was just moved to the root"),
+        }
+    }
+
+    /// Check that a layer can be added to an array alongside `layers`
+    fn check_layer(layers: &[Self], layer: &Self) -> ShapeResult {
+        layer.validate()?;
+        if let Some(first) = layers.first() {
+            Self::uniformity_check([first, layer].into_iter(), Self::dimensions, "dimensions")?;
+            Self::uniformity_check([first, layer].into_iter(), Self::mips, "mips")?;
+            Self::uniformity_check([first, layer].into_iter(), Self::faces, "faces")?;
+        }
+        Self::nesting_check([layer].into_iter(), Self::layers, "array")
+    }
+
+    /// Mutably borrow the surface at the given layer, face, and mip, as yielded by [`TextureShape::iter`].
+    /// Returns [`None`] if an index is out of range, or is missing for a structure the tree has.
+    /// The surface's dimensions must not be changed, otherwise the tree may no longer be a valid shape
+    pub(crate) fn get_surface_mut(
+        &mut self,
+        layer: Option<usize>,
+        face: Option<CubeFace>,
+        mip: Option<usize>,
+    ) -> Option<&mut S> {
+        match self {
+            TextureShapeNode::Array(layers) => {
+                layers.get_mut(layer?)?.get_surface_mut(layer, face, mip)
+            }
+            TextureShapeNode::CubeMap(faces) => {
+                faces.get_mut(&face?)?.get_surface_mut(layer, face, mip)
+            }
+            TextureShapeNode::MipMap(mips) => mips.get_mut(mip?)?.get_surface_mut(layer, face, mip),
+            TextureShapeNode::Surface(s) => Some(s),
+        }
+    }
+
+    fn reorder_layers_inner(&mut self, permutation: &[usize]) {
+        match self {
+            TextureShapeNode::Array(layers) => {
+                *layers = permutation.iter().map(|&l| layers[l].clone()).collect();
+            }
+            TextureShapeNode::CubeMap(faces) => faces
+                .values_mut()
+                .for_each(|t| t.reorder_layers_inner(permutation)),
+            TextureShapeNode::MipMap(mips) => mips
+                .iter_mut()
+                .for_each(|t| t.reorder_layers_inner(permutation)),
+            TextureShapeNode::Surface(_) => {}
+        }
+    }
+
+    fn strip_top_mips_inner(&mut self, n: usize) {
+        match self {
+            TextureShapeNode::Array(layers) => {
+                layers.iter_mut().for_each(|t| t.strip_top_mips_inner(n))
+            }
+            TextureShapeNode::CubeMap(faces) => {
+                faces.values_mut().for_each(|t| t.strip_top_mips_inner(n))
+            }
+            TextureShapeNode::MipMap(mips) => {
+                mips.drain(..n);
+            }
+            TextureShapeNode::Surface(_) => {}
+        }
+    }
+
+    fn try_map_inner<T, E, F>(&self, f: &mut F) -> Result<TextureShapeNode<T>, E>
+    where
+        T: Clone + Dimensioned,
+        F: FnMut(&S) -> Result<T, E>,
+    {
+        Ok(match self {
+            TextureShapeNode::Array(layers) => {
+                TextureShapeNode::Array(layers.iter().map(|t| t.try_map_inner(f)).try_collect()?)
+            }
+            TextureShapeNode::CubeMap(faces) => TextureShapeNode::CubeMap(
+                faces
+                    .iter()
+                    .map(|(face, t)| Ok((*face, t.try_map_inner(f)?)))
+                    .try_collect()?,
+            ),
+            TextureShapeNode::MipMap(mips) => {
+                TextureShapeNode::MipMap(mips.iter().map(|t| t.try_map_inner(f)).try_collect()?)
+            }
+            TextureShapeNode::Surface(s) => TextureShapeNode::Surface(f(s)?),
+        })
+    }
+}
+
+impl<S> Dimensioned for TextureShapeNode<S>
+where
+    S: Clone + Dimensioned,
+{
+    fn dimensions(&self) -> Dimensions {
+        match self {
+            TextureShapeNode::Surface(s) => s.dimensions(),
+            _ => self.try_dimensions().expect("Malformed texture shape"),
+        }
+    }
+}
+
+impl<S> TextureShape for TextureShapeNode<S>
+where
+    S: Clone + Dimensioned,
+{
+    type Surface = S;
+
+    fn get(&self, index: TextureIndex) -> Option<Self> {
+        return match (self, index) {
+            (TextureShapeNode::Surface { .. }, _) => None, // target index was never found :(
+
+            (TextureShapeNode::CubeMap(faces), TextureIndex::Face(f)) => {
+                Some(faces.get(&f)?.clone())
+            }
+            (TextureShapeNode::CubeMap(faces), index) => Some(TextureShapeNode::CubeMap(
+                faces
+                    .iter()
+                    .map(|(i, f)| Some((*i, f.get(index)?)))
+                    .collect::<Option<_>>()?,
+            )),
+
+            (TextureShapeNode::MipMap(mips), TextureIndex::Mip(m)) => {
+                let mips = mips.get(m)?.as_slice();
+                match &mips[..] {
+                    [single] => {
+                        assert_eq!(single.mips(), None);
+                        Some(single.clone())
+                    }
+                    [..] => Some(TextureShapeNode::MipMap(mips.into())),
+                }
+            }
+            (TextureShapeNode::MipMap(mips), _) => Some(TextureShapeNode::MipMap(
+                mips.iter().map(|t| t.get(index)).collect::<Option<_>>()?,
+            )),
+
+            (TextureShapeNode::Array(layers), TextureIndex::Layer(l)) => {
+                let layers = layers.get(l)?.as_slice();
+                match &layers[..] {
+                    [single] => {
+                        assert_eq!(single.layers(), None);
+                        Some(single.clone())
+                    }
+                    [..] => Some(TextureShapeNode::Array(layers.into())),
+                }
+            }
+            (TextureShapeNode::Array(layers), _) => Some(TextureShapeNode::Array(
+                layers.iter().map(|t| t.get(index)).collect::<Option<_>>()?,
+            )),
+        };
+    }
+
+    fn try_from_mips<I: IntoIterator<Item = Self>>(iter: I) -> ShapeResult<Self> {
+        let mips = iter.into_iter().collect_vec();
+        Self::check_mips(&mips)?;
+        Ok(Self::MipMap(mips))
+    }
+
+    fn try_from_faces<I: IntoIterator<Item = (CubeFace, Self)>>(iter: I) -> ShapeResult<Self> {
+        let mut faces = BTreeMap::new();
+
+        // add faces and check for duplicates
+        for (face, t) in iter {
+            if faces.insert(face, t).is_some() {
+                return Err(DuplicateFaces);
+            }
+        }
+
+        Self::check_faces(&faces)?;
+        Ok(Self::CubeMap(faces))
+    }
+
+    fn try_from_layers<I: IntoIterator<Item = Self>>(iter: I) -> ShapeResult<Self> {
+        let layers = iter.into_iter().collect_vec();
+        Self::check_layers(&layers)?;
+        Ok(Self::Array(layers))
+    }
+
+    fn mips(&self) -> Option<usize> {
+        match self {
+            TextureShapeNode::Surface { .. } => None,
+            TextureShapeNode::MipMap(v) => Some(v.len()),
+            _ => self.first_child()?.mips(),
+        }
+    }
+
+    fn layers(&self) -> Option<usize> {
+        match self {
+            TextureShapeNode::Surface { .. } => None,
+            TextureShapeNode::Array(v) => Some(v.len()),
+            _ => self.first_child()?.layers(),
+        }
+    }
+
+    fn faces(&self) -> Option<Vec<CubeFace>> {
+        match self {
+            TextureShapeNode::Surface { .. } => None,
+            TextureShapeNode::CubeMap(faces) => Some(faces.keys().cloned().collect()),
+            _ => self.first_child()?.faces(),
+        }
+    }
+
+    fn try_into_surface(self) -> Option<S> {
+        match self {
+            TextureShapeNode::Surface(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn is_surface(&self) -> bool {
+        match self {
+            TextureShapeNode::Surface(_) => true,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,406 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::btree_map::Keys;
+use std::collections::BTreeMap;
+use std::iter::{Copied, FusedIterator};
+use std::mem;
+use std::ops::Range;
+
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::shape::{
+    CubeFace, ShapeError, ShapeResult, TextureIndex, TextureIterResult, TextureShape,
+    TextureShapeNode,
+};
+
+/// A borrowed, read-only view into part of a [`TextureShapeNode`].
+///
+/// Where [`TextureShape::get`] builds a new tree out of cloned subtrees, a slice only records which
+/// layer, face, and mip have been selected, so indexing and traversal never allocate.
+/// A slice behaves like the subtree `get` would have returned:
+/// once a structure has been indexed, it is no longer reported by [`mips`](Self::mips),
+/// [`layers`](Self::layers), or [`faces`](Self::faces).
+#[derive(Debug)]
+pub struct TextureSlice<'a, S: Clone + Dimensioned> {
+    root: &'a TextureShapeNode<S>,
+    layer: Option<usize>,
+    face: Option<CubeFace>,
+    mip: Option<usize>,
+}
+
+impl<S: Clone + Dimensioned> Clone for TextureSlice<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S: Clone + Dimensioned> Copy for TextureSlice<'_, S> {}
+
+impl<'a, S: Clone + Dimensioned> TextureSlice<'a, S> {
+    /// Create a slice covering the entire tree under `root`
+    pub(super) fn new(root: &'a TextureShapeNode<S>) -> Self {
+        Self {
+            root,
+            layer: None,
+            face: None,
+            mip: None,
+        }
+    }
+
+    /// Get a slice of all the surfaces that match the passed index. If there are no matching
+    /// surfaces, or the indexed structure is not present in the slice, this will return [`None`]
+    pub fn get(self, index: TextureIndex) -> Option<Self> {
+        let mut slice = self;
+        match index {
+            TextureIndex::Layer(l) => {
+                (l < self.layers()?).then_some(())?;
+                slice.layer = Some(l);
+            }
+            TextureIndex::Mip(m) => {
+                (m < self.mips()?).then_some(())?;
+                slice.mip = Some(m);
+            }
+            TextureIndex::Face(f) => {
+                self.cubemap()?.get(&f)?;
+                slice.face = Some(f);
+            }
+        }
+        Some(slice)
+    }
+
+    /// Get the array layer matching the given layer index
+    pub fn get_layer(self, index: usize) -> Option<Self> {
+        self.get(TextureIndex::Layer(index))
+    }
+
+    /// Get the cubemap face matching the given face index
+    pub fn get_face(self, index: CubeFace) -> Option<Self> {
+        self.get(TextureIndex::Face(index))
+    }
+
+    /// Get the mip matching the given mip index
+    pub fn get_mip(self, index: usize) -> Option<Self> {
+        self.get(TextureIndex::Mip(index))
+    }
+
+    /// Get the number of mips in the slice
+    pub fn mips(&self) -> Option<usize> {
+        match self.mip {
+            Some(_) => None,
+            None => self.root.mips(),
+        }
+    }
+
+    /// Get the number of layers in the slice
+    pub fn layers(&self) -> Option<usize> {
+        match self.layer {
+            Some(_) => None,
+            None => self.root.layers(),
+        }
+    }
+
+    /// Get a Vec of the cubemap faces in the slice
+    pub fn faces(&self) -> Option<Vec<CubeFace>> {
+        Some(self.cubemap()?.keys().copied().collect())
+    }
+
+    /// Borrow the cubemap node used to look up faces, if the slice has an unindexed cubemap structure
+    fn cubemap(&self) -> Option<&'a BTreeMap<CubeFace, TextureShapeNode<S>>> {
+        match self.face {
+            Some(_) => None,
+            None => self.root.find_first(|n| match n {
+                TextureShapeNode::CubeMap(faces) => Some(faces),
+                _ => None,
+            }),
+        }
+    }
+
+    /// Iterate over the layers of the slice.
+    /// If this slice has an array structure, returns each layer along with its index.
+    /// Otherwise, this iterator returns a single item `(None, self)`
+    pub fn iter_layers(self) -> SliceIndexIter<'a, S> {
+        SliceIter::new(self, self.layers().map(|l| 0..l), Self::with_layer)
+    }
+
+    /// Iterate over the cubemap faces of the slice.
+    /// If this slice has a cubemap structure, returns each face along with its index.
+    /// Otherwise, this iterator returns a single item `(None, self)`
+    pub fn iter_faces(self) -> SliceFaceIter<'a, S> {
+        SliceIter::new(
+            self,
+            self.cubemap().map(|f| f.keys().copied()),
+            Self::with_face,
+        )
+    }
+
+    /// Iterate over the mips of the slice.
+    /// If this slice has a mipmap structure, returns each mip along with its index.
+    /// Otherwise, this iterator returns a single item `(None, self)`
+    pub fn iter_mips(self) -> SliceIndexIter<'a, S> {
+        SliceIter::new(self, self.mips().map(|m| 0..m), Self::with_mip)
+    }
+
+    /// Iterate over all the surfaces in the slice, returning the layer, face, and mip index for
+    /// each one if present. Surfaces are visited in the same order as [`TextureShape::iter`].
+    /// The iterator panics if the underlying tree is malformed. See [`Self::try_iter`] for a fallible version
+    pub fn iter(self) -> SliceSurfaceIter<'a, S> {
+        let mips = self.mips();
+        let faces = self.cubemap();
+        let layers = self.layers();
+        let len = mips.unwrap_or(1) * faces.map_or(1, |f| f.len()) * layers.unwrap_or(1);
+
+        SliceSurfaceIter {
+            slice: self,
+            mips,
+            faces,
+            layers,
+            front: 0,
+            back: len,
+        }
+    }
+
+    /// Iterate over all the surfaces in the slice like [`Self::iter`].
+    /// Returns an error up front if any surface can't be found because the underlying tree is malformed
+    pub fn try_iter(self) -> ShapeResult<SliceSurfaceIter<'a, S>> {
+        // catches empty structures, which would otherwise iterate over nothing
+        self.try_dimensions()?;
+        let iter = self.iter();
+        (iter.front..iter.back).try_for_each(|p| iter.try_at(p).map(|_| ()))?;
+        Ok(iter)
+    }
+
+    /// Returns the surface this slice refers to, if it only refers to one. Otherwise returns [`None`]
+    pub fn try_into_surface(self) -> Option<&'a S> {
+        if !self.is_surface() {
+            return None;
+        }
+
+        let mut node = self.root;
+        loop {
+            node = match node {
+                TextureShapeNode::Surface(s) => return Some(s),
+                TextureShapeNode::Array(layers) => layers.get(self.layer?)?,
+                TextureShapeNode::CubeMap(faces) => faces.get(&self.face?)?,
+                TextureShapeNode::MipMap(mips) => mips.get(self.mip?)?,
+            }
+        }
+    }
+
+    /// Get the dimensions of the surfaces in this slice,
+    /// or return an error if the underlying tree is malformed
+    pub fn try_dimensions(&self) -> ShapeResult<Dimensions> {
+        let dimensions = self.root.try_dimensions()?;
+        match self.mip {
+            Some(mip) => dimensions.mips().nth(mip).ok_or(ShapeError::Malformed(
+                "mip index out of range for dimensions",
+            )),
+            None => Ok(dimensions),
+        }
+    }
+
+    /// Returns if this slice represents a single surface
+    pub fn is_surface(&self) -> bool {
+        self.mips().is_none() && self.layers().is_none() && self.cubemap().is_none()
+    }
+
+    // unchecked versions of `get_*` for use when iterating over known-good indices
+    fn with_layer(mut self, layer: usize) -> Self {
+        self.layer = Some(layer);
+        self
+    }
+
+    fn with_face(mut self, face: CubeFace) -> Self {
+        self.face = Some(face);
+        self
+    }
+
+    fn with_mip(mut self, mip: usize) -> Self {
+        self.mip = Some(mip);
+        self
+    }
+}
+
+impl<S: Clone + Dimensioned> Dimensioned for TextureSlice<'_, S> {
+    fn dimensions(&self) -> Dimensions {
+        self.try_dimensions().expect("Malformed texture shape")
+    }
+}
+
+/// Iterator over one structure of a [`TextureSlice`], along with the index of each item.
+/// Returned by [`TextureSlice::iter_layers`], [`TextureSlice::iter_faces`], and [`TextureSlice::iter_mips`].
+///
+/// If the slice does not have the iterated structure, this yields a single item `(None, slice)`
+pub struct SliceIter<'a, S: Clone + Dimensioned, K: Iterator> {
+    slice: TextureSlice<'a, S>,
+    indices: Option<K>,
+    pad: bool,
+    select: fn(TextureSlice<'a, S>, K::Item) -> TextureSlice<'a, S>,
+}
+
+/// Iterator over the array layers or mips of a [`TextureSlice`]
+pub type SliceIndexIter<'a, S> = SliceIter<'a, S, Range<usize>>;
+
+/// Iterator over the cubemap faces of a [`TextureSlice`]
+pub type SliceFaceIter<'a, S> = SliceIter<'a, S, Copied<Keys<'a, CubeFace, TextureShapeNode<S>>>>;
+
+impl<'a, S: Clone + Dimensioned, K: Iterator> SliceIter<'a, S, K> {
+    fn new(
+        slice: TextureSlice<'a, S>,
+        indices: Option<K>,
+        select: fn(TextureSlice<'a, S>, K::Item) -> TextureSlice<'a, S>,
+    ) -> Self {
+        Self {
+            slice,
+            indices,
+            pad: true,
+            select,
+        }
+    }
+
+    fn item(&self, index: Option<K::Item>) -> (Option<K::Item>, TextureSlice<'a, S>)
+    where
+        K::Item: Copy,
+    {
+        match index {
+            Some(i) => (index, (self.select)(self.slice, i)),
+            None => (None, self.slice),
+        }
+    }
+}
+
+impl<'a, S, K> Iterator for SliceIter<'a, S, K>
+where
+    S: Clone + Dimensioned,
+    K: ExactSizeIterator,
+    K::Item: Copy,
+{
+    type Item = (Option<K::Item>, TextureSlice<'a, S>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = match &mut self.indices {
+            Some(indices) => Some(indices.next()?),
+            None => mem::take(&mut self.pad).then_some(None)?,
+        };
+        Some(self.item(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match &self.indices {
+            Some(indices) => indices.len(),
+            None => self.pad as usize,
+        };
+        (len, Some(len))
+    }
+}
+
+impl<S, K> DoubleEndedIterator for SliceIter<'_, S, K>
+where
+    S: Clone + Dimensioned,
+    K: ExactSizeIterator + DoubleEndedIterator,
+    K::Item: Copy,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let index = match &mut self.indices {
+            Some(indices) => Some(indices.next_back()?),
+            None => mem::take(&mut self.pad).then_some(None)?,
+        };
+        Some(self.item(index))
+    }
+}
+
+impl<S, K> ExactSizeIterator for SliceIter<'_, S, K>
+where
+    S: Clone + Dimensioned,
+    K: ExactSizeIterator,
+    K::Item: Copy,
+{
+}
+
+impl<S, K> FusedIterator for SliceIter<'_, S, K>
+where
+    S: Clone + Dimensioned,
+    K: ExactSizeIterator + FusedIterator,
+    K::Item: Copy,
+{
+}
+
+/// Iterator over every surface in a [`TextureSlice`]. Returned by [`TextureSlice::iter`]
+pub struct SliceSurfaceIter<'a, S: Clone + Dimensioned> {
+    slice: TextureSlice<'a, S>,
+    mips: Option<usize>,
+    faces: Option<&'a BTreeMap<CubeFace, TextureShapeNode<S>>>,
+    layers: Option<usize>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a, S: Clone + Dimensioned> SliceSurfaceIter<'a, S> {
+    /// Get the surface at a flat position in mip, face, layer order
+    fn at(&self, position: usize) -> TextureIterResult<&'a S> {
+        self.try_at(position).expect("Malformed texture shape")
+    }
+
+    /// Get the surface at a flat position in mip, face, layer order,
+    /// or return an error if the slice does not resolve to a surface there
+    fn try_at(&self, position: usize) -> ShapeResult<TextureIterResult<&'a S>> {
+        let layer_count = self.layers.unwrap_or(1);
+        let face_count = self.faces.map_or(1, |f| f.len());
+
+        let layer = self.layers.map(|_| position % layer_count);
+        let face = self.faces.and_then(|f| {
+            let face_index = (position / layer_count) % face_count;
+            f.keys().nth(face_index).copied()
+        });
+        let mip = self.mips.map(|_| position / (layer_count * face_count));
+
+        let mut slice = self.slice;
+        if let Some(layer) = layer {
+            slice = slice.with_layer(layer);
+        }
+        if let Some(face) = face {
+            slice = slice.with_face(face);
+        }
+        if let Some(mip) = mip {
+            slice = slice.with_mip(mip);
+        }
+
+        Ok(TextureIterResult {
+            layer,
+            face,
+            mip,
+            surface: slice.try_into_surface().ok_or(ShapeError::Malformed(
+                "texture slice does not resolve to a surface",
+            ))?,
+        })
+    }
+}
+
+impl<'a, S: Clone + Dimensioned> Iterator for SliceSurfaceIter<'a, S> {
+    type Item = TextureIterResult<&'a S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        (self.front < self.back).then(|| {
+            self.front += 1;
+            self.at(self.front - 1)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<S: Clone + Dimensioned> DoubleEndedIterator for SliceSurfaceIter<'_, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        (self.front < self.back).then(|| {
+            self.back -= 1;
+            self.at(self.back)
+        })
+    }
+}
+
+impl<S: Clone + Dimensioned> ExactSizeIterator for SliceSurfaceIter<'_, S> {}
+
+impl<S: Clone + Dimensioned> FusedIterator for SliceSurfaceIter<'_, S> {}
@@ -0,0 +1,422 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use anyhow::Result;
+use itertools::Itertools;
+use strum::VariantArray;
+
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::texture::{Surface, Surfaces};
+
+use super::{CubeFace, ShapeError, ShapeViolation, TextureIndex, TextureShape, TextureShapeNode};
+
+/// Build a surface whose buffer is a single tag byte, so surfaces can be told apart after traversal
+fn surface(dimensions: Dimensions, tag: u8) -> Surfaces {
+    TextureShapeNode::from_surface(Surface::new(dimensions, vec![tag]))
+}
+
+/// Build an array of `layers` cubemaps, each with a full mipchain starting at 16x16
+fn cube_array(layers: usize) -> Result<Surfaces> {
+    let dimensions = Dimensions::try_from([16, 16])?;
+    let mut tag = 0u8;
+    let layers = (0..layers)
+        .map(|_| {
+            let faces = CubeFace::VARIANTS.iter().map(|f| {
+                let mips = dimensions.mips().map(|d| {
+                    tag += 1;
+                    surface(d, tag)
+                });
+                (*f, Surfaces::try_from_mips(mips.collect_vec()).unwrap())
+            });
+            Surfaces::try_from_faces(faces.collect_vec())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Surfaces::try_from_layers(layers)?)
+}
+
+#[test]
+fn iter_lengths() -> Result<()> {
+    let texture = cube_array(3)?;
+
+    assert_eq!(texture.iter_layers().len(), 3);
+    assert_eq!(texture.iter_faces().len(), 6);
+    assert_eq!(texture.iter_mips().len(), 5);
+    assert_eq!(texture.iter().len(), 3 * 6 * 5);
+    assert_eq!(texture.iter().len(), texture.len());
+
+    let surface = surface(Dimensions::try_from([4, 4])?, 0);
+    assert_eq!(
+        surface.iter_layers().len(),
+        1,
+        "missing structures pad to one item"
+    );
+    assert_eq!(surface.iter().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn iter_rev() -> Result<()> {
+    let texture = cube_array(2)?;
+
+    let forward = texture.iter_mips().map(|(m, _)| m).collect_vec();
+    let mut backward = texture.iter_mips().rev().map(|(m, _)| m).collect_vec();
+    backward.reverse();
+    assert_eq!(forward, backward);
+
+    let faces = texture
+        .iter_faces()
+        .rev()
+        .map(|(f, _)| f.unwrap())
+        .collect_vec();
+    assert_eq!(faces.first(), Some(&CubeFace::NegativeZ));
+    Ok(())
+}
+
+#[test]
+fn slice_matches_owned() -> Result<()> {
+    let texture = cube_array(2)?;
+
+    let owned = texture
+        .iter()
+        .map(|r| (r.layer, r.face, r.mip, r.surface.buffer[0]))
+        .collect_vec();
+    let sliced = texture
+        .slice()
+        .iter()
+        .map(|r| (r.layer, r.face, r.mip, r.surface.buffer[0]))
+        .collect_vec();
+    assert_eq!(owned, sliced);
+
+    let mut sliced_rev = texture
+        .slice()
+        .iter()
+        .rev()
+        .map(|r| (r.layer, r.face, r.mip, r.surface.buffer[0]))
+        .collect_vec();
+    sliced_rev.reverse();
+    assert_eq!(owned, sliced_rev);
+
+    let mip = texture.slice().get_mip(2).unwrap();
+    assert_eq!(mip.mips(), None);
+    assert_eq!(mip.layers(), Some(2));
+    assert_eq!(mip.dimensions(), Dimensions::try_from([4, 4])?);
+    assert_eq!(mip.iter().len(), 2 * 6);
+    assert!(texture.slice().get_mip(5).is_none());
+    Ok(())
+}
+
+#[test]
+fn normalize_collapses() -> Result<()> {
+    let dimensions = Dimensions::try_from([1, 1])?;
+    let mip = Surfaces::try_from_mips([surface(dimensions, 0)])?;
+    let mut texture = Surfaces::try_from_layers([mip])?;
+
+    let report = texture.normalize()?;
+    assert_eq!(report.collapsed_arrays, 1);
+    assert_eq!(report.collapsed_mipmaps, 1);
+    assert!(texture.is_surface());
+
+    let report = texture.normalize()?;
+    assert!(report.is_unchanged());
+    Ok(())
+}
+
+#[test]
+fn normalize_invalid_unchanged() -> Result<()> {
+    let mut texture = TextureShapeNode::Array(vec![
+        TextureShapeNode::Array(vec![surface(Dimensions::try_from([8, 8])?, 0)]),
+        surface(Dimensions::try_from([4, 4])?, 1),
+    ]);
+    assert!(texture.normalize().is_err());
+    let TextureShapeNode::Array(layers) = &texture else {
+        panic!("texture is no longer an array")
+    };
+    assert!(matches!(&layers[0], TextureShapeNode::Array(inner) if inner.len() == 1));
+    Ok(())
+}
+
+#[test]
+fn validate_catches_hand_built_errors() -> Result<()> {
+    let texture = TextureShapeNode::Array(vec![
+        surface(Dimensions::try_from([8, 8])?, 0),
+        surface(Dimensions::try_from([4, 4])?, 1),
+    ]);
+    assert!(texture.validate().is_err());
+    assert!(TextureShapeNode::<Surface>::MipMap(vec![])
+        .validate()
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn try_iter_catches_hand_built_errors() -> Result<()> {
+    let texture = cube_array(2)?;
+    assert_eq!(texture.try_iter()?.len(), texture.len());
+    assert_eq!(texture.slice().try_iter()?.len(), texture.len());
+
+    let dimensions = Dimensions::try_from([8, 8])?;
+    let mips = vec![
+        surface(dimensions, 1),
+        surface(Dimensions::try_from([4, 4])?, 2),
+    ];
+    let mixed =
+        TextureShapeNode::Array(vec![surface(dimensions, 0), TextureShapeNode::MipMap(mips)]);
+    assert!(matches!(mixed.try_iter(), Err(ShapeError::Malformed(_))));
+    assert!(matches!(
+        mixed.slice().try_iter(),
+        Err(ShapeError::Malformed(_))
+    ));
+
+    let empty = TextureShapeNode::<Surface>::Array(vec![]);
+    assert!(empty.try_iter().is_err());
+    assert!(empty.slice().try_iter().is_err());
+    Ok(())
+}
+
+#[test]
+fn try_map_preserves_shape() -> Result<()> {
+    let texture = cube_array(2)?;
+    let mapped = texture
+        .try_map(|s| -> Result<_> { Ok(Surface::new(s.dimensions(), vec![s.buffer[0] * 2])) })?;
+
+    mapped.validate()?;
+    assert_eq!(mapped.layers(), texture.layers());
+    assert_eq!(mapped.faces(), texture.faces());
+    assert_eq!(mapped.mips(), texture.mips());
+    for (a, b) in mapped.slice().iter().zip(texture.slice().iter()) {
+        assert_eq!(a.surface.buffer[0], b.surface.buffer[0] * 2);
+    }
+
+    let failed = texture.try_map(|s| match s.buffer[0] {
+        7 => Err(s.buffer[0]),
+        _ => Ok(s.clone()),
+    });
+    assert_eq!(failed.err(), Some(7), "mapping stops at the first error");
+    Ok(())
+}
+
+#[test]
+fn reorder_layers() -> Result<()> {
+    let original = cube_array(3)?;
+    let mut texture = original.clone();
+    texture.reorder_layers(&[2, 0, 1])?;
+    texture.validate()?;
+
+    for (new, old) in [(0, 2), (1, 0), (2, 1)] {
+        let tags = |t: &Surfaces, l| {
+            t.get_layer(l)
+                .unwrap()
+                .iter()
+                .map(|s| s.surface.buffer[0])
+                .collect_vec()
+        };
+        assert_eq!(tags(&texture, new), tags(&original, old));
+    }
+
+    // invalid permutations leave the texture untouched
+    for permutation in [&[0, 1][..], &[0, 1, 3], &[0, 1, 1]] {
+        assert!(matches!(
+            texture.reorder_layers(permutation),
+            Err(ShapeError::InvalidPermutation(_))
+        ));
+    }
+    assert_eq!(texture.get_layer(0).unwrap().primary().buffer[0], 61);
+
+    let mut surface = surface(Dimensions::try_from([4, 4])?, 0);
+    assert!(surface.reorder_layers(&[0]).is_err());
+    Ok(())
+}
+
+#[test]
+fn sort_layers_by() -> Result<()> {
+    let mut texture = cube_array(3)?;
+    // sort by descending primary tag, which reverses the layers
+    let permutation = texture.sort_layers_by(|_, l| std::cmp::Reverse(l.primary().buffer[0]))?;
+    assert_eq!(permutation, [2, 1, 0]);
+    assert_eq!(texture.get_layer(0).unwrap().primary().buffer[0], 61);
+
+    // sorting is stable
+    let permutation = texture.sort_layers_by(|_, _| 0)?;
+    assert_eq!(permutation, [0, 1, 2]);
+    Ok(())
+}
+
+#[test]
+fn edit_layers() -> Result<()> {
+    let mut texture = cube_array(3)?;
+    let extra = cube_array(1)?.get_layer(0).unwrap();
+
+    texture.push_layer(extra.clone())?;
+    texture.validate()?;
+    assert_eq!(texture.layers(), Some(4));
+    assert_eq!(texture.get_layer(3).unwrap().primary().buffer[0], 1);
+
+    let removed = texture.remove_layer(1)?;
+    assert_eq!(removed.primary().buffer[0], 31);
+    assert_eq!(removed.layers(), None);
+    assert_eq!(texture.get_layer(1).unwrap().primary().buffer[0], 61);
+
+    texture.insert_layer(0, removed)?;
+    let old = texture.replace_layer(1, extra)?;
+    assert_eq!(old.primary().buffer[0], 1);
+    let primaries = (texture.iter_layers()).map(|(_, l)| l.primary().buffer[0]);
+    assert_eq!(primaries.collect_vec(), [31, 1, 61, 1]);
+    texture.validate()?;
+
+    // mismatched layers and bad indices leave the texture untouched
+    let small = surface(Dimensions::try_from([8, 8])?, 0);
+    assert!(matches!(
+        texture.push_layer(small),
+        Err(ShapeError::NonUniform(_))
+    ));
+    assert!(matches!(
+        texture.push_layer(cube_array(2)?),
+        Err(ShapeError::Nested(_))
+    ));
+    assert!(matches!(
+        texture.remove_layer(4),
+        Err(ShapeError::InvalidIndex(_))
+    ));
+    assert_eq!(texture.layers(), Some(4));
+
+    let mut single = surface(Dimensions::try_from([4, 4])?, 0);
+    assert!(matches!(
+        single.push_layer(single.clone()),
+        Err(ShapeError::InvalidIndex(_))
+    ));
+    let mut one = Surfaces::try_from_layers([single])?;
+    assert!(matches!(one.remove_layer(0), Err(ShapeError::Empty(_))));
+    Ok(())
+}
+
+#[test]
+fn edit_nested_layers() -> Result<()> {
+    // a mipmap of arrays has its array moved to the root when layers are edited
+    let dimensions = Dimensions::try_from([4, 4])?;
+    let mips = dimensions.mips().enumerate().map(|(m, d)| {
+        let layers = (0..2).map(|l| surface(d, (l * 10 + m) as u8));
+        Surfaces::try_from_layers(layers.collect_vec()).unwrap()
+    });
+    let mut texture = Surfaces::try_from_mips(mips.collect_vec())?;
+    let layer = texture.remove_layer(0)?;
+    assert!(matches!(texture, TextureShapeNode::Array(_)));
+    assert_eq!(texture.layers(), Some(1));
+    assert_eq!(texture.mips(), Some(3));
+    assert_eq!(
+        layer.iter().map(|s| s.surface.buffer[0]).collect_vec(),
+        [0, 1, 2]
+    );
+    texture.validate()?;
+    Ok(())
+}
+
+#[test]
+fn replace_mip() -> Result<()> {
+    let dimensions = Dimensions::try_from([8, 8])?;
+    let mips = dimensions
+        .mips()
+        .enumerate()
+        .map(|(m, d)| surface(d, m as u8));
+    let mut texture = Surfaces::try_from_mips(mips.collect_vec())?;
+
+    let old = texture.replace_mip(1, surface(Dimensions::try_from([4, 4])?, 9))?;
+    assert_eq!(old.primary().buffer[0], 1);
+    assert_eq!(texture.get_mip(1).unwrap().primary().buffer[0], 9);
+    texture.validate()?;
+
+    assert!(matches!(
+        texture.replace_mip(1, surface(Dimensions::try_from([2, 2])?, 0)),
+        Err(ShapeError::InvalidMipChain)
+    ));
+    assert!(matches!(
+        texture.replace_mip(4, surface(Dimensions::try_from([1, 1])?, 0)),
+        Err(ShapeError::InvalidIndex(_))
+    ));
+    assert!(cube_array(1)?
+        .replace_mip(0, surface(dimensions, 0))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn collect_all_errors() -> Result<()> {
+    // mips 3 and 5 have the wrong dimensions, and mip 5 is already a mipmap
+    let mut mips = Dimensions::try_from([32, 32])?
+        .mips()
+        .map(|d| surface(d, 0))
+        .collect_vec();
+    mips[3] = surface(Dimensions::try_from([3, 3])?, 0);
+    mips[5] = Surfaces::try_from_mips([
+        surface(Dimensions::try_from([2, 2])?, 0),
+        surface(Dimensions::try_from([1, 1])?, 0),
+    ])?;
+    let errors = Surfaces::try_from_mips_all(mips.clone()).unwrap_err();
+    let found = (errors.violations.iter())
+        .map(|v| (v.index.map(|i| i.to_string()), v.error.to_string()))
+        .collect_vec();
+    assert_eq!(errors.violations.len(), 3, "{errors}");
+    assert!(
+        matches!(
+            errors.violations[..],
+            [
+                ShapeViolation {
+                    index: Some(TextureIndex::Mip(3)),
+                    error: ShapeError::InvalidMipChain
+                },
+                ShapeViolation {
+                    index: Some(TextureIndex::Mip(5)),
+                    error: ShapeError::InvalidMipChain
+                },
+                ShapeViolation {
+                    index: Some(TextureIndex::Mip(5)),
+                    error: ShapeError::Nested(_)
+                },
+            ]
+        ),
+        "{found:?}"
+    );
+    assert!(errors.to_string().starts_with("mip 3: "));
+
+    // fixing every reported mip makes the chain valid
+    mips[3] = surface(Dimensions::try_from([4, 4])?, 0);
+    mips[5] = surface(Dimensions::try_from([1, 1])?, 0);
+    assert!(Surfaces::try_from_mips_all(mips)?.validate().is_ok());
+
+    let dimensions = Dimensions::try_from([4, 4])?;
+    let errors = Surfaces::try_from_faces_all([
+        (CubeFace::PositiveX, surface(dimensions, 0)),
+        (
+            CubeFace::NegativeX,
+            surface(Dimensions::try_from([2, 2])?, 0),
+        ),
+        (CubeFace::PositiveX, surface(dimensions, 1)),
+    ])
+    .unwrap_err();
+    assert!(matches!(
+        errors.violations[..],
+        [
+            ShapeViolation {
+                index: Some(TextureIndex::Face(CubeFace::PositiveX)),
+                error: ShapeError::DuplicateFaces
+            },
+            ShapeViolation {
+                index: Some(TextureIndex::Face(CubeFace::NegativeX)),
+                error: ShapeError::NonUniform("dimensions")
+            },
+        ]
+    ));
+
+    let errors = Surfaces::try_from_layers_all([]).unwrap_err();
+    assert!(matches!(
+        errors.violations[..],
+        [ShapeViolation {
+            index: None,
+            error: ShapeError::Empty("array")
+        }]
+    ));
+    assert!(
+        Surfaces::try_from_layers_all([surface(dimensions, 0), surface(dimensions, 1)]).is_ok()
+    );
+    Ok(())
+}
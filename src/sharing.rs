@@ -0,0 +1,263 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Encoding texture arrays so that similar layers share identical blocks.
+//!
+//! Arrays of near-identical layers, like variations of a terrain splat map, compress their layers
+//! independently into blocks that differ in their low bits even where the pixels barely do.
+//! [`Texture::encode_shared_layers`] encodes every layer normally, then replaces each block with the
+//! block at the same position in an earlier layer whenever that adds little error. The shared blocks
+//! are byte-for-byte identical, so deduplication or general-purpose compression downstream can remove them
+
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::format::{EncodeOptions, Format};
+use crate::shape::{CubeFace, TextureShape};
+use crate::texture::Texture;
+
+/// Options for [`Texture::encode_shared_layers`]
+#[derive(Clone, Copy, Debug)]
+pub struct LayerSharingOptions {
+    /// Options used to encode each layer
+    pub encode: EncodeOptions,
+
+    /// The most a block's mean squared error per channel, in 8-bit units, may grow by
+    /// when it is replaced by a block from an earlier layer
+    pub max_error_increase: f64,
+}
+
+impl Default for LayerSharingOptions {
+    fn default() -> Self {
+        Self {
+            encode: EncodeOptions::default(),
+            max_error_increase: 4.0,
+        }
+    }
+}
+
+/// How many blocks [`Texture::encode_shared_layers`] was able to share between layers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LayerSharingReport {
+    /// The number of blocks in every layer after the first, across every face and mip
+    pub blocks: usize,
+
+    /// The number of those blocks that are identical to the block at the same position in an earlier layer
+    pub shared: usize,
+
+    /// The number of shared blocks in each layer. The first layer never shares any
+    pub shared_per_layer: Vec<usize>,
+}
+
+impl LayerSharingReport {
+    /// The fraction of blocks after the first layer that are shared, from 0 to 1
+    pub fn fraction(&self) -> f64 {
+        match self.blocks {
+            0 => 0.0,
+            blocks => self.shared as f64 / blocks as f64,
+        }
+    }
+}
+
+/// The squared error of each block between two decoded 8-bit RGBA surfaces, with blocks in row order
+fn block_errors(a: &[u8], b: &[u8], dimensions: Dimensions, block: Dimensions) -> Vec<f64> {
+    let (width, height) = (dimensions.width() as usize, dimensions.height() as usize);
+    let (bw, bh) = (block.width() as usize, block.height() as usize);
+    let columns = width.div_ceil(bw);
+    let mut errors = vec![0u64; columns * height.div_ceil(bh)];
+    for y in 0..height {
+        for x in 0..width {
+            let p = (y * width + x) * 4;
+            let error: u64 = (a[p..p + 4].iter().zip(&b[p..p + 4]))
+                .map(|(&a, &b)| (a.abs_diff(b) as u64).pow(2))
+                .sum();
+            errors[(y / bh) * columns + x / bw] += error;
+        }
+    }
+    let samples = (bw * bh * 4) as f64;
+    errors.into_iter().map(|e| e as f64 / samples).collect()
+}
+
+impl Texture {
+    /// Encode an array texture into `format` so that blocks are shared between similar layers.
+    ///
+    /// Each layer is encoded normally, and then each of its blocks is replaced by the block at the same position
+    /// in an earlier layer if that is the closest of the earlier blocks to the original pixels, and raises the
+    /// block's error by no more than [`LayerSharingOptions::max_error_increase`].
+    /// Returns [`TextureError::Capability`] if the texture is not an array, or an error if either format can't
+    /// be decoded or encoded
+    pub fn encode_shared_layers(
+        &self,
+        format: Format,
+        options: LayerSharingOptions,
+    ) -> TextureResult<(Texture, LayerSharingReport)> {
+        let layers = self.layers().ok_or_else(|| {
+            TextureError::Capability(
+                "Only array textures can share blocks between layers".to_string(),
+            )
+        })?;
+        let original = self.decode()?;
+        let mut encoded = original.encode(format, options.encode)?;
+        let decoder = format.decoder()?;
+        let block = format.block_dimensions()?;
+        let block_size = format.size_for(block)?;
+
+        let mut report = LayerSharingReport {
+            shared_per_layer: vec![0; layers],
+            ..Default::default()
+        };
+        let chains: Vec<(Option<CubeFace>, Option<usize>)> = (original.slice().iter())
+            .filter(|s| s.layer == Some(0))
+            .map(|s| (s.face, s.mip))
+            .collect();
+
+        for (face, mip) in chains {
+            let surface = |texture: &Texture, layer: usize| {
+                let node = texture.surfaces.get_layer(layer)?;
+                let node = match face {
+                    Some(face) => node.get_face(face)?,
+                    None => node,
+                };
+                match mip {
+                    Some(mip) => node.get_mip(mip)?.try_into_surface(),
+                    None => node.try_into_surface(),
+                }
+            };
+            let missing =
+                || TextureError::Other(format!("Layer surface is missing at {face:?} mip {mip:?}"));
+
+            // the final encoded and decoded contents of each layer processed so far
+            let mut done: Vec<(Vec<u8>, Vec<u8>)> = Vec::with_capacity(layers);
+            for layer in 0..layers {
+                let source = surface(&original, layer).ok_or_else(missing)?;
+                let own = surface(&encoded, layer).ok_or_else(missing)?;
+                let dimensions = own.dimensions;
+                let mut bytes = own.buffer.to_vec();
+
+                if layer > 0 {
+                    let own_decoded = decoder.decode_buffer(own.buffer.clone(), dimensions)?;
+                    let own_errors =
+                        block_errors(&source.buffer, &own_decoded.to_rgba8(), dimensions, block);
+                    let candidates: Vec<Vec<f64>> = (done.iter())
+                        .map(|(_, decoded)| {
+                            block_errors(&source.buffer, decoded, dimensions, block)
+                        })
+                        .collect();
+
+                    for (b, &own_error) in own_errors.iter().enumerate() {
+                        let range = b * block_size..(b + 1) * block_size;
+                        let best = (0..done.len())
+                            .min_by(|&i, &j| candidates[i][b].total_cmp(&candidates[j][b]))
+                            .expect("earlier layers are never empty");
+                        if candidates[best][b] <= own_error + options.max_error_increase {
+                            bytes[range.clone()].copy_from_slice(&done[best].0[range.clone()]);
+                        }
+                        // blocks can also come out identical without being replaced
+                        if done
+                            .iter()
+                            .any(|(earlier, _)| earlier[range.clone()] == bytes[range.clone()])
+                        {
+                            report.shared += 1;
+                            report.shared_per_layer[layer] += 1;
+                        }
+                    }
+                    report.blocks += own_errors.len();
+                }
+
+                let decoded = decoder.decode_buffer(bytes.clone().into(), dimensions)?;
+                let decoded = decoded.into_rgba8().into_raw();
+                let target = encoded
+                    .surfaces
+                    .get_surface_mut(Some(layer), face, mip)
+                    .ok_or_else(missing)?;
+                target.buffer = bytes.clone().into();
+                done.push((bytes, decoded));
+            }
+        }
+        Ok((encoded, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use image::{Rgba, RgbaImage};
+
+    use crate::format::Quality;
+    use crate::shape::TextureShapeNode;
+
+    use super::*;
+
+    /// An array of layers that share a base pattern, with a small variation in part of each layer
+    fn splats(layers: u32) -> Result<Texture> {
+        let layers = (0..layers).map(|l| {
+            let image = RgbaImage::from_fn(16, 16, |x, y| {
+                let base = ((x * 13 + y * 7) % 64) as u8 * 3;
+                let variation = if x >= 8 && y >= 8 { (l * 40) as u8 } else { 0 };
+                Rgba([base, base.wrapping_add(variation), 100 + variation, 255])
+            });
+            Ok(
+                Texture::from_image(&image.into(), Format::rgba8(false), true, Quality::Normal)?
+                    .into_surfaces(),
+            )
+        });
+        let surfaces = TextureShapeNode::try_from_layers(layers.collect::<Result<Vec<_>>>()?)?;
+        Ok(Texture::new(Format::rgba8(false), surfaces)?)
+    }
+
+    #[test]
+    fn shares_blocks() -> Result<()> {
+        let texture = splats(3)?;
+        let bc1 = Format::BC1 { srgb: false };
+        let (shared, report) = texture.encode_shared_layers(bc1, LayerSharingOptions::default())?;
+        assert_eq!(shared.format, bc1);
+        assert_eq!(shared.layers(), Some(3));
+        // 16 + 4 + 1 + 1 + 1 blocks per layer for 16x16, 8x8, 4x4, 2x2, and 1x1 mips
+        assert_eq!(report.blocks, 2 * 23);
+        assert_eq!(report.shared_per_layer[0], 0);
+        // at least the three quarters of the top mip without any variation are shared
+        assert!(
+            report.shared_per_layer.iter().skip(1).all(|&s| s >= 12),
+            "{report:?}"
+        );
+        assert_eq!(report.shared, report.shared_per_layer.iter().sum::<usize>());
+
+        let top = |layer: usize| {
+            shared
+                .surfaces
+                .get_layer(layer)
+                .unwrap()
+                .get_mip(0)
+                .unwrap()
+                .primary()
+        };
+        assert_eq!(top(0).buffer[..8], top(2).buffer[..8]);
+        assert_ne!(
+            top(1).buffer[15 * 8..],
+            top(2).buffer[15 * 8..],
+            "varied blocks are kept"
+        );
+
+        // without any allowed error increase, blocks are only shared where sharing costs nothing
+        let strict = LayerSharingOptions {
+            max_error_increase: 0.0,
+            ..Default::default()
+        };
+        let (_, strict) = texture.encode_shared_layers(bc1, strict)?;
+        assert!(strict.shared <= report.shared);
+        assert!(report.fraction() > 0.5);
+        Ok(())
+    }
+
+    #[test]
+    fn requires_array() -> Result<()> {
+        let image = RgbaImage::new(8, 8);
+        let texture =
+            Texture::from_image(&image.into(), Format::rgba8(false), false, Quality::Normal)?;
+        assert!(matches!(
+            texture.encode_shared_layers(Format::BC1 { srgb: false }, Default::default()),
+            Err(TextureError::Capability(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,432 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Copying every surface of a texture straight into a GPU staging buffer, in the layout the target API expects.
+//!
+//! Surfaces are placed in face-major order, the subresource order of D3D12 and Vulkan, where each
+//! array slice holds every mip of one face of one layer. Rows of pixels (or blocks, for block compressed
+//! formats) are padded to the target's row pitch, and each surface starts at the target's surface alignment.
+//! Padding bytes are left as they were, so a reused staging buffer isn't cleared on every upload.
+//!
+//! [`Subresource`](crate::staging::Subresource) converts between a surface's layer, face, and mip and the subresource indices
+//! D3D12 and Vulkan use to address it in copy commands
+
+use strum::VariantArray;
+
+use crate::dimensions::Dimensions;
+use crate::error::{TextureError, TextureResult};
+use crate::shape::{CubeFace, TextureShape};
+use crate::texture::{SurfaceOrder, Texture};
+
+/// The alignments of a staging buffer, for [`Texture::copy_into`]. All values are in bytes,
+/// and an alignment of 0 or 1 adds no padding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TargetLayout {
+    /// Alignment of the pitch of each row of pixels or blocks
+    pub row_pitch: usize,
+
+    /// Alignment of the start of each surface
+    pub surface_alignment: usize,
+
+    /// Distance between the starts of consecutive array slices, where every face of every layer is a slice.
+    /// If `None`, each slice starts right after the last, aligned to [`Self::surface_alignment`]
+    pub array_stride: Option<usize>,
+}
+
+impl TargetLayout {
+    /// Tightly packed surfaces with no padding, the same as [`Texture::payload`] in face-major order
+    pub const PACKED: Self = Self {
+        row_pitch: 1,
+        surface_alignment: 1,
+        array_stride: None,
+    };
+
+    /// D3D12's requirements for copying from a buffer into a texture:
+    /// 256-byte row pitches and 512-byte aligned subresources
+    pub const D3D12: Self = Self {
+        row_pitch: 256,
+        surface_alignment: 512,
+        array_stride: None,
+    };
+
+    /// Layout that satisfies `vkCmdCopyBufferToImage` for any format: tightly packed rows,
+    /// and 16-byte aligned surfaces, which is a multiple of every texel block size
+    pub const VULKAN: Self = Self {
+        row_pitch: 1,
+        surface_alignment: 16,
+        array_stride: None,
+    };
+}
+
+/// Where a single surface was placed in a staging buffer by [`Texture::copy_into`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SurfaceFootprint {
+    /// The array layer of the surface, if the texture is an array
+    pub layer: Option<usize>,
+
+    /// The cubemap face of the surface, if the texture is a cubemap
+    pub face: Option<CubeFace>,
+
+    /// The mip level of the surface, if the texture has mipmaps
+    pub mip: Option<usize>,
+
+    /// Dimensions of the surface
+    pub dimensions: Dimensions,
+
+    /// Offset of the first row from the start of the buffer
+    pub offset: usize,
+
+    /// Distance between the starts of consecutive rows
+    pub row_pitch: usize,
+
+    /// Size of each row without padding
+    pub row_size: usize,
+
+    /// Number of rows, which for 3D surfaces covers every depth slice
+    pub rows: usize,
+}
+
+impl SurfaceFootprint {
+    /// The offset of the first byte after the surface's last row
+    pub fn end(&self) -> usize {
+        self.offset + self.row_pitch * self.rows.saturating_sub(1) + self.row_size
+    }
+
+    /// The subresource this surface is copied to. See [`Subresource::new`]
+    pub fn subresource(&self) -> Subresource {
+        Subresource::new(self.layer, self.face, self.mip)
+    }
+}
+
+/// A surface's position as D3D12 and Vulkan count them: a mip level and an array slice,
+/// where each face of a cubemap is its own slice. Slice `layer * 6 + face` holds `face` of `layer`,
+/// with faces in [`CubeFace`] order, and both APIs expect all 6 faces of a cubemap to be present
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Subresource {
+    /// The mip level, 0 for the largest
+    pub mip: u32,
+    /// The array slice, counting each face of each layer
+    pub array_slice: u32,
+}
+
+/// The mip level and array slices addressed by a Vulkan `VkImageSubresourceLayers`.
+/// The aspect mask is always the color aspect, so it is left out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VulkanSubresourceLayers {
+    /// `mipLevel`
+    pub mip_level: u32,
+    /// `baseArrayLayer`
+    pub base_array_layer: u32,
+    /// `layerCount`
+    pub layer_count: u32,
+}
+
+impl Subresource {
+    /// The subresource of the surface at `layer`, `face`, and `mip`, as found in a [`TextureIterResult`](crate::shape::TextureIterResult).
+    /// Missing positions count as 0
+    pub fn new(layer: Option<usize>, face: Option<CubeFace>, mip: Option<usize>) -> Self {
+        let faces = match face {
+            Some(_) => CubeFace::VARIANTS.len(),
+            None => 1,
+        };
+        let slice = layer.unwrap_or(0) * faces + face.map_or(0, |f| f as usize);
+        Self {
+            mip: mip.unwrap_or(0) as u32,
+            array_slice: slice as u32,
+        }
+    }
+
+    /// The layer, face, and mip of this subresource in a texture with `is_cubemap` faces,
+    /// the inverse of [`Self::new`] for textures with layers and mips
+    pub fn location(&self, is_cubemap: bool) -> (usize, Option<CubeFace>, usize) {
+        let slice = self.array_slice as usize;
+        match is_cubemap {
+            true => {
+                let faces = CubeFace::VARIANTS.len();
+                (
+                    slice / faces,
+                    Some(CubeFace::VARIANTS[slice % faces]),
+                    self.mip as usize,
+                )
+            }
+            false => (slice, None, self.mip as usize),
+        }
+    }
+
+    /// The D3D12 subresource index, as computed by `D3D12CalcSubresource` for the first plane
+    /// of a resource with `mip_levels` mips
+    pub fn d3d12_index(&self, mip_levels: u32) -> u32 {
+        self.mip + self.array_slice * mip_levels
+    }
+
+    /// The subresource with a D3D12 subresource index in a resource with `mip_levels` mips and `array_size` slices,
+    /// or `None` if the index is out of range or in a later plane
+    pub fn from_d3d12_index(index: u32, mip_levels: u32, array_size: u32) -> Option<Self> {
+        if mip_levels == 0 || index >= mip_levels.checked_mul(array_size)? {
+            return None;
+        }
+        Some(Self {
+            mip: index % mip_levels,
+            array_slice: index / mip_levels,
+        })
+    }
+
+    /// The Vulkan image subresource layers addressing just this subresource
+    pub fn vulkan_layers(&self) -> VulkanSubresourceLayers {
+        VulkanSubresourceLayers {
+            mip_level: self.mip,
+            base_array_layer: self.array_slice,
+            layer_count: 1,
+        }
+    }
+}
+
+/// Round `value` up to a multiple of `alignment`, treating 0 as 1
+fn align(value: usize, alignment: usize) -> TextureResult<usize> {
+    value
+        .checked_next_multiple_of(alignment.max(1))
+        .ok_or_else(|| TextureError::Other("Staging buffer offset overflows".to_string()))
+}
+
+impl Texture {
+    /// The number of mip levels and array slices in the texture, as D3D12 and Vulkan count them.
+    /// Every face of a cubemap counts as a slice, whether or not it is present. See [`Subresource`]
+    pub fn subresource_counts(&self) -> (u32, u32) {
+        let faces = match self.faces() {
+            Some(_) => CubeFace::VARIANTS.len(),
+            None => 1,
+        };
+        let slices = self.layers().unwrap_or(1) * faces;
+        (self.mips().unwrap_or(1) as u32, slices as u32)
+    }
+
+    /// The Vulkan image subresource layers addressing every array slice of `mip`, for copying
+    /// a whole mip level of an array or cubemap at once
+    pub fn vulkan_mip_layers(&self, mip: u32) -> VulkanSubresourceLayers {
+        VulkanSubresourceLayers {
+            mip_level: mip,
+            base_array_layer: 0,
+            layer_count: self.subresource_counts().1,
+        }
+    }
+
+    /// Place every surface of the texture in a staging buffer with `layout`, without copying anything.
+    /// The buffer needs to be at least as large as the [`SurfaceFootprint::end`] of the last footprint.
+    /// Returns an error if [`TargetLayout::array_stride`] is too small to hold an array slice
+    pub fn staging_footprints(
+        &self,
+        layout: &TargetLayout,
+    ) -> TextureResult<Vec<SurfaceFootprint>> {
+        self.check_format()?;
+        let mut footprints: Vec<SurfaceFootprint> = Vec::new();
+        let mut offset = 0;
+        let mut slice = None;
+        let mut slice_index = 0;
+        for s in SurfaceOrder::FACE_MAJOR.ordered(&self.surfaces) {
+            if slice != Some((s.layer, s.face)) {
+                if slice.is_some() {
+                    slice_index += 1;
+                }
+                slice = Some((s.layer, s.face));
+                if let Some(stride) = layout.array_stride {
+                    let start = stride.checked_mul(slice_index).ok_or_else(|| {
+                        TextureError::Other("Staging buffer offset overflows".to_string())
+                    })?;
+                    if start < offset {
+                        return Err(TextureError::Other(format!(
+                            "Array stride of {stride} bytes is too small for slices of {offset} bytes"
+                        )));
+                    }
+                    offset = start;
+                }
+            }
+
+            let dimensions = s.surface.dimensions;
+            let row_size = self
+                .format
+                .size_for(Dimensions::try_from([dimensions.width(), 1])?)?;
+            let rows = s.surface.buffer.len() / row_size;
+            let row_pitch = align(row_size, layout.row_pitch)?;
+            offset = align(offset, layout.surface_alignment)?;
+            let footprint = SurfaceFootprint {
+                layer: s.layer,
+                face: s.face,
+                mip: s.mip,
+                dimensions,
+                offset,
+                row_pitch,
+                row_size,
+                rows,
+            };
+            offset = row_pitch
+                .checked_mul(rows)
+                .and_then(|size| offset.checked_add(size))
+                .ok_or_else(|| {
+                    TextureError::Other("Staging buffer offset overflows".to_string())
+                })?;
+            footprints.push(footprint);
+        }
+        Ok(footprints)
+    }
+
+    /// The smallest staging buffer that can hold the texture in `layout`, in bytes. See [`Self::copy_into`]
+    pub fn staging_size(&self, layout: &TargetLayout) -> TextureResult<usize> {
+        let footprints = self.staging_footprints(layout)?;
+        Ok(footprints.last().map_or(0, SurfaceFootprint::end))
+    }
+
+    /// Copy every surface of the texture into `dst` in one pass, laid out as described by `layout`,
+    /// and return where each surface was placed, for issuing the GPU copy commands.
+    /// See the [module documentation](crate::staging) for the order of surfaces.
+    /// Returns an error if `dst` is smaller than [`Self::staging_size`]
+    pub fn copy_into(
+        &self,
+        dst: &mut [u8],
+        layout: TargetLayout,
+    ) -> TextureResult<Vec<SurfaceFootprint>> {
+        let footprints = self.staging_footprints(&layout)?;
+        let size = footprints.last().map_or(0, SurfaceFootprint::end);
+        if dst.len() < size {
+            return Err(TextureError::Other(format!(
+                "Staging buffer of {} bytes is too small for {size} bytes",
+                dst.len()
+            )));
+        }
+
+        let ordered = SurfaceOrder::FACE_MAJOR.ordered(&self.surfaces);
+        for (s, footprint) in ordered.iter().zip(&footprints) {
+            let rows = s.surface.buffer.chunks_exact(footprint.row_size);
+            for (i, row) in rows.enumerate() {
+                let start = footprint.offset + i * footprint.row_pitch;
+                dst[start..start + footprint.row_size].copy_from_slice(row);
+            }
+        }
+        Ok(footprints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::format::Format;
+    use crate::shape::TextureShapeNode;
+    use crate::texture::{Surface, Surfaces};
+
+    use super::*;
+
+    /// A BC1 array of `layers` layers with 4 mips, whose bytes are all the layer index plus one
+    fn array(layers: usize) -> Result<Texture> {
+        let layer = |l: usize| -> Result<Surfaces> {
+            let mips = [[8, 8], [4, 4], [2, 2], [1, 1]].map(|d| {
+                let dimensions = Dimensions::try_from(d).unwrap();
+                let size = Format::BC1 { srgb: false }.size_for(dimensions).unwrap();
+                TextureShapeNode::from_surface(Surface::new(dimensions, vec![l as u8 + 1; size]))
+            });
+            Ok(Surfaces::try_from_mips(mips)?)
+        };
+        let layers = (0..layers).map(layer).collect::<Result<Vec<_>>>()?;
+        Ok(Texture::new(
+            Format::BC1 { srgb: false },
+            Surfaces::try_from_layers(layers)?,
+        )?)
+    }
+
+    #[test]
+    fn packed() -> Result<()> {
+        let texture = array(2)?;
+        let size = texture.staging_size(&TargetLayout::PACKED)?;
+        assert_eq!(size, texture.payload(SurfaceOrder::FACE_MAJOR).len());
+
+        let mut dst = vec![0xAA; size];
+        let footprints = texture.copy_into(&mut dst, TargetLayout::PACKED)?;
+        assert_eq!(dst, texture.payload(SurfaceOrder::FACE_MAJOR));
+        assert_eq!(footprints.len(), 8);
+        assert_eq!(
+            (
+                footprints[1].offset,
+                footprints[1].row_pitch,
+                footprints[1].rows
+            ),
+            (32, 8, 1)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn aligned() -> Result<()> {
+        let texture = array(2)?;
+        let layout = TargetLayout::D3D12;
+        let mut dst = vec![0xAA; texture.staging_size(&layout)?];
+        let footprints = texture.copy_into(&mut dst, layout)?;
+
+        let offsets = footprints.iter().map(|f| f.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 512, 1024, 1536, 2048, 2560, 3072, 3584]);
+        assert_eq!(footprints[0].row_pitch, 256);
+        assert_eq!(footprints[0].rows, 2);
+        assert_eq!(dst.len(), 3584 + 8);
+
+        // second row of mip 0 of layer 0, then its padding
+        assert_eq!(dst[256..272], [1; 16]);
+        assert_eq!(dst[272], 0xAA);
+        assert_eq!(dst[2048..2056], [2; 8]);
+        Ok(())
+    }
+
+    #[test]
+    fn array_stride() -> Result<()> {
+        let texture = array(3)?;
+        let layout = TargetLayout {
+            array_stride: Some(1000),
+            ..TargetLayout::PACKED
+        };
+        let mut dst = vec![0; texture.staging_size(&layout)?];
+        let footprints = texture.copy_into(&mut dst, layout)?;
+        assert_eq!(footprints[4].offset, 1000);
+        assert_eq!(footprints[8].offset, 2000);
+        assert_eq!(dst[2000], 3);
+
+        let small = TargetLayout {
+            array_stride: Some(40),
+            ..TargetLayout::PACKED
+        };
+        assert!(texture.staging_footprints(&small).is_err());
+        assert!(texture
+            .copy_into(&mut [0; 16], TargetLayout::PACKED)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn subresources() -> Result<()> {
+        let texture = array(3)?;
+        assert_eq!(texture.subresource_counts(), (4, 3));
+        let footprints = texture.staging_footprints(&TargetLayout::PACKED)?;
+        let indices = (footprints.iter())
+            .map(|f| f.subresource().d3d12_index(4))
+            .collect::<Vec<_>>();
+        assert_eq!(indices, (0..12).collect::<Vec<_>>());
+        assert_eq!(
+            footprints[6].subresource().vulkan_layers(),
+            VulkanSubresourceLayers {
+                mip_level: 2,
+                base_array_layer: 1,
+                layer_count: 1
+            }
+        );
+        assert_eq!(texture.vulkan_mip_layers(1).layer_count, 3);
+
+        let cube = Subresource::new(Some(2), Some(CubeFace::NegativeY), Some(1));
+        assert_eq!(cube.array_slice, 15);
+        assert_eq!(cube.d3d12_index(5), 76);
+        assert_eq!(Subresource::from_d3d12_index(76, 5, 18), Some(cube));
+        assert_eq!(cube.location(true), (2, Some(CubeFace::NegativeY), 1));
+        assert_eq!(cube.location(false), (15, None, 1));
+        assert_eq!(Subresource::new(None, None, None), Subresource::default());
+
+        assert_eq!(Subresource::from_d3d12_index(90, 5, 18), None);
+        assert_eq!(Subresource::from_d3d12_index(0, 0, 18), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Approximate color statistics of BC textures, computed from block endpoints without decoding.
+//!
+//! Every pixel in a BC block is interpolated between the block's endpoints, so the endpoints bound the
+//! colors in the block, and their midpoint approximates the block's average. Reading only the endpoints
+//! skips the indices entirely, which makes auditing the brightness or roughness of thousands of files cheap.
+//!
+//! The results are approximate. The minimum and maximum can be wider than the actual pixels when a block
+//! doesn't use both of its endpoints, and the average assumes each block's pixels are spread evenly between
+//! them. BC7 channel rotation is ignored. BC1 punch-through transparency is counted exactly with a bit mask
+//! over the indices, and BC2 stores alpha for each pixel instead of with endpoints, so its alpha statistics are exact
+
+use crate::blocktexture::Block;
+use crate::bptc::bc7::{BC7Block, MODES};
+use crate::color::{Color, ColorImpl};
+use crate::dimensions::{Dimensioned, Dimensions};
+use crate::error::{TextureError, TextureResult};
+use crate::format::Format;
+use crate::s3tc::bc4::BC4Block;
+use crate::shape::CubeFace;
+use crate::texture::Texture;
+
+/// Approximate statistics of the colors in a set of blocks, from their endpoints. See the [module docs](self)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorStats {
+    /// The lowest value of each RGBA channel
+    pub min: Color,
+    /// The highest value of each RGBA channel
+    pub max: Color,
+    /// The average value of each RGBA channel, from 0 to 255
+    pub mean: [f64; 4],
+    /// Number of blocks sampled
+    pub blocks: usize,
+}
+
+impl Default for ColorStats {
+    fn default() -> Self {
+        Self {
+            min: Color::fill(u8::MAX),
+            max: Color::fill(0),
+            mean: [0.0; 4],
+            blocks: 0,
+        }
+    }
+}
+
+impl ColorStats {
+    /// Combine the statistics of two sets of blocks, weighting the averages by their block counts
+    pub fn merge(&self, other: &Self) -> Self {
+        let blocks = self.blocks + other.blocks;
+        let weight = |a: f64, b: f64| match blocks {
+            0 => 0.0,
+            _ => (a * self.blocks as f64 + b * other.blocks as f64) / blocks as f64,
+        };
+        Self {
+            min: Color::vec(std::array::from_fn(|c| self.min[c].min(other.min[c]))),
+            max: Color::vec(std::array::from_fn(|c| self.max[c].max(other.max[c]))),
+            mean: std::array::from_fn(|c| weight(self.mean[c], other.mean[c])),
+            blocks,
+        }
+    }
+
+    /// The luminance of the average color with Rec. 709 weights, from 0 to 255.
+    /// This is computed from encoded values, so it is a perceptual brightness for sRGB textures
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.mean[0] + 0.7152 * self.mean[1] + 0.0722 * self.mean[2]
+    }
+
+    /// Statistics of a single block with the given endpoint colors
+    fn of_endpoints(colors: &[Color]) -> Self {
+        let mut stats = Self {
+            blocks: 1,
+            ..Self::default()
+        };
+        for color in colors {
+            for c in 0..4 {
+                stats.min[c] = stats.min[c].min(color[c]);
+                stats.max[c] = stats.max[c].max(color[c]);
+                stats.mean[c] += color[c] as f64 / colors.len() as f64;
+            }
+        }
+        stats
+    }
+
+    /// Replace the statistics of one channel with those of a block of single-channel values
+    fn with_channel(mut self, channel: usize, values: &[u8]) -> Self {
+        self.min[channel] = values.iter().copied().min().unwrap_or_default();
+        self.max[channel] = values.iter().copied().max().unwrap_or_default();
+        self.mean[channel] =
+            values.iter().map(|&v| v as f64).sum::<f64>() / values.len().max(1) as f64;
+        self
+    }
+}
+
+/// Approximate color statistics for a single surface, from [`Texture::endpoint_stats`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurfaceStats {
+    /// The array layer of the surface, if the texture is an array
+    pub layer: Option<usize>,
+
+    /// The cubemap face of the surface, if the texture is a cubemap
+    pub face: Option<CubeFace>,
+
+    /// The mip level of the surface, if the texture has mipmaps
+    pub mip: Option<usize>,
+
+    /// Dimensions of the surface
+    pub dimensions: Dimensions,
+
+    /// Statistics of the sampled blocks
+    pub stats: ColorStats,
+}
+
+/// Approximate color statistics for every surface of a texture, from [`Texture::endpoint_stats`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsReport {
+    /// Statistics for each surface, in the same order as [`TextureSlice::iter`](crate::shape::TextureSlice::iter)
+    pub surfaces: Vec<SurfaceStats>,
+}
+
+impl StatsReport {
+    /// Statistics of every sampled block in the texture. Every block is weighted equally,
+    /// so the largest surfaces dominate the average
+    pub fn total(&self) -> ColorStats {
+        self.surfaces
+            .iter()
+            .fold(ColorStats::default(), |total, s| total.merge(&s.stats))
+    }
+}
+
+/// The two endpoint colors of a BC1 color block
+fn bc1_endpoints(block: &[u8]) -> [Color; 2] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    [Color::from_565(c0), Color::from_565(c1)]
+}
+
+/// Statistics of a single BC1 block, including any transparent pixels in 3-color mode
+fn bc1_stats(block: &[u8]) -> ColorStats {
+    let endpoints = bc1_endpoints(block);
+    let stats = ColorStats::of_endpoints(&endpoints);
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    if c0 > c1 {
+        return stats;
+    }
+
+    // in 3-color mode, index 3 is transparent black. Each pixel with both of its index bits set is one
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let transparent = (indices & (indices >> 1) & 0x5555_5555).count_ones();
+    if transparent == 0 {
+        return stats;
+    }
+    let opaque = 1.0 - transparent as f64 / 16.0;
+    ColorStats {
+        min: Color::default(),
+        mean: stats.mean.map(|m| m * opaque),
+        ..stats
+    }
+}
+
+/// The two endpoint values of a BC4 block, as unsigned bytes
+fn bc4_endpoints<const SIGNED: bool>(block: &[u8]) -> [u8; 2] {
+    let block = BC4Block::<SIGNED>::from_bytes(block.try_into().expect("BC4 blocks are 8 bytes"));
+    let palette = block.palette();
+    [palette[0], palette[1]]
+}
+
+/// Statistics of a single BC7 block. The reserved mode decodes to transparent black
+fn bc7_stats(block: &[u8]) -> ColorStats {
+    let block = BC7Block::from_bytes(block.try_into().expect("BC7 blocks are 16 bytes"));
+    match block.mode() {
+        None => ColorStats::of_endpoints(&[Color::default()]),
+        Some(mode) => {
+            let colors = block.colors();
+            ColorStats::of_endpoints(colors[..MODES[mode].subsets].as_flattened())
+        }
+    }
+}
+
+/// If blocks in `format` store endpoints this module can read, which is true of BC1 through BC5 and BC7
+pub(crate) fn has_endpoints(format: Format) -> bool {
+    matches!(
+        format,
+        Format::BC1 { .. }
+            | Format::BC2 { .. }
+            | Format::BC3 { .. }
+            | Format::BC4 { .. }
+            | Format::BC5 { .. }
+            | Format::BC7 { .. }
+    )
+}
+
+/// Statistics of a single block in `format`, which must be one of the BC formats
+fn block_stats(format: Format, block: &[u8]) -> ColorStats {
+    // single-channel formats decode with 0 in their missing color channels, and are always opaque
+    let channels = |r: [u8; 2], g: [u8; 2]| {
+        ColorStats::of_endpoints(&[
+            Color::vec([r[0], g[0], 0, u8::MAX]),
+            Color::vec([r[1], g[1], 0, u8::MAX]),
+        ])
+    };
+    match format {
+        Format::BC1 { .. } => bc1_stats(block),
+        Format::BC2 { .. } => {
+            let alpha = block[..8]
+                .iter()
+                .flat_map(|&byte| [(byte & 0x0F) * 17, (byte >> 4) * 17])
+                .collect::<Vec<_>>();
+            ColorStats::of_endpoints(&bc1_endpoints(&block[8..])).with_channel(3, &alpha)
+        }
+        Format::BC3 { .. } => ColorStats::of_endpoints(&bc1_endpoints(&block[8..]))
+            .with_channel(3, &bc4_endpoints::<false>(&block[..8])),
+        Format::BC4 { signed: false } => channels(bc4_endpoints::<false>(block), [0; 2]),
+        Format::BC4 { signed: true } => channels(bc4_endpoints::<true>(block), [0; 2]),
+        Format::BC5 { signed: false } => channels(
+            bc4_endpoints::<false>(&block[..8]),
+            bc4_endpoints::<false>(&block[8..]),
+        ),
+        Format::BC5 { signed: true } => channels(
+            bc4_endpoints::<true>(&block[..8]),
+            bc4_endpoints::<true>(&block[8..]),
+        ),
+        Format::BC7 { .. } => bc7_stats(block),
+        _ => unreachable!("endpoint statistics are only computed for BC formats"),
+    }
+}
+
+impl Texture {
+    /// Approximate the color statistics of each surface from block endpoints, without decoding.
+    /// Only every `step`th block of each surface is sampled, so a `step` of 1 reads every block and
+    /// larger steps trade accuracy for speed. See the [module docs](crate::stats) for how the results are approximated.
+    /// Returns [`TextureError::Format`] for formats other than BC1 through BC5 and BC7,
+    /// or an error if any surface does not match the texture's format
+    pub fn endpoint_stats(&self, step: usize) -> TextureResult<StatsReport> {
+        if !has_endpoints(self.format) {
+            return Err(TextureError::Format(format!(
+                "Endpoint statistics are only available for BC textures, not {:?}",
+                self.format
+            )));
+        }
+        self.check_format()?;
+        let block_size = self.format.size_for(Dimensions::try_from([1])?)?;
+        let surfaces = self
+            .slice()
+            .iter()
+            .map(|s| SurfaceStats {
+                layer: s.layer,
+                face: s.face,
+                mip: s.mip,
+                dimensions: s.surface.dimensions(),
+                stats: s
+                    .surface
+                    .buffer
+                    .chunks_exact(block_size)
+                    .step_by(step.max(1))
+                    .fold(ColorStats::default(), |stats, block| {
+                        stats.merge(&block_stats(self.format, block))
+                    }),
+            })
+            .collect();
+        Ok(StatsReport { surfaces })
+    }
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use std::fs::File;
+
+    use anyhow::Result;
+
+    use crate::container::ContainerHeader;
+    use crate::dds::DDSHeader;
+    use crate::format::Quality;
+    use crate::shape::TextureShapeNode;
+    use crate::texture::Surface;
+
+    use super::*;
+
+    fn read_peppers(name: &str) -> Result<Texture> {
+        let path = format!(
+            "{}/tests/images/dds/peppers16 {name}.dds",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        Ok(DDSHeader::read_texture(&mut File::open(path)?)?)
+    }
+
+    /// Exact statistics of a decoded texture, for comparing against the approximation
+    fn decoded_stats(texture: &Texture) -> Result<ColorStats> {
+        let decoded = texture.decode()?;
+        let pixels = decoded
+            .slice()
+            .iter()
+            .next()
+            .unwrap()
+            .surface
+            .buffer
+            .clone();
+        let colors = pixels
+            .chunks_exact(4)
+            .map(|p| Color::vec([p[0], p[1], p[2], p[3]]))
+            .collect::<Vec<_>>();
+        Ok(ColorStats::of_endpoints(&colors))
+    }
+
+    #[test]
+    fn approximates_decoded() -> Result<()> {
+        for name in ["bc1", "bc4", "bc5"] {
+            let texture = read_peppers(name)?;
+            let report = texture.endpoint_stats(1)?;
+            // compare the largest mip, which is the first surface
+            let approx = report.surfaces[0].stats;
+            let exact = decoded_stats(&texture)?;
+            assert_eq!(approx.blocks, 16, "{name}");
+            for c in 0..4 {
+                assert!(approx.min[c] <= exact.min[c], "{name} channel {c}");
+                assert!(approx.max[c] >= exact.max[c], "{name} channel {c}");
+                assert!(
+                    (approx.mean[c] - exact.mean[c]).abs() < 16.0,
+                    "{name} channel {c}: {} vs {}",
+                    approx.mean[c],
+                    exact.mean[c]
+                );
+            }
+
+            // sampling fewer blocks is still in the right neighborhood
+            let sampled = texture.endpoint_stats(3)?.surfaces[0].stats;
+            assert_eq!(sampled.blocks, 6);
+            assert!((sampled.luminance() - exact.luminance()).abs() < 32.0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn blocks() -> Result<()> {
+        let dimensions = Dimensions::try_from([8, 4])?;
+        let texture = |format, buffer: Vec<u8>| {
+            Texture::new(
+                format,
+                TextureShapeNode::from_surface(Surface::new(dimensions, buffer)),
+            )
+        };
+
+        // a black to white block and a solid red block
+        let mut bc1 = vec![0xFF, 0xFF, 0x00, 0x00, 0, 0, 0, 0];
+        bc1.extend([0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0]);
+        let stats = texture(Format::BC1 { srgb: false }, bc1)?
+            .endpoint_stats(1)?
+            .total();
+        assert_eq!(stats.min, Color::vec([0, 0, 0, 255]));
+        assert_eq!(stats.max, Color::vec([255, 255, 255, 255]));
+        assert_eq!(stats.mean, [191.25, 63.75, 63.75, 255.0]);
+
+        // BC2 alpha is read from every pixel
+        let mut bc2 = vec![0x0F; 8];
+        bc2.extend([0x00; 8]);
+        bc2.extend([0xF0; 8]);
+        bc2.extend([0x00; 8]);
+        let stats = texture(Format::BC2 { srgb: false }, bc2)?
+            .endpoint_stats(1)?
+            .total();
+        assert_eq!((stats.min[3], stats.max[3]), (0, 255));
+        assert_eq!(stats.mean[3], 127.5);
+
+        // BC7 encodes a solid color with endpoints close to that color
+        let solid = Texture::new(
+            Format::rgba8(false),
+            TextureShapeNode::from_surface(Surface::new(
+                dimensions,
+                [40u8, 80, 120, 255].repeat(32),
+            )),
+        )?
+        .transcode(Format::BC7 { srgb: false }, Quality::Fastest)?;
+        let stats = solid.endpoint_stats(1)?.total();
+        for (c, value) in [40.0, 80.0, 120.0, 255.0].into_iter().enumerate() {
+            assert!((stats.mean[c] - value).abs() < 4.0, "channel {c}");
+        }
+
+        let rgba = Texture::new(
+            Format::rgba8(false),
+            TextureShapeNode::from_surface(Surface::new(dimensions, vec![0u8; 128])),
+        )?;
+        assert!(matches!(
+            rgba.endpoint_stats(1),
+            Err(TextureError::Format(_))
+        ));
+        Ok(())
+    }
+}
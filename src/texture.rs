@@ -3,20 +3,30 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use std::fmt::{Debug, Formatter};
-use std::io::Read;
+use std::io::{Read, Write};
 use std::rc::Rc;
 
-use itertools::Itertools;
+#[cfg(feature = "image")]
+use image::{DynamicImage, GrayImage};
+use itertools::{iproduct, Itertools};
 
 use crate::dimensions::{Dimensioned, Dimensions};
-use crate::error::TextureResult;
+use crate::error::{TextureError, TextureResult};
 use crate::format::Format;
-use crate::shape::{CubeFace, ShapeError, TextureIndex, TextureShape, TextureShapeNode};
+#[cfg(feature = "image")]
+use crate::format::{DecodeOptions, Decoder, EncodeOptions, Quality, RoiQuality};
+#[cfg(feature = "image")]
+use crate::resize::resize;
+use crate::shape::{
+    CubeFace, ShapeError, ShapeErrors, ShapeResult, ShapeViolation, TextureIndex,
+    TextureIterResult, TextureShape, TextureShapeNode, TextureSlice,
+};
 
 /// A single surface of a [`Texture`], consisting of dimensions and a buffer of bytes
 #[derive(Clone)]
 pub struct Surface {
     pub(crate) dimensions: Dimensions,
+    /// The encoded contents of the surface
     pub buffer: Rc<[u8]>,
 }
 
@@ -33,26 +43,358 @@ impl Debug for Surface {
     }
 }
 
+impl Surface {
+    /// Create a new surface from its dimensions and a buffer of encoded data.
+    /// The buffer is not checked against any format until it becomes part of a [`Texture`]
+    pub fn new(dimensions: Dimensions, buffer: impl Into<Rc<[u8]>>) -> Self {
+        Self {
+            dimensions,
+            buffer: buffer.into(),
+        }
+    }
+
+    /// Check that this surface's buffer is exactly the size required to hold its dimensions in `format`.
+    /// A mismatch usually means the surface was encoded with a different format than the texture it is part of.
+    pub fn check_format(&self, format: Format) -> TextureResult<()> {
+        let expected = format.size_for(self.dimensions)?;
+        if self.buffer.len() != expected {
+            return Err(TextureError::SurfaceSize {
+                format,
+                dimensions: self.dimensions,
+                expected,
+                actual: self.buffer.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Decode this surface from `format` into a new surface in [`Format::rgba8`].
+    /// Returns [`TextureError::Format`] if the format does not have a decoder, or the buffer is the wrong size
+    #[cfg(feature = "image")]
+    pub fn decode(&self, format: Format) -> TextureResult<Surface> {
+        self.check_format(format)?;
+        self.decode_with(format.decoder()?.as_ref())
+    }
+
+    #[cfg(feature = "image")]
+    fn decode_with(&self, decoder: &dyn Decoder) -> TextureResult<Surface> {
+        let image = decoder.decode_buffer(self.buffer.clone(), self.dimensions)?;
+        Ok(Surface::new(self.dimensions, image.into_rgba8().into_raw()))
+    }
+}
+
+/// A horizontal strip of whole block rows from a [`Surface`], with its own copy of the encoded data.
+/// Chunks don't share anything with each other or the surface they came from, so they can be
+/// modified in parallel and put back together with [`Surface::from_chunks`]. See [`Surface::chunks`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SurfaceChunk {
+    /// The first pixel row of the chunk within its surface
+    pub y: u32,
+    /// The size of the chunk in pixels, with the same width as its surface
+    pub dimensions: Dimensions,
+    /// The encoded contents of the chunk
+    pub buffer: Vec<u8>,
+}
+
+impl Surface {
+    /// Split this surface into chunks of `rows_per_chunk` block rows each, for processing rows in parallel.
+    /// Chunks are in order from top to bottom, and the last chunk is shorter if the rows don't divide evenly.
+    /// A 1D surface is a single row.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use quicktex::prelude::*;
+    /// use quicktex::Surface;
+    ///
+    /// // a 4x12 BC1 surface is 3 rows of 1 block
+    /// let format = Format::BC1 { srgb: false };
+    /// let surface = Surface::new(Dimensions::try_from([4, 12])?, vec![0u8; 24]);
+    /// let mut chunks = surface.chunks(format, 2)?;
+    /// assert_eq!(chunks.len(), 2);
+    ///
+    /// chunks.par_iter_mut().for_each(|c| c.buffer.fill(c.y as u8));
+    /// let surface = Surface::from_chunks(format, surface.dimensions(), chunks)?;
+    /// assert_eq!(surface.buffer[..16], [0u8; 16]);
+    /// assert_eq!(surface.buffer[16..], [8u8; 8]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// Returns an error if `rows_per_chunk` is 0, the surface is 3D,
+    /// or the surface is the wrong size for `format`
+    pub fn chunks(
+        &self,
+        format: Format,
+        rows_per_chunk: usize,
+    ) -> TextureResult<Vec<SurfaceChunk>> {
+        if rows_per_chunk == 0 {
+            return Err(TextureError::Other(
+                "A chunk needs at least one row".to_string(),
+            ));
+        }
+        if self.dimensions.depth() > 1 {
+            return Err(TextureError::Capability(
+                "3D surfaces cannot be split into row chunks".to_string(),
+            ));
+        }
+        self.check_format(format)?;
+        if let Dimensions::_1D(_) = self.dimensions {
+            return Ok(vec![SurfaceChunk {
+                y: 0,
+                dimensions: self.dimensions,
+                buffer: self.buffer.to_vec(),
+            }]);
+        }
+
+        let (width, height) = (self.dimensions.width(), self.dimensions.height());
+        let chunk_height = (format.block_dimensions()?.height() as usize)
+            .saturating_mul(rows_per_chunk)
+            .min(height as usize) as u32;
+        let mut offset = 0;
+        (0..height)
+            .step_by(chunk_height as usize)
+            .map(|y| {
+                let dimensions = Dimensions::try_from([width, chunk_height.min(height - y)])?;
+                let size = format.size_for(dimensions)?;
+                let buffer = self.buffer[offset..offset + size].to_vec();
+                offset += size;
+                Ok(SurfaceChunk {
+                    y,
+                    dimensions,
+                    buffer,
+                })
+            })
+            .collect()
+    }
+
+    /// Put chunks from [`Surface::chunks`] back together into a surface with the given dimensions.
+    /// Returns an error if the chunks are out of order, overlap, leave gaps,
+    /// or the result is the wrong size for `format`
+    pub fn from_chunks(
+        format: Format,
+        dimensions: Dimensions,
+        chunks: impl IntoIterator<Item = SurfaceChunk>,
+    ) -> TextureResult<Surface> {
+        let mut buffer = Vec::new();
+        let mut y = 0;
+        for chunk in chunks {
+            if chunk.y != y || chunk.dimensions.width() != dimensions.width() {
+                return Err(TextureError::Other(format!(
+                    "Chunk at row {} with dimensions {:?} does not continue a {dimensions:?} surface at row {y}",
+                    chunk.y, chunk.dimensions
+                )));
+            }
+            y += chunk.dimensions.height();
+            buffer.extend_from_slice(&chunk.buffer);
+        }
+        let surface = Surface::new(dimensions, buffer);
+        surface.check_format(format)?;
+        Ok(surface)
+    }
+}
+
 impl Dimensioned for Surface {
     fn dimensions(&self) -> Dimensions {
         self.dimensions
     }
 }
 
+/// A tree of [`Surface`]s, as stored in a [`Texture`]
 pub type Surfaces = TextureShapeNode<Surface>;
 
+/// The order a container stores the surfaces of a texture in, from the outermost loop to the innermost.
+/// Cubemap faces are always stored in [`CubeFace`] order, starting with +X
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SurfaceOrder {
+    /// Each layer, then each face of that layer, then each mip of that face. Used by DDS
+    #[default]
+    LayersFacesMips,
+    /// Each mip, then each layer at that mip, then each face of that layer. Used by KTX
+    MipsLayersFaces,
+}
+
+impl SurfaceOrder {
+    /// The face-major order, where all the mips of a face are stored together. Used by DDS and Direct3D
+    pub const FACE_MAJOR: Self = SurfaceOrder::LayersFacesMips;
+
+    /// The mip-major order, where each mip of every layer and face is stored together,
+    /// as expected by engines that upload a texture one mip level at a time. Used by KTX
+    pub const MIP_MAJOR: Self = SurfaceOrder::MipsLayersFaces;
+
+    /// The sort key of a surface at the given position in this order
+    fn key(self, layer: usize, face: usize, mip: usize) -> (usize, usize, usize) {
+        match self {
+            SurfaceOrder::LayersFacesMips => (layer, face, mip),
+            SurfaceOrder::MipsLayersFaces => (mip, layer, face),
+        }
+    }
+
+    /// Every `(layer, face, mip)` index of a texture with the given counts, in this order.
+    /// Indices are produced lazily, so huge counts from a hostile header don't allocate anything
+    fn positions(
+        self,
+        layers: usize,
+        faces: usize,
+        mips: usize,
+    ) -> Box<dyn Iterator<Item = (usize, usize, usize)>> {
+        match self {
+            SurfaceOrder::LayersFacesMips => Box::new(iproduct!(0..layers, 0..faces, 0..mips)),
+            SurfaceOrder::MipsLayersFaces => {
+                Box::new(iproduct!(0..mips, 0..layers, 0..faces).map(|(m, l, f)| (l, f, m)))
+            }
+        }
+    }
+
+    /// Where each surface of a texture in `format` with the shape in `counts` is stored, if they are stored
+    /// in this order with no padding starting at `start`. Surfaces are listed in the order they are stored,
+    /// and the iterator stops early if an offset would overflow a `u64`
+    pub fn offsets(
+        self,
+        format: Format,
+        counts: &SurfaceCounts,
+        start: u64,
+    ) -> TextureResult<Box<dyn Iterator<Item = SurfaceOffset>>> {
+        let faces = counts
+            .faces
+            .clone()
+            .map(|f| f.into_iter().sorted().collect_vec());
+        let (layer_count, mip_count) = (counts.layers.unwrap_or(1), counts.mips.unwrap_or(1));
+        let face_count = faces.as_ref().map_or(1, Vec::len);
+        let sizes = counts
+            .dimensions
+            .mips()
+            .take(mip_count)
+            .map(|d| format.size_for(d))
+            .collect::<TextureResult<Vec<_>>>()?;
+        if sizes.len() < mip_count {
+            return Err(ShapeError::Malformed("more mips than the dimensions allow").into());
+        }
+
+        let (has_layers, has_mips) = (counts.layers.is_some(), counts.mips.is_some());
+        let offsets = self.positions(layer_count, face_count, mip_count).scan(
+            start,
+            move |offset, (l, f, m)| {
+                let surface = SurfaceOffset {
+                    layer: has_layers.then_some(l),
+                    face: faces.as_ref().map(|faces| faces[f]),
+                    mip: has_mips.then_some(m),
+                    offset: *offset,
+                    length: sizes[m],
+                };
+                *offset = offset.checked_add(sizes[m] as u64)?;
+                Some(surface)
+            },
+        );
+        Ok(Box::new(offsets))
+    }
+
+    /// List every surface in `surfaces` in this order, along with its position in the texture
+    pub fn ordered(self, surfaces: &Surfaces) -> Vec<TextureIterResult<&Surface>> {
+        surfaces
+            .slice()
+            .iter()
+            .sorted_by_key(|s| self.surface_key(s))
+            .collect()
+    }
+
+    /// The sort key of a surface from a texture iterator in this order
+    fn surface_key(self, s: &TextureIterResult<&Surface>) -> (usize, usize, usize) {
+        self.key(
+            s.layer.unwrap_or(0),
+            s.face.map_or(0, |f| f as usize),
+            s.mip.unwrap_or(0),
+        )
+    }
+
+    /// Convert a raw payload of every surface of a texture in `format` with the shape in `counts`
+    /// from this order to `order`, such as from the face-major order of a DDS file to the mip-major order
+    /// an engine uploads in. Returns an error if `payload` is not exactly the size of the texture
+    pub fn reorder_payload(
+        self,
+        order: SurfaceOrder,
+        payload: &[u8],
+        format: Format,
+        counts: &SurfaceCounts,
+    ) -> TextureResult<Vec<u8>> {
+        let mut reader = payload;
+        let surfaces = SurfaceReader {
+            format,
+            reader: &mut reader,
+        }
+        .read_shaped(self, counts)?;
+        if !reader.is_empty() {
+            return Err(TextureError::Other(format!(
+                "Payload has {} bytes left over after reading every surface",
+                reader.len()
+            )));
+        }
+
+        let mut reordered = Vec::with_capacity(payload.len());
+        SurfaceWriter::new(&mut reordered, 0).write_shaped(order, &surfaces)?;
+        Ok(reordered)
+    }
+}
+
+/// The shape of a texture stored in a container, as read from its header. See [`SurfaceReader::read_shaped`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SurfaceCounts {
+    /// Dimensions of the largest mip
+    pub dimensions: Dimensions,
+    /// Number of array layers, if the texture is an array
+    pub layers: Option<usize>,
+    /// Cubemap faces present, if the texture is a cubemap, in any order
+    pub faces: Option<Vec<CubeFace>>,
+    /// Number of mips, if the texture is mipmapped
+    pub mips: Option<usize>,
+}
+
+impl SurfaceCounts {
+    /// Get the shape of an existing tree of surfaces
+    pub fn of(surfaces: &Surfaces) -> Self {
+        Self {
+            dimensions: surfaces.dimensions(),
+            layers: surfaces.layers(),
+            faces: surfaces.faces(),
+            mips: surfaces.mips(),
+        }
+    }
+}
+
+/// Where a surface's data is stored in a container file. See [`ContainerHeader::surface_offsets`](crate::container::ContainerHeader::surface_offsets)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SurfaceOffset {
+    /// The array layer of the surface, if the texture is an array
+    pub layer: Option<usize>,
+    /// The cubemap face of the surface, if the texture is a cubemap
+    pub face: Option<CubeFace>,
+    /// The mip level of the surface, if the texture has mipmaps
+    pub mip: Option<usize>,
+    /// Offset of the surface's first byte from the start of the header
+    pub offset: u64,
+    /// Size of the surface in bytes
+    pub length: usize,
+}
+
 /// Struct to simplify reading a texture from a file
 pub struct SurfaceReader<'a, R: Read> {
+    /// The format of each surface to read
     pub format: Format,
+    /// The reader to read surface data from
     pub reader: &'a mut R,
 }
 
 impl<'a, R: Read> SurfaceReader<'a, R> {
     /// Read a single surface from a binary reader using the given dimensions
     pub fn read_surface(&mut self, dimensions: Dimensions) -> TextureResult<Surfaces> {
-        let size = self.format.size_for(dimensions);
-        let mut buffer: Vec<u8> = vec![0; size];
-        self.reader.read_exact(&mut buffer[..])?; // read into the vec buffer
+        let size = self.format.size_for(dimensions)?;
+        // read through `take` instead of allocating `size` bytes up front,
+        // so a header claiming a huge surface can't allocate more than the file actually contains
+        let mut buffer: Vec<u8> = Vec::new();
+        (&mut self.reader)
+            .take(size as u64)
+            .read_to_end(&mut buffer)?;
+        if buffer.len() != size {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
         let buffer = Rc::<[u8]>::from(buffer); // move buffer contents into an RC WITH A COPY
 
         // doing this without a copy without `new_uninit` appears to be impossible
@@ -62,6 +404,47 @@ impl<'a, R: Read> SurfaceReader<'a, R> {
         return Ok(surfaces);
     }
 
+    /// Read every surface of a texture with the shape in `counts`, stored in `order`.
+    /// This is a single entry point for containers, instead of nesting [`Self::read_layers`],
+    /// [`Self::read_faces`], and [`Self::read_mips`] by hand
+    pub fn read_shaped(
+        &mut self,
+        order: SurfaceOrder,
+        counts: &SurfaceCounts,
+    ) -> TextureResult<Surfaces> {
+        let faces = counts
+            .faces
+            .clone()
+            .map(|f| f.into_iter().sorted().collect_vec());
+        let (layer_count, mip_count) = (counts.layers.unwrap_or(1), counts.mips.unwrap_or(1));
+        let face_count = faces.as_ref().map_or(1, Vec::len);
+        let mip_dimensions = counts.dimensions.mips().take(mip_count).collect_vec();
+        if mip_dimensions.len() < mip_count {
+            return Err(ShapeError::Malformed("more mips than the dimensions allow").into());
+        }
+
+        // read surfaces in file order, then assemble them in the order the tree is built.
+        // positions are visited lazily, so a header claiming a huge number of layers can't allocate
+        // more than the surfaces actually in the file
+        let mut read = Vec::new();
+        for (l, f, m) in order.positions(layer_count, face_count, mip_count) {
+            let index = (l * face_count + f) * mip_count + m;
+            read.push((index, self.read_surface(mip_dimensions[m])?));
+        }
+        read.sort_unstable_by_key(|(index, _)| *index);
+        let mut read = read.into_iter().map(|(_, surface)| surface);
+
+        self.read_layers(counts.dimensions, counts.layers, |r, d| {
+            r.read_faces(d, faces.clone(), |r, d| {
+                r.read_mips(d, counts.mips, |_, _| {
+                    Ok(read
+                        .next()
+                        .ok_or(ShapeError::Malformed("surface is missing"))?)
+                })
+            })
+        })
+    }
+
     /// Construct a mipmap out of the textures produced by `inner`, or short circuit to `inner` if `mip_count` is [`None`]
     pub fn read_mips<F>(
         &mut self,
@@ -126,13 +509,538 @@ impl<'a, R: Read> SurfaceReader<'a, R> {
     }
 }
 
+/// Struct to simplify writing a texture to a file, mirroring [`SurfaceReader`]
+pub struct SurfaceWriter<'a, W: Write> {
+    /// The writer to write surface data to
+    pub writer: &'a mut W,
+    /// Offset of the writer from the start of the file, which alignment is measured from.
+    /// This is advanced by every byte written, including padding
+    pub offset: u64,
+}
+
+impl<'a, W: Write> SurfaceWriter<'a, W> {
+    /// Create a surface writer for a writer positioned at `offset` bytes from the start of the file
+    pub fn new(writer: &'a mut W, offset: u64) -> Self {
+        Self { writer, offset }
+    }
+
+    /// Write every surface in `surfaces` in `order`, with no padding
+    pub fn write_shaped(&mut self, order: SurfaceOrder, surfaces: &Surfaces) -> TextureResult<()> {
+        for s in order.ordered(surfaces) {
+            self.write_bytes(&s.surface.buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Write every surface in `surfaces` in `order`, using `padding` to insert zero bytes between
+    /// levels, surfaces, and block rows of `format`. See [`SurfacePadding`]
+    pub fn write_shaped_padded<P: SurfacePadding>(
+        &mut self,
+        order: SurfaceOrder,
+        surfaces: &Surfaces,
+        format: Format,
+        padding: &mut P,
+    ) -> TextureResult<()> {
+        let mut level = None;
+        for s in order.ordered(surfaces) {
+            let (outer, _, _) = order.surface_key(&s);
+            if level != Some(outer) {
+                level = Some(outer);
+                let pad = padding.before_level(outer, self.offset);
+                self.write_zeros(pad)?;
+            }
+            let pad = padding.before_surface(&s, self.offset);
+            self.write_zeros(pad)?;
+
+            let surface = s.surface;
+            surface.check_format(format)?;
+            let dimensions = surface.dimensions;
+            let row_height = format.block_dimensions()?.height().min(dimensions.height());
+            let row_size =
+                format.size_for(Dimensions::try_from([dimensions.width(), row_height])?)?;
+            let pitch = padding.row_pitch(row_size);
+            if pitch < row_size {
+                return Err(TextureError::Other(format!(
+                    "Row pitch {pitch} is smaller than a row of {row_size} bytes"
+                )));
+            }
+            for row in surface.buffer.chunks(row_size) {
+                self.write_bytes(row)?;
+                self.write_zeros(pitch - row_size)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> TextureResult<()> {
+        self.writer.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn write_zeros(&mut self, count: usize) -> TextureResult<()> {
+        self.write_bytes(&vec![0u8; count])
+    }
+}
+
+/// Hooks for inserting padding while writing surfaces with [`SurfaceWriter::write_shaped_padded`],
+/// for containers that require parts of the file to be aligned.
+/// Every method adds no padding by default, and `()` implements this trait with no padding.
+/// Offsets are measured from the start of the file, using [`SurfaceWriter::offset`]
+pub trait SurfacePadding {
+    /// Number of zero bytes to write before a level, which is each value of the outermost loop
+    /// of the [`SurfaceOrder`]. For [`SurfaceOrder::MipsLayersFaces`] this is the mip level
+    fn before_level(&mut self, _level: usize, _offset: u64) -> usize {
+        0
+    }
+
+    /// Number of zero bytes to write before each surface
+    fn before_surface(&mut self, _surface: &TextureIterResult<&Surface>, _offset: u64) -> usize {
+        0
+    }
+
+    /// The size in bytes to pad each row of blocks to, given the unpadded size of a row.
+    /// Must not be smaller than `row_size`
+    fn row_pitch(&mut self, row_size: usize) -> usize {
+        row_size
+    }
+}
+
+impl SurfacePadding for () {}
+
+/// Fixed alignments for [`SurfaceWriter::write_shaped_padded`], in bytes.
+/// An alignment of 0 or 1 adds no padding
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Alignment {
+    /// Alignment of the start of each level. KTX requires 4 bytes
+    pub level: u64,
+    /// Alignment of the start of each surface
+    pub surface: u64,
+    /// Alignment of the pitch of each row of blocks. Some console formats require 256 bytes
+    pub row: usize,
+}
+
+/// Number of bytes needed to advance `offset` to a multiple of `alignment`
+fn padding_to(offset: u64, alignment: u64) -> usize {
+    match alignment {
+        0 | 1 => 0,
+        a => (offset.next_multiple_of(a) - offset) as usize,
+    }
+}
+
+impl SurfacePadding for Alignment {
+    fn before_level(&mut self, _level: usize, offset: u64) -> usize {
+        padding_to(offset, self.level)
+    }
+
+    fn before_surface(&mut self, _surface: &TextureIterResult<&Surface>, offset: u64) -> usize {
+        padding_to(offset, self.surface)
+    }
+
+    fn row_pitch(&mut self, row_size: usize) -> usize {
+        row_size + padding_to(row_size as u64, self.row as u64)
+    }
+}
+
 /// An encoded texture, consisting of a [`Format`] and one or more [`Surface`]s
+///
+/// All surfaces in a texture share the same format. Textures that need a different format per
+/// surface (e.g. a higher quality format for mip 0) must be stored as separate textures,
+/// since no supported container can represent them.
+///
+/// Every surface's buffer is the size its format requires for its dimensions, as checked by [`Texture::new`].
+/// The exception is a texture in [`Format::Unknown`] loaded with
+/// [`ReadOptions::passthrough_unknown`](crate::container::ReadOptions::passthrough_unknown),
+/// whose single surface holds an opaque payload of whatever size the file had.
 #[derive(Clone, Debug)]
 pub struct Texture {
+    /// The format shared by every surface in the texture
     pub format: Format,
     pub(crate) surfaces: TextureShapeNode<Surface>,
 }
 
+impl Texture {
+    /// Create a new texture from a format and a tree of surfaces.
+    /// Returns [`TextureError::Shape`] if the tree is not [valid](TextureShapeNode::validate),
+    /// or [`TextureError::SurfaceSize`] if any surface's buffer is not the size required
+    /// by `format` for its dimensions
+    ///
+    /// ```
+    /// use quicktex::dimensions::Dimensions;
+    /// use quicktex::error::TextureError;
+    /// use quicktex::format::Format;
+    /// use quicktex::shape::TextureShapeNode;
+    /// use quicktex::texture::{Surface, Texture};
+    ///
+    /// // a 4x4 BC3 surface needs 16 bytes, not 8
+    /// let surface = Surface::new(Dimensions::try_from([4, 4])?, vec![0u8; 8]);
+    /// let result = Texture::new(Format::BC3 { srgb: false }, TextureShapeNode::from_surface(surface));
+    /// assert!(matches!(result, Err(TextureError::SurfaceSize { expected: 16, actual: 8, .. })));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn new(format: Format, surfaces: Surfaces) -> TextureResult<Self> {
+        surfaces.validate()?;
+        let texture = Self { format, surfaces };
+        texture.check_format()?;
+        Ok(texture)
+    }
+
+    /// Get the dimensions of the texture's largest mip like [`Dimensioned::dimensions`],
+    /// or return an error if its tree of surfaces is malformed. See [`TextureShapeNode::try_dimensions`]
+    pub fn try_dimensions(&self) -> ShapeResult<Dimensions> {
+        self.surfaces.try_dimensions()
+    }
+
+    /// Get the tree of surfaces that make up this texture
+    pub fn surfaces(&self) -> &Surfaces {
+        &self.surfaces
+    }
+
+    /// Borrow a read-only view of this texture's surfaces, for traversal without cloning.
+    /// See [`TextureSlice`]
+    pub fn slice(&self) -> TextureSlice<'_, Surface> {
+        self.surfaces.slice()
+    }
+
+    /// Consume this texture, returning its tree of surfaces
+    pub fn into_surfaces(self) -> Surfaces {
+        self.surfaces
+    }
+
+    /// Encode an image into a new texture in `format`.
+    /// If `mips` is set, a full mip chain is generated by downscaling the image with a triangle filter.
+    /// If `format` is sRGB, mips are filtered in linear light so they don't darken. See [`resize`](crate::resize)
+    #[cfg(feature = "image")]
+    pub fn from_image(
+        image: &DynamicImage,
+        format: Format,
+        mips: bool,
+        quality: Quality,
+    ) -> TextureResult<Self> {
+        Self::from_image_with(
+            image,
+            format,
+            mips,
+            EncodeOptions::default().quality(quality),
+        )
+    }
+
+    /// Encode an image into a new texture in `format`, configured by `options`. See [`Self::from_image`]
+    #[cfg(feature = "image")]
+    pub fn from_image_with(
+        image: &DynamicImage,
+        format: Format,
+        mips: bool,
+        options: EncodeOptions,
+    ) -> TextureResult<Self> {
+        let (encoder, quality) = (format.encoder_with(&options)?, options.quality);
+        let dimensions = Dimensions::try_from([image.width(), image.height()])?;
+        let mut encode = |d: Dimensions| -> Surfaces {
+            let mip = match d == dimensions {
+                true => image.clone(),
+                // resize from the full size image each time, so errors don't accumulate down the chain
+                false => resize(image, d.width(), d.height(), format.srgb()),
+            };
+            TextureShapeNode::from_surface(Surface::new(d, encoder.encode_buffer(mip, quality)))
+        };
+
+        let surfaces = match mips {
+            true => Surfaces::try_from_mips(dimensions.mips().map(&mut encode).collect_vec())?,
+            false => encode(dimensions),
+        };
+        Self::new(format, surfaces)
+    }
+
+    /// The total size in bytes of every surface in the texture
+    pub fn payload_size(&self) -> usize {
+        self.slice().iter().map(|s| s.surface.buffer.len()).sum()
+    }
+
+    /// Decode every surface in the texture and re-encode it in `format`, keeping the texture's shape.
+    /// Returns [`TextureError::Format`] if either format does not have a codec
+    #[cfg(feature = "image")]
+    pub fn transcode(&self, format: Format, quality: Quality) -> TextureResult<Self> {
+        self.encode(format, EncodeOptions::default().quality(quality))
+    }
+
+    /// Encode every surface in the texture into `format`, keeping the texture's shape.
+    /// The texture is usually uncompressed, but any format with a decoder works.
+    /// Returns [`TextureError::Format`] if this format does not have a decoder or `format` does not have an encoder
+    ///
+    /// ```
+    /// use image::{Rgba, RgbaImage};
+    /// use quicktex::format::{EncodeOptions, Format, Quality};
+    /// use quicktex::texture::Texture;
+    ///
+    /// let image = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]));
+    /// let texture = Texture::from_image(&image.into(), Format::rgba8(false), false, Quality::Normal)?;
+    /// let bc1 = texture.encode(Format::BC1 { srgb: false }, EncodeOptions::default().quality(Quality::High))?;
+    /// assert_eq!(bc1.payload_size(), 32);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "image")]
+    pub fn encode(&self, format: Format, options: EncodeOptions) -> TextureResult<Self> {
+        let decoder = self
+            .format
+            .decoder_with(&DecodeOptions::default().threads(options.threads))?;
+        let encoder = format.encoder_with(&options)?;
+        let surfaces = self.surfaces.try_map(|s| -> TextureResult<Surface> {
+            let image = decoder.decode_buffer(s.buffer.clone(), s.dimensions)?;
+            Ok(Surface::new(
+                s.dimensions,
+                encode_stacked(image, s.dimensions, |i| {
+                    encoder.encode_buffer(i, options.quality)
+                }),
+            ))
+        })?;
+        Self::new(format, surfaces)
+    }
+
+    /// Encode every surface in the texture into `format` like [`Self::encode`], but with the quality tier
+    /// of each block chosen from a weight mask, so important regions like faces or text get a more thorough
+    /// search than the background. `weights` is stretched to cover each surface, so the same mask is used for
+    /// every mip, layer, and face. [`EncodeOptions::quality`] is not used, in favor of `roi`
+    #[cfg(feature = "image")]
+    pub fn encode_weighted(
+        &self,
+        format: Format,
+        options: EncodeOptions,
+        weights: &GrayImage,
+        roi: RoiQuality,
+    ) -> TextureResult<Self> {
+        if weights.width() == 0 || weights.height() == 0 {
+            return Err(TextureError::Other("Weight mask is empty".to_string()));
+        }
+        let decoder = self
+            .format
+            .decoder_with(&DecodeOptions::default().threads(options.threads))?;
+        let encoder = format.encoder_with(&options)?;
+        let surfaces = self.surfaces.try_map(|s| -> TextureResult<Surface> {
+            let image = decoder.decode_buffer(s.buffer.clone(), s.dimensions)?;
+            Ok(Surface::new(
+                s.dimensions,
+                encode_stacked(image, s.dimensions, |i| {
+                    encoder.encode_buffer_weighted(i, weights, &roi)
+                }),
+            ))
+        })?;
+        Self::new(format, surfaces)
+    }
+
+    /// Decode every surface in the texture to 8-bit RGBA, keeping the texture's shape.
+    /// The result is in [`Format::rgba8`], and is sRGB encoded if this texture is.
+    /// Returns [`TextureError::Format`] if the format does not have a decoder
+    #[cfg(feature = "image")]
+    pub fn decode(&self) -> TextureResult<Self> {
+        self.decode_with(DecodeOptions::default())
+    }
+
+    /// Decode every surface in the texture to 8-bit RGBA, configured by `options`. See [`Self::decode`]
+    #[cfg(feature = "image")]
+    pub fn decode_with(&self, options: DecodeOptions) -> TextureResult<Self> {
+        let decoder = self.format.decoder_with(&options)?;
+        let surfaces = self.surfaces.try_map(|s| s.decode_with(decoder.as_ref()))?;
+        Self::new(Format::rgba8(self.format.srgb()), surfaces)
+    }
+
+    /// Concatenate every surface of the texture into a single raw payload in `order`,
+    /// for uploading to engines that expect a specific order, such as [`SurfaceOrder::MIP_MAJOR`].
+    /// [`SurfaceOrder::ordered`] lists where each surface ends up
+    pub fn payload(&self, order: SurfaceOrder) -> Vec<u8> {
+        order
+            .ordered(&self.surfaces)
+            .into_iter()
+            .flat_map(|s| s.surface.buffer.iter().copied())
+            .collect()
+    }
+
+    /// Reorder the array layers of the texture without re-encoding them, so that layer `i` of the result
+    /// is layer `permutation[i]` of the original. See [`TextureShapeNode::reorder_layers`]
+    pub fn reorder_layers(&mut self, permutation: &[usize]) -> TextureResult<()> {
+        Ok(self.surfaces.reorder_layers(permutation)?)
+    }
+
+    /// Stably sort the array layers of the texture without re-encoding them, by the key `f` returns for
+    /// each layer given its index and the layer as a texture of its own.
+    /// Returns the permutation that was applied, mapping each new layer index to its old one.
+    /// See [`TextureShapeNode::sort_layers_by`]
+    ///
+    /// ```
+    /// use quicktex::dimensions::Dimensions;
+    /// use quicktex::format::Format;
+    /// use quicktex::shape::{TextureShape, TextureShapeNode};
+    /// use quicktex::texture::{Surface, Surfaces, Texture};
+    ///
+    /// let dimensions = Dimensions::try_from([1, 1])?;
+    /// let layers = [30u8, 10, 20].map(|v| TextureShapeNode::from_surface(Surface::new(dimensions, vec![v, 0, 0, 255])));
+    /// let mut texture = Texture::new(Format::rgba8(false), Surfaces::try_from_layers(layers)?)?;
+    ///
+    /// let permutation = texture.sort_layers_by(|_, layer| layer.primary().buffer[0])?;
+    /// assert_eq!(permutation, [1, 2, 0]);
+    /// assert_eq!(texture.get_layer(0).unwrap().primary().buffer[0], 10);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn sort_layers_by<K, F>(&mut self, mut f: F) -> TextureResult<Vec<usize>>
+    where
+        K: Ord,
+        F: FnMut(usize, &Texture) -> K,
+    {
+        let format = self.format;
+        Ok(self.surfaces.sort_layers_by(|l, surfaces| {
+            f(
+                l,
+                &Texture {
+                    format,
+                    surfaces: surfaces.clone(),
+                },
+            )
+        })?)
+    }
+
+    /// Add a layer to the end of an array texture. See [`Self::insert_layer`]
+    pub fn push_layer(&mut self, layer: Texture) -> TextureResult<()> {
+        self.check_same_format(&layer)?;
+        Ok(self.surfaces.push_layer(layer.surfaces)?)
+    }
+
+    /// Insert a layer into an array texture at `index`, without re-encoding anything.
+    /// Returns [`TextureError::Format`] if `layer` is in a different format, or [`TextureError::Shape`]
+    /// without modifying the texture if it does not match the other layers. See [`TextureShapeNode::insert_layer`]
+    pub fn insert_layer(&mut self, index: usize, layer: Texture) -> TextureResult<()> {
+        self.check_same_format(&layer)?;
+        Ok(self.surfaces.insert_layer(index, layer.surfaces)?)
+    }
+
+    /// Remove the layer at `index` from an array texture, returning it as a texture of its own.
+    /// See [`TextureShapeNode::remove_layer`]
+    pub fn remove_layer(&mut self, index: usize) -> TextureResult<Texture> {
+        let surfaces = self.surfaces.remove_layer(index)?;
+        Ok(Texture {
+            format: self.format,
+            surfaces,
+        })
+    }
+
+    /// Replace the layer at `index` of an array texture, returning the old layer as a texture of its own.
+    /// See [`Self::insert_layer`] and [`TextureShapeNode::replace_layer`]
+    pub fn replace_layer(&mut self, index: usize, layer: Texture) -> TextureResult<Texture> {
+        self.check_same_format(&layer)?;
+        let surfaces = self.surfaces.replace_layer(index, layer.surfaces)?;
+        Ok(Texture {
+            format: self.format,
+            surfaces,
+        })
+    }
+
+    /// Replace mip `index` of a texture with a single mip chain, returning the old mip.
+    /// `surface` must already be encoded in [`Self::format`] and have the same dimensions as the mip it replaces.
+    /// Returns [`TextureError::Shape`] if the texture has layers or faces, or no mip `index`
+    pub fn replace_mip(&mut self, index: usize, surface: Surface) -> TextureResult<Surface> {
+        if self.layers().is_some() || self.faces().is_some() {
+            return Err(ShapeError::InvalidIndex("texture has more than one mip chain").into());
+        }
+        surface.check_format(self.format)?;
+        let old = self
+            .surfaces
+            .replace_mip(index, TextureShapeNode::from_surface(surface))?;
+        Ok(old
+            .try_into_surface()
+            .expect("mips of a single chain are surfaces"))
+    }
+
+    fn check_same_format(&self, other: &Texture) -> TextureResult<()> {
+        if other.format != self.format {
+            return Err(TextureError::Format(format!(
+                "Expected a texture in {:?}, not {:?}",
+                self.format, other.format
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that every surface in the texture is sized correctly for [`Self::format`],
+    /// returning [`TextureError::SurfaceSize`] for the first one that is not,
+    /// or [`TextureError::Shape`] if the tree of surfaces is malformed
+    pub fn check_format(&self) -> TextureResult<()> {
+        self.surfaces
+            .try_iter()?
+            .try_for_each(|s| s.surface.check_format(self.format))
+    }
+
+    /// Like [`TextureShape::try_from_mips`], but returns every violated invariant instead of only the first,
+    /// including each mip whose format differs from the first mip's. See [`TextureShapeNode::try_from_mips_all`]
+    pub fn try_from_mips_all<I: IntoIterator<Item = Self>>(iter: I) -> Result<Self, ShapeErrors> {
+        let (formats, nodes): (Vec<_>, Vec<_>) = (iter.into_iter().enumerate())
+            .map(|(i, t)| ((TextureIndex::Mip(i), t.format), t.surfaces))
+            .unzip();
+        Self::from_node_all(&formats, TextureShapeNode::try_from_mips_all(nodes))
+    }
+
+    /// Like [`TextureShape::try_from_faces`], but returns every violated invariant instead of only the first,
+    /// including each face whose format differs from the first face's. See [`TextureShapeNode::try_from_faces_all`]
+    pub fn try_from_faces_all<I>(iter: I) -> Result<Self, ShapeErrors>
+    where
+        I: IntoIterator<Item = (CubeFace, Self)>,
+    {
+        let (formats, nodes): (Vec<_>, Vec<_>) = iter
+            .into_iter()
+            .map(|(f, t)| ((TextureIndex::Face(f), t.format), (f, t.surfaces)))
+            .unzip();
+        Self::from_node_all(&formats, TextureShapeNode::try_from_faces_all(nodes))
+    }
+
+    /// Like [`TextureShape::try_from_layers`], but returns every violated invariant instead of only the first,
+    /// including each layer whose format differs from the first layer's. See [`TextureShapeNode::try_from_layers_all`]
+    pub fn try_from_layers_all<I: IntoIterator<Item = Self>>(iter: I) -> Result<Self, ShapeErrors> {
+        let (formats, nodes): (Vec<_>, Vec<_>) = (iter.into_iter().enumerate())
+            .map(|(i, t)| ((TextureIndex::Layer(i), t.format), t.surfaces))
+            .unzip();
+        Self::from_node_all(&formats, TextureShapeNode::try_from_layers_all(nodes))
+    }
+
+    /// Combine the format violations of a structure's children with the violations found building its node
+    fn from_node_all(
+        formats: &[(TextureIndex, Format)],
+        surfaces: Result<Surfaces, ShapeErrors>,
+    ) -> Result<Self, ShapeErrors> {
+        let format = formats.first().map(|&(_, f)| f);
+        let mut violations = (formats.iter())
+            .filter(|&&(_, f)| Some(f) != format)
+            .map(|&(index, _)| ShapeViolation {
+                index: Some(index),
+                error: ShapeError::NonUniform("format"),
+            })
+            .collect_vec();
+        match (surfaces, format) {
+            (Ok(surfaces), Some(format)) => {
+                ShapeErrors::check(violations, Self { format, surfaces })
+            }
+            (Ok(_), None) => unreachable!("structures cannot be empty"),
+            (Err(errors), _) => {
+                violations.extend(errors.violations);
+                Err(ShapeErrors { violations })
+            }
+        }
+    }
+}
+
+/// Encode a decoded surface one depth slice at a time with `encode`, since slices of 3D surfaces are stored
+/// independently and decode stacked vertically into a single image. See [`crate::codec`]
+#[cfg(feature = "image")]
+fn encode_stacked(
+    image: DynamicImage,
+    dimensions: Dimensions,
+    encode: impl Fn(DynamicImage) -> Rc<[u8]>,
+) -> Rc<[u8]> {
+    if dimensions.depth() == 1 {
+        return encode(image);
+    }
+    let height = dimensions.height();
+    (0..dimensions.depth())
+        .flat_map(|z| encode(image.crop_imm(0, z * height, image.width(), height)).to_vec())
+        .collect()
+}
+
 impl Dimensioned for Texture {
     fn dimensions(&self) -> Dimensions {
         self.surfaces.dimensions()
@@ -208,3 +1116,288 @@ impl TextureShape for Texture {
         self.surfaces.try_into_surface()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::format::{AlphaFormat, ColorFormat};
+
+    use super::*;
+
+    #[test]
+    fn chunks() -> Result<()> {
+        let format = Format::Uncompressed {
+            pitch: 1,
+            color_format: ColorFormat::L { l_mask: 0xFF },
+            alpha_format: AlphaFormat::Opaque,
+        };
+        let dimensions = Dimensions::try_from([3, 5])?;
+        let surface = Surface::new(dimensions, (0..15).collect::<Vec<u8>>());
+
+        let chunks = surface.chunks(format, 2)?;
+        assert_eq!(chunks.iter().map(|c| c.y).collect_vec(), [0, 2, 4]);
+        assert_eq!(chunks[1].buffer, [6, 7, 8, 9, 10, 11]);
+        assert_eq!(chunks[2].dimensions, Dimensions::try_from([3, 1])?);
+
+        let joined = Surface::from_chunks(format, dimensions, chunks.clone())?;
+        assert_eq!(joined.buffer, surface.buffer);
+
+        assert!(Surface::from_chunks(format, dimensions, chunks.into_iter().rev()).is_err());
+        assert!(surface.chunks(format, 0).is_err());
+        assert!(surface.chunks(Format::BC1 { srgb: false }, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn new_validates_shape() -> Result<()> {
+        let format = Format::rgba8(false);
+        let surface = |size: u32| -> Result<Surfaces> {
+            let dimensions = Dimensions::try_from([size, size])?;
+            Ok(TextureShapeNode::from_surface(Surface::new(
+                dimensions,
+                vec![0; format.size_for(dimensions)?],
+            )))
+        };
+        // layers with different shapes, built by hand instead of through try_from_layers
+        let surfaces = TextureShapeNode::Array(vec![
+            surface(2)?,
+            TextureShapeNode::MipMap(vec![surface(2)?, surface(1)?]),
+        ]);
+        assert!(matches!(
+            Texture::new(format, surfaces),
+            Err(TextureError::Shape(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn mixed_formats() -> Result<()> {
+        let texture = |format: Format, dimensions: [u32; 2]| -> Result<Texture> {
+            let dimensions = Dimensions::try_from(dimensions)?;
+            let surface = Surface::new(dimensions, vec![0; format.size_for(dimensions)?]);
+            Ok(Texture::new(
+                format,
+                TextureShapeNode::from_surface(surface),
+            )?)
+        };
+        let (bc1, bc3) = (Format::BC1 { srgb: false }, Format::BC3 { srgb: false });
+        let layers = [
+            texture(bc1, [8, 8])?,
+            texture(bc3, [8, 8])?,
+            texture(bc1, [4, 4])?,
+        ];
+        let errors = Texture::try_from_layers_all(layers.clone()).unwrap_err();
+        assert_eq!(
+            errors.to_string(),
+            "layer 1: Non-uniform format in provided textures; \
+             layer 2: Non-uniform dimensions in provided textures"
+        );
+
+        let mips = Texture::try_from_mips_all([texture(bc1, [8, 8])?, texture(bc1, [4, 4])?]);
+        assert!(mips.is_err(), "mipchain stops before 1x1");
+        let faces = Texture::try_from_faces_all([(CubeFace::PositiveZ, layers[0].clone())])?;
+        assert_eq!(faces.format, bc1);
+        assert_eq!(faces.faces(), Some(vec![CubeFace::PositiveZ]));
+        Ok(())
+    }
+
+    #[test]
+    fn surface_order() -> Result<()> {
+        use std::io::Cursor;
+
+        let format = Format::Uncompressed {
+            pitch: 1,
+            color_format: ColorFormat::L { l_mask: 0xFF },
+            alpha_format: AlphaFormat::Opaque,
+        };
+        // 2 layers of a 2x1 mip chain, with each surface filled with 0xLM
+        let layers = (0..2u8).map(|l| {
+            let mips = Dimensions::try_from([2, 1]).unwrap().mips().enumerate();
+            Surfaces::try_from_mips(mips.map(|(m, d)| {
+                let value = l << 4 | m as u8;
+                TextureShapeNode::from_surface(Surface::new(d, vec![value; d.product() as usize]))
+            }))
+        });
+        let surfaces = Surfaces::try_from_layers(layers.collect::<Result<Vec<_>, _>>()?)?;
+
+        let mut file = Cursor::new(Vec::new());
+        SurfaceWriter::new(&mut file, 0).write_shaped(SurfaceOrder::MipsLayersFaces, &surfaces)?;
+        assert_eq!(file.get_ref(), &[0x00, 0x00, 0x10, 0x10, 0x01, 0x11]);
+
+        let counts = SurfaceCounts {
+            dimensions: Dimensions::try_from([2, 1])?,
+            layers: Some(2),
+            faces: None,
+            mips: Some(2),
+        };
+        file.set_position(0);
+        let read = SurfaceReader {
+            format,
+            reader: &mut file,
+        }
+        .read_shaped(SurfaceOrder::MipsLayersFaces, &counts)?;
+        let buffers = |s: &Surfaces| {
+            s.slice()
+                .iter()
+                .map(|s| s.surface.buffer.to_vec())
+                .collect_vec()
+        };
+        assert_eq!(buffers(&read), buffers(&surfaces));
+        Ok(())
+    }
+
+    #[test]
+    fn reorder_payload() -> Result<()> {
+        let format = Format::Uncompressed {
+            pitch: 1,
+            color_format: ColorFormat::L { l_mask: 0xFF },
+            alpha_format: AlphaFormat::Opaque,
+        };
+        // a cubemap with a 2x1 mip chain, with each surface filled with 0xFM
+        use strum::VariantArray;
+
+        let faces = CubeFace::VARIANTS.iter().map(|&f| {
+            let mips = Dimensions::try_from([2, 1]).unwrap().mips().enumerate();
+            let mips = mips.map(|(m, d)| {
+                let value = (f as u8) << 4 | m as u8;
+                TextureShapeNode::from_surface(Surface::new(d, vec![value; d.product() as usize]))
+            });
+            (f, Surfaces::try_from_mips(mips).unwrap())
+        });
+        let texture = Texture::new(format, Surfaces::try_from_faces(faces)?)?;
+        let counts = SurfaceCounts::of(texture.surfaces());
+        assert_eq!(counts.faces.as_ref().map(Vec::len), Some(6));
+
+        let face_major = texture.payload(SurfaceOrder::FACE_MAJOR);
+        assert_eq!(face_major[..6], [0x00, 0x00, 0x01, 0x10, 0x10, 0x11]);
+        let mip_major = texture.payload(SurfaceOrder::MIP_MAJOR);
+        assert_eq!(mip_major[..4], [0x00, 0x00, 0x10, 0x10]);
+        assert_eq!(mip_major[12..], [0x01, 0x11, 0x21, 0x31, 0x41, 0x51]);
+
+        let converted = SurfaceOrder::FACE_MAJOR.reorder_payload(
+            SurfaceOrder::MIP_MAJOR,
+            &face_major,
+            format,
+            &counts,
+        )?;
+        assert_eq!(converted, mip_major);
+        let converted = SurfaceOrder::MIP_MAJOR.reorder_payload(
+            SurfaceOrder::FACE_MAJOR,
+            &mip_major,
+            format,
+            &counts,
+        )?;
+        assert_eq!(converted, face_major);
+
+        // payloads of the wrong size are rejected
+        let order = SurfaceOrder::FACE_MAJOR;
+        assert!(order
+            .reorder_payload(order, &face_major[1..], format, &counts)
+            .is_err());
+        assert!(order
+            .reorder_payload(
+                order,
+                &[face_major.clone(), vec![0]].concat(),
+                format,
+                &counts
+            )
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn padding() -> Result<()> {
+        let format = Format::Uncompressed {
+            pitch: 1,
+            color_format: ColorFormat::L { l_mask: 0xFF },
+            alpha_format: AlphaFormat::Opaque,
+        };
+        let layers = (0..2u8).map(|l| {
+            let mips = Dimensions::try_from([3, 1]).unwrap().mips().enumerate();
+            Surfaces::try_from_mips(mips.map(|(m, d)| {
+                let value = l << 4 | m as u8;
+                TextureShapeNode::from_surface(Surface::new(d, vec![value; d.product() as usize]))
+            }))
+        });
+        let surfaces = Surfaces::try_from_layers(layers.collect::<Result<Vec<_>, _>>()?)?;
+
+        // levels start at offsets 4 and 12, measured from a writer already 1 byte into the file
+        let mut file = Vec::new();
+        let mut alignment = Alignment {
+            level: 4,
+            ..Default::default()
+        };
+        let mut writer = SurfaceWriter::new(&mut file, 1);
+        writer.write_shaped_padded(
+            SurfaceOrder::MipsLayersFaces,
+            &surfaces,
+            format,
+            &mut alignment,
+        )?;
+        assert_eq!(writer.offset, 14);
+        assert_eq!(
+            file,
+            [0, 0, 0, 0x00, 0x00, 0x00, 0x10, 0x10, 0x10, 0, 0, 0x01, 0x11]
+        );
+
+        let surface = Surface::new(Dimensions::try_from([3, 2])?, vec![7u8; 6]);
+        let mut file = Vec::new();
+        let mut alignment = Alignment {
+            row: 4,
+            ..Default::default()
+        };
+        SurfaceWriter::new(&mut file, 0).write_shaped_padded(
+            SurfaceOrder::default(),
+            &TextureShapeNode::from_surface(surface),
+            format,
+            &mut alignment,
+        )?;
+        assert_eq!(file, [7, 7, 7, 0, 7, 7, 7, 0]);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn edit_layers() -> Result<()> {
+        let format = Format::rgba8(false);
+        let layer = |v: u8| -> Result<Texture> {
+            let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([v, 0, 0, 255]));
+            Ok(Texture::from_image(
+                &image.into(),
+                format,
+                true,
+                Quality::Normal,
+            )?)
+        };
+        let layers = [layer(1)?, layer(2)?].map(|l| l.into_surfaces());
+        let mut texture = Texture::new(format, Surfaces::try_from_layers(layers)?)?;
+
+        texture.push_layer(layer(3)?)?;
+        assert_eq!(texture.remove_layer(0)?.primary().buffer[0], 1);
+        assert_eq!(texture.replace_layer(1, layer(4)?)?.primary().buffer[0], 3);
+        let primaries = texture.iter_layers().map(|(_, l)| l.primary().buffer[0]);
+        assert_eq!(primaries.collect_vec(), [2, 4]);
+
+        let bc1 = layer(5)?.encode(Format::BC1 { srgb: false }, EncodeOptions::default())?;
+        assert!(matches!(
+            texture.push_layer(bc1),
+            Err(TextureError::Format(_))
+        ));
+        assert!(matches!(
+            texture.replace_mip(0, Surface::new(Dimensions::try_from([4, 4])?, [0; 64])),
+            Err(TextureError::Shape(ShapeError::InvalidIndex(_)))
+        ));
+
+        let mut single = layer(6)?;
+        let old =
+            single.replace_mip(2, Surface::new(Dimensions::try_from([1, 1])?, [9, 9, 9, 9]))?;
+        assert_eq!(old.buffer[..], [6, 0, 0, 255]);
+        assert_eq!(single.get_mip(2).unwrap().primary().buffer[0], 9);
+        assert!(matches!(
+            single.replace_mip(2, Surface::new(Dimensions::try_from([1, 1])?, [0; 3])),
+            Err(TextureError::SurfaceSize { .. })
+        ));
+        Ok(())
+    }
+}
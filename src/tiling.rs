@@ -0,0 +1,138 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Making textures tile seamlessly, by cross-fading the band along each edge with the middle of the image.
+//!
+//! Each axis is handled in its own pass. The image is shifted by half its size along the axis, which puts
+//! pixels that were next to each other in the middle of the image on opposite edges, and the shifted copy
+//! is blended in with full weight at the edges, fading out towards the inside of the band.
+//! The seam of the shifted copy lands in the middle of the image where it has no weight, so as long as the
+//! band is less than half the image, the middle is left unchanged.
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::error::TextureResult;
+use crate::format::Quality;
+use crate::texture::{Surface, Texture};
+
+/// The default width of the cross-faded band, as a fraction of the image's size
+pub const DEFAULT_BAND: f32 = 0.125;
+
+/// Weight of the shifted image at coordinate `p` along an axis `size` pixels long:
+/// 1 for the pixels on the edges, falling to 0 at `band` pixels in
+fn weight(p: u32, size: u32, band: f32) -> f32 {
+    if band <= 0.0 {
+        return 0.0;
+    }
+    let distance = p.min(size - 1 - p) as f32;
+    (1.0 - distance / band).clamp(0.0, 1.0)
+}
+
+/// Cross-fade one axis of `image` with a copy of itself shifted by half its size along that axis
+fn crossfade(image: &RgbaImage, band: f32, horizontal: bool) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (t, shifted) = match horizontal {
+            true => (
+                weight(x, width, band * width as f32),
+                image.get_pixel((x + width / 2) % width, y),
+            ),
+            false => (
+                weight(y, height, band * height as f32),
+                image.get_pixel(x, (y + height / 2) % height),
+            ),
+        };
+        let pixel = image.get_pixel(x, y);
+        Rgba(std::array::from_fn(|c| {
+            (pixel[c] as f32 * (1.0 - t) + shifted[c] as f32 * t).round() as u8
+        }))
+    })
+}
+
+/// Make `image` tile seamlessly, cross-fading a band along each edge that is `band` of its size wide.
+/// `band` is clamped between 0, which leaves the image unchanged, and 0.5, which blends the entire image.
+/// Every channel is blended, including alpha
+pub fn make_tileable(image: &RgbaImage, band: f32) -> RgbaImage {
+    let band = band.clamp(0.0, 0.5);
+    crossfade(&crossfade(image, band, true), band, false)
+}
+
+impl Texture {
+    /// Make every surface of the texture tile seamlessly with [`make_tileable`], decoding and re-encoding
+    /// each surface in the texture's own format. Each mip is made tileable on its own, so every mip tiles.
+    /// Returns [`TextureError::Format`](crate::error::TextureError::Format) if the format does not have a codec
+    pub fn make_tileable(&self, band: f32, quality: Quality) -> TextureResult<Self> {
+        let decoder = self.format.decoder()?;
+        let encoder = self.format.encoder()?;
+        let surfaces = self.surfaces.try_map(|s| -> TextureResult<Surface> {
+            let image = decoder.decode_buffer(s.buffer.clone(), s.dimensions)?;
+            let image = DynamicImage::from(make_tileable(&image.into_rgba8(), band));
+            Ok(Surface::new(
+                s.dimensions,
+                encoder.encode_buffer(image, quality),
+            ))
+        })?;
+        Self::new(self.format, surfaces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::format::Format;
+    use crate::shape::TextureShape;
+
+    use super::*;
+
+    /// A horizontal ramp with a hard seam where it wraps, and a vertical ramp in green with the same
+    fn ramp() -> RgbaImage {
+        RgbaImage::from_fn(32, 16, |x, y| Rgba([x as u8 * 8, y as u8 * 16, 0, 255]))
+    }
+
+    #[test]
+    fn seamless() {
+        let tiled = make_tileable(&ramp(), DEFAULT_BAND);
+
+        // opposite edges are as close as neighboring pixels in the middle of the ramp
+        for y in 0..16 {
+            let (left, right) = (tiled.get_pixel(0, y), tiled.get_pixel(31, y));
+            assert!(left[0].abs_diff(right[0]) <= 8, "{left:?} {right:?}");
+        }
+        for x in 0..32 {
+            let (top, bottom) = (tiled.get_pixel(x, 0), tiled.get_pixel(x, 15));
+            assert!(top[1].abs_diff(bottom[1]) <= 16, "{top:?} {bottom:?}");
+        }
+
+        // the middle is untouched
+        for (x, y) in [(8, 8), (16, 4), (23, 11)] {
+            assert_eq!(tiled.get_pixel(x, y), ramp().get_pixel(x, y));
+        }
+    }
+
+    #[test]
+    fn band() {
+        assert_eq!(make_tileable(&ramp(), 0.0), ramp());
+        assert_eq!(make_tileable(&ramp(), -1.0), ramp());
+        // 1x1 images are already tileable
+        let pixel = RgbaImage::from_pixel(1, 1, Rgba([1, 2, 3, 4]));
+        assert_eq!(make_tileable(&pixel, 0.5), pixel);
+    }
+
+    #[test]
+    fn texture() -> Result<()> {
+        let format = Format::rgba8(false);
+        let texture = Texture::from_image(&ramp().into(), format, true, Quality::Normal)?;
+        let tiled = texture.make_tileable(DEFAULT_BAND, Quality::Normal)?;
+        assert_eq!(tiled.mips(), texture.mips());
+
+        let mip0 = tiled.surfaces().get_mip(0).unwrap();
+        let buffer = &mip0.try_into_surface().unwrap().buffer;
+        assert_eq!(
+            buffer[..],
+            make_tileable(&ramp(), DEFAULT_BAND).as_raw()[..]
+        );
+        Ok(())
+    }
+}
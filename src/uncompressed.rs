@@ -0,0 +1,414 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::rc::Rc;
+
+use half::f16;
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive, Rgba, RgbaImage};
+
+use crate::codec::stacked_height;
+use crate::dimensions::{DimensionError, Dimensions};
+use crate::error::{TextureError, TextureResult};
+use crate::format::{AlphaFormat, ChannelType, ColorFormat, Decoder, Encoder, Format, Quality};
+
+/// Codec for [`Format::Uncompressed`] surfaces with pixels of up to 4 bytes,
+/// packing and unpacking each channel using its bitmask
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct UncompressedCodec {
+    pitch: usize,
+    color_format: ColorFormat,
+    alpha_format: AlphaFormat,
+}
+
+impl UncompressedCodec {
+    pub(crate) fn new(format: Format) -> TextureResult<Self> {
+        let Format::Uncompressed {
+            pitch,
+            color_format,
+            alpha_format,
+        } = format
+        else {
+            return Err(TextureError::Format(format!(
+                "{format:?} is not an uncompressed format"
+            )));
+        };
+
+        if pitch == 0 || pitch > 4 {
+            return Err(TextureError::Format(format!(
+                "Uncompressed pixels must be 1 to 4 bytes, not {pitch}"
+            )));
+        }
+        if let ColorFormat::YUV { .. } = color_format {
+            return Err(TextureError::Format(
+                "YUV formats are not supported".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            pitch,
+            color_format,
+            alpha_format,
+        })
+    }
+
+    fn alpha_mask(&self) -> Option<u32> {
+        match self.alpha_format {
+            AlphaFormat::Custom { alpha_mask }
+            | AlphaFormat::Straight { alpha_mask }
+            | AlphaFormat::Premultiplied { alpha_mask } => Some(alpha_mask),
+            AlphaFormat::Opaque => None,
+        }
+    }
+}
+
+/// Extract the channel selected by `mask` from `pixel`, scaled to 8 bits
+fn extract(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = (mask >> mask.trailing_zeros()) as u64;
+    let value = ((pixel & mask) >> mask.trailing_zeros()) as u64;
+    ((value * 255 + max / 2) / max) as u8
+}
+
+/// Scale an 8-bit value to the channel selected by `mask`
+fn insert(value: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = (mask >> mask.trailing_zeros()) as u64;
+    ((((value as u64) * max + 127) / 255) as u32) << mask.trailing_zeros()
+}
+
+/// Check that `buffer` holds a surface of pixels that are `pitch` bytes each
+fn check_buffer(buffer: &[u8], pitch: usize, dimensions: Dimensions) -> TextureResult<()> {
+    let expected = pitch
+        .checked_mul(dimensions.try_product()?)
+        .ok_or(DimensionError::Overflow(dimensions))?;
+    if buffer.len() != expected {
+        return Err(TextureError::Other(format!(
+            "Buffer has {} bytes, but {expected} are required",
+            buffer.len()
+        )));
+    }
+    Ok(())
+}
+
+impl Decoder for UncompressedCodec {
+    fn decode_buffer(
+        &self,
+        buffer: Rc<[u8]>,
+        dimensions: Dimensions,
+    ) -> TextureResult<DynamicImage> {
+        check_buffer(&buffer, self.pitch, dimensions)?;
+
+        let mut image = RgbaImage::new(dimensions.width(), stacked_height(dimensions)?);
+        for (src, dst) in buffer.chunks_exact(self.pitch).zip(image.pixels_mut()) {
+            let mut bytes = [0u8; 4];
+            bytes[..self.pitch].copy_from_slice(src);
+            let pixel = u32::from_le_bytes(bytes);
+
+            let [r, g, b] = match self.color_format {
+                ColorFormat::RGB {
+                    r_mask,
+                    g_mask,
+                    b_mask,
+                    ..
+                } => [
+                    extract(pixel, r_mask),
+                    extract(pixel, g_mask),
+                    extract(pixel, b_mask),
+                ],
+                ColorFormat::L { l_mask } => [extract(pixel, l_mask); 3],
+                ColorFormat::None | ColorFormat::YUV { .. } => [0; 3],
+            };
+            let a = self.alpha_mask().map_or(u8::MAX, |m| extract(pixel, m));
+            dst.0 = [r, g, b, a];
+        }
+        Ok(DynamicImage::ImageRgba8(image))
+    }
+}
+
+impl Encoder for UncompressedCodec {
+    fn encode_buffer(&self, image: DynamicImage, _quality: Quality) -> Rc<[u8]> {
+        let image = image.into_rgba8();
+        let mut buffer = Vec::with_capacity(self.pitch * image.pixels().len());
+
+        for &image::Rgba([r, g, b, a]) in image.pixels() {
+            let mut pixel = match self.color_format {
+                ColorFormat::RGB {
+                    r_mask,
+                    g_mask,
+                    b_mask,
+                    ..
+                } => insert(r, r_mask) | insert(g, g_mask) | insert(b, b_mask),
+                ColorFormat::L { l_mask } => {
+                    // Rec. 709 luma
+                    let l = (r as u32 * 2126 + g as u32 * 7152 + b as u32 * 722 + 5000) / 10000;
+                    insert(l as u8, l_mask)
+                }
+                ColorFormat::None | ColorFormat::YUV { .. } => 0,
+            };
+            if let Some(alpha_mask) = self.alpha_mask() {
+                pixel |= insert(a, alpha_mask);
+            }
+            buffer.extend_from_slice(&pixel.to_le_bytes()[..self.pitch]);
+        }
+
+        buffer.into()
+    }
+}
+
+/// Codec for [`Format::Channels`] surfaces. 8 and 16-bit channels decode to images with the same depth,
+/// and floating point channels decode to 32-bit float images, so HDR values above 1 are kept.
+/// Signed channels are remapped like [`SignedBC4Block`](crate::s3tc::bc4::SignedBC4Block),
+/// so that -1 is 0 and 1 is the largest unsigned value
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ChannelCodec {
+    channel_type: ChannelType,
+    channels: usize,
+}
+
+impl ChannelCodec {
+    pub(crate) fn new(format: Format) -> TextureResult<Self> {
+        let Format::Channels {
+            channel_type,
+            channels,
+        } = format
+        else {
+            return Err(TextureError::Format(format!(
+                "{format:?} is not a format of typed channels"
+            )));
+        };
+
+        if channels == 0 || channels > 4 {
+            return Err(TextureError::Format(format!(
+                "Pixels must have 1 to 4 channels, not {channels}"
+            )));
+        }
+
+        Ok(Self {
+            channel_type,
+            channels,
+        })
+    }
+
+    /// Read each pixel of `buffer` into an RGBA image `height` pixels tall, with `read` converting a single channel
+    fn unpack<T: Primitive>(
+        &self,
+        buffer: &[u8],
+        dimensions: Dimensions,
+        height: u32,
+        read: impl Fn(&[u8]) -> T,
+    ) -> ImageBuffer<Rgba<T>, Vec<T>>
+    where
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        let (min, max) = (T::DEFAULT_MIN_VALUE, T::DEFAULT_MAX_VALUE);
+        let mut image =
+            ImageBuffer::from_pixel(dimensions.width(), height, Rgba([min, min, min, max]));
+        let size = self.channel_type.size();
+        for (src, dst) in buffer
+            .chunks_exact(size * self.channels)
+            .zip(image.pixels_mut())
+        {
+            for (value, channel) in src.chunks_exact(size).zip(dst.0.iter_mut()) {
+                *channel = read(value);
+            }
+        }
+        image
+    }
+
+    /// Write the first [`Self::channels`] channels of each pixel of `image`, with `write` converting a single channel
+    fn pack<T: Primitive, const N: usize>(
+        &self,
+        image: &ImageBuffer<Rgba<T>, Vec<T>>,
+        write: impl Fn(T) -> [u8; N],
+    ) -> Rc<[u8]>
+    where
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        (image.pixels())
+            .flat_map(|p| p.0.into_iter().take(self.channels))
+            .flat_map(write)
+            .collect()
+    }
+}
+
+/// Convert an unsigned value out of `u_max` to a signed one out of `s_max`, so that 0 is -`s_max`
+fn to_snorm(value: u32, u_max: u32, s_max: i32) -> i32 {
+    ((value as i64 * 2 * s_max as i64 + u_max as i64 / 2) / u_max as i64) as i32 - s_max
+}
+
+/// Convert a signed value out of `s_max` to an unsigned one out of `u_max`. The extra lowest value is treated as -1
+fn from_snorm(value: i32, u_max: u32, s_max: i32) -> u32 {
+    let value = value.max(-s_max) + s_max;
+    ((value as i64 * u_max as i64 + s_max as i64) / (2 * s_max as i64)) as u32
+}
+
+impl Decoder for ChannelCodec {
+    fn decode_buffer(
+        &self,
+        buffer: Rc<[u8]>,
+        dimensions: Dimensions,
+    ) -> TextureResult<DynamicImage> {
+        let size = self.channel_type.size();
+        check_buffer(&buffer, size * self.channels, dimensions)?;
+        let height = stacked_height(dimensions)?;
+
+        let le16 = |v: &[u8]| u16::from_le_bytes([v[0], v[1]]);
+        Ok(match self.channel_type {
+            ChannelType::UNorm8 => self.unpack(&buffer, dimensions, height, |v| v[0]).into(),
+            ChannelType::SNorm8 => self
+                .unpack(&buffer, dimensions, height, |v| {
+                    from_snorm(v[0] as i8 as i32, 0xFF, 0x7F) as u8
+                })
+                .into(),
+            ChannelType::UNorm16 => self.unpack(&buffer, dimensions, height, le16).into(),
+            ChannelType::SNorm16 => self
+                .unpack(&buffer, dimensions, height, |v| {
+                    from_snorm(le16(v) as i16 as i32, 0xFFFF, 0x7FFF) as u16
+                })
+                .into(),
+            ChannelType::Float16 => self
+                .unpack(&buffer, dimensions, height, |v| {
+                    f16::from_bits(le16(v)).to_f32()
+                })
+                .into(),
+            ChannelType::Float32 => self
+                .unpack(&buffer, dimensions, height, |v| {
+                    f32::from_le_bytes([v[0], v[1], v[2], v[3]])
+                })
+                .into(),
+        })
+    }
+}
+
+impl Encoder for ChannelCodec {
+    fn encode_buffer(&self, image: DynamicImage, _quality: Quality) -> Rc<[u8]> {
+        match self.channel_type {
+            ChannelType::UNorm8 => self.pack(&image.into_rgba8(), |v| [v]),
+            ChannelType::SNorm8 => self.pack(&image.into_rgba8(), |v| {
+                [to_snorm(v as u32, 0xFF, 0x7F) as i8 as u8]
+            }),
+            ChannelType::UNorm16 => self.pack(&image.into_rgba16(), u16::to_le_bytes),
+            ChannelType::SNorm16 => self.pack(&image.into_rgba16(), |v| {
+                (to_snorm(v as u32, 0xFFFF, 0x7FFF) as i16).to_le_bytes()
+            }),
+            ChannelType::Float16 => {
+                self.pack(&image.into_rgba32f(), |v| f16::from_f32(v).to_le_bytes())
+            }
+            ChannelType::Float32 => self.pack(&image.into_rgba32f(), f32::to_le_bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn bgra8() -> Format {
+        Format::Uncompressed {
+            pitch: 4,
+            color_format: ColorFormat::RGB {
+                r_mask: 0x00FF0000,
+                g_mask: 0x0000FF00,
+                b_mask: 0x000000FF,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Straight {
+                alpha_mask: 0xFF000000,
+            },
+        }
+    }
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        let dimensions = Dimensions::try_from([2, 1])?;
+        let codec = UncompressedCodec::new(bgra8())?;
+        let buffer: Rc<[u8]> = [1, 2, 3, 4, 5, 6, 7, 8].into();
+
+        let image = codec.decode_buffer(buffer.clone(), dimensions)?;
+        assert_eq!(image.to_rgba8().get_pixel(0, 0).0, [3, 2, 1, 4]);
+        assert_eq!(codec.encode_buffer(image, Quality::default()), buffer);
+        Ok(())
+    }
+
+    #[test]
+    fn rgb565() -> Result<()> {
+        let codec = UncompressedCodec::new(Format::Uncompressed {
+            pitch: 2,
+            color_format: ColorFormat::RGB {
+                r_mask: 0xF800,
+                g_mask: 0x07E0,
+                b_mask: 0x001F,
+                srgb: false,
+            },
+            alpha_format: AlphaFormat::Opaque,
+        })?;
+        let image = codec.decode_buffer([0x1F, 0xF8].into(), Dimensions::try_from([1])?)?;
+        assert_eq!(image.to_rgba8().get_pixel(0, 0).0, [255, 0, 255, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn channels() -> Result<()> {
+        let dimensions = Dimensions::try_from([2, 1])?;
+        let format = |channel_type, channels| {
+            ChannelCodec::new(Format::Channels {
+                channel_type,
+                channels,
+            })
+        };
+
+        // HDR values survive a round trip through float channels
+        let codec = format(ChannelType::Float32, 4)?;
+        let pixels = [[4.0f32, 0.5, 0.0, 1.0], [-1.0, 0.25, 16.0, 0.0]];
+        let buffer: Rc<[u8]> = pixels
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let image = codec.decode_buffer(buffer.clone(), dimensions)?;
+        assert_eq!(image.to_rgba32f().get_pixel(1, 0).0, pixels[1]);
+        assert_eq!(
+            codec.encode_buffer(image.clone(), Quality::default()),
+            buffer
+        );
+
+        let half = format(ChannelType::Float16, 2)?.encode_buffer(image, Quality::default());
+        assert_eq!(half.len(), 8);
+        assert_eq!(&half[..4], [0x00, 0x44, 0x00, 0x38]);
+        let image = format(ChannelType::Float16, 2)?.decode_buffer(half, dimensions)?;
+        assert_eq!(image.to_rgba32f().get_pixel(0, 0).0, [4.0, 0.5, 0.0, 1.0]);
+
+        // 16-bit channels keep their precision
+        let codec = format(ChannelType::UNorm16, 1)?;
+        let image = codec.decode_buffer([0x34, 0x12, 0xFF, 0xFF].into(), dimensions)?;
+        assert_eq!(image.to_rgba16().get_pixel(0, 0).0, [0x1234, 0, 0, 0xFFFF]);
+
+        // signed channels are remapped so that -1 is 0 and 1 is the largest value
+        let codec = format(ChannelType::SNorm8, 1)?;
+        let image = codec.decode_buffer([0x81, 0x7F].into(), dimensions)?;
+        assert_eq!(image.to_rgba8().get_pixel(0, 0).0[0], 0);
+        assert_eq!(image.to_rgba8().get_pixel(1, 0).0[0], 255);
+        for v in [0x80u8, 0x81, 0xC0, 0x00, 0x40, 0x7F] {
+            let image = codec.decode_buffer([v, v].into(), dimensions)?;
+            let expected = if v == 0x80 { 0x81 } else { v };
+            assert_eq!(codec.encode_buffer(image, Quality::default())[0], expected);
+        }
+        for v in [-32768i16, -1, 0, 1, 12345, 32767] {
+            let codec = format(ChannelType::SNorm16, 1)?;
+            let buffer: Rc<[u8]> = [v.to_le_bytes(), v.to_le_bytes()].concat().into();
+            let image = codec.decode_buffer(buffer, dimensions)?;
+            let encoded = codec.encode_buffer(image, Quality::default());
+            assert_eq!(i16::from_le_bytes([encoded[0], encoded[1]]), v.max(-32767));
+        }
+
+        assert!(format(ChannelType::UNorm8, 5).is_err());
+        Ok(())
+    }
+}
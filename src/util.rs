@@ -19,3 +19,33 @@ impl<T> AsSlice<T> for &[T] {
         self
     }
 }
+
+/// 64-bit FNV-1a, used because it is tiny and, unlike [`std::hash::DefaultHasher`],
+/// guaranteed to be stable between runs and Rust versions
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+/// Incremental form of [`fnv1a`], for hashing data that isn't in one contiguous buffer
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |h, &b| {
+            (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+        });
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,299 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Texture files stored inside a general-purpose compressed stream, such as `.dds.gz`.
+//!
+//! Containers need to seek while reading and writing, which compressed streams can't do,
+//! so the whole file is decompressed into memory before it is read, and written to memory before it is compressed.
+//! Wrapped files are recognized by their magic bytes when reading, or by their extension when writing to a path.
+//! The example below needs the `deflate` feature.
+//!
+//! ```
+//! use std::io::Cursor;
+//! use quicktex::prelude::*;
+//! use quicktex::{Surface, TextureShapeNode};
+//! use quicktex::wrapped::{read_wrapped, write_wrapped, Wrapper};
+//!
+//! # #[cfg(feature = "deflate")] {
+//! let surface = Surface::new(Dimensions::try_from([8, 8])?, vec![0u8; 32]);
+//! let texture = Texture::new(Format::BC1 { srgb: false }, TextureShapeNode::from_surface(surface))?;
+//!
+//! let mut file = Vec::new();
+//! write_wrapped::<DDSHeader, _>(&mut file, &texture, Some(Wrapper::Gzip))?;
+//! assert_eq!(Wrapper::sniff(&file), Some(Wrapper::Gzip));
+//!
+//! let read = read_wrapped::<DDSHeader, _>(&mut Cursor::new(file))?;
+//! assert_eq!(read.dimensions(), texture.dimensions());
+//! # }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use std::path::Path;
+
+use crate::container::ContainerHeader;
+use crate::error::{TextureError, TextureResult};
+use crate::texture::Texture;
+
+/// A compressed stream around a texture file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Wrapper {
+    /// gzip, with the extension `.gz`. Requires the `deflate` feature
+    Gzip,
+    /// Zstandard, with the extension `.zst`. Requires the `zstd` feature
+    Zstd,
+}
+
+impl Wrapper {
+    /// Find the wrapper used by a file from its first bytes, or [`None`] if it isn't wrapped
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1F, 0x8B]) {
+            Some(Wrapper::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Some(Wrapper::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Find the wrapper used by a file from its extension, such as `.dds.gz`, or [`None`] if it isn't wrapped
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "gz" => Some(Wrapper::Gzip),
+            "zst" => Some(Wrapper::Zstd),
+            _ => None,
+        }
+    }
+
+    fn unsupported(self) -> TextureError {
+        TextureError::Capability(match self {
+            Wrapper::Gzip => "gzip-wrapped files require the `deflate` feature".to_string(),
+            Wrapper::Zstd => "zstd-wrapped files require the `zstd` feature".to_string(),
+        })
+    }
+
+    /// Decompress a whole wrapped file
+    #[cfg_attr(
+        not(any(feature = "deflate", feature = "zstd")),
+        allow(unused_variables)
+    )]
+    pub fn decompress(self, data: &[u8]) -> TextureResult<Vec<u8>> {
+        match self {
+            #[cfg(feature = "deflate")]
+            Wrapper::Gzip => {
+                let mut decompressed = Vec::new();
+                flate2::read::MultiGzDecoder::new(data).read_to_end(&mut decompressed)?;
+                Ok(decompressed)
+            }
+            #[cfg(feature = "zstd")]
+            Wrapper::Zstd => Ok(zstd::decode_all(data)?),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    /// Compress a whole file
+    #[cfg_attr(
+        not(any(feature = "deflate", feature = "zstd")),
+        allow(unused_variables)
+    )]
+    pub fn compress(self, data: &[u8]) -> TextureResult<Vec<u8>> {
+        match self {
+            #[cfg(feature = "deflate")]
+            Wrapper::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Wrapper::Zstd => Ok(zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unsupported()),
+        }
+    }
+}
+
+/// Read all of `reader` into memory, decompressing it if it starts with the magic bytes of a [`Wrapper`]
+pub fn unwrap_reader<R: Read>(reader: &mut R) -> TextureResult<Cursor<Vec<u8>>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    match Wrapper::sniff(&data) {
+        Some(wrapper) => Ok(Cursor::new(wrapper.decompress(&data)?)),
+        None => Ok(Cursor::new(data)),
+    }
+}
+
+/// Read a texture in container `H` that may be wrapped in a compressed stream, detected by its magic bytes.
+/// Unlike [`ContainerHeader::read_texture`], `reader` doesn't need to be seekable
+pub fn read_wrapped<H, R>(reader: &mut R) -> TextureResult<Texture>
+where
+    H: ContainerHeader,
+    for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+    for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+    R: Read,
+{
+    H::read_texture(&mut unwrap_reader(reader)?)
+}
+
+/// Write a texture in container `H`, wrapped in a compressed stream if `wrapper` is set.
+/// Unlike [`ContainerHeader::write_texture`], `writer` doesn't need to be seekable
+pub fn write_wrapped<H, W>(
+    writer: &mut W,
+    texture: &Texture,
+    wrapper: Option<Wrapper>,
+) -> TextureResult<()>
+where
+    H: ContainerHeader,
+    for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+    for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+    W: Write,
+{
+    let mut file = Cursor::new(Vec::new());
+    H::write_texture(&mut file, texture)?;
+    let data = match wrapper {
+        Some(wrapper) => wrapper.compress(file.get_ref())?,
+        None => file.into_inner(),
+    };
+    Ok(writer.write_all(&data)?)
+}
+
+/// Read a texture in container `H` from a file, which may be wrapped in a compressed stream
+pub fn read_wrapped_path<H>(path: impl AsRef<Path>) -> TextureResult<Texture>
+where
+    H: ContainerHeader,
+    for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+    for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+{
+    read_wrapped::<H, _>(&mut BufReader::new(File::open(path)?))
+}
+
+/// Write a texture in container `H` to a file, wrapped in a compressed stream if the path's extension
+/// is one used by a [`Wrapper`], such as `texture.dds.gz`
+pub fn write_wrapped_path<H>(path: impl AsRef<Path>, texture: &Texture) -> TextureResult<()>
+where
+    H: ContainerHeader,
+    for<'a> <H as binrw::BinRead>::Args<'a>: Default,
+    for<'a> <H as binrw::BinWrite>::Args<'a>: Default,
+{
+    let wrapper = Wrapper::from_path(&path);
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_wrapped::<H, _>(&mut writer, texture, wrapper)?;
+    Ok(writer.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::dds::DDSHeader;
+    use crate::dimensions::Dimensions;
+    use crate::format::Format;
+    use crate::shape::{TextureShape, TextureShapeNode};
+    use crate::texture::Surface;
+
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn gzip_path() -> Result<()> {
+        let surface = Surface::new(Dimensions::try_from([16, 16])?, vec![3u8; 128]);
+        let texture = Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(surface.clone()),
+        )?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("texture.dds.gz");
+        write_wrapped_path::<DDSHeader>(&path, &texture)?;
+        assert_eq!(Wrapper::sniff(&std::fs::read(&path)?), Some(Wrapper::Gzip));
+
+        let read = read_wrapped_path::<DDSHeader>(&path)?;
+        assert_eq!(
+            read.slice().try_into_surface().unwrap().buffer,
+            surface.buffer
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unwrapped() -> Result<()> {
+        let surface = Surface::new(Dimensions::try_from([4, 4])?, vec![3u8; 8]);
+        let texture = Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(surface),
+        )?;
+        let mut file = Vec::new();
+        write_wrapped::<DDSHeader, _>(&mut file, &texture, None)?;
+        assert_eq!(&file[..4], b"DDS ");
+        read_wrapped::<DDSHeader, _>(&mut &file[..])?;
+
+        assert_eq!(Wrapper::from_path("a.dds.zst"), Some(Wrapper::Zstd));
+        assert_eq!(Wrapper::from_path("a.dds"), None);
+        #[cfg(not(feature = "zstd"))]
+        {
+            let zstd = [0x28, 0xB5, 0x2F, 0xFD, 0, 0];
+            assert!(matches!(
+                read_wrapped::<DDSHeader, _>(&mut &zstd[..]),
+                Err(TextureError::Capability(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn zstd_path() -> Result<()> {
+        let surface = Surface::new(Dimensions::try_from([16, 16])?, vec![7u8; 128]);
+        let texture = Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(surface.clone()),
+        )?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("texture.dds.zst");
+        write_wrapped_path::<DDSHeader>(&path, &texture)?;
+        assert_eq!(Wrapper::sniff(&std::fs::read(&path)?), Some(Wrapper::Zstd));
+
+        let read = read_wrapped_path::<DDSHeader>(&path)?;
+        assert_eq!(
+            read.slice().try_into_surface().unwrap().buffer,
+            surface.buffer
+        );
+        Ok(())
+    }
+
+    /// Paths longer than Windows' traditional 260 character limit, and on Unix, names that aren't valid UTF-8
+    #[test]
+    fn unusual_paths() -> Result<()> {
+        let surface = Surface::new(Dimensions::try_from([4, 4])?, vec![5u8; 8]);
+        let texture = Texture::new(
+            Format::BC1 { srgb: false },
+            TextureShapeNode::from_surface(surface),
+        )?;
+        let dir = tempfile::tempdir()?;
+        let mut long = dir.path().to_owned();
+        for _ in 0..6 {
+            long.push("a".repeat(60));
+        }
+        std::fs::create_dir_all(&long)?;
+        let mut paths = vec![long.join("texture.dds")];
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let name = std::ffi::OsStr::from_bytes(b"t\xe9xture.dds");
+            paths.push(dir.path().join(name));
+            assert_eq!(
+                Wrapper::from_path(long.join(name).with_extension("dds.gz")),
+                Some(Wrapper::Gzip)
+            );
+        }
+
+        for path in paths {
+            write_wrapped_path::<DDSHeader>(&path, &texture)?;
+            let read = read_wrapped_path::<DDSHeader>(&path)?;
+            assert_eq!(read.primary().buffer[..], [5u8; 8]);
+        }
+        Ok(())
+    }
+}